@@ -98,7 +98,7 @@ fn main() -> anyhow::Result<()> {
                 // Clean the cargo build directory for limousine_instance
                 Command::new("cargo")
                     .current_dir(limousine_instance_path)
-                    .args(&["clean"])
+                    .args(["clean"])
                     .stdout(Stdio::null())
                     .spawn()?
                     .wait()?;
@@ -142,7 +142,7 @@ fn main() -> anyhow::Result<()> {
 
             if !Command::new("cargo")
                 .current_dir(limousine_instance_path.clone())
-                .args(&["build", "--release", "--features", "instance"])
+                .args(["build", "--release", "--features", "instance"])
                 .stdout(Stdio::from(log_file))
                 .stderr(Stdio::from(err_file))
                 .spawn()?
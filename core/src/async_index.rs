@@ -0,0 +1,307 @@
+//! A non-blocking facade over an in-memory index, for services running on an async runtime. Inserts
+//! which stay within a single base node, and searches, run inline and return a task which is
+//! already complete. Inserts which may restructure the layers above, as well as any operation
+//! issued while the index is busy, are handed to an `Executor` instead of blocking the caller.
+//!
+//! The facade doesn't depend on a particular runtime, any executor able to run a blocking closure
+//! can be plugged in, such as `tokio::task::spawn_blocking`.
+
+use crate::{IndexRead, IndexWrite, QuickInsert};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::task::{Context, Poll, Waker};
+
+/// A closure scheduled by an `AsyncIndex`
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the jobs scheduled by an `AsyncIndex`. Jobs hold a lock on the index, so they should run on
+/// threads which are allowed to block.
+pub trait Executor: Send + Sync + 'static {
+    fn spawn(&self, job: Job);
+}
+
+impl<F> Executor for F
+where
+    F: Fn(Job) + Send + Sync + 'static,
+{
+    fn spawn(&self, job: Job) {
+        self(job)
+    }
+}
+
+/// Runs every job on a freshly spawned thread, for callers without a blocking pool of their own
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadExecutor;
+
+impl Executor for ThreadExecutor {
+    fn spawn(&self, job: Job) {
+        std::thread::spawn(job);
+    }
+}
+
+struct TaskState<T> {
+    result: Option<crate::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// The result of an operation on an `AsyncIndex`, which resolves once the operation has run
+pub struct Task<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Task<T> {
+    fn ready(result: crate::Result<T>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TaskState {
+                result: Some(result),
+                waker: None,
+            })),
+        }
+    }
+
+    fn pending() -> (Self, Completer<T>) {
+        let state = Arc::new(Mutex::new(TaskState {
+            result: None,
+            waker: None,
+        }));
+
+        let completer = Completer {
+            state: Some(state.clone()),
+        };
+
+        (Self { state }, completer)
+    }
+
+    /// Whether the task resolves without waiting on the executor
+    pub fn is_ready(&self) -> bool {
+        lock(&self.state).result.is_some()
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = crate::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Resolves a pending `Task`. If a job is dropped without running, or panics, the task resolves to
+/// an error rather than never resolving.
+struct Completer<T> {
+    state: Option<Arc<Mutex<TaskState<T>>>>,
+}
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: crate::Result<T>) {
+        self.resolve(result);
+    }
+
+    fn resolve(&mut self, result: crate::Result<T>) {
+        if let Some(state) = self.state.take() {
+            let mut state = lock(&state);
+            state.result = Some(result);
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.resolve(Err(anyhow::anyhow!("Task was dropped before completing!")));
+    }
+}
+
+/// Lock a mutex even if a panicking job poisoned it, the state guarded by task mutexes stays
+/// consistent regardless
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An operation waiting for the index, which resolves its own task
+type Op<I> = Box<dyn FnOnce(&mut I) + Send>;
+
+struct Shared<I> {
+    index: Mutex<I>,
+
+    /// Operations handed to the executor, run strictly in the order they were issued
+    queue: Mutex<VecDeque<Op<I>>>,
+}
+
+impl<I> Shared<I> {
+    /// Run every queued operation. Operations queued behind a panicking one are dropped, which
+    /// resolves their tasks to errors.
+    fn drain(&self) {
+        let Ok(mut index) = self.index.lock() else {
+            lock(&self.queue).clear();
+            return;
+        };
+
+        while let Some(op) = lock(&self.queue).pop_front() {
+            op(&mut index);
+        }
+    }
+}
+
+/// Non-blocking facade over an index, see the module documentation. Operations issued through the
+/// facade take effect in the order they were issued, whether they ran inline or not. Cloning the
+/// facade shares the underlying index.
+pub struct AsyncIndex<I, E> {
+    shared: Arc<Shared<I>>,
+    executor: Arc<E>,
+}
+
+impl<I, E> Clone for AsyncIndex<I, E> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            executor: self.executor.clone(),
+        }
+    }
+}
+
+impl<I, E> AsyncIndex<I, E>
+where
+    I: Send + 'static,
+    E: Executor,
+{
+    pub fn new(index: I, executor: E) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                index: Mutex::new(index),
+                queue: Mutex::new(VecDeque::new()),
+            }),
+            executor: Arc::new(executor),
+        }
+    }
+
+    /// Search for `key`, inline unless the index is busy
+    pub fn search<K, V>(&self, key: K) -> Task<Option<V>>
+    where
+        I: IndexRead<K, V>,
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        match self.try_lock() {
+            Ok(Some(index)) => Task::ready(index.try_search(key)),
+            Ok(None) => self.schedule_fallible(move |index| index.try_search(key)),
+            Err(err) => Task::ready(Err(err)),
+        }
+    }
+
+    /// Insert `key`, inline if the index is idle and the insert stays within a single base node,
+    /// and on the executor otherwise
+    pub fn insert<K, V>(&self, key: K, value: V) -> Task<Option<V>>
+    where
+        I: IndexWrite<K, V> + QuickInsert<K>,
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        match self.try_lock() {
+            Ok(Some(mut index)) if index.is_quick_insert(&key) => {
+                Task::ready(index.try_put(key, value))
+            }
+            Ok(_) => self.schedule_fallible(move |index| index.try_put(key, value)),
+            Err(err) => Task::ready(Err(err)),
+        }
+    }
+
+    /// Run `f` with exclusive access to the index on the executor, for heavy operations such as
+    /// garbage collection or a rebuild
+    pub fn schedule<R>(&self, f: impl FnOnce(&mut I) -> R + Send + 'static) -> Task<R>
+    where
+        R: Send + 'static,
+    {
+        self.schedule_fallible(move |index| Ok(f(index)))
+    }
+
+    /// The underlying index, if this is the last handle to it and no job is holding on to it
+    pub fn into_inner(self) -> Option<I> {
+        let shared = Arc::try_unwrap(self.shared).ok()?;
+        shared.index.into_inner().ok()
+    }
+
+    fn schedule_fallible<R>(
+        &self,
+        f: impl FnOnce(&mut I) -> crate::Result<R> + Send + 'static,
+    ) -> Task<R>
+    where
+        R: Send + 'static,
+    {
+        let (task, completer) = Task::pending();
+
+        lock(&self.shared.queue).push_back(Box::new(move |index| completer.complete(f(index))));
+
+        let shared = self.shared.clone();
+        self.executor.spawn(Box::new(move || shared.drain()));
+
+        task
+    }
+
+    /// The index if it is idle and no operation is queued, or `None` otherwise
+    fn try_lock(&self) -> crate::Result<Option<MutexGuard<'_, I>>> {
+        let index = match self.shared.index.try_lock() {
+            Ok(index) => index,
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Poisoned(_)) => return Err(poisoned()),
+        };
+
+        // Running inline would overtake the queued operations
+        if !lock(&self.shared.queue).is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(index))
+    }
+}
+
+fn poisoned() -> anyhow::Error {
+    anyhow::anyhow!("Index was poisoned by a panicking job!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn task_resolves_when_dropped() {
+        let (task, completer) = Task::<()>::pending();
+        assert!(!task.is_ready());
+
+        drop(completer);
+        assert!(task.is_ready());
+        assert!(lock(&task.state).result.take().unwrap().is_err());
+    }
+
+    #[test]
+    fn schedule_runs_on_executor() {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let executor = move |job: Job| sender.send(job).unwrap();
+        let index = AsyncIndex::new(0u64, executor);
+
+        let task = index.schedule(|value| {
+            *value += 1;
+            *value
+        });
+        assert!(!task.is_ready());
+
+        receiver.recv().unwrap()();
+        assert!(task.is_ready());
+        assert_eq!(lock(&task.state).result.take().unwrap().unwrap(), 1);
+        assert_eq!(index.into_inner(), Some(1));
+    }
+}
@@ -0,0 +1,360 @@
+//! An internal component which picks its own kind when it's built, generated for `auto()` layers.
+//! The layer below is measured first: how many nodes it has, and how well the lower bounds of
+//! those nodes segment or how evenly they spread. The layer is then built as whichever of a B-tree,
+//! a PGM and a dense table is expected to route a lookup in the fewest comparisons, which
+//! `layer_report` records along with the estimates.
+
+use crate::classical::dense::dense_stride;
+use crate::classical::{BTreeInternalComponent, DenseInternalComponent};
+use crate::common::list::memory::ArenaID;
+use crate::component::{CompactComponent, InternalComponent, PropagateInsert, RemapComponent};
+use crate::explain::Probe;
+use crate::learned::{LayerReport, PGMInternalComponent};
+use crate::node_layer::NodeLayer;
+use crate::traits::{Address, Key, KeyBound};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use hashbrown::HashMap;
+use learned_index_segmentation::{LinearModel, SegmentationModel};
+use num::{Float, PrimInt};
+
+pub type AutoInternalAddress = ArenaID;
+
+/// Fanout of the B-tree an `auto()` layer can be built as
+pub const AUTO_FANOUT: usize = 32;
+
+/// A kind of layer an `auto()` layer can be built as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum AutoChoice {
+    BTree { fanout: usize },
+    PGM { epsilon: usize },
+    Dense,
+}
+
+/// What an `auto()` layer measured when it was built, and the kind it picked
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoDecision {
+    pub choice: AutoChoice,
+
+    /// Number of nodes in the layer below, each of which the layer holds a key of
+    pub keys: usize,
+
+    /// Estimated comparisons to route a lookup through the layer as every candidate
+    pub costs: Vec<(AutoChoice, f64)>,
+}
+
+impl AutoDecision {
+    /// Pick the cheapest kind of layer over `keys`, preferring a B-tree on ties since its cost
+    /// doesn't depend on the keys
+    pub fn measure<K: Key + PrimInt>(keys: &[K]) -> Self {
+        let costs = vec![
+            (
+                AutoChoice::BTree {
+                    fanout: AUTO_FANOUT,
+                },
+                btree_cost(keys.len(), AUTO_FANOUT),
+            ),
+            (
+                AutoChoice::PGM { epsilon: 16 },
+                pgm_cost(keys.len(), 16, segments::<K, 16>(keys)),
+            ),
+            (
+                AutoChoice::PGM { epsilon: 64 },
+                pgm_cost(keys.len(), 64, segments::<K, 64>(keys)),
+            ),
+            (AutoChoice::Dense, dense_cost(keys)),
+        ];
+
+        // `min_by` keeps the first of equally cheap candidates
+        let choice = costs
+            .iter()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|&(choice, _)| choice)
+            .unwrap();
+
+        Self {
+            choice,
+            keys: keys.len(),
+            costs,
+        }
+    }
+}
+
+/// Comparisons of a binary search over `n` entries
+fn binary_search_cost(n: f64) -> f64 {
+    Float::log2(n.max(1.0)) + 1.0
+}
+
+/// A B-tree is built with half full nodes, so a lookup searches a node of `fanout / 2` keys, after
+/// the layers above search through every node
+fn btree_cost(keys: usize, fanout: usize) -> f64 {
+    let entries = (fanout / 2) as f64;
+    let nodes = Float::ceil(keys as f64 / entries);
+
+    binary_search_cost(entries) + binary_search_cost(nodes)
+}
+
+/// Evaluating the model of a segment, counted in comparisons
+const MODEL_COST: f64 = 1.0;
+
+/// A PGM searches the `2 * epsilon + 1` keys around the prediction of a segment, or the whole
+/// segment if it's shorter, after the layers above search through every segment
+fn pgm_cost(keys: usize, epsilon: usize, segments: usize) -> f64 {
+    let segments = segments.max(1) as f64;
+    let window = ((2 * epsilon + 1) as f64).min(keys as f64 / segments);
+
+    MODEL_COST + binary_search_cost(window) + binary_search_cost(segments)
+}
+
+/// A dense table divides to find the slot of a key, then steps over the keys within that slot up
+/// to the one routed to, so a lookup of every key is averaged. Keys spread evenly take a single
+/// step, while clustered keys crowd into a few slots.
+fn dense_cost<K: Key + PrimInt>(keys: &[K]) -> f64 {
+    // The first key is routed every key below the second, and left out of the table
+    let routed = keys.get(1..).unwrap_or_default();
+    let Some(&origin) = routed.first() else {
+        return MODEL_COST + 1.0;
+    };
+    let stride = dense_stride(routed);
+
+    let mut steps = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for key in routed {
+        let slot = key.checked_sub(&origin).map(|offset| offset / stride);
+        run = if slot == previous { run + 1 } else { 1 };
+        previous = slot;
+        steps += run;
+    }
+
+    MODEL_COST + steps as f64 / routed.len() as f64
+}
+
+/// Number of segments a PGM with `EPSILON` splits `keys` into
+fn segments<K: Key + PrimInt, const EPSILON: usize>(keys: &[K]) -> usize {
+    LinearModel::<K, EPSILON>::train(keys.iter().map(|&key| (key, ()))).len()
+}
+
+/// Every layer an `AutoInternalComponent` can be built as
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum AnyInternal<K: Key + PrimInt, X: 'static, BA, PA> {
+    BTree(BTreeInternalComponent<K, X, AUTO_FANOUT, BA, PA>),
+    PGM16(PGMInternalComponent<K, X, 16, BA, PA>),
+    PGM64(PGMInternalComponent<K, X, 64, BA, PA>),
+    Dense(DenseInternalComponent<K, X, BA, PA>),
+}
+
+macro_rules! dispatch {
+    ($any:expr, $layer:ident => $body:expr) => {
+        match $any {
+            AnyInternal::BTree($layer) => $body,
+            AnyInternal::PGM16($layer) => $body,
+            AnyInternal::PGM64($layer) => $body,
+            AnyInternal::Dense($layer) => $body,
+        }
+    };
+}
+
+impl<K, X, BA, PA> NodeLayer<K, AutoInternalAddress, PA> for AnyInternal<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    fn parent(&self, ptr: AutoInternalAddress) -> Option<PA> {
+        dispatch!(self, layer => layer.parent(ptr))
+    }
+
+    fn set_parent(&mut self, ptr: AutoInternalAddress, parent: PA) {
+        dispatch!(self, layer => layer.set_parent(ptr, parent))
+    }
+
+    fn lower_bound(&self, ptr: AutoInternalAddress) -> KeyBound<K> {
+        dispatch!(self, layer => layer.lower_bound(ptr))
+    }
+
+    fn next(&self, ptr: AutoInternalAddress) -> Option<AutoInternalAddress> {
+        dispatch!(self, layer => layer.next(ptr))
+    }
+
+    fn prev(&self, ptr: AutoInternalAddress) -> Option<AutoInternalAddress> {
+        dispatch!(self, layer => layer.prev(ptr))
+    }
+
+    fn contains_node(&self, ptr: AutoInternalAddress) -> bool {
+        dispatch!(self, layer => layer.contains_node(ptr))
+    }
+
+    fn first(&self) -> AutoInternalAddress {
+        dispatch!(self, layer => layer.first())
+    }
+
+    fn last(&self) -> AutoInternalAddress {
+        dispatch!(self, layer => layer.last())
+    }
+
+    fn node_count(&self) -> usize {
+        dispatch!(self, layer => layer.node_count())
+    }
+
+    fn memory_usage(&self) -> usize {
+        dispatch!(self, layer => layer.memory_usage())
+    }
+
+    fn shared_count(&self) -> usize {
+        dispatch!(self, layer => layer.shared_count())
+    }
+
+    fn share_nodes(&mut self) {
+        dispatch!(self, layer => layer.share_nodes())
+    }
+
+    fn prefetch(&self, ptr: AutoInternalAddress) {
+        dispatch!(self, layer => layer.prefetch(ptr))
+    }
+}
+
+/// An `InternalComponent` which is built as a B-tree, a PGM or a dense table, whichever suits the
+/// layer below
+#[derive(Clone)]
+pub struct AutoInternalComponent<K: Key + PrimInt, X: 'static, BA, PA> {
+    inner: AnyInternal<K, X, BA, PA>,
+    decision: AutoDecision,
+}
+
+impl<K, X, BA, PA> NodeLayer<K, AutoInternalAddress, PA> for AutoInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    crate::node_layer::impl_node_layer!(ArenaID, PA);
+}
+
+impl<K, X, BA, PA> AutoInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    /// What the layer measured when it was built
+    pub fn decision(&self) -> &AutoDecision {
+        &self.decision
+    }
+
+    /// Name of the kind of layer the component was built as
+    pub fn name(&self) -> &'static str {
+        match self.inner {
+            AnyInternal::BTree(_) => "AutoBTreeInternal32",
+            AnyInternal::PGM16(_) => "AutoPGMInternal16",
+            AnyInternal::PGM64(_) => "AutoPGMInternal64",
+            AnyInternal::Dense(_) => "AutoDenseInternal",
+        }
+    }
+
+    /// Summarize the layer as the kind it was built as, along with the decision
+    pub fn report(&self) -> LayerReport {
+        let report = dispatch!(&self.inner, layer => layer.report());
+
+        LayerReport {
+            auto: Some(self.decision.clone()),
+            ..report
+        }
+    }
+}
+
+impl<K, X, BA, PA, B: NodeLayer<K, BA, AutoInternalAddress>>
+    InternalComponent<K, B, BA, AutoInternalAddress, PA> for AutoInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    fn search(&self, base: &B, ptr: AutoInternalAddress, key: &K) -> BA {
+        dispatch!(&self.inner, layer => layer.search(base, ptr, key))
+    }
+
+    fn probe(&self, base: &B, ptr: AutoInternalAddress, key: &K) -> Probe {
+        dispatch!(&self.inner, layer => layer.probe(base, ptr, key))
+    }
+
+    fn insert(
+        &mut self,
+        base: &mut B,
+        prop: PropagateInsert<K, BA, AutoInternalAddress>,
+    ) -> Option<PropagateInsert<K, AutoInternalAddress, PA>> {
+        dispatch!(&mut self.inner, layer => layer.insert(base, prop))
+    }
+
+    fn build(base: &mut B) -> Self {
+        let keys: Vec<K> = base.nodes().map(|(key, _)| key.resolve()).collect();
+        let decision = AutoDecision::measure(&keys);
+
+        let inner = match decision.choice {
+            AutoChoice::BTree { .. } => AnyInternal::BTree(InternalComponent::build(base)),
+            AutoChoice::PGM { epsilon: 16 } => AnyInternal::PGM16(InternalComponent::build(base)),
+            AutoChoice::PGM { .. } => AnyInternal::PGM64(InternalComponent::build(base)),
+            AutoChoice::Dense => AnyInternal::Dense(InternalComponent::build(base)),
+        };
+
+        Self { inner, decision }
+    }
+}
+
+impl<K, X, BA, PA> CompactComponent<AutoInternalAddress, PA> for AutoInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<AutoInternalAddress, AutoInternalAddress> {
+        dispatch!(&mut self.inner, layer => layer.compact())
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        dispatch!(&mut self.inner, layer => layer.remap_parents(remap))
+    }
+}
+
+impl<K, X, BA, PA> RemapComponent<BA> for AutoInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address + Hash,
+    PA: Address,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        dispatch!(&mut self.inner, layer => layer.remap_children(remap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_prefers_dense_on_even_keys() {
+        let even: Vec<u64> = (0..10_000).map(|i| i * 7).collect();
+        assert_eq!(AutoDecision::measure(&even).choice, AutoChoice::Dense);
+
+        // Two linear runs, the first crowded into the first slot of a table spanning both
+        let runs: Vec<u64> = (0..5_000)
+            .chain((0..5_000).map(|i| 1_000_000 + i * 1_000_000))
+            .collect();
+        assert!(matches!(
+            AutoDecision::measure(&runs).choice,
+            AutoChoice::PGM { .. }
+        ));
+
+        // Keys spread too unevenly for segments to save anything over a node
+        let exponential: Vec<u64> = (0..63).map(|i| 1 << i).collect();
+        assert_eq!(
+            AutoDecision::measure(&exponential).choice,
+            AutoChoice::BTree {
+                fanout: AUTO_FANOUT
+            }
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Lookups bounded by a budget, generated as `search_with_budget` on every index. A service with a
+//! latency target can give up on a lookup which would have to visit more layers, or read more from
+//! disk, than it can afford, and degrade gracefully rather than stall on a cold path.
+
+/// Limits on the work a single lookup may do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProbeBudget {
+    /// Nodes which may be visited, one per layer descended through
+    pub nodes: usize,
+
+    /// Bytes which may be read from disk by persisted layers
+    pub disk_bytes: u64,
+}
+
+impl Default for ProbeBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl ProbeBudget {
+    pub fn unlimited() -> Self {
+        Self {
+            nodes: usize::MAX,
+            disk_bytes: u64::MAX,
+        }
+    }
+
+    /// Visit at most `nodes` nodes
+    pub fn with_nodes(mut self, nodes: usize) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Read at most `bytes` bytes from disk
+    pub fn with_disk_bytes(mut self, bytes: u64) -> Self {
+        self.disk_bytes = bytes;
+        self
+    }
+
+    /// Whether another node can be visited after `nodes` were visited and `disk_bytes` were read
+    pub fn allows_next(&self, nodes: usize, disk_bytes: u64) -> bool {
+        nodes < self.nodes && disk_bytes <= self.disk_bytes
+    }
+}
+
+/// Result of a lookup bounded by a `ProbeBudget`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchOutcome<V> {
+    Found(V),
+    NotFound,
+
+    /// The lookup gave up before visiting `layer`, counting up from the base layer, having
+    /// visited `nodes` nodes and read `disk_bytes` bytes
+    Exceeded {
+        layer: usize,
+        nodes: usize,
+        disk_bytes: u64,
+    },
+}
+
+impl<V> SearchOutcome<V> {
+    /// The value found, as `search` would have returned it, or `None` if the budget ran out
+    pub fn completed(self) -> Option<Option<V>> {
+        match self {
+            Self::Found(value) => Some(Some(value)),
+            Self::NotFound => Some(None),
+            Self::Exceeded { .. } => None,
+        }
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        matches!(self, Self::Exceeded { .. })
+    }
+}
+
+impl<V> From<Option<V>> for SearchOutcome<V> {
+    fn from(value: Option<V>) -> Self {
+        match value {
+            Some(value) => Self::Found(value),
+            None => Self::NotFound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_allows_next() {
+        let budget = ProbeBudget::unlimited().with_nodes(2).with_disk_bytes(100);
+        assert!(budget.allows_next(1, 100));
+        assert!(!budget.allows_next(2, 0));
+        assert!(!budget.allows_next(0, 101));
+
+        assert_eq!(SearchOutcome::from(Some(3)).completed(), Some(Some(3)));
+        assert_eq!(SearchOutcome::<u64>::from(None).completed(), Some(None));
+
+        let exceeded = SearchOutcome::<u64>::Exceeded {
+            layer: 1,
+            nodes: 2,
+            disk_bytes: 0,
+        };
+        assert!(exceeded.is_exceeded());
+        assert_eq!(exceeded.completed(), None);
+    }
+}
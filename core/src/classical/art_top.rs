@@ -0,0 +1,534 @@
+use crate::component::{PropagateInsert, RemapComponent, TopComponent};
+use crate::explain::Probe;
+use crate::node_layer::NodeLayer;
+use crate::traits::Address;
+use crate::Key;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+use num::PrimInt;
+
+/// Widest key the tree can be built over, in bytes
+const MAX_KEY_BYTES: usize = 32;
+
+/// A `TopComponent` implementation as an adaptive radix tree over the bytes of the keys.
+///
+/// Keys are split into big endian bytes, with the sign bit of signed keys flipped so that the
+/// bytes order the same way as the keys. Inner nodes grow from 4 to 16, 48 and 256 children as
+/// they fill up, and store the bytes shared by every key beneath them as a prefix, so a dense key
+/// set is searched in a few byte lookups no matter how many entries the top holds.
+#[derive(Clone)]
+pub struct ARTTopComponent<K: Key + PrimInt, X, A> {
+    root: Option<Child>,
+    nodes: Vec<Inner>,
+    leaves: Vec<(K, A)>,
+    _ph: core::marker::PhantomData<X>,
+}
+
+#[derive(Clone, Copy)]
+enum Child {
+    Inner(usize),
+    Leaf(usize),
+}
+
+#[derive(Clone)]
+struct Inner {
+    /// Bytes shared by every key beneath the node, following the byte which led to it
+    prefix: [u8; MAX_KEY_BYTES],
+    prefix_len: usize,
+    children: Children,
+}
+
+#[derive(Clone)]
+enum Children {
+    Node4(Sparse<4>),
+    Node16(Sparse<16>),
+    Node48(Box<Indexed>),
+    Node256(Box<[Option<Child>; 256]>),
+}
+
+/// Children sorted by their byte
+#[derive(Clone)]
+struct Sparse<const N: usize> {
+    len: usize,
+    bytes: [u8; N],
+    children: [Child; N],
+}
+
+/// Children in insertion order, with a slot per byte pointing to them
+#[derive(Clone)]
+struct Indexed {
+    len: usize,
+
+    /// One past the position of the child for every byte, or zero if it has none
+    slots: [u8; 256],
+    children: [Child; 48],
+}
+
+/// Order preserving big endian bytes of a key, and the number of them
+fn radix<K: Key + PrimInt>(key: K) -> ([u8; MAX_KEY_BYTES], usize) {
+    let bits = K::zero().count_zeros() as usize;
+    let width = bits / 8;
+    debug_assert!(width <= MAX_KEY_BYTES);
+
+    // Flipping the sign bit orders negative keys before positive ones, and is a no-op for unsigned
+    let key = key ^ K::min_value();
+    let mask = num::cast::<u8, K>(0x7f).unwrap() | (K::one() << 7);
+
+    let mut bytes = [0; MAX_KEY_BYTES];
+    for (index, byte) in bytes[..width].iter_mut().enumerate() {
+        let shift = (bits - 8 * (index + 1)) as u32;
+        *byte = (key.unsigned_shr(shift) & mask).to_i64().unwrap() as u8;
+    }
+
+    (bytes, width)
+}
+
+fn common_prefix(left: &[u8], right: &[u8]) -> usize {
+    left.iter()
+        .zip(right.iter())
+        .take_while(|(left, right)| left == right)
+        .count()
+}
+
+impl<const N: usize> Sparse<N> {
+    fn new() -> Self {
+        Self {
+            len: 0,
+            bytes: [0; N],
+            children: [Child::Leaf(0); N],
+        }
+    }
+
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.bytes[..self.len].binary_search(&byte)
+    }
+
+    fn insert(&mut self, byte: u8, child: Child) {
+        let index = self.position(byte).unwrap_err();
+
+        self.bytes.copy_within(index..self.len, index + 1);
+        self.children.copy_within(index..self.len, index + 1);
+        self.bytes[index] = byte;
+        self.children[index] = child;
+        self.len += 1;
+    }
+
+    fn below(&self, byte: u8) -> Option<Child> {
+        match self.position(byte) {
+            Ok(0) | Err(0) => None,
+            Ok(index) | Err(index) => Some(self.children[index - 1]),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u8, Child)> + '_ {
+        self.bytes[..self.len]
+            .iter()
+            .copied()
+            .zip(self.children[..self.len].iter().copied())
+    }
+}
+
+impl Children {
+    fn get(&self, byte: u8) -> Option<Child> {
+        match self {
+            Self::Node4(sparse) => sparse.position(byte).ok().map(|i| sparse.children[i]),
+            Self::Node16(sparse) => sparse.position(byte).ok().map(|i| sparse.children[i]),
+            Self::Node48(indexed) => match indexed.slots[byte as usize] {
+                0 => None,
+                slot => Some(indexed.children[slot as usize - 1]),
+            },
+            Self::Node256(children) => children[byte as usize],
+        }
+    }
+
+    fn set(&mut self, byte: u8, child: Child) {
+        match self {
+            Self::Node4(sparse) => sparse.children[sparse.position(byte).unwrap()] = child,
+            Self::Node16(sparse) => sparse.children[sparse.position(byte).unwrap()] = child,
+            Self::Node48(indexed) => {
+                indexed.children[indexed.slots[byte as usize] as usize - 1] = child;
+            }
+            Self::Node256(children) => children[byte as usize] = Some(child),
+        }
+    }
+
+    /// The child with the largest byte smaller than `byte`
+    fn below(&self, byte: u8) -> Option<Child> {
+        match self {
+            Self::Node4(sparse) => sparse.below(byte),
+            Self::Node16(sparse) => sparse.below(byte),
+            _ => (0..byte).rev().find_map(|byte| self.get(byte)),
+        }
+    }
+
+    fn first(&self) -> Child {
+        match self {
+            Self::Node4(sparse) => sparse.children[0],
+            Self::Node16(sparse) => sparse.children[0],
+            _ => (0..=u8::MAX).find_map(|byte| self.get(byte)).unwrap(),
+        }
+    }
+
+    fn last(&self) -> Child {
+        match self {
+            Self::Node4(sparse) => sparse.children[sparse.len - 1],
+            Self::Node16(sparse) => sparse.children[sparse.len - 1],
+            _ => (0..=u8::MAX).rev().find_map(|byte| self.get(byte)).unwrap(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self {
+            Self::Node4(sparse) => sparse.len == 4,
+            Self::Node16(sparse) => sparse.len == 16,
+            Self::Node48(indexed) => indexed.len == 48,
+            Self::Node256(_) => false,
+        }
+    }
+
+    /// Move the children into the next larger kind of node
+    fn grow(&mut self) {
+        *self = match self {
+            Self::Node4(sparse) => {
+                let mut grown = Sparse::new();
+                sparse.iter().for_each(|(byte, child)| grown.insert(byte, child));
+                Self::Node16(grown)
+            }
+            Self::Node16(sparse) => {
+                let mut grown = Box::new(Indexed {
+                    len: 0,
+                    slots: [0; 256],
+                    children: [Child::Leaf(0); 48],
+                });
+
+                for (byte, child) in sparse.iter() {
+                    grown.children[grown.len] = child;
+                    grown.len += 1;
+                    grown.slots[byte as usize] = grown.len as u8;
+                }
+
+                Self::Node48(grown)
+            }
+            Self::Node48(indexed) => {
+                let mut grown = Box::new([None; 256]);
+
+                for (byte, &slot) in indexed.slots.iter().enumerate() {
+                    if slot != 0 {
+                        grown[byte] = Some(indexed.children[slot as usize - 1]);
+                    }
+                }
+
+                Self::Node256(grown)
+            }
+            Self::Node256(_) => unreachable!(),
+        };
+    }
+
+    fn insert(&mut self, byte: u8, child: Child) {
+        if self.is_full() {
+            self.grow();
+        }
+
+        match self {
+            Self::Node4(sparse) => sparse.insert(byte, child),
+            Self::Node16(sparse) => sparse.insert(byte, child),
+            Self::Node48(indexed) => {
+                indexed.children[indexed.len] = child;
+                indexed.len += 1;
+                indexed.slots[byte as usize] = indexed.len as u8;
+            }
+            Self::Node256(children) => children[byte as usize] = Some(child),
+        }
+    }
+}
+
+impl Inner {
+    fn new(prefix: &[u8]) -> Self {
+        let mut result = Self {
+            prefix: [0; MAX_KEY_BYTES],
+            prefix_len: 0,
+            children: Children::Node4(Sparse::new()),
+        };
+
+        result.set_prefix(prefix);
+        result
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.prefix[..self.prefix_len]
+    }
+
+    fn set_prefix(&mut self, prefix: &[u8]) {
+        self.prefix[..prefix.len()].copy_from_slice(prefix);
+        self.prefix_len = prefix.len();
+    }
+}
+
+impl<K, X, A> ARTTopComponent<K, X, A>
+where
+    K: Key + PrimInt,
+    A: Address + Copy,
+{
+    fn empty() -> Self {
+        Self {
+            root: None,
+            nodes: Vec::new(),
+            leaves: Vec::new(),
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// Number of inner nodes in the tree
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn push_leaf(&mut self, key: K, address: A) -> Child {
+        self.leaves.push((key, address));
+        Child::Leaf(self.leaves.len() - 1)
+    }
+
+    fn push_node(&mut self, node: Inner) -> Child {
+        self.nodes.push(node);
+        Child::Inner(self.nodes.len() - 1)
+    }
+
+    fn first_leaf(&self, mut child: Child) -> usize {
+        loop {
+            match child {
+                Child::Inner(node) => child = self.nodes[node].children.first(),
+                Child::Leaf(leaf) => return leaf,
+            }
+        }
+    }
+
+    fn last_leaf(&self, mut child: Child) -> usize {
+        loop {
+            match child {
+                Child::Inner(node) => child = self.nodes[node].children.last(),
+                Child::Leaf(leaf) => return leaf,
+            }
+        }
+    }
+
+    /// The leaf with the largest key not greater than `key` beneath `child`, counting the nodes
+    /// visited on the way
+    fn floor(
+        &self,
+        child: Child,
+        key: &K,
+        bytes: &[u8],
+        depth: usize,
+        visited: &mut usize,
+    ) -> Option<usize> {
+        *visited += 1;
+
+        let node = match child {
+            Child::Leaf(leaf) => return (self.leaves[leaf].0 <= *key).then_some(leaf),
+            Child::Inner(node) => &self.nodes[node],
+        };
+
+        let prefix = node.prefix();
+        match prefix.cmp(&bytes[depth..depth + prefix.len()]) {
+            Ordering::Less => return Some(self.last_leaf(child)),
+            Ordering::Greater => return None,
+            Ordering::Equal => (),
+        }
+
+        let depth = depth + prefix.len();
+        let byte = bytes[depth];
+
+        if let Some(next) = node.children.get(byte) {
+            if let Some(leaf) = self.floor(next, key, bytes, depth + 1, visited) {
+                return Some(leaf);
+            }
+        }
+
+        node.children.below(byte).map(|child| self.last_leaf(child))
+    }
+
+    /// Leaf to descend into from `key`, and the number of nodes visited, or nothing if the top is
+    /// empty
+    fn search_leaf(&self, key: &K) -> Option<(usize, usize)> {
+        let root = self.root?;
+        let (bytes, _) = radix(*key);
+        let mut visited = 0;
+
+        // Keys below every entry fall back to the first entry, like the other tops
+        let leaf = self
+            .floor(root, key, &bytes, 0, &mut visited)
+            .unwrap_or_else(|| self.first_leaf(root));
+
+        Some((leaf, visited))
+    }
+
+    /// Insert beneath `child`, returning what should take its place in its parent
+    fn insert_at(&mut self, child: Child, key: K, bytes: &[u8], depth: usize, address: A) -> Child {
+        let index = match child {
+            Child::Leaf(leaf) => {
+                if self.leaves[leaf].0 == key {
+                    self.leaves[leaf].1 = address;
+                    return child;
+                }
+
+                // Replace the leaf with a node branching at the first byte the keys differ in
+                let (existing, width) = radix(self.leaves[leaf].0);
+                let common = common_prefix(&existing[depth..width], &bytes[depth..width]);
+
+                let mut node = Inner::new(&bytes[depth..depth + common]);
+                node.children.insert(existing[depth + common], child);
+                let new_leaf = self.push_leaf(key, address);
+                node.children.insert(bytes[depth + common], new_leaf);
+
+                return self.push_node(node);
+            }
+            Child::Inner(index) => index,
+        };
+
+        let prefix_len = self.nodes[index].prefix_len;
+        let common = common_prefix(self.nodes[index].prefix(), &bytes[depth..]);
+
+        // Split the prefix, with a new node branching at the first byte it differs in
+        if common < prefix_len {
+            let prefix = self.nodes[index].prefix;
+            self.nodes[index].set_prefix(&prefix[common + 1..prefix_len]);
+
+            let mut node = Inner::new(&prefix[..common]);
+            node.children.insert(prefix[common], child);
+            let new_leaf = self.push_leaf(key, address);
+            node.children.insert(bytes[depth + common], new_leaf);
+
+            return self.push_node(node);
+        }
+
+        let depth = depth + prefix_len;
+        let byte = bytes[depth];
+
+        match self.nodes[index].children.get(byte) {
+            Some(next) => {
+                let next = self.insert_at(next, key, bytes, depth + 1, address);
+                self.nodes[index].children.set(byte, next);
+            }
+            None => {
+                let new_leaf = self.push_leaf(key, address);
+                self.nodes[index].children.insert(byte, new_leaf);
+            }
+        }
+
+        child
+    }
+
+    fn insert_entry(&mut self, key: K, address: A) {
+        let root = match self.root {
+            Some(root) => {
+                let (bytes, _) = radix(key);
+                self.insert_at(root, key, &bytes, 0, address)
+            }
+            None => self.push_leaf(key, address),
+        };
+
+        self.root = Some(root);
+    }
+}
+
+impl<K, X, BA> RemapComponent<BA> for ARTTopComponent<K, X, BA>
+where
+    K: Key + PrimInt,
+    BA: Address + Copy + Hash,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        for (_, address) in self.leaves.iter_mut() {
+            if let Some(&to) = remap.get(address) {
+                *address = to;
+            }
+        }
+    }
+}
+
+impl<K, X, Base, BA> TopComponent<K, Base, BA, ()> for ARTTopComponent<K, X, BA>
+where
+    Base: NodeLayer<K, BA, ()>,
+    K: Key + PrimInt,
+    BA: Address + Copy,
+{
+    fn search(&self, base: &Base, key: &K) -> BA {
+        match self.search_leaf(key) {
+            Some((leaf, _)) => self.leaves[leaf].1,
+            // An empty top falls back to the first node of the layer below, which always exists
+            None => base.first(),
+        }
+    }
+
+    fn probe(&self, _: &Base, key: &K) -> Probe {
+        let visited = self.search_leaf(key).map_or(0, |(_, visited)| visited);
+        Probe::counted(visited)
+    }
+
+    fn insert(&mut self, base: &mut Base, prop: PropagateInsert<K, BA, ()>) {
+        match prop {
+            PropagateInsert::Single(key, address, _) => {
+                base.set_parent(address, ());
+                self.insert_entry(key, address);
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn build(base: &mut Base) -> Self {
+        let mut result = Self::empty();
+        let mut iter = base
+            .range_mut(Bound::Unbounded, Bound::Unbounded)
+            .anchored();
+
+        while let Some((key, address, parent)) = iter.next() {
+            result.insert_entry(key.resolve(), address);
+            parent.set(());
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radix_preserves_order() {
+        let keys = [i32::MIN, -70_000, -256, -1, 0, 1, 255, 256, 70_000, i32::MAX];
+
+        for pair in keys.windows(2) {
+            assert!(radix(pair[0]).0 < radix(pair[1]).0);
+        }
+
+        assert_eq!(radix(0x0102u16), radix(0x0102u16));
+        assert_eq!(&radix(0x0102u16).0[..2], &[1, 2]);
+        assert_eq!(&radix(-1i8).0[..1], &[0x7f]);
+    }
+
+    #[test]
+    fn floor_matches_btree_map() {
+        use alloc::collections::BTreeMap;
+
+        let mut top = ARTTopComponent::<i64, (), usize>::empty();
+        let mut expected = BTreeMap::new();
+
+        // Scrambled, so nodes grow and prefixes split in every order
+        for index in 0..5_000i64 {
+            let key = (index * 7_919) % 5_003 * 37 - 50_000;
+            top.insert_entry(key, index as usize);
+            expected.insert(key, index as usize);
+        }
+
+        let first = *expected.values().next().unwrap();
+        for key in -60_000..150_000 {
+            let floor = expected.range(..=key).next_back().map(|(_, &v)| v);
+            let (leaf, _) = top.search_leaf(&key).unwrap();
+            assert_eq!(top.leaves[leaf].1, floor.unwrap_or(first));
+        }
+    }
+}
@@ -1,28 +1,37 @@
+use num::PrimInt;
 use std::ops::Bound;
 
 use crate::{
-    classical::node::BTreeNode,
+    classical::node::{BTreeNode, ContainsExact, ProjectExact},
     common::{
         list::deep_disk::DeepDiskList,
-        storage::{GlobalStore, StoreID},
+        storage::{
+            GlobalStore, NoCompression, PageCompression, RemapStoreIDs, StoreID, StoreIDRemap,
+            WarmStats,
+        },
     },
-    impl_node_layer, Address, Key, KeyBounded, NodeLayer, Persisted,
+    component::LayerInsert,
+    impl_node_layer,
+    projection::{project, FieldSelector, Projectable},
+    Address, Key, NodeLayer, Persisted,
 };
 
-pub struct DeepDiskBTreeLayer<K, V, const FANOUT: usize, PA>
+pub struct DeepDiskBTreeLayer<K, V, const FANOUT: usize, PA, Z = NoCompression>
 where
     K: Persisted + Ord,
     V: Persisted + Eq,
     PA: Persisted + Eq,
+    Z: PageCompression,
 {
-    inner: DeepDiskList<BTreeNode<K, V, FANOUT>, PA>,
+    inner: DeepDiskList<K, BTreeNode<K, V, FANOUT>, PA, Z>,
 }
 
-impl<K, V, const FANOUT: usize, PA> DeepDiskBTreeLayer<K, V, FANOUT, PA>
+impl<K, V, const FANOUT: usize, PA, Z> DeepDiskBTreeLayer<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted + Eq,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     pub fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
         Ok(Self {
@@ -30,19 +39,35 @@ where
         })
     }
 
-    pub fn fill(&mut self, iter: impl Iterator<Item = (K, V)>) -> crate::Result<()> {
-        // Add empty cap node
-        let mut ptr = self.inner.clear()?;
+    /// Fill an empty layer with entries in ascending key order, leaving every node half full so
+    /// that the first inserts into it don't split it right away
+    pub fn fill(&mut self, iter: impl Iterator<Item = crate::Result<(K, V)>>) -> crate::Result<()> {
+        let Some(mut ptr) = self.inner.is_empty()? else {
+            anyhow::bail!("Only an empty layer can be filled!");
+        };
+
+        // Nodes are built in memory and written out once, rather than once per entry
+        let mut node = BTreeNode::empty();
+        let mut entries = 0;
+
+        for entry in iter {
+            let (key, value) = entry?;
 
-        for (key, address) in iter {
             // If node too full, carry over to next
-            if self.inner.get_node(ptr)?.unwrap().is_half_full() {
+            if node.is_half_full() {
+                self.inner
+                    .transform_node(ptr, |page| *page = node.clone())?;
                 ptr = self.inner.insert_after(BTreeNode::empty(), ptr)?;
+                node = BTreeNode::empty();
             }
 
-            self.insert_into_node(key, &address, ptr)?;
+            node.insert(key, value);
+            entries += 1;
         }
 
+        self.inner.transform_node(ptr, |page| *page = node.clone())?;
+        self.inner.set_entry_count(entries);
+
         Ok(())
     }
 
@@ -59,7 +84,7 @@ where
                     ptr = self.inner.insert_after(BTreeNode::empty(), ptr)?;
                 }
 
-                self.insert_into_node(key.clone(), &address, ptr)?;
+                self.insert_into_node(key.resolve(), &address, ptr)?;
                 parent.set(ptr);
             }
         }
@@ -69,19 +94,111 @@ where
 
     fn insert_into_node(&mut self, key: K, value: &V, ptr: StoreID) -> crate::Result<Option<V>> {
         self.inner
-            .transform_node(ptr, |node| node.insert(key.clone(), value.clone()))
+            .transform_node(ptr, |node| node.insert(key, value.clone()))
+    }
+
+    /// Keep every node of the layer cached
+    pub fn pin_resident(&mut self) {
+        self.inner.pin_resident();
+    }
+
+    /// Write every page of the layer out
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Drop every change which wasn't written out, as the layer was when it was last flushed
+    pub fn reload(&mut self) -> crate::Result<()> {
+        self.inner.reload()
+    }
+
+    /// Read the node at `ptr` into the cache ahead of time
+    pub fn warm_node(&self, ptr: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        self.inner.warm(ptr, stats)
+    }
+
+    /// Read every node of the layer into the cache ahead of time
+    pub fn warm_all(&self, stats: &mut WarmStats) -> crate::Result<()> {
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(node) = ptr {
+            self.inner.warm(node, stats)?;
+            ptr = self.inner.next(node);
+        }
+
+        Ok(())
+    }
+
+    /// Every page holding a node of the layer
+    pub fn pages(&self) -> Vec<StoreID> {
+        self.inner.pages().collect()
+    }
+
+    /// Copy the layer into the local store `ident` of `store`, see `DeepDiskList::copy_into`
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+        remap_node: impl Fn(&mut BTreeNode<K, V, FANOUT>, &StoreIDRemap) -> crate::Result<()>,
+    ) -> crate::Result<()>
+    where
+        PA: RemapStoreIDs,
+    {
+        self.inner.copy_into(store, ident, remap, remap_node)
+    }
+
+    /// Remove every entry whose value is rejected by `keep`, returning the number of entries
+    /// removed. Nodes keep their lower bound in the list even once their smallest key is removed.
+    /// The count of entries is left to the caller, see `set_entry_count`.
+    pub fn retain(&mut self, keep: impl Fn(&V) -> bool) -> crate::Result<usize> {
+        let mut removed = 0;
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(node) = ptr {
+            removed += self.inner.transform_node(node, |node| node.retain(&keep))?;
+            ptr = self.inner.next(node);
+        }
+
+        Ok(removed)
     }
 
     pub fn get_node(&self, ptr: StoreID) -> crate::Result<BTreeNode<K, V, FANOUT>> {
         self.inner.get_node(ptr).map(|node| node.unwrap())
     }
 
+    /// The fields picked by `S` of the value stored at `key` in the node at `ptr`
+    pub fn project_exact<S>(&self, ptr: StoreID, key: &K) -> crate::Result<Option<S::Output>>
+    where
+        V: Projectable,
+        S: FieldSelector<V>,
+    {
+        let seed = ProjectExact::<K, V, S>::new(key);
+        let projection = self.inner.read_node_with(ptr, seed, |node| {
+            node.get_exact(key).map(project::<V, S>)
+        })?;
+
+        Ok(projection.flatten())
+    }
+
+    /// Whether the node at `ptr` holds `key`, without reading its value
+    pub fn contains_exact(&self, ptr: StoreID, key: &K) -> crate::Result<bool> {
+        let seed = ContainsExact::<K, V>::new(key);
+        let contains = self
+            .inner
+            .read_node_with(ptr, seed, |node| node.get_exact(key).is_some())?;
+
+        Ok(contains.unwrap_or(false))
+    }
+
+    /// Insert an entry into the node at `ptr`, returning the value the key held before, and the
+    /// node split off to make room for it, if any
     pub fn insert(
         &mut self,
         key: K,
         value: V,
         ptr: StoreID,
-    ) -> crate::Result<Option<(K, StoreID, PA)>> {
+    ) -> crate::Result<LayerInsert<K, V, StoreID, PA>> {
         if self.inner.get_node(ptr)?.unwrap().is_full() {
             let parent = self.inner.parent(ptr).unwrap();
 
@@ -90,26 +207,45 @@ where
             let new_node_ptr = self.inner.insert_after(new_node, ptr)?;
 
             // Insert into the right node
-            if key < split_point {
-                self.insert_into_node(key, &value, ptr)?;
+            let previous = if key < split_point {
+                self.insert_into_node(key, &value, ptr)?
             } else {
-                self.insert_into_node(key, &value, new_node_ptr)?;
-            }
+                self.insert_into_node(key, &value, new_node_ptr)?
+            };
+            self.count_insert(&previous);
 
-            return Ok(Some((
-                self.inner
-                    .get_node(new_node_ptr)?
-                    .unwrap()
-                    .lower_bound()
-                    .clone(),
-                new_node_ptr,
-                parent,
-            )));
-        } else {
-            self.insert_into_node(key, &value, ptr)?;
+            return Ok((
+                previous,
+                Some((
+                    *self.inner.get_node(new_node_ptr)?.unwrap().min(),
+                    new_node_ptr,
+                    parent,
+                )),
+            ));
         }
 
-        Ok(None)
+        let previous = self.insert_into_node(key, &value, ptr)?;
+        self.count_insert(&previous);
+
+        Ok((previous, None))
+    }
+
+    /// Number of entries in the layer, kept in its catalog. Only inserts through `insert` and
+    /// `fill` are counted, the layers above keep no count of their routing entries.
+    pub fn entry_count(&self) -> usize {
+        self.inner.entry_count()
+    }
+
+    /// Set the number of entries in the layer, for values which don't count as entries
+    pub fn set_entry_count(&mut self, entries: usize) {
+        self.inner.set_entry_count(entries);
+    }
+
+    fn count_insert(&mut self, previous: &Option<V>) {
+        if previous.is_none() {
+            let entries = self.inner.entry_count();
+            self.inner.set_entry_count(entries + 1);
+        }
     }
 
     pub fn insert_with_parent<B: NodeLayer<K, V, StoreID>>(
@@ -141,11 +277,7 @@ where
             }
 
             return Ok(Some((
-                self.inner
-                    .get_node(new_node_ptr)?
-                    .unwrap()
-                    .lower_bound()
-                    .clone(),
+                *self.inner.get_node(new_node_ptr)?.unwrap().min(),
                 new_node_ptr,
                 parent,
             )));
@@ -158,12 +290,13 @@ where
     }
 }
 
-impl<K, V, const FANOUT: usize, PA> NodeLayer<K, StoreID, PA>
-    for DeepDiskBTreeLayer<K, V, FANOUT, PA>
+impl<K, V, const FANOUT: usize, PA, Z> NodeLayer<K, StoreID, PA>
+    for DeepDiskBTreeLayer<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted + Eq,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     impl_node_layer!(StoreID, PA);
 }
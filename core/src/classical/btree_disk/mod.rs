@@ -1,47 +1,186 @@
 use crate::{
-    common::storage::{GlobalStore, StoreID},
-    impl_node_layer, Address, BoundaryDiskBaseComponent, BoundaryDiskInternalComponent,
+    common::storage::{
+        GlobalStore, NoCompression, PageCompression, RemapStoreIDs, StoreID, StoreIDRemap,
+        WarmStats,
+    },
+    impl_node_layer, Address, BaseInsert, BoundaryDiskBaseComponent, BoundaryDiskInternalComponent,
     DeepDiskBaseComponent, DeepDiskInternalComponent, Key, NodeLayer, Persisted, PropagateInsert,
 };
 
+use crate::classical::node::BTreeNode;
+use crate::explain::Probe;
+use crate::paging::{CursorPage, CursorToken};
+use crate::projection::{FieldSelector, Projectable};
+use num::PrimInt;
+
 use self::boundary_layer::BoundaryDiskBTreeLayer;
 use self::deep_layer::DeepDiskBTreeLayer;
 
 mod boundary_layer;
 mod deep_layer;
 
+/// The values of internal nodes are the addresses of their children
+impl<K: Ord, V: RemapStoreIDs, const FANOUT: usize> RemapStoreIDs for BTreeNode<K, V, FANOUT> {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        for index in 0..self.len() {
+            self.get_value_mut(index).unwrap().remap_store_ids(remap)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Up to `limit` entries following the key of `token`, reading on from the node at `ptr` of
+/// `layer` through `get_node`. The offset of the token is only trusted while the entries around it
+/// still bracket the key, since entries may have been inserted or removed since.
+fn read_page<K, V, const FANOUT: usize, PA: Address>(
+    layer: &impl NodeLayer<K, StoreID, PA>,
+    get_node: impl Fn(StoreID) -> crate::Result<BTreeNode<K, V, FANOUT>>,
+    mut ptr: StoreID,
+    token: &CursorToken<K, StoreID>,
+    limit: usize,
+) -> crate::Result<CursorPage<K, V, StoreID>>
+where
+    K: Key + PrimInt,
+    V: Clone,
+{
+    let after = *token.after();
+
+    let mut node = get_node(ptr)?;
+    let entries = node.entries();
+
+    let mut index = match token.offset() {
+        Some(offset)
+            if offset <= entries.len()
+                && (offset == 0 || entries[offset - 1].key <= after)
+                && entries.get(offset).is_none_or(|entry| entry.key > after) =>
+        {
+            offset
+        }
+        _ => match node.position(&after) {
+            index if entries.get(index).is_some_and(|entry| entry.key == after) => index + 1,
+            index => index,
+        },
+    };
+
+    let mut page = CursorPage {
+        entries: Vec::new(),
+        next: None,
+    };
+    let mut last = *token;
+
+    loop {
+        while let Some(entry) = node.entries().get(index) {
+            if page.entries.len() == limit {
+                page.next = Some(last);
+                return Ok(page);
+            }
+
+            page.entries.push((entry.key, entry.value.clone()));
+            index += 1;
+            last = CursorToken::new(ptr, Some(index), entry.key);
+        }
+
+        match layer.next(ptr) {
+            Some(next) => {
+                ptr = next;
+                node = get_node(ptr)?;
+                index = 0;
+            }
+            None => return Ok(page),
+        }
+    }
+}
+
 // -------------------------------------------------------
 //                 Boundary Internal Component
 // -------------------------------------------------------
 
 pub type BoundaryDiskBTreeInternalAddress = StoreID;
 
-pub struct BoundaryDiskBTreeInternalComponent<K, X, const FANOUT: usize, BA, PA>
+pub struct BoundaryDiskBTreeInternalComponent<K, X, const FANOUT: usize, BA, PA, Z = NoCompression>
 where
     K: Persisted + Ord,
     BA: Persisted,
+    Z: PageCompression,
 {
-    pub inner: BoundaryDiskBTreeLayer<K, BA, FANOUT, PA>,
+    pub inner: BoundaryDiskBTreeLayer<K, BA, FANOUT, PA, Z>,
     _ph: std::marker::PhantomData<X>,
 }
 
-impl<K, X, const FANOUT: usize, BA, PA> NodeLayer<K, BoundaryDiskBTreeInternalAddress, PA>
-    for BoundaryDiskBTreeInternalComponent<K, X, FANOUT, BA, PA>
+impl<K, X, const FANOUT: usize, BA, PA, Z>
+    BoundaryDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
+where
+    K: Persisted + Key + PrimInt,
+    BA: Persisted + Address,
+    PA: Address,
+    Z: PageCompression,
+{
+    /// Keep the pages of this layer cached instead of evicting them along with the base layer.
+    /// Internal layers are on the path of every lookup, so the generated index pins all of them.
+    pub fn pin_resident(&mut self) {
+        self.inner.pin_resident();
+    }
+
+    /// Read every page of this layer into the cache, so that the first lookups after loading the
+    /// index don't have to
+    pub fn warm(&self, stats: &mut WarmStats) -> crate::Result<()> {
+        self.inner.warm_all(stats)
+    }
+
+    /// Every page holding a node of this layer
+    pub fn pages(&self) -> Vec<StoreID> {
+        self.inner.pages()
+    }
+
+    /// Write every page of this layer out, ahead of when its cache would
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Copy this layer into the local store `ident` of `store`, translating the pages of its
+    /// children through `remap` along with its own
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+    ) -> crate::Result<()>
+    where
+        BA: RemapStoreIDs,
+    {
+        self.inner.copy_into(store, ident, remap, |node, remap| {
+            node.remap_store_ids(remap)
+        })
+    }
+}
+
+impl<K, X, const FANOUT: usize, BA, PA, Z> NodeLayer<K, BoundaryDiskBTreeInternalAddress, PA>
+    for BoundaryDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     BA: Persisted + Address,
     PA: Address,
+    Z: PageCompression,
 {
     impl_node_layer!(StoreID, PA);
 }
 
-impl<K, X, BA, PA, B: NodeLayer<K, BA, BoundaryDiskBTreeInternalAddress>, const FANOUT: usize>
-    BoundaryDiskInternalComponent<K, B, BA, BoundaryDiskBTreeInternalAddress, PA>
-    for BoundaryDiskBTreeInternalComponent<K, X, FANOUT, BA, PA>
+impl<
+        K,
+        X,
+        BA,
+        PA,
+        Z,
+        B: NodeLayer<K, BA, BoundaryDiskBTreeInternalAddress>,
+        const FANOUT: usize,
+    > BoundaryDiskInternalComponent<K, B, BA, BoundaryDiskBTreeInternalAddress, PA>
+    for BoundaryDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     BA: Persisted + Address,
     PA: Address,
+    Z: PageCompression,
 {
     fn search(&self, _: &B, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<BA> {
         Ok(self
@@ -51,6 +190,11 @@ where
             .clone())
     }
 
+    fn probe(&self, _: &B, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<Probe> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(Probe::counted(node.search_comparisons(key)))
+    }
+
     fn insert(
         &mut self,
         base: &mut B,
@@ -76,6 +220,11 @@ where
             _ph: std::marker::PhantomData,
         })
     }
+
+    fn reload(&mut self, base: &mut B) -> crate::Result<()> {
+        self.inner.reload()?;
+        self.inner.fill_with_parent(base)
+    }
 }
 
 // -------------------------------------------------------
@@ -84,54 +233,161 @@ where
 
 pub type BoundaryDiskBTreeBaseAddress = StoreID;
 
-pub struct BoundaryDiskBTreeBaseComponent<K, V, const FANOUT: usize, PA>
+pub struct BoundaryDiskBTreeBaseComponent<K, V, const FANOUT: usize, PA, Z = NoCompression>
 where
     K: Persisted + Ord,
     V: Persisted,
+    Z: PageCompression,
 {
-    pub inner: BoundaryDiskBTreeLayer<K, V, FANOUT, PA>,
+    pub inner: BoundaryDiskBTreeLayer<K, V, FANOUT, PA, Z>,
 }
 
-impl<K, V, const FANOUT: usize, PA> NodeLayer<K, BoundaryDiskBTreeBaseAddress, PA>
-    for BoundaryDiskBTreeBaseComponent<K, V, FANOUT, PA>
+impl<K, V, const FANOUT: usize, PA, Z> NodeLayer<K, BoundaryDiskBTreeBaseAddress, PA>
+    for BoundaryDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted,
     PA: Address,
+    Z: PageCompression,
 {
     impl_node_layer!(StoreID, PA);
 }
 
-impl<K, V, const FANOUT: usize, PA: 'static>
+impl<K, V, const FANOUT: usize, PA, Z> BoundaryDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
+where
+    K: Persisted + Key + PrimInt,
+    V: Persisted,
+    PA: Address,
+    Z: PageCompression,
+{
+    /// Read the page of the node at `ptr` into the cache ahead of time
+    pub fn warm_node(&self, ptr: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        self.inner.warm_node(ptr, stats)
+    }
+
+    /// Every page holding a node of this layer
+    pub fn pages(&self) -> Vec<StoreID> {
+        self.inner.pages()
+    }
+
+    /// Write every page of this layer out, ahead of when its cache would
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Copy this layer into the local store `ident` of `store`, translating its pages through
+    /// `remap`. Values are copied as they are.
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+    ) -> crate::Result<()> {
+        self.inner.copy_into(store, ident, remap, |_, _| Ok(()))
+    }
+}
+
+impl<K, V, const FANOUT: usize, PA: 'static, Z>
     BoundaryDiskBaseComponent<K, V, BoundaryDiskBTreeBaseAddress, PA>
-    for BoundaryDiskBTreeBaseComponent<K, V, FANOUT, PA>
+    for BoundaryDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted,
     PA: Address,
+    Z: PageCompression,
 {
     fn insert(
         &mut self,
         ptr: BoundaryDiskBTreeInternalAddress,
         key: K,
         value: V,
-    ) -> crate::Result<Option<PropagateInsert<K, BoundaryDiskBTreeBaseAddress, PA>>> {
-        if let Some((key, address, parent)) = self.inner.insert(key, value, ptr)? {
-            Ok(Some(PropagateInsert::Single(key, address, parent)))
-        } else {
-            Ok(None)
-        }
+    ) -> crate::Result<BaseInsert<K, V, BoundaryDiskBTreeBaseAddress, PA>> {
+        let (previous, split) = self.inner.insert(key, value, ptr)?;
+
+        Ok(BaseInsert {
+            previous,
+            propagate: split
+                .map(|(key, address, parent)| PropagateInsert::Single(key, address, parent)),
+        })
     }
 
     fn search(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<Option<V>> {
         Ok(self.inner.get_node(ptr)?.get_exact(key).cloned())
     }
 
+    fn search_node(
+        &self,
+        ptr: BoundaryDiskBTreeInternalAddress,
+        keys: &[K],
+    ) -> crate::Result<Vec<Option<V>>> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(keys
+            .iter()
+            .map(|key| node.get_exact(key).cloned())
+            .collect())
+    }
+
+    fn search_project<S>(
+        &self,
+        ptr: BoundaryDiskBTreeInternalAddress,
+        key: &K,
+    ) -> crate::Result<Option<S::Output>>
+    where
+        V: Projectable,
+        S: FieldSelector<V>,
+    {
+        self.inner.project_exact::<S>(ptr, key)
+    }
+
+    fn contains(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<bool> {
+        self.inner.contains_exact(ptr, key)
+    }
+
+    fn probe(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<Probe> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(Probe::counted(node.search_comparisons(key)))
+    }
+
+    fn len(&self) -> usize {
+        self.inner.entry_count()
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.inner.set_entry_count(len);
+    }
+
+    fn retain(&mut self, keep: impl Fn(&V) -> bool) -> crate::Result<usize> {
+        self.inner.retain(keep)
+    }
+
+    fn fill(&mut self, entries: impl Iterator<Item = crate::Result<(K, V)>>) -> crate::Result<()> {
+        self.inner.fill(entries)
+    }
+
+    fn page(
+        &self,
+        ptr: StoreID,
+        token: &CursorToken<K, StoreID>,
+        limit: usize,
+    ) -> crate::Result<CursorPage<K, V, StoreID>> {
+        read_page(
+            &self.inner,
+            |ptr| self.inner.get_node(ptr),
+            ptr,
+            token,
+            limit,
+        )
+    }
+
     fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
         Ok(Self {
             inner: BoundaryDiskBTreeLayer::load(store, ident)?,
         })
     }
+
+    fn reload(&mut self) -> crate::Result<()> {
+        self.inner.reload()
+    }
 }
 
 // -------------------------------------------------------
@@ -140,33 +396,83 @@ where
 
 pub type DeepDiskBTreeInternalAddress = StoreID;
 
-pub struct DeepDiskBTreeInternalComponent<K, X, const FANOUT: usize, BA, PA>
+pub struct DeepDiskBTreeInternalComponent<K, X, const FANOUT: usize, BA, PA, Z = NoCompression>
 where
     K: Persisted + Ord,
     BA: Persisted + Eq,
     PA: Persisted + Eq,
+    Z: PageCompression,
 {
-    pub inner: DeepDiskBTreeLayer<K, BA, FANOUT, PA>,
+    pub inner: DeepDiskBTreeLayer<K, BA, FANOUT, PA, Z>,
     _ph: std::marker::PhantomData<X>,
 }
 
-impl<K, X, const FANOUT: usize, BA, PA> NodeLayer<K, DeepDiskBTreeInternalAddress, PA>
-    for DeepDiskBTreeInternalComponent<K, X, FANOUT, BA, PA>
+impl<K, X, const FANOUT: usize, BA, PA, Z> DeepDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
+where
+    K: Persisted + Key + PrimInt,
+    BA: Persisted + Address,
+    PA: Persisted + Address,
+    Z: PageCompression,
+{
+    /// Keep the pages of this layer cached instead of evicting them along with the base layer.
+    /// Internal layers are on the path of every lookup, so the generated index pins all of them.
+    pub fn pin_resident(&mut self) {
+        self.inner.pin_resident();
+    }
+
+    /// Read every page of this layer into the cache, so that the first lookups after loading the
+    /// index don't have to
+    pub fn warm(&self, stats: &mut WarmStats) -> crate::Result<()> {
+        self.inner.warm_all(stats)
+    }
+
+    /// Every page holding a node of this layer
+    pub fn pages(&self) -> Vec<StoreID> {
+        self.inner.pages()
+    }
+
+    /// Write every page of this layer out, ahead of when its cache would
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Copy this layer into the local store `ident` of `store`, translating the pages of its
+    /// children through `remap` along with its own
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+    ) -> crate::Result<()>
+    where
+        BA: RemapStoreIDs,
+        PA: RemapStoreIDs,
+    {
+        self.inner.copy_into(store, ident, remap, |node, remap| {
+            node.remap_store_ids(remap)
+        })
+    }
+}
+
+impl<K, X, const FANOUT: usize, BA, PA, Z> NodeLayer<K, DeepDiskBTreeInternalAddress, PA>
+    for DeepDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     BA: Persisted + Address,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     impl_node_layer!(StoreID, PA);
 }
 
-impl<K, X, BA, PA, B: NodeLayer<K, BA, DeepDiskBTreeInternalAddress>, const FANOUT: usize>
+impl<K, X, BA, PA, Z, B: NodeLayer<K, BA, DeepDiskBTreeInternalAddress>, const FANOUT: usize>
     DeepDiskInternalComponent<K, B, BA, DeepDiskBTreeInternalAddress, PA>
-    for DeepDiskBTreeInternalComponent<K, X, FANOUT, BA, PA>
+    for DeepDiskBTreeInternalComponent<K, X, FANOUT, BA, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     BA: Persisted + Address,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     fn search(&self, _: &B, ptr: DeepDiskBTreeInternalAddress, key: &K) -> crate::Result<BA> {
         Ok(self
@@ -176,6 +482,11 @@ where
             .clone())
     }
 
+    fn probe(&self, _: &B, ptr: DeepDiskBTreeInternalAddress, key: &K) -> crate::Result<Probe> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(Probe::counted(node.search_comparisons(key)))
+    }
+
     fn insert(
         &mut self,
         base: &mut B,
@@ -201,6 +512,11 @@ where
             _ph: std::marker::PhantomData,
         })
     }
+
+    fn reload(&mut self, base: &mut B) -> crate::Result<()> {
+        self.inner.reload()?;
+        self.inner.fill_with_parent(base)
+    }
 }
 
 // -------------------------------------------------------
@@ -209,53 +525,163 @@ where
 
 pub type DeepDiskBTreeBaseAddress = StoreID;
 
-pub struct DeepDiskBTreeBaseComponent<K, V, const FANOUT: usize, PA>
+pub struct DeepDiskBTreeBaseComponent<K, V, const FANOUT: usize, PA, Z = NoCompression>
 where
     K: Persisted + Ord,
     V: Persisted + Eq,
     PA: Persisted + Eq,
+    Z: PageCompression,
 {
-    pub inner: DeepDiskBTreeLayer<K, V, FANOUT, PA>,
+    pub inner: DeepDiskBTreeLayer<K, V, FANOUT, PA, Z>,
 }
 
-impl<K, V, const FANOUT: usize, PA: 'static> NodeLayer<K, DeepDiskBTreeBaseAddress, PA>
-    for DeepDiskBTreeBaseComponent<K, V, FANOUT, PA>
+impl<K, V, const FANOUT: usize, PA: 'static, Z> NodeLayer<K, DeepDiskBTreeBaseAddress, PA>
+    for DeepDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted + Eq,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     impl_node_layer!(StoreID, PA);
 }
 
-impl<K, V, const FANOUT: usize, PA: 'static>
+impl<K, V, const FANOUT: usize, PA: 'static, Z> DeepDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
+where
+    K: Persisted + Key + PrimInt,
+    V: Persisted + Eq,
+    PA: Persisted + Address,
+    Z: PageCompression,
+{
+    /// Read the page of the node at `ptr` into the cache ahead of time
+    pub fn warm_node(&self, ptr: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        self.inner.warm_node(ptr, stats)
+    }
+
+    /// Every page holding a node of this layer
+    pub fn pages(&self) -> Vec<StoreID> {
+        self.inner.pages()
+    }
+
+    /// Write every page of this layer out, ahead of when its cache would
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Copy this layer into the local store `ident` of `store`, translating its pages through
+    /// `remap`. Values are copied as they are.
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+    ) -> crate::Result<()>
+    where
+        PA: RemapStoreIDs,
+    {
+        self.inner.copy_into(store, ident, remap, |_, _| Ok(()))
+    }
+}
+
+impl<K, V, const FANOUT: usize, PA: 'static, Z>
     DeepDiskBaseComponent<K, V, BoundaryDiskBTreeBaseAddress, PA>
-    for DeepDiskBTreeBaseComponent<K, V, FANOUT, PA>
+    for DeepDiskBTreeBaseComponent<K, V, FANOUT, PA, Z>
 where
-    K: Persisted + Key,
+    K: Persisted + Key + PrimInt,
     V: Persisted + Eq,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     fn insert(
         &mut self,
         ptr: BoundaryDiskBTreeInternalAddress,
         key: K,
         value: V,
-    ) -> crate::Result<Option<PropagateInsert<K, BoundaryDiskBTreeBaseAddress, PA>>> {
-        if let Some((key, address, parent)) = self.inner.insert(key, value, ptr)? {
-            Ok(Some(PropagateInsert::Single(key, address, parent)))
-        } else {
-            Ok(None)
-        }
+    ) -> crate::Result<BaseInsert<K, V, BoundaryDiskBTreeBaseAddress, PA>> {
+        let (previous, split) = self.inner.insert(key, value, ptr)?;
+
+        Ok(BaseInsert {
+            previous,
+            propagate: split
+                .map(|(key, address, parent)| PropagateInsert::Single(key, address, parent)),
+        })
     }
 
     fn search(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<Option<V>> {
         Ok(self.inner.get_node(ptr)?.get_exact(key).cloned())
     }
 
+    fn search_node(
+        &self,
+        ptr: BoundaryDiskBTreeInternalAddress,
+        keys: &[K],
+    ) -> crate::Result<Vec<Option<V>>> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(keys
+            .iter()
+            .map(|key| node.get_exact(key).cloned())
+            .collect())
+    }
+
+    fn search_project<S>(
+        &self,
+        ptr: BoundaryDiskBTreeInternalAddress,
+        key: &K,
+    ) -> crate::Result<Option<S::Output>>
+    where
+        V: Projectable,
+        S: FieldSelector<V>,
+    {
+        self.inner.project_exact::<S>(ptr, key)
+    }
+
+    fn contains(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<bool> {
+        self.inner.contains_exact(ptr, key)
+    }
+
+    fn probe(&self, ptr: BoundaryDiskBTreeInternalAddress, key: &K) -> crate::Result<Probe> {
+        let node = self.inner.get_node(ptr)?;
+        Ok(Probe::counted(node.search_comparisons(key)))
+    }
+
+    fn len(&self) -> usize {
+        self.inner.entry_count()
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.inner.set_entry_count(len);
+    }
+
+    fn retain(&mut self, keep: impl Fn(&V) -> bool) -> crate::Result<usize> {
+        self.inner.retain(keep)
+    }
+
+    fn fill(&mut self, entries: impl Iterator<Item = crate::Result<(K, V)>>) -> crate::Result<()> {
+        self.inner.fill(entries)
+    }
+
+    fn page(
+        &self,
+        ptr: StoreID,
+        token: &CursorToken<K, StoreID>,
+        limit: usize,
+    ) -> crate::Result<CursorPage<K, V, StoreID>> {
+        read_page(
+            &self.inner,
+            |ptr| self.inner.get_node(ptr),
+            ptr,
+            token,
+            limit,
+        )
+    }
+
     fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
         Ok(Self {
             inner: DeepDiskBTreeLayer::load(store, ident)?,
         })
     }
+
+    fn reload(&mut self) -> crate::Result<()> {
+        self.inner.reload()
+    }
 }
@@ -1,31 +1,54 @@
 use crate::classical::node::BTreeNode;
+use crate::classical::split::SplitPolicy;
+use crate::common::list::alloc::{ArenaAlloc, DefaultAlloc};
 use crate::common::list::memory::*;
+use crate::component::{LayerInsert, RebuildPlan};
 use crate::node_layer::{impl_node_layer, NodeLayer};
-use crate::traits::{Address, KeyBounded};
+use crate::traits::{Address, KeyBound};
 use crate::Key;
-use std::ops::Bound;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
 
 // ----------------------------------------
 // Layer Type
 // ----------------------------------------
 
-pub struct MemoryBTreeLayer<K: Ord, V, const FANOUT: usize, PA> {
-    inner: MemoryList<BTreeNode<K, V, FANOUT>, PA>,
+#[derive(Clone)]
+pub struct MemoryBTreeLayer<K: Ord, V, const FANOUT: usize, PA, AL: ArenaAlloc = DefaultAlloc> {
+    inner: MemoryList<BTreeNode<K, V, FANOUT>, PA, AL>,
 }
 
-impl<K, V, const FANOUT: usize, PA> MemoryBTreeLayer<K, V, FANOUT, PA>
+impl<K, V, const FANOUT: usize, PA, AL> MemoryBTreeLayer<K, V, FANOUT, PA, AL>
 where
     K: Key,
+    V: Clone,
+    PA: Clone,
+    AL: ArenaAlloc,
 {
-    pub fn empty() -> Self {
+    pub fn empty() -> Self
+    where
+        AL: Default,
+    {
+        Self::with_alloc(AL::default())
+    }
+
+    pub fn with_alloc(alloc: AL) -> Self {
         Self {
-            inner: MemoryList::empty(),
+            inner: MemoryList::with_alloc(alloc),
         }
     }
 
+    /// Number of nodes needed to hold `entries` half full nodes, plus the cap node
+    fn node_hint(entries: usize) -> usize {
+        entries / (FANOUT / 2).max(1) + 1
+    }
+
     pub fn fill(&mut self, iter: impl Iterator<Item = (K, V)>) {
         // Add empty cap node
-        let mut ptr = self.inner.clear();
+        let entries = iter.size_hint().0;
+        let mut ptr = self.inner.clear_with_hint(|| Self::node_hint(entries));
 
         for (key, address) in iter {
             // If node too full, carry over to next
@@ -37,26 +60,41 @@ where
         }
     }
 
-    pub fn fill_with_parent<B: NodeLayer<K, V, ArenaID>>(&mut self, base: &mut B)
+    /// Lay the nodes out in list order, see `MemoryList::compact`
+    pub fn compact(&mut self) -> Remap {
+        self.inner.compact()
+    }
+
+    pub fn remap_parents(&mut self, remap: &HashMap<PA, PA>)
     where
-        V: Address,
+        PA: Eq + Hash,
     {
-        // Add empty cap node
-        let mut ptr = self.inner.clear();
-        let mut iter = base.range_mut(Bound::Unbounded, Bound::Unbounded);
+        self.inner.remap_parents(remap);
+    }
 
-        while let Some((key, address, parent)) = iter.next() {
-            // If node too full, carry over to next
-            if self.inner[ptr].is_half_full() {
-                ptr = self.inner.insert_after(BTreeNode::empty(), ptr);
+    /// Point every value at where its node moved when the layer below was compacted
+    pub fn remap_values(&mut self, remap: &HashMap<V, V>)
+    where
+        V: Eq + Hash,
+    {
+        self.inner.for_each_mut(|node| {
+            for index in 0..node.len() {
+                let value = node.get_value_mut(index).unwrap();
+                if let Some(moved) = remap.get(value) {
+                    *value = moved.clone();
+                }
             }
-
-            self.inner[ptr].insert(key.clone(), address.clone());
-            parent.set(ptr);
-        }
+        });
     }
 
-    pub fn insert(&mut self, key: K, value: V, ptr: ArenaID) -> Option<(K, ArenaID, PA)>
+    /// Insert an entry into the node at `ptr`, returning the value the key held before, and the
+    /// node split off to make room for it by the policy `P`, if any
+    pub fn insert<P: SplitPolicy>(
+        &mut self,
+        key: K,
+        value: V,
+        ptr: ArenaID,
+    ) -> LayerInsert<K, V, ArenaID, PA>
     where
         PA: Address,
     {
@@ -64,29 +102,94 @@ where
             let parent = self.inner.parent(ptr).unwrap();
 
             // Split
-            let (split_point, new_node) = self.inner[ptr].split();
+            let (split_point, new_node) = self.inner[ptr].split_with::<P>(&key);
             let new_node_ptr = self.inner.insert_after(new_node, ptr);
 
             // Insert into the right node
-            if key < split_point {
-                self.inner[ptr].insert(key, value);
+            let previous = if key < split_point {
+                self.inner[ptr].insert(key, value)
             } else {
-                self.inner[new_node_ptr].insert(key, value);
+                self.inner[new_node_ptr].insert(key, value)
+            };
+
+            return (
+                previous,
+                Some((*self.inner[new_node_ptr].min(), new_node_ptr, parent)),
+            );
+        }
+
+        (self.inner[ptr].insert(key, value), None)
+    }
+}
+
+/// An internal layer is keyed by the lower bounds of the nodes of the layer below, so that its
+/// first entry covers every key below the first node without a smallest key to stand in for it
+impl<K, V, const FANOUT: usize, PA, AL> MemoryBTreeLayer<KeyBound<K>, V, FANOUT, PA, AL>
+where
+    K: Key,
+    V: Address,
+    PA: Clone,
+    AL: ArenaAlloc,
+{
+    pub fn fill_with_parent<B: NodeLayer<K, V, ArenaID>>(&mut self, base: &mut B) {
+        let plan = Self::plan_with_parent(base);
+        self.install(base, plan);
+    }
+
+    /// Build the nodes of the layer from `base`, without touching either
+    pub fn plan_with_parent<B: NodeLayer<K, V, ArenaID>>(
+        base: &B,
+    ) -> RebuildPlan<BTreeNode<KeyBound<K>, V, FANOUT>> {
+        let mut nodes = Vec::with_capacity(Self::node_hint(base.node_count()));
+        let mut node = BTreeNode::empty();
+
+        for (key, address) in base.range(Bound::Unbounded, Bound::Unbounded).anchored() {
+            // If node too full, carry over to next
+            if node.is_half_full() {
+                nodes.push(core::mem::take(&mut node));
             }
 
-            return Some((
-                self.inner[new_node_ptr].lower_bound().clone(),
-                new_node_ptr,
-                parent,
-            ));
-        } else {
-            self.inner[ptr].insert(key, value);
+            node.insert(key, address);
         }
 
-        None
+        nodes.push(node);
+        RebuildPlan::new(nodes, base.node_count())
+    }
+
+    pub fn apply_with_parent<B: NodeLayer<K, V, ArenaID>>(
+        &mut self,
+        base: &mut B,
+        plan: RebuildPlan<BTreeNode<KeyBound<K>, V, FANOUT>>,
+    ) -> crate::Result<()> {
+        plan.check(base)?;
+        self.install(base, plan);
+        Ok(())
+    }
+
+    fn install<B: NodeLayer<K, V, ArenaID>>(
+        &mut self,
+        base: &mut B,
+        plan: RebuildPlan<BTreeNode<KeyBound<K>, V, FANOUT>>,
+    ) {
+        let mut nodes = plan.nodes.into_iter();
+
+        // The first node takes the place of the cap node
+        let mut ptr = self.inner.clear_with_hint(|| nodes.len());
+        self.inner[ptr] = nodes.next().unwrap_or_default();
+
+        loop {
+            for entry in self.inner[ptr].iter() {
+                base.set_parent(entry.value.clone(), ptr);
+            }
+
+            match nodes.next() {
+                Some(node) => ptr = self.inner.insert_after(node, ptr),
+                None => break,
+            }
+        }
     }
 
-    pub fn insert_with_parent<B: NodeLayer<K, V, ArenaID>>(
+    pub fn insert_with_parent<P: SplitPolicy, B: NodeLayer<K, V, ArenaID>>(
         &mut self,
         key: K,
         value: V,
@@ -94,14 +197,15 @@ where
         ptr: ArenaID,
     ) -> Option<(K, ArenaID, PA)>
     where
-        V: Address,
         PA: Address,
     {
+        let key = KeyBound::Key(key);
+
         if self.inner[ptr].is_full() {
             let parent = self.inner.parent(ptr).unwrap();
 
             // Split
-            let (split_point, new_node) = self.inner[ptr].split();
+            let (split_point, new_node) = self.inner[ptr].split_with::<P>(&key);
             let new_node_ptr = self.inner.insert_after(new_node, ptr);
 
             // Update all of the parents for the split node
@@ -118,11 +222,13 @@ where
                 base.set_parent(value, new_node_ptr);
             }
 
-            return Some((
-                self.inner[new_node_ptr].lower_bound().clone(),
-                new_node_ptr,
-                parent,
-            ));
+            // Only the first node of the layer holds the `NegInf` entry, and it is never split off
+            let split_key = self.inner[new_node_ptr]
+                .min()
+                .into_key()
+                .expect("Split off node starts at a sentinel!");
+
+            return Some((split_key, new_node_ptr, parent));
         } else {
             self.inner[ptr].insert(key, value.clone());
             base.set_parent(value, ptr);
@@ -132,8 +238,8 @@ where
     }
 }
 
-impl<K: Ord, V, const FANOUT: usize, PA> core::ops::Index<ArenaID>
-    for MemoryBTreeLayer<K, V, FANOUT, PA>
+impl<K: Ord, V, const FANOUT: usize, PA, AL: ArenaAlloc> core::ops::Index<ArenaID>
+    for MemoryBTreeLayer<K, V, FANOUT, PA, AL>
 {
     type Output = BTreeNode<K, V, FANOUT>;
 
@@ -142,10 +248,21 @@ impl<K: Ord, V, const FANOUT: usize, PA> core::ops::Index<ArenaID>
     }
 }
 
-impl<K, V, const FANOUT: usize, PA> NodeLayer<K, ArenaID, PA> for MemoryBTreeLayer<K, V, FANOUT, PA>
+impl<K: Ord + Clone, V: Clone, const FANOUT: usize, PA: Clone, AL: ArenaAlloc>
+    core::ops::IndexMut<ArenaID> for MemoryBTreeLayer<K, V, FANOUT, PA, AL>
+{
+    fn index_mut(&mut self, index: ArenaID) -> &mut Self::Output {
+        &mut self.inner[index]
+    }
+}
+
+impl<K, V, const FANOUT: usize, PA, AL> NodeLayer<K, ArenaID, PA>
+    for MemoryBTreeLayer<K, V, FANOUT, PA, AL>
 where
     K: Key,
+    V: Clone,
     PA: Address,
+    AL: ArenaAlloc,
 {
     impl_node_layer!(ArenaID, PA);
 }
@@ -1,10 +1,19 @@
 mod layer;
 
+use crate::classical::node::BTreeNode;
+use crate::classical::split::{EvenSplit, SplitPolicy};
+use crate::common::list::alloc::{ArenaAlloc, DefaultAlloc};
 use crate::common::list::memory::ArenaID;
-use crate::node_layer::{impl_node_layer, NodeLayer};
-use crate::traits::Address;
+use crate::explain::Probe;
+use crate::learned::LayerReport;
+use crate::node_layer::NodeLayer;
+use crate::traits::{Address, KeyBound};
 use crate::{component::*, Key, Value};
-use layer::*;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use hashbrown::HashMap;
+pub use layer::MemoryBTreeLayer;
+use slice_search::{OptimalSearch, Search};
 
 // -------------------------------------------------------
 //                  Internal Component
@@ -12,31 +21,130 @@ use layer::*;
 
 pub type BTreeInternalAddress = ArenaID;
 
-pub struct BTreeInternalComponent<K: Key, X: 'static, const FANOUT: usize, BA, PA> {
-    inner: MemoryBTreeLayer<K, BA, FANOUT, PA>,
-    _ph: std::marker::PhantomData<X>,
+/// Nodes are searched with the strategy `S`, which by default scans small nodes linearly and binary
+/// searches large ones. Full nodes are split by the policy `P`, in half by default.
+#[derive(Clone)]
+pub struct BTreeInternalComponent<
+    K: Key,
+    X: 'static,
+    const FANOUT: usize,
+    BA,
+    PA,
+    S: Search = OptimalSearch,
+    P: SplitPolicy = EvenSplit,
+    AL: ArenaAlloc = DefaultAlloc,
+> {
+    inner: MemoryBTreeLayer<KeyBound<K>, BA, FANOUT, PA, AL>,
+    _ph: core::marker::PhantomData<(X, S, P)>,
+}
+
+impl<K, X, const FANOUT: usize, BA, PA, S: Search, P: SplitPolicy, AL: ArenaAlloc + Default>
+    NodeLayer<K, BTreeInternalAddress, PA>
+    for BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
+where
+    K: Key,
+    BA: Address,
+    PA: Address,
+{
+    fn parent(&self, ptr: ArenaID) -> Option<PA> {
+        self.inner.parent(ptr)
+    }
+
+    fn set_parent(&mut self, ptr: ArenaID, parent: PA) {
+        self.inner.set_parent(ptr, parent)
+    }
+
+    fn lower_bound(&self, ptr: ArenaID) -> KeyBound<K> {
+        self.inner.lower_bound(ptr).flatten()
+    }
+
+    fn next(&self, ptr: ArenaID) -> Option<ArenaID> {
+        self.inner.next(ptr)
+    }
+
+    fn prev(&self, ptr: ArenaID) -> Option<ArenaID> {
+        self.inner.prev(ptr)
+    }
+
+    fn contains_node(&self, ptr: ArenaID) -> bool {
+        self.inner.contains_node(ptr)
+    }
+
+    fn first(&self) -> ArenaID {
+        self.inner.first()
+    }
+
+    fn last(&self) -> ArenaID {
+        self.inner.last()
+    }
+
+    fn node_count(&self) -> usize {
+        self.inner.node_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.inner.memory_usage()
+    }
+
+    fn shared_count(&self) -> usize {
+        self.inner.shared_count()
+    }
+
+    fn share_nodes(&mut self) {
+        self.inner.share_nodes()
+    }
+
+    fn prefetch(&self, ptr: ArenaID) {
+        self.inner.prefetch(ptr)
+    }
 }
 
-impl<K, X, const FANOUT: usize, BA, PA> NodeLayer<K, BTreeInternalAddress, PA>
-    for BTreeInternalComponent<K, X, FANOUT, BA, PA>
+impl<K, X, const FANOUT: usize, BA, PA, S: Search, P: SplitPolicy, AL: ArenaAlloc + Default>
+    BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
 where
     K: Key,
     BA: Address,
     PA: Address,
 {
-    impl_node_layer!(ArenaID, PA);
+    /// Summarize how full the nodes of this layer are, as a report of a layer without models
+    pub fn report(&self) -> LayerReport {
+        let mut nodes = Vec::new();
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(current) = ptr {
+            nodes.push((self.inner[current].len(), 0));
+            ptr = self.inner.next(current);
+        }
+
+        LayerReport::from_nodes(0, nodes.into_iter())
+    }
 }
 
-impl<K, X, BA, PA, B: NodeLayer<K, BA, BTreeInternalAddress>, const FANOUT: usize>
-    InternalComponent<K, B, BA, BTreeInternalAddress, PA>
-    for BTreeInternalComponent<K, X, FANOUT, BA, PA>
+impl<
+        K,
+        X,
+        BA,
+        PA,
+        B: NodeLayer<K, BA, BTreeInternalAddress>,
+        const FANOUT: usize,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > InternalComponent<K, B, BA, BTreeInternalAddress, PA>
+    for BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
 where
     K: Key,
     BA: Address,
     PA: Address,
 {
     fn search(&self, _: &B, ptr: BTreeInternalAddress, key: &K) -> BA {
-        self.inner[ptr].get_lower_bound_always(key).clone()
+        self.inner[ptr]
+            .get_lower_bound_always_with::<S>(&KeyBound::Key(*key))
+            .clone()
+    }
+
+    fn probe(&self, _: &B, ptr: BTreeInternalAddress, key: &K) -> Probe {
+        Probe::counted(self.inner[ptr].search_comparisons_with::<S>(&KeyBound::Key(*key)))
     }
 
     fn insert(
@@ -47,7 +155,7 @@ where
         match prop {
             PropagateInsert::Single(key, address, ptr) => self
                 .inner
-                .insert_with_parent(key, address, base, ptr)
+                .insert_with_parent::<P, _>(key, address, base, ptr)
                 .map(|(key, address, parent)| PropagateInsert::Single(key, address, parent)),
             PropagateInsert::Replace { .. } => {
                 unimplemented!()
@@ -61,33 +169,178 @@ where
 
         Self {
             inner: result,
-            _ph: std::marker::PhantomData,
+            _ph: core::marker::PhantomData,
         }
     }
 }
 
+impl<
+        K,
+        X,
+        BA,
+        PA,
+        B: NodeLayer<K, BA, BTreeInternalAddress>,
+        const FANOUT: usize,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > RebuildComponent<K, B, BA, BTreeInternalAddress, PA>
+    for BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
+where
+    K: Key,
+    BA: Address,
+    PA: Address,
+{
+    type Node = BTreeNode<KeyBound<K>, BA, FANOUT>;
+
+    fn plan_rebuild(&self, base: &B) -> RebuildPlan<Self::Node> {
+        MemoryBTreeLayer::<KeyBound<K>, BA, FANOUT, PA, AL>::plan_with_parent(base)
+    }
+
+    fn apply_rebuild(&mut self, base: &mut B, plan: RebuildPlan<Self::Node>) -> crate::Result<()> {
+        self.inner.apply_with_parent(base, plan)
+    }
+}
+
+impl<K, X, const FANOUT: usize, BA, PA, S: Search, P: SplitPolicy, AL: ArenaAlloc + Default>
+    CompactComponent<BTreeInternalAddress, PA>
+    for BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
+where
+    K: Key,
+    BA: Address,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<BTreeInternalAddress, BTreeInternalAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<K, X, const FANOUT: usize, BA, PA, S: Search, P: SplitPolicy, AL: ArenaAlloc + Default>
+    RemapComponent<BA> for BTreeInternalComponent<K, X, FANOUT, BA, PA, S, P, AL>
+where
+    K: Key,
+    BA: Address + Hash,
+    PA: Address,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        self.inner.remap_values(remap);
+    }
+}
+
 // -------------------------------------------------------
 //                  Base Component
 // -------------------------------------------------------
 
 pub type BTreeBaseAddress = BTreeInternalAddress;
 
-pub struct BTreeBaseComponent<K: Ord, V, const FANOUT: usize, PA> {
-    inner: MemoryBTreeLayer<K, V, FANOUT, PA>,
+/// Nodes are searched with the strategy `S` and split by the policy `P`, as for
+/// `BTreeInternalComponent`
+#[derive(Clone)]
+pub struct BTreeBaseComponent<
+    K: Ord,
+    V,
+    const FANOUT: usize,
+    PA,
+    S: Search = OptimalSearch,
+    P: SplitPolicy = EvenSplit,
+    AL: ArenaAlloc = DefaultAlloc,
+> {
+    inner: MemoryBTreeLayer<K, V, FANOUT, PA, AL>,
+
+    /// Lower bound of every node other than the first whose smallest key was removed. The layers
+    /// above route to a node by the key it started with, so the node keeps that key as its lower
+    /// bound, even once it holds no entries at all.
+    fences: HashMap<BTreeBaseAddress, K>,
+
+    /// Number of entries across every node
+    len: usize,
+    _ph: core::marker::PhantomData<(S, P)>,
 }
 
-impl<K, V, const FANOUT: usize, PA: 'static> NodeLayer<K, BTreeBaseAddress, PA>
-    for BTreeBaseComponent<K, V, FANOUT, PA>
+impl<
+        K,
+        V,
+        const FANOUT: usize,
+        PA: 'static,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > NodeLayer<K, BTreeBaseAddress, PA> for BTreeBaseComponent<K, V, FANOUT, PA, S, P, AL>
 where
     K: Key,
     V: Value,
     PA: Address,
 {
-    impl_node_layer!(ArenaID, PA);
+    fn parent(&self, ptr: ArenaID) -> Option<PA> {
+        self.inner.parent(ptr)
+    }
+
+    fn set_parent(&mut self, ptr: ArenaID, parent: PA) {
+        self.inner.set_parent(ptr, parent)
+    }
+
+    fn lower_bound(&self, ptr: ArenaID) -> KeyBound<K> {
+        match self.fences.get(&ptr) {
+            Some(fence) => KeyBound::Key(*fence),
+            None => self.inner.lower_bound(ptr),
+        }
+    }
+
+    fn next(&self, ptr: ArenaID) -> Option<ArenaID> {
+        self.inner.next(ptr)
+    }
+
+    fn prev(&self, ptr: ArenaID) -> Option<ArenaID> {
+        self.inner.prev(ptr)
+    }
+
+    fn contains_node(&self, ptr: ArenaID) -> bool {
+        self.inner.contains_node(ptr)
+    }
+
+    fn first(&self) -> ArenaID {
+        self.inner.first()
+    }
+
+    fn last(&self) -> ArenaID {
+        self.inner.last()
+    }
+
+    fn node_count(&self) -> usize {
+        self.inner.node_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.inner.memory_usage()
+            + self.fences.capacity() * core::mem::size_of::<(BTreeBaseAddress, K)>()
+    }
+
+    fn shared_count(&self) -> usize {
+        self.inner.shared_count()
+    }
+
+    fn share_nodes(&mut self) {
+        self.inner.share_nodes()
+    }
+
+    fn prefetch(&self, ptr: ArenaID) {
+        self.inner.prefetch(ptr)
+    }
 }
 
-impl<K, V, const FANOUT: usize, PA: 'static> BaseComponent<K, V, BTreeBaseAddress, PA>
-    for BTreeBaseComponent<K, V, FANOUT, PA>
+impl<
+        K,
+        V,
+        const FANOUT: usize,
+        PA: 'static,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > BaseComponent<K, V, BTreeBaseAddress, PA> for BTreeBaseComponent<K, V, FANOUT, PA, S, P, AL>
 where
     K: Key,
     V: Value,
@@ -98,28 +351,152 @@ where
         ptr: BTreeInternalAddress,
         key: K,
         value: V,
-    ) -> Option<PropagateInsert<K, BTreeBaseAddress, PA>> {
-        if let Some((key, address, parent)) = self.inner.insert(key, value, ptr) {
-            Some(PropagateInsert::Single(key, address, parent))
-        } else {
-            None
+    ) -> BaseInsert<K, V, BTreeBaseAddress, PA> {
+        let (previous, split) = self.inner.insert::<P>(key, value, ptr);
+        if previous.is_none() {
+            self.len += 1;
+        }
+
+        BaseInsert {
+            previous,
+            propagate: split
+                .map(|(key, address, parent)| PropagateInsert::Single(key, address, parent)),
         }
     }
 
     fn search(&self, ptr: BTreeInternalAddress, key: &K) -> Option<V> {
-        self.inner[ptr].get_exact(key).cloned()
+        self.get(ptr, key).cloned()
+    }
+
+    fn get(&self, ptr: BTreeInternalAddress, key: &K) -> Option<&V> {
+        self.inner[ptr].get_exact_with::<S>(key)
+    }
+
+    fn get_mut(&mut self, ptr: BTreeInternalAddress, key: &K) -> Option<&mut V> {
+        self.inner[ptr].get_exact_mut_with::<S>(key)
+    }
+
+    fn probe(&self, ptr: BTreeInternalAddress, key: &K) -> Probe {
+        Probe::counted(self.inner[ptr].search_comparisons_with::<S>(key))
+    }
+
+    fn absorbs(&self, ptr: BTreeInternalAddress, _: &K) -> bool {
+        // Full nodes are split before the insert, even if the key is already present
+        !self.inner[ptr].is_full()
+    }
+
+    fn len(&self) -> usize {
+        self.len
     }
 
     fn empty() -> Self {
         let result = MemoryBTreeLayer::empty();
 
-        Self { inner: result }
+        Self {
+            inner: result,
+            fences: HashMap::new(),
+            len: 0,
+            _ph: core::marker::PhantomData,
+        }
     }
 
     fn build(iter: impl Iterator<Item = (K, V)>) -> Self {
         let mut result = MemoryBTreeLayer::empty();
-        result.fill(iter);
+        let mut len = 0;
+        result.fill(iter.inspect(|_| len += 1));
+
+        Self {
+            inner: result,
+            fences: HashMap::new(),
+            len,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V, const FANOUT: usize, PA, S: Search, P: SplitPolicy, AL: ArenaAlloc + Default>
+    CompactComponent<BTreeBaseAddress, PA> for BTreeBaseComponent<K, V, FANOUT, PA, S, P, AL>
+where
+    K: Key,
+    V: Value,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<BTreeBaseAddress, BTreeBaseAddress> {
+        let remap = self.inner.compact();
+        self.fences = self
+            .fences
+            .drain()
+            .map(|(ptr, fence)| (remap[&ptr], fence))
+            .collect();
+
+        remap
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<
+        K,
+        V,
+        const FANOUT: usize,
+        PA: 'static,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > EntryComponent<K, V, BTreeBaseAddress, PA> for BTreeBaseComponent<K, V, FANOUT, PA, S, P, AL>
+where
+    K: Key,
+    V: Value,
+    PA: Address,
+{
+    fn get_entry(&self, ptr: BTreeBaseAddress, key: &K) -> Option<(&K, &V)> {
+        self.inner[ptr].get_entry_with::<S>(key)
+    }
+}
+
+impl<
+        K,
+        V,
+        const FANOUT: usize,
+        PA: 'static,
+        S: Search,
+        P: SplitPolicy,
+        AL: ArenaAlloc + Default,
+    > CursorComponent<K, V, BTreeBaseAddress, PA> for BTreeBaseComponent<K, V, FANOUT, PA, S, P, AL>
+where
+    K: Key,
+    V: Value,
+    PA: Address,
+{
+    fn entry_count(&self, ptr: BTreeBaseAddress) -> usize {
+        self.inner[ptr].len()
+    }
+
+    fn entry(&self, ptr: BTreeBaseAddress, index: usize) -> (&K, &V) {
+        let entry = &self.inner[ptr].entries()[index];
+        (&entry.key, &entry.value)
+    }
+
+    fn value_mut(&mut self, ptr: BTreeBaseAddress, index: usize) -> &mut V {
+        self.inner[ptr].get_value_mut(index).unwrap()
+    }
+
+    fn position(&self, ptr: BTreeBaseAddress, key: &K) -> usize {
+        self.inner[ptr].position(key)
+    }
+
+    fn remove(&mut self, ptr: BTreeBaseAddress, index: usize) -> (K, V) {
+        let entry = self.inner[ptr].remove_index(index);
+        self.len -= 1;
+
+        // Keys below the first node are routed to it all the same, so only later nodes keep the
+        // key they were routed by
+        if index == 0 && ptr != self.inner.first() {
+            self.fences.entry(ptr).or_insert(entry.key);
+        }
 
-        Self { inner: result }
+        (entry.key, entry.value)
     }
 }
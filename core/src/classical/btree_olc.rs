@@ -0,0 +1,514 @@
+//! A B+ tree which can be searched and written by many threads at once, using optimistic lock
+//! coupling. Every node carries a versioned lock: searches never write to it, and only validate
+//! that the version didn't change while they read the node, while inserts only lock the leaf they
+//! write to, and briefly a parent whenever a node has to split. Operations which observe a
+//! concurrent write restart from the root.
+//!
+//! Nodes have a fixed size, and are never freed before the tree itself, so a reader may always
+//! dereference a pointer it read, even if the node was split in the meantime. Keys and values are
+//! read out of nodes while they may be written to, so nodes store them as `AtomicU64` words, which
+//! is why both have to be an `OlcWord`, and no word is used before its node was validated.
+//!
+//! Only built with the `olc` feature. The tree is a standalone index, it isn't a component which
+//! can be used in a layout yet.
+
+use crate::common::olc::{OptLock, Restart};
+use crate::{IndexRead, IndexWrite, Key};
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use num::PrimInt;
+
+/// A key or value which fits into 64 bits, so that nodes can hold it in an `AtomicU64`
+pub trait OlcWord: Copy {
+    fn to_word(self) -> u64;
+
+    fn from_word(word: u64) -> Self;
+}
+
+macro_rules! impl_olc_word {
+    ($($t:ty),+) => {
+        $(
+            impl OlcWord for $t {
+                fn to_word(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_word(word: u64) -> Self {
+                    word as $t
+                }
+            }
+        )+
+    };
+}
+
+impl_olc_word!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl OlcWord for f32 {
+    fn to_word(self) -> u64 {
+        self.to_bits() as u64
+    }
+
+    fn from_word(word: u64) -> Self {
+        f32::from_bits(word as u32)
+    }
+}
+
+impl OlcWord for f64 {
+    fn to_word(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn from_word(word: u64) -> Self {
+        f64::from_bits(word)
+    }
+}
+
+enum Slots<K, V, const FANOUT: usize> {
+    Leaf([AtomicU64; FANOUT]),
+    Inner([AtomicPtr<Node<K, V, FANOUT>>; FANOUT]),
+}
+
+/// A node holds up to `FANOUT` entries sorted by key. An inner node routes a key to the child with
+/// the largest key not greater than it, or to its first child if there is none.
+struct Node<K, V, const FANOUT: usize> {
+    lock: OptLock,
+    len: AtomicUsize,
+    keys: [AtomicU64; FANOUT],
+    slots: Slots<K, V, FANOUT>,
+}
+
+impl<K: Key + PrimInt + OlcWord, V: OlcWord, const FANOUT: usize> Node<K, V, FANOUT> {
+    fn leaf() -> Box<Self> {
+        Self::with_slots(Slots::Leaf(core::array::from_fn(|_| AtomicU64::new(0))))
+    }
+
+    fn inner() -> Box<Self> {
+        Self::with_slots(Slots::Inner(core::array::from_fn(|_| {
+            AtomicPtr::new(core::ptr::null_mut())
+        })))
+    }
+
+    fn with_slots(slots: Slots<K, V, FANOUT>) -> Box<Self> {
+        Box::new(Self {
+            lock: OptLock::new(),
+            len: AtomicUsize::new(0),
+            keys: core::array::from_fn(|_| AtomicU64::new(0)),
+            slots,
+        })
+    }
+
+    /// Nodes never change their kind, so this holds without validation
+    fn is_leaf(&self) -> bool {
+        matches!(self.slots, Slots::Leaf(_))
+    }
+
+    /// Clamped, since a concurrent write may leave an optimistic reader with any length
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed).min(FANOUT)
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == FANOUT
+    }
+
+    // Writes only happen under the write lock of the node, which orders them with its version. An
+    // optimistic read racing a write may see a mix of old and new words, which the reader discards
+    // once it fails to validate the node, so relaxed accesses are enough.
+
+    fn key(&self, index: usize) -> K {
+        K::from_word(self.keys[index].load(Ordering::Relaxed))
+    }
+
+    fn set_key(&self, index: usize, key: K) {
+        self.keys[index].store(key.to_word(), Ordering::Relaxed)
+    }
+
+    fn value(&self, index: usize) -> V {
+        match self.slots {
+            Slots::Leaf(ref values) => V::from_word(values[index].load(Ordering::Relaxed)),
+            Slots::Inner(_) => unreachable!("Inner nodes have no values!"),
+        }
+    }
+
+    fn set_value(&self, index: usize, value: V) {
+        match self.slots {
+            Slots::Leaf(ref values) => values[index].store(value.to_word(), Ordering::Relaxed),
+            Slots::Inner(_) => unreachable!("Inner nodes have no values!"),
+        }
+    }
+
+    fn child(&self, index: usize) -> *mut Self {
+        match self.slots {
+            Slots::Inner(ref children) => children[index].load(Ordering::Acquire),
+            Slots::Leaf(_) => unreachable!("Leaf nodes have no children!"),
+        }
+    }
+
+    fn set_child(&self, index: usize, child: *mut Self) {
+        match self.slots {
+            Slots::Inner(ref children) => children[index].store(child, Ordering::Release),
+            Slots::Leaf(_) => unreachable!("Leaf nodes have no children!"),
+        }
+    }
+
+    /// Copy the entry at `from` into `to`, which may belong to another node of the same kind
+    fn copy_entry(&self, from: usize, to: &Self, index: usize) {
+        to.set_key(index, self.key(from));
+
+        if self.is_leaf() {
+            to.set_value(index, self.value(from));
+        } else {
+            to.set_child(index, self.child(from));
+        }
+    }
+
+    /// Number of keys not greater than `key`
+    fn upper_bound(&self, key: &K) -> usize {
+        let (mut low, mut high) = (0, self.len());
+
+        while low < high {
+            let mid = (low + high) / 2;
+
+            if self.key(mid) <= *key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        low
+    }
+
+    fn child_for(&self, key: &K) -> *mut Self {
+        self.child(self.upper_bound(key).saturating_sub(1))
+    }
+
+    fn search(&self, key: &K) -> Option<V> {
+        let index = self.upper_bound(key);
+
+        if index > 0 && self.key(index - 1) == *key {
+            return Some(self.value(index - 1));
+        }
+
+        None
+    }
+
+    /// Make room for an entry at `index`, the node has to be locked and not full
+    fn open_slot(&self, index: usize) {
+        let len = self.len();
+
+        for from in (index..len).rev() {
+            self.copy_entry(from, self, from + 1);
+        }
+
+        self.len.store(len + 1, Ordering::Relaxed);
+    }
+
+    /// Insert into a locked leaf which is not full, returning the value it replaced
+    fn insert_value(&self, key: K, value: V) -> Option<V> {
+        let index = self.upper_bound(&key);
+
+        if index > 0 && self.key(index - 1) == key {
+            let old = self.value(index - 1);
+            self.set_value(index - 1, value);
+            return Some(old);
+        }
+
+        self.open_slot(index);
+        self.set_key(index, key);
+        self.set_value(index, value);
+        None
+    }
+
+    /// Insert a child into a locked inner node which is not full
+    fn insert_child(&self, key: K, child: *mut Self) {
+        let index = self.upper_bound(&key);
+
+        self.open_slot(index);
+        self.set_key(index, key);
+        self.set_child(index, child);
+    }
+
+    /// Move the upper half of a locked full node into a new sibling, returning the smallest key of
+    /// the sibling along with it
+    fn split(&self) -> (K, *mut Self) {
+        let sibling = if self.is_leaf() {
+            Self::leaf()
+        } else {
+            Self::inner()
+        };
+
+        let half = FANOUT / 2;
+        for (index, from) in (half..FANOUT).enumerate() {
+            self.copy_entry(from, &sibling, index);
+        }
+
+        sibling.len.store(FANOUT - half, Ordering::Relaxed);
+        self.len.store(half, Ordering::Relaxed);
+
+        (sibling.key(0), Box::into_raw(sibling))
+    }
+}
+
+/// A B+ tree which can be shared between threads, and written to through a shared reference
+pub struct OlcBTree<K, V, const FANOUT: usize = 32> {
+    /// Acts as the lock of the parent of the root, guarding `root` itself
+    root_lock: OptLock,
+    root: AtomicPtr<Node<K, V, FANOUT>>,
+    len: AtomicUsize,
+}
+
+// Nodes are only ever written under their lock, and readers validate everything they read
+unsafe impl<K: Send, V: Send, const FANOUT: usize> Send for OlcBTree<K, V, FANOUT> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, const FANOUT: usize> Sync for OlcBTree<K, V, FANOUT> {}
+
+impl<K: Key + PrimInt + OlcWord, V: OlcWord, const FANOUT: usize> Default
+    for OlcBTree<K, V, FANOUT>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Key + PrimInt + OlcWord, V: OlcWord, const FANOUT: usize> OlcBTree<K, V, FANOUT> {
+    pub fn new() -> Self {
+        assert!(FANOUT >= 4, "OlcBTree needs a fanout of at least 4!");
+
+        Self {
+            root_lock: OptLock::new(),
+            root: AtomicPtr::new(Box::into_raw(Node::leaf())),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of keys in the tree. While other threads are inserting, this is only a snapshot.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn search(&self, key: K) -> Option<V> {
+        loop {
+            if let Ok(result) = self.try_search(&key) {
+                return result;
+            }
+        }
+    }
+
+    /// Insert a key, returning the value it replaced. Any number of threads may insert at once.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        loop {
+            if let Ok(result) = self.try_insert(key, value) {
+                if result.is_none() {
+                    self.len.fetch_add(1, Ordering::Relaxed);
+                }
+
+                return result;
+            }
+        }
+    }
+
+    fn try_search(&self, key: &K) -> Result<Option<V>, Restart> {
+        let root_version = self.root_lock.read_lock()?;
+        let mut node = unsafe { &*self.root.load(Ordering::Acquire) };
+        let mut version = node.lock.read_lock()?;
+        self.root_lock.validate(root_version)?;
+
+        loop {
+            if node.is_leaf() {
+                let result = node.search(key);
+                node.lock.validate(version)?;
+                return Ok(result);
+            }
+
+            let child = node.child_for(key);
+            node.lock.validate(version)?;
+
+            // The child is only known to belong to the node if the node is still unchanged after
+            // locking the child
+            let child = unsafe { &*child };
+            let child_version = child.lock.read_lock()?;
+            node.lock.validate(version)?;
+
+            node = child;
+            version = child_version;
+        }
+    }
+
+    fn try_insert(&self, key: K, value: V) -> Result<Option<V>, Restart> {
+        let mut parent_lock = &self.root_lock;
+        let mut parent_version = parent_lock.read_lock()?;
+        let mut parent: Option<&Node<K, V, FANOUT>> = None;
+
+        let mut node = unsafe { &*self.root.load(Ordering::Acquire) };
+        let mut version = node.lock.read_lock()?;
+        parent_lock.validate(parent_version)?;
+
+        loop {
+            // Split full nodes on the way down, so that the parent of a split always has room
+            if node.is_full() {
+                parent_lock.upgrade(parent_version)?;
+                if let Err(restart) = node.lock.upgrade(version) {
+                    parent_lock.write_unlock();
+                    return Err(restart);
+                }
+
+                let (separator, sibling) = node.split();
+
+                match parent {
+                    Some(parent) => parent.insert_child(separator, sibling),
+                    None => {
+                        let root = Node::inner();
+                        root.insert_child(K::min_value(), node as *const _ as *mut _);
+                        root.insert_child(separator, sibling);
+                        self.root.store(Box::into_raw(root), Ordering::Release);
+                    }
+                }
+
+                node.lock.write_unlock();
+                parent_lock.write_unlock();
+                return Err(Restart);
+            }
+
+            if node.is_leaf() {
+                // Leaves only lose keys by splitting, which would have changed their version
+                node.lock.upgrade(version)?;
+                let result = node.insert_value(key, value);
+                node.lock.write_unlock();
+
+                return Ok(result);
+            }
+
+            let child = node.child_for(&key);
+            node.lock.validate(version)?;
+
+            let child = unsafe { &*child };
+            let child_version = child.lock.read_lock()?;
+            node.lock.validate(version)?;
+
+            parent_lock = &node.lock;
+            parent_version = version;
+            parent = Some(node);
+
+            node = child;
+            version = child_version;
+        }
+    }
+}
+
+impl<K, V, const FANOUT: usize> Drop for OlcBTree<K, V, FANOUT> {
+    fn drop(&mut self) {
+        // SAFETY: the tree is exclusively borrowed, and every node is reachable from the root
+        unsafe { free_tree(*self.root.get_mut()) }
+    }
+}
+
+/// Free the subtree rooted at `node`, which nothing else may access anymore. This is a free
+/// function since `Drop` can't require the bounds of the node methods.
+unsafe fn free_tree<K, V, const FANOUT: usize>(node: *mut Node<K, V, FANOUT>) {
+    let node = Box::from_raw(node);
+
+    if let Slots::Inner(ref children) = node.slots {
+        let len = node.len.load(Ordering::Relaxed);
+
+        for child in children.iter().take(len) {
+            free_tree(child.load(Ordering::Relaxed));
+        }
+    }
+}
+
+impl<K: Key + PrimInt + OlcWord, V: OlcWord, const FANOUT: usize> IndexRead<K, V>
+    for OlcBTree<K, V, FANOUT>
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        Ok(OlcBTree::search(self, key))
+    }
+
+    fn len(&self) -> usize {
+        OlcBTree::len(self)
+    }
+}
+
+impl<K: Key + PrimInt + OlcWord, V: OlcWord, const FANOUT: usize> IndexWrite<K, V>
+    for OlcBTree<K, V, FANOUT>
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        Ok(OlcBTree::insert(self, key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{unsorted_keys, TestRng};
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn olc_btree_matches_btreemap() {
+        let mut rng = TestRng::from_env();
+        let tree: OlcBTree<u64, u64, 4> = OlcBTree::new();
+        let mut expected = BTreeMap::new();
+
+        for key in unsorted_keys::<u64>(&mut rng, 5_000) {
+            let key = key % 10_000;
+            assert_eq!(tree.insert(key, key + 1), expected.insert(key, key + 1));
+        }
+
+        assert_eq!(tree.len(), expected.len());
+        for key in 0..10_000 {
+            assert_eq!(tree.search(key), expected.get(&key).copied());
+        }
+    }
+
+    #[test]
+    fn olc_btree_signed_and_float_words() {
+        let tree: OlcBTree<i32, f64, 4> = OlcBTree::new();
+
+        for key in -500..500 {
+            assert_eq!(tree.insert(key, key as f64 / 4.0), None);
+        }
+
+        assert_eq!(tree.search(i32::MIN), None);
+        for key in -500..500 {
+            assert_eq!(tree.search(key), Some(key as f64 / 4.0));
+        }
+    }
+
+    #[test]
+    fn olc_btree_concurrent() {
+        const THREADS: u64 = 4;
+        const KEYS: u64 = 20_000;
+
+        let tree: OlcBTree<u64, u64, 8> = OlcBTree::new();
+
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let tree = &tree;
+
+                // Writers interleave their keys, so they keep splitting the same nodes
+                scope.spawn(move || {
+                    for key in (thread..KEYS).step_by(THREADS as usize) {
+                        assert_eq!(tree.insert(key, key * 2), None);
+                    }
+                });
+
+                // Readers only ever see a key with its value, or not at all
+                scope.spawn(move || {
+                    for key in (0..KEYS).rev() {
+                        if let Some(value) = tree.search(key) {
+                            assert_eq!(value, key * 2);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(tree.len(), KEYS as usize);
+        for key in 0..KEYS {
+            assert_eq!(tree.search(key), Some(key * 2));
+        }
+    }
+}
@@ -1,37 +1,269 @@
-use crate::component::{PropagateInsert, TopComponent};
+use crate::classical::node::BTreeNode;
+use crate::component::{PropagateInsert, RemapComponent, TopComponent};
+use crate::explain::Probe;
 use crate::node_layer::NodeLayer;
-use crate::traits::Address;
+use crate::traits::{Address, KeyBound};
 use crate::Key;
-use std::collections::BTreeMap;
-use std::ops::Bound;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+
+/// Fanout of the layers materialized beneath the top once it exceeds its capacity
+const PROMOTED_FANOUT: usize = 64;
 
 /// A `TopComponent` implementation built around the BTreeMap implementation in the Rust standard
 /// library.
-pub struct BTreeTopComponent<K, X, A> {
-    pub inner: BTreeMap<K, A>,
-    _ph: std::marker::PhantomData<X>,
+///
+/// Once the map holds more than `MAX_ENTRIES` entries, a new layer of nodes is materialized
+/// beneath it from the layer below and the map is reset to only index that new layer, which keeps
+/// the map small and cache-resident as the data grows.
+///
+/// Nodes are keyed by their lower bounds, so the first node of the layer below is keyed by
+/// `NegInf` and keys need no smallest value.
+#[derive(Clone)]
+pub struct BTreeTopComponent<K: Ord, X, A, const MAX_ENTRIES: usize = { usize::MAX }> {
+    pub inner: BTreeMap<KeyBound<K>, A>,
+    promoted: Option<PromotedLayers<KeyBound<K>, A>>,
+    _ph: core::marker::PhantomData<X>,
+}
+
+/// Layers materialized underneath the top, which are navigated purely by key since the layer
+/// below the top has no room for parent pointers
+#[derive(Clone)]
+struct PromotedLayers<K: Ord, A> {
+    /// Index of every node in the highest layer, keyed by its lower bound
+    root: BTreeMap<K, usize>,
+
+    /// The lowest layer, pointing into the layer below the top
+    leaves: Vec<BTreeNode<K, A, PROMOTED_FANOUT>>,
+
+    /// The layers above the leaves ordered bottom to top, each pointing into the one beneath
+    layers: Vec<Vec<BTreeNode<K, usize, PROMOTED_FANOUT>>>,
+}
+
+/// Value of the greatest entry at or below `key`, falling back to the first entry for keys below
+/// every entry, and to nothing only if the map is empty
+fn floor<K: Ord, V: Copy>(map: &BTreeMap<K, V>, key: &K) -> Option<V> {
+    map.range(..=key)
+        .next_back()
+        .or_else(|| map.iter().next())
+        .map(|(_, value)| *value)
+}
+
+/// Pack entries into half full nodes, leaving room for inserts
+fn group<K: Key, V>(
+    entries: impl Iterator<Item = (K, V)>,
+) -> Vec<BTreeNode<K, V, PROMOTED_FANOUT>> {
+    let mut nodes = vec![BTreeNode::empty()];
+
+    for (key, value) in entries {
+        if nodes.last().unwrap().is_half_full() {
+            nodes.push(BTreeNode::empty());
+        }
+
+        nodes.last_mut().unwrap().insert(key, value);
+    }
+
+    nodes
+}
+
+fn index<K: Key, V>(nodes: &[BTreeNode<K, V, PROMOTED_FANOUT>]) -> BTreeMap<K, usize> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (*node.min(), index))
+        .collect()
+}
+
+/// Insert into a node, splitting it if it is full. Returns the lower bound and index of the newly
+/// created node if a split happened.
+fn insert_split<K: Key, V>(
+    nodes: &mut Vec<BTreeNode<K, V, PROMOTED_FANOUT>>,
+    index: usize,
+    key: K,
+    value: V,
+) -> Option<(K, usize)> {
+    if nodes[index].is_full() {
+        let (split_point, new_node) = nodes[index].split();
+        nodes.push(new_node);
+        let new_index = nodes.len() - 1;
+
+        if key < split_point {
+            nodes[index].insert(key, value);
+        } else {
+            nodes[new_index].insert(key, value);
+        }
+
+        return Some((split_point, new_index));
+    }
+
+    nodes[index].insert(key, value);
+    None
 }
 
-impl<K, X, Base, BA: Copy> TopComponent<K, Base, BA, ()> for BTreeTopComponent<K, X, BA>
+impl<K: Key, A: Copy> PromotedLayers<K, A> {
+    fn new(leaves: Vec<BTreeNode<K, A, PROMOTED_FANOUT>>) -> Self {
+        Self {
+            root: index(&leaves),
+            leaves,
+            layers: Vec::new(),
+        }
+    }
+
+    fn search(&self, key: &K) -> A {
+        // The root indexes at least the one node `group` always creates
+        let mut index = floor(&self.root, key).unwrap_or(0);
+
+        for layer in self.layers.iter().rev() {
+            index = *layer[index].get_lower_bound_always(key);
+        }
+
+        *self.leaves[index].get_lower_bound_always(key)
+    }
+
+    fn probe(&self, key: &K) -> Probe {
+        let mut probe = Probe::binary_search(self.root.len());
+        let mut index = floor(&self.root, key).unwrap_or(0);
+
+        for layer in self.layers.iter().rev() {
+            probe = probe.then(Probe::counted(layer[index].search_comparisons(key)));
+            index = *layer[index].get_lower_bound_always(key);
+        }
+
+        probe.then(Probe::counted(self.leaves[index].search_comparisons(key)))
+    }
+
+    fn insert(&mut self, key: K, address: A) {
+        // Record the path from the top down to the leaves
+        let mut path = Vec::with_capacity(self.layers.len());
+        let mut index = floor(&self.root, &key).unwrap_or(0);
+
+        for layer in self.layers.iter().rev() {
+            path.push(index);
+            index = *layer[index].get_lower_bound_always(&key);
+        }
+
+        // Propagate splits back up
+        let mut carry = insert_split(&mut self.leaves, index, key, address);
+
+        for (layer, index) in self.layers.iter_mut().zip(path.into_iter().rev()) {
+            match carry {
+                Some((key, node)) => carry = insert_split(layer, index, key, node),
+                None => return,
+            }
+        }
+
+        if let Some((key, node)) = carry {
+            self.root.insert(key, node);
+        }
+    }
+
+    /// Materialize a new layer from the root, and reset the root to index it instead
+    fn promote(&mut self) {
+        let layer = group(core::mem::take(&mut self.root).into_iter());
+        self.root = index(&layer);
+        self.layers.push(layer);
+    }
+}
+
+impl<K, X, A, const MAX_ENTRIES: usize> BTreeTopComponent<K, X, A, MAX_ENTRIES>
+where
+    K: Key,
+    A: Address + Copy,
+{
+    fn is_over_capacity(entries: usize) -> bool {
+        // A root with a single entry can't shrink any further
+        entries > MAX_ENTRIES.max(1)
+    }
+
+    /// Materialize a layer beneath the top by rebuilding it from the layer below
+    fn promote<Base: NodeLayer<K, A, ()>>(&mut self, base: &Base) {
+        let entries = base.range(Bound::Unbounded, Bound::Unbounded).anchored();
+        let mut promoted = PromotedLayers::new(group(entries));
+
+        while Self::is_over_capacity(promoted.root.len()) {
+            promoted.promote();
+        }
+
+        self.inner.clear();
+        self.promoted = Some(promoted);
+    }
+}
+
+impl<K, X, BA, const MAX_ENTRIES: usize> RemapComponent<BA>
+    for BTreeTopComponent<K, X, BA, MAX_ENTRIES>
+where
+    K: Key,
+    BA: Address + Copy + Hash,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        let moved = |address: &mut BA| {
+            if let Some(&to) = remap.get(address) {
+                *address = to;
+            }
+        };
+
+        self.inner.values_mut().for_each(moved);
+
+        if let Some(ref mut promoted) = self.promoted {
+            for leaf in promoted.leaves.iter_mut() {
+                for index in 0..leaf.len() {
+                    moved(leaf.get_value_mut(index).unwrap());
+                }
+            }
+        }
+    }
+}
+
+impl<K, X, Base, BA: Copy, const MAX_ENTRIES: usize> TopComponent<K, Base, BA, ()>
+    for BTreeTopComponent<K, X, BA, MAX_ENTRIES>
 where
     Base: NodeLayer<K, BA, ()>,
     K: Key,
     BA: Address,
 {
-    fn search(&self, _: &Base, key: &K) -> BA {
-        *self
-            .inner
-            .range(..=key)
-            .next_back()
-            .unwrap_or(self.inner.range(..).next().unwrap())
-            .1
+    fn search(&self, base: &Base, key: &K) -> BA {
+        let key = &KeyBound::Key(*key);
+
+        match self.promoted {
+            Some(ref promoted) => promoted.search(key),
+            // An empty top falls back to the first node of the layer below, which always exists
+            None => floor(&self.inner, key).unwrap_or_else(|| base.first()),
+        }
+    }
+
+    fn probe(&self, _: &Base, key: &K) -> Probe {
+        match self.promoted {
+            Some(ref promoted) => promoted.probe(&KeyBound::Key(*key)),
+            None => Probe::binary_search(self.inner.len()),
+        }
     }
 
     fn insert(&mut self, base: &mut Base, prop: PropagateInsert<K, BA, ()>) {
         match prop {
             PropagateInsert::Single(key, address, _) => {
-                self.inner.insert(key, address);
+                let key = KeyBound::Key(key);
                 base.set_parent(address, ());
+
+                match self.promoted {
+                    Some(ref mut promoted) => {
+                        promoted.insert(key, address);
+
+                        if Self::is_over_capacity(promoted.root.len()) {
+                            promoted.promote();
+                        }
+                    }
+                    None => {
+                        self.inner.insert(key, address);
+
+                        if Self::is_over_capacity(self.inner.len()) {
+                            self.promote(base);
+                        }
+                    }
+                }
             }
             _ => unimplemented!(),
         }
@@ -39,16 +271,25 @@ where
 
     fn build(base: &mut Base) -> Self {
         let mut inner = BTreeMap::new();
-        let mut iter = base.range_mut(Bound::Unbounded, Bound::Unbounded);
+        let mut iter = base
+            .range_mut(Bound::Unbounded, Bound::Unbounded)
+            .anchored();
 
         while let Some((key, address, parent)) = iter.next() {
-            inner.insert(key.clone(), address);
+            inner.insert(key, address);
             parent.set(());
         }
 
-        Self {
+        let mut result = Self {
             inner,
-            _ph: std::marker::PhantomData,
+            promoted: None,
+            _ph: core::marker::PhantomData,
+        };
+
+        if Self::is_over_capacity(result.inner.len()) {
+            result.promote(base);
         }
+
+        result
     }
 }
@@ -0,0 +1,252 @@
+use crate::common::list::memory::*;
+use crate::common::prefetch::prefetch_slice;
+use crate::explain::Probe;
+use crate::node_layer::{impl_node_layer, NodeLayer};
+use crate::traits::{Address, KeyBound, KeyBounded, PrefetchData};
+use crate::{component::*, Key};
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+use num::PrimInt;
+
+// -------------------------------------------------------
+//                  Bucket Node
+// -------------------------------------------------------
+
+/// An equi-depth bucket, the boundaries are kept apart from the addresses so that the binary
+/// search only touches a flat array of keys
+#[derive(Clone)]
+pub struct BucketNode<K, V> {
+    boundaries: Vec<K>,
+    addresses: Vec<V>,
+}
+
+impl<K, V> Default for BucketNode<K, V> {
+    fn default() -> Self {
+        Self {
+            boundaries: Vec::new(),
+            addresses: Vec::new(),
+        }
+    }
+}
+
+impl<K: Ord, V> BucketNode<K, V> {
+    pub fn len(&self) -> usize {
+        self.boundaries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boundaries.is_empty()
+    }
+
+    /// Address of the last boundary less than or equal to `key`, or the first address
+    pub fn search(&self, key: &K) -> &V {
+        let index = self.boundaries.partition_point(|bound| bound <= key);
+        &self.addresses[index.saturating_sub(1)]
+    }
+
+    /// Number of comparisons `search` makes
+    pub fn search_comparisons(&self, key: &K) -> usize {
+        let mut comparisons = 0;
+        let _ = self.boundaries.partition_point(|bound| {
+            comparisons += 1;
+            bound <= key
+        });
+
+        comparisons
+    }
+
+    fn push(&mut self, key: K, address: V) {
+        self.boundaries.push(key);
+        self.addresses.push(address);
+    }
+
+    fn insert(&mut self, key: K, address: V) {
+        let index = self.boundaries.partition_point(|bound| *bound < key);
+        self.boundaries.insert(index, key);
+        self.addresses.insert(index, address);
+    }
+
+    /// Point every address at where its node moved when the layer below was compacted
+    fn remap(&mut self, remap: &HashMap<V, V>)
+    where
+        V: Eq + Hash + Clone,
+    {
+        for address in self.addresses.iter_mut() {
+            if let Some(to) = remap.get(address) {
+                *address = to.clone();
+            }
+        }
+    }
+
+    fn split(&mut self) -> Self {
+        let split_idx = self.len() / 2;
+
+        Self {
+            boundaries: self.boundaries.split_off(split_idx),
+            addresses: self.addresses.split_off(split_idx),
+        }
+    }
+}
+
+impl<K, V> KeyBounded<K> for BucketNode<K, V> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        match self.boundaries.first() {
+            Some(key) => KeyBound::Key(key),
+            None => KeyBound::NegInf,
+        }
+    }
+}
+
+impl<K, V> PrefetchData for BucketNode<K, V> {
+    fn prefetch_data(&self) {
+        prefetch_slice(&self.boundaries);
+        prefetch_slice(&self.addresses);
+    }
+}
+
+// -------------------------------------------------------
+//                  Internal Component
+// -------------------------------------------------------
+
+pub type BucketInternalAddress = ArenaID;
+
+/// Smallest bucket which is split, so that a layer built from few nodes doesn't degenerate into
+/// buckets of a single entry
+const MIN_SPLIT_LEN: usize = 16;
+
+/// Partitions the layer below into `COUNT` buckets holding the same number of nodes each. Inserts
+/// grow the buckets, and a bucket holding twice its built depth is split in half.
+#[derive(Clone)]
+pub struct BucketInternalComponent<K: Key + PrimInt, X: 'static, const COUNT: usize, BA, PA> {
+    inner: MemoryList<BucketNode<K, BA>, PA>,
+    depth: usize,
+    _ph: core::marker::PhantomData<X>,
+}
+
+impl<K, X, const COUNT: usize, BA, PA> NodeLayer<K, BucketInternalAddress, PA>
+    for BucketInternalComponent<K, X, COUNT, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    impl_node_layer!(ArenaID, PA);
+}
+
+impl<K: Key + PrimInt, X, const COUNT: usize, BA, PA> BucketInternalComponent<K, X, COUNT, BA, PA> {
+    /// Number of entries in a bucket right after the layer was built
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl<K, X, BA, PA, B: NodeLayer<K, BA, BucketInternalAddress>, const COUNT: usize>
+    InternalComponent<K, B, BA, BucketInternalAddress, PA>
+    for BucketInternalComponent<K, X, COUNT, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    fn search(&self, _: &B, ptr: BucketInternalAddress, key: &K) -> BA {
+        self.inner[ptr].search(key).clone()
+    }
+
+    fn probe(&self, _: &B, ptr: BucketInternalAddress, key: &K) -> Probe {
+        Probe::counted(self.inner[ptr].search_comparisons(key))
+    }
+
+    fn insert(
+        &mut self,
+        base: &mut B,
+        prop: PropagateInsert<K, BA, BucketInternalAddress>,
+    ) -> Option<PropagateInsert<K, BucketInternalAddress, PA>> {
+        match prop {
+            PropagateInsert::Single(key, address, ptr) => {
+                self.inner[ptr].insert(key, address.clone());
+                base.set_parent(address, ptr);
+
+                if self.inner[ptr].len() < (2 * self.depth).max(MIN_SPLIT_LEN) {
+                    return None;
+                }
+
+                let parent = self.inner.parent(ptr).unwrap();
+
+                // Split
+                let new_node = self.inner[ptr].split();
+                let new_node_ptr = self.inner.insert_after(new_node, ptr);
+
+                // Update all of the parents for the split node
+                for address in self.inner[new_node_ptr].addresses.iter() {
+                    base.set_parent(address.clone(), new_node_ptr);
+                }
+
+                Some(PropagateInsert::Single(
+                    self.inner[new_node_ptr].boundaries[0],
+                    new_node_ptr,
+                    parent,
+                ))
+            }
+            PropagateInsert::Replace { .. } => {
+                unimplemented!()
+            }
+        }
+    }
+
+    fn build(base: &mut B) -> Self {
+        let entries = base.node_count();
+        let depth = entries.div_ceil(COUNT.max(1)).max(1);
+
+        let mut inner: MemoryList<BucketNode<K, BA>, PA> = MemoryList::empty();
+        let mut ptr = inner.clear_with_hint(|| COUNT);
+        let mut iter = base
+            .range_mut(Bound::Unbounded, Bound::Unbounded)
+            .anchored();
+
+        while let Some((key, address, parent)) = iter.next() {
+            // If bucket is full, carry over to next
+            if inner[ptr].len() >= depth {
+                ptr = inner.insert_after(BucketNode::default(), ptr);
+            }
+
+            inner[ptr].push(key.resolve(), address.clone());
+            parent.set(ptr);
+        }
+
+        Self {
+            inner,
+            depth,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, X, const COUNT: usize, BA, PA> CompactComponent<BucketInternalAddress, PA>
+    for BucketInternalComponent<K, X, COUNT, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<BucketInternalAddress, BucketInternalAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<K, X, const COUNT: usize, BA, PA> RemapComponent<BA>
+    for BucketInternalComponent<K, X, COUNT, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address + Hash,
+    PA: Address,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        self.inner.for_each_mut(|node| node.remap(remap));
+    }
+}
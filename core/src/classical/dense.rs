@@ -0,0 +1,709 @@
+use crate::common::list::memory::*;
+use crate::common::prefetch::prefetch_slice;
+use crate::explain::Probe;
+use crate::learned::LayerReport;
+use crate::node_layer::{impl_node_layer, NodeLayer};
+use crate::traits::{Address, KeyBound, KeyBounded, PrefetchData};
+use crate::{component::*, Key, Value};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+use num::PrimInt;
+
+/// First key of the window of `PAGE` consecutive keys `key` falls into. Windows are aligned to
+/// multiples of `PAGE`, so every key falls into exactly one of them.
+pub fn window_start<K: Key + PrimInt, const PAGE: usize>(key: &K) -> K {
+    let page = K::from(PAGE).expect("Page size doesn't fit in the key type!");
+    let offset = *key % page;
+
+    // Negative keys leave a negative remainder, their window starts one page further down
+    if offset < K::zero() {
+        *key - offset - page
+    } else {
+        *key - offset
+    }
+}
+
+// -------------------------------------------------------
+//                  Dense Page
+// -------------------------------------------------------
+
+/// A window of `PAGE` consecutive keys, with a bit per key telling whether it's present, and the
+/// values of the present keys packed in key order
+#[derive(Clone)]
+pub struct DensePage<K, V, const PAGE: usize> {
+    /// The start of the window, except for the first page of the layer, which is routed every key
+    /// below it as well
+    min: KeyBound<K>,
+    start: K,
+
+    /// Bit `i` is set if the key `start + i` is present. Empty until the page is placed at a window.
+    present: Vec<u64>,
+    values: Vec<V>,
+}
+
+impl<K: Key + PrimInt, V, const PAGE: usize> Default for DensePage<K, V, PAGE> {
+    fn default() -> Self {
+        Self {
+            min: KeyBound::NegInf,
+            start: K::zero(),
+            present: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<K: Key + PrimInt, V, const PAGE: usize> DensePage<K, V, PAGE> {
+    fn new(min: KeyBound<K>, start: K) -> Self {
+        Self {
+            min,
+            start,
+            present: vec![0; PAGE.div_ceil(64)],
+            values: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether the page was placed at a window yet
+    fn is_placed(&self) -> bool {
+        !self.present.is_empty()
+    }
+
+    /// Bytes held on the heap by the bitmap and values
+    fn heap_size(&self) -> usize {
+        self.present.capacity() * core::mem::size_of::<u64>()
+            + self.values.capacity() * core::mem::size_of::<V>()
+    }
+
+    /// Position of `key` within the window, if it falls into it
+    fn offset(&self, key: &K) -> Option<usize> {
+        if !self.is_placed() || *key < self.start {
+            return None;
+        }
+
+        key.checked_sub(&self.start)?
+            .to_usize()
+            .filter(|&offset| offset < PAGE)
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        (self.present[offset / 64] >> (offset % 64)) & 1 == 1
+    }
+
+    /// Index of the value of the key at `offset`, the number of present keys below it
+    fn rank(&self, offset: usize) -> usize {
+        let (word, bit) = (offset / 64, offset % 64);
+        let below: u32 = self.present[..word]
+            .iter()
+            .map(|word| word.count_ones())
+            .sum();
+
+        (below + (self.present[word] & ((1 << bit) - 1)).count_ones()) as usize
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let offset = self.offset(key)?;
+        if !self.contains(offset) {
+            return None;
+        }
+
+        Some(&self.values[self.rank(offset)])
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let offset = self.offset(key)?;
+        if !self.contains(offset) {
+            return None;
+        }
+
+        let rank = self.rank(offset);
+        Some(&mut self.values[rank])
+    }
+
+    /// Set the key at `offset` to `value`, returning the value it held
+    fn insert(&mut self, offset: usize, value: V) -> Option<V> {
+        let rank = self.rank(offset);
+        if self.contains(offset) {
+            return Some(core::mem::replace(&mut self.values[rank], value));
+        }
+
+        self.present[offset / 64] |= 1 << (offset % 64);
+        self.values.insert(rank, value);
+        None
+    }
+}
+
+impl<K, V, const PAGE: usize> KeyBounded<K> for DensePage<K, V, PAGE> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        self.min.as_ref()
+    }
+}
+
+impl<K, V, const PAGE: usize> PrefetchData for DensePage<K, V, PAGE> {
+    fn prefetch_data(&self) {
+        prefetch_slice(&self.present);
+        prefetch_slice(&self.values);
+    }
+}
+
+// -------------------------------------------------------
+//                  Base Component
+// -------------------------------------------------------
+
+pub type DenseBaseAddress = ArenaID;
+
+/// A base layer for dense keys, such as sequence numbers or row IDs. Every node is a page holding
+/// the window of `PAGE` consecutive keys it's placed at, so a lookup finds its key by offset rather
+/// than by searching, and a page only holds the keys which are present, missing keys cost a bit.
+/// A key outside of every page places a new page at its window.
+#[derive(Clone)]
+pub struct DenseBaseComponent<K: Key + PrimInt, V, const PAGE: usize, PA> {
+    inner: MemoryList<DensePage<K, V, PAGE>, PA>,
+
+    /// Number of entries across every page
+    len: usize,
+}
+
+impl<K, V, const PAGE: usize, PA> NodeLayer<K, DenseBaseAddress, PA>
+    for DenseBaseComponent<K, V, PAGE, PA>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address,
+{
+    impl_node_layer!(ArenaID, PA);
+}
+
+impl<K, V, const PAGE: usize, PA> BaseComponent<K, V, DenseBaseAddress, PA>
+    for DenseBaseComponent<K, V, PAGE, PA>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address,
+{
+    fn insert(
+        &mut self,
+        ptr: DenseBaseAddress,
+        key: K,
+        value: V,
+    ) -> BaseInsert<K, V, DenseBaseAddress, PA> {
+        let before = self.inner[ptr].heap_size();
+
+        // The first page of an empty layer is placed at the window of the first key
+        if !self.inner[ptr].is_placed() {
+            self.inner[ptr] = DensePage::new(KeyBound::NegInf, window_start::<K, PAGE>(&key));
+        }
+
+        if let Some(offset) = self.inner[ptr].offset(&key) {
+            let previous = self.inner[ptr].insert(offset, value);
+            if previous.is_none() {
+                self.len += 1;
+            }
+
+            let after = self.inner[ptr].heap_size();
+            self.inner.track_heap(before, after);
+
+            return BaseInsert {
+                previous,
+                propagate: None,
+            };
+        }
+
+        let parent = self.inner.parent(ptr).unwrap();
+        let start = window_start::<K, PAGE>(&key);
+
+        // Only the first page is routed keys below its window. It's placed at the key's window
+        // instead, and its keys move to a page of their own right after it.
+        let new_page_ptr = if key < self.inner[ptr].start {
+            let mut moved = core::mem::replace(
+                &mut self.inner[ptr],
+                DensePage::new(KeyBound::NegInf, start),
+            );
+            moved.min = KeyBound::Key(moved.start);
+
+            let offset = self.inner[ptr].offset(&key).unwrap();
+            self.inner[ptr].insert(offset, value);
+            self.inner.insert_after(moved, ptr)
+        } else {
+            let mut page = DensePage::new(KeyBound::Key(start), start);
+            page.insert(page.offset(&key).unwrap(), value);
+            self.inner.insert_after(page, ptr)
+        };
+        self.len += 1;
+
+        let after = self.inner[ptr].heap_size() + self.inner[new_page_ptr].heap_size();
+        self.inner.track_heap(before, after);
+
+        BaseInsert {
+            previous: None,
+            propagate: Some(PropagateInsert::Single(
+                self.inner[new_page_ptr].start,
+                new_page_ptr,
+                parent,
+            )),
+        }
+    }
+
+    fn search(&self, ptr: DenseBaseAddress, key: &K) -> Option<V> {
+        self.get(ptr, key).cloned()
+    }
+
+    fn get(&self, ptr: DenseBaseAddress, key: &K) -> Option<&V> {
+        self.inner[ptr].get(key)
+    }
+
+    fn get_mut(&mut self, ptr: DenseBaseAddress, key: &K) -> Option<&mut V> {
+        self.inner[ptr].get_mut(key)
+    }
+
+    fn probe(&self, _: DenseBaseAddress, _: &K) -> Probe {
+        // The key is found by its offset, the only comparison is whether it falls into the window
+        Probe::counted(1)
+    }
+
+    fn absorbs(&self, ptr: DenseBaseAddress, key: &K) -> bool {
+        !self.inner[ptr].is_placed() || self.inner[ptr].offset(key).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn empty() -> Self {
+        Self {
+            inner: MemoryList::empty(),
+            len: 0,
+        }
+    }
+
+    fn build(iter: impl Iterator<Item = (K, V)>) -> Self {
+        let mut inner: MemoryList<DensePage<K, V, PAGE>, PA> = MemoryList::empty();
+        let mut ptr = inner.clear_with_hint(|| 1);
+        let mut len = 0;
+
+        for (key, value) in iter {
+            if !inner[ptr].is_placed() {
+                inner[ptr] = DensePage::new(KeyBound::NegInf, window_start::<K, PAGE>(&key));
+            }
+
+            // Keys arrive in order, so a key past the window of the page opens the next one
+            let offset = match inner[ptr].offset(&key) {
+                Some(offset) => offset,
+                None => {
+                    let start = window_start::<K, PAGE>(&key);
+                    ptr = inner.insert_after(DensePage::new(KeyBound::Key(start), start), ptr);
+                    inner[ptr].offset(&key).unwrap()
+                }
+            };
+
+            if inner[ptr].insert(offset, value).is_none() {
+                len += 1;
+            }
+        }
+
+        let mut heap = 0;
+        inner.for_each_mut(|page| heap += page.heap_size());
+        inner.track_heap(0, heap);
+
+        Self { inner, len }
+    }
+}
+
+impl<K, V, const PAGE: usize, PA> CompactComponent<DenseBaseAddress, PA>
+    for DenseBaseComponent<K, V, PAGE, PA>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<DenseBaseAddress, DenseBaseAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+// -------------------------------------------------------
+//                  Dense Table
+// -------------------------------------------------------
+
+/// Most slots the table of a dense layer holds per child, past which slots are widened so that
+/// sparse children don't blow up the table
+const MAX_SLOTS_PER_CHILD: usize = 2;
+
+/// Width of the slots of a table over `bounds`, the lower bounds of the children it routes to.
+/// Slots no wider than the closest pair of bounds hold at most one bound each, unless that would
+/// take more than `MAX_SLOTS_PER_CHILD` slots per child.
+pub(crate) fn dense_stride<K: Key + PrimInt>(bounds: &[K]) -> K {
+    let closest = bounds
+        .windows(2)
+        .map(|pair| pair[1].checked_sub(&pair[0]).unwrap_or(K::max_value()))
+        .min()
+        .unwrap_or(K::one());
+
+    let span = match (bounds.first(), bounds.last()) {
+        (Some(first), Some(last)) => last.checked_sub(first).unwrap_or(K::max_value()),
+        _ => K::zero(),
+    };
+    let widest = match K::from(MAX_SLOTS_PER_CHILD * bounds.len()) {
+        Some(slots) if slots > K::zero() => span / slots + K::one(),
+        _ => K::one(),
+    };
+
+    closest.max(widest).max(K::one())
+}
+
+/// The children of a dense internal layer, along with a table of the last child bounded below
+/// every slot of `stride` keys past the lower bound of the second child. A lookup finds the slot of
+/// its key by dividing, then steps over the children bounded within that slot before the key.
+#[derive(Clone)]
+pub struct DenseTable<K, V> {
+    bounds: Vec<K>,
+    addresses: Vec<V>,
+    stride: K,
+    table: Vec<usize>,
+}
+
+impl<K: Key + PrimInt, V> Default for DenseTable<K, V> {
+    fn default() -> Self {
+        Self {
+            bounds: Vec::new(),
+            addresses: Vec::new(),
+            stride: K::one(),
+            table: Vec::new(),
+        }
+    }
+}
+
+impl<K: Key + PrimInt, V: Address> DenseTable<K, V> {
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// Bytes held on the heap by the children and the table
+    fn heap_size(&self) -> usize {
+        self.bounds.capacity() * core::mem::size_of::<K>()
+            + self.addresses.capacity() * core::mem::size_of::<V>()
+            + self.table.capacity() * core::mem::size_of::<usize>()
+    }
+
+    /// Slot of the table `key` falls into, if it falls into the table at all
+    fn slot(&self, key: &K) -> Option<usize> {
+        let origin = self.bounds.get(1)?;
+        if key < origin {
+            return None;
+        }
+
+        (key.checked_sub(origin)? / self.stride).to_usize()
+    }
+
+    /// Index of the child `key` falls into, along with the number of comparisons made to find it.
+    /// The first child is routed every key below the second, whatever its own bound, since the
+    /// leftmost bound of a layer can be stale.
+    fn route(&self, key: &K) -> (usize, usize) {
+        if self.bounds.len() < 2 || *key < self.bounds[1] {
+            return (0, 1);
+        }
+
+        let last = self.table.len() - 1;
+        let mut index = self.table[self.slot(key).map_or(last, |slot| slot.min(last))];
+
+        let mut comparisons = 1;
+        while index + 1 < self.bounds.len() && self.bounds[index + 1] <= *key {
+            comparisons += 1;
+            index += 1;
+        }
+
+        (index, comparisons)
+    }
+
+    fn push(&mut self, key: K, address: V) {
+        self.bounds.push(key);
+        self.addresses.push(address);
+    }
+
+    /// Insert a child at `index`, which is never 0 since the first child is only ever split
+    fn insert(&mut self, index: usize, key: K, address: V) {
+        let slots = self.table.len();
+        let resume = self
+            .table
+            .get(self.slot(&key).unwrap_or(0).min(slots.saturating_sub(1)));
+        let resume = resume.copied().filter(|&child| child < index);
+
+        self.bounds.insert(index, key);
+        self.addresses.insert(index, address);
+
+        // A new second child moves the origin of the table
+        let (slot, resume) = match (self.slot(&key), resume) {
+            (Some(slot), Some(resume)) if index > 1 => (slot, resume),
+            _ => return self.reindex(),
+        };
+
+        if slot >= slots {
+            if slot >= MAX_SLOTS_PER_CHILD * self.len() {
+                return self.reindex();
+            }
+
+            self.table.resize(slot + 1, 0);
+        }
+
+        // Slots below the key's keep the children they had, which all sit below the new one
+        self.fill(slot.min(slots - 1), resume);
+    }
+
+    /// Size the slots to the children and fill the whole table again
+    fn reindex(&mut self) {
+        self.table.clear();
+        if self.bounds.len() < 2 {
+            return;
+        }
+
+        self.stride = dense_stride(&self.bounds[1..]);
+
+        let last = self.bounds.last().unwrap();
+        let span = last.checked_sub(&self.bounds[1]).unwrap_or(K::max_value());
+        let slots = (span / self.stride).to_usize().unwrap_or(0) + 1;
+        self.table.resize(slots, 0);
+        self.fill(0, 1);
+    }
+
+    /// Fill the slots from `from` onwards, stepping forward from the child at `index`, which is
+    /// bounded below the first of them
+    fn fill(&mut self, from: usize, mut index: usize) {
+        let origin = self.bounds[1];
+
+        for slot in from..self.table.len() {
+            let start = origin + self.stride * K::from(slot).unwrap();
+            while index + 1 < self.bounds.len() && self.bounds[index + 1] <= start {
+                index += 1;
+            }
+
+            self.table[slot] = index;
+        }
+    }
+
+    /// Point every address at where its node moved when the layer below was compacted
+    fn remap(&mut self, remap: &HashMap<V, V>)
+    where
+        V: Hash,
+    {
+        for address in self.addresses.iter_mut() {
+            if let Some(to) = remap.get(address) {
+                *address = to.clone();
+            }
+        }
+    }
+}
+
+impl<K, V> KeyBounded<K> for DenseTable<K, V> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        // The table is the only node of its layer
+        KeyBound::NegInf
+    }
+}
+
+impl<K, V> PrefetchData for DenseTable<K, V> {
+    fn prefetch_data(&self) {
+        prefetch_slice(&self.table);
+        prefetch_slice(&self.bounds);
+        prefetch_slice(&self.addresses);
+    }
+}
+
+// -------------------------------------------------------
+//                  Internal Component
+// -------------------------------------------------------
+
+pub type DenseInternalAddress = ArenaID;
+
+/// An internal layer routing to the layer below through a single `DenseTable`, so a lookup takes a
+/// division and a step when the nodes below are spread evenly, such as the pages of a dense base.
+/// The table only grows, so the layer never propagates an insert to the layer above.
+#[derive(Clone)]
+pub struct DenseInternalComponent<K: Key + PrimInt, X: 'static, BA, PA> {
+    inner: MemoryList<DenseTable<K, BA>, PA>,
+    _ph: core::marker::PhantomData<X>,
+}
+
+impl<K, X, BA, PA> NodeLayer<K, DenseInternalAddress, PA> for DenseInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    impl_node_layer!(ArenaID, PA);
+}
+
+impl<K, X, BA, PA> DenseInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    /// Summarize the table, as a report of a layer without models
+    pub fn report(&self) -> LayerReport {
+        let table = &self.inner[self.inner.first()];
+        LayerReport::from_nodes(0, core::iter::once((table.len(), 0)))
+    }
+}
+
+impl<K, X, BA, PA, B: NodeLayer<K, BA, DenseInternalAddress>>
+    InternalComponent<K, B, BA, DenseInternalAddress, PA> for DenseInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    fn search(&self, _: &B, ptr: DenseInternalAddress, key: &K) -> BA {
+        let table = &self.inner[ptr];
+        let (index, _) = table.route(key);
+
+        table.addresses[index].clone()
+    }
+
+    fn probe(&self, _: &B, ptr: DenseInternalAddress, key: &K) -> Probe {
+        let (_, comparisons) = self.inner[ptr].route(key);
+        Probe::counted(comparisons)
+    }
+
+    fn insert(
+        &mut self,
+        base: &mut B,
+        prop: PropagateInsert<K, BA, DenseInternalAddress>,
+    ) -> Option<PropagateInsert<K, DenseInternalAddress, PA>> {
+        match prop {
+            PropagateInsert::Single(key, address, ptr) => {
+                let before = self.inner[ptr].heap_size();
+
+                // A node split off below lands right after the child it was split from
+                let index = if self.inner[ptr].is_empty() {
+                    0
+                } else {
+                    self.inner[ptr].route(&key).0 + 1
+                };
+                self.inner[ptr].insert(index, key, address.clone());
+                base.set_parent(address, ptr);
+
+                let after = self.inner[ptr].heap_size();
+                self.inner.track_heap(before, after);
+
+                None
+            }
+            PropagateInsert::Replace { .. } => {
+                unimplemented!()
+            }
+        }
+    }
+
+    fn build(base: &mut B) -> Self {
+        let mut inner: MemoryList<DenseTable<K, BA>, PA> = MemoryList::empty();
+        let ptr = inner.clear_with_hint(|| 1);
+
+        let mut iter = base.range_mut(Bound::Unbounded, Bound::Unbounded);
+        while let Some((key, address, parent)) = iter.next() {
+            inner[ptr].push(key.resolve(), address.clone());
+            parent.set(ptr);
+        }
+
+        inner[ptr].reindex();
+
+        let heap = inner[ptr].heap_size();
+        inner.track_heap(0, heap);
+
+        Self {
+            inner,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, X, BA, PA> CompactComponent<DenseInternalAddress, PA>
+    for DenseInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<DenseInternalAddress, DenseInternalAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<K, X, BA, PA> RemapComponent<BA> for DenseInternalComponent<K, X, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address + Hash,
+    PA: Address,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        self.inner.for_each_mut(|table| table.remap(remap));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_start_aligns_keys() {
+        assert_eq!(window_start::<i64, 64>(&0), 0);
+        assert_eq!(window_start::<i64, 64>(&63), 0);
+        assert_eq!(window_start::<i64, 64>(&64), 64);
+        assert_eq!(window_start::<i64, 64>(&-1), -64);
+        assert_eq!(window_start::<i64, 64>(&-64), -64);
+        assert_eq!(window_start::<i64, 64>(&i64::MIN), i64::MIN);
+        assert_eq!(window_start::<u8, 100>(&u8::MAX), 200);
+    }
+
+    #[test]
+    fn dense_table_routes_keys() {
+        let mut table: DenseTable<u64, ArenaID> = DenseTable::default();
+        let addresses: Vec<ArenaID> = (0..8).map(|i| ArenaID::from_raw_parts(i, 0)).collect();
+
+        for (i, bound) in [0, 64, 128, 256, 320].into_iter().enumerate() {
+            table.push(bound, addresses[i]);
+        }
+        table.reindex();
+
+        let route = |table: &DenseTable<u64, ArenaID>, key| table.route(&key).0;
+        assert_eq!(route(&table, 10), 0);
+        assert_eq!(route(&table, 64), 1);
+        assert_eq!(route(&table, 200), 2);
+        assert_eq!(route(&table, 319), 3);
+        assert_eq!(route(&table, 1000), 4);
+
+        // Evenly spaced children are found in a single step
+        assert_eq!(table.route(&300).1, 1);
+
+        // Children landing between, before and past the others
+        table.insert(3, 192, addresses[5]);
+        table.insert(1, 32, addresses[6]);
+        table.insert(7, 5000, addresses[7]);
+        let bounds = [0, 32, 64, 128, 192, 256, 320, 5000];
+        for (index, &bound) in bounds.iter().enumerate() {
+            assert_eq!(route(&table, bound), index);
+            assert_eq!(route(&table, bound + 1), index);
+        }
+        assert_eq!(route(&table, 31), 0);
+        assert_eq!(route(&table, u64::MAX), 7);
+    }
+}
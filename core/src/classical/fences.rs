@@ -0,0 +1,341 @@
+use crate::common::list::memory::*;
+use crate::common::prefetch::prefetch_slice;
+use crate::explain::Probe;
+use crate::node_layer::{impl_node_layer, NodeLayer};
+use crate::traits::{Address, KeyBound, KeyBounded, PrefetchData};
+use crate::{component::*, Key};
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+use num::PrimInt;
+
+/// The leading `bytes` bytes of `key`, at most 8, as an integer which orders keys the same way,
+/// except that keys sharing those bytes tie. Signed keys have their sign bit flipped, so that they
+/// order as unsigned integers.
+pub fn truncate<K: Key + PrimInt>(key: &K, bytes: usize) -> u64 {
+    let bits = K::zero().count_zeros();
+    let shift = bits.saturating_sub(8 * bytes.min(8) as u32);
+
+    let key = if K::min_value() < K::zero() {
+        *key ^ K::min_value()
+    } else {
+        *key
+    };
+    let prefix = key.unsigned_shr(shift);
+
+    // Keys of at most 64 bits keep every bit, including the sign bit, which `to_u64` rejects
+    prefix.to_u64().unwrap_or_else(|| {
+        let low = (prefix ^ K::min_value()).to_u64().unwrap_or(0);
+        low | 1 << (bits - 1)
+    })
+}
+
+// -------------------------------------------------------
+//                  Fence Node
+// -------------------------------------------------------
+
+/// A node holding a truncated fence key per child in place of its full lower bound. Only the
+/// lower bound of the node itself is kept in full, since the layer above is routed by it.
+#[derive(Clone)]
+pub struct FenceNode<K, V> {
+    min: KeyBound<K>,
+    fences: Vec<u64>,
+    addresses: Vec<V>,
+}
+
+impl<K, V> Default for FenceNode<K, V> {
+    fn default() -> Self {
+        Self {
+            min: KeyBound::NegInf,
+            fences: Vec::new(),
+            addresses: Vec::new(),
+        }
+    }
+}
+
+impl<K: Key + PrimInt, V: Address> FenceNode<K, V> {
+    pub fn len(&self) -> usize {
+        self.fences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fences.is_empty()
+    }
+
+    /// Bytes held on the heap by the fences and addresses
+    fn heap_size(&self) -> usize {
+        self.fences.capacity() * core::mem::size_of::<u64>()
+            + self.addresses.capacity() * core::mem::size_of::<V>()
+    }
+
+    /// Index of the child `key` falls into, along with the number of comparisons made to find it.
+    /// The fences only narrow the search down to the children whose fence ties with the key's, the
+    /// last of which lower bounded by the key is found through the full lower bounds in `base`.
+    fn route<B, PA>(&self, base: &B, key: &K, bytes: usize) -> (usize, usize)
+    where
+        B: NodeLayer<K, V, PA>,
+        PA: Address,
+    {
+        let fence = truncate(key, bytes);
+
+        let mut comparisons = 0;
+        let mut index = self
+            .fences
+            .partition_point(|bound| {
+                comparisons += 1;
+                *bound <= fence
+            })
+            .saturating_sub(1);
+
+        while index > 0 && self.fences[index] == fence {
+            comparisons += 1;
+            if base.lower_bound(self.addresses[index].clone()).covers(key) {
+                break;
+            }
+
+            index -= 1;
+        }
+
+        (index, comparisons)
+    }
+
+    fn push(&mut self, key: K, address: V, bytes: usize) {
+        if self.is_empty() {
+            self.min = KeyBound::Key(key);
+        }
+
+        self.fences.push(truncate(&key, bytes));
+        self.addresses.push(address);
+    }
+
+    fn insert(&mut self, index: usize, key: &K, address: V, bytes: usize) {
+        self.fences.insert(index, truncate(key, bytes));
+        self.addresses.insert(index, address);
+    }
+
+    /// Point every address at where its node moved when the layer below was compacted
+    fn remap(&mut self, remap: &HashMap<V, V>)
+    where
+        V: Hash,
+    {
+        for address in self.addresses.iter_mut() {
+            if let Some(to) = remap.get(address) {
+                *address = to.clone();
+            }
+        }
+    }
+
+    /// Split off the upper half of the node, whose lower bound is that of its first child
+    fn split(&mut self, min: KeyBound<K>) -> Self {
+        let split_idx = self.len() / 2;
+
+        Self {
+            min,
+            fences: self.fences.split_off(split_idx),
+            addresses: self.addresses.split_off(split_idx),
+        }
+    }
+}
+
+impl<K, V> KeyBounded<K> for FenceNode<K, V> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        self.min.as_ref()
+    }
+}
+
+impl<K, V> PrefetchData for FenceNode<K, V> {
+    fn prefetch_data(&self) {
+        prefetch_slice(&self.fences);
+        prefetch_slice(&self.addresses);
+    }
+}
+
+// -------------------------------------------------------
+//                  Internal Component
+// -------------------------------------------------------
+
+pub type TruncatedBTreeInternalAddress = ArenaID;
+
+/// A BTree layer which keeps the leading `BYTES` bytes of every key as its fences, rather than the
+/// keys in full. Wide keys take less memory per entry and are routed by comparing integers of at
+/// most 64 bits, and only children whose fences tie with the key are told apart by their full
+/// lower bounds in the layer below.
+#[derive(Clone)]
+pub struct TruncatedBTreeInternalComponent<
+    K: Key + PrimInt,
+    X: 'static,
+    const FANOUT: usize,
+    const BYTES: usize,
+    BA,
+    PA,
+> {
+    inner: MemoryList<FenceNode<K, BA>, PA>,
+    _ph: core::marker::PhantomData<X>,
+}
+
+impl<K, X, const FANOUT: usize, const BYTES: usize, BA, PA>
+    NodeLayer<K, TruncatedBTreeInternalAddress, PA>
+    for TruncatedBTreeInternalComponent<K, X, FANOUT, BYTES, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+{
+    impl_node_layer!(ArenaID, PA);
+}
+
+impl<K, X, BA, PA, B, const FANOUT: usize, const BYTES: usize>
+    InternalComponent<K, B, BA, TruncatedBTreeInternalAddress, PA>
+    for TruncatedBTreeInternalComponent<K, X, FANOUT, BYTES, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+    B: NodeLayer<K, BA, TruncatedBTreeInternalAddress>,
+{
+    fn search(&self, base: &B, ptr: TruncatedBTreeInternalAddress, key: &K) -> BA {
+        let node = &self.inner[ptr];
+        let (index, _) = node.route(base, key, BYTES);
+
+        node.addresses[index].clone()
+    }
+
+    fn probe(&self, base: &B, ptr: TruncatedBTreeInternalAddress, key: &K) -> Probe {
+        let (_, comparisons) = self.inner[ptr].route(base, key, BYTES);
+        Probe::counted(comparisons)
+    }
+
+    fn insert(
+        &mut self,
+        base: &mut B,
+        prop: PropagateInsert<K, BA, TruncatedBTreeInternalAddress>,
+    ) -> Option<PropagateInsert<K, TruncatedBTreeInternalAddress, PA>> {
+        match prop {
+            PropagateInsert::Single(key, address, ptr) => {
+                let before = self.inner[ptr].heap_size();
+
+                // A node split off below lands right after the child it was split from
+                let index = if self.inner[ptr].is_empty() {
+                    0
+                } else {
+                    self.inner[ptr].route(base, &key, BYTES).0 + 1
+                };
+                self.inner[ptr].insert(index, &key, address.clone(), BYTES);
+                base.set_parent(address, ptr);
+
+                if self.inner[ptr].len() <= FANOUT {
+                    let after = self.inner[ptr].heap_size();
+                    self.inner.track_heap(before, after);
+                    return None;
+                }
+
+                let parent = self.inner.parent(ptr).unwrap();
+
+                // Split
+                let first = self.inner[ptr].addresses[self.inner[ptr].len() / 2].clone();
+                let min = base.lower_bound(first);
+                let new_node = self.inner[ptr].split(min);
+                let new_node_ptr = self.inner.insert_after(new_node, ptr);
+
+                let after = self.inner[ptr].heap_size() + self.inner[new_node_ptr].heap_size();
+                self.inner.track_heap(before, after);
+
+                // Update all of the parents for the split node
+                for address in self.inner[new_node_ptr].addresses.iter() {
+                    base.set_parent(address.clone(), new_node_ptr);
+                }
+
+                Some(PropagateInsert::Single(min.resolve(), new_node_ptr, parent))
+            }
+            PropagateInsert::Replace { .. } => {
+                unimplemented!()
+            }
+        }
+    }
+
+    fn build(base: &mut B) -> Self {
+        let nodes = base.node_count();
+
+        let mut inner: MemoryList<FenceNode<K, BA>, PA> = MemoryList::empty();
+        let mut ptr = inner.clear_with_hint(|| nodes / (FANOUT / 2).max(1) + 1);
+
+        let mut iter = base
+            .range_mut(Bound::Unbounded, Bound::Unbounded)
+            .anchored();
+        while let Some((key, address, parent)) = iter.next() {
+            // If node too full, carry over to next
+            if inner[ptr].len() >= (FANOUT / 2).max(1) {
+                ptr = inner.insert_after(FenceNode::default(), ptr);
+            }
+
+            inner[ptr].push(key.resolve(), address.clone(), BYTES);
+            parent.set(ptr);
+        }
+
+        let mut heap = 0;
+        inner.for_each_mut(|node| heap += node.heap_size());
+        inner.track_heap(0, heap);
+
+        Self {
+            inner,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, X, const FANOUT: usize, const BYTES: usize, BA, PA>
+    CompactComponent<TruncatedBTreeInternalAddress, PA>
+    for TruncatedBTreeInternalComponent<K, X, FANOUT, BYTES, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address + Hash,
+{
+    fn compact(&mut self) -> HashMap<TruncatedBTreeInternalAddress, TruncatedBTreeInternalAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<K, X, const FANOUT: usize, const BYTES: usize, BA, PA> RemapComponent<BA>
+    for TruncatedBTreeInternalComponent<K, X, FANOUT, BYTES, BA, PA>
+where
+    K: Key + PrimInt,
+    BA: Address + Hash,
+    PA: Address,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        self.inner.for_each_mut(|node| node.remap(remap));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_order() {
+        assert_eq!(truncate(&0x1234_5678u32, 2), 0x1234);
+        assert_eq!(truncate(&0x1234_5678u32, 8), 0x1234_5678);
+        assert_eq!(truncate(&u64::MAX, 8), u64::MAX);
+
+        let keys = [i128::MIN, -(1 << 100), -1, 0, 1, 1 << 100, i128::MAX];
+        for bytes in 1..=8 {
+            for pair in keys.windows(2) {
+                assert!(truncate(&pair[0], bytes) <= truncate(&pair[1], bytes));
+            }
+        }
+
+        // Signed keys of at most 64 bits keep their sign bit
+        assert!(truncate(&-1i64, 8) < truncate(&0i64, 8));
+        assert!(truncate(&i64::MIN, 8) < truncate(&-1i64, 8));
+        assert_eq!(truncate(&-1i8, 8), 0x7f);
+
+        // Keys sharing their leading bytes tie
+        assert_eq!(truncate(&1u128, 8), truncate(&2u128, 8));
+    }
+}
@@ -1,9 +1,28 @@
+pub mod art_top;
+#[cfg(feature = "std")]
 pub mod btree_disk;
 pub mod btree_memory;
+#[cfg(feature = "olc")]
+pub mod btree_olc;
 pub mod btree_top;
+pub mod bucket;
+pub mod dense;
+pub mod fences;
+pub mod split;
 
 mod node;
 
+pub use art_top::*;
+#[cfg(feature = "std")]
 pub use btree_disk::*;
 pub use btree_memory::*;
+#[cfg(feature = "olc")]
+pub use btree_olc::*;
 pub use btree_top::*;
+pub use bucket::*;
+pub use dense::{
+    DenseBaseAddress, DenseBaseComponent, DenseInternalAddress, DenseInternalComponent, DensePage,
+    DenseTable,
+};
+pub use fences::{FenceNode, TruncatedBTreeInternalAddress, TruncatedBTreeInternalComponent};
+pub use split::*;
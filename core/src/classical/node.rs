@@ -1,9 +1,16 @@
-use crate::traits::KeyBounded;
-use crate::traits::StaticBounded;
+#[cfg(feature = "std")]
+use crate::projection::{FieldSelector, ProjectSeed, Projectable, Skip};
+use crate::classical::split::SplitPolicy;
+use crate::common::prefetch::prefetch_slice;
+use crate::traits::{KeyBound, KeyBounded, PrefetchData};
+use core::ops::Deref;
+use core::ops::DerefMut;
+#[cfg(feature = "std")]
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+#[cfg(feature = "std")]
+use serde::Deserializer;
 use serde::{Deserialize, Serialize};
 use sorted_array::SortedArray;
-use std::ops::Deref;
-use std::ops::DerefMut;
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub struct BTreeNode<K: Ord, V, const FANOUT: usize> {
@@ -39,28 +46,57 @@ impl<K: Ord, V, const FANOUT: usize> BTreeNode<K, V, FANOUT> {
         self.inner.len() >= FANOUT / 2
     }
 
-    pub fn min(&self) -> &K
+    /// Smallest key in the node, which must not be empty
+    pub fn min(&self) -> &K {
+        &self.inner.entries()[0].key
+    }
+
+    pub fn split(&mut self) -> (K, Self)
     where
-        K: StaticBounded,
+        K: Clone,
     {
-        if self.is_empty() {
-            K::min_ref()
-        } else {
-            &self.inner.entries()[0].key
-        }
+        self.split_at(FANOUT / 2)
     }
 
-    pub fn split(&mut self) -> (K, Self)
+    /// Split the node to make room for `key`, keeping as many entries as the policy `P` picks
+    pub fn split_with<P: SplitPolicy>(&mut self, key: &K) -> (K, Self)
     where
         K: Clone,
     {
-        let split_idx = FANOUT / 2;
+        let len = self.inner.len();
+        let split_idx = P::split_index(len, self.inner.position(key));
+
+        self.split_at(split_idx.clamp(1, len - 1))
+    }
 
+    fn split_at(&mut self, split_idx: usize) -> (K, Self)
+    where
+        K: Clone,
+    {
         let key = self.inner.entries()[split_idx].key.clone();
         let map = self.inner.split_off(split_idx);
 
         (key, Self { inner: map })
     }
+
+    /// Remove every entry whose value is rejected by `keep`, returning the number of entries
+    /// removed
+    pub fn retain(&mut self, keep: impl Fn(&V) -> bool) -> usize
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut kept = SortedArray::empty();
+        for entry in self.inner.iter() {
+            if keep(&entry.value) {
+                kept.insert(entry.key.clone(), entry.value.clone());
+            }
+        }
+
+        let removed = self.inner.len() - kept.len();
+        self.inner = kept;
+        removed
+    }
 }
 
 impl<K: Ord, V, const FANOUT: usize> Default for BTreeNode<K, V, FANOUT> {
@@ -69,8 +105,359 @@ impl<K: Ord, V, const FANOUT: usize> Default for BTreeNode<K, V, FANOUT> {
     }
 }
 
-impl<K: StaticBounded, V, const FANOUT: usize> KeyBounded<K> for BTreeNode<K, V, FANOUT> {
-    fn lower_bound(&self) -> &K {
-        self.min()
+/// Entries are stored inline, unless `sorted_array` keeps them in a vector with its `safe` feature
+impl<K: Ord, V, const FANOUT: usize> PrefetchData for BTreeNode<K, V, FANOUT> {
+    fn prefetch_data(&self) {
+        prefetch_slice(self.inner.entries());
+    }
+}
+
+impl<K: Ord, V, const FANOUT: usize> KeyBounded<K> for BTreeNode<K, V, FANOUT> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        match self.inner.entries().first() {
+            Some(entry) => KeyBound::Key(&entry.key),
+            None => KeyBound::NegInf,
+        }
+    }
+}
+
+/// Reads the fields picked by `S` of the value stored at a key from a serialized `BTreeNode`,
+/// following the layout of `SortedArray`: a sequence of entries, each a key followed by a value.
+/// Entries are sorted, so reading stops as soon as the key is found or passed.
+#[cfg(feature = "std")]
+pub struct ProjectExact<'a, K, V, S> {
+    key: &'a K,
+    _ph: core::marker::PhantomData<(V, S)>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, K, V, S> ProjectExact<'a, K, V, S> {
+    pub fn new(key: &'a K) -> Self {
+        Self {
+            key,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V, S> DeserializeSeed<'de> for ProjectExact<'_, K, V, S>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de> + Projectable,
+    S: FieldSelector<V>,
+{
+    type Value = Option<S::Output>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V, S> Visitor<'de> for ProjectExact<'_, K, V, S>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de> + Projectable,
+    S: FieldSelector<V>,
+{
+    type Value = Option<S::Output>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a sequence of node entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        while let Some(entry) = seq.next_element_seed(EntrySeed::<K, V, S>::new(self.key))? {
+            match entry {
+                EntryProjection::Before => continue,
+                EntryProjection::Found(output) => return Ok(Some(output)),
+                EntryProjection::After => return Ok(None),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "std")]
+enum EntryProjection<T> {
+    Before,
+    Found(T),
+    After,
+}
+
+/// Reads a single entry, projecting its value only if it is stored at `key`
+#[cfg(feature = "std")]
+struct EntrySeed<'a, K, V, S>(ProjectExact<'a, K, V, S>);
+
+#[cfg(feature = "std")]
+impl<'a, K, V, S> EntrySeed<'a, K, V, S> {
+    fn new(key: &'a K) -> Self {
+        Self(ProjectExact::new(key))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V, S> DeserializeSeed<'de> for EntrySeed<'_, K, V, S>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de> + Projectable,
+    S: FieldSelector<V>,
+{
+    type Value = EntryProjection<S::Output>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V, S> Visitor<'de> for EntrySeed<'_, K, V, S>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de> + Projectable,
+    S: FieldSelector<V>,
+{
+    type Value = EntryProjection<S::Output>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a key followed by a value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let missing = || serde::de::Error::invalid_length(0, &self);
+        let key: K = seq.next_element()?.ok_or_else(missing)?;
+
+        Ok(match key.cmp(self.0.key) {
+            core::cmp::Ordering::Less => {
+                seq.next_element::<Skip<V>>()?.ok_or_else(missing)?;
+                EntryProjection::Before
+            }
+            core::cmp::Ordering::Equal => EntryProjection::Found(
+                seq.next_element_seed(ProjectSeed::<V, S>::default())?
+                    .ok_or_else(missing)?,
+            ),
+            core::cmp::Ordering::Greater => EntryProjection::After,
+        })
+    }
+}
+
+/// Reads whether a serialized `BTreeNode` holds a key, stepping over the values of smaller keys.
+/// The value of the key itself is never read, and reading stops as soon as the key is passed.
+#[cfg(feature = "std")]
+pub struct ContainsExact<'a, K, V> {
+    key: &'a K,
+    _ph: core::marker::PhantomData<V>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, K, V> ContainsExact<'a, K, V> {
+    pub fn new(key: &'a K) -> Self {
+        Self {
+            key,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Clone for ContainsExact<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self::new(self.key)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V> DeserializeSeed<'de> for ContainsExact<'_, K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V> Visitor<'de> for ContainsExact<'_, K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a sequence of node entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        while let Some(order) = seq.next_element_seed(KeySeed(self.clone()))? {
+            match order {
+                core::cmp::Ordering::Less => continue,
+                core::cmp::Ordering::Equal => return Ok(true),
+                core::cmp::Ordering::Greater => return Ok(false),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Reads the key of a single entry, compared against the key looked for, and steps over the value
+/// only if the key is smaller
+#[cfg(feature = "std")]
+struct KeySeed<'a, K, V>(ContainsExact<'a, K, V>);
+
+#[cfg(feature = "std")]
+impl<'de, K, V> DeserializeSeed<'de> for KeySeed<'_, K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = core::cmp::Ordering;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, K, V> Visitor<'de> for KeySeed<'_, K, V>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    type Value = core::cmp::Ordering;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a key followed by a value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let missing = || serde::de::Error::invalid_length(0, &self);
+        let key: K = seq.next_element()?.ok_or_else(missing)?;
+
+        let order = key.cmp(self.0.key);
+        if order == core::cmp::Ordering::Less {
+            seq.next_element::<Skip<V>>()?.ok_or_else(missing)?;
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::project;
+    use bincode::Options;
+
+    #[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+    struct Row {
+        id: u64,
+        name: String,
+    }
+
+    #[derive(Default)]
+    struct RowPartial {
+        id: Option<u64>,
+        name: Option<String>,
+    }
+
+    struct Id;
+    struct Name;
+
+    impl Projectable for Row {
+        const FIELDS: &'static [&'static str] = &["id", "name"];
+
+        type Partial = RowPartial;
+
+        fn read_fields<'de, A: SeqAccess<'de>>(
+            mut seq: A,
+            mask: u64,
+        ) -> Result<Self::Partial, A::Error> {
+            let mut partial = RowPartial::default();
+
+            if mask & 1 != 0 {
+                partial.id = seq.next_element()?;
+            } else {
+                seq.next_element::<Skip<u64>>()?;
+            }
+
+            if mask & 2 != 0 {
+                partial.name = seq.next_element()?;
+            }
+
+            Ok(partial)
+        }
+
+        fn copy_fields(&self, mask: u64) -> Self::Partial {
+            RowPartial {
+                id: (mask & 1 != 0).then_some(self.id),
+                name: (mask & 2 != 0).then(|| self.name.clone()),
+            }
+        }
+    }
+
+    impl FieldSelector<Row> for Id {
+        const MASK: u64 = 1;
+        type Output = u64;
+
+        fn get(partial: &RowPartial) -> u64 {
+            partial.id.unwrap()
+        }
+    }
+
+    impl FieldSelector<Row> for Name {
+        const MASK: u64 = 2;
+        type Output = String;
+
+        fn get(partial: &RowPartial) -> String {
+            partial.name.clone().unwrap()
+        }
+    }
+
+    #[test]
+    fn project_serialized_node() {
+        let mut node = BTreeNode::<u32, Row, 16>::empty();
+        for key in (0..10).map(|key| key * 2) {
+            let name = format!("row {}", key);
+            node.insert(key, Row { id: key as u64, name });
+        }
+
+        let data = bincode::serialize(&node).unwrap();
+        let read = |key: u32| {
+            bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .allow_trailing_bytes()
+                .deserialize_seed(ProjectExact::<u32, Row, (Name, Id)>::new(&key), &data)
+                .unwrap()
+        };
+
+        assert_eq!(read(0), Some(("row 0".to_string(), 0)));
+        assert_eq!(read(8), Some(("row 8".to_string(), 8)));
+        assert_eq!(read(18), Some(("row 18".to_string(), 18)));
+        assert_eq!(read(7), None);
+        assert_eq!(read(100), None);
+
+        let contains = |key: u32| {
+            bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .allow_trailing_bytes()
+                .deserialize_seed(ContainsExact::<u32, Row>::new(&key), &data)
+                .unwrap()
+        };
+
+        assert!(contains(0));
+        assert!(contains(18));
+        assert!(!contains(7));
+        assert!(!contains(100));
+
+        let row = node.get_exact(&4).unwrap();
+        assert_eq!(project::<Row, Id>(row), 4);
+        assert_eq!(project::<Row, (Id, Name)>(row), (4, "row 4".to_string()));
     }
 }
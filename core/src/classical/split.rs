@@ -0,0 +1,61 @@
+//! Where a full node of an in-memory BTree is split. An even split leaves room on both sides, which
+//! suits keys inserted in random order, but when keys mostly arrive in ascending order the left node
+//! is never written to again, and splitting it in half leaves every node of the layer half empty.
+
+/// Picks how many entries a full node keeps when it is split to make room for a key, with the
+/// others moved to a new node on its right. Selected with the `split` attribute of a `btree`
+/// component.
+pub trait SplitPolicy: 'static {
+    /// Entries kept out of the `len` entries of a full node, when the key being inserted belongs
+    /// at `position` among them. Clamped so that both nodes hold at least one entry.
+    fn split_index(len: usize, position: usize) -> usize;
+}
+
+/// Split in half, `split = even`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EvenSplit;
+
+impl SplitPolicy for EvenSplit {
+    fn split_index(len: usize, _: usize) -> usize {
+        len / 2
+    }
+}
+
+/// Keep nine tenths of the entries, leaving most of the room in the new node, `split = lean_right`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LeanRightSplit;
+
+impl SplitPolicy for LeanRightSplit {
+    fn split_index(len: usize, _: usize) -> usize {
+        len * 9 / 10
+    }
+}
+
+/// Keep every entry but the last when the key is appended past the end of the node, and split in
+/// half otherwise, `split = append`. Ascending inserts then fill nodes all the way, while the
+/// occasional insert in the middle of the key range still leaves room on both sides.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AppendSplit;
+
+impl SplitPolicy for AppendSplit {
+    fn split_index(len: usize, position: usize) -> usize {
+        if position >= len {
+            len - 1
+        } else {
+            len / 2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_index() {
+        assert_eq!(EvenSplit::split_index(32, 32), 16);
+        assert_eq!(LeanRightSplit::split_index(32, 0), 28);
+        assert_eq!(AppendSplit::split_index(32, 32), 31);
+        assert_eq!(AppendSplit::split_index(32, 5), 16);
+    }
+}
@@ -0,0 +1,548 @@
+//! Allocation strategies for the arenas backing in-memory layers. Arena slots are written as soon
+//! as they are reserved, so a strategy controls both how much memory a layer holds on to and where
+//! that memory is faulted in.
+
+use allocator_api2::alloc::{AllocError, Allocator, Global, Layout};
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// Decides how the arena behind a `MemoryList` is sized and allocated
+pub trait ArenaAlloc: Clone + 'static {
+    /// Where the slots of the arena live
+    type Storage: Allocator + Clone;
+
+    /// The allocator for the slots of a new arena
+    fn storage(&self) -> Self::Storage;
+
+    /// Number of slots to reserve when a layer is (re)built, `hint` lazily computes the expected
+    /// number of nodes in the layer
+    fn capacity(&self, hint: impl FnOnce() -> usize) -> usize;
+
+    /// Number of slots to add to a full arena currently holding `capacity` slots, zero leaves the
+    /// growth to the arena itself
+    fn grow(&self, capacity: usize) -> usize;
+
+    /// Perform an allocation of arena memory
+    fn allocate(&self, allocation: &mut dyn FnMut()) {
+        allocation()
+    }
+}
+
+/// Lets the arena grow on its own, this is what layers use unless told otherwise
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultAlloc;
+
+impl ArenaAlloc for DefaultAlloc {
+    type Storage = Global;
+
+    fn storage(&self) -> Global {
+        Global
+    }
+
+    fn capacity(&self, _: impl FnOnce() -> usize) -> usize {
+        0
+    }
+
+    fn grow(&self, _: usize) -> usize {
+        0
+    }
+}
+
+/// For build-once workloads, sizes the arena exactly to the build hint and afterwards only grows in
+/// fixed size chunks, so a layer never holds much more memory than it uses
+#[derive(Clone, Copy, Debug)]
+pub struct BumpAlloc {
+    pub chunk: usize,
+}
+
+impl Default for BumpAlloc {
+    fn default() -> Self {
+        Self { chunk: 1024 }
+    }
+}
+
+impl ArenaAlloc for BumpAlloc {
+    type Storage = Global;
+
+    fn storage(&self) -> Global {
+        Global
+    }
+
+    fn capacity(&self, hint: impl FnOnce() -> usize) -> usize {
+        hint()
+    }
+
+    fn grow(&self, _: usize) -> usize {
+        self.chunk.max(1)
+    }
+}
+
+/// Sizes the arena up front from the build hint, leaving `slack` (as a fraction of the hint) for
+/// nodes created by later inserts
+#[derive(Clone, Copy, Debug)]
+pub struct PresizedAlloc {
+    pub slack: f64,
+}
+
+impl Default for PresizedAlloc {
+    fn default() -> Self {
+        Self { slack: 0.25 }
+    }
+}
+
+impl ArenaAlloc for PresizedAlloc {
+    type Storage = Global;
+
+    fn storage(&self) -> Global {
+        Global
+    }
+
+    fn capacity(&self, hint: impl FnOnce() -> usize) -> usize {
+        num::Float::ceil(hint() as f64 * (1.0 + self.slack.max(0.0))) as usize
+    }
+
+    fn grow(&self, capacity: usize) -> usize {
+        capacity.max(1)
+    }
+}
+
+/// Faults the memory of the arena in on a single NUMA node, by pinning the allocating thread to the
+/// CPUs of that node for the duration of the allocation. Sizing is delegated to `inner`. Outside of
+/// Linux, without the `std` feature, or if the node does not exist, allocations are performed as
+/// usual.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NumaAlloc<A = PresizedAlloc> {
+    pub node: usize,
+    pub inner: A,
+}
+
+impl<A: ArenaAlloc> ArenaAlloc for NumaAlloc<A> {
+    type Storage = A::Storage;
+
+    fn storage(&self) -> A::Storage {
+        self.inner.storage()
+    }
+
+    fn capacity(&self, hint: impl FnOnce() -> usize) -> usize {
+        self.inner.capacity(hint)
+    }
+
+    fn grow(&self, capacity: usize) -> usize {
+        // The arena has to grow through `allocate` to stay on the node
+        self.inner.grow(capacity).max(capacity).max(1)
+    }
+
+    fn allocate(&self, allocation: &mut dyn FnMut()) {
+        #[cfg(all(target_os = "linux", feature = "std"))]
+        numa::with_node_affinity(self.node, allocation);
+
+        #[cfg(not(all(target_os = "linux", feature = "std")))]
+        allocation();
+    }
+}
+
+/// A byte buffer provided by the caller, which the arenas of a layout bump allocate their slots
+/// from. Memory is only handed back to the buffer if it was the last block allocated, so a layout
+/// is sized up front and should grow little afterwards. While `spill` is set, a request which
+/// doesn't fit is served from the heap instead and counted in `spilled`, so a build can find out
+/// how much memory it was short of. Otherwise such a request fails, and the arena panics.
+pub struct FixedBuffer {
+    start: AtomicPtr<u8>,
+    len: AtomicUsize,
+    used: AtomicUsize,
+
+    /// Number of blocks currently allocated from the buffer
+    live: AtomicUsize,
+
+    /// Bytes served from the heap since the buffer was provided
+    spilled: AtomicUsize,
+    spill: AtomicBool,
+}
+
+impl FixedBuffer {
+    pub const fn new() -> Self {
+        Self {
+            start: AtomicPtr::new(core::ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            used: AtomicUsize::new(0),
+            live: AtomicUsize::new(0),
+            spilled: AtomicUsize::new(0),
+            spill: AtomicBool::new(false),
+        }
+    }
+
+    /// Allocate from `buffer` from now on. Panics if an index still holds memory of the previous
+    /// buffer, since only one index can be built into the buffer of a layout at a time.
+    pub fn provide(&self, buffer: &'static mut [u8]) {
+        assert_eq!(
+            self.live.load(Ordering::Acquire),
+            0,
+            "The fixed buffer is still in use by another index!"
+        );
+
+        self.start.store(buffer.as_mut_ptr(), Ordering::Release);
+        self.len.store(buffer.len(), Ordering::Release);
+        self.used.store(0, Ordering::Release);
+        self.spilled.store(0, Ordering::Release);
+    }
+
+    /// Serve requests which don't fit from the heap while `spill` is set, rather than failing them
+    pub fn set_spill(&self, spill: bool) {
+        self.spill.store(spill, Ordering::Release);
+    }
+
+    /// Bytes allocated from the buffer so far, including padding and freed blocks which couldn't
+    /// be handed back
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Bytes requested from the heap while spilling, since the buffer was provided
+    pub fn spilled(&self) -> usize {
+        self.spilled.load(Ordering::Acquire)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Whether `ptr` points into the buffer
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let start = self.start.load(Ordering::Acquire);
+        let offset = (ptr as usize).wrapping_sub(start as usize);
+        !start.is_null() && offset < self.len.load(Ordering::Acquire)
+    }
+
+    /// Bump `used` past a block of `layout`, returning the offset of the block
+    fn bump(&self, layout: Layout) -> Option<usize> {
+        let start = self.start.load(Ordering::Acquire) as usize;
+        let len = self.len.load(Ordering::Acquire);
+        if start == 0 {
+            return None;
+        }
+
+        // Offset of the first address past `used` aligned for the block
+        let aligned = |used: usize| {
+            (start + used)
+                .checked_next_multiple_of(layout.align())
+                .map(|address| address - start)
+        };
+
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                let end = aligned(used)? + layout.size();
+                (end <= len).then_some(end)
+            })
+            .ok()
+            .and_then(aligned)
+    }
+}
+
+impl Default for FixedBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl Allocator for FixedBuffer {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if let Some(offset) = self.bump(layout) {
+            self.live.fetch_add(1, Ordering::AcqRel);
+
+            // SAFETY: the block lies inside the buffer, which starts at a non-null address
+            let start = self.start.load(Ordering::Acquire);
+            let ptr = unsafe { NonNull::new_unchecked(start.add(offset)) };
+            return Ok(NonNull::slice_from_raw_parts(ptr, layout.size()));
+        }
+
+        if !self.spill.load(Ordering::Acquire) {
+            return Err(AllocError);
+        }
+
+        self.spilled.fetch_add(layout.size(), Ordering::AcqRel);
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if !self.contains(ptr.as_ptr()) {
+            // SAFETY: blocks outside of the buffer were spilled onto the heap
+            return unsafe { Global.deallocate(ptr, layout) };
+        }
+
+        // Hand the block back if nothing was allocated after it
+        let offset = ptr.as_ptr() as usize - self.start.load(Ordering::Acquire) as usize;
+        let _ = self.used.compare_exchange(
+            offset + layout.size(),
+            offset,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+
+        self.live.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // Extend the block in place if nothing was allocated after it, which is how an arena
+        // sized by the build hint gets its slots
+        if self.contains(ptr.as_ptr()) && ptr.as_ptr().align_offset(new_layout.align()) == 0 {
+            let offset = ptr.as_ptr() as usize - self.start.load(Ordering::Acquire) as usize;
+            let fits = offset + new_layout.size() <= self.len.load(Ordering::Acquire);
+
+            if fits
+                && self
+                    .used
+                    .compare_exchange(
+                        offset + old_layout.size(),
+                        offset + new_layout.size(),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok()
+            {
+                return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+            }
+        }
+
+        let new = self.allocate(new_layout)?;
+
+        // SAFETY: the new block is a separate allocation at least as large as the old one
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                ptr.as_ptr(),
+                new.cast::<u8>().as_ptr(),
+                old_layout.size(),
+            );
+            self.deallocate(ptr, old_layout);
+        }
+
+        Ok(new)
+    }
+}
+
+/// Names the static `FixedBuffer` of a layout, which `create_kv_store!` generates for layouts with
+/// `alloc: fixed`
+pub trait StaticBuffer: 'static {
+    fn buffer() -> &'static FixedBuffer;
+}
+
+/// Places the arena in the `FixedBuffer` of `B`, sized exactly to the build hint and doubling once
+/// it is full, as the arena would on its own
+pub struct FixedAlloc<B>(PhantomData<B>);
+
+impl<B> Clone for FixedAlloc<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for FixedAlloc<B> {}
+
+impl<B> Default for FixedAlloc<B> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<B> core::fmt::Debug for FixedAlloc<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("FixedAlloc")
+    }
+}
+
+unsafe impl<B: StaticBuffer> Allocator for FixedAlloc<B> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        B::buffer().allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: every `FixedAlloc<B>` allocates from the same buffer
+        unsafe { B::buffer().deallocate(ptr, layout) }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: as above
+        unsafe { B::buffer().grow(ptr, old_layout, new_layout) }
+    }
+}
+
+impl<B: StaticBuffer> ArenaAlloc for FixedAlloc<B> {
+    type Storage = Self;
+
+    fn storage(&self) -> Self {
+        *self
+    }
+
+    fn capacity(&self, hint: impl FnOnce() -> usize) -> usize {
+        hint()
+    }
+
+    fn grow(&self, capacity: usize) -> usize {
+        capacity.max(1)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod numa {
+    use core::mem::{size_of, zeroed};
+
+    /// Parse a kernel cpu list, such as `0-3,8,10-11`
+    pub(super) fn parse_cpu_list(list: &str) -> Vec<usize> {
+        let mut cpus = Vec::new();
+
+        for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+            let bounds: Option<(usize, usize)> = match range.split_once('-') {
+                Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+                None => range.parse().ok().map(|cpu| (cpu, cpu)),
+            };
+
+            if let Some((start, end)) = bounds {
+                cpus.extend(start..=end);
+            }
+        }
+
+        cpus
+    }
+
+    pub(super) fn with_node_affinity(node: usize, allocation: &mut dyn FnMut()) {
+        let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+        let cpus = match std::fs::read_to_string(path) {
+            Ok(list) => parse_cpu_list(&list),
+            Err(_) => return allocation(),
+        };
+
+        // SAFETY: `cpu_set_t` is a plain bitmask, and both sets outlive the calls using them
+        unsafe {
+            let mut previous: libc::cpu_set_t = zeroed();
+            if libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut previous) != 0 {
+                return allocation();
+            }
+
+            let mut pinned: libc::cpu_set_t = zeroed();
+            for cpu in cpus
+                .into_iter()
+                .filter(|&cpu| cpu < libc::CPU_SETSIZE as usize)
+            {
+                libc::CPU_SET(cpu, &mut pinned);
+            }
+
+            if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &pinned) != 0 {
+                return allocation();
+            }
+
+            let _restore = RestoreAffinity(previous);
+            allocation();
+        }
+    }
+
+    /// Puts the affinity of the thread back as it is dropped, even if the allocation panicked
+    struct RestoreAffinity(libc::cpu_set_t);
+
+    impl Drop for RestoreAffinity {
+        fn drop(&mut self) {
+            // SAFETY: as above, the set outlives the call
+            unsafe {
+                libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &self.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alloc_capacity() {
+        assert_eq!(DefaultAlloc.capacity(|| 100), 0);
+        assert_eq!(BumpAlloc { chunk: 16 }.capacity(|| 100), 100);
+        assert_eq!(BumpAlloc { chunk: 16 }.grow(100), 16);
+        assert_eq!(PresizedAlloc { slack: 0.5 }.capacity(|| 100), 150);
+    }
+
+    #[test]
+    fn fixed_buffer_bumps() {
+        use crate::common::list::arena::Arena;
+
+        static BUFFER: FixedBuffer = FixedBuffer::new();
+        struct Buffer;
+
+        impl StaticBuffer for Buffer {
+            fn buffer() -> &'static FixedBuffer {
+                &BUFFER
+            }
+        }
+
+        BUFFER.provide(Box::leak(vec![0u8; 1024].into_boxed_slice()));
+
+        // The arena grows in place, since nothing was allocated after it
+        let mut arena: Arena<u64, _> = Arena::new_in(FixedAlloc::<Buffer>::default());
+        let (used, initial) = (BUFFER.used(), arena.memory_usage());
+        arena.reserve(36);
+        assert_eq!(BUFFER.used() - used, arena.memory_usage() - initial);
+
+        // Without spilling, a request which doesn't fit fails
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        assert!(BUFFER.allocate(layout).is_err());
+
+        BUFFER.set_spill(true);
+        let spilled = BUFFER.allocate(layout).unwrap();
+        assert!(!BUFFER.contains(spilled.cast::<u8>().as_ptr()));
+        assert_eq!(BUFFER.spilled(), 1024);
+        unsafe { BUFFER.deallocate(spilled.cast(), layout) };
+        BUFFER.set_spill(false);
+
+        // The last block is handed back to the buffer once it is freed
+        drop(arena);
+        assert_eq!(BUFFER.used(), used - initial);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "std"))]
+    fn numa_parse_cpu_list() {
+        assert_eq!(
+            numa::parse_cpu_list("0-3,8,10-11\n"),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(numa::parse_cpu_list("\n"), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "std"))]
+    fn numa_restores_affinity_on_panic() {
+        use core::mem::{size_of, zeroed};
+
+        let affinity = || unsafe {
+            let mut set: libc::cpu_set_t = zeroed();
+            libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set);
+            (0..libc::CPU_SETSIZE as usize)
+                .map(|cpu| libc::CPU_ISSET(cpu, &set))
+                .collect::<Vec<bool>>()
+        };
+
+        let before = affinity();
+        let result = std::panic::catch_unwind(|| {
+            numa::with_node_affinity(0, &mut || panic!("allocation failed"));
+        });
+
+        assert!(result.is_err());
+        assert_eq!(affinity(), before);
+    }
+
+    #[test]
+    fn numa_alloc_runs_allocation() {
+        let mut ran = false;
+        NumaAlloc::<DefaultAlloc>::default().allocate(&mut || ran = true);
+        assert!(ran);
+    }
+}
@@ -0,0 +1,259 @@
+//! A generational arena whose slots are stored in memory from an `Allocator`, so that the nodes of a
+//! layer can live in a buffer provided by the caller rather than on the heap. Free slots are kept
+//! in a list threaded through the arena, and every slot is tagged with the generation it was filled
+//! in, so an index to a slot which was freed and filled again is rejected rather than aliasing the
+//! new value.
+
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+
+/// Number of slots an empty arena starts out with
+const DEFAULT_CAPACITY: usize = 4;
+
+/// The slot of a value in an `Arena`, along with the generation the value was inserted in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Index {
+    index: usize,
+    generation: u64,
+}
+
+impl Index {
+    pub fn from_raw_parts(index: usize, generation: u64) -> Self {
+        Self { index, generation }
+    }
+
+    pub fn into_raw_parts(self) -> (usize, u64) {
+        (self.index, self.generation)
+    }
+}
+
+#[derive(Clone)]
+enum Slot<T> {
+    Free { next_free: Option<usize> },
+    Occupied { generation: u64, value: T },
+}
+
+#[derive(Clone)]
+pub struct Arena<T, A: Allocator = Global> {
+    items: Vec<Slot<T>, A>,
+    generation: u64,
+    free_list_head: Option<usize>,
+    len: usize,
+}
+
+impl<T, A: Allocator> Arena<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        let mut arena = Self {
+            items: Vec::new_in(alloc),
+            generation: 0,
+            free_list_head: None,
+            len: 0,
+        };
+
+        arena.reserve(DEFAULT_CAPACITY);
+        arena
+    }
+
+    /// Number of values in the arena
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of slots in the arena, free or not
+    pub fn capacity(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Bytes held by the slots of the arena
+    pub fn memory_usage(&self) -> usize {
+        self.items.capacity() * core::mem::size_of::<Slot<T>>()
+    }
+
+    /// Add `additional` free slots. Panics if the allocator is out of memory, which for a fixed
+    /// buffer means that the buffer is exhausted.
+    pub fn reserve(&mut self, additional: usize) {
+        let start = self.items.len();
+        let end = start + additional;
+
+        self.items
+            .try_reserve_exact(additional)
+            .expect("Out of memory for the slots of an arena!");
+
+        let head = self.free_list_head;
+        self.items.extend((start..end).map(|index| Slot::Free {
+            next_free: if index == end - 1 {
+                head
+            } else {
+                Some(index + 1)
+            },
+        }));
+        self.free_list_head = Some(start);
+    }
+
+    /// Insert a value into a free slot, adding slots if there is none
+    pub fn insert(&mut self, value: T) -> Index {
+        if self.free_list_head.is_none() {
+            self.reserve(self.items.len().max(1));
+        }
+
+        let index = self.free_list_head.unwrap();
+        let Slot::Free { next_free } = self.items[index] else {
+            unreachable!("Corrupt free list!")
+        };
+
+        self.free_list_head = next_free;
+        self.len += 1;
+        self.items[index] = Slot::Occupied {
+            generation: self.generation,
+            value,
+        };
+
+        Index {
+            index,
+            generation: self.generation,
+        }
+    }
+
+    /// Remove the value at `index`, which bumps the generation so that `index` can't alias the
+    /// next value inserted into its slot
+    pub fn remove(&mut self, index: Index) -> Option<T> {
+        self.get(index)?;
+
+        let slot = core::mem::replace(
+            &mut self.items[index.index],
+            Slot::Free {
+                next_free: self.free_list_head,
+            },
+        );
+
+        self.generation += 1;
+        self.free_list_head = Some(index.index);
+        self.len -= 1;
+
+        match slot {
+            Slot::Occupied { value, .. } => Some(value),
+            Slot::Free { .. } => unreachable!(),
+        }
+    }
+
+    /// Free every slot, keeping the memory of the arena. Slots are handed out front to back again.
+    pub fn clear(&mut self) {
+        self.items.clear();
+
+        let end = self.items.capacity();
+        self.items.extend((0..end).map(|index| Slot::Free {
+            next_free: (index + 1 < end).then_some(index + 1),
+        }));
+
+        if self.len > 0 {
+            self.generation += 1;
+        }
+
+        self.free_list_head = (end > 0).then_some(0);
+        self.len = 0;
+    }
+
+    pub fn contains(&self, index: Index) -> bool {
+        self.get(index).is_some()
+    }
+
+    pub fn get(&self, index: Index) -> Option<&T> {
+        match self.items.get(index.index) {
+            Some(Slot::Occupied { generation, value }) if *generation == index.generation => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
+        match self.items.get_mut(index.index) {
+            Some(Slot::Occupied { generation, value }) if *generation == index.generation => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Every value along with its index, in the order of the slots
+    pub fn iter(&self) -> impl Iterator<Item = (Index, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Occupied { generation, value } => Some((
+                    Index {
+                        index,
+                        generation: *generation,
+                    },
+                    value,
+                )),
+                Slot::Free { .. } => None,
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Index, &mut T)> {
+        self.items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Occupied { generation, value } => Some((
+                    Index {
+                        index,
+                        generation: *generation,
+                    },
+                    value,
+                )),
+                Slot::Free { .. } => None,
+            })
+    }
+}
+
+impl<T, A: Allocator> core::ops::Index<Index> for Arena<T, A> {
+    type Output = T;
+
+    fn index(&self, index: Index) -> &T {
+        self.get(index)
+            .expect("No value at this index of the arena!")
+    }
+}
+
+impl<T, A: Allocator> core::ops::IndexMut<Index> for Arena<T, A> {
+    fn index_mut(&mut self, index: Index) -> &mut T {
+        self.get_mut(index)
+            .expect("No value at this index of the arena!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_generations() {
+        let mut arena = Arena::new_in(Global);
+        let first = arena.insert(1);
+        let second = arena.insert(2);
+        assert_eq!((arena[first], arena[second], arena.len()), (1, 2, 2));
+
+        // A removed slot is filled again, under a new generation
+        assert_eq!(arena.remove(first), Some(1));
+        assert_eq!(arena.remove(first), None);
+        let third = arena.insert(3);
+        assert!(!arena.contains(first));
+        assert_eq!(arena.get(third), Some(&3));
+
+        // Clearing keeps the slots, and invalidates every index
+        let capacity = arena.capacity();
+        arena.clear();
+        assert_eq!((arena.len(), arena.capacity()), (0, capacity));
+        assert!(!arena.contains(second) && !arena.contains(third));
+
+        // Slots are added once the arena is full
+        for value in 0..capacity + 1 {
+            arena.insert(value);
+        }
+        assert!(arena.capacity() > capacity);
+        assert_eq!(arena.iter().count(), capacity + 1);
+    }
+}
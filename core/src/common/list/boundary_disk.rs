@@ -1,6 +1,6 @@
 use crate::common::storage::*;
 use crate::node_layer::NodeLayer;
-use crate::traits::KeyBounded;
+use crate::traits::{KeyBound, KeyBounded};
 use crate::traits::*;
 
 use serde::{Deserialize, Serialize};
@@ -20,40 +20,119 @@ pub enum BoundaryDiskListState {
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
-pub struct BoundaryDiskListCatalogPage {
+pub struct BoundaryDiskListCatalogPage<K> {
     first: StoreID,
     last: StoreID,
 
     // Maps node to next and previous links
+    #[serde(serialize_with = "crate::common::storage::format::sorted")]
     links: HashMap<StoreID, Link>,
 
+    // Maps node to its lower bound, so that the layers above can be built on load without reading
+    // a single node. The fences stand in for the parameters of the models above, which are not
+    // persisted, since those layers are trained from the fences just as they were trained from the
+    // nodes.
+    #[serde(
+        serialize_with = "crate::common::storage::format::sorted",
+        bound(serialize = "K: Serialize")
+    )]
+    fences: HashMap<StoreID, KeyBound<K>>,
+
     // Simple flag to mark the state of this list
     state: BoundaryDiskListState,
+    // Number of entries held by the nodes, kept up to date by the layer owning the list
+    entries: usize,
+}
+
+/// Format version which added the fences to the catalog
+const FENCES_VERSION: u8 = 2;
+
+/// The catalog as written before `FENCES_VERSION`, without fences or a count of entries, which are
+/// rebuilt from the nodes as the list loads
+#[derive(Deserialize)]
+struct LegacyCatalogPage {
+    first: StoreID,
+    last: StoreID,
+    links: HashMap<StoreID, Link>,
+    state: BoundaryDiskListState,
+}
+
+impl<K> From<LegacyCatalogPage> for BoundaryDiskListCatalogPage<K> {
+    fn from(legacy: LegacyCatalogPage) -> Self {
+        Self {
+            first: legacy.first,
+            last: legacy.last,
+            links: legacy.links,
+            fences: HashMap::new(),
+            state: legacy.state,
+            entries: 0,
+        }
+    }
+}
+
+impl RemapStoreIDs for Link {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        self.next.remap_store_ids(remap)?;
+        self.prev.remap_store_ids(remap)
+    }
 }
 
-pub struct BoundaryDiskList<N: Persisted, PA> {
-    store: LocalStore<BoundaryDiskListCatalogPage, N>,
+impl<K: Clone> RemapStoreIDs for BoundaryDiskListCatalogPage<K> {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        self.first.remap_store_ids(remap)?;
+        self.last.remap_store_ids(remap)?;
+        self.links = remap_keys(&self.links, remap, |link| link.remap_store_ids(remap))?;
+        self.fences = remap_keys(&self.fences, remap, |_| Ok(()))?;
+
+        Ok(())
+    }
+}
+
+pub struct BoundaryDiskList<K: Persisted, N: Persisted, PA, Z: PageCompression = NoCompression> {
+    store: LocalStore<BoundaryDiskListCatalogPage<K>, N, Z>,
 
     // We should only persist parents when we are in a deep persisted layer, in a boundary layer we
     // keep them in transient memory
     parents: HashMap<StoreID, PA>,
 
+    // Set if the catalog was migrated from before `FENCES_VERSION` as it loaded
+    migrated: bool,
+
     _ph: std::marker::PhantomData<N>,
 }
 
-impl<N, PA> BoundaryDiskList<N, PA>
+impl<K, N, PA, Z> BoundaryDiskList<K, N, PA, Z>
 where
-    N: Persisted + Default + Eq,
+    K: Persisted + Ord,
+    N: KeyBounded<K> + Persisted + Default + Eq,
+    Z: PageCompression,
 {
     pub fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
-        let mut store: LocalStore<BoundaryDiskListCatalogPage, N> =
-            store.load_local_store(ident)?;
+        let mut migrated = false;
+        let mut store: LocalStore<BoundaryDiskListCatalogPage<K>, N, Z> = store
+            .load_local_store_with(ident, |data| {
+                format::decode_migrating(data, FENCES_VERSION, |legacy: LegacyCatalogPage| {
+                    migrated = true;
+                    legacy.into()
+                })
+            })?;
         let parents = HashMap::new();
 
+        if migrated {
+            let pages: Vec<StoreID> = store.catalog.links.keys().copied().collect();
+
+            for ptr in pages {
+                if let Some(node) = store.read_page(ptr)? {
+                    let fence = node.lower_bound().cloned();
+                    store.catalog.fences.insert(ptr, fence);
+                }
+            }
+        }
+
         if store.catalog.state == BoundaryDiskListState::Uninitialized {
             store.catalog.state = BoundaryDiskListState::Initialized;
             let ptr = store.allocate_page();
-            store.write_page(&N::default(), ptr)?;
+            Self::write_node(&mut store, &N::default(), ptr)?;
             store.catalog.first = ptr;
             store.catalog.last = ptr;
             store.catalog.links.insert(ptr, Default::default());
@@ -62,10 +141,63 @@ where
         Ok(Self {
             store,
             parents,
+            migrated,
             _ph: std::marker::PhantomData,
         })
     }
 
+    /// Whether the catalog was written before it kept fences and a count of entries. The fences
+    /// are rebuilt from the nodes as the list loads, while the count is left to the layer owning
+    /// the list.
+    pub fn migrated(&self) -> bool {
+        self.migrated
+    }
+
+    /// Keep every node of the list cached
+    pub fn pin_resident(&mut self) {
+        self.store.pin_resident();
+    }
+
+    /// Write every node of the list out
+    pub fn flush(&self) -> crate::Result<()> {
+        self.store.flush()
+    }
+
+    /// Drop every change which wasn't written out, after the batch it was written to was aborted.
+    /// Parents are transient, so the layer above has to set them again.
+    pub fn reload(&mut self) -> crate::Result<()> {
+        self.store.reload()?;
+        self.parents.clear();
+        self.migrated = false;
+
+        Ok(())
+    }
+
+    /// Read the node at `ptr` into the cache ahead of time
+    pub fn warm(&self, ptr: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        self.store.warm_page(ptr, stats)
+    }
+
+    /// Write a node along with its lower bound. The layers above route to a node by the key it
+    /// was fenced with, so the node keeps that fence once its smallest key is removed, even once
+    /// it holds no entries at all. Only a key below the fence lowers it.
+    fn write_node(
+        store: &mut LocalStore<BoundaryDiskListCatalogPage<K>, N, Z>,
+        node: &N,
+        ptr: StoreID,
+    ) -> crate::Result<()> {
+        let fence = match (store.catalog.fences.remove(&ptr), node.lower_bound()) {
+            (Some(KeyBound::Key(fence)), KeyBound::Key(key)) if *key < fence => {
+                KeyBound::Key(key.clone())
+            }
+            (Some(KeyBound::Key(fence)), _) => KeyBound::Key(fence),
+            (_, bound) => bound.cloned(),
+        };
+
+        store.catalog.fences.insert(ptr, fence);
+        store.write_page(node, ptr)
+    }
+
     pub fn is_empty(&self) -> crate::Result<Option<StoreID>> {
         if self.store.catalog.first == self.store.catalog.last
             && self.get_node(self.store.catalog.first)?.unwrap() == N::default()
@@ -83,7 +215,7 @@ where
     ) -> crate::Result<T> {
         let mut node = self.get_node(ptr)?.unwrap();
         let result = closure(&mut node);
-        self.store.write_page(&node, ptr)?;
+        Self::write_node(&mut self.store, &node, ptr)?;
 
         Ok(result)
     }
@@ -92,6 +224,19 @@ where
         self.store.read_page(ptr)
     }
 
+    /// Read part of a node through `seed` without caching it, see `LocalStore::read_page_with`
+    pub fn read_node_with<T, S>(
+        &self,
+        ptr: StoreID,
+        seed: S,
+        cached: impl FnOnce(&N) -> T,
+    ) -> crate::Result<Option<T>>
+    where
+        S: for<'de> serde::de::DeserializeSeed<'de, Value = T>,
+    {
+        self.store.read_page_with(ptr, seed, cached)
+    }
+
     fn get_next(&self, ptr: StoreID) -> Option<StoreID> {
         self.store.catalog.links.get(&ptr).unwrap().next
     }
@@ -109,7 +254,7 @@ where
 
         let new_node_ptr = self.store.allocate_page();
 
-        self.store.write_page(&inner, new_node_ptr)?;
+        Self::write_node(&mut self.store, &inner, new_node_ptr)?;
         self.store.catalog.links.insert(new_node_ptr, new_link);
         self.store.catalog.links.get_mut(&ptr).unwrap().next = Some(new_node_ptr);
 
@@ -125,15 +270,61 @@ where
     #[allow(unused)]
     pub fn clear(&mut self) -> crate::Result<StoreID> {
         self.store.clear()?;
+        self.store.catalog.links.clear();
+        self.store.catalog.fences.clear();
+        self.store.catalog.entries = 0;
 
         let ptr = self.store.allocate_page();
-        self.store.write_page(&N::default(), ptr)?;
+        Self::write_node(&mut self.store, &N::default(), ptr)?;
         self.store.catalog.first = ptr;
         self.store.catalog.last = ptr;
         self.store.catalog.links.insert(ptr, Default::default());
 
         Ok(ptr)
     }
+
+    /// Number of entries held by the nodes of the list, as last set by `set_entry_count`
+    pub fn entry_count(&self) -> usize {
+        self.store.catalog.entries
+    }
+
+    pub fn set_entry_count(&mut self, entries: usize) {
+        self.store.catalog.entries = entries;
+    }
+
+        /// Every page holding a node of the list
+    pub fn pages(&self) -> impl Iterator<Item = StoreID> + '_ {
+        self.store.catalog.links.keys().copied()
+    }
+
+    /// Copy the list into the local store `ident` of `store`, which must not hold a list yet.
+    /// `remap` has to hold every page of the list, and `remap_node` translates the pages each
+    /// node references.
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+        remap_node: impl Fn(&mut N, &StoreIDRemap) -> crate::Result<()>,
+    ) -> crate::Result<()> {
+        let mut target: LocalStore<BoundaryDiskListCatalogPage<K>, N, Z> =
+            store.load_local_store(ident)?;
+
+        if target.catalog.state != BoundaryDiskListState::Uninitialized {
+            anyhow::bail!("Cannot copy a list over one which already exists!");
+        }
+
+        target.catalog = self.store.catalog.clone();
+        target.catalog.remap_store_ids(remap)?;
+
+        for ptr in self.pages() {
+            let mut node = self.get_node(ptr)?.unwrap();
+            remap_node(&mut node, remap)?;
+            target.write_page(&node, remap.get(ptr)?)?;
+        }
+
+        target.flush()
+    }
     //
     //     #[allow(unused)]
     //     pub fn insert_before(&mut self, inner: N, ptr: ArenaID) -> ArenaID {
@@ -171,11 +362,12 @@ where
     // }
 }
 
-impl<K, N, PA> NodeLayer<K, StoreID, PA> for BoundaryDiskList<N, PA>
+impl<K, N, PA, Z> NodeLayer<K, StoreID, PA> for BoundaryDiskList<K, N, PA, Z>
 where
-    K: Clone,
+    K: Persisted + Ord,
     N: KeyBounded<K> + Persisted + Eq,
     PA: Address,
+    Z: PageCompression,
 {
     fn first(&self) -> StoreID {
         self.store.catalog.first
@@ -185,6 +377,10 @@ where
         self.store.catalog.last
     }
 
+    fn node_count(&self) -> usize {
+        self.store.catalog.links.len()
+    }
+
     fn parent(&self, ptr: StoreID) -> Option<PA> {
         self.parents.get(&ptr).cloned()
     }
@@ -193,8 +389,19 @@ where
         self.parents.insert(ptr, parent);
     }
 
-    fn lower_bound(&self, ptr: StoreID) -> K {
-        self.get_node(ptr).unwrap().unwrap().lower_bound().clone()
+    fn lower_bound(&self, ptr: StoreID) -> KeyBound<K> {
+        if let Some(fence) = self.store.catalog.fences.get(&ptr) {
+            return fence.clone();
+        }
+
+        // Every node written has a fence, but a catalog which lost one can still fall back to
+        // reading the node itself
+        self.store
+            .read_page(ptr)
+            .ok()
+            .flatten()
+            .map(|node| node.lower_bound().cloned())
+            .expect("Node of the list could not be read!")
     }
 
     fn next(&self, ptr: StoreID) -> Option<StoreID> {
@@ -204,6 +411,10 @@ where
     fn prev(&self, ptr: StoreID) -> Option<StoreID> {
         self.get_prev(ptr)
     }
+
+    fn contains_node(&self, ptr: StoreID) -> bool {
+        self.store.catalog.links.contains_key(&ptr)
+    }
 }
 
 #[cfg(test)]
@@ -214,7 +425,8 @@ mod tests {
     fn test_linked_list_new() {
         let dir = tempfile::tempdir().unwrap();
         let mut store = GlobalStore::load(&dir).unwrap();
-        let list: BoundaryDiskList<i32, ()> = BoundaryDiskList::load(&mut store, "test").unwrap();
+        let list: BoundaryDiskList<i32, i32, ()> =
+            BoundaryDiskList::load(&mut store, "test").unwrap();
 
         assert_eq!(
             list.get_node(list.first()).unwrap(),
@@ -226,7 +438,7 @@ mod tests {
     fn linked_list_insert_after() {
         let dir = tempfile::tempdir().unwrap();
         let mut store = GlobalStore::load(&dir).unwrap();
-        let mut list: BoundaryDiskList<u32, ()> =
+        let mut list: BoundaryDiskList<u32, u32, ()> =
             BoundaryDiskList::load(&mut store, "test").unwrap();
 
         let first_ptr = list.first();
@@ -236,6 +448,75 @@ mod tests {
         assert_eq!(list.get_prev(second_ptr), Some(first_ptr));
         assert_eq!(list.last(), second_ptr);
     }
+
+    #[test]
+    fn linked_list_keeps_fence_of_removed_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(&dir).unwrap();
+        let mut list: BoundaryDiskList<u32, u32, ()> =
+            BoundaryDiskList::load(&mut store, "test").unwrap();
+
+        let second = list.insert_after(5, list.first()).unwrap();
+
+        list.transform_node(second, |node| *node = 7).unwrap();
+        assert_eq!(list.lower_bound(second), KeyBound::Key(5));
+
+        list.transform_node(second, |node| *node = 3).unwrap();
+        assert_eq!(list.lower_bound(second), KeyBound::Key(3));
+    }
+
+    #[test]
+    fn linked_list_migrates_catalog_without_fences() {
+        let backend = MemoryBackend::new();
+
+        let (catalog, first, second) = {
+            let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+            let mut list: BoundaryDiskList<u32, u32, ()> =
+                BoundaryDiskList::load(&mut store, "test").unwrap();
+
+            let first = list.first();
+            list.transform_node(first, |node| *node = 1).unwrap();
+            let second = list.insert_after(5, first).unwrap();
+
+            (list.store.catalog_page(), first, second)
+        };
+
+        // The catalog as written in format version 1, before it kept fences
+        let links: HashMap<StoreID, Link> = [
+            (
+                first,
+                Link {
+                    next: Some(second),
+                    prev: None,
+                },
+            ),
+            (
+                second,
+                Link {
+                    next: None,
+                    prev: Some(first),
+                },
+            ),
+        ]
+        .into();
+        let legacy = (first, second, links, BoundaryDiskListState::Initialized);
+
+        let mut data = format::encode(&legacy).unwrap();
+        data[4] = FENCES_VERSION - 1;
+        backend.write_batch(vec![(catalog, Some(data))]).unwrap();
+
+        let mut store = GlobalStore::with_backend(backend).unwrap();
+        let mut list: BoundaryDiskList<u32, u32, ()> =
+            BoundaryDiskList::load(&mut store, "test").unwrap();
+
+        assert!(list.migrated());
+        assert_eq!(list.lower_bound(first), KeyBound::Key(1));
+        assert_eq!(list.lower_bound(second), KeyBound::Key(5));
+
+        // A node without a fence is read instead
+        list.store.catalog.fences.remove(&second);
+        assert_eq!(list.lower_bound(second), KeyBound::Key(5));
+    }
     //
     //     #[test]
     //     fn linked_list_insert_before() {
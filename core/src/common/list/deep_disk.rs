@@ -1,6 +1,6 @@
 use crate::common::storage::*;
 use crate::node_layer::NodeLayer;
-use crate::traits::KeyBounded;
+use crate::traits::{KeyBound, KeyBounded};
 use crate::traits::*;
 
 use serde::{Deserialize, Serialize};
@@ -22,39 +22,120 @@ pub enum DeepDiskListState {
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
-pub struct DeepDiskListCatalogPage<PA> {
+pub struct DeepDiskListCatalogPage<K, PA> {
     first: StoreID,
     last: StoreID,
 
     // Maps node to next, previous, and parent links
+    #[serde(
+        serialize_with = "crate::common::storage::format::sorted",
+        bound(serialize = "PA: Serialize")
+    )]
     links: HashMap<StoreID, Link<PA>>,
 
+    // Maps node to its lower bound, which it keeps once its smallest key is removed
+    #[serde(
+        serialize_with = "crate::common::storage::format::sorted",
+        bound(serialize = "K: Serialize")
+    )]
+    fences: HashMap<StoreID, KeyBound<K>>,
+
     // Simple flag to mark the state of this list
     state: DeepDiskListState,
+    // Number of entries held by the nodes, kept up to date by the layer owning the list
+    entries: usize,
+}
+
+/// Format version which added the fences to the catalog
+const FENCES_VERSION: u8 = 3;
+
+/// The catalog as written before `FENCES_VERSION`, without fences, which are rebuilt from the nodes
+/// as the list loads
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "PA: Deserialize<'de>"))]
+struct LegacyCatalogPage<PA> {
+    first: StoreID,
+    last: StoreID,
+    links: HashMap<StoreID, Link<PA>>,
+    state: DeepDiskListState,
+    entries: usize,
+}
+
+impl<K, PA> From<LegacyCatalogPage<PA>> for DeepDiskListCatalogPage<K, PA> {
+    fn from(legacy: LegacyCatalogPage<PA>) -> Self {
+        Self {
+            first: legacy.first,
+            last: legacy.last,
+            links: legacy.links,
+            fences: HashMap::new(),
+            state: legacy.state,
+            entries: legacy.entries,
+        }
+    }
 }
 
-pub struct DeepDiskList<N, PA>
+impl<PA: RemapStoreIDs> RemapStoreIDs for Link<PA> {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        self.next.remap_store_ids(remap)?;
+        self.prev.remap_store_ids(remap)?;
+        self.parent.remap_store_ids(remap)
+    }
+}
+
+impl<K: Clone, PA: RemapStoreIDs + Clone> RemapStoreIDs for DeepDiskListCatalogPage<K, PA> {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        self.first.remap_store_ids(remap)?;
+        self.last.remap_store_ids(remap)?;
+        self.links = remap_keys(&self.links, remap, |link| link.remap_store_ids(remap))?;
+        self.fences = remap_keys(&self.fences, remap, |_| Ok(()))?;
+
+        Ok(())
+    }
+}
+
+pub struct DeepDiskList<K, N, PA, Z = NoCompression>
 where
+    K: Persisted,
     PA: Persisted + Address,
     N: Persisted,
+    Z: PageCompression,
 {
-    store: LocalStore<DeepDiskListCatalogPage<PA>, N>,
+    store: LocalStore<DeepDiskListCatalogPage<K, PA>, N, Z>,
     _ph: std::marker::PhantomData<N>,
 }
 
-impl<N, PA> DeepDiskList<N, PA>
+impl<K, N, PA, Z> DeepDiskList<K, N, PA, Z>
 where
-    N: Persisted,
+    K: Persisted + Ord,
+    N: KeyBounded<K> + Persisted,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     pub fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
-        let mut store: LocalStore<DeepDiskListCatalogPage<PA>, N> =
-            store.load_local_store(ident)?;
+        let mut migrated = false;
+        let mut store: LocalStore<DeepDiskListCatalogPage<K, PA>, N, Z> = store
+            .load_local_store_with(ident, |data| {
+                format::decode_migrating(data, FENCES_VERSION, |legacy: LegacyCatalogPage<PA>| {
+                    migrated = true;
+                    legacy.into()
+                })
+            })?;
+
+        if migrated {
+            let pages: Vec<StoreID> = store.catalog.links.keys().copied().collect();
+
+            for ptr in pages {
+                if let Some(node) = store.read_page(ptr)? {
+                    let fence = node.lower_bound().cloned();
+                    store.catalog.fences.insert(ptr, fence);
+                }
+            }
+        }
 
         if store.catalog.state == DeepDiskListState::Uninitialized {
             store.catalog.state = DeepDiskListState::Initialized;
             let ptr = store.allocate_page();
-            store.write_page(&N::default(), ptr)?;
+            Self::write_node(&mut store, &N::default(), ptr)?;
             store.catalog.first = ptr;
             store.catalog.last = ptr;
             store.catalog.links.insert(ptr, Default::default());
@@ -66,6 +147,45 @@ where
         })
     }
 
+    /// Keep every node of the list cached
+    pub fn pin_resident(&mut self) {
+        self.store.pin_resident();
+    }
+
+    /// Write every node of the list out
+    pub fn flush(&self) -> crate::Result<()> {
+        self.store.flush()
+    }
+
+    /// Drop every change which wasn't written out, after the batch it was written to was aborted
+    pub fn reload(&mut self) -> crate::Result<()> {
+        self.store.reload()
+    }
+
+    /// Read the node at `ptr` into the cache ahead of time
+    pub fn warm(&self, ptr: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        self.store.warm_page(ptr, stats)
+    }
+
+    /// Write a node along with its lower bound, which only a key below it lowers, as for
+    /// `BoundaryDiskList`
+    fn write_node(
+        store: &mut LocalStore<DeepDiskListCatalogPage<K, PA>, N, Z>,
+        node: &N,
+        ptr: StoreID,
+    ) -> crate::Result<()> {
+        let fence = match (store.catalog.fences.remove(&ptr), node.lower_bound()) {
+            (Some(KeyBound::Key(fence)), KeyBound::Key(key)) if *key < fence => {
+                KeyBound::Key(key.clone())
+            }
+            (Some(KeyBound::Key(fence)), _) => KeyBound::Key(fence),
+            (_, bound) => bound.cloned(),
+        };
+
+        store.catalog.fences.insert(ptr, fence);
+        store.write_page(node, ptr)
+    }
+
     pub fn is_empty(&self) -> crate::Result<Option<StoreID>> {
         if self.store.catalog.first == self.store.catalog.last
             && self.get_node(self.store.catalog.first)?.unwrap() == N::default()
@@ -83,7 +203,7 @@ where
     ) -> crate::Result<T> {
         let mut node = self.get_node(ptr)?.unwrap();
         let result = closure(&mut node);
-        self.store.write_page(&node, ptr)?;
+        Self::write_node(&mut self.store, &node, ptr)?;
 
         Ok(result)
     }
@@ -92,6 +212,19 @@ where
         self.store.read_page(ptr)
     }
 
+    /// Read part of a node through `seed` without caching it, see `LocalStore::read_page_with`
+    pub fn read_node_with<T, S>(
+        &self,
+        ptr: StoreID,
+        seed: S,
+        cached: impl FnOnce(&N) -> T,
+    ) -> crate::Result<Option<T>>
+    where
+        S: for<'de> serde::de::DeserializeSeed<'de, Value = T>,
+    {
+        self.store.read_page_with(ptr, seed, cached)
+    }
+
     fn get_next(&self, ptr: StoreID) -> Option<StoreID> {
         self.store.catalog.links.get(&ptr).unwrap().next
     }
@@ -110,7 +243,7 @@ where
 
         let new_node_ptr = self.store.allocate_page();
 
-        self.store.write_page(&inner, new_node_ptr)?;
+        Self::write_node(&mut self.store, &inner, new_node_ptr)?;
         self.store.catalog.links.insert(new_node_ptr, new_link);
         self.store.catalog.links.get_mut(&ptr).unwrap().next = Some(new_node_ptr);
 
@@ -123,24 +256,75 @@ where
         Ok(new_node_ptr)
     }
 
+    #[allow(unused)]
     pub fn clear(&mut self) -> crate::Result<StoreID> {
         self.store.clear()?;
+        self.store.catalog.links.clear();
+        self.store.catalog.fences.clear();
+        self.store.catalog.entries = 0;
 
         let ptr = self.store.allocate_page();
-        self.store.write_page(&N::default(), ptr)?;
+        Self::write_node(&mut self.store, &N::default(), ptr)?;
         self.store.catalog.first = ptr;
         self.store.catalog.last = ptr;
         self.store.catalog.links.insert(ptr, Default::default());
 
         Ok(ptr)
     }
+
+    /// Number of entries held by the nodes of the list, as last set by `set_entry_count`
+    pub fn entry_count(&self) -> usize {
+        self.store.catalog.entries
+    }
+
+    pub fn set_entry_count(&mut self, entries: usize) {
+        self.store.catalog.entries = entries;
+    }
+
+        /// Every page holding a node of the list
+    pub fn pages(&self) -> impl Iterator<Item = StoreID> + '_ {
+        self.store.catalog.links.keys().copied()
+    }
+
+    /// Copy the list into the local store `ident` of `store`, which must not hold a list yet.
+    /// `remap` has to hold every page of the list along with the pages of its parents, and
+    /// `remap_node` translates the pages each node references.
+    pub fn copy_into(
+        &self,
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        remap: &StoreIDRemap,
+        remap_node: impl Fn(&mut N, &StoreIDRemap) -> crate::Result<()>,
+    ) -> crate::Result<()>
+    where
+        PA: RemapStoreIDs,
+    {
+        let mut target: LocalStore<DeepDiskListCatalogPage<K, PA>, N, Z> =
+            store.load_local_store(ident)?;
+
+        if target.catalog.state != DeepDiskListState::Uninitialized {
+            anyhow::bail!("Cannot copy a list over one which already exists!");
+        }
+
+        target.catalog = self.store.catalog.clone();
+        target.catalog.remap_store_ids(remap)?;
+
+        for ptr in self.pages() {
+            let mut node = self.get_node(ptr)?.unwrap();
+            remap_node(&mut node, remap)?;
+            target.write_page(&node, remap.get(ptr)?)?;
+        }
+
+        target.flush()
+    }
 }
 
-impl<K, N, PA> NodeLayer<K, StoreID, PA> for DeepDiskList<N, PA>
+impl<K, N, PA, Z> NodeLayer<K, StoreID, PA> for DeepDiskList<K, N, PA, Z>
 where
-    K: Persisted,
+    K: Persisted + Ord,
     N: Persisted + KeyBounded<K> + Eq,
     PA: Persisted + Address,
+    Z: PageCompression,
 {
     fn first(&self) -> StoreID {
         self.store.catalog.first
@@ -150,6 +334,10 @@ where
         self.store.catalog.last
     }
 
+    fn node_count(&self) -> usize {
+        self.store.catalog.links.len()
+    }
+
     fn parent(&self, ptr: StoreID) -> Option<PA> {
         self.store.catalog.links.get(&ptr).unwrap().clone().parent
     }
@@ -158,8 +346,11 @@ where
         self.store.catalog.links.get_mut(&ptr).unwrap().parent = Some(parent);
     }
 
-    fn lower_bound(&self, ptr: StoreID) -> K {
-        self.get_node(ptr).unwrap().unwrap().lower_bound().clone()
+    fn lower_bound(&self, ptr: StoreID) -> KeyBound<K> {
+        match self.store.catalog.fences.get(&ptr) {
+            Some(fence) => fence.clone(),
+            None => self.get_node(ptr).unwrap().unwrap().lower_bound().cloned(),
+        }
     }
 
     fn next(&self, ptr: StoreID) -> Option<StoreID> {
@@ -169,6 +360,10 @@ where
     fn prev(&self, ptr: StoreID) -> Option<StoreID> {
         self.get_prev(ptr)
     }
+
+    fn contains_node(&self, ptr: StoreID) -> bool {
+        self.store.catalog.links.contains_key(&ptr)
+    }
 }
 
 #[cfg(test)]
@@ -179,7 +374,7 @@ mod tests {
     fn test_linked_list_new() {
         let dir = tempfile::tempdir().unwrap();
         let mut store = GlobalStore::load(&dir).unwrap();
-        let list: DeepDiskList<i32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
+        let list: DeepDiskList<i32, i32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
 
         assert_eq!(
             list.get_node(list.first()).unwrap(),
@@ -191,7 +386,7 @@ mod tests {
     fn linked_list_insert_after() {
         let dir = tempfile::tempdir().unwrap();
         let mut store = GlobalStore::load(&dir).unwrap();
-        let mut list: DeepDiskList<u32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
+        let mut list: DeepDiskList<u32, u32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
 
         let first_ptr = list.first();
         let second_ptr = list.insert_after(2, first_ptr).unwrap();
@@ -200,6 +395,124 @@ mod tests {
         assert_eq!(list.get_prev(second_ptr), Some(first_ptr));
         assert_eq!(list.last(), second_ptr);
     }
+
+    #[test]
+    fn linked_list_clear_node_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(&dir).unwrap();
+        let mut list: DeepDiskList<u32, u32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
+
+        let first_ptr = list.first();
+        let second_ptr = list.insert_after(2, first_ptr).unwrap();
+        list.insert_after(3, second_ptr).unwrap();
+        assert_eq!(NodeLayer::<u32, _, _>::node_count(&list), 3);
+
+        list.clear().unwrap();
+        assert_eq!(NodeLayer::<u32, _, _>::node_count(&list), 1);
+    }
+
+    #[test]
+    fn linked_list_copy_into() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path().join("source")).unwrap();
+        let mut list: DeepDiskList<u32, u32, StoreID> =
+            DeepDiskList::load(&mut store, "test").unwrap();
+
+        let first_ptr = list.first();
+        let second_ptr = list.insert_after(2, first_ptr).unwrap();
+        list.set_parent(second_ptr, first_ptr);
+
+        // The target already holds pages with the ids of the list
+        let mut target = GlobalStore::load(dir.path().join("target")).unwrap();
+        let _taken: DeepDiskList<u32, u32, StoreID> =
+            DeepDiskList::load(&mut target, "taken").unwrap();
+
+        let mut remap = StoreIDRemap::default();
+        remap.reserve(&mut target, list.pages());
+        list.copy_into(&mut target, "copy", &remap, |node, _| {
+            *node += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        let copy: DeepDiskList<u32, u32, StoreID> =
+
+            DeepDiskList::load(&mut target, "copy").unwrap();
+        let copy_second = NodeLayer::<u32, _, _>::next(&copy, copy.first()).unwrap();
+        assert_eq!(copy_second, remap.get(second_ptr).unwrap());
+        assert_eq!(copy.get_node(copy_second).unwrap(), Some(3));
+        assert_eq!(copy.parent(copy_second), Some(remap.get(first_ptr).unwrap()));
+        drop(copy);
+
+        assert!(list
+            .copy_into(&mut target, "copy", &remap, |_, _| Ok(()))
+            .is_err());
+    }
+
+    #[test]
+    fn linked_list_keeps_fence_of_removed_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(&dir).unwrap();
+        let mut list: DeepDiskList<u32, u32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
+
+        let second = list.insert_after(5, list.first()).unwrap();
+
+        list.transform_node(second, |node| *node = 7).unwrap();
+        assert_eq!(list.lower_bound(second), KeyBound::Key(5));
+
+        list.transform_node(second, |node| *node = 3).unwrap();
+        assert_eq!(list.lower_bound(second), KeyBound::Key(3));
+    }
+
+    #[test]
+    fn linked_list_migrates_catalog_without_fences() {
+        let backend = MemoryBackend::new();
+
+        let (catalog, first, second) = {
+            let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+            let mut list: DeepDiskList<u32, u32, ()> =
+                DeepDiskList::load(&mut store, "test").unwrap();
+
+            let first = list.first();
+            list.transform_node(first, |node| *node = 1).unwrap();
+            let second = list.insert_after(5, first).unwrap();
+
+            (list.store.catalog_page(), first, second)
+        };
+
+        // The catalog as written in format version 2, before it kept fences
+        let links: HashMap<StoreID, Link<()>> = [
+            (
+                first,
+                Link {
+                    next: Some(second),
+                    prev: None,
+                    parent: None,
+                },
+            ),
+            (
+                second,
+                Link {
+                    next: None,
+                    prev: Some(first),
+                    parent: None,
+                },
+            ),
+        ]
+        .into();
+        let legacy = (first, second, links, DeepDiskListState::Initialized, 2usize);
+
+        let mut data = format::encode(&legacy).unwrap();
+        data[4] = FENCES_VERSION - 1;
+        backend.write_batch(vec![(catalog, Some(data))]).unwrap();
+
+        let mut store = GlobalStore::with_backend(backend).unwrap();
+        let list: DeepDiskList<u32, u32, ()> = DeepDiskList::load(&mut store, "test").unwrap();
+
+        assert_eq!(list.lower_bound(first), KeyBound::Key(1));
+        assert_eq!(list.lower_bound(second), KeyBound::Key(5));
+        assert_eq!(list.entry_count(), 2);
+    }
     //
     //     #[test]
     //     fn linked_list_insert_before() {
@@ -1,19 +1,76 @@
-use generational_arena::Arena;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
 
+use super::alloc::{ArenaAlloc, DefaultAlloc};
+use super::arena::{self, Arena};
 use crate::{
-    node_layer::NodeLayer,
-    traits::{Address, KeyBounded},
+    common::prefetch::prefetch_read,
+    node_layer::{NodeLayer, StaleAddress},
+    traits::{Address, KeyBound, KeyBounded, PrefetchData},
 };
 
-pub type ArenaID = generational_arena::Index;
+pub type ArenaID = arena::Index;
 
-pub struct MemoryList<N, PA> {
-    arena: Arena<(MemoryNode<N>, Option<PA>)>,
+/// Where every node of a compacted list moved, see `MemoryList::compact`
+pub type Remap = HashMap<ArenaID, ArenaID>;
+
+/// A node along with the address of its parent
+type Entry<N, PA> = (MemoryNode<N>, Option<PA>);
+
+/// Nodes are held in the arena itself, until `share` moves them behind a reference count for a
+/// snapshot of the list. Shared nodes are copied the first time either list modifies them.
+#[derive(Clone)]
+enum Slot<T> {
+    Inline(T),
+    Shared(Arc<T>),
+}
+
+impl<T> core::ops::Deref for Slot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Slot::Inline(value) => value,
+            Slot::Shared(value) => value,
+        }
+    }
+}
+
+impl<T> Slot<T> {
+    fn is_shared(&self) -> bool {
+        matches!(self, Slot::Shared(value) if Arc::strong_count(value) > 1)
+    }
+}
+
+impl<T: Clone> Slot<T> {
+    fn make_mut(&mut self) -> &mut T {
+        match self {
+            Slot::Inline(value) => value,
+            Slot::Shared(value) => Arc::make_mut(value),
+        }
+    }
+}
+
+/// Cloning a `MemoryList` clones the underlying arena slot-for-slot, so every `ArenaID` (both the
+/// links inside this list and the addresses held by neighbouring layers) stays valid in the copy.
+/// Nodes are copied along with the arena, unless `share` moved them behind a reference count
+/// first, in which case only the reference is copied.
+#[derive(Clone)]
+pub struct MemoryList<N, PA, AL: ArenaAlloc = DefaultAlloc> {
+    arena: Arena<Slot<Entry<N, PA>>, AL::Storage>,
     first: ArenaID,
     last: ArenaID,
+    alloc: AL,
+
+    /// Heap memory held by the nodes, as reported by the layer through `track_heap`
+    heap: usize,
+
+    /// Nodes moved behind a reference count by `share`
+    shared: usize,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct MemoryNode<N> {
     pub inner: N,
     next: Option<ArenaID>,
@@ -30,34 +87,115 @@ impl<N> MemoryNode<N> {
     }
 }
 
-impl<N, PA> MemoryList<N, PA>
+impl<N, PA, AL> MemoryList<N, PA, AL>
 where
     N: Default,
+    AL: ArenaAlloc,
 {
-    pub fn empty() -> Self {
-        let mut arena = Arena::new();
-        let ptr = arena.insert((Default::default(), None));
+    #[allow(unused)]
+    pub fn empty() -> Self
+    where
+        AL: Default,
+    {
+        Self::with_alloc(AL::default())
+    }
+
+    pub fn with_alloc(alloc: AL) -> Self {
+        let mut arena = Arena::new_in(alloc.storage());
+        let ptr = arena.insert(Slot::Inline((Default::default(), None)));
 
         MemoryList {
             arena,
             first: ptr,
             last: ptr,
+            alloc,
+            heap: 0,
+            shared: 0,
+        }
+    }
+
+    /// Make room for at least `additional` more nodes
+    fn reserve(&mut self, additional: usize) {
+        let arena = &mut self.arena;
+        self.alloc.allocate(&mut || arena.reserve(additional));
+    }
+
+    fn reserve_for_insert(&mut self) {
+        if self.arena.len() == self.arena.capacity() {
+            let additional = self.alloc.grow(self.arena.capacity());
+
+            if additional > 0 {
+                self.reserve(additional);
+            }
         }
     }
 
+    #[allow(unused)]
+    #[must_use]
+    pub fn clear(&mut self) -> ArenaID {
+        self.clear_with_hint(|| 0)
+    }
+
+    /// Clear the list before rebuilding it, `hint` lazily computes the expected number of nodes
+    #[must_use]
+    pub fn clear_with_hint(&mut self, hint: impl FnOnce() -> usize) -> ArenaID {
+        self.arena.clear();
+        self.heap = 0;
+        self.shared = 0;
+
+        let capacity = self.alloc.capacity(hint);
+        if capacity > self.arena.capacity() {
+            self.reserve(capacity - self.arena.capacity());
+        }
+
+        let ptr = self
+            .arena
+            .insert(Slot::Inline((MemoryNode::new(Default::default()), None)));
+
+        self.first = ptr;
+        self.last = ptr;
+        ptr
+    }
+
+    #[allow(unused)]
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Number of nodes the arena can hold without allocating
+    #[allow(unused)]
+    pub fn capacity(&self) -> usize {
+        self.arena.capacity()
+    }
+
+    /// Record that a node which held `before` bytes on the heap now holds `after`, for nodes which
+    /// own memory outside of their slot
+    pub fn track_heap(&mut self, before: usize, after: usize) {
+        self.heap = (self.heap + after).saturating_sub(before);
+    }
+}
+
+impl<N, PA, AL> MemoryList<N, PA, AL>
+where
+    N: Default + Clone,
+    PA: Clone,
+    AL: ArenaAlloc,
+{
     #[must_use]
     pub fn insert_after(&mut self, node: N, ptr: ArenaID) -> ArenaID {
+        self.reserve_for_insert();
+
         let next_ptr = self.arena[ptr].0.next;
 
         let mut new_node = MemoryNode::new(node);
         new_node.previous = Some(ptr);
         new_node.next = next_ptr;
 
-        let new_node_ptr = self.arena.insert((new_node, None));
-        self.arena[ptr].0.next = Some(new_node_ptr);
+        let new_node_ptr = self.arena.insert(Slot::Inline((new_node, None)));
+        self.slot_mut(ptr).0.next = Some(new_node_ptr);
 
         if let Some(next_ptr) = next_ptr {
-            self.arena[next_ptr].0.previous = Some(new_node_ptr);
+            self.slot_mut(next_ptr).0.previous = Some(new_node_ptr);
         } else {
             self.last = new_node_ptr;
         }
@@ -68,39 +206,127 @@ where
     #[allow(unused)]
     #[must_use]
     pub fn insert_before(&mut self, node: N, ptr: ArenaID) -> ArenaID {
+        self.reserve_for_insert();
         let previous_ptr = self.arena[ptr].0.previous;
 
         let mut new_node = MemoryNode::new(node);
         new_node.previous = previous_ptr;
         new_node.next = Some(ptr);
 
-        let new_node_ptr = self.arena.insert((new_node, None));
-        self.arena[ptr].0.previous = Some(new_node_ptr);
+        let new_node_ptr = self.arena.insert(Slot::Inline((new_node, None)));
+        self.slot_mut(ptr).0.previous = Some(new_node_ptr);
 
         if let Some(previous_ptr) = previous_ptr {
-            self.arena[previous_ptr].0.next = Some(new_node_ptr);
+            self.slot_mut(previous_ptr).0.next = Some(new_node_ptr);
         } else {
             self.first = new_node_ptr;
         }
 
         new_node_ptr
     }
+}
 
-    #[must_use]
-    pub fn clear(&mut self) -> ArenaID {
+impl<N: Clone, PA: Clone, AL: ArenaAlloc> MemoryList<N, PA, AL> {
+    /// The slot of a node, copied first if it is shared with a clone of the list
+    fn slot_mut(&mut self, ptr: ArenaID) -> &mut Entry<N, PA> {
+        self.arena[ptr].make_mut()
+    }
+
+    /// Move every node behind a reference count, so that clones of the list share them until one
+    /// of the lists modifies the node. Nodes linked in afterwards are held inline again.
+    pub fn share(&mut self) {
+        for (_, slot) in self.arena.iter_mut() {
+            if let Slot::Inline(entry) = slot {
+                *slot = Slot::Shared(Arc::new(entry.clone()));
+                self.shared += 1;
+            }
+        }
+    }
+
+    /// Lay the nodes out at the front of the arena in list order, so that walking the list walks
+    /// the arena front to back. Splits append their new node wherever the arena has room, so after
+    /// many inserts neighbouring nodes end up far apart. Nodes behind a reference count move back
+    /// into the arena as well, where a node still shared with a snapshot is copied, since its links
+    /// change. Returns where every node moved, every address of the old layout is stale afterwards.
+    pub fn compact(&mut self) -> Remap {
+        let mut order = Vec::with_capacity(self.arena.len());
+        let mut ptr = Some(self.first);
+
+        while let Some(current) = ptr {
+            order.push(current);
+            ptr = self.arena[current].0.next;
+        }
+
+        // Removing a slot bumps the generation of the arena, so old addresses can't alias the new
+        // slots, and clearing resets the free list to hand out slots front to back
+        let slots: Vec<_> = order
+            .iter()
+            .map(|ptr| match self.arena.remove(*ptr).unwrap() {
+                Slot::Shared(entry) => {
+                    Slot::Inline(Arc::try_unwrap(entry).unwrap_or_else(|entry| (*entry).clone()))
+                }
+                slot => slot,
+            })
+            .collect();
         self.arena.clear();
-        let ptr = self
+        self.shared = 0;
+
+        let remap: Remap = order
+            .into_iter()
+            .zip(slots)
+            .map(|(old, slot)| (old, self.arena.insert(slot)))
+            .collect();
+
+        for (_, &ptr) in remap.iter() {
+            let node = &mut self.slot_mut(ptr).0;
+            node.next = node.next.map(|next| remap[&next]);
+            node.previous = node.previous.map(|previous| remap[&previous]);
+        }
+
+        self.first = remap[&self.first];
+        self.last = remap[&self.last];
+        remap
+    }
+
+    /// Call `f` on every node, in no particular order
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(&mut N)) {
+        for (_, slot) in self.arena.iter_mut() {
+            f(&mut slot.make_mut().0.inner);
+        }
+    }
+
+    /// Point the parent of every node at where it moved when the layer above was compacted
+    pub fn remap_parents(&mut self, remap: &HashMap<PA, PA>)
+    where
+        PA: Eq + core::hash::Hash,
+    {
+        let moved: Vec<_> = self
             .arena
-            .insert((MemoryNode::new(Default::default()), None));
+            .iter()
+            .filter_map(|(ptr, slot)| Some((ptr, remap.get(slot.1.as_ref()?)?.clone())))
+            .collect();
 
-        self.first = ptr;
-        self.last = ptr;
-        ptr
+        for (ptr, parent) in moved {
+            self.slot_mut(ptr).1 = Some(parent);
+        }
+    }
+}
+
+impl<N, PA, AL: ArenaAlloc> MemoryList<N, PA, AL> {
+    /// The node at `ptr`, or an error if the list was cleared or compacted since `ptr` was handed
+    /// out, where indexing the list would panic
+    #[allow(unused)]
+    pub fn node_ref(&self, ptr: ArenaID) -> Result<&N, StaleAddress> {
+        self.arena
+            .get(ptr)
+            .map(|slot| &slot.0.inner)
+            .ok_or(StaleAddress)
     }
 
+    /// Whether the node is still shared with a clone of the list, rather than owned by it alone
     #[allow(unused)]
-    pub fn len(&self) -> usize {
-        self.arena.len()
+    pub fn is_shared(&self, ptr: ArenaID) -> bool {
+        self.arena[ptr].is_shared()
     }
 }
 
@@ -112,7 +338,7 @@ impl<K, N> KeyBounded<K> for MemoryNode<N>
 where
     N: KeyBounded<K>,
 {
-    fn lower_bound(&self) -> &K {
+    fn lower_bound(&self) -> KeyBound<&K> {
         self.inner.lower_bound()
     }
 }
@@ -123,7 +349,12 @@ impl<N> AsRef<MemoryNode<N>> for &MemoryNode<N> {
     }
 }
 
-impl<N, PA> std::ops::Index<ArenaID> for MemoryList<N, PA> {
+/// Components address nodes by `ArenaID` rather than by pointer, and every access goes through
+/// these impls. An `ArenaID` is checked against the generation of its slot, so a stale address
+/// panics instead of reading another node, and the returned borrow keeps the arena from being
+/// mutated, and so from reallocating, while a node reference is live. A node is copied before it is
+/// borrowed mutably if a clone of the list still shares it.
+impl<N, PA, AL: ArenaAlloc> core::ops::Index<ArenaID> for MemoryList<N, PA, AL> {
     type Output = N;
 
     fn index(&self, index: ArenaID) -> &Self::Output {
@@ -131,16 +362,17 @@ impl<N, PA> std::ops::Index<ArenaID> for MemoryList<N, PA> {
     }
 }
 
-impl<N, PA> std::ops::IndexMut<ArenaID> for MemoryList<N, PA> {
+impl<N: Clone, PA: Clone, AL: ArenaAlloc> core::ops::IndexMut<ArenaID> for MemoryList<N, PA, AL> {
     fn index_mut(&mut self, index: ArenaID) -> &mut Self::Output {
-        &mut self.arena[index].0.inner
+        &mut self.slot_mut(index).0.inner
     }
 }
 
-impl<K, N, PA> NodeLayer<K, ArenaID, PA> for MemoryList<N, PA>
+impl<K, N, PA, AL> NodeLayer<K, ArenaID, PA> for MemoryList<N, PA, AL>
 where
+    AL: ArenaAlloc,
     K: Clone,
-    N: KeyBounded<K>,
+    N: KeyBounded<K> + PrefetchData + Clone,
     PA: Address,
 {
     fn parent(&self, ptr: ArenaID) -> Option<PA> {
@@ -148,11 +380,15 @@ where
     }
 
     fn set_parent(&mut self, ptr: ArenaID, parent: PA) {
-        self.arena[ptr].1 = Some(parent);
+        // Rebuilding a layer above resets the parent of every node, so skip the copy of nodes
+        // whose parent doesn't change
+        if self.arena[ptr].1.as_ref() != Some(&parent) {
+            self.slot_mut(ptr).1 = Some(parent);
+        }
     }
 
-    fn lower_bound(&self, ptr: ArenaID) -> K {
-        self.arena[ptr].0.lower_bound().clone()
+    fn lower_bound(&self, ptr: ArenaID) -> KeyBound<K> {
+        self.arena[ptr].0.lower_bound().cloned()
     }
 
     fn next(&self, ptr: ArenaID) -> Option<ArenaID> {
@@ -163,6 +399,11 @@ where
         self.arena[ptr].0.previous
     }
 
+    fn contains_node(&self, ptr: ArenaID) -> bool {
+        // Clearing or compacting the list bumps the generation of every slot
+        self.arena.contains(ptr)
+    }
+
     fn first(&self) -> ArenaID {
         self.first
     }
@@ -170,11 +411,177 @@ where
     fn last(&self) -> ArenaID {
         self.last
     }
+
+    fn node_count(&self) -> usize {
+        // Nodes are never removed from the arena, it is cleared as a whole instead
+        self.arena.len()
+    }
+
+    /// Every slot of the arena, and the reference counted node of every shared slot, along with
+    /// the heap memory reported through `track_heap`. Nodes shared with a clone are counted by both.
+    fn memory_usage(&self) -> usize {
+        let slots = self.arena.memory_usage();
+        let shared = self.shared
+            * (core::mem::size_of::<Entry<N, PA>>() + 2 * core::mem::size_of::<usize>());
+
+        slots + shared + self.heap
+    }
+
+    fn shared_count(&self) -> usize {
+        self.arena
+            .iter()
+            .filter(|(_, slot)| slot.is_shared())
+            .count()
+    }
+
+    fn share_nodes(&mut self) {
+        self.share();
+    }
+
+    /// Prefetches the slot of the node, and then the data the node keeps behind pointers of its
+    /// own, which is where a scan misses for nodes which don't store their entries inline
+    fn prefetch(&self, ptr: ArenaID) {
+        if let Some(slot) = self.arena.get(ptr) {
+            prefetch_read(&**slot);
+            slot.0.inner.prefetch_data();
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::ops::Bound;
+
+    #[test]
+    fn linked_list_next_chunk() {
+        let mut list: MemoryList<u32, ()> = MemoryList::empty();
+
+        let mut ptrs = vec![list.first];
+        for i in 1..10 {
+            ptrs.push(list.insert_after(i, *ptrs.last().unwrap()));
+        }
+
+        let mut iter = list.range(Bound::Unbounded, Bound::Unbounded);
+        let mut buffer = Vec::new();
+        let mut visited = Vec::new();
+
+        loop {
+            let count = iter.next_chunk(&mut buffer, 4);
+            assert_eq!(count, buffer.len());
+            if count == 0 {
+                break;
+            }
+
+            // Only the last chunk comes up short, whatever the capacity of the buffer
+            assert!(count == 4 || visited.len() + count == ptrs.len());
+            visited.extend(buffer.iter().copied());
+        }
+
+        let plain: Vec<_> = list.range(Bound::Unbounded, Bound::Unbounded).collect();
+        assert_eq!(visited, plain);
+        assert_eq!(visited.iter().map(|(_, ptr)| *ptr).collect::<Vec<_>>(), ptrs);
+    }
+
+    #[test]
+    fn linked_list_size_hint() {
+        let mut list: MemoryList<u32, ()> = MemoryList::empty();
+
+        let mut ptrs = vec![list.first];
+        for i in 1..10 {
+            ptrs.push(list.insert_after(i, *ptrs.last().unwrap()));
+        }
+
+        let mut nodes = list.nodes();
+        assert_eq!(nodes.len(), 10);
+        nodes.next();
+        assert_eq!(nodes.len(), 9);
+        assert_eq!(nodes.count(), 9);
+
+        // Partial ranges are only bounded by the size of the layer
+        let range = list.range(Bound::Included(ptrs[5]), Bound::Unbounded);
+        assert_eq!(range.size_hint(), (1, Some(10)));
+        assert_eq!(range.count(), 5);
+    }
+
+    #[test]
+    fn linked_list_with_alloc() {
+        use crate::common::list::alloc::BumpAlloc;
+
+        let mut list: MemoryList<u32, (), BumpAlloc> =
+            MemoryList::with_alloc(BumpAlloc { chunk: 8 });
+
+        let mut ptr = list.clear_with_hint(|| 20);
+        assert_eq!(list.capacity(), 20);
+
+        for i in 1..20 {
+            ptr = list.insert_after(i, ptr);
+        }
+        assert_eq!(list.capacity(), 20);
+
+        // A full arena only grows by a single chunk
+        let _ = list.insert_after(20, ptr);
+        assert_eq!(list.capacity(), 28);
+        assert_eq!(list.len(), 21);
+    }
+
+    #[test]
+    fn linked_list_clone_shares_nodes() {
+        let mut list: MemoryList<u32, ()> = MemoryList::empty();
+
+        let mut ptrs = vec![list.first];
+        for i in 1..4 {
+            ptrs.push(list.insert_after(i, *ptrs.last().unwrap()));
+        }
+
+        // Nodes are held inline and copied along with the list, until they are shared
+        let clone = list.clone();
+        assert_eq!(clone.shared_count(), 0);
+        assert!(!list.is_shared(ptrs[0]));
+
+        let unshared = list.memory_usage();
+        list.share();
+        assert!(list.memory_usage() > unshared);
+
+        let mut clone = list.clone();
+        assert_eq!(clone.shared_count(), 4);
+        assert!(ptrs.iter().all(|ptr| list.is_shared(*ptr)));
+
+        // Only the modified node is copied, the other list keeps the original
+        clone[ptrs[2]] = 20;
+        assert_eq!((list[ptrs[2]], clone[ptrs[2]]), (2, 20));
+        assert!(!list.is_shared(ptrs[2]));
+        assert_eq!(list.shared_count(), 3);
+
+        // Linking in a node copies its neighbours, since their links change
+        let _ = clone.insert_after(4, ptrs[0]);
+        assert_eq!(list.shared_count(), 1);
+        assert_eq!(list.nodes().count(), 4);
+        assert_eq!(clone.nodes().count(), 5);
+    }
+
+    #[test]
+    fn linked_list_memory_usage() {
+        let mut list: MemoryList<u32, ()> = MemoryList::empty();
+        let empty = list.memory_usage();
+        assert!(empty > 0);
+
+        // Nodes are held in the slots of the arena, so only growing the arena grows the list
+        let mut ptr = list.first;
+        for i in 0..list.capacity() as u32 {
+            ptr = list.insert_after(i, ptr);
+        }
+        let grown = list.memory_usage();
+        assert!(grown > empty);
+
+        // Heap memory reported by the layer is added on top, and dropped with the nodes
+        list.track_heap(0, 1_000);
+        list.track_heap(1_000, 1_500);
+        assert_eq!(list.memory_usage(), grown + 1_500);
+
+        let _ = list.clear();
+        assert_eq!(list.memory_usage(), grown);
+    }
 
     #[test]
     fn test_linked_list_new() {
@@ -185,6 +592,54 @@ mod tests {
         assert_eq!(list.first, list.last);
     }
 
+    #[test]
+    fn linked_list_compact() {
+        let mut list: MemoryList<i32, i32> = MemoryList::empty();
+
+        // Every node is inserted right after the first, so the arena holds them in reverse order
+        let first = list.first;
+        let ptrs: Vec<_> = (1..10).map(|i| list.insert_after(i, first)).collect();
+        for (i, ptr) in ptrs.iter().enumerate() {
+            list.set_parent(*ptr, i as i32);
+        }
+
+        let remap = list.compact();
+        assert_eq!(remap.len(), 10);
+        assert!(list.arena.get(ptrs[0]).is_none());
+
+        // Walking the list now walks the arena front to back
+        let mut ptr = Some(list.first);
+        let mut walked = Vec::new();
+        while let Some(current) = ptr {
+            walked.push((current.into_raw_parts().0, list[current]));
+            ptr = NodeLayer::<i32, _, _>::next(&list, current);
+        }
+
+        let expected: Vec<_> = core::iter::once(0)
+            .chain((1..10).rev())
+            .enumerate()
+            .collect();
+        assert_eq!(walked, expected);
+        assert_eq!(list.last, remap[&ptrs[0]]);
+
+        list.remap_parents(&(0..9).map(|i| (i, i + 100)).collect());
+        assert_eq!(
+            NodeLayer::<i32, _, _>::parent(&list, remap[&ptrs[3]]),
+            Some(103)
+        );
+
+        // Shared nodes move back into the arena, and a clone still sharing them keeps its own
+        let inline = list.memory_usage();
+        list.share();
+        let clone = list.clone();
+        let head = list.first;
+
+        let remap = list.compact();
+        assert_eq!((list.shared_count(), clone.shared_count()), (0, 0));
+        assert_eq!(list.memory_usage(), inline);
+        assert_eq!(list[remap[&head]], clone[head]);
+    }
+
     #[test]
     fn linked_list_insert_after() {
         let mut list: MemoryList<u32, ()> = MemoryList::empty();
@@ -212,11 +667,11 @@ mod tests {
     #[test]
     fn test_linked_list_clear() {
         let mut list: MemoryList<i32, ()> = MemoryList::empty();
-        list.insert_after(2, list.first);
+        let _ = list.insert_after(2, list.first);
 
         assert_eq!(list.arena.len(), 2);
 
-        list.clear();
+        let _ = list.clear();
 
         assert_eq!(list.len(), 1);
         assert_eq!(list[list.first], Default::default());
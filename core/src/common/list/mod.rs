@@ -1,3 +1,7 @@
+pub mod alloc;
+pub mod arena;
+#[cfg(feature = "std")]
 pub mod boundary_disk;
+#[cfg(feature = "std")]
 pub mod deep_disk;
 pub mod memory;
@@ -1,2 +1,11 @@
 pub mod list;
+pub mod mvcc;
+#[cfg(feature = "olc")]
+pub mod olc;
+pub mod prefetch;
+pub mod reverse;
+#[cfg(feature = "std")]
 pub mod storage;
+pub mod tombstone;
+pub mod ttl;
+pub mod u256;
@@ -0,0 +1,99 @@
+//! Multi-version values. In a versioned layout every insert is stamped with a monotonically
+//! increasing version, and the base layer stores the chain of values each key held over time
+//! instead of only the latest one.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Position of a write in the history of a versioned index
+pub type Version = u64;
+
+/// The values a single key held over time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionChain<V> {
+    // Ordered from oldest to newest
+    versions: Vec<(Version, V)>,
+}
+
+impl<V> VersionChain<V> {
+    pub fn new(version: Version, value: V) -> Self {
+        Self {
+            versions: vec![(version, value)],
+        }
+    }
+
+    /// Record a new value, `version` must be newer than every version already in the chain
+    pub fn push(&mut self, version: Version, value: V) {
+        debug_assert!(self.versions.last().is_none_or(|(last, _)| *last < version));
+        self.versions.push((version, value));
+    }
+
+    /// The most recent value
+    pub fn latest(&self) -> Option<&V> {
+        self.versions.last().map(|(_, value)| value)
+    }
+
+    /// The value as of `version`, ignoring every later write
+    pub fn at(&self, version: Version) -> Option<&V> {
+        let index = self.versions.partition_point(|(v, _)| *v <= version);
+
+        match index {
+            0 => None,
+            _ => Some(&self.versions[index - 1].1),
+        }
+    }
+
+    /// Drop every value which is no longer visible as of `before_version` or later
+    pub fn gc(&mut self, before_version: Version) {
+        let index = self.versions.partition_point(|(v, _)| *v <= before_version);
+
+        if index > 1 {
+            self.versions.drain(..index - 1);
+        }
+    }
+
+    /// Number of versions in the chain
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_chain_at() {
+        let mut chain = VersionChain::new(2, "a");
+        chain.push(5, "b");
+        chain.push(9, "c");
+
+        assert_eq!(chain.at(1), None);
+        assert_eq!(chain.at(2), Some(&"a"));
+        assert_eq!(chain.at(4), Some(&"a"));
+        assert_eq!(chain.at(5), Some(&"b"));
+        assert_eq!(chain.at(100), Some(&"c"));
+        assert_eq!(chain.latest(), Some(&"c"));
+    }
+
+    #[test]
+    fn version_chain_gc() {
+        let mut chain = VersionChain::new(2, "a");
+        chain.push(5, "b");
+        chain.push(9, "c");
+
+        // The value visible at version 6 has to survive
+        chain.gc(6);
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.at(6), Some(&"b"));
+        assert_eq!(chain.at(9), Some(&"c"));
+
+        chain.gc(100);
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain.latest(), Some(&"c"));
+    }
+}
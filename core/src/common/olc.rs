@@ -0,0 +1,94 @@
+//! Versioned locks for optimistic lock coupling. Readers never write to a lock: they remember its
+//! version before reading the data it protects, and validate afterwards that no writer held the
+//! lock in the meantime, restarting their operation otherwise. Writers acquire the lock exclusively
+//! by upgrading the version they read.
+
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+/// Set while a writer holds the lock. Unlocking increments the version past it, so every write
+/// produces a new version.
+const LOCKED: u64 = 1;
+
+/// Returned when an optimistic operation observed a concurrent write, and has to start over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Restart;
+
+/// A lock whose readers don't write to shared memory
+#[derive(Debug, Default)]
+pub struct OptLock {
+    version: AtomicU64,
+}
+
+impl OptLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin an optimistic read, returning the version to validate against
+    pub fn read_lock(&self) -> Result<u64, Restart> {
+        let version = self.version.load(Ordering::Acquire);
+
+        if version & LOCKED != 0 {
+            core::hint::spin_loop();
+            return Err(Restart);
+        }
+
+        Ok(version)
+    }
+
+    /// Check that nothing was written since `read_lock` returned `version`, meaning that everything
+    /// read in between is consistent
+    pub fn validate(&self, version: u64) -> Result<(), Restart> {
+        fence(Ordering::Acquire);
+
+        if self.version.load(Ordering::Relaxed) != version {
+            return Err(Restart);
+        }
+
+        Ok(())
+    }
+
+    /// Turn an optimistic read into exclusive access, failing if there was a write since
+    pub fn upgrade(&self, version: u64) -> Result<(), Restart> {
+        self.version
+            .compare_exchange(
+                version,
+                version | LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .map_err(|_| Restart)?;
+
+        // Keep the writes which follow from being observed before the lock
+        fence(Ordering::Release);
+        Ok(())
+    }
+
+    pub fn write_unlock(&self) {
+        self.version.fetch_add(LOCKED, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn olc_versions() {
+        let lock = OptLock::new();
+        let version = lock.read_lock().unwrap();
+        assert_eq!(lock.validate(version), Ok(()));
+
+        lock.upgrade(version).unwrap();
+        assert_eq!(lock.read_lock(), Err(Restart));
+        assert_eq!(lock.validate(version), Err(Restart));
+
+        // Writes invalidate every version read before them
+        lock.write_unlock();
+        assert_eq!(lock.validate(version), Err(Restart));
+        assert_eq!(lock.upgrade(version), Err(Restart));
+
+        let next = lock.read_lock().unwrap();
+        assert_ne!(next, version);
+    }
+}
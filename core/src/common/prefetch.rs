@@ -0,0 +1,37 @@
+//! Software prefetching of nodes ahead of a scan. Prefetching is skipped with `safe-mode`, since
+//! Miri doesn't support the intrinsic.
+
+/// Upper bound on the number of cache lines prefetched for a single region, large regions are only
+/// partially prefetched since the hardware prefetcher picks up sequential access after that
+#[allow(unused)]
+const MAX_PREFETCH_LINES: usize = 8;
+#[allow(unused)]
+const CACHE_LINE_SIZE: usize = 64;
+
+/// Prefetch the cache lines holding `value` itself, but nothing it points to
+#[inline(always)]
+pub fn prefetch_read<T>(value: &T) {
+    prefetch_bytes(value as *const T as *const u8, core::mem::size_of::<T>());
+}
+
+/// Prefetch the first cache lines of the elements of `slice`, wherever they are allocated
+#[inline(always)]
+pub fn prefetch_slice<T>(slice: &[T]) {
+    prefetch_bytes(slice.as_ptr() as *const u8, core::mem::size_of_val(slice));
+}
+
+#[inline(always)]
+fn prefetch_bytes(base: *const u8, len: usize) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "safe-mode")))]
+    {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+        for line in (0..len).step_by(CACHE_LINE_SIZE).take(MAX_PREFETCH_LINES) {
+            // SAFETY: prefetching is only a hint, and every address lies within the region
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(base.add(line) as *const i8) };
+        }
+    }
+
+    #[cfg(any(not(target_arch = "x86_64"), feature = "safe-mode"))]
+    let _ = (base, len);
+}
@@ -0,0 +1,76 @@
+//! Reverse lookups from values to the keys holding them. A layout with `reverse_lookup: true`
+//! keeps a `ReverseIndex` next to its layers, and updates it on every insert. Values are compared
+//! by their order rather than hashed, so any `Ord` value works, and the keys of a value come back
+//! in ascending order.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+/// The keys holding every value of an index
+#[derive(Clone, Debug)]
+pub struct ReverseIndex<K, V> {
+    keys: BTreeMap<V, BTreeSet<K>>,
+}
+
+impl<K: Ord, V: Ord> ReverseIndex<K, V> {
+    pub fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Record that `key` now holds `value`, replacing `previous` if it held a value before
+    pub fn insert(&mut self, key: K, value: V, previous: Option<&V>) {
+        if let Some(previous) = previous {
+            if let Some(keys) = self.keys.get_mut(previous) {
+                keys.remove(&key);
+
+                if keys.is_empty() {
+                    self.keys.remove(previous);
+                }
+            }
+        }
+
+        self.keys.entry(value).or_default().insert(key);
+    }
+
+    /// Every key holding `value`, in ascending order
+    pub fn find(&self, value: &V) -> impl Iterator<Item = &K> + '_ {
+        self.keys.get(value).into_iter().flatten()
+    }
+
+    /// Number of distinct values
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<K: Ord, V: Ord> Default for ReverseIndex<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reverse_index_overwrite() {
+        let mut index = ReverseIndex::new();
+
+        index.insert(1, "a", None);
+        index.insert(3, "a", None);
+        index.insert(2, "b", None);
+        assert_eq!(index.find(&"a").collect::<Vec<_>>(), [&1, &3]);
+
+        // Overwriting the last key of a value drops the value
+        index.insert(2, "a", Some(&"b"));
+        assert_eq!(index.find(&"a").collect::<Vec<_>>(), [&1, &2, &3]);
+        assert_eq!(index.find(&"b").count(), 0);
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+//! Where a `GlobalStore` keeps its pages. A backend is a flat map from page ids to byte strings,
+//! everything above it, such as caching, compression and encryption, is handled by the store.
+
+use super::lock::{StoreLock, StoreLocked};
+use super::StoreID;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Size of the data held by a backend
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Number of pages currently stored
+    pub pages: u64,
+
+    /// Bytes taken up by the backend, including space not yet reclaimed by `maintenance`
+    pub bytes: u64,
+
+    /// Share of `bytes` held by overwritten or removed pages, which backends may only estimate
+    pub dead_bytes: u64,
+}
+
+/// What had to be recovered when a `GlobalStore` was loaded, see `GlobalStore::load_with_report`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Pages the backend applied from its own log of unfinished writes
+    pub pages_replayed: u64,
+
+    /// Partially written pages the backend threw away
+    pub pages_discarded: u64,
+
+    /// Catalogs which were missing and had to be created again, losing track of the pages they held
+    pub catalogs_rebuilt: u64,
+}
+
+impl RecoveryReport {
+    /// Whether the store was shut down cleanly, and nothing had to be recovered
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Storage of raw pages underneath a `GlobalStore`
+pub trait StorageBackend: 'static {
+    /// Read the page stored at `id`, if any
+    fn read(&self, id: StoreID) -> crate::Result<Option<Vec<u8>>>;
+
+    /// Write every page of `batch`, removing the pages which map to `None`. Backends should apply
+    /// a batch atomically if they can.
+    fn write_batch(&self, batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()>;
+
+    /// Reclaim space held by overwritten or removed pages, returning the number of pages moved
+    fn maintenance(&self) -> crate::Result<usize>;
+
+    fn stats(&self) -> StorageStats;
+
+    /// Clean up after a crash, right before the store is loaded. Backends which finish or roll back
+    /// interrupted writes on their own have nothing to report.
+    fn recover(&self) -> crate::Result<RecoveryReport> {
+        Ok(RecoveryReport::default())
+    }
+}
+
+/// The default backend, a log-structured object store which applies batches atomically
+pub struct MarbleBackend {
+    inner: marble::Marble,
+
+    /// Lock on the directory, held until the backend is dropped, unless it was opened read-only
+    _lock: Option<StoreLock>,
+}
+
+impl MarbleBackend {
+    /// Open the store in `path`, or create one. The backend holds a lock on the directory until it
+    /// is dropped, and opening it again in the meantime, from this process or another, fails with
+    /// `StoreLocked`.
+    pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let lock = StoreLock::acquire(path.as_ref())?;
+
+        Ok(Self {
+            inner: marble::open(path.as_ref())?,
+            _lock: Some(lock),
+        })
+    }
+
+    /// Open the existing store in `path` without taking its lock, for a `GlobalStore` loaded
+    /// read-only. Marble locks its directory for as long as it is open, so this still fails with
+    /// `StoreLocked` while another backend has the store open.
+    pub fn open_read_only(path: impl AsRef<Path>) -> crate::Result<Self> {
+        if !path.as_ref().is_dir() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+
+        match marble::open(path.as_ref()) {
+            Ok(inner) => Ok(Self { inner, _lock: None }),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                Err(StoreLocked::read(path).into())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl StorageBackend for MarbleBackend {
+    fn read(&self, id: StoreID) -> crate::Result<Option<Vec<u8>>> {
+        Ok(self.inner.read(id)?.map(Vec::from))
+    }
+
+    fn write_batch(&self, batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()> {
+        Ok(self.inner.write_batch(batch)?)
+    }
+
+    fn maintenance(&self) -> crate::Result<usize> {
+        Ok(self.inner.maintenance()?)
+    }
+
+    fn stats(&self) -> StorageStats {
+        let stats = self.inner.stats();
+
+        // Marble only counts dead objects, so their share of the files is an estimate
+        let dead_ratio = if stats.stored_objects > 0 {
+            stats.dead_objects as f64 / stats.stored_objects as f64
+        } else {
+            0.0
+        };
+
+        StorageStats {
+            pages: stats.live_objects,
+            bytes: stats.total_file_size,
+            dead_bytes: (stats.total_file_size as f64 * dead_ratio.clamp(0.0, 1.0)) as u64,
+        }
+    }
+}
+
+/// Stores every page in its own file within a directory. Each page is written to a temporary file
+/// which is synced and renamed over the page, so pages are never torn, but a batch interrupted by a
+/// crash may be partially applied. Pages are only ever renamed into place, so the directory can be
+/// read by a backend opened with `open_read_only` while another one writes to it.
+pub struct FileBackend {
+    dir: PathBuf,
+
+    /// Lock on the directory, held until the backend is dropped. Backends opened read-only hold
+    /// none, and refuse every write.
+    lock: Option<StoreLock>,
+}
+
+const PAGE_EXTENSION: &str = "page";
+const TEMP_EXTENSION: &str = "tmp";
+
+impl FileBackend {
+    /// Open the store in `path`, or create one. The backend holds a lock on the directory until it
+    /// is dropped, and opening it again in the meantime, from this process or another, fails with
+    /// `StoreLocked`.
+    pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let lock = StoreLock::acquire(path.as_ref())?;
+
+        Ok(Self {
+            dir: path.as_ref().to_path_buf(),
+            lock: Some(lock),
+        })
+    }
+
+    /// Open the existing store in `path` without taking its lock, alongside the backend which
+    /// writes to it. Every write, removal and cleanup fails.
+    pub fn open_read_only(path: impl AsRef<Path>) -> crate::Result<Self> {
+        if !path.as_ref().is_dir() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+
+        Ok(Self {
+            dir: path.as_ref().to_path_buf(),
+            lock: None,
+        })
+    }
+
+    /// Fail unless the backend holds the lock on its directory
+    fn check_writable(&self) -> crate::Result<()> {
+        match self.lock {
+            Some(_) => Ok(()),
+            None => Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into()),
+        }
+    }
+
+    fn page_path(&self, id: StoreID) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", id, PAGE_EXTENSION))
+    }
+
+    /// Files in the directory with the given extension
+    fn files(
+        &self,
+        extension: &'static str,
+    ) -> crate::Result<impl Iterator<Item = std::fs::DirEntry>> {
+        Ok(std::fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(move |entry| entry.path().extension().is_some_and(|ext| ext == extension)))
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn read(&self, id: StoreID) -> crate::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.page_path(id)) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn write_batch(&self, batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()> {
+        self.check_writable()?;
+
+        for (id, data) in batch {
+            let path = self.page_path(id);
+
+            match data {
+                Some(data) => {
+                    let temp = path.with_extension(TEMP_EXTENSION);
+
+                    let mut file = File::create(&temp)?;
+                    file.write_all(&data)?;
+                    file.sync_all()?;
+
+                    std::fs::rename(&temp, &path)?;
+                }
+                None => match std::fs::remove_file(&path) {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(error.into())
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        // Renames and removals are only durable once the directory itself is synced
+        #[cfg(unix)]
+        File::open(&self.dir)?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Pages are rewritten in place, so there is nothing to reclaim besides temporary files left
+    /// behind by a crash
+    fn maintenance(&self) -> crate::Result<usize> {
+        self.recover()?;
+        Ok(0)
+    }
+
+    fn stats(&self) -> StorageStats {
+        let size = |entry: std::fs::DirEntry| entry.metadata().map_or(0, |metadata| metadata.len());
+        let mut stats = StorageStats::default();
+
+        for entry in self.files(PAGE_EXTENSION).into_iter().flatten() {
+            stats.pages += 1;
+            stats.bytes += size(entry);
+        }
+
+        for entry in self.files(TEMP_EXTENSION).into_iter().flatten() {
+            stats.dead_bytes += size(entry);
+        }
+
+        stats.bytes += stats.dead_bytes;
+        stats
+    }
+
+    /// A temporary file is a page whose write was interrupted before it was renamed over the page
+    fn recover(&self) -> crate::Result<RecoveryReport> {
+        self.check_writable()?;
+        let mut report = RecoveryReport::default();
+
+        for entry in self.files(TEMP_EXTENSION)? {
+            std::fs::remove_file(entry.path())?;
+            report.pages_discarded += 1;
+        }
+
+        Ok(report)
+    }
+}
+
+/// Keeps pages in memory, meant for tests. Clones share the same pages, so a store can be loaded
+/// again from a clone of the backend after it was dropped.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    pages: Rc<RefCell<HashMap<StoreID, Vec<u8>>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read(&self, id: StoreID) -> crate::Result<Option<Vec<u8>>> {
+        Ok(self.pages.borrow().get(&id).cloned())
+    }
+
+    fn write_batch(&self, batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()> {
+        let mut pages = self.pages.borrow_mut();
+
+        for (id, data) in batch {
+            match data {
+                Some(data) => pages.insert(id, data),
+                None => pages.remove(&id),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn maintenance(&self) -> crate::Result<usize> {
+        Ok(0)
+    }
+
+    fn stats(&self) -> StorageStats {
+        let pages = self.pages.borrow();
+
+        StorageStats {
+            pages: pages.len() as u64,
+            bytes: pages.values().map(|page| page.len() as u64).sum(),
+            dead_bytes: 0,
+        }
+    }
+}
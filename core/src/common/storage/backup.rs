@@ -0,0 +1,135 @@
+//! Incremental backups of a `GlobalStore`. Every batch written to a store advances its generation,
+//! and the store remembers the generation each page last changed in. A backup is then a stream of
+//! `PageDelta`s, one for every page which changed since the generation of the previous backup,
+//! which a replica applies as is. Pages are shipped as stored, compressed and encrypted, so the
+//! replica has to be opened with the same key as the primary.
+
+use super::format::{self, checksum};
+use super::StoreID;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Generation every page last changed in, kept alongside the catalog of a `GlobalStore`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub(super) struct ChangeLog {
+    pub generation: u64,
+    #[serde(serialize_with = "format::sorted")]
+    pub pages: HashMap<StoreID, u64>,
+}
+
+impl ChangeLog {
+    /// A log for a store written before changes were tracked, in which every page is as old as
+    /// the first generation
+    pub fn legacy(ids: impl Iterator<Item = StoreID>) -> Self {
+        Self {
+            generation: 1,
+            pages: ids.map(|id| (id, 1)).collect(),
+        }
+    }
+
+    /// Start a new generation, in which every page of `ids` changed
+    pub fn record(&mut self, ids: impl Iterator<Item = StoreID>) {
+        self.generation += 1;
+
+        for id in ids {
+            self.pages.insert(id, self.generation);
+        }
+    }
+
+    /// Every page which changed after `generation`, ordered by id
+    pub fn changed_since(&self, generation: u64) -> Vec<StoreID> {
+        let mut ids: Vec<StoreID> = self
+            .pages
+            .iter()
+            .filter(|(_, changed)| **changed > generation)
+            .map(|(id, _)| *id)
+            .collect();
+
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// A page which changed on the primary, or `None` if it was removed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PageDelta {
+    pub id: StoreID,
+    pub data: Option<Vec<u8>>,
+
+    /// Checksum of `data`, verified before the delta is applied
+    pub checksum: u64,
+}
+
+impl PageDelta {
+    pub fn new(id: StoreID, data: Option<Vec<u8>>) -> Self {
+        let checksum = checksum(data.as_deref().unwrap_or_default());
+        Self { id, data, checksum }
+    }
+
+    /// Whether the data of the delta still matches its checksum
+    pub fn verify(&self) -> bool {
+        checksum(self.data.as_deref().unwrap_or_default()) == self.checksum
+    }
+}
+
+/// Write a delta to a backup stream, prefixed by its length
+pub(super) fn write_delta(writer: &mut impl Write, delta: &PageDelta) -> crate::Result<()> {
+    let data = format::encode(delta)?;
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(&data)?;
+
+    Ok(())
+}
+
+/// Read every delta of a backup stream, failing if any of them doesn't match its checksum
+pub(super) fn read_deltas(reader: &mut impl Read) -> crate::Result<Vec<PageDelta>> {
+    let mut deltas = Vec::new();
+    let mut len = [0; 8];
+
+    loop {
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error.into()),
+        }
+
+        let mut data = vec![0; u64::from_le_bytes(len) as usize];
+        reader.read_exact(&mut data)?;
+
+        let delta: PageDelta = format::decode(&data)?;
+        if !delta.verify() {
+            anyhow::bail!("Page {} of the backup is corrupted!", delta.id);
+        }
+
+        deltas.push(delta);
+    }
+
+    Ok(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_stream_round_trip() {
+        let deltas = vec![
+            PageDelta::new(0, Some(b"catalog".to_vec())),
+            PageDelta::new(3, None),
+            PageDelta::new(7, Some(vec![0; 1024])),
+        ];
+
+        let mut stream = Vec::new();
+        for delta in deltas.iter() {
+            write_delta(&mut stream, delta).unwrap();
+        }
+
+        assert_eq!(read_deltas(&mut stream.as_slice()).unwrap(), deltas);
+
+        // Flip a byte of the last page
+        let last = stream.len() - 100;
+        stream[last] ^= 1;
+        assert!(read_deltas(&mut stream.as_slice()).is_err());
+    }
+}
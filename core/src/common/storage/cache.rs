@@ -0,0 +1,336 @@
+//! The page cache of every `LocalStore`. Evictable pages are evicted in least recently used order,
+//! and with `CachePolicy::TinyLfu`, a page read from the backend only replaces the least recently
+//! used page if it was accessed more often recently, as estimated by a small frequency sketch. A
+//! scan reads every page once, so under TinyLFU it can't push out the pages of point lookups,
+//! which are read over and over.
+
+use super::StoreID;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+
+/// How the page cache of a `GlobalStore` picks which pages read from the backend to keep
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Every page read is cached, evicting the least recently used page once the cache is full
+    #[default]
+    Lru,
+
+    /// A page read once the cache is full is only cached if it was accessed more often than the
+    /// least recently used page, which it evicts
+    TinyLfu,
+}
+
+/// Lookups into the page caches of every local store of a `GlobalStore`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub policy: CachePolicy,
+
+    /// Pages looked up which were cached
+    pub hits: u64,
+
+    /// Pages looked up which had to be read from the backend
+    pub misses: u64,
+
+    /// Pages evicted to make room for others
+    pub evictions: u64,
+
+    /// Pages read from the backend which TinyLFU didn't cache
+    pub rejected: u64,
+}
+
+impl CacheStats {
+    /// Fraction of the pages looked up which were cached
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Rows of the frequency sketch, each hashing pages to their own counter
+const ROWS: usize = 4;
+
+/// Counters saturate here, a page accessed more often than that is simply hot
+const MAX_COUNT: u8 = 15;
+
+/// Counters are halved once this many accesses per counter of a row were counted, so that the
+/// sketch only reflects recent accesses
+const SAMPLE_FACTOR: usize = 10;
+
+const SEEDS: [u64; ROWS] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xbf58_476d_1ce4_e5b9,
+    0x94d0_49bb_1331_11eb,
+    0x2545_f491_4f6c_dd1d,
+];
+
+/// Count-min sketch of how often every page was accessed lately
+struct FrequencySketch {
+    counters: Vec<u8>,
+    width: usize,
+    additions: usize,
+}
+
+impl FrequencySketch {
+    fn new(capacity: usize) -> Self {
+        let width = capacity.next_power_of_two().clamp(64, 1 << 20);
+
+        Self {
+            counters: vec![0; ROWS * width],
+            width,
+            additions: 0,
+        }
+    }
+
+    fn slot(&self, id: StoreID, row: usize) -> usize {
+        // Finalizer of splitmix64
+        let mut hash = id ^ SEEDS[row];
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+
+        row * self.width + (hash as usize & (self.width - 1))
+    }
+
+    fn record(&mut self, id: StoreID) {
+        for row in 0..ROWS {
+            let slot = self.slot(id, row);
+            self.counters[slot] = (self.counters[slot] + 1).min(MAX_COUNT);
+        }
+
+        self.additions += 1;
+        if self.additions >= SAMPLE_FACTOR * self.width {
+            self.counters.iter_mut().for_each(|count| *count /= 2);
+            self.additions /= 2;
+        }
+    }
+
+    fn estimate(&self, id: StoreID) -> u8 {
+        (0..ROWS)
+            .map(|row| self.counters[self.slot(id, row)])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// Pages of a local store, along with the order they were last used in. A page is cached as
+/// `None` once it was freed. Dirty pages are never evicted, they are held until written out.
+pub(crate) struct PageCache<P> {
+    pages: HashMap<StoreID, (Option<P>, u64)>,
+    recency: BTreeMap<u64, StoreID>,
+    tick: u64,
+
+    /// Pages held before evicting, unless the store is resident
+    capacity: usize,
+    resident: bool,
+
+    sketch: Option<FrequencySketch>,
+    stats: Rc<Cell<CacheStats>>,
+}
+
+impl<P> PageCache<P> {
+    pub fn new(policy: CachePolicy, capacity: usize, stats: Rc<Cell<CacheStats>>) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            pages: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            capacity,
+            resident: false,
+            sketch: (policy == CachePolicy::TinyLfu).then(|| FrequencySketch::new(capacity)),
+            stats,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_resident(&mut self, resident: bool) {
+        self.resident = resident;
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn contains(&self, id: StoreID) -> bool {
+        self.pages.contains_key(&id)
+    }
+
+    /// The cached page `id`, without counting it as used
+    pub fn peek(&self, id: StoreID) -> Option<&Option<P>> {
+        self.pages.get(&id).map(|(page, _)| page)
+    }
+
+    /// Look up the page `id`, counting it as a hit and marking it as the most recently used if
+    /// it is cached, and as a miss otherwise
+    pub fn get(&mut self, id: StoreID) -> Option<&Option<P>> {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.record(id);
+        }
+
+        let cached = self.pages.contains_key(&id);
+        self.update_stats(|stats| match cached {
+            true => stats.hits += 1,
+            false => stats.misses += 1,
+        });
+
+        if cached {
+            self.touch(id);
+        }
+
+        self.peek(id)
+    }
+
+    /// Cache a page which was written or freed, which has to stay cached until it is written
+    /// out, evicting clean pages if the cache is full
+    pub fn insert(&mut self, id: StoreID, page: Option<P>, dirty: &HashSet<StoreID>) {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.record(id);
+        }
+
+        self.put(id, page);
+        self.shrink(dirty);
+    }
+
+    /// Cache a page read from the backend, unless the cache is full and TinyLFU estimates the
+    /// page was accessed less often than the one it would evict
+    pub fn admit(&mut self, id: StoreID, page: P, dirty: &HashSet<StoreID>) {
+        if self.resident || self.pages.len() < self.capacity || self.contains(id) {
+            self.put(id, Some(page));
+            return;
+        }
+
+        // With only dirty pages cached, the page is kept until they are written out
+        if let (Some(sketch), Some(victim)) = (self.sketch.as_ref(), self.victim(dirty)) {
+            if sketch.estimate(id) <= sketch.estimate(victim) {
+                self.update_stats(|stats| stats.rejected += 1);
+                return;
+            }
+        }
+
+        self.put(id, Some(page));
+        self.shrink(dirty);
+    }
+
+    /// Evict clean pages in least recently used order until the cache is within its capacity
+    pub fn shrink(&mut self, dirty: &HashSet<StoreID>) {
+        if self.resident {
+            return;
+        }
+
+        while self.pages.len() > self.capacity {
+            let Some(victim) = self.victim(dirty) else {
+                break;
+            };
+
+            let (_, used) = self.pages.remove(&victim).unwrap();
+            self.recency.remove(&used);
+            self.update_stats(|stats| stats.evictions += 1);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.recency.clear();
+    }
+
+    /// The least recently used page which can be evicted
+    fn victim(&self, dirty: &HashSet<StoreID>) -> Option<StoreID> {
+        self.recency
+            .values()
+            .find(|id| !dirty.contains(id))
+            .copied()
+    }
+
+    fn put(&mut self, id: StoreID, page: Option<P>) {
+        self.tick += 1;
+        if let Some((_, used)) = self.pages.insert(id, (page, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, id);
+    }
+
+    fn touch(&mut self, id: StoreID) {
+        if let Some((_, used)) = self.pages.get_mut(&id) {
+            self.recency.remove(used);
+            self.tick += 1;
+            *used = self.tick;
+            self.recency.insert(self.tick, id);
+        }
+    }
+
+    fn update_stats(&self, update: impl FnOnce(&mut CacheStats)) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(policy: CachePolicy, capacity: usize) -> PageCache<u64> {
+        let stats = CacheStats {
+            policy,
+            ..Default::default()
+        };
+        PageCache::new(policy, capacity, Rc::new(Cell::new(stats)))
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let clean = HashSet::new();
+        let mut cache = cache(CachePolicy::Lru, 2);
+
+        cache.admit(1, 10, &clean);
+        cache.admit(2, 20, &clean);
+        assert_eq!(cache.get(1), Some(&Some(10)));
+
+        cache.admit(3, 30, &clean);
+        assert!(cache.contains(1) && !cache.contains(2) && cache.contains(3));
+
+        // Dirty pages stay until they are written out
+        let dirty = HashSet::from([1, 3, 4]);
+        cache.insert(4, Some(40), &dirty);
+        assert_eq!(cache.len(), 3);
+        cache.shrink(&clean);
+        assert!(!cache.contains(1) && cache.contains(4));
+
+        let stats = cache.stats.get();
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+        assert_eq!(stats.evictions, 2);
+    }
+
+    #[test]
+    fn tinylfu_rejects_cold_pages() {
+        let clean = HashSet::new();
+        let mut cache = cache(CachePolicy::TinyLfu, 4);
+
+        for _ in 0..3 {
+            for id in 0..4 {
+                if cache.get(id).is_none() {
+                    cache.admit(id, id * 10, &clean);
+                }
+            }
+        }
+
+        // A scan of pages read once doesn't evict the hot ones
+        for id in 100..200 {
+            assert_eq!(cache.get(id), None);
+            cache.admit(id, id * 10, &clean);
+        }
+
+        assert!((0..4).all(|id| cache.contains(id)));
+
+        let stats = cache.stats.get();
+        assert_eq!(stats.rejected, 100);
+        assert_eq!(stats.evictions, 0);
+        assert_eq!((stats.hits, stats.misses), (8, 104));
+    }
+}
@@ -0,0 +1,138 @@
+//! Transparent compression of the pages written by a `LocalStore`. Compressed stores prefix every
+//! page with a one byte flag recording how it is encoded, so a page which does not shrink is kept
+//! raw, and pages written with one codec can still be read back with another.
+
+use std::borrow::Cow;
+
+const RAW: u8 = 0;
+const LZ4: u8 = 1;
+const ZSTD: u8 = 2;
+
+/// Decides how the serialized pages of a `LocalStore` are encoded on disk
+pub trait PageCompression: 'static {
+    /// Encode a serialized page
+    fn encode(page: Vec<u8>) -> crate::Result<Vec<u8>>;
+
+    /// Decode a page written by `encode`
+    fn decode(data: &[u8]) -> crate::Result<Cow<'_, [u8]>>;
+}
+
+/// Pages are stored as is, without a flag, which is the format of every store predating
+/// compression
+pub struct NoCompression;
+
+impl PageCompression for NoCompression {
+    fn encode(page: Vec<u8>) -> crate::Result<Vec<u8>> {
+        Ok(page)
+    }
+
+    fn decode(data: &[u8]) -> crate::Result<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+/// Fast compression with a modest ratio, suited to pages which are read often
+pub struct Lz4;
+
+impl PageCompression for Lz4 {
+    fn encode(page: Vec<u8>) -> crate::Result<Vec<u8>> {
+        Ok(flag_smaller(page, LZ4, lz4_flex::compress_prepend_size))
+    }
+
+    fn decode(data: &[u8]) -> crate::Result<Cow<'_, [u8]>> {
+        decode_flagged(data)
+    }
+}
+
+/// Zstandard compression at `LEVEL`, trading CPU for fewer bytes read from disk
+pub struct Zstd<const LEVEL: i32>;
+
+impl<const LEVEL: i32> PageCompression for Zstd<LEVEL> {
+    fn encode(page: Vec<u8>) -> crate::Result<Vec<u8>> {
+        let compressed = zstd::bulk::compress(&page, LEVEL)?;
+        let size = u32::try_from(page.len())?;
+
+        // Zstandard frames don't always record their size, so it is prepended like for lz4
+        Ok(flag_smaller(page, ZSTD, |_| {
+            let mut data = size.to_le_bytes().to_vec();
+            data.extend_from_slice(&compressed);
+            data
+        }))
+    }
+
+    fn decode(data: &[u8]) -> crate::Result<Cow<'_, [u8]>> {
+        decode_flagged(data)
+    }
+}
+
+/// Flag the page with `flag` if compressing it saves space, and store it raw otherwise
+fn flag_smaller(page: Vec<u8>, flag: u8, compress: impl FnOnce(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    let compressed = compress(&page);
+
+    let (flag, payload) = if compressed.len() < page.len() {
+        (flag, compressed)
+    } else {
+        (RAW, page)
+    };
+
+    let mut data = Vec::with_capacity(payload.len() + 1);
+    data.push(flag);
+    data.extend_from_slice(&payload);
+    data
+}
+
+fn decode_flagged(data: &[u8]) -> crate::Result<Cow<'_, [u8]>> {
+    let (&flag, payload) = data
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Missing page compression flag!"))?;
+
+    match flag {
+        RAW => Ok(Cow::Borrowed(payload)),
+        LZ4 => Ok(Cow::Owned(lz4_flex::decompress_size_prepended(payload)?)),
+        ZSTD => {
+            let (size, frame) = payload
+                .split_first_chunk::<4>()
+                .ok_or_else(|| anyhow::anyhow!("Truncated zstd page!"))?;
+            let size = u32::from_le_bytes(*size) as usize;
+
+            Ok(Cow::Owned(zstd::bulk::decompress(frame, size)?))
+        }
+        _ => Err(anyhow::anyhow!("Unknown page compression flag {}!", flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<Z: PageCompression>(page: &[u8]) -> Vec<u8> {
+        let data = Z::encode(page.to_vec()).unwrap();
+        assert_eq!(Z::decode(&data).unwrap().as_ref(), page);
+        data
+    }
+
+    #[test]
+    fn compression_round_trip() {
+        let compressible = vec![7u8; 4096];
+        let incompressible: Vec<u8> = (0..64u8).collect();
+
+        assert_eq!(round_trip::<NoCompression>(&compressible).len(), 4096);
+        assert!(round_trip::<Lz4>(&compressible).len() < 4096);
+        assert!(round_trip::<Zstd<3>>(&compressible).len() < 4096);
+
+        // Pages which don't shrink are stored raw behind the flag
+        assert_eq!(round_trip::<Lz4>(&incompressible)[0], RAW);
+        assert_eq!(round_trip::<Zstd<3>>(&incompressible)[0], RAW);
+    }
+
+    #[test]
+    fn compression_mixed_codecs() {
+        let page = vec![1u8; 1024];
+
+        let data = Lz4::encode(page.clone()).unwrap();
+        assert_eq!(Zstd::<3>::decode(&data).unwrap().as_ref(), page.as_slice());
+
+        let data = Zstd::<3>::encode(page.clone()).unwrap();
+        assert_eq!(Lz4::decode(&data).unwrap().as_ref(), page.as_slice());
+    }
+}
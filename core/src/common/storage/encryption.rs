@@ -0,0 +1,126 @@
+//! Encryption at rest. Every page written to an encrypted `GlobalStore`, including the catalogs, is
+//! sealed with ChaCha20-Poly1305 under a fresh random nonce, which is stored in front of the
+//! ciphertext as the page header. The id of the page is authenticated along with it, so pages can't
+//! be swapped around on disk without failing to decrypt.
+
+use super::StoreID;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+const NONCE_BYTES: usize = 12;
+
+/// A 256-bit key to encrypt a store with. It is never written to disk, so losing it loses the
+/// store.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Generate a random key
+    pub fn generate() -> Self {
+        Self(ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+pub(crate) struct PageCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl PageCipher {
+    pub fn new(key: &EncryptionKey) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(&key.0.into()),
+        }
+    }
+
+    /// Encrypt the page stored at `id`, prefixing it with its nonce
+    pub fn seal(&self, id: StoreID, page: &[u8]) -> crate::Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = id.to_le_bytes();
+
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: page,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt page {}!", id))?;
+
+        let mut data = Vec::with_capacity(NONCE_BYTES + ciphertext.len());
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Decrypt a page written by `seal`, failing if it was written with another key, or under
+    /// another id
+    pub fn open(&self, id: StoreID, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let (nonce, ciphertext) = data
+            .split_first_chunk::<NONCE_BYTES>()
+            .ok_or_else(|| anyhow::anyhow!("Truncated encrypted page {}!", id))?;
+        let aad = id.to_le_bytes();
+
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Failed to decrypt page {}, the store is either corrupted or was written with another key!",
+                    id
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encryption_round_trip() {
+        let cipher = PageCipher::new(&EncryptionKey::generate());
+        let page = b"some page".to_vec();
+
+        let data = cipher.seal(7, &page).unwrap();
+        assert_eq!(data.len(), NONCE_BYTES + page.len() + 16);
+        assert_eq!(cipher.open(7, &data).unwrap(), page);
+
+        // Nonces are never reused, so sealing a page twice gives different data
+        assert_ne!(cipher.seal(7, &page).unwrap(), data);
+    }
+
+    #[test]
+    fn encryption_rejects_tampering() {
+        let cipher = PageCipher::new(&EncryptionKey::from_bytes([1; 32]));
+        let other = PageCipher::new(&EncryptionKey::from_bytes([2; 32]));
+        let mut data = cipher.seal(7, b"some page").unwrap();
+
+        assert!(other.open(7, &data).is_err());
+        assert!(cipher.open(8, &data).is_err());
+        assert!(cipher.open(7, &data[..4]).is_err());
+
+        *data.last_mut().unwrap() ^= 1;
+        assert!(cipher.open(7, &data).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! External sorting, for building persisted indexes over more entries than fit in memory. A
+//! `DiskBuilder` buffers entries in memory, sorts each full buffer and spills it as a run of pages
+//! to the `GlobalStore`, then k-way merges the runs back into a single ascending stream, which only
+//! ever holds one page of every run in memory. Pages of a run are freed as soon as they are merged.
+
+use super::{store::ObjectStoreGeneric, GlobalStore, LocalStore, StoreID};
+use crate::{Key, Persisted};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Entries sorted in memory before a run is spilled, unless set with `DiskBuilder::load`
+pub const DEFAULT_RUN_ENTRIES: usize = 1 << 20;
+
+/// Entries stored in every page of a run
+const RUN_PAGE_ENTRIES: usize = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct RunCatalog {
+    /// Pages of every spilled run, in the order the runs were spilled
+    runs: Vec<Vec<StoreID>>,
+}
+
+/// Sorts a stream of entries in any order, by spilling sorted runs to a `GlobalStore`
+pub struct DiskBuilder<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    store: LocalStore<RunCatalog, Vec<(K, V)>>,
+    buffer: Vec<(K, V)>,
+    run_entries: usize,
+}
+
+impl<K, V> DiskBuilder<K, V>
+where
+    K: Persisted + Key,
+    V: Persisted,
+{
+    /// Keep the runs in the local store `ident` of `store`, sorting `run_entries` entries in
+    /// memory at a time. Runs left behind by a build which didn't finish are dropped.
+    pub fn load(
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        run_entries: usize,
+    ) -> crate::Result<Self> {
+        assert!(run_entries > 0, "A run must hold at least one entry!");
+
+        let mut store: LocalStore<RunCatalog, Vec<(K, V)>> = store.load_local_store(ident)?;
+
+        for page in std::mem::take(&mut store.catalog.runs)
+            .into_iter()
+            .flatten()
+        {
+            store.free_page(page)?;
+        }
+
+        Ok(Self {
+            store,
+            buffer: Vec::with_capacity(run_entries),
+            run_entries,
+        })
+    }
+
+    /// Add an entry, spilling a run if the buffer is full. Of several entries with the same key,
+    /// the one pushed last is kept.
+    pub fn push(&mut self, key: K, value: V) -> crate::Result<()> {
+        self.buffer.push((key, value));
+
+        if self.buffer.len() >= self.run_entries {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = (K, V)>) -> crate::Result<()> {
+        for (key, value) in entries {
+            self.push(key, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of runs spilled so far
+    pub fn runs(&self) -> usize {
+        self.store.catalog.runs.len()
+    }
+
+    /// Spill the last run, and merge every run into a stream of entries in ascending key order
+    pub fn finish(mut self) -> crate::Result<MergedRuns<K, V>> {
+        self.spill()?;
+
+        let mut runs = Vec::new();
+        for pages in self.store.catalog.runs.clone() {
+            runs.push(Run {
+                pages: pages.into(),
+                entries: Vec::new().into_iter(),
+            });
+        }
+
+        let mut merged = MergedRuns {
+            store: self.store,
+            heads: Vec::new(),
+            heap: BinaryHeap::new(),
+            runs,
+        };
+
+        for run in 0..merged.runs.len() {
+            let head = merged.advance(run)?;
+            merged.heads.push(head);
+        }
+
+        Ok(merged)
+    }
+
+    /// Sort the buffer and write it out as a run, keeping the last entry of every key
+    fn spill(&mut self) -> crate::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        // The sort is stable, so entries with the same key stay in the order they were pushed
+        self.buffer.sort_by_key(|(key, _)| *key);
+
+        let mut run: Vec<(K, V)> = Vec::with_capacity(self.buffer.len());
+        for (key, value) in self.buffer.drain(..) {
+            match run.last_mut() {
+                Some(last) if last.0 == key => last.1 = value,
+                _ => run.push((key, value)),
+            }
+        }
+
+        let mut pages = Vec::new();
+        for chunk in run.chunks(RUN_PAGE_ENTRIES) {
+            let page = self.store.allocate_page();
+            self.store.write_page(&chunk.to_vec(), page)?;
+            pages.push(page);
+        }
+
+        self.store.catalog.runs.push(pages);
+
+        // Write the run out, rather than keeping it in the cache
+        self.store.flush()
+    }
+}
+
+/// The pages of a run which are left to merge, along with the entries of the current page
+struct Run<K, V> {
+    pages: VecDeque<StoreID>,
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+/// Entries of every run of a `DiskBuilder`, in ascending key order and without duplicate keys
+pub struct MergedRuns<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    store: LocalStore<RunCatalog, Vec<(K, V)>>,
+    runs: Vec<Run<K, V>>,
+
+    /// The smallest entry left in every run
+    heads: Vec<Option<(K, V)>>,
+
+    /// Keys of the heads, along with their run
+    heap: BinaryHeap<Reverse<(K, usize)>>,
+}
+
+impl<K, V> MergedRuns<K, V>
+where
+    K: Persisted + Key,
+    V: Persisted,
+{
+    /// Take the next entry of a run, reading its next page if needed, and queue its key
+    fn advance(&mut self, run: usize) -> crate::Result<Option<(K, V)>> {
+        let entry = loop {
+            if let Some(entry) = self.runs[run].entries.next() {
+                break entry;
+            }
+
+            let Some(page) = self.runs[run].pages.pop_front() else {
+                return Ok(None);
+            };
+
+            let entries = self
+                .store
+                .read_page(page)?
+                .ok_or_else(|| anyhow::anyhow!("Missing page {} of a sorted run!", page))?;
+
+            self.store.free_page(page)?;
+            self.runs[run].entries = entries.into_iter();
+        };
+
+        self.heap.push(Reverse((entry.0, run)));
+        Ok(Some(entry))
+    }
+
+    /// Replace the head of a run with its next entry, returning the old head
+    fn pop_head(&mut self, run: usize) -> crate::Result<(K, V)> {
+        let next = self.advance(run)?;
+        let head = std::mem::replace(&mut self.heads[run], next);
+
+        Ok(head.expect("Queued run has no head!"))
+    }
+
+    fn next_entry(&mut self) -> crate::Result<Option<(K, V)>> {
+        let Some(Reverse((key, run))) = self.heap.pop() else {
+            return Ok(None);
+        };
+
+        let mut entry = self.pop_head(run)?;
+
+        // Runs with the same key pop in the order they were spilled, so the last one wins
+        while self
+            .heap
+            .peek()
+            .is_some_and(|Reverse((next, _))| *next == key)
+        {
+            let Reverse((_, run)) = self.heap.pop().unwrap();
+            entry = self.pop_head(run)?;
+        }
+
+        Ok(Some(entry))
+    }
+}
+
+impl<K, V> Iterator for MergedRuns<K, V>
+where
+    K: Persisted + Key,
+    V: Persisted,
+{
+    type Item = crate::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+impl<K, V> Drop for MergedRuns<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    /// Every page left is freed as the runs are merged, so there are no runs left to track
+    fn drop(&mut self) {
+        if self.heap.is_empty() {
+            self.store.catalog.runs.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::storage::MemoryBackend;
+    use crate::testkit::{TestRng, DEFAULT_SEED};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn disk_builder_merge() {
+        let mut store = GlobalStore::with_backend(MemoryBackend::new()).unwrap();
+        let mut builder: DiskBuilder<u64, u64> =
+            DiskBuilder::load(&mut store, "Runs", 500).unwrap();
+
+        let mut rng = TestRng::new(DEFAULT_SEED);
+        let mut expected = BTreeMap::new();
+
+        for value in 0..5_000 {
+            let key = rng.below(2_000) as u64;
+            builder.push(key, value).unwrap();
+            expected.insert(key, value);
+        }
+
+        assert_eq!(builder.runs(), 10);
+
+        let merged: Vec<(u64, u64)> = builder
+            .finish()
+            .unwrap()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+
+        assert_eq!(merged, expected.into_iter().collect::<Vec<_>>());
+    }
+}
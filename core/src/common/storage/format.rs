@@ -0,0 +1,226 @@
+//! The portable on-disk format of pages and catalogs. Every page starts with a header holding a
+//! magic number and the version of the format, followed by the page serialized by bincode with
+//! explicit options: little-endian, fixed-width integers, and `usize` widened to 64 bits. Pages are
+//! then laid out the same way on every host, so a store written on one machine opens on any other.
+//!
+//! Pages without a header were written before the format was versioned, by `bincode::serialize`.
+//! Those are still read, as are pages of older versions, and are migrated to the current format the
+//! next time they are written. Pages whose layout changed with a version are read through
+//! `decode_migrating`.
+
+use bincode::Options;
+use serde::de::{DeserializeOwned, DeserializeSeed};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+
+/// Marks a page written in a versioned format. Pages predating the header begin with the length
+/// of a sequence or the fields of a catalog, which never start with these bytes in practice.
+const MAGIC: [u8; 4] = *b"LIMO";
+
+/// Version of the format written by `encode`. Version 2 added the fences to the catalogs of
+/// boundary lists, and version 3 to those of deep lists.
+pub const FORMAT_VERSION: u8 = 3;
+
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// The bincode options of every version so far, spelled out rather than relying on the defaults of
+/// `bincode::serialize`. These also read pages predating the header, which used the same layout.
+fn options() -> impl Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+/// Serialize a page in the current format, behind its header
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> crate::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(HEADER_LEN + options().serialized_size(value)? as usize);
+    data.extend_from_slice(&MAGIC);
+    data.push(FORMAT_VERSION);
+    options().serialize_into(&mut data, value)?;
+
+    Ok(data)
+}
+
+/// Deserialize a page written by `encode`, or a page predating the header
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> crate::Result<T> {
+    Ok(options().deserialize(payload(data)?)?)
+}
+
+/// Deserialize a page whose layout changed in version `since`. Pages written before it are
+/// deserialized in their old layout `L`, and converted with `migrate`.
+pub fn decode_migrating<T, L>(
+    data: &[u8],
+    since: u8,
+    migrate: impl FnOnce(L) -> T,
+) -> crate::Result<T>
+where
+    T: DeserializeOwned,
+    L: DeserializeOwned,
+{
+    match version(data) {
+        Some(version) if version >= since => decode(data),
+        _ => Ok(migrate(decode(data)?)),
+    }
+}
+
+/// Deserialize part of a page through `seed`, as for `decode`
+pub fn decode_seed<'de, S: DeserializeSeed<'de>>(
+    seed: S,
+    data: &'de [u8],
+) -> crate::Result<S::Value> {
+    Ok(options().deserialize_seed(seed, payload(data)?)?)
+}
+
+/// Serialize a record which is packed into a page of bytes, with the options of `encode` but
+/// without a header, since the page has one already
+pub fn encode_record<T: Serialize + ?Sized>(value: &T) -> crate::Result<Vec<u8>> {
+    Ok(options().serialize(value)?)
+}
+
+/// Deserialize a record written by `encode_record` from the front of `data`, which is advanced
+/// past it
+pub fn decode_record<T: DeserializeOwned>(data: &mut &[u8]) -> crate::Result<T> {
+    Ok(options().deserialize_from(data)?)
+}
+
+/// Version of the format `data` was written in, or `None` for a page predating the header
+pub fn version(data: &[u8]) -> Option<u8> {
+    match data.get(..HEADER_LEN)? {
+        [magic @ .., version] if magic == MAGIC => Some(*version),
+        _ => None,
+    }
+}
+
+/// Serialize a map in the order of its keys rather than the random order of its hasher, so that
+/// equal catalogs are always written as the same bytes. Meant for `#[serde(serialize_with)]`, and
+/// read back like any other map.
+pub fn sorted<K, T, S>(map: &HashMap<K, T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Serialize,
+    T: Serialize,
+    S: Serializer,
+{
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_unstable_by_key(|(key, _)| *key);
+
+    serializer.collect_map(entries)
+}
+
+/// 64-bit FNV-1a, which is enough to catch corruption in transit
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The serialized page behind the header, if there is one
+fn payload(data: &[u8]) -> crate::Result<&[u8]> {
+    match version(data) {
+        Some(version) if version <= FORMAT_VERSION => Ok(&data[HEADER_LEN..]),
+        Some(version) => Err(anyhow::anyhow!(
+            "Page was written in format version {}, but only versions up to {} can be read!",
+            version,
+            FORMAT_VERSION
+        )),
+        None => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_round_trip() {
+        let page: Vec<(u64, usize)> = (0..100).map(|key| (key, key as usize * 3)).collect();
+
+        let data = encode(&page).unwrap();
+        assert_eq!(version(&data), Some(FORMAT_VERSION));
+        assert_eq!(decode::<Vec<(u64, usize)>>(&data).unwrap(), page);
+
+        // Explicitly little-endian and fixed-width, regardless of the host
+        assert_eq!(&data[HEADER_LEN..HEADER_LEN + 8], &100u64.to_le_bytes());
+        assert_eq!(data.len(), HEADER_LEN + 8 + 100 * 16);
+    }
+
+    #[test]
+    fn format_reads_legacy_pages() {
+        let page = vec![String::from("before"), String::from("versioning")];
+
+        let legacy = bincode::serialize(&page).unwrap();
+        assert_eq!(version(&legacy), None);
+        assert_eq!(decode::<Vec<String>>(&legacy).unwrap(), page);
+    }
+
+    #[test]
+    fn format_sorts_maps() {
+        #[derive(Serialize)]
+        struct Catalog {
+            #[serde(serialize_with = "sorted")]
+            registry: HashMap<u64, u64>,
+        }
+
+        let forward = (0..1_000).map(|id| (id, id * 2)).collect();
+        let backward = (0..1_000).rev().map(|id| (id, id * 2)).collect();
+
+        let data = encode(&Catalog { registry: forward }).unwrap();
+        assert_eq!(data, encode(&Catalog { registry: backward }).unwrap());
+        assert_eq!(
+            decode::<HashMap<u64, u64>>(&data).unwrap(),
+            (0..1_000).map(|id| (id, id * 2)).collect()
+        );
+    }
+
+    #[test]
+    fn format_migrates_older_layouts() {
+        use serde::Deserialize;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Catalog {
+            first: u64,
+            added: Vec<u64>,
+        }
+
+        let mut old = encode(&3u64).unwrap();
+        old[MAGIC.len()] = FORMAT_VERSION - 1;
+
+        let migrate = |first: u64| Catalog {
+            first,
+            added: vec![first],
+        };
+
+        assert_eq!(
+            decode_migrating(&old, FORMAT_VERSION, migrate).unwrap(),
+            Catalog {
+                first: 3,
+                added: vec![3]
+            }
+        );
+        assert_eq!(
+            decode_migrating(&bincode::serialize(&3u64).unwrap(), FORMAT_VERSION, migrate).unwrap(),
+            Catalog {
+                first: 3,
+                added: vec![3]
+            }
+        );
+
+        let current = Catalog {
+            first: 5,
+            added: vec![],
+        };
+        let data = encode(&current).unwrap();
+        assert_eq!(
+            decode_migrating(&data, FORMAT_VERSION, migrate).unwrap(),
+            current
+        );
+    }
+
+    #[test]
+    fn format_rejects_newer_versions() {
+        let mut data = encode(&7u32).unwrap();
+        data[MAGIC.len()] = FORMAT_VERSION + 1;
+
+        assert!(decode::<u32>(&data).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Latency histograms of the operations on a persisted index, recorded with the `metrics`
+//! feature. Unlike `IndexStats`, they only cover the time since the index was opened, and are
+//! never written to disk.
+
+use std::{cell::RefCell, time::Duration, time::Instant};
+
+/// Buckets per power of two, so that every bucket is within 1/32 of the latencies it holds
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+/// Powers of two covered above the linear buckets, up to about a minute in nanoseconds. Longer
+/// latencies are counted in the last bucket.
+const MAGNITUDES: usize = 31;
+const BUCKETS: usize = (MAGNITUDES + 1) * SUB_BUCKETS;
+
+/// A histogram of latencies in the style of HDR histograms: buckets are linear below
+/// `SUB_BUCKETS` nanoseconds, and above that every power of two is split into `SUB_BUCKETS`
+/// linear buckets, so quantiles are exact to within about 3% at any scale
+#[derive(Clone, Debug)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+        self.max = self.max.max(latency);
+    }
+
+    /// Number of latencies recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Longest latency recorded, exactly
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// The latency which `quantile` of the recorded latencies are at most, rounded up to the end
+    /// of its bucket, or zero if nothing was recorded
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                return Duration::from_nanos(upper_bound(bucket)).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            count: self.count,
+            p50: self.quantile(0.5),
+            p99: self.quantile(0.99),
+            p999: self.quantile(0.999),
+            max: self.max,
+        }
+    }
+}
+
+/// Bucket holding a latency of `nanos`
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+
+    // The leading `SUB_BUCKET_BITS + 1` bits pick the bucket within its power of two
+    let magnitude = 63 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+    let sub = (nanos >> magnitude) as usize;
+
+    ((magnitude as usize + 1) * SUB_BUCKETS + sub - SUB_BUCKETS).min(BUCKETS - 1)
+}
+
+/// Largest latency in nanoseconds held by `bucket`
+fn upper_bound(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+
+    let magnitude = bucket / SUB_BUCKETS - 1;
+    let sub = (bucket % SUB_BUCKETS + SUB_BUCKETS) as u64;
+
+    ((sub + 1) << magnitude) - 1
+}
+
+/// Quantiles of the latencies of one kind of operation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    /// Number of operations recorded
+    pub count: u64,
+
+    pub p50: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+    pub max: Duration,
+}
+
+/// Latencies of the operations on an index since it was opened, see `latency_summary`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Point lookups, including `search_project`
+    pub search: Percentiles,
+
+    /// Inserts, including overwrites and deletes
+    pub insert: Percentiles,
+
+    /// Pages of a range scan, each a call to `resume`
+    pub range: Percentiles,
+}
+
+/// The kind of operation timed by a `LatencyTimer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LatencyOp {
+    Search,
+    Insert,
+    Range,
+}
+
+/// A histogram of every `LatencyOp`, recorded into through a shared reference since searches only
+/// borrow the index
+#[derive(Default)]
+pub(crate) struct Latencies {
+    search: RefCell<LatencyHistogram>,
+    insert: RefCell<LatencyHistogram>,
+    range: RefCell<LatencyHistogram>,
+}
+
+impl Latencies {
+    fn histogram(&self, op: LatencyOp) -> &RefCell<LatencyHistogram> {
+        match op {
+            LatencyOp::Search => &self.search,
+            LatencyOp::Insert => &self.insert,
+            LatencyOp::Range => &self.range,
+        }
+    }
+
+    pub fn time(&self, op: LatencyOp) -> LatencyTimer<'_> {
+        LatencyTimer {
+            histogram: self.histogram(op),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            search: self.search.borrow().percentiles(),
+            insert: self.insert.borrow().percentiles(),
+            range: self.range.borrow().percentiles(),
+        }
+    }
+}
+
+/// Records the time from its creation until it is dropped, so that every return out of an
+/// operation is timed
+pub struct LatencyTimer<'a> {
+    histogram: &'a RefCell<LatencyHistogram>,
+    start: Instant,
+}
+
+impl Drop for LatencyTimer<'_> {
+    fn drop(&mut self) {
+        self.histogram.borrow_mut().record(self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_their_latencies() {
+        for nanos in (0..100_000).chain((1..40).map(|shift| (1u64 << shift) + 12_345)) {
+            let bucket = bucket(nanos);
+            if bucket == BUCKETS - 1 {
+                continue;
+            }
+
+            assert!(nanos <= upper_bound(bucket), "{}", nanos);
+            assert!(bucket == 0 || nanos > upper_bound(bucket - 1), "{}", nanos);
+        }
+    }
+
+    #[test]
+    fn histogram_quantiles() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentiles(), Percentiles::default());
+
+        for micros in 1..=1_000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        histogram.record(Duration::from_secs(3_600));
+
+        let within = |latency: Duration, micros: u64| {
+            let expected = Duration::from_micros(micros);
+            latency >= expected && latency <= expected + expected / SUB_BUCKETS as u32
+        };
+
+        let percentiles = histogram.percentiles();
+        assert_eq!(percentiles.count, 1_001);
+        assert!(within(percentiles.p50, 501), "{:?}", percentiles.p50);
+        assert!(within(percentiles.p99, 991), "{:?}", percentiles.p99);
+        assert!(within(percentiles.p999, 1_000), "{:?}", percentiles.p999);
+        assert_eq!(percentiles.max, Duration::from_secs(3_600));
+    }
+}
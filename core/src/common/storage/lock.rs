@@ -0,0 +1,73 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions, TryLockError},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Name of the lock file in the directory of a store opened by a file based backend
+const LOCK_FILE: &str = "LOCK";
+
+/// Returned when opening a `MarbleBackend` or `FileBackend` over a directory which another backend
+/// already has open, whether in another process or in this one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StoreLocked {
+    /// The process holding the store, unless it hadn't recorded itself in the lock file yet
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for StoreLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "store is already loaded by process {}", pid),
+            None => write!(f, "store is already loaded by another process"),
+        }
+    }
+}
+
+impl std::error::Error for StoreLocked {}
+
+impl StoreLocked {
+    /// The lock held on the store in `dir`, naming the process which recorded itself as holding it
+    pub(crate) fn read(dir: impl AsRef<Path>) -> Self {
+        let pid = std::fs::read_to_string(dir.as_ref().join(LOCK_FILE)).ok();
+
+        Self {
+            pid: pid.and_then(|pid| pid.trim().parse().ok()),
+        }
+    }
+}
+
+/// An advisory lock on the lock file of a store, which records the id of the process holding it.
+/// The lock is released by the operating system once the file is closed, so a process which
+/// crashed never leaves its store locked.
+pub(crate) struct StoreLock {
+    _file: File,
+}
+
+impl StoreLock {
+    /// Take the lock of the store in `dir`, creating the directory if needed
+    pub fn acquire(dir: impl AsRef<Path>) -> crate::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.as_ref().join(LOCK_FILE))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(StoreLocked::read(dir).into()),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_data()?;
+
+        Ok(Self { _file: file })
+    }
+}
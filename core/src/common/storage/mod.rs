@@ -1,7 +1,42 @@
+mod backend;
+mod backup;
+mod cache;
+mod compression;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod external_sort;
+pub(crate) mod format;
+#[cfg(feature = "metrics")]
+mod latency;
+mod lock;
+mod remap;
+mod stats;
 mod store;
+mod usage;
+mod vlog;
+mod wal;
 
+pub use backend::{
+    FileBackend, MarbleBackend, MemoryBackend, RecoveryReport, StorageBackend, StorageStats,
+};
+pub use backup::PageDelta;
+pub use cache::{CachePolicy, CacheStats};
+pub use compression::{Lz4, NoCompression, PageCompression, Zstd};
+#[cfg(feature = "encryption")]
+pub use encryption::EncryptionKey;
+pub use external_sort::{DiskBuilder, MergedRuns, DEFAULT_RUN_ENTRIES};
+#[cfg(feature = "metrics")]
+pub use latency::{LatencyHistogram, LatencyOp, LatencySummary, LatencyTimer, Percentiles};
+pub use lock::StoreLocked;
+pub(crate) use remap::remap_keys;
+pub use remap::{RemapStoreIDs, StoreIDRemap};
+pub use stats::{IndexStats, StatsStore};
+pub use store::CachePriority;
 pub use store::GlobalStore;
 pub use store::LocalStore;
 pub use store::ObjectStoreGeneric;
+pub use usage::{DiskStats, DiskUsage, WarmStats};
+pub use vlog::{VLogValue, ValueLog, ValuePointer};
+pub use wal::WriteAheadLog;
 
 pub type StoreID = u64;
@@ -0,0 +1,107 @@
+//! Copying an index into another `GlobalStore`. Every store allocates page ids on its own, so the
+//! pages of an index collide with pages already in the store it's copied into. Every page of the
+//! index is therefore given a fresh id in the target store before any page is written, and the
+//! references held by pages and catalogs, such as child pointers and the links between the nodes
+//! of a layer, are translated through a `StoreIDRemap` as they are copied.
+
+use super::{GlobalStore, ObjectStoreGeneric, StoreID};
+use std::collections::HashMap;
+
+/// Ids of pages in the store an index is copied from, along with the ids they are given in the
+/// store it's copied into
+#[derive(Clone, Debug, Default)]
+pub struct StoreIDRemap {
+    ids: HashMap<StoreID, StoreID>,
+}
+
+impl StoreIDRemap {
+    /// Allocate a page in `store` for every page of `ids` which doesn't have one yet
+    pub fn reserve(&mut self, store: &mut GlobalStore, ids: impl IntoIterator<Item = StoreID>) {
+        for id in ids {
+            self.ids.entry(id).or_insert_with(|| store.allocate_page());
+        }
+    }
+
+    /// The id `id` is given in the target store, which fails for pages which aren't copied
+    pub fn get(&self, id: StoreID) -> crate::Result<StoreID> {
+        match self.ids.get(&id) {
+            Some(&target) => Ok(target),
+            None => anyhow::bail!("Page {} is referenced, but isn't copied!", id),
+        }
+    }
+
+    /// Number of pages given an id in the target store
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Pages and catalogs which reference other pages by id. Keys and values are never translated,
+/// only the addresses of pages.
+pub trait RemapStoreIDs {
+    /// Translate every page id held through `remap`
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()>;
+}
+
+impl RemapStoreIDs for StoreID {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        *self = remap.get(*self)?;
+        Ok(())
+    }
+}
+
+impl<T: RemapStoreIDs> RemapStoreIDs for Option<T> {
+    fn remap_store_ids(&mut self, remap: &StoreIDRemap) -> crate::Result<()> {
+        match self {
+            Some(inner) => inner.remap_store_ids(remap),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Translate the keys of a map keyed by page id, along with its values
+pub(crate) fn remap_keys<T>(
+    map: &HashMap<StoreID, T>,
+    remap: &StoreIDRemap,
+    remap_value: impl Fn(&mut T) -> crate::Result<()>,
+) -> crate::Result<HashMap<StoreID, T>>
+where
+    T: Clone,
+{
+    map.iter()
+        .map(|(&id, value)| {
+            let mut value = value.clone();
+            remap_value(&mut value)?;
+            Ok((remap.get(id)?, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::storage::MemoryBackend;
+
+    #[test]
+    fn remap_reserves_fresh_pages() {
+        let mut store = GlobalStore::with_backend(MemoryBackend::new()).unwrap();
+        let taken = store.allocate_page();
+
+        let mut remap = StoreIDRemap::default();
+        remap.reserve(&mut store, [taken, 7, taken]);
+        assert_eq!(remap.len(), 2);
+
+        // Reserved pages don't collide with pages already in the store
+        let mut id = taken;
+        id.remap_store_ids(&remap).unwrap();
+        assert_ne!(id, taken);
+        assert_ne!(remap.get(7).unwrap(), taken);
+
+        let mut link = Some(9);
+        assert!(link.remap_store_ids(&remap).is_err());
+    }
+}
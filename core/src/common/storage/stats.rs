@@ -0,0 +1,208 @@
+//! Counters which accumulate over the whole lifetime of a persisted index, across restarts. They
+//! are kept in the catalog of a dedicated `LocalStore`, which is written out when the index is
+//! dropped, so the counts since the last clean shutdown are lost after a crash.
+
+#[cfg(feature = "metrics")]
+use super::latency::{Latencies, LatencyOp, LatencySummary, LatencyTimer};
+use super::{GlobalStore, LocalStore};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cumulative statistics of a persisted index
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStats {
+    /// Keys inserted, including overwrites
+    pub inserts: u64,
+
+    /// Calls to `compact`, and rewrites of the value log by `maintenance`
+    pub compactions: u64,
+
+    /// Bytes written to the backend, including catalogs
+    pub bytes_written: u64,
+
+    /// Times the index was opened
+    pub opens: u64,
+
+    /// When the index was created, or the stats last reset, in seconds since the Unix epoch, or
+    /// zero in a `deterministic` store
+    pub created_at: u64,
+}
+
+impl IndexStats {
+    /// Time since the index was created, or the stats last reset
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.created_at))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Keeps the `IndexStats` of an index in the catalog of its own `LocalStore`
+pub struct StatsStore {
+    store: LocalStore<IndexStats, ()>,
+
+    /// `GlobalStore::bytes_written` when the counted bytes were last folded into the catalog
+    written: u64,
+
+    /// Whether the store is `deterministic`, so that no creation time is recorded
+    deterministic: bool,
+
+    #[cfg(feature = "metrics")]
+    latencies: Latencies,
+}
+
+impl StatsStore {
+    pub fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self> {
+        let deterministic = store.is_deterministic();
+        let mut store: LocalStore<IndexStats, ()> = store.load_local_store(ident)?;
+
+        if store.catalog.created_at == 0 && !deterministic {
+            store.catalog.created_at = now();
+        }
+
+        store.catalog.opens += 1;
+
+        Ok(Self {
+            written: store.bytes_written(),
+            deterministic,
+            store,
+            #[cfg(feature = "metrics")]
+            latencies: Latencies::default(),
+        })
+    }
+
+    pub fn record_insert(&mut self) {
+        self.store.catalog.inserts += 1;
+    }
+
+    pub fn record_compaction(&mut self) {
+        self.store.catalog.compactions += 1;
+    }
+
+    /// The current counts, including bytes written since they were last folded into the catalog
+    pub fn get(&self) -> IndexStats {
+        IndexStats {
+            bytes_written: self.store.catalog.bytes_written + self.store.bytes_written()
+                - self.written,
+            ..self.store.catalog
+        }
+    }
+
+    /// Bytes of pages read from the backend by every store of the `GlobalStore` since it was
+    /// loaded, which isn't kept across restarts
+    pub fn bytes_read(&self) -> u64 {
+        self.store.bytes_read()
+    }
+
+    /// Time an operation until the returned timer is dropped
+    #[cfg(feature = "metrics")]
+    pub fn time(&self, op: LatencyOp) -> LatencyTimer<'_> {
+        self.latencies.time(op)
+    }
+
+    /// Quantiles of the latencies of every operation timed since the index was opened, or the
+    /// stats last reset
+    #[cfg(feature = "metrics")]
+    pub fn latency_summary(&self) -> LatencySummary {
+        self.latencies.summary()
+    }
+
+    /// Zero every counter and restart the lifetime of the index
+    pub fn reset(&mut self) {
+        self.store.catalog = IndexStats {
+            created_at: if self.deterministic { 0 } else { now() },
+            ..Default::default()
+        };
+
+        self.written = self.store.bytes_written();
+
+        #[cfg(feature = "metrics")]
+        {
+            self.latencies = Latencies::default();
+        }
+    }
+}
+
+impl Drop for StatsStore {
+    /// Fold the bytes written into the catalog, which the store writes out as it is dropped. Layers
+    /// declared before the stats in the index are dropped, and flushed, first.
+    fn drop(&mut self) {
+        self.store.catalog = self.get();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::storage::MemoryBackend;
+
+    #[test]
+    fn stats_store_reload() {
+        let backend = MemoryBackend::new();
+
+        {
+            let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+            let mut stats = StatsStore::load(&mut store, "Stats").unwrap();
+
+            stats.record_insert();
+            stats.record_insert();
+            stats.record_compaction();
+            store.flush().unwrap();
+
+            assert!(stats.get().bytes_written > 0);
+        }
+
+        let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+        let mut stats = StatsStore::load(&mut store, "Stats").unwrap();
+
+        let loaded = stats.get();
+        assert_eq!(loaded.inserts, 2);
+        assert_eq!(loaded.compactions, 1);
+        assert_eq!(loaded.opens, 2);
+        assert!(loaded.bytes_written > 0);
+        assert!(loaded.created_at > 0);
+
+        stats.reset();
+        assert_eq!(stats.get().inserts, 0);
+        assert_eq!(stats.get().bytes_written, 0);
+        assert!(stats.get().age() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn stats_store_deterministic() {
+        let store = GlobalStore::with_backend(MemoryBackend::new()).unwrap();
+        let mut store = store.deterministic().unwrap();
+        let mut stats = StatsStore::load(&mut store, "Stats").unwrap();
+
+        stats.record_insert();
+        assert_eq!(stats.get().created_at, 0);
+
+        stats.reset();
+        assert_eq!(stats.get().created_at, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn stats_store_latencies() {
+        let mut store = GlobalStore::with_backend(MemoryBackend::new()).unwrap();
+        let mut stats = StatsStore::load(&mut store, "Stats").unwrap();
+
+        for _ in 0..10 {
+            let _timer = stats.time(LatencyOp::Search);
+        }
+        drop(stats.time(LatencyOp::Range));
+
+        let summary = stats.latency_summary();
+        assert_eq!(summary.search.count, 10);
+        assert_eq!(summary.insert.count, 0);
+        assert_eq!(summary.range.count, 1);
+        assert!(summary.search.p50 <= summary.search.max);
+
+        stats.reset();
+        assert_eq!(stats.latency_summary().search.count, 0);
+    }
+}
@@ -1,10 +1,20 @@
-use super::StoreID;
+use super::backup::{self, ChangeLog, PageDelta};
+use super::cache::{CachePolicy, CacheStats, PageCache};
+#[cfg(feature = "encryption")]
+use super::encryption::{EncryptionKey, PageCipher};
+use super::{
+    format, MarbleBackend, NoCompression, PageCompression, RecoveryReport, StorageBackend,
+    StorageStats, StoreID, WarmStats,
+};
 use core::panic;
 use id_allocator::IDAllocator;
+use serde::de::DeserializeSeed;
 use serde::{Deserialize, Serialize};
 use std::{
-    cell::{Ref, RefCell, RefMut},
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    cell::{Cell, Ref, RefCell, RefMut},
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, Write},
     path::Path,
     rc::Rc,
 };
@@ -12,6 +22,7 @@ use std::{
 #[derive(Serialize, Deserialize, Clone)]
 struct GlobalStoreCatalog {
     ids: IDAllocator<StoreID>,
+    #[serde(serialize_with = "format::sorted")]
     registry: HashMap<String, StoreID>,
 }
 
@@ -38,65 +49,393 @@ pub struct GlobalStore {
 }
 
 struct GlobalStoreInner {
-    store: marble::Marble,
+    store: Box<dyn StorageBackend>,
     active_stores: HashSet<String>,
     catalog: GlobalStoreCatalog,
+
+    /// Generation every page last changed in, stored on the page of the catalog right after it
+    changes: ChangeLog,
+
+    /// Bytes handed to the backend since the store was loaded
+    bytes_written: u64,
+
+    /// Bytes of pages read from the backend by local stores since the store was loaded
+    bytes_read: Cell<u64>,
+
+    /// Policy and capacity in pages of the caches of local stores loaded from now on, the
+    /// capacity defaulting to `CACHE_SIZE` bytes worth of pages
+    cache_policy: CachePolicy,
+    cache_pages: Option<usize>,
+
+    /// Lookups into the caches of every local store, which they all count into
+    cache_stats: Rc<Cell<CacheStats>>,
+
+    #[cfg(feature = "encryption")]
+    cipher: Option<PageCipher>,
+
+    /// Whether the store refuses every write, see `with_backend_read_only`
+    read_only: bool,
+
+    /// Whether indexes keep anything but their contents out of their pages, see `deterministic`
+    deterministic: bool,
+
+    /// Pages held back from the backend while a batch is open, see `LocalStore::begin_batch`
+    batch: Option<PendingBatch>,
+}
+
+/// Pages written since a batch was opened, along with the catalog and generations as they were
+/// then, which are restored if the batch is aborted
+struct PendingBatch {
+    pages: BTreeMap<StoreID, Option<Vec<u8>>>,
+    catalog: GlobalStoreCatalog,
+    changes: ChangeLog,
+}
+
+impl GlobalStoreInner {
+    fn new(store: Box<dyn StorageBackend>) -> Self {
+        Self {
+            store,
+            catalog: Default::default(),
+            changes: Default::default(),
+            active_stores: HashSet::new(),
+            bytes_written: 0,
+            bytes_read: Cell::new(0),
+            cache_policy: CachePolicy::default(),
+            cache_pages: None,
+            cache_stats: Default::default(),
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            read_only: false,
+            deterministic: false,
+            batch: None,
+        }
+    }
+
+    /// Encode a page right before it is written to disk at `id`
+    #[allow(unused_variables)]
+    fn seal(&self, id: StoreID, data: Vec<u8>) -> crate::Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if let Some(ref cipher) = self.cipher {
+            return cipher.seal(id, &data);
+        }
+
+        Ok(data)
+    }
+
+    /// Write a batch to the backend, counting the bytes written. The catalog is written along with
+    /// every batch, so that the pages it changed are recorded atomically with the pages themselves.
+    /// While a batch is open, the pages are held back until it commits instead.
+    fn write_batch(&mut self, mut batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()> {
+        let id = GLOBAL_STORE_CATALOG_ID;
+        batch.retain(|(page, _)| *page != id);
+
+        if let Some(ref mut pending) = self.batch {
+            pending.pages.extend(batch);
+            return Ok(());
+        }
+
+        self.changes
+            .record(batch.iter().map(|(id, _)| *id).chain(std::iter::once(id)));
+        let catalog = format::encode(&(&self.catalog, &self.changes))?;
+        batch.push((id, Some(self.seal(id, catalog)?)));
+
+        self.write_untracked(batch)
+    }
+
+    /// Write a batch to the backend without recording the pages it changed
+    fn write_untracked(&mut self, batch: Vec<(StoreID, Option<Vec<u8>>)>) -> crate::Result<()> {
+        if self.read_only {
+            anyhow::bail!("Store was opened read-only!");
+        }
+
+        self.bytes_written += batch
+            .iter()
+            .map(|(_, data)| data.as_ref().map_or(0, |data| data.len() as u64))
+            .sum::<u64>();
+
+        self.store.write_batch(batch)
+    }
+
+    /// Read a page, as an open batch wrote it if it did
+    fn read(&self, id: StoreID) -> crate::Result<Option<Vec<u8>>> {
+        if let Some(page) = self
+            .batch
+            .as_ref()
+            .and_then(|pending| pending.pages.get(&id))
+        {
+            return Ok(page.clone());
+        }
+
+        self.store.read(id)
+    }
+
+    fn begin_batch(&mut self) -> crate::Result<()> {
+        if self.read_only {
+            anyhow::bail!("Store was opened read-only!");
+        }
+
+        if self.batch.is_some() {
+            anyhow::bail!("A batch is already open!");
+        }
+
+        self.batch = Some(PendingBatch {
+            pages: BTreeMap::new(),
+            catalog: self.catalog.clone(),
+            changes: self.changes.clone(),
+        });
+        Ok(())
+    }
+
+    /// Write every page of the open batch to the backend in one batch, or abort the batch if that
+    /// fails
+    fn commit_batch(&mut self) -> crate::Result<()> {
+        let Some(pending) = self.batch.take() else {
+            anyhow::bail!("No batch is open!");
+        };
+
+        let result = self.write_batch(pending.pages.into_iter().collect());
+        if result.is_err() {
+            self.catalog = pending.catalog;
+            self.changes = pending.changes;
+        }
+
+        result
+    }
+
+    /// Drop every page of the open batch, and forget the pages it allocated or freed
+    fn abort_batch(&mut self) {
+        if let Some(pending) = self.batch.take() {
+            self.catalog = pending.catalog;
+            self.changes = pending.changes;
+        }
+    }
+
+    fn count_read(&self, bytes: usize) {
+        self.bytes_read.set(self.bytes_read.get() + bytes as u64);
+    }
+
+    /// Decode a page written by `seal`
+    #[allow(unused_variables)]
+    fn unseal<'a>(&self, id: StoreID, data: &'a [u8]) -> crate::Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "encryption")]
+        if let Some(ref cipher) = self.cipher {
+            return Ok(Cow::Owned(cipher.open(id, data)?));
+        }
+
+        Ok(Cow::Borrowed(data))
+    }
 }
 
 impl GlobalStore {
+    /// Load the store at `path`, or create one, using the default `MarbleBackend`. The backend
+    /// holds a lock on the directory until the store is dropped, and loading it again in the
+    /// meantime, from this process or another, fails with `StoreLocked`.
     pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
-        let store = marble::open(path.as_ref())?;
+        Ok(Self::load_with_report(path)?.0)
+    }
+
+    /// Load a store kept by `backend`, or create one if the backend holds no pages
+    pub fn with_backend(backend: impl StorageBackend) -> crate::Result<Self> {
+        Ok(Self::with_backend_and_report(backend)?.0)
+    }
+
+    /// Load the store at `path` as with `load`, along with a report of what had to be recovered
+    /// because the store wasn't shut down cleanly
+    pub fn load_with_report(path: impl AsRef<Path>) -> crate::Result<(Self, RecoveryReport)> {
+        Self::with_backend_and_report(MarbleBackend::open(path)?)
+    }
+
+    /// Load a store kept by `backend` as with `with_backend`, along with a report of what had to
+    /// be recovered
+    pub fn with_backend_and_report(
+        backend: impl StorageBackend,
+    ) -> crate::Result<(Self, RecoveryReport)> {
+        Self::load_inner(GlobalStoreInner::new(Box::new(backend)))
+    }
+
+    /// Load the existing store at `path` with `MarbleBackend::open_read_only`, refusing every
+    /// write as with `with_backend_read_only`. Marble locks its directory while it is open, so
+    /// this fails with `StoreLocked` while another process has the store loaded.
+    pub fn load_read_only(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Self::with_backend_read_only(MarbleBackend::open_read_only(path)?)
+    }
+
+    /// Load a store kept by `backend` which refuses every write, for inspecting a store while
+    /// another process has it loaded. Nothing is recovered, pages and catalogs can't be written,
+    /// and the store is neither flushed nor compacted when dropped. A store which is loaded
+    /// elsewhere can only be inspected through a backend which allows readers alongside a
+    /// writer, such as a `FileBackend` opened with `open_read_only`.
+    pub fn with_backend_read_only(backend: impl StorageBackend) -> crate::Result<Self> {
+        let mut inner = GlobalStoreInner::new(Box::new(backend));
+        inner.read_only = true;
+
+        Ok(Self::load_inner(inner)?.0)
+    }
+
+    /// Whether the store was loaded with `with_backend_read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.inner_ref().read_only
+    }
+
+    /// Load a store whose pages are encrypted with `key`, or create one. Every page is encrypted,
+    /// including the catalogs, so loading a store with the wrong key, or loading an unencrypted
+    /// store with a key, fails. Encryption can't be turned on or off for an existing store.
+    #[cfg(feature = "encryption")]
+    pub fn load_with_key(path: impl AsRef<Path>, key: &EncryptionKey) -> crate::Result<Self> {
+        Self::with_backend_and_key(MarbleBackend::open(path)?, key)
+    }
+
+    /// Load a store kept by `backend` whose pages are encrypted with `key`, as for `load_with_key`
+    #[cfg(feature = "encryption")]
+    pub fn with_backend_and_key(
+        backend: impl StorageBackend,
+        key: &EncryptionKey,
+    ) -> crate::Result<Self> {
+        let mut inner = GlobalStoreInner::new(Box::new(backend));
+        inner.cipher = Some(PageCipher::new(key));
+
+        Ok(Self::load_inner(inner)?.0)
+    }
+
+    /// Load the catalog before wrapping the store, since a store which is dropped flushes its
+    /// catalog, and would otherwise overwrite one that failed to load. A read-only store recovers
+    /// nothing, since what looks torn may still be being written by the process which owns it.
+    fn load_inner(mut inner: GlobalStoreInner) -> crate::Result<(Self, RecoveryReport)> {
+        let id = GLOBAL_STORE_CATALOG_ID;
+        let mut report = match inner.read_only {
+            true => RecoveryReport::default(),
+            false => inner.store.recover()?,
+        };
 
         // Load catalog
-        let catalog = match store.read(GLOBAL_STORE_CATALOG_ID)? {
-            Some(data) => bincode::deserialize(&data)?,
+        match inner.store.read(id)? {
+            Some(data) => {
+                let data = inner.unseal(id, data.as_ref())?;
+                (inner.catalog, inner.changes) = decode_catalog(&data)?;
+            }
             None => {
-                let catalog = GlobalStoreCatalog::default();
-                let data = bincode::serialize(&catalog).unwrap();
+                // Pages without a catalog belong to a store whose catalog was lost
+                if inner.store.stats().pages > 0 {
+                    report.catalogs_rebuilt += 1;
+                }
 
-                store.write_batch([(GLOBAL_STORE_CATALOG_ID, Some(&data))])?;
-                catalog
+                if !inner.read_only {
+                    inner.write_batch(Vec::new())?;
+                }
             }
+        }
+
+        // A local catalog whose page is missing is created again when its store is next loaded
+        for &local in inner.catalog.registry.values() {
+            if inner.store.read(local)?.is_none() {
+                report.catalogs_rebuilt += 1;
+            }
+        }
+
+        let store = GlobalStore {
+            inner: Rc::new(RefCell::new(inner)),
         };
 
-        Ok(GlobalStore {
-            inner: Rc::new(RefCell::new(GlobalStoreInner {
-                store,
-                catalog,
-                active_stores: HashSet::new(),
-            })),
-        })
+        Ok((store, report))
+    }
+
+    /// Cache the pages of local stores loaded from now on with `policy`, which starts counting
+    /// `cache_stats` afresh
+    pub fn with_cache_policy(self, policy: CachePolicy) -> Self {
+        {
+            let mut inner = self.inner_ref_mut();
+            inner.cache_policy = policy;
+            inner.cache_stats.set(CacheStats {
+                policy,
+                ..Default::default()
+            });
+        }
+
+        self
+    }
+
+    /// Cache up to `pages` pages of every evictable local store loaded from now on, rather than
+    /// as many as fit in the default cache size
+    pub fn with_cache_pages(self, pages: usize) -> Self {
+        self.inner_ref_mut().cache_pages = Some(pages);
+        self
+    }
+
+    /// Write the same bytes for the same operations, wherever and whenever they run, so that the
+    /// store of an index built twice from the same input is identical page for page: indexes
+    /// loaded from now on record no creation time in their `IndexStats`. Catalogs are always
+    /// written in a fixed order. Encryption seals every page under a random nonce, so an encrypted
+    /// store can't be made deterministic.
+    pub fn deterministic(self) -> crate::Result<Self> {
+        #[cfg(feature = "encryption")]
+        if self.inner_ref().cipher.is_some() {
+            anyhow::bail!("An encrypted store cannot be deterministic!");
+        }
+
+        self.inner_ref_mut().deterministic = true;
+        Ok(self)
+    }
+
+    /// Whether the store was made `deterministic`
+    pub fn is_deterministic(&self) -> bool {
+        self.inner_ref().deterministic
+    }
+
+    /// Lookups into the caches of every local store of this store since it was loaded, or since
+    /// its cache policy was set
+    pub fn cache_stats(&self) -> CacheStats {
+        self.inner_ref().cache_stats.get()
     }
 
     fn write_page<P>(&self, page: &P, id: StoreID) -> crate::Result<()>
     where
         P: Serialize,
     {
-        self.inner_ref_mut()
-            .store
-            .write_batch([(id, Some(bincode::serialize(page)?))])?;
+        let mut inner = self.inner_ref_mut();
+        let data = inner.seal(id, format::encode(page)?)?;
+        inner.write_batch(vec![(id, Some(data))])?;
 
         Ok(())
     }
 
-    fn read_page<P>(&self, id: StoreID) -> crate::Result<Option<P>>
-    where
-        for<'de> P: Deserialize<'de>,
-    {
-        if let Some(data) = self.inner_ref().store.read(id)? {
-            return Ok(Some(bincode::deserialize(data.as_ref())?));
+    /// Read a page, deserialized with `decode`
+    fn read_page<P>(
+        &self,
+        id: StoreID,
+        decode: impl FnOnce(&[u8]) -> crate::Result<P>,
+    ) -> crate::Result<Option<P>> {
+        let inner = self.inner_ref();
+
+        if let Some(data) = inner.read(id)? {
+            return Ok(Some(decode(&inner.unseal(id, data.as_ref())?)?));
         }
 
         Ok(None)
     }
 
-    pub fn load_local_store<C, P>(
+    pub fn load_local_store<C, P, Z>(
+        &mut self,
+        ident: impl ToString,
+    ) -> crate::Result<LocalStore<C, P, Z>>
+    where
+        C: Serialize + for<'de> Deserialize<'de> + Clone + Default,
+        P: Serialize + for<'de> Deserialize<'de> + Clone,
+        Z: PageCompression,
+    {
+        self.load_local_store_with(ident, format::decode)
+    }
+
+    /// Load a local store whose catalog is deserialized with `decode`, for catalogs whose layout
+    /// changed between format versions, see `format::decode_migrating`
+    pub fn load_local_store_with<C, P, Z>(
         &mut self,
         ident: impl ToString,
-    ) -> crate::Result<LocalStore<C, P>>
+        decode: impl FnOnce(&[u8]) -> crate::Result<C>,
+    ) -> crate::Result<LocalStore<C, P, Z>>
     where
         C: Serialize + for<'de> Deserialize<'de> + Clone + Default,
         P: Serialize + for<'de> Deserialize<'de> + Clone,
+        Z: PageCompression,
     {
         if self.inner_ref().active_stores.contains(&ident.to_string()) {
             panic!("Catalog `{}` has already been loaded!", ident.to_string());
@@ -118,36 +457,144 @@ impl GlobalStore {
             id
         });
 
-        let catalog = match self.read_page::<C>(id)? {
+        let catalog = match self.read_page(id, decode)? {
             Some(catalog) => catalog,
             None => {
                 let catalog = C::default();
-                self.write_page(&catalog, id)?;
+                if !self.is_read_only() {
+                    self.write_page(&catalog, id)?;
+                }
                 catalog
             }
         };
 
         self.inner_ref_mut().active_stores.insert(ident.to_string());
 
+        let cache = {
+            let inner = self.inner_ref();
+            let pages = inner
+                .cache_pages
+                .unwrap_or(CACHE_SIZE / std::mem::size_of::<P>().max(1));
+
+            PageCache::new(inner.cache_policy, pages, inner.cache_stats.clone())
+        };
+
         Ok(LocalStore {
             root: self.inner.clone(),
             catalog,
             id,
             ident: ident.to_string(),
-            cache: Rc::new(RefCell::new(HashMap::new())),
+            cache: Rc::new(RefCell::new(cache)),
+            dirty: Rc::new(RefCell::new(HashSet::new())),
+            priority: CachePriority::default(),
+            _ph: std::marker::PhantomData,
         })
     }
 
+    /// Write out the catalog, which a read-only store has nothing to write of
     pub fn flush(&mut self) -> crate::Result<()> {
-        let catalog = self.inner_ref_mut().catalog.clone();
-        self.write_page(&catalog, GLOBAL_STORE_CATALOG_ID)?;
+        if self.is_read_only() {
+            return Ok(());
+        }
 
-        Ok(())
+        self.inner_ref_mut().write_batch(Vec::new())
     }
 
-    pub fn stats(&self) -> marble::Stats {
+    /// The generation of the store, which advances with every batch of pages written to it
+    pub fn generation(&self) -> u64 {
+        self.inner_ref().changes.generation
+    }
+
+    /// Write a `PageDelta` to `writer` for every page which changed after `generation`, and return
+    /// the generation the backup is current as of, to pass to the next incremental backup. A full
+    /// backup starts from generation 0. Indexes opened in the store should be flushed first, since
+    /// pages they haven't written out yet aren't part of the backup.
+    pub fn export_changed_since(
+        &mut self,
+        generation: u64,
+        mut writer: impl Write,
+    ) -> crate::Result<u64> {
+        self.flush()?;
+
+        let inner = self.inner_ref();
+        for id in inner.changes.changed_since(generation) {
+            backup::write_delta(&mut writer, &PageDelta::new(id, inner.store.read(id)?))?;
+        }
+
+        writer.flush()?;
+        Ok(inner.changes.generation)
+    }
+
+    /// Apply a backup written by `export_changed_since` to this store, which becomes a replica of
+    /// the primary as of the generation of the backup, returning the number of pages applied.
+    /// Every delta is verified before any is applied, and no index may be open in the store.
+    pub fn import_changes(&mut self, mut reader: impl Read) -> crate::Result<usize> {
+        if !self.inner_ref().active_stores.is_empty() {
+            anyhow::bail!("Cannot import changes while indexes are open in the store!");
+        }
+
+        let deltas = backup::read_deltas(&mut reader)?;
+        let mut inner = self.inner_ref_mut();
+
+        // The catalog of the primary, with the pages it changed, replaces the catalog of the replica
+        let id = GLOBAL_STORE_CATALOG_ID;
+        let catalog = match deltas.iter().find(|delta| delta.id == id) {
+            Some(PageDelta {
+                data: Some(data), ..
+            }) => decode_catalog(&inner.unseal(id, data)?)?,
+            _ => anyhow::bail!("The backup holds no catalog!"),
+        };
+
+        let pages = deltas.len();
+        inner.write_untracked(
+            deltas
+                .into_iter()
+                .map(|delta| (delta.id, delta.data))
+                .collect(),
+        )?;
+        (inner.catalog, inner.changes) = catalog;
+
+        Ok(pages)
+    }
+
+    pub fn stats(&self) -> StorageStats {
         self.inner_ref().store.stats()
     }
+
+    /// Bytes written to the backend since the store was loaded
+    pub fn bytes_written(&self) -> u64 {
+        self.inner_ref().bytes_written
+    }
+
+    /// Bytes of pages read from the backend by its local stores since the store was loaded
+    pub fn bytes_read(&self) -> u64 {
+        self.inner_ref().bytes_read.get()
+    }
+
+    /// Reclaim the space held by overwritten and freed pages, returning the number of pages moved.
+    /// This also runs whenever the store is dropped, unless it is read-only.
+    pub fn maintenance(&self) -> crate::Result<usize> {
+        if self.is_read_only() {
+            anyhow::bail!("Store was opened read-only!");
+        }
+
+        self.inner_ref().store.maintenance()
+    }
+}
+
+/// Decode the catalog page, along with the generations of every page. Catalogs written before
+/// changes were tracked hold only the catalog, and every page they allocated counts as changed in
+/// the first generation.
+fn decode_catalog(data: &[u8]) -> crate::Result<(GlobalStoreCatalog, ChangeLog)> {
+    if let Ok(catalog) = format::decode(data) {
+        return Ok(catalog);
+    }
+
+    let catalog: GlobalStoreCatalog = format::decode(data)?;
+    let changes =
+        ChangeLog::legacy(std::iter::once(GLOBAL_STORE_CATALOG_ID).chain(catalog.ids.iter()));
+
+    Ok((catalog, changes))
 }
 
 impl Drop for GlobalStore {
@@ -158,96 +605,296 @@ impl Drop for GlobalStore {
             "Shutting down global object store, but not all local object stores have been freed!"
         );
 
+        if self.is_read_only() {
+            return;
+        }
+
         self.flush().expect("Failed to flush GlobalStore to disk!");
 
-        self.inner_ref_mut()
-            .store
-            .maintenance()
-            .expect("Defragmentation failed!");
+        self.maintenance().expect("Defragmentation failed!");
     }
 }
 
-pub struct LocalStore<C, P>
+/// Whether the pages of a `LocalStore` may be evicted from its cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePriority {
+    /// Pages are evicted by the cache policy of the `GlobalStore` once the cache is full, and the
+    /// whole cache is dropped whenever the store is flushed
+    #[default]
+    Evictable,
+
+    /// Pages stay cached once read or written, only dirty pages are written out when there are
+    /// too many of them. Meant for small layers which are on the path of every lookup.
+    Resident,
+}
+
+/// A store of pages of type `P`, along with a catalog page `C`. Pages are encoded with `Z` on
+/// disk, while the catalog is always stored uncompressed. Both are encrypted after encoding if the
+/// `GlobalStore` has a key.
+pub struct LocalStore<C, P, Z = NoCompression>
 where
     C: Serialize + for<'de> Deserialize<'de> + Clone,
     P: Serialize + for<'de> Deserialize<'de> + Clone,
+    Z: PageCompression,
 {
     root: Rc<RefCell<GlobalStoreInner>>,
     pub catalog: C,
     id: StoreID,
     ident: String,
 
-    cache: Rc<RefCell<HashMap<StoreID, Option<P>>>>,
+    cache: Rc<RefCell<PageCache<P>>>,
+    dirty: Rc<RefCell<HashSet<StoreID>>>,
+    priority: CachePriority,
+    _ph: std::marker::PhantomData<Z>,
 }
 
-impl<C, P> LocalStore<C, P>
+impl<C, P, Z> LocalStore<C, P, Z>
 where
     C: Serialize + for<'de> Deserialize<'de> + Clone,
     P: Serialize + for<'de> Deserialize<'de> + Clone,
+    Z: PageCompression,
 {
+    pub fn priority(&self) -> CachePriority {
+        self.priority
+    }
+
+    /// Page holding the catalog of this store
+    pub fn catalog_page(&self) -> StoreID {
+        self.id
+    }
+
+    pub fn set_priority(&mut self, priority: CachePriority) {
+        self.priority = priority;
+        self.cache
+            .as_ref()
+            .borrow_mut()
+            .set_resident(priority == CachePriority::Resident);
+    }
+
+    /// Keep every page of this store cached
+    pub fn pin_resident(&mut self) {
+        self.set_priority(CachePriority::Resident);
+    }
+
     pub fn flush(&self) -> crate::Result<()> {
+        self.write_dirty()?;
+
+        if self.priority == CachePriority::Evictable {
+            self.cache.as_ref().borrow_mut().clear();
+        }
+
+        Ok(())
+    }
+
+    /// Hold back every page any local store of the `GlobalStore` writes from now on, until
+    /// `commit_batch` hands them all to the backend as one batch, or `abort_batch` drops them.
+    /// Reads see the pages held back in the meantime.
+    pub fn begin_batch(&self) -> crate::Result<()> {
+        self.inner_ref_mut().begin_batch()
+    }
+
+    /// Write every page held back since `begin_batch` in one batch of the backend. If that fails,
+    /// the batch is aborted.
+    pub fn commit_batch(&self) -> crate::Result<()> {
+        self.inner_ref_mut().commit_batch()
+    }
+
+    /// Drop every page held back since `begin_batch`, along with the pages it allocated. Local
+    /// stores which wrote to the batch have to `reload` before they are used again.
+    pub fn abort_batch(&self) {
+        self.inner_ref_mut().abort_batch();
+    }
+
+    /// Drop every cached and dirty page, and read the catalog again as the backend holds it
+    pub fn reload(&mut self) -> crate::Result<()> {
+        let catalog = {
+            let root = self.inner_ref();
+
+            match root.read(self.id)? {
+                Some(data) => format::decode(&root.unseal(self.id, data.as_ref())?)?,
+                None => anyhow::bail!("The catalog of {} was never written!", self.ident),
+            }
+        };
+
+        self.catalog = catalog;
+        self.dirty.as_ref().borrow_mut().clear();
+        self.cache.as_ref().borrow_mut().clear();
+
+        Ok(())
+    }
+
+    /// Write out the dirty pages along with the catalog, keeping them cached. A read-only store
+    /// never has dirty pages, and its catalog is left as it was loaded.
+    fn write_dirty(&self) -> crate::Result<()> {
+        if self.inner_ref().read_only {
+            return Ok(());
+        }
+
         let catalog = self.catalog.clone();
 
-        // Serialize the cache
-        let mut write_batch: Vec<(StoreID, Option<Vec<u8>>)> = self
-            .cache
+        // Serialize the dirty pages, which stay dirty until the batch holding them is written
+        let root = self.inner_ref();
+        let cache = self.cache.as_ref().borrow();
+        let mut write_batch = self
+            .dirty
             .as_ref()
-            .borrow_mut()
+            .borrow()
             .iter()
-            .map_while(|(&id, page)| {
-                if let Some(page) = page {
-                    let data = bincode::serialize(page).ok()?;
-                    return Some((id, Some(data)));
+            .map(|&id| match cache.peek(id) {
+                Some(Some(page)) => {
+                    let data = Z::encode(format::encode(page)?)?;
+                    Ok((id, Some(root.seal(id, data)?)))
                 }
-
-                Some((id, None))
+                _ => Ok((id, None)),
             })
-            .collect();
-        self.cache.as_ref().borrow_mut().clear();
+            .collect::<crate::Result<Vec<(StoreID, Option<Vec<u8>>)>>>()?;
+        drop(cache);
+
+        write_batch.push((
+            self.id,
+            Some(root.seal(self.id, format::encode(&catalog)?)?),
+        ));
+        drop(root);
+
+        self.inner_ref_mut().write_batch(write_batch)?;
+        self.dirty.as_ref().borrow_mut().clear();
+
+        Ok(())
+    }
+
+    /// Write out the dirty pages once there are more than fit in the cache, bounding the amount of
+    /// unwritten data, and evict the pages which were only held because they were dirty
+    fn flush_if_full(&self) -> crate::Result<()> {
+        let mut cache = self.cache.as_ref().borrow_mut();
+
+        if self.dirty.as_ref().borrow().len() > cache.capacity() {
+            drop(cache);
+            self.write_dirty()?;
 
-        write_batch.push((self.id, Some(bincode::serialize(&catalog)?)));
+            cache = self.cache.as_ref().borrow_mut();
+            cache.shrink(&self.dirty.as_ref().borrow());
+        }
 
-        self.inner_ref_mut().store.write_batch(write_batch)?;
         Ok(())
     }
 
     pub fn write_page(&self, page: &P, id: StoreID) -> crate::Result<()> {
+        if self.inner_ref().read_only {
+            anyhow::bail!("Store was opened read-only!");
+        }
+
+        let mut dirty = self.dirty.as_ref().borrow_mut();
+        dirty.insert(id);
         self.cache
             .as_ref()
             .borrow_mut()
-            .insert(id, Some(page.clone()));
+            .insert(id, Some(page.clone()), &dirty);
+        drop(dirty);
 
-        // Periodically flush the cache when writing
-        if self.cache.as_ref().borrow().len() * std::mem::size_of::<P>() > CACHE_SIZE {
-            self.flush()?;
-        }
-
-        Ok(())
+        self.flush_if_full()
     }
 
     pub fn read_page(&self, id: StoreID) -> crate::Result<Option<P>> {
-        if let Some(data) = self.cache.as_ref().borrow().get(&id) {
+        if let Some(data) = self.cache.as_ref().borrow_mut().get(id) {
             return Ok(data.clone());
         }
 
-        if let Some(data) = self.inner_ref().store.read(id)? {
-            let data: P = bincode::deserialize(data.as_ref())?;
+        Ok(self.fetch_page(id)?.map(|(page, _)| page))
+    }
+
+    /// Read a page from the backend into the cache, along with its size as stored
+    fn fetch_page(&self, id: StoreID) -> crate::Result<Option<(P, usize)>> {
+        let page = {
+            let root = self.inner_ref();
+
+            match root.read(id)? {
+                Some(data) => {
+                    root.count_read(data.len());
+                    Some((root.unseal(id, data.as_ref())?.into_owned(), data.len()))
+                }
+                None => None,
+            }
+        };
+
+        if let Some((data, size)) = page {
+            let data: P = format::decode(&Z::decode(&data)?)?;
             self.cache
                 .as_ref()
                 .borrow_mut()
-                .insert(id, Some(data.clone()));
+                .admit(id, data.clone(), &self.dirty.as_ref().borrow());
 
-            return Ok(Some(data));
+            return Ok(Some((data, size)));
         }
 
         Ok(None)
     }
+
+    /// Read a page into the cache ahead of its first lookup, unless it is already cached
+    pub fn warm_page(&self, id: StoreID, stats: &mut WarmStats) -> crate::Result<()> {
+        if self.cache.as_ref().borrow().contains(id) {
+            stats.cached += 1;
+            return Ok(());
+        }
+
+        if let Some((_, size)) = self.fetch_page(id)? {
+            stats.pages += 1;
+            stats.bytes += size as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Read part of a page through `seed`, straight from its bytes on disk, without adding the page
+    /// to the cache. A page which is already cached is handed to `cached` instead.
+    pub fn read_page_with<T, S>(
+        &self,
+        id: StoreID,
+        seed: S,
+        cached: impl FnOnce(&P) -> T,
+    ) -> crate::Result<Option<T>>
+    where
+        S: for<'de> DeserializeSeed<'de, Value = T>,
+    {
+        if let Some(page) = self.cache.as_ref().borrow_mut().get(id) {
+            return Ok(page.as_ref().map(cached));
+        }
+
+        let root = self.inner_ref();
+
+        match root.read(id)? {
+            Some(data) => {
+                root.count_read(data.len());
+                let data = root.unseal(id, data.as_ref())?;
+                let data = Z::decode(&data)?;
+
+                Ok(Some(format::decode_seed(seed, &data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Number of pages currently held in the cache
+    pub fn cached_pages(&self) -> usize {
+        self.cache.as_ref().borrow().len()
+    }
+
+    /// Bytes written to the backend by every store of the `GlobalStore` since it was loaded
+    pub fn bytes_written(&self) -> u64 {
+        self.inner_ref().bytes_written
+    }
+
+    /// Bytes of pages read from the backend by every store of the `GlobalStore` since it was
+    /// loaded
+    pub fn bytes_read(&self) -> u64 {
+        self.inner_ref().bytes_read.get()
+    }
 }
 
-impl<C, P> Drop for LocalStore<C, P>
+impl<C, P, Z> Drop for LocalStore<C, P, Z>
 where
     C: Serialize + for<'de> Deserialize<'de> + Clone,
     P: Serialize + for<'de> Deserialize<'de> + Clone,
+    Z: PageCompression,
 {
     fn drop(&mut self) {
         self.inner_ref_mut().active_stores.remove(&self.ident);
@@ -273,8 +920,7 @@ where
         if self.inner_ref_mut().catalog.ids.free(id) {
             self.remove_page(id);
 
-            let empty_page: Option<[u8; 1]> = None;
-            self.inner_ref_mut().store.write_batch([(id, empty_page)])?;
+            self.inner_ref_mut().write_batch(vec![(id, None)])?;
             return Ok(true);
         }
 
@@ -282,13 +928,13 @@ where
     }
 
     fn clear(&mut self) -> crate::Result<()> {
-        let mut clear_batch: Vec<(StoreID, Option<[u8; 1]>)> = vec![];
+        let mut clear_batch: Vec<(StoreID, Option<Vec<u8>>)> = vec![];
 
         for id in self.inner_ref().catalog.ids.iter() {
             clear_batch.push((id, None));
         }
 
-        self.inner_ref_mut().store.write_batch(clear_batch)?;
+        self.inner_ref_mut().write_batch(clear_batch)?;
         self.inner_ref_mut().catalog.ids.clear();
 
         Ok(())
@@ -296,37 +942,41 @@ where
 }
 
 trait ObjectStoreInner {
-    fn inner_ref(&self) -> Ref<GlobalStoreInner>;
-    fn inner_ref_mut(&self) -> RefMut<GlobalStoreInner>;
+    fn inner_ref(&self) -> Ref<'_, GlobalStoreInner>;
+    fn inner_ref_mut(&self) -> RefMut<'_, GlobalStoreInner>;
 
     // Callback for removing a page
-    fn remove_page(&self, _id: StoreID) -> () {}
+    fn remove_page(&self, _id: StoreID) {}
 }
 
-impl<C, P> ObjectStoreInner for LocalStore<C, P>
+impl<C, P, Z> ObjectStoreInner for LocalStore<C, P, Z>
 where
     C: Serialize + for<'de> Deserialize<'de> + Clone,
     P: Serialize + for<'de> Deserialize<'de> + Clone,
+    Z: PageCompression,
 {
-    fn inner_ref(&self) -> Ref<GlobalStoreInner> {
+    fn inner_ref(&self) -> Ref<'_, GlobalStoreInner> {
         self.root.as_ref().borrow()
     }
 
-    fn inner_ref_mut(&self) -> RefMut<GlobalStoreInner> {
+    fn inner_ref_mut(&self) -> RefMut<'_, GlobalStoreInner> {
         self.root.as_ref().borrow_mut()
     }
 
-    fn remove_page(&self, id: StoreID) -> () {
-        self.cache.as_ref().borrow_mut().insert(id, None);
+    fn remove_page(&self, id: StoreID) {
+        self.cache
+            .as_ref()
+            .borrow_mut()
+            .insert(id, None, &self.dirty.as_ref().borrow());
     }
 }
 
 impl ObjectStoreInner for GlobalStore {
-    fn inner_ref(&self) -> Ref<GlobalStoreInner> {
+    fn inner_ref(&self) -> Ref<'_, GlobalStoreInner> {
         self.inner.as_ref().borrow()
     }
 
-    fn inner_ref_mut(&self) -> RefMut<GlobalStoreInner> {
+    fn inner_ref_mut(&self) -> RefMut<'_, GlobalStoreInner> {
         self.inner.as_ref().borrow_mut()
     }
 }
@@ -334,6 +984,7 @@ impl ObjectStoreInner for GlobalStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::storage::Lz4;
 
     #[test]
     fn global_load() {
@@ -367,6 +1018,72 @@ mod tests {
         }
     }
 
+    #[test]
+    fn global_load_locked() {
+        use crate::common::storage::StoreLocked;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = GlobalStore::load(dir.path()).unwrap();
+
+        let err = GlobalStore::load(dir.path()).err().unwrap();
+        let pid = Some(std::process::id());
+        assert_eq!(err.downcast_ref(), Some(&StoreLocked { pid }));
+
+        // Marble keeps its directory locked, so a read-only load has to wait for the owner too
+        let err = GlobalStore::load_read_only(dir.path()).err().unwrap();
+        assert_eq!(err.downcast_ref(), Some(&StoreLocked { pid }));
+
+        // The lock is released along with the store
+        drop(store);
+        let inspector = GlobalStore::load_read_only(dir.path()).unwrap();
+        assert!(inspector.is_read_only());
+        drop(inspector);
+
+        let _store = GlobalStore::load(dir.path()).unwrap();
+        assert!(GlobalStore::load_read_only(dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn read_only_alongside_owner() {
+        use crate::common::storage::{FileBackend, StorageBackend, StoreLocked};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut owner = GlobalStore::with_backend(FileBackend::open(dir.path()).unwrap()).unwrap();
+        let mut local: LocalStore<TestCatalog, i32> = owner.load_local_store("test").unwrap();
+        local.catalog.id = local.allocate_page();
+        local.write_page(&7, local.catalog.id).unwrap();
+        local.flush().unwrap();
+
+        // The owner's backend holds the lock, so only a read-only backend can open the directory
+        let err = FileBackend::open(dir.path()).err().unwrap();
+        let pid = Some(std::process::id());
+        assert_eq!(err.downcast_ref(), Some(&StoreLocked { pid }));
+
+        let backend = FileBackend::open_read_only(dir.path()).unwrap();
+        assert!(backend.write_batch(vec![(1, None)]).is_err());
+
+        let mut inspector = GlobalStore::with_backend_read_only(backend).unwrap();
+        assert!(inspector.is_read_only());
+
+        {
+            let view: LocalStore<TestCatalog, i32> = inspector.load_local_store("test").unwrap();
+            assert_eq!(view.read_page(view.catalog.id).unwrap(), Some(7));
+            assert!(view.write_page(&8, view.catalog.id).is_err());
+
+            // A store which was never written isn't created
+            let _missing: LocalStore<TestCatalog, i32> =
+                inspector.load_local_store("missing").unwrap();
+        }
+
+        assert!(inspector.maintenance().is_err());
+        drop(inspector);
+
+        // The owner carries on as if the inspector was never there
+        local.write_page(&9, local.catalog.id).unwrap();
+        drop(local);
+        assert_eq!(owner.stats().pages, 3);
+    }
+
     #[test]
     fn allocate_and_free_page() {
         let dir = tempfile::tempdir().unwrap();
@@ -394,7 +1111,7 @@ mod tests {
 
         // Read data back from the page
         let read_data: Option<String> = store
-            .read_page(page_id)
+            .read_page(page_id, format::decode)
             .expect("Failed to read data from the page");
         assert_eq!(
             read_data,
@@ -406,6 +1123,86 @@ mod tests {
         store.free_page(page_id).unwrap();
     }
 
+    #[test]
+    fn migrate_legacy_pages() {
+        use crate::common::storage::MemoryBackend;
+
+        let backend = MemoryBackend::new();
+        let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+        let mut local: LocalStore<TestCatalog, Vec<u64>> = store.load_local_store("test").unwrap();
+
+        // A page as written before the format was versioned
+        let page_id = local.allocate_page();
+        let page: Vec<u64> = (0..100).collect();
+        backend
+            .write_batch(vec![(page_id, Some(bincode::serialize(&page).unwrap()))])
+            .unwrap();
+
+        assert_eq!(local.read_page(page_id).unwrap(), Some(page.clone()));
+
+        // Written back in the current format
+        local.write_page(&page, page_id).unwrap();
+        local.flush().unwrap();
+
+        let data = backend.read(page_id).unwrap().unwrap();
+        assert_eq!(format::version(&data), Some(format::FORMAT_VERSION));
+    }
+
+    #[test]
+    fn incremental_backup() {
+        use crate::common::storage::MemoryBackend;
+
+        let primary_backend = MemoryBackend::new();
+        let replica_backend = MemoryBackend::new();
+        let mut primary = GlobalStore::with_backend(primary_backend.clone()).unwrap();
+        let mut replica = GlobalStore::with_backend(replica_backend.clone()).unwrap();
+
+        let (first, second) = {
+            let mut local: LocalStore<TestCatalog, Vec<u64>> =
+                primary.load_local_store("test").unwrap();
+            let first = local.allocate_page();
+            let second = local.allocate_page();
+            local.write_page(&(0..100).collect(), first).unwrap();
+            local.write_page(&(0..10).collect(), second).unwrap();
+
+            (first, second)
+        };
+
+        // A full backup ships every page
+        let mut backup = Vec::new();
+        let generation = primary.export_changed_since(0, &mut backup).unwrap();
+        assert_eq!(replica.import_changes(backup.as_slice()).unwrap(), 4);
+
+        // An incremental one only ships the pages changed since, and the catalog
+        {
+            let mut local: LocalStore<TestCatalog, Vec<u64>> =
+                primary.load_local_store("test").unwrap();
+            local.write_page(&vec![7], second).unwrap();
+            local.free_page(first).unwrap();
+        }
+
+        let mut backup = Vec::new();
+        primary
+            .export_changed_since(generation, &mut backup)
+            .unwrap();
+        assert_eq!(replica.import_changes(backup.as_slice()).unwrap(), 4);
+        assert_eq!(replica.generation(), primary.generation());
+
+        for id in [GLOBAL_STORE_CATALOG_ID, first, second] {
+            assert_eq!(
+                replica_backend.read(id).unwrap(),
+                primary_backend.read(id).unwrap()
+            );
+        }
+
+        let local: LocalStore<TestCatalog, Vec<u64>> = replica.load_local_store("test").unwrap();
+        assert_eq!(local.read_page(first).unwrap(), None);
+        assert_eq!(local.read_page(second).unwrap(), Some(vec![7]));
+
+        // No changes can be applied while the store is in use
+        assert!(replica.import_changes(backup.as_slice()).is_err());
+    }
+
     #[test]
     fn page_not_found() {
         let dir = tempfile::tempdir().unwrap();
@@ -414,7 +1211,7 @@ mod tests {
         // Attempt to read a page that does not exist
         let page_id = 9999; // Assume this page ID is not used
         let result: Option<Vec<u8>> = store
-            .read_page(page_id)
+            .read_page(page_id, format::decode)
             .expect("Failed to perform read operation");
         assert!(result.is_none(), "Expected no data for an unused page ID");
     }
@@ -474,6 +1271,321 @@ mod tests {
         }
     }
 
+    #[test]
+    fn local_store_resident() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+
+        let mut resident: LocalStore<TestCatalog, i32> =
+            store.load_local_store("resident").unwrap();
+        let mut evictable: LocalStore<TestCatalog, i32> =
+            store.load_local_store("evictable").unwrap();
+        resident.pin_resident();
+
+        for i in 0..10 {
+            let id = resident.allocate_page();
+            resident.write_page(&i, id).unwrap();
+
+            let id = evictable.allocate_page();
+            evictable.write_page(&i, id).unwrap();
+        }
+
+        resident.flush().unwrap();
+        evictable.flush().unwrap();
+
+        assert_eq!(resident.priority(), CachePriority::Resident);
+        assert_eq!(resident.cached_pages(), 10);
+        assert_eq!(evictable.cached_pages(), 0);
+    }
+
+    /// Compression which refuses pages of more than a few bytes
+    struct RejectLarge;
+
+    impl PageCompression for RejectLarge {
+        fn encode(page: Vec<u8>) -> crate::Result<Vec<u8>> {
+            if page.len() > 64 {
+                anyhow::bail!("Page of {} bytes is too large!", page.len());
+            }
+
+            Ok(page)
+        }
+
+        fn decode(data: &[u8]) -> crate::Result<Cow<'_, [u8]>> {
+            Ok(Cow::Borrowed(data))
+        }
+    }
+
+    #[test]
+    fn flush_keeps_pages_dirty_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+
+        let ids = {
+            let mut local: LocalStore<TestCatalog, Vec<u64>, RejectLarge> =
+                store.load_local_store("test").unwrap();
+            let ids: Vec<StoreID> = (0..8).map(|_| local.allocate_page()).collect();
+
+            for &id in ids.iter() {
+                local.write_page(&vec![id], id).unwrap();
+            }
+            local.write_page(&(0..100).collect(), ids[3]).unwrap();
+
+            // None of the pages are written, and all of them are still waiting to be
+            assert!(local.flush().is_err());
+            assert_eq!(local.dirty.as_ref().borrow().len(), ids.len());
+
+            local.write_page(&vec![ids[3]], ids[3]).unwrap();
+            local.flush().unwrap();
+            assert!(local.dirty.as_ref().borrow().is_empty());
+
+            ids
+        };
+
+        let local: LocalStore<TestCatalog, Vec<u64>, RejectLarge> =
+            store.load_local_store("test").unwrap();
+        for id in ids {
+            assert_eq!(local.read_page(id).unwrap(), Some(vec![id]));
+        }
+    }
+
+    #[test]
+    fn local_store_batch() {
+        use crate::common::storage::MemoryBackend;
+
+        let backend = MemoryBackend::new();
+        let mut store = GlobalStore::with_backend(backend.clone()).unwrap();
+
+        let mut local_store: LocalStore<TestCatalog, i32> = store.load_local_store("test").unwrap();
+        local_store.catalog.id = local_store.allocate_page();
+        local_store.write_page(&1, local_store.catalog.id).unwrap();
+        local_store.flush().unwrap();
+        let written = store.bytes_written();
+
+        // Pages written in a batch read back, but only reach the backend once it commits
+        local_store.begin_batch().unwrap();
+        let id = local_store.allocate_page();
+        local_store.write_page(&2, id).unwrap();
+        local_store.catalog.entries.push("two".to_string());
+        local_store.flush().unwrap();
+        assert_eq!(local_store.read_page(id).unwrap(), Some(2));
+        assert_eq!(store.bytes_written(), written);
+
+        // Aborting drops the pages along with the page allocated
+        local_store.abort_batch();
+        local_store.reload().unwrap();
+        assert!(local_store.catalog.entries.is_empty());
+        assert_eq!(local_store.read_page(id).unwrap(), None);
+        assert_eq!(local_store.allocate_page(), id);
+
+        local_store.begin_batch().unwrap();
+        local_store.write_page(&3, id).unwrap();
+        local_store.catalog.entries.push("three".to_string());
+        local_store.flush().unwrap();
+        local_store.commit_batch().unwrap();
+
+        drop(local_store);
+        drop(store);
+
+        let mut store = GlobalStore::with_backend(backend).unwrap();
+        let local_store: LocalStore<TestCatalog, i32> = store.load_local_store("test").unwrap();
+        assert_eq!(local_store.catalog.entries, vec!["three".to_string()]);
+        assert_eq!(local_store.read_page(id).unwrap(), Some(3));
+    }
+
+    /// Hit rate of point lookups into a few hot pages, interleaved with scans over every page
+    fn scan_mixed_hit_rate(policy: CachePolicy) -> f64 {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path())
+            .unwrap()
+            .with_cache_policy(policy)
+            .with_cache_pages(16);
+
+        let mut local_store: LocalStore<TestCatalog, i32> = store.load_local_store("test").unwrap();
+        let ids: Vec<StoreID> = (0..256)
+            .map(|i| {
+                let id = local_store.allocate_page();
+                local_store.write_page(&i, id).unwrap();
+                id
+            })
+            .collect();
+        local_store.flush().unwrap();
+
+        for round in 0..40 {
+            for &id in &ids[..8] {
+                local_store.read_page(id).unwrap();
+            }
+
+            for &id in ids[8..].iter().cycle().skip(round * 32).take(32) {
+                local_store.read_page(id).unwrap();
+            }
+        }
+
+        assert!(local_store.cached_pages() <= 16);
+        let stats = store.cache_stats();
+        assert_eq!(stats.policy, policy);
+        assert_eq!(stats.hits + stats.misses, 40 * (8 + 32));
+
+        stats.hit_rate()
+    }
+
+    #[test]
+    fn local_store_cache_policy() {
+        let lru = scan_mixed_hit_rate(CachePolicy::Lru);
+        let tinylfu = scan_mixed_hit_rate(CachePolicy::TinyLfu);
+
+        // Scans push the hot pages out of an LRU cache, but not past TinyLFU
+        assert!(tinylfu > lru + 0.15, "{} vs {}", tinylfu, lru);
+    }
+
+    #[test]
+    fn local_store_compressed() {
+        use crate::common::storage::Zstd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let page: Vec<i32> = (0..1024).map(|i| i / 100).collect();
+
+        {
+            let mut local_store: LocalStore<TestCatalog, Vec<i32>, Lz4> =
+                store.load_local_store("test").unwrap();
+            local_store.catalog.id = local_store.allocate_page();
+            local_store
+                .write_page(&page, local_store.catalog.id)
+                .unwrap();
+        }
+
+        // Compressed pages record their codec, so they can be read back with another one
+        let local_store: LocalStore<TestCatalog, Vec<i32>, Zstd<3>> =
+            store.load_local_store("test").unwrap();
+        let read: Vec<i32> = local_store
+            .read_page(local_store.catalog.id)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(read, page);
+    }
+
+    /// Write a page and reload it, returning the stats of the reloaded store
+    fn reload_with<B: StorageBackend>(open: impl Fn() -> B) -> StorageStats {
+        let page: Vec<i32> = (0..1024).collect();
+
+        {
+            let mut store = GlobalStore::with_backend(open()).unwrap();
+            let mut local_store: LocalStore<TestCatalog, Vec<i32>, Lz4> =
+                store.load_local_store("test").unwrap();
+            local_store.catalog.id = local_store.allocate_page();
+            local_store
+                .write_page(&page, local_store.catalog.id)
+                .unwrap();
+
+            let freed = local_store.allocate_page();
+            local_store.write_page(&page, freed).unwrap();
+            local_store.free_page(freed).unwrap();
+        }
+
+        let mut store = GlobalStore::with_backend(open()).unwrap();
+        let stats = store.stats();
+
+        let local_store: LocalStore<TestCatalog, Vec<i32>, Lz4> =
+            store.load_local_store("test").unwrap();
+        let read: Vec<i32> = local_store
+            .read_page(local_store.catalog.id)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(read, page);
+        stats
+    }
+
+    #[test]
+    fn backends_reload() {
+        use crate::common::storage::{FileBackend, MarbleBackend, MemoryBackend};
+
+        let dir = tempfile::tempdir().unwrap();
+        reload_with(|| MarbleBackend::open(dir.path().join("marble")).unwrap());
+
+        // Both catalogs and the page which wasn't freed
+        let stats = reload_with(|| FileBackend::open(dir.path().join("files")).unwrap());
+        assert_eq!(stats.pages, 3);
+
+        let memory = MemoryBackend::new();
+        assert_eq!(reload_with(|| memory.clone()).pages, 3);
+    }
+
+    #[test]
+    fn load_with_report() {
+        use crate::common::storage::{FileBackend, MemoryBackend};
+
+        let dir = tempfile::tempdir().unwrap();
+        let open = || FileBackend::open(dir.path()).unwrap();
+
+        let (_, report) = GlobalStore::with_backend_and_report(open()).unwrap();
+        assert!(report.is_clean());
+
+        let catalog = {
+            let mut store = GlobalStore::with_backend(open()).unwrap();
+            let local: LocalStore<TestCatalog, i32> = store.load_local_store("test").unwrap();
+            local.id
+        };
+
+        // A page torn by a crash, and a local catalog lost with it
+        std::fs::write(dir.path().join("00000000000000ff.tmp"), b"torn").unwrap();
+        open().write_batch(vec![(catalog, None)]).unwrap();
+
+        {
+            let (mut store, report) = GlobalStore::with_backend_and_report(open()).unwrap();
+            assert_eq!(report.pages_discarded, 1);
+            assert_eq!(report.catalogs_rebuilt, 1);
+            assert!(!report.is_clean());
+
+            let _local: LocalStore<TestCatalog, i32> = store.load_local_store("test").unwrap();
+        }
+
+        let (_, report) = GlobalStore::with_backend_and_report(open()).unwrap();
+        assert!(report.is_clean());
+
+        // Pages left without the global catalog
+        let backend = MemoryBackend::new();
+        backend.write_batch(vec![(5, Some(vec![1, 2, 3]))]).unwrap();
+
+        let (_, report) = GlobalStore::with_backend_and_report(backend).unwrap();
+        assert_eq!(report.catalogs_rebuilt, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn local_store_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = EncryptionKey::generate();
+        let page: Vec<i32> = (0..1024).collect();
+
+        {
+            let mut store = GlobalStore::load_with_key(dir.path(), &key).unwrap();
+            let mut local_store: LocalStore<TestCatalog, Vec<i32>, Lz4> =
+                store.load_local_store("test").unwrap();
+            local_store.catalog.id = local_store.allocate_page();
+            local_store
+                .write_page(&page, local_store.catalog.id)
+                .unwrap();
+        }
+
+        // Neither the wrong key nor no key can read the catalog back
+        let other = EncryptionKey::generate();
+        assert!(GlobalStore::load_with_key(dir.path(), &other).is_err());
+        assert!(GlobalStore::load(dir.path()).is_err());
+
+        // Failed loads must not overwrite the catalog
+        let mut store = GlobalStore::load_with_key(dir.path(), &key).unwrap();
+        let local_store: LocalStore<TestCatalog, Vec<i32>, Lz4> =
+            store.load_local_store("test").unwrap();
+        let read: Vec<i32> = local_store
+            .read_page(local_store.catalog.id)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(read, page);
+    }
+
     #[test]
     #[should_panic(
         expected = "Shutting down global object store, but not all local object stores have been freed!"
@@ -549,7 +1661,8 @@ mod tests {
                 .expect("Should be able to write raw bytes as corrupted data");
 
             // Attempt to read the corrupted data as a TestCatalog
-            let read_result: crate::Result<Option<TestCatalog>> = store.read_page(page_id);
+            let read_result: crate::Result<Option<TestCatalog>> =
+                store.read_page(page_id, format::decode);
             assert!(
                 read_result.is_err(),
                 "Reading corrupted data should result in an error"
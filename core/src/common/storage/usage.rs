@@ -0,0 +1,112 @@
+//! Disk usage of a persisted index, broken down by layer. Backends only report the size of the
+//! store as a whole, so the live bytes are attributed to every layer by its share of the pages.
+
+use super::StorageStats;
+
+/// Pages held by one part of an index
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub pages: u64,
+
+    /// Estimated from the share of pages
+    pub bytes: u64,
+}
+
+/// Disk usage of a persisted index
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiskStats {
+    /// Usage of every persisted layer as `(layer, usage)`, ordered from the base layer up
+    pub layers: Vec<(usize, DiskUsage)>,
+
+    /// Usage of the value log, for layouts with one
+    pub value_log: Option<DiskUsage>,
+
+    /// Bytes held by pages which are still in use, including catalogs
+    pub live_bytes: u64,
+
+    /// Bytes held by overwritten or freed pages, which compaction reclaims
+    pub dead_bytes: u64,
+}
+
+impl DiskStats {
+    /// Attribute the live bytes of `storage` to `layers` and the value log, given the number of
+    /// pages each of them holds
+    pub fn attribute(
+        storage: StorageStats,
+        layers: impl IntoIterator<Item = (usize, u64)>,
+        value_log: Option<u64>,
+    ) -> Self {
+        let live_bytes = storage.bytes.saturating_sub(storage.dead_bytes);
+
+        let usage = |pages: u64| DiskUsage {
+            pages,
+            bytes: if storage.pages > 0 {
+                (live_bytes as u128 * pages.min(storage.pages) as u128 / storage.pages as u128)
+                    as u64
+            } else {
+                0
+            },
+        };
+
+        Self {
+            layers: layers
+                .into_iter()
+                .map(|(layer, pages)| (layer, usage(pages)))
+                .collect(),
+            value_log: value_log.map(usage),
+            live_bytes,
+            dead_bytes: storage.dead_bytes,
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.live_bytes + self.dead_bytes
+    }
+
+    /// Fraction of the bytes on disk which compaction would reclaim
+    pub fn fragmentation(&self) -> f64 {
+        match self.total_bytes() {
+            0 => 0.0,
+            total => self.dead_bytes as f64 / total as f64,
+        }
+    }
+}
+
+/// Pages read ahead of time by `warm`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WarmStats {
+    /// Pages read from the backend into the cache
+    pub pages: u64,
+
+    /// Bytes read from the backend, as stored
+    pub bytes: u64,
+
+    /// Pages which were already cached, and weren't read again
+    pub cached: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_stats_attribute() {
+        let storage = StorageStats {
+            pages: 10,
+            bytes: 1_500,
+            dead_bytes: 500,
+        };
+
+        let stats = DiskStats::attribute(storage, [(0, 6), (2, 2)], Some(1));
+        assert_eq!(stats.live_bytes, 1_000);
+        assert_eq!(stats.layers[0].0, 0);
+        assert_eq!(stats.layers[0].1.pages, 6);
+        assert_eq!(stats.layers[0].1.bytes, 600);
+        assert_eq!(stats.layers[1].1.bytes, 200);
+        assert_eq!(stats.value_log.map(|usage| usage.bytes), Some(100));
+        assert!((stats.fragmentation() - 1.0 / 3.0).abs() < 1e-9);
+
+        let empty = DiskStats::attribute(StorageStats::default(), [(0, 0)], None);
+        assert_eq!(empty.fragmentation(), 0.0);
+    }
+}
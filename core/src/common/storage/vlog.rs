@@ -0,0 +1,446 @@
+//! A WiscKey-style value log. Values which are too large to be stored inline in the base layer are
+//! appended to segments of a log instead, and the base layer only stores a `ValuePointer` to them.
+//! Overwritten values are left in place as garbage, and are reclaimed by relocating the live
+//! values of mostly-dead segments.
+//!
+//! A segment is a page of bytes holding its entries back to back, and a pointer holds the range of
+//! its entry, so a lookup decodes only the entry it points to. The head segment is buffered in
+//! memory while it fills up, and is written to the store once it is sealed or the log is flushed.
+
+use super::{format, store::ObjectStoreGeneric, GlobalStore, LocalStore, StoreID};
+use crate::Persisted;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ops::Range;
+use serde::de::{DeserializeSeed, Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+/// Segments are sealed once they reach this size, after which they become candidates for garbage
+/// collection
+const SEGMENT_BYTES: u64 = 64 * 1024;
+
+/// A sealed segment is garbage collected once at least this fraction of its entries are dead
+const GC_DEAD_RATIO: f64 = 0.5;
+
+/// Location of a value inside of the value log
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValuePointer {
+    segment: StoreID,
+    offset: u32,
+    len: u32,
+}
+
+impl ValuePointer {
+    /// Bytes of the entry inside of its segment
+    fn range(&self) -> Range<usize> {
+        self.offset as usize..self.offset as usize + self.len as usize
+    }
+}
+
+/// The value type stored in the base layer of a layout with a value log
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum VLogValue<V> {
+    Inline(V),
+    Pointer(ValuePointer),
+}
+
+impl<V: Default> Default for VLogValue<V> {
+    fn default() -> Self {
+        Self::Inline(V::default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+struct SegmentStats {
+    live: u32,
+    dead: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct ValueLogCatalog {
+    head: Option<StoreID>,
+    segments: BTreeMap<StoreID, SegmentStats>,
+}
+
+/// Decode the entry behind a pointer out of the bytes of its segment
+fn decode_entry<K, V>(segment: &[u8], ptr: ValuePointer) -> crate::Result<(K, V)>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    let mut entry = segment
+        .get(ptr.range())
+        .ok_or_else(|| anyhow::anyhow!("Dangling value log pointer {:?}!", ptr))?;
+
+    format::decode_record(&mut entry)
+}
+
+/// Decodes the entry behind a pointer straight from the bytes of its segment on disk, without
+/// deserializing the rest of the segment. The segment is a `Vec<u8>`, which bincode lays out just
+/// like a byte slice, so it can be borrowed whole.
+struct EntrySeed<K, V> {
+    ptr: ValuePointer,
+    _ph: PhantomData<(K, V)>,
+}
+
+impl<'de, K: Persisted, V: Persisted> DeserializeSeed<'de> for EntrySeed<K, V> {
+    type Value = crate::Result<(K, V)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_bytes(self)
+    }
+}
+
+impl<'de, K: Persisted, V: Persisted> Visitor<'de> for EntrySeed<K, V> {
+    type Value = crate::Result<(K, V)>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(formatter, "a value log segment")
+    }
+
+    fn visit_bytes<E: Error>(self, segment: &[u8]) -> Result<Self::Value, E> {
+        Ok(decode_entry(segment, self.ptr))
+    }
+}
+
+pub struct ValueLog<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    store: LocalStore<ValueLogCatalog, Vec<u8>>,
+    threshold: u64,
+
+    /// Entries of the head segment, and whether they have changed since they were last written
+    head: Vec<u8>,
+    head_dirty: Cell<bool>,
+
+    _ph: PhantomData<(K, V)>,
+}
+
+impl<K, V> ValueLog<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    /// Load the value log, values whose serialized size exceeds `threshold` bytes are stored out
+    /// of line
+    pub fn load(
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        threshold: u64,
+    ) -> crate::Result<Self> {
+        let store: LocalStore<ValueLogCatalog, Vec<u8>> = store.load_local_store(ident)?;
+
+        let head = match store.catalog.head {
+            Some(head) => store.read_page(head)?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            store,
+            threshold,
+            head,
+            head_dirty: Cell::new(false),
+            _ph: PhantomData,
+        })
+    }
+
+    /// Convert a value into its base layer representation, appending it to the log if it is
+    /// too large to be stored inline
+    pub fn store(&mut self, key: &K, value: V) -> crate::Result<VLogValue<V>> {
+        if bincode::serialized_size(&value)? > self.threshold {
+            return Ok(VLogValue::Pointer(self.append(key.clone(), value)?));
+        }
+
+        Ok(VLogValue::Inline(value))
+    }
+
+    /// Read the value behind a base layer representation
+    pub fn resolve(&self, value: VLogValue<V>) -> crate::Result<V> {
+        match value {
+            VLogValue::Inline(value) => Ok(value),
+            VLogValue::Pointer(ptr) if Some(ptr.segment) == self.store.catalog.head => {
+                Ok(decode_entry::<K, V>(&self.head, ptr)?.1)
+            }
+            VLogValue::Pointer(ptr) => {
+                let seed = EntrySeed::<K, V> {
+                    ptr,
+                    _ph: PhantomData,
+                };
+
+                let entry = self
+                    .store
+                    .read_page_with(ptr.segment, seed, |segment| decode_entry(segment, ptr))?
+                    .ok_or_else(|| anyhow::anyhow!("Missing value log segment {}!", ptr.segment))?;
+
+                Ok(entry?.1)
+            }
+        }
+    }
+
+    /// Mark a value which has been overwritten in the base layer as garbage
+    pub fn release(&mut self, value: &VLogValue<V>) {
+        if let VLogValue::Pointer(ptr) = value {
+            if let Some(stats) = self.store.catalog.segments.get_mut(&ptr.segment) {
+                stats.live = stats.live.saturating_sub(1);
+                stats.dead += 1;
+            }
+        }
+    }
+
+    /// Append an entry to the head segment of the log, sealing the head first if the entry
+    /// doesn't fit in it
+    pub fn append(&mut self, key: K, value: V) -> crate::Result<ValuePointer> {
+        let entry = format::encode_record(&(&key, &value))?;
+        let fits = (self.head.len() + entry.len()) as u64 <= SEGMENT_BYTES;
+
+        let segment = match self.store.catalog.head {
+            Some(head) if fits || self.head.is_empty() => head,
+            _ => {
+                self.seal()?;
+
+                let head = self.store.allocate_page();
+                self.store.catalog.head = Some(head);
+                self.store.catalog.segments.insert(head, Default::default());
+                head
+            }
+        };
+
+        let ptr = ValuePointer {
+            segment,
+            offset: self.head.len() as u32,
+            len: entry.len() as u32,
+        };
+
+        self.head.extend_from_slice(&entry);
+        self.head_dirty.set(true);
+
+        if let Some(stats) = self.store.catalog.segments.get_mut(&segment) {
+            stats.live += 1;
+        }
+
+        Ok(ptr)
+    }
+
+    /// Write the head segment out for the last time, and start an empty one
+    fn seal(&mut self) -> crate::Result<()> {
+        self.write_head()?;
+        self.head.clear();
+
+        Ok(())
+    }
+
+    /// Write the head segment to the store, if it changed since it was last written
+    fn write_head(&self) -> crate::Result<()> {
+        if let (Some(head), true) = (self.store.catalog.head, self.head_dirty.get()) {
+            self.store.write_page(&self.head, head)?;
+            self.head_dirty.set(false);
+        }
+
+        Ok(())
+    }
+
+    /// Sealed segments which have accumulated enough garbage to be worth rewriting
+    pub fn gc_candidates(&self) -> Vec<StoreID> {
+        let catalog = &self.store.catalog;
+
+        catalog
+            .segments
+            .iter()
+            .filter(|(&id, _)| Some(id) != catalog.head)
+            .filter(|(_, stats)| {
+                let total = (stats.live + stats.dead) as f64;
+                total == 0.0 || stats.dead as f64 >= GC_DEAD_RATIO * total
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// All entries of a segment, whether or not they are still live
+    pub fn segment_entries(&self, segment: StoreID) -> crate::Result<Vec<(ValuePointer, K, V)>> {
+        let page = match Some(segment) == self.store.catalog.head {
+            true => self.head.clone(),
+            false => self.store.read_page(segment)?.unwrap_or_default(),
+        };
+
+        let mut entries = Vec::new();
+        let mut rest = page.as_slice();
+
+        while !rest.is_empty() {
+            let offset = page.len() - rest.len();
+            let (key, value) = format::decode_record(&mut rest)?;
+
+            let ptr = ValuePointer {
+                segment,
+                offset: offset as u32,
+                len: (page.len() - rest.len() - offset) as u32,
+            };
+
+            entries.push((ptr, key, value));
+        }
+
+        Ok(entries)
+    }
+
+    /// Drop a segment whose live values have all been relocated
+    pub fn remove_segment(&mut self, segment: StoreID) -> crate::Result<()> {
+        self.store.catalog.segments.remove(&segment);
+        self.store.free_page(segment)?;
+
+        Ok(())
+    }
+
+    /// Number of segments currently in the log
+    pub fn segment_count(&self) -> usize {
+        self.store.catalog.segments.len()
+    }
+
+    /// Write every segment out, along with the catalog
+    pub fn flush(&self) -> crate::Result<()> {
+        self.write_head()?;
+        self.store.flush()
+    }
+
+    /// Drop every entry appended since the log was last flushed, after the batch of writes they
+    /// went to was aborted
+    pub fn reload(&mut self) -> crate::Result<()> {
+        self.store.reload()?;
+
+        self.head = match self.store.catalog.head {
+            Some(head) => self.store.read_page(head)?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        self.head_dirty.set(false);
+
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for ValueLog<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    fn drop(&mut self) {
+        // The store flushes itself as it is dropped, right after this
+        self.write_head()
+            .expect("Failed to write value log head segment!");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vlog_inline_and_pointer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let mut vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+
+        let small = vlog.store(&1, vec![1; 8]).unwrap();
+        let large = vlog.store(&2, vec![2; 256]).unwrap();
+
+        assert_eq!(small, VLogValue::Inline(vec![1; 8]));
+        assert!(matches!(large, VLogValue::Pointer(_)));
+
+        assert_eq!(vlog.resolve(small).unwrap(), vec![1; 8]);
+        assert_eq!(vlog.resolve(large).unwrap(), vec![2; 256]);
+    }
+
+    #[test]
+    fn vlog_persists() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Both in a sealed segment and in the head
+        let values: Vec<VLogValue<Vec<u8>>> = {
+            let mut store = GlobalStore::load(dir.path()).unwrap();
+            let mut vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+
+            (0..128)
+                .map(|key| vlog.store(&key, vec![key as u8; 1024]).unwrap())
+                .collect()
+        };
+
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+        assert!(vlog.segment_count() > 1);
+
+        for (key, value) in values.into_iter().enumerate() {
+            assert_eq!(vlog.resolve(value).unwrap(), vec![key as u8; 1024]);
+        }
+    }
+
+    #[test]
+    fn vlog_buffers_head_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let mut vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+
+        // Appends to the head stay in memory until it is sealed
+        let values: Vec<VLogValue<Vec<u8>>> = (0..16)
+            .map(|key| vlog.store(&key, vec![key as u8; 1024]).unwrap())
+            .collect();
+        assert_eq!(vlog.store.cached_pages(), 0);
+
+        for key in 16..128 {
+            vlog.store(&key, vec![key as u8; 1024]).unwrap();
+        }
+        assert!(vlog.store.cached_pages() > 0);
+
+        for (key, value) in values.into_iter().enumerate() {
+            assert_eq!(vlog.resolve(value).unwrap(), vec![key as u8; 1024]);
+        }
+    }
+
+    #[test]
+    fn vlog_segment_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let mut vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+
+        let ptrs: Vec<ValuePointer> = (0..8)
+            .map(|key| {
+                vlog.append(key, vec![key as u8; 100 + key as usize])
+                    .unwrap()
+            })
+            .collect();
+
+        let entries = vlog.segment_entries(ptrs[0].segment).unwrap();
+        assert_eq!(entries.len(), 8);
+
+        for ((ptr, key, value), expected) in entries.into_iter().zip(ptrs) {
+            assert_eq!(ptr, expected);
+            assert_eq!(value, vec![key as u8; 100 + key as usize]);
+        }
+    }
+
+    #[test]
+    fn vlog_gc_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = GlobalStore::load(dir.path()).unwrap();
+        let mut vlog: ValueLog<u64, Vec<u8>> = ValueLog::load(&mut store, "vlog", 64).unwrap();
+
+        // Fill a few segments
+        let values: Vec<VLogValue<Vec<u8>>> = (0..256)
+            .map(|key| vlog.store(&key, vec![0; 1024]).unwrap())
+            .collect();
+        assert!(vlog.segment_count() > 1);
+        assert!(vlog.gc_candidates().is_empty());
+
+        // Kill every value, every sealed segment should now be collectable
+        for value in values.iter() {
+            vlog.release(value);
+        }
+
+        let candidates = vlog.gc_candidates();
+        assert_eq!(candidates.len(), vlog.segment_count() - 1);
+
+        for segment in candidates {
+            vlog.remove_segment(segment).unwrap();
+        }
+        assert_eq!(vlog.segment_count(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! The write-ahead log of a persisted index, which holds the `WriteBatch` being applied in the
+//! catalog of its own `LocalStore`. A batch is logged before any of it is written to the layers.
+//! The store then holds back every page written while the batch is applied, and writes them to
+//! the backend in one batch along with the cleared log, so a batch found in the log when the index
+//! is opened never reached the layers, and is applied again. A batch which fails halfway is
+//! aborted instead: the pages held back are dropped, the layers reload what the backend holds, and
+//! the log is cleared.
+
+use super::{GlobalStore, LocalStore};
+use crate::write_batch::WriteBatch;
+use crate::Persisted;
+
+pub struct WriteAheadLog<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    store: LocalStore<Option<WriteBatch<K, V>>, ()>,
+
+    /// Whether the index can delete keys, which takes tombstones for persisted layouts
+    deletes: bool,
+}
+
+impl<K, V> WriteAheadLog<K, V>
+where
+    K: Persisted,
+    V: Persisted,
+{
+    pub fn load(
+        store: &mut GlobalStore,
+        ident: impl ToString,
+        deletes: bool,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            store: store.load_local_store(ident)?,
+            deletes,
+        })
+    }
+
+    /// Durably record `batch` as the one being applied, refusing batches which delete keys if the
+    /// index can't
+    pub fn log(&mut self, batch: &WriteBatch<K, V>) -> crate::Result<()> {
+        if !self.deletes && batch.has_deletes() {
+            anyhow::bail!("Deleting keys requires a layout with `tombstones: true`!");
+        }
+
+        self.store.catalog = Some(batch.clone());
+        self.store.flush()
+    }
+
+    /// Durably mark the batch being applied as done
+    pub fn clear(&mut self) -> crate::Result<()> {
+        self.store.catalog = None;
+        self.store.flush()
+    }
+
+    /// Hold back every page written to the store from now on, until the batch commits or aborts
+    pub fn begin(&mut self) -> crate::Result<()> {
+        self.store.begin_batch()
+    }
+
+    /// Mark the batch being applied as done, and write every page held back since `begin` along
+    /// with the log in one batch of the backend. If that fails, nothing was written.
+    pub fn commit(&mut self) -> crate::Result<()> {
+        self.store.catalog = None;
+        self.store.flush()?;
+        self.store.commit_batch()
+    }
+
+    /// Drop every page held back since `begin`. Layers which wrote to the batch have to reload,
+    /// and the log has to be cleared, since none of the batch will be applied.
+    pub fn abort(&mut self) {
+        self.store.abort_batch();
+    }
+
+    /// The batch which was being applied when the index was last closed, if it was cut short
+    pub fn pending(&self) -> Option<WriteBatch<K, V>> {
+        self.store.catalog.clone()
+    }
+}
@@ -0,0 +1,28 @@
+//! Deletes in persisted layouts. Removing an entry in place could empty a base node, whose lower
+//! bound the layers above route by, so a delete instead overwrites the value with a tombstone.
+//! Searches treat a tombstone as a missing key, and tombstones are only dropped when the base layer
+//! is purged.
+
+use serde::{Deserialize, Serialize};
+
+/// The value type stored in the base layer of a layout with `tombstones`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Entry<V> {
+    Value(V),
+    #[default]
+    Tombstone,
+}
+
+impl<V> Entry<V> {
+    pub fn is_tombstone(&self) -> bool {
+        matches!(self, Self::Tombstone)
+    }
+
+    /// The live value, or `None` if the key was deleted
+    pub fn into_value(self) -> Option<V> {
+        match self {
+            Self::Value(value) => Some(value),
+            Self::Tombstone => None,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Expiring entries in persisted layouts. With `ttl: enabled`, every value is stored along with
+//! the time it expires at, in milliseconds since the Unix epoch. Searches treat an expired value as
+//! a missing key, and expired values are only dropped when the base layer is swept.
+
+use serde::{Deserialize, Serialize};
+
+/// The value type stored in the base layer of a layout with `ttl: enabled`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Expiring<V> {
+    pub value: V,
+
+    /// Milliseconds since the Unix epoch from which on the value is expired, or `None` if it never
+    /// expires
+    pub expires_at: Option<u64>,
+}
+
+impl<V> Expiring<V> {
+    pub fn new(value: V, expires_at: Option<u64>) -> Self {
+        Self { value, expires_at }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// The value, or `None` if it is expired as of `now`
+    pub fn into_live(self, now: u64) -> Option<V> {
+        (!self.is_expired(now)).then_some(self.value)
+    }
+}
+
+/// Milliseconds since the Unix epoch, the clock expiry times are compared against
+#[cfg(feature = "std")]
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
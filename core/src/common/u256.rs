@@ -0,0 +1,692 @@
+//! A 256-bit unsigned key, for hash-keyed workloads which don't fit into a `u128`. Implements
+//! `PrimInt`, so it works with every component, including the learned ones: models only ever
+//! convert the difference between two keys into a float, so nearby keys stay distinguishable even
+//! though a float can't represent every 256-bit key.
+
+use crate::traits::{KeyBound, KeyBounded};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub};
+use num::traits::{
+    Bounded, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Num, NumCast, One, PrimInt,
+    Saturating, ToPrimitive, Zero,
+};
+use serde::{Deserialize, Serialize};
+
+/// A 256-bit unsigned integer, stored as 64-bit words from the most to the least significant, so
+/// that the derived ordering is the numeric one
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const BITS: u32 = 256;
+    pub const ZERO: Self = Self([0; 4]);
+    pub const ONE: Self = Self([0, 0, 0, 1]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    /// From 64-bit words, ordered from the most to the least significant
+    pub const fn from_words(words: [u64; 4]) -> Self {
+        Self(words)
+    }
+
+    /// Same as `From<u64>`, which is ambiguous with `NumCast::from` whenever `num` is in scope
+    pub const fn from_u64(value: u64) -> Self {
+        Self([0, 0, 0, value])
+    }
+
+    pub const fn from_u128(value: u128) -> Self {
+        Self([0, 0, (value >> 64) as u64, value as u64])
+    }
+
+    pub const fn words(&self) -> [u64; 4] {
+        self.0
+    }
+
+    /// From a big-endian byte string, such as a hash digest
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut words = [0; 4];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+            *word = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+
+        Self(words)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(self.0.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Words from the least to the most significant, which is the order arithmetic carries in
+    fn little(self) -> [u64; 4] {
+        let [a, b, c, d] = self.0;
+        [d, c, b, a]
+    }
+
+    fn from_little([a, b, c, d]: [u64; 4]) -> Self {
+        Self([d, c, b, a])
+    }
+
+    /// The value if it fits into a `u128`
+    fn as_u128(self) -> Option<u128> {
+        match self.0 {
+            [0, 0, high, low] => Some((high as u128) << 64 | low as u128),
+            _ => None,
+        }
+    }
+
+    pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        let (a, b) = (self.little(), rhs.little());
+        let mut result = [0; 4];
+        let mut carry = false;
+
+        for i in 0..4 {
+            let (sum, first) = a[i].overflowing_add(b[i]);
+            let (sum, second) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = first || second;
+        }
+
+        (Self::from_little(result), carry)
+    }
+
+    pub fn overflowing_sub(self, rhs: Self) -> (Self, bool) {
+        let (a, b) = (self.little(), rhs.little());
+        let mut result = [0; 4];
+        let mut borrow = false;
+
+        for i in 0..4 {
+            let (difference, first) = a[i].overflowing_sub(b[i]);
+            let (difference, second) = difference.overflowing_sub(borrow as u64);
+            result[i] = difference;
+            borrow = first || second;
+        }
+
+        (Self::from_little(result), borrow)
+    }
+
+    pub fn overflowing_mul(self, rhs: Self) -> (Self, bool) {
+        let (a, b) = (self.little(), rhs.little());
+
+        // Schoolbook multiplication into the full 512-bit product, widening every word product
+        let mut product = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+
+            for j in 0..4 {
+                let wide = a[i] as u128 * b[j] as u128 + product[i + j] as u128 + carry;
+                product[i + j] = wide as u64;
+                carry = wide >> 64;
+            }
+
+            product[i + 4] = carry as u64;
+        }
+
+        let low = [product[0], product[1], product[2], product[3]];
+        let overflow = product[4..].iter().any(|&word| word != 0);
+
+        (Self::from_little(low), overflow)
+    }
+
+    /// Quotient and remainder, panicking on division by zero like the primitive integers
+    pub fn div_rem(self, rhs: Self) -> (Self, Self) {
+        assert!(!rhs.is_zero(), "attempt to divide by zero");
+
+        if let (Some(a), Some(b)) = (self.as_u128(), rhs.as_u128()) {
+            return ((a / b).into(), (a % b).into());
+        }
+
+        // Shift and subtract, one bit at a time
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+
+        for bit in (0..Self::BITS - self.leading_zeros()).rev() {
+            let carry = remainder.bit(Self::BITS - 1);
+            remainder = remainder << 1;
+
+            if self.bit(bit) {
+                remainder.0[3] |= 1;
+            }
+
+            if carry || remainder >= rhs {
+                remainder = remainder.overflowing_sub(rhs).0;
+                quotient.set_bit(bit);
+            }
+        }
+
+        (quotient, remainder)
+    }
+
+    fn bit(&self, index: u32) -> bool {
+        self.0[3 - (index / 64) as usize] >> (index % 64) & 1 == 1
+    }
+
+    fn set_bit(&mut self, index: u32) {
+        self.0[3 - (index / 64) as usize] |= 1 << (index % 64);
+    }
+
+    /// Convert from a float, truncating the fractional part
+    fn from_f64(value: f64) -> Option<Self> {
+        let value = num::Float::trunc(value);
+        if !(0.0..num::Float::powi(2f64, Self::BITS as i32)).contains(&value) {
+            return None;
+        }
+
+        if value < num::Float::powi(2f64, 64) {
+            return Some((value as u64).into());
+        }
+
+        // Every float above 2^64 is an integer mantissa scaled by a positive power of two
+        let bits = value.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as usize - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+
+        Some(Self::from_u64(mantissa) << exponent)
+    }
+
+    /// Convert into a float. The leading 64 bits are rounded on their own and then scaled, which
+    /// keeps the conversion monotone.
+    fn to_f64_monotone(self) -> f64 {
+        let significant = Self::BITS - self.leading_zeros();
+        if significant <= 64 {
+            return self.0[3] as f64;
+        }
+
+        let shift = significant - 64;
+        (self >> shift as usize).0[3] as f64 * num::Float::powi(2f64, shift as i32)
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        Self::from_u64(value)
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        Self::from_u128(value)
+    }
+}
+
+impl KeyBounded<U256> for U256 {
+    fn lower_bound(&self) -> KeyBound<&U256> {
+        KeyBound::Key(self)
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(value) = self.as_u128() {
+            return fmt::Display::fmt(&value, f);
+        }
+
+        // Peel off 19 decimal digits at a time, the most which fit into a word
+        let chunk = Self::from_u64(10u64.pow(19));
+        let mut chunks = Vec::new();
+        let mut rest = *self;
+
+        while !rest.is_zero() {
+            let (quotient, remainder) = rest.div_rem(chunk);
+            chunks.push(remainder.0[3]);
+            rest = quotient;
+        }
+
+        let mut digits = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            digits.push_str(&format!("{:019}", chunk));
+        }
+
+        f.pad_integral(true, "", &digits)
+    }
+}
+
+/// Error returned when parsing a `U256` fails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseU256Error;
+
+impl fmt::Display for ParseU256Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid 256-bit integer")
+    }
+}
+
+impl core::error::Error for ParseU256Error {}
+
+impl core::str::FromStr for U256 {
+    type Err = ParseU256Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_radix(s, 10)
+    }
+}
+
+// ----------------------------------------
+// Operators
+// ----------------------------------------
+
+impl Add for U256 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.checked_add(&rhs)
+            .expect("attempt to add with overflow")
+    }
+}
+
+impl Sub for U256 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.checked_sub(&rhs)
+            .expect("attempt to subtract with overflow")
+    }
+}
+
+impl Mul for U256 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        self.checked_mul(&rhs)
+            .expect("attempt to multiply with overflow")
+    }
+}
+
+impl Div for U256 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        self.div_rem(rhs).0
+    }
+}
+
+impl Rem for U256 {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self {
+        self.div_rem(rhs).1
+    }
+}
+
+impl Not for U256 {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0.map(|word| !word))
+    }
+}
+
+macro_rules! impl_bitwise {
+    ($($trait:ident, $fn:ident, $op:tt);+) => {
+        $(
+            impl $trait for U256 {
+                type Output = Self;
+
+                fn $fn(self, rhs: Self) -> Self {
+                    let mut words = self.0;
+                    for (word, rhs) in words.iter_mut().zip(rhs.0) {
+                        *word $op rhs;
+                    }
+
+                    Self(words)
+                }
+            }
+        )+
+    }
+}
+
+impl_bitwise!(BitAnd, bitand, &=; BitOr, bitor, |=; BitXor, bitxor, ^=);
+
+impl Shl<usize> for U256 {
+    type Output = Self;
+
+    fn shl(self, shift: usize) -> Self {
+        assert!(shift < 256, "attempt to shift left with overflow");
+
+        let (words, bits) = (shift / 64, shift % 64);
+        let little = self.little();
+        let mut result = [0; 4];
+
+        for i in words..4 {
+            result[i] = little[i - words] << bits;
+            if bits > 0 && i > words {
+                result[i] |= little[i - words - 1] >> (64 - bits);
+            }
+        }
+
+        Self::from_little(result)
+    }
+}
+
+impl Shr<usize> for U256 {
+    type Output = Self;
+
+    fn shr(self, shift: usize) -> Self {
+        assert!(shift < 256, "attempt to shift right with overflow");
+
+        let (words, bits) = (shift / 64, shift % 64);
+        let little = self.little();
+        let mut result = [0; 4];
+
+        for i in 0..4 - words {
+            result[i] = little[i + words] >> bits;
+            if bits > 0 && i + words + 1 < 4 {
+                result[i] |= little[i + words + 1] << (64 - bits);
+            }
+        }
+
+        Self::from_little(result)
+    }
+}
+
+// ----------------------------------------
+// Numeric traits
+// ----------------------------------------
+
+impl Zero for U256 {
+    fn zero() -> Self {
+        Self::ZERO
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+impl One for U256 {
+    fn one() -> Self {
+        Self::ONE
+    }
+}
+
+impl Bounded for U256 {
+    fn min_value() -> Self {
+        Self::ZERO
+    }
+
+    fn max_value() -> Self {
+        Self::MAX
+    }
+}
+
+impl Num for U256 {
+    type FromStrRadixErr = ParseU256Error;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        if s.is_empty() || !(2..=36).contains(&radix) {
+            return Err(ParseU256Error);
+        }
+
+        let radix_wide = Self::from_u64(radix as u64);
+        s.chars().try_fold(Self::ZERO, |value, digit| {
+            let digit = digit.to_digit(radix).ok_or(ParseU256Error)?;
+
+            value
+                .checked_mul(&radix_wide)
+                .and_then(|value| value.checked_add(&Self::from_u64(digit as u64)))
+                .ok_or(ParseU256Error)
+        })
+    }
+}
+
+impl CheckedAdd for U256 {
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        match self.overflowing_add(*rhs) {
+            (result, false) => Some(result),
+            (_, true) => None,
+        }
+    }
+}
+
+impl CheckedSub for U256 {
+    fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        match self.overflowing_sub(*rhs) {
+            (result, false) => Some(result),
+            (_, true) => None,
+        }
+    }
+}
+
+impl CheckedMul for U256 {
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        match self.overflowing_mul(*rhs) {
+            (result, false) => Some(result),
+            (_, true) => None,
+        }
+    }
+}
+
+impl CheckedDiv for U256 {
+    fn checked_div(&self, rhs: &Self) -> Option<Self> {
+        (!rhs.is_zero()).then(|| self.div_rem(*rhs).0)
+    }
+}
+
+impl Saturating for U256 {
+    fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(&rhs).unwrap_or(Self::MAX)
+    }
+
+    fn saturating_sub(self, rhs: Self) -> Self {
+        self.checked_sub(&rhs).unwrap_or(Self::ZERO)
+    }
+}
+
+impl ToPrimitive for U256 {
+    fn to_i64(&self) -> Option<i64> {
+        self.as_u128()?.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.as_u128()?.to_u64()
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        self.as_u128()?.to_i128()
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        self.as_u128()
+    }
+
+    fn to_f32(&self) -> Option<f32> {
+        Some(self.to_f64_monotone() as f32)
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.to_f64_monotone())
+    }
+}
+
+impl NumCast for U256 {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        match n.to_u128() {
+            Some(value) => Some(value.into()),
+            None => Self::from_f64(n.to_f64()?),
+        }
+    }
+}
+
+impl PrimInt for U256 {
+    fn count_ones(self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn count_zeros(self) -> u32 {
+        Self::BITS - self.count_ones()
+    }
+
+    fn leading_zeros(self) -> u32 {
+        match self.0.iter().position(|&word| word != 0) {
+            Some(index) => index as u32 * 64 + self.0[index].leading_zeros(),
+            None => Self::BITS,
+        }
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        match self.little().iter().position(|&word| word != 0) {
+            Some(index) => index as u32 * 64 + self.little()[index].trailing_zeros(),
+            None => Self::BITS,
+        }
+    }
+
+    fn rotate_left(self, n: u32) -> Self {
+        match n % Self::BITS {
+            0 => self,
+            n => self << n as usize | self >> (Self::BITS - n) as usize,
+        }
+    }
+
+    fn rotate_right(self, n: u32) -> Self {
+        self.rotate_left(Self::BITS - n % Self::BITS)
+    }
+
+    fn signed_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    /// Shift as if the value was signed, filling in ones when the top bit is set
+    fn signed_shr(self, n: u32) -> Self {
+        if self.bit(Self::BITS - 1) {
+            !(!self >> n as usize)
+        } else {
+            self >> n as usize
+        }
+    }
+
+    fn unsigned_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    fn unsigned_shr(self, n: u32) -> Self {
+        self >> n as usize
+    }
+
+    fn swap_bytes(self) -> Self {
+        Self::from_little(self.0.map(u64::swap_bytes))
+    }
+
+    fn from_be(x: Self) -> Self {
+        x.to_be()
+    }
+
+    fn from_le(x: Self) -> Self {
+        x.to_le()
+    }
+
+    fn to_be(self) -> Self {
+        if cfg!(target_endian = "big") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn to_le(self) -> Self {
+        if cfg!(target_endian = "little") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn pow(self, exp: u32) -> Self {
+        num::traits::pow(self, exp as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use learned_index_segmentation::{LinearModel, SegmentationModel};
+
+    fn big(words: [u64; 4]) -> U256 {
+        U256::from_words(words)
+    }
+
+    #[test]
+    fn u256_arithmetic() {
+        let a = big([0, 1, u64::MAX, u64::MAX]);
+        assert_eq!(a + U256::ONE, big([0, 2, 0, 0]));
+        assert_eq!(big([0, 2, 0, 0]) - U256::ONE, a);
+        assert_eq!(U256::MAX.checked_add(&U256::ONE), None);
+        assert_eq!(U256::ZERO.saturating_sub(U256::ONE), U256::ZERO);
+
+        let b = U256::from_u128(u128::MAX);
+        assert_eq!(b * b, (b << 128) - b);
+        assert_eq!(U256::MAX.checked_mul(&U256::from_u64(2)), None);
+
+        let c = big([7, 3, 5, 11]);
+        let d = big([0, 0, 9, 13]);
+        let (quotient, remainder) = c.div_rem(d);
+        assert_eq!(quotient * d + remainder, c);
+        assert!(remainder < d);
+
+        assert_eq!(
+            U256::from_u64(3).pow(161) / U256::from_u64(3).pow(160),
+            U256::from_u64(3)
+        );
+    }
+
+    #[test]
+    fn u256_bits() {
+        let a = big([1, 0, 0, 1 << 63]);
+        assert_eq!(a.leading_zeros(), 63);
+        assert_eq!(a.trailing_zeros(), 63);
+        assert_eq!(a.count_ones(), 2);
+        assert_eq!(a << 1, big([2, 0, 1, 0]));
+        assert_eq!(a >> 64, big([0, 1, 0, 0]));
+        assert_eq!(a.rotate_left(1), big([2, 0, 1, 0]) | U256::ZERO);
+        assert_eq!(U256::MAX.signed_shr(100), U256::MAX);
+        assert_eq!(U256::from_be_bytes(a.to_be_bytes()), a);
+    }
+
+    #[test]
+    fn u256_conversions() {
+        let a = big([0, 0, 1, 0]);
+        assert_eq!(a.to_string(), (1u128 << 64).to_string());
+        assert_eq!(U256::MAX.to_string().len(), 78);
+        assert_eq!(U256::MAX.to_string().parse::<U256>(), Ok(U256::MAX));
+        assert_eq!(U256::from_str_radix("ff", 16), Ok(U256::from_u64(255)));
+
+        assert_eq!(num::cast::<_, U256>(-1i64), None);
+        assert_eq!(num::cast::<_, U256>(2f64.powi(200)), Some(U256::ONE << 200));
+        assert_eq!(num::cast::<_, f64>(U256::ONE << 200), Some(2f64.powi(200)));
+
+        // Converting into a float never reorders keys
+        let keys: Vec<U256> = (0..256)
+            .map(|shift| (U256::ONE << shift) - U256::ONE)
+            .collect();
+        let floats: Vec<f64> = keys.iter().map(|key| key.to_f64().unwrap()).collect();
+        assert!(floats.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn u256_linear_model() {
+        // Keys far apart at the top of the key space, but close to each other
+        let base = big([u64::MAX, 0, 0, 0]);
+        let keys: Vec<U256> = (0..10_000u64)
+            .map(|i| base + U256::from_u64(i * 1_000))
+            .collect();
+
+        let trained = LinearModel::<U256, 8>::train(keys.iter().map(|key| (*key, ())));
+        assert_eq!(trained.len(), 1);
+
+        let (model, _) = &trained[0];
+        for (rank, key) in keys.iter().enumerate() {
+            assert!(model.approximate(key).contains(&rank));
+        }
+    }
+}
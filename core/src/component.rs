@@ -1,6 +1,14 @@
+#[cfg(feature = "std")]
 use crate::common::storage::GlobalStore;
+use crate::explain::Probe;
 use crate::node_layer::NodeLayer;
+#[cfg(feature = "std")]
+use crate::paging::{CursorPage, CursorToken};
+#[cfg(feature = "std")]
+use crate::projection::{project, FieldSelector, Projectable};
 use crate::traits::*;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
 
 pub enum PropagateInsert<K, SA, PA> {
     /// Insert a single newly created node into the layer
@@ -10,6 +18,19 @@ pub enum PropagateInsert<K, SA, PA> {
     Replace(PA, PA),
 }
 
+/// The outcome of inserting an entry into a base component
+pub struct BaseInsert<K, V, SA, PA> {
+    /// The value the key held before the insert, if any
+    pub previous: Option<V>,
+
+    /// Change to the layer above, if the insert restructured the base layer
+    pub propagate: Option<PropagateInsert<K, SA, PA>>,
+}
+
+/// The previous value of the key inserted into a base layer, and the key, address and parent of a
+/// node split off by the insert
+pub type LayerInsert<K, V, SA, PA> = (Option<V>, Option<(K, SA, PA)>);
+
 pub trait TopComponent<K, Base, BA, SA>
 where
     Base: NodeLayer<K, BA, SA>,
@@ -19,6 +40,9 @@ where
 {
     fn search(&self, base: &Base, key: &K) -> BA;
 
+    /// The work done by `search`
+    fn probe(&self, base: &Base, key: &K) -> Probe;
+
     fn insert(&mut self, base: &mut Base, prop: PropagateInsert<K, BA, SA>);
 
     fn build(base: &mut Base) -> Self;
@@ -35,6 +59,9 @@ where
 {
     fn search(&self, base: &Base, ptr: SA, key: &K) -> BA;
 
+    /// The work done by `search`
+    fn probe(&self, base: &Base, ptr: SA, key: &K) -> Probe;
+
     fn insert(
         &mut self,
         base: &mut Base,
@@ -44,6 +71,76 @@ where
     fn build(base: &mut Base) -> Self;
 }
 
+/// Nodes of an internal layer built ahead of time from the layer below, see `RebuildComponent`
+pub struct RebuildPlan<N> {
+    pub(crate) nodes: Vec<N>,
+
+    /// Number of nodes in the layer below when the plan was made
+    base_nodes: usize,
+}
+
+impl<N> RebuildPlan<N> {
+    pub(crate) fn new(nodes: Vec<N>, base_nodes: usize) -> Self {
+        Self { nodes, base_nodes }
+    }
+
+    /// Number of nodes the rebuilt layer will hold
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Number of nodes in the layer below when the plan was made
+    pub fn base_nodes(&self) -> usize {
+        self.base_nodes
+    }
+
+    /// Nodes are never removed from an in-memory layer, so a plan only goes stale once the layer
+    /// below splits a node, and the new node has no parent in the plan
+    pub(crate) fn check<K, B: NodeLayer<K, BA, SA>, BA: Address, SA: Address>(
+        &self,
+        base: &B,
+    ) -> crate::Result<()> {
+        if base.node_count() != self.base_nodes {
+            return Err(anyhow::anyhow!(
+                "Rebuild plan is stale, the layer below has {} nodes instead of {}!",
+                base.node_count(),
+                self.base_nodes
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// An internal component whose layer can be rebuilt in two phases. `plan_rebuild` only reads the
+/// layer below, so it can run on another thread alongside searches, while `apply_rebuild` only
+/// needs exclusive access for as long as it takes to link the planned nodes. Every node of the
+/// layer is replaced, so the component above has to be built again afterwards.
+pub trait RebuildComponent<K, Base, BA, SA, PA>: InternalComponent<K, Base, BA, SA, PA>
+where
+    Base: NodeLayer<K, BA, SA>,
+    BA: Address,
+    SA: Address,
+    PA: Address,
+    K: Key,
+{
+    type Node;
+
+    fn plan_rebuild(&self, base: &Base) -> RebuildPlan<Self::Node>;
+
+    /// Install a plan, which fails if the layer below changed since it was planned
+    fn apply_rebuild(
+        &mut self,
+        base: &mut Base,
+        plan: RebuildPlan<Self::Node>,
+    ) -> crate::Result<()>;
+}
+
+#[cfg(feature = "std")]
 pub trait BoundaryDiskInternalComponent<K, Base, BA, SA, PA>
 where
     Self: NodeLayer<K, SA, PA> + Sized,
@@ -55,6 +152,9 @@ where
 {
     fn search(&self, base: &Base, ptr: SA, key: &K) -> crate::Result<BA>;
 
+    /// The work done by `search`
+    fn probe(&self, base: &Base, ptr: SA, key: &K) -> crate::Result<Probe>;
+
     fn insert(
         &mut self,
         base: &mut Base,
@@ -62,8 +162,13 @@ where
     ) -> crate::Result<Option<PropagateInsert<K, SA, PA>>>;
 
     fn load(base: &mut Base, store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self>;
+
+    /// Drop every change which wasn't written out, after the batch of writes it went to was
+    /// aborted, and link the layer to `base` again as `load` does
+    fn reload(&mut self, base: &mut Base) -> crate::Result<()>;
 }
 
+#[cfg(feature = "std")]
 pub trait DeepDiskInternalComponent<K, Base, BA, SA, PA>
 where
     Self: NodeLayer<K, SA, PA> + Sized,
@@ -75,6 +180,9 @@ where
 {
     fn search(&self, base: &Base, ptr: SA, key: &K) -> crate::Result<BA>;
 
+    /// The work done by `search`
+    fn probe(&self, base: &Base, ptr: SA, key: &K) -> crate::Result<Probe>;
+
     fn insert(
         &mut self,
         base: &mut Base,
@@ -82,6 +190,10 @@ where
     ) -> crate::Result<Option<PropagateInsert<K, SA, PA>>>;
 
     fn load(base: &mut Base, store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self>;
+
+    /// Drop every change which wasn't written out, after the batch of writes it went to was
+    /// aborted, and link the layer to `base` again as `load` does
+    fn reload(&mut self, base: &mut Base) -> crate::Result<()>;
 }
 
 pub trait BaseComponent<K, V, SA, PA>
@@ -91,15 +203,89 @@ where
     PA: Address,
     K: Key,
 {
-    fn insert(&mut self, ptr: SA, key: K, value: V) -> Option<PropagateInsert<K, SA, PA>>;
+    fn insert(&mut self, ptr: SA, key: K, value: V) -> BaseInsert<K, V, SA, PA>;
 
     fn search(&self, ptr: SA, key: &K) -> Option<V>;
 
+    /// The value stored at `key` in the node at `ptr`, without cloning it
+    fn get(&self, ptr: SA, key: &K) -> Option<&V>;
+
+    /// The value stored at `key` in the node at `ptr`, to be modified in place
+    fn get_mut(&mut self, ptr: SA, key: &K) -> Option<&mut V>;
+
+    /// The work done by `search`
+    fn probe(&self, ptr: SA, key: &K) -> Probe;
+
+    /// Whether inserting `key` into the node at `ptr` is certain not to propagate to the layer
+    /// above, by default no insert is assumed to be
+    fn absorbs(&self, _ptr: SA, _key: &K) -> bool {
+        false
+    }
+
+    /// Number of entries in the layer, counted as they are inserted and removed
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn empty() -> Self;
 
     fn build(iter: impl Iterator<Item = (K, V)>) -> Self;
 }
 
+/// A base component which stores the key of every entry, so that lookups can borrow it along with
+/// the value
+pub trait EntryComponent<K, V, SA, PA>: BaseComponent<K, V, SA, PA>
+where
+    SA: Address,
+    PA: Address,
+    K: Key,
+{
+    /// The key and value stored at `key` in the node at `ptr`, without cloning them
+    fn get_entry(&self, ptr: SA, key: &K) -> Option<(&K, &V)>;
+}
+
+/// Positional access to the entries of every node of a base layer, which cursors walk along. The
+/// entries of a node are indexed in key order.
+pub trait CursorComponent<K, V, SA, PA>: NodeLayer<K, SA, PA>
+where
+    SA: Address,
+    PA: Address,
+{
+    /// Number of entries in the node at `ptr`
+    fn entry_count(&self, ptr: SA) -> usize;
+
+    fn entry(&self, ptr: SA, index: usize) -> (&K, &V);
+
+    fn value_mut(&mut self, ptr: SA, index: usize) -> &mut V;
+
+    /// Index of the first entry of the node at `ptr` whose key is at least `key`
+    fn position(&self, ptr: SA, key: &K) -> usize;
+
+    /// Remove the entry at `index` of the node at `ptr`. The node keeps its lower bound, which the
+    /// layers above route by, even if that was the key removed.
+    fn remove(&mut self, ptr: SA, index: usize) -> (K, V);
+}
+
+/// An in-memory component whose nodes can be laid out again in list order, see
+/// `compact_memory`. Every node moves to a new address, so the layers around it are pointed at the
+/// new addresses through `remap_parents` and `RemapComponent::remap_children`.
+pub trait CompactComponent<SA, PA> {
+    /// Lay the nodes out contiguously in list order, returning where every node moved
+    fn compact(&mut self) -> HashMap<SA, SA>;
+
+    /// Point the parent of every node at where it moved when the layer above was compacted
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>);
+}
+
+/// A component holding the addresses of the nodes of a compacted layer below it
+pub trait RemapComponent<BA> {
+    /// Point every address into the layer below at where its node moved
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>);
+}
+
+#[cfg(feature = "std")]
 pub trait BoundaryDiskBaseComponent<K, V, SA, PA>
 where
     Self: NodeLayer<K, SA, PA> + Sized,
@@ -107,18 +293,74 @@ where
     PA: Address,
     K: Key,
 {
-    fn insert(
-        &mut self,
-        ptr: SA,
-        key: K,
-        value: V,
-    ) -> crate::Result<Option<PropagateInsert<K, SA, PA>>>;
+    fn insert(&mut self, ptr: SA, key: K, value: V) -> crate::Result<BaseInsert<K, V, SA, PA>>;
 
     fn search(&self, ptr: SA, key: &K) -> crate::Result<Option<V>>;
 
+    /// The fields picked by `S` of the value of `key`. By default the whole value is searched for,
+    /// components which can read fields straight from their pages should do so instead.
+    fn search_project<S>(&self, ptr: SA, key: &K) -> crate::Result<Option<S::Output>>
+    where
+        V: Projectable,
+        S: FieldSelector<V>,
+    {
+        Ok(self.search(ptr, key)?.as_ref().map(project::<V, S>))
+    }
+
+    /// Whether `key` is present. By default the whole value is searched for, components which can
+    /// stop at the keys of their pages should do so instead.
+    fn contains(&self, ptr: SA, key: &K) -> crate::Result<bool> {
+        Ok(self.search(ptr, key)?.is_some())
+    }
+
+    /// The values of `keys`, all of which the node at `ptr` covers. By default every key is
+    /// searched for on its own, components should read the page of the node only once instead.
+    fn search_node(&self, ptr: SA, keys: &[K]) -> crate::Result<Vec<Option<V>>> {
+        keys.iter()
+            .map(|key| self.search(ptr.clone(), key))
+            .collect()
+    }
+
+    /// The work done by `search`
+    fn probe(&self, ptr: SA, key: &K) -> crate::Result<Probe>;
+
+    /// Number of entries in the layer, kept in its catalog so that it survives restarts
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set the number of entries `len` reports, for indexes whose values don't all count as
+    /// entries, such as tombstones
+    fn set_len(&mut self, len: usize);
+
+    /// Remove every entry whose value is rejected by `keep`, returning the number of entries
+    /// removed. A node whose smallest key is removed keeps it as its lower bound, since the layers
+    /// above route to the node by it. The count reported by `len` is left to the caller, since
+    /// the values removed need not have counted as entries.
+    fn retain(&mut self, keep: impl Fn(&V) -> bool) -> crate::Result<usize>;
+
+    /// Fill an empty layer with entries in ascending key order, without duplicates
+    fn fill(&mut self, entries: impl Iterator<Item = crate::Result<(K, V)>>) -> crate::Result<()>;
+
+    /// Up to `limit` entries following the key of `token` in key order, read on from the node at
+    /// `ptr`, which covers that key
+    fn page(
+        &self,
+        ptr: SA,
+        token: &CursorToken<K, SA>,
+        limit: usize,
+    ) -> crate::Result<CursorPage<K, V, SA>>;
+
     fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self>;
+
+    /// Drop every change which wasn't written out, after the batch of writes it went to was
+    /// aborted. The layers above have to be linked to it again.
+    fn reload(&mut self) -> crate::Result<()>;
 }
 
+#[cfg(feature = "std")]
 pub trait DeepDiskBaseComponent<K, V, SA, PA>
 where
     Self: NodeLayer<K, SA, PA> + Sized,
@@ -126,14 +368,69 @@ where
     PA: Persisted + Address,
     K: Key,
 {
-    fn insert(
-        &mut self,
-        ptr: SA,
-        key: K,
-        value: V,
-    ) -> crate::Result<Option<PropagateInsert<K, SA, PA>>>;
+    fn insert(&mut self, ptr: SA, key: K, value: V) -> crate::Result<BaseInsert<K, V, SA, PA>>;
 
     fn search(&self, ptr: SA, key: &K) -> crate::Result<Option<V>>;
 
+    /// The fields picked by `S` of the value of `key`. By default the whole value is searched for,
+    /// components which can read fields straight from their pages should do so instead.
+    fn search_project<S>(&self, ptr: SA, key: &K) -> crate::Result<Option<S::Output>>
+    where
+        V: Projectable,
+        S: FieldSelector<V>,
+    {
+        Ok(self.search(ptr, key)?.as_ref().map(project::<V, S>))
+    }
+
+    /// Whether `key` is present. By default the whole value is searched for, components which can
+    /// stop at the keys of their pages should do so instead.
+    fn contains(&self, ptr: SA, key: &K) -> crate::Result<bool> {
+        Ok(self.search(ptr, key)?.is_some())
+    }
+
+    /// The values of `keys`, all of which the node at `ptr` covers. By default every key is
+    /// searched for on its own, components should read the page of the node only once instead.
+    fn search_node(&self, ptr: SA, keys: &[K]) -> crate::Result<Vec<Option<V>>> {
+        keys.iter()
+            .map(|key| self.search(ptr.clone(), key))
+            .collect()
+    }
+
+    /// The work done by `search`
+    fn probe(&self, ptr: SA, key: &K) -> crate::Result<Probe>;
+
+    /// Number of entries in the layer, kept in its catalog so that it survives restarts
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set the number of entries `len` reports, for indexes whose values don't all count as
+    /// entries, such as tombstones
+    fn set_len(&mut self, len: usize);
+
+    /// Remove every entry whose value is rejected by `keep`, returning the number of entries
+    /// removed. A node whose smallest key is removed keeps it as its lower bound, since the layers
+    /// above route to the node by it. The count reported by `len` is left to the caller, since
+    /// the values removed need not have counted as entries.
+    fn retain(&mut self, keep: impl Fn(&V) -> bool) -> crate::Result<usize>;
+
+    /// Fill an empty layer with entries in ascending key order, without duplicates
+    fn fill(&mut self, entries: impl Iterator<Item = crate::Result<(K, V)>>) -> crate::Result<()>;
+
+    /// Up to `limit` entries following the key of `token` in key order, read on from the node at
+    /// `ptr`, which covers that key
+    fn page(
+        &self,
+        ptr: SA,
+        token: &CursorToken<K, SA>,
+        limit: usize,
+    ) -> crate::Result<CursorPage<K, V, SA>>;
+
     fn load(store: &mut GlobalStore, ident: impl ToString) -> crate::Result<Self>;
+
+    /// Drop every change which wasn't written out, after the batch of writes it went to was
+    /// aborted. The layers above have to be linked to it again.
+    fn reload(&mut self) -> crate::Result<()>;
 }
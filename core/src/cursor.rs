@@ -0,0 +1,422 @@
+//! Cursors over the entries of an in-memory index, in key order. A cursor points either at an
+//! entry, or at the "ghost" position past the last entry, from which moving forwards wraps around
+//! to the first entry and moving backwards to the last. Cursors walk the linked nodes of the base
+//! layer directly, and only descend from the top to find the node they start in.
+//!
+//! A `ScanHandle` is the forward-only counterpart to a cursor for consuming a scan in batches.
+//! Once it runs past the last entry it stays exhausted instead of wrapping around.
+
+use crate::component::CursorComponent;
+use crate::kv_store::KVStore;
+use crate::node_layer::NodeLayer;
+use crate::traits::{Address, Key, Value};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Implemented by generated indexes whose base layer can be walked by a cursor
+pub trait CursorIndex<K, V> {
+    type Address: Address;
+    type Parent: Address;
+    type Base: CursorComponent<K, V, Self::Address, Self::Parent>;
+
+    fn base(&self) -> &Self::Base;
+
+    fn base_mut(&mut self) -> &mut Self::Base;
+
+    /// The base node a search for `key` ends up in
+    fn locate(&self, key: &K) -> Self::Address;
+
+    /// Number of nodes in every layer below the top as `(layer, nodes)`, ordered from the base
+    /// layer up
+    fn node_counts(&self) -> Vec<(usize, usize)>;
+
+    /// Called once a `CursorMut` replaced the value of `key`, which held `previous`. Inserts go
+    /// through `KVStore::insert` instead, so only indexes which report their changes need this.
+    fn replaced(&self, _key: &K, _previous: &V, _value: &V) {}
+
+    /// Called once a `CursorMut` removed `key`, which held `previous`
+    fn removed(&self, _key: &K, _previous: &V) {}
+}
+
+/// Why a `CursorMut` refused to modify the index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorError {
+    /// The key doesn't fall strictly between the entries around the cursor
+    UnorderedKey,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::UnorderedKey => {
+                f.write_str("key does not fall between the entries around the cursor")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CursorError {}
+
+/// Returned by `compare_and_swap` when the index was left untouched
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CasError<V> {
+    /// The key held `actual` rather than the expected value, so `new` was not written
+    Mismatch { actual: Option<V>, new: Option<V> },
+}
+
+impl<V> fmt::Display for CasError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CasError::Mismatch { .. } => f.write_str("key does not hold the expected value"),
+        }
+    }
+}
+
+impl<V: fmt::Debug> core::error::Error for CasError<V> {}
+
+/// A node and the index of an entry within it, `None` is the ghost position
+type Position<SA> = Option<(SA, usize)>;
+
+/// The first entry at or after `index` in `node`, skipping over empty nodes
+fn forward<K, V, SA: Address, PA: Address>(
+    base: &impl CursorComponent<K, V, SA, PA>,
+    mut node: SA,
+    mut index: usize,
+) -> Position<SA> {
+    while index >= base.entry_count(node.clone()) {
+        node = base.next(node)?;
+        index = 0;
+    }
+
+    Some((node, index))
+}
+
+/// The last entry before `index` in `node`, skipping over empty nodes
+fn backward<K, V, SA: Address, PA: Address>(
+    base: &impl CursorComponent<K, V, SA, PA>,
+    mut node: SA,
+    mut index: usize,
+) -> Position<SA> {
+    while index == 0 {
+        node = base.prev(node)?;
+        index = base.entry_count(node.clone());
+    }
+
+    Some((node, index - 1))
+}
+
+fn seek<K, V, I: CursorIndex<K, V>>(index: &I, key: &K) -> Position<I::Address> {
+    let node = index.locate(key);
+    let position = index.base().position(node.clone(), key);
+
+    forward(index.base(), node, position)
+}
+
+fn next<K, V, I: CursorIndex<K, V>>(
+    index: &I,
+    position: &Position<I::Address>,
+) -> Position<I::Address> {
+    match position {
+        Some((node, entry)) => forward(index.base(), node.clone(), entry + 1),
+        None => forward(index.base(), index.base().first(), 0),
+    }
+}
+
+fn prev<K, V, I: CursorIndex<K, V>>(
+    index: &I,
+    position: &Position<I::Address>,
+) -> Position<I::Address> {
+    match position {
+        Some((node, entry)) => backward(index.base(), node.clone(), *entry),
+        None => {
+            let last = index.base().last();
+            let count = index.base().entry_count(last.clone());
+            backward(index.base(), last, count)
+        }
+    }
+}
+
+fn entry<'a, K, V, I: CursorIndex<K, V>>(
+    index: &'a I,
+    position: &Position<I::Address>,
+) -> Option<(&'a K, &'a V)> {
+    let (node, entry) = position.clone()?;
+    Some(index.base().entry(node, entry))
+}
+
+/// A read-only cursor over the entries of an index
+pub struct Cursor<'a, K, V, I: CursorIndex<K, V>> {
+    index: &'a I,
+    position: Position<I::Address>,
+    _ph: core::marker::PhantomData<(K, V)>,
+}
+
+impl<'a, K: Key, V: Value, I: CursorIndex<K, V>> Cursor<'a, K, V, I> {
+    /// A cursor at the first entry whose key is at least `key`
+    pub fn new(index: &'a I, key: &K) -> Self {
+        Self {
+            position: seek(index, key),
+            index,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// A cursor at the first entry of the index, or at the ghost position if it is empty
+    pub fn first(index: &'a I) -> Self {
+        Self {
+            position: next(index, &None),
+            index,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// A cursor at the last entry of the index, or at the ghost position if it is empty
+    pub fn last(index: &'a I) -> Self {
+        Self {
+            position: prev(index, &None),
+            index,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    pub fn key(&self) -> Option<&'a K> {
+        self.current().map(|(key, _)| key)
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        self.current().map(|(_, value)| value)
+    }
+
+    /// The entry the cursor points at, or `None` at the ghost position
+    pub fn current(&self) -> Option<(&'a K, &'a V)> {
+        entry(self.index, &self.position)
+    }
+
+    /// The entry after the one the cursor points at, without moving
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        entry(self.index, &next(self.index, &self.position))
+    }
+
+    /// The entry before the one the cursor points at, without moving
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        entry(self.index, &prev(self.index, &self.position))
+    }
+
+    pub fn move_next(&mut self) {
+        self.position = next(self.index, &self.position);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.position = prev(self.index, &self.position);
+    }
+}
+
+/// A scan over the entries of an index from a key onwards, consumed in batches with `next_n`. The
+/// handle remembers the base node it stopped in, so every batch reads on from there without
+/// descending from the top again.
+pub struct ScanHandle<'a, K, V, I: CursorIndex<K, V>> {
+    index: &'a I,
+    position: Position<I::Address>,
+    _ph: core::marker::PhantomData<(K, V)>,
+}
+
+impl<'a, K: Key, V: Value, I: CursorIndex<K, V>> ScanHandle<'a, K, V, I> {
+    /// A scan starting at the first entry whose key is at least `key`
+    pub fn new(index: &'a I, key: &K) -> Self {
+        Self {
+            position: seek(index, key),
+            index,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// The entry the next batch starts with, or `None` once the scan is exhausted
+    pub fn peek(&self) -> Option<(&'a K, &'a V)> {
+        entry(self.index, &self.position)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.position.is_none()
+    }
+
+    /// The next `n` entries of the scan, or fewer once it runs out of entries
+    pub fn next_n(&mut self, n: usize) -> Vec<(&'a K, &'a V)> {
+        let mut entries = Vec::with_capacity(n);
+
+        while entries.len() < n {
+            let Some(current) = entry(self.index, &self.position) else {
+                break;
+            };
+
+            entries.push(current);
+            self.position = next(self.index, &self.position);
+        }
+
+        entries
+    }
+}
+
+impl<'a, K: Key, V: Value, I: CursorIndex<K, V>> Iterator for ScanHandle<'a, K, V, I> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = entry(self.index, &self.position)?;
+        self.position = next(self.index, &self.position);
+
+        Some(current)
+    }
+}
+
+/// A cursor which can modify the index as it walks over its entries
+pub struct CursorMut<'a, K, V, I: CursorIndex<K, V>> {
+    index: &'a mut I,
+    position: Position<I::Address>,
+    _ph: core::marker::PhantomData<(K, V)>,
+}
+
+impl<'a, K: Key, V: Value, I: CursorIndex<K, V>> CursorMut<'a, K, V, I> {
+    /// A cursor at the first entry whose key is at least `key`
+    pub fn new(index: &'a mut I, key: &K) -> Self {
+        Self {
+            position: seek(index, key),
+            index,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.current().map(|(key, _)| key)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.current().map(|(_, value)| value)
+    }
+
+    /// The entry the cursor points at, or `None` at the ghost position
+    pub fn current(&self) -> Option<(&K, &V)> {
+        entry(self.index, &self.position)
+    }
+
+    /// The entry after the one the cursor points at, without moving
+    pub fn peek_next(&self) -> Option<(&K, &V)> {
+        entry(self.index, &next(self.index, &self.position))
+    }
+
+    /// The entry before the one the cursor points at, without moving
+    pub fn peek_prev(&self) -> Option<(&K, &V)> {
+        entry(self.index, &prev(self.index, &self.position))
+    }
+
+    pub fn move_next(&mut self) {
+        self.position = next(self.index, &self.position);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.position = prev(self.index, &self.position);
+    }
+
+    /// The value the cursor points at, which can be modified in place. Changes made in place
+    /// aren't reported to the index, use `replace_current` where they have to be.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let (node, entry) = self.position.clone()?;
+        Some(self.index.base_mut().value_mut(node, entry))
+    }
+
+    /// Write `value` to the entry the cursor points at, returning the value it held. Returns `None`
+    /// and writes nothing at the ghost position.
+    pub fn replace_current(&mut self, value: V) -> Option<V> {
+        let (node, entry) = self.position.clone()?;
+
+        let slot = self.index.base_mut().value_mut(node.clone(), entry);
+        let previous = core::mem::replace(slot, value);
+
+        let (key, value) = self.index.base().entry(node, entry);
+        self.index.replaced(key, &previous, value);
+
+        Some(previous)
+    }
+
+    /// Insert an entry right before the one the cursor points at, or after the last entry at the
+    /// ghost position. The key has to fall strictly between the entries around the cursor. The
+    /// insert goes through the index, so nodes are split as usual, and the cursor keeps pointing
+    /// at the same entry.
+    pub fn insert_before(&mut self, key: K, value: V) -> Result<(), CursorError>
+    where
+        I: KVStore<K, V>,
+    {
+        let current = self.key().copied();
+
+        let after_prev = self.peek_prev().is_none_or(|(prev, _)| *prev < key);
+        let before_current = current.is_none_or(|current| key < current);
+
+        if !(after_prev && before_current) {
+            return Err(CursorError::UnorderedKey);
+        }
+
+        self.index.insert(key, value);
+
+        if let Some(current) = current {
+            self.position = seek(self.index, &current);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the entry the cursor points at, and move to the next one. Returns `None` at the
+    /// ghost position. A base node which loses its smallest key keeps that key as its lower bound,
+    /// so the layers above route to it as before, even once it holds no entries.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let (node, entry) = self.position.clone()?;
+
+        let removed = self.index.base_mut().remove(node.clone(), entry);
+        self.position = forward(self.index.base(), node, entry);
+        self.index.removed(&removed.0, &removed.1);
+
+        Some(removed)
+    }
+
+    /// Write `new` to `key`, or remove `key` if `new` is `None`, only if the key holds `expected`,
+    /// where `None` expects the key to be absent. The cursor moves to the first entry at or after
+    /// `key` first, unless it already points there, as a cursor created at `key` does.
+    pub fn compare_and_swap(
+        &mut self,
+        key: K,
+        expected: Option<&V>,
+        new: Option<V>,
+    ) -> Result<(), CasError<V>>
+    where
+        I: KVStore<K, V>,
+        V: PartialEq,
+    {
+        let after_prev = self.peek_prev().is_none_or(|(prev, _)| *prev < key);
+        let at_or_after = self.key().is_none_or(|current| key <= *current);
+
+        if !(after_prev && at_or_after) {
+            self.position = seek(self.index, &key);
+        }
+
+        let actual = self
+            .current()
+            .filter(|&(found, _)| *found == key)
+            .map(|(_, value)| value.clone());
+
+        if actual.as_ref() != expected {
+            return Err(CasError::Mismatch { actual, new });
+        }
+
+        match (actual, new) {
+            (Some(_), Some(new)) => {
+                self.replace_current(new);
+            }
+            (None, Some(new)) => self
+                .insert_before(key, new)
+                .expect("the cursor points at the first entry after the key"),
+            (Some(_), None) => {
+                self.remove_current();
+            }
+            (None, None) => (),
+        }
+
+        Ok(())
+    }
+}
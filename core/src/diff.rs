@@ -0,0 +1,130 @@
+//! Differences between two indexes of the same layout, for checking that an index maintained
+//! incrementally ends up holding the same entries as one rebuilt from scratch. The base layers are
+//! walked side by side with a cursor each, in a single linear merge like the set operations.
+
+use crate::cursor::{Cursor, CursorIndex};
+use crate::traits::{Key, Value};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// Number of nodes a layer holds in either index, for a layer where they differ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerDiff {
+    /// Position of the layer in the layout, counting up from the base layer at 0
+    pub layer: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Everything `diff` found to differ between a left and a right index, in key order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffReport<K, V> {
+    /// Entries only the right index holds
+    pub added: Vec<(K, V)>,
+
+    /// Entries only the left index holds
+    pub removed: Vec<(K, V)>,
+
+    /// Keys both indexes hold, as `(key, left, right)`, where the values differ
+    pub changed: Vec<(K, V, V)>,
+
+    /// Layers below the top which hold a different number of nodes, ordered from the base up
+    pub layers: Vec<LayerDiff>,
+}
+
+impl<K, V> DiffReport<K, V> {
+    /// Whether both indexes hold the same entries, however they are laid out
+    pub fn same_entries(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Whether both indexes hold the same entries in the same number of nodes per layer
+    pub fn is_empty(&self) -> bool {
+        self.same_entries() && self.layers.is_empty()
+    }
+}
+
+impl<K, V> fmt::Display for DiffReport<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+
+        for layer in self.layers.iter() {
+            writeln!(
+                f,
+                "  layer {:<3} {} nodes -> {} nodes",
+                layer.layer, layer.left, layer.right
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare the entries and the shape of two indexes, reporting the changes which turn `left`
+/// into `right`
+pub fn diff<K, V, I>(left: &I, right: &I) -> DiffReport<K, V>
+where
+    K: Key,
+    V: Value + PartialEq,
+    I: CursorIndex<K, V>,
+{
+    let mut report = DiffReport {
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        layers: Vec::new(),
+    };
+
+    let mut lhs = Cursor::first(left);
+    let mut rhs = Cursor::first(right);
+
+    // Cursors wrap around past their last entry, so neither moves once it ran out
+    loop {
+        match (lhs.current(), rhs.current()) {
+            (Some((key, value)), Some((other, other_value))) => match key.cmp(other) {
+                Ordering::Less => {
+                    report.removed.push((*key, value.clone()));
+                    lhs.move_next();
+                }
+                Ordering::Greater => {
+                    report.added.push((*other, other_value.clone()));
+                    rhs.move_next();
+                }
+                Ordering::Equal => {
+                    if value != other_value {
+                        report
+                            .changed
+                            .push((*key, value.clone(), other_value.clone()));
+                    }
+                    lhs.move_next();
+                    rhs.move_next();
+                }
+            },
+            (Some((key, value)), None) => {
+                report.removed.push((*key, value.clone()));
+                lhs.move_next();
+            }
+            (None, Some((key, value))) => {
+                report.added.push((*key, value.clone()));
+                rhs.move_next();
+            }
+            (None, None) => break,
+        }
+    }
+
+    let counts = left.node_counts().into_iter().zip(right.node_counts());
+    for ((layer, left), (_, right)) in counts {
+        if left != right {
+            report.layers.push(LayerDiff { layer, left, right });
+        }
+    }
+
+    report
+}
@@ -0,0 +1,375 @@
+//! Detecting when inserted keys stop following the distribution the learned layers were trained
+//! on. A `DriftMonitor` wraps an index, keeps a fixed-size reservoir sample of the keys it was
+//! trained on and another of the keys inserted since, and measures the distance between the two
+//! with the two-sample Kolmogorov-Smirnov statistic. A drift close to 0 means inserts still look
+//! like the training keys, while a drift close to 1 means they barely overlap, and segments are
+//! likely to split or grow their error until the index is rebuilt.
+
+use crate::testkit::{TestRng, DEFAULT_SEED};
+use crate::{IndexRead, IndexWrite};
+
+/// Keys kept by each sample unless set with `with_sample_size`
+pub const DEFAULT_SAMPLE_SIZE: usize = 1024;
+
+/// A uniform sample of a stream of keys, of at most `capacity` keys
+#[derive(Clone, Debug)]
+pub struct KeySample<K> {
+    keys: Vec<K>,
+    capacity: usize,
+
+    /// Number of keys offered to the sample so far
+    seen: u64,
+}
+
+impl<K: Ord + Clone> KeySample<K> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "A sample must hold at least one key!");
+
+        Self {
+            keys: Vec::with_capacity(capacity),
+            capacity,
+            seen: 0,
+        }
+    }
+
+    /// Offer a key to the sample, which keeps it with probability `capacity / seen`
+    pub fn offer(&mut self, key: K, rng: &mut TestRng) {
+        self.seen += 1;
+
+        if self.keys.len() < self.capacity {
+            self.keys.push(key);
+        } else {
+            let slot = (rng.next_u64() % self.seen) as usize;
+
+            if slot < self.capacity {
+                self.keys[slot] = key;
+            }
+        }
+    }
+
+    /// Merge two samples into a sample of both streams, picking keys from each in proportion to
+    /// the number of keys it has seen
+    pub fn merge(self, other: Self, rng: &mut TestRng) -> Self {
+        let mut merged = Self::new(self.capacity);
+        merged.seen = self.seen + other.seen;
+
+        // Every key of a sample stands in for `seen / len` keys of its stream
+        let weight = |sample: &Self| sample.seen as f64 / sample.keys.len().max(1) as f64;
+        let (left_weight, right_weight) = (weight(&self), weight(&other));
+        let (mut left, mut right) = (self.keys, other.keys);
+
+        while merged.keys.len() < merged.capacity && !(left.is_empty() && right.is_empty()) {
+            let left_total = left.len() as f64 * left_weight;
+            let right_total = right.len() as f64 * right_weight;
+
+            let keys = if rng.chance(left_total / (left_total + right_total)) {
+                &mut left
+            } else {
+                &mut right
+            };
+
+            merged.keys.push(keys.swap_remove(rng.below(keys.len())));
+        }
+
+        merged
+    }
+
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// The largest distance between the empirical distributions of two samples, from 0 for samples
+/// drawn from the same distribution to 1 for samples which don't overlap at all
+pub fn ks_statistic<K: Ord + Clone>(left: &[K], right: &[K]) -> f64 {
+    if left.is_empty() || right.is_empty() {
+        return 0.0;
+    }
+
+    let (mut left, mut right) = (left.to_vec(), right.to_vec());
+    left.sort_unstable();
+    right.sort_unstable();
+
+    let (mut i, mut j) = (0, 0);
+    let mut max: f64 = 0.0;
+
+    while i < left.len() && j < right.len() {
+        // Step over every copy of the smallest key in either sample at once
+        let key = std::cmp::min(&left[i], &right[j]).clone();
+
+        while i < left.len() && left[i] <= key {
+            i += 1;
+        }
+
+        while j < right.len() && right[j] <= key {
+            j += 1;
+        }
+
+        let distance = (i as f64 / left.len() as f64 - j as f64 / right.len() as f64).abs();
+        max = max.max(distance);
+    }
+
+    max
+}
+
+/// Called with the drift once it exceeds the threshold given to `on_drift`
+type DriftCallback = Box<dyn FnMut(f64)>;
+
+/// An index which tracks how far its inserted keys drift from the keys its learned layers were
+/// trained on. Searches and inserts are forwarded to the wrapped index.
+pub struct DriftMonitor<I, K> {
+    index: I,
+
+    trained: KeySample<K>,
+    inserted: KeySample<K>,
+    rng: TestRng,
+
+    callback: Option<(f64, DriftCallback)>,
+
+    /// Whether the callback fired since the last `retrained`, so that it fires only once
+    fired: bool,
+
+    /// Inserts until the drift is next checked against the threshold
+    until_check: usize,
+}
+
+impl<I, K: Ord + Clone> DriftMonitor<I, K> {
+    /// Monitor an index whose learned layers were trained on `keys`, such as the keys of the
+    /// entries passed to `build`
+    pub fn new(index: I, keys: impl IntoIterator<Item = K>) -> Self {
+        Self::with_sample_size(index, keys, DEFAULT_SAMPLE_SIZE)
+    }
+
+    /// Like `new`, keeping `size` keys in each sample. Larger samples detect smaller shifts, at
+    /// the cost of memory and of slower checks.
+    pub fn with_sample_size(index: I, keys: impl IntoIterator<Item = K>, size: usize) -> Self {
+        let mut rng = TestRng::new(DEFAULT_SEED);
+        let mut trained = KeySample::new(size);
+
+        for key in keys {
+            trained.offer(key, &mut rng);
+        }
+
+        Self {
+            index,
+            trained,
+            inserted: KeySample::new(size),
+            rng,
+            callback: None,
+            fired: false,
+            until_check: Self::check_interval(size),
+        }
+    }
+
+    /// Call `callback` with the drift the first time it exceeds `threshold`. The drift is checked
+    /// periodically as keys are inserted, rather than on every insert, and only once the sample of
+    /// inserted keys is full.
+    pub fn on_drift(mut self, threshold: f64, callback: impl FnMut(f64) + 'static) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&threshold),
+            "Drift threshold must be within 0 and 1!"
+        );
+
+        self.callback = Some((threshold, Box::new(callback)));
+        self
+    }
+
+    /// Distance between the keys inserted since training and the keys trained on, see
+    /// `ks_statistic`. Until enough keys are inserted the sample is noisy, so the drift of a
+    /// handful of inserts should not be read into.
+    pub fn drift(&self) -> f64 {
+        ks_statistic(self.trained.keys(), self.inserted.keys())
+    }
+
+    /// Number of keys inserted since training
+    pub fn inserted(&self) -> u64 {
+        self.inserted.seen()
+    }
+
+    /// Record that the learned layers were rebuilt over every key inserted so far, which become
+    /// part of the distribution they were trained on
+    pub fn retrained(&mut self) {
+        let capacity = self.trained.capacity;
+        let trained = std::mem::replace(&mut self.trained, KeySample::new(capacity));
+        let inserted = std::mem::replace(&mut self.inserted, KeySample::new(capacity));
+
+        self.trained = trained.merge(inserted, &mut self.rng);
+        self.fired = false;
+        self.until_check = Self::check_interval(capacity);
+    }
+
+    /// The wrapped index. Writes have to go through the monitor, so it is only lent out
+    /// immutably.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// The wrapped index, for rebuilding it. Keys inserted through it aren't sampled.
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.index
+    }
+
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+
+    fn check_interval(size: usize) -> usize {
+        (size / 8).max(1)
+    }
+
+    fn record(&mut self, key: K) {
+        self.inserted.offer(key, &mut self.rng);
+
+        self.until_check -= 1;
+        if self.until_check > 0 {
+            return;
+        }
+
+        self.until_check = Self::check_interval(self.trained.capacity);
+
+        // A partial sample is too noisy to act on
+        if self.fired
+            || self.callback.is_none()
+            || self.inserted.keys.len() < self.inserted.capacity
+        {
+            return;
+        }
+
+        let drift = self.drift();
+        let (threshold, callback) = self.callback.as_mut().unwrap();
+
+        if drift > *threshold {
+            self.fired = true;
+            callback(drift);
+        }
+    }
+}
+
+impl<I, K, V> IndexRead<K, V> for DriftMonitor<I, K>
+where
+    I: IndexRead<K, V>,
+    K: Ord + Clone,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        self.index.try_search(key)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<I, K, V> IndexWrite<K, V> for DriftMonitor<I, K>
+where
+    I: IndexWrite<K, V>,
+    K: Ord + Clone,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        self.record(key.clone());
+        self.index.try_put(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Map(BTreeMap<u64, u64>);
+
+    impl IndexRead<u64, u64> for Map {
+        fn try_search(&self, key: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.get(&key).copied())
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl IndexWrite<u64, u64> for Map {
+        fn try_put(&mut self, key: u64, value: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.insert(key, value))
+        }
+    }
+
+    #[test]
+    fn ks_statistic_bounds() {
+        let low: Vec<u64> = (0..100).collect();
+        let high: Vec<u64> = (100..200).collect();
+        let even: Vec<u64> = (0..200).step_by(2).collect();
+
+        assert_eq!(ks_statistic(&low, &low), 0.0);
+        assert_eq!(ks_statistic(&low, &high), 1.0);
+        assert!((ks_statistic(&low, &even) - 0.5).abs() < 1e-9);
+        assert_eq!(ks_statistic::<u64>(&low, &[]), 0.0);
+    }
+
+    #[test]
+    fn key_sample_merge() {
+        let mut rng = TestRng::new(DEFAULT_SEED);
+        let (mut low, mut high) = (KeySample::new(100), KeySample::new(100));
+
+        for key in 0..9_000u64 {
+            low.offer(key, &mut rng);
+        }
+
+        for key in 0..1_000u64 {
+            high.offer(1_000_000 + key, &mut rng);
+        }
+
+        let merged = low.merge(high, &mut rng);
+        let high_keys = merged
+            .keys()
+            .iter()
+            .filter(|key| **key >= 1_000_000)
+            .count();
+
+        assert_eq!(merged.seen(), 10_000);
+        assert_eq!(merged.keys().len(), 100);
+        assert!((2..=25).contains(&high_keys));
+    }
+
+    #[test]
+    fn drift_monitor_callback() {
+        let fired = Rc::new(Cell::new(0));
+        let counter = fired.clone();
+
+        let mut index = DriftMonitor::with_sample_size(Map::default(), 0..10_000, 256)
+            .on_drift(0.5, move |_| counter.set(counter.get() + 1));
+
+        // Inserts spread over the trained keys don't drift
+        let mut rng = TestRng::new(DEFAULT_SEED);
+        for _ in 0..2_000 {
+            let key = rng.below(10_000) as u64;
+            index.try_put(key, key).unwrap();
+        }
+
+        assert!(index.drift() < 0.2);
+        assert_eq!(fired.get(), 0);
+
+        // Inserts past the trained keys do, which fires the callback once
+        for key in 20_000..30_000 {
+            index.try_put(key, key).unwrap();
+        }
+
+        assert!(index.drift() > 0.5);
+        assert_eq!(fired.get(), 1);
+        assert_eq!(index.try_search(25_000).unwrap(), Some(25_000));
+
+        index.retrained();
+        assert_eq!(index.drift(), 0.0);
+        assert_eq!(index.inserted(), 0);
+    }
+}
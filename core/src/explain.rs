@@ -0,0 +1,146 @@
+//! A layer by layer account of a single lookup, generated as `explain` on every index. Useful for
+//! finding out why a layout is slow for a particular key distribution: which layers are visited,
+//! which node each of them searched, and how much work the search in that node took.
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The work a component did to find `key` in one of its nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Probe {
+    /// Width of the approximation window of the model, only reported by learned components
+    pub window: Option<usize>,
+
+    /// Number of key comparisons made. The top component is a standard library map, which can't
+    /// be instrumented, so it reports the comparisons of a binary search over its entries instead.
+    pub comparisons: usize,
+}
+
+impl Probe {
+    pub fn counted(comparisons: usize) -> Self {
+        Self {
+            window: None,
+            comparisons,
+        }
+    }
+
+    /// Comparisons made by a binary search over `len` entries
+    pub fn binary_search(len: usize) -> Self {
+        Self::counted((usize::BITS - len.leading_zeros()) as usize)
+    }
+
+    /// Combine the work done in two successive searches
+    pub fn then(self, other: Probe) -> Self {
+        Self {
+            window: other.window.or(self.window),
+            comparisons: self.comparisons + other.comparisons,
+        }
+    }
+}
+
+/// A single layer visited by a lookup
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupStep<K> {
+    /// Counting up from the base layer
+    pub layer: usize,
+
+    /// Name of the component, as in the layout
+    pub component: &'static str,
+
+    /// Lower bound of the node which was searched, or `None` for the top component and for empty
+    /// nodes, which have no smallest key
+    pub node: Option<K>,
+
+    pub probe: Probe,
+}
+
+/// Every layer visited by a lookup, ordered from the top down to the base layer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupTrace<K> {
+    pub key: K,
+    pub steps: Vec<LookupStep<K>>,
+
+    /// Whether the base layer holds an entry for the key
+    pub found: bool,
+}
+
+impl<K> LookupTrace<K> {
+    /// Comparisons made over the whole lookup
+    pub fn comparisons(&self) -> usize {
+        self.steps.iter().map(|step| step.probe.comparisons).sum()
+    }
+}
+
+impl<K: fmt::Debug> fmt::Display for LookupTrace<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = if self.found { "found" } else { "not found" };
+        writeln!(
+            f,
+            "lookup of {:?}: {}, {} comparisons",
+            self.key,
+            outcome,
+            self.comparisons()
+        )?;
+
+        for step in self.steps.iter() {
+            write!(f, "  layer {:<3} {:<24}", step.layer, step.component)?;
+
+            match step.node {
+                Some(ref node) => write!(f, " node {:<24}", format!("{:?}", node))?,
+                None => write!(f, " {:<29}", "")?,
+            }
+
+            if let Some(window) = step.probe.window {
+                write!(f, " window {:<6}", window)?;
+            }
+
+            writeln!(f, " comparisons {}", step.probe.comparisons)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_search_comparisons() {
+        assert_eq!(Probe::binary_search(0).comparisons, 0);
+        assert_eq!(Probe::binary_search(1).comparisons, 1);
+        assert_eq!(Probe::binary_search(7).comparisons, 3);
+        assert_eq!(Probe::binary_search(8).comparisons, 4);
+    }
+
+    #[test]
+    fn display_trace() {
+        let trace = LookupTrace {
+            key: 42u64,
+            steps: vec![
+                LookupStep {
+                    layer: 1,
+                    component: "BTreeTop",
+                    node: None,
+                    probe: Probe::binary_search(3),
+                },
+                LookupStep {
+                    layer: 0,
+                    component: "PGMBase{8}",
+                    node: Some(40),
+                    probe: Probe {
+                        window: Some(17),
+                        comparisons: 3,
+                    },
+                },
+            ],
+            found: true,
+        };
+
+        let text = trace.to_string();
+        assert!(text.starts_with("lookup of 42: found, 5 comparisons"));
+        assert!(text.contains("window 17"));
+        assert_eq!(text.lines().count(), 3);
+    }
+}
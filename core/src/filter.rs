@@ -0,0 +1,405 @@
+//! Filters which answer lookups of absent keys before the descent, kept by layouts with
+//! `filter: bloom(fpr = 0.01)` or `filter: learned(fpr = 0.01)`. A `bloom` filter hashes every
+//! key into a `BloomFilter`. A `learned` filter first fits a model to the keys it is built over,
+//! made of `FilterSegment`s which each hold a run of evenly spaced keys exactly, and only hashes
+//! the keys no segment is worth spending on into a backup `BloomFilter`. Segments never claim an
+//! absent key, so both kinds answer absent keys wrongly at most at the rate `fpr`, but dense or
+//! regularly spaced keys take a learned filter a fraction of the memory.
+
+use crate::traits::Key;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use num::{Float, PrimInt};
+
+/// Fewest keys the first stage of a `BloomFilter` is sized for
+const MIN_CAPACITY: usize = 64;
+
+/// Which filter a layout keeps, as chosen by its `filter` field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    Bloom,
+    Learned,
+}
+
+/// Bits a bloom filter spends on every key to answer absent keys wrongly at the rate `fpr`
+fn bits_per_key(fpr: f64) -> f64 {
+    -fpr.ln() / (core::f64::consts::LN_2 * core::f64::consts::LN_2)
+}
+
+/// Fold a key into 128 bits, wider keys having their upper half folded onto their lower half
+fn fold<K: Key + PrimInt>(key: &K) -> u128 {
+    if let Some(key) = key.to_i128() {
+        return key as u128;
+    }
+
+    if let Some(key) = key.to_u128() {
+        return key;
+    }
+
+    let low = K::from(u128::MAX)
+        .and_then(|mask| (*key & mask).to_u128())
+        .unwrap_or_default();
+    let high = key.unsigned_shr(128).to_u128().unwrap_or_default();
+
+    low ^ high.rotate_left(64)
+}
+
+/// Finalizer of splitmix64
+fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Two independent hashes of a key, combined into as many as a stage needs
+fn hash<K: Key + PrimInt>(key: &K) -> (u64, u64) {
+    let folded = fold(key);
+    let first = mix(folded as u64 ^ mix((folded >> 64) as u64));
+    let second = mix(first ^ 0x9e37_79b9_7f4a_7c15) | 1;
+
+    (first, second)
+}
+
+#[derive(Clone)]
+struct BloomStage {
+    bits: Vec<u64>,
+    hashes: u32,
+    capacity: usize,
+    len: usize,
+}
+
+impl BloomStage {
+    fn new(capacity: usize, fpr: f64) -> Self {
+        let bits = (capacity as f64 * bits_per_key(fpr)).ceil().max(64.0) as usize;
+        let words = bits.div_ceil(64);
+        let hashes = (words as f64 * 64.0 / capacity as f64 * core::f64::consts::LN_2).round();
+
+        Self {
+            bits: alloc::vec![0; words],
+            hashes: (hashes as u32).max(1),
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Bit set by the `index`th of the hashes of a key
+    fn position(&self, (first, second): (u64, u64), index: u32) -> usize {
+        let bits = self.bits.len() as u64 * 64;
+        (first.wrapping_add((index as u64).wrapping_mul(second)) % bits) as usize
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        for index in 0..self.hashes {
+            let position = self.position(hash, index);
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+
+        self.len += 1;
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        (0..self.hashes).all(|index| {
+            let position = self.position(hash, index);
+            self.bits[position / 64] & (1 << (position % 64)) != 0
+        })
+    }
+}
+
+/// A bloom filter which grows as keys are inserted past its capacity. Every stage it adds holds
+/// twice the keys of the one before at half the false positive rate, and the first stage is sized
+/// at half of `fpr`, so the rates of all stages add up to less than `fpr`.
+#[derive(Clone)]
+pub struct BloomFilter {
+    stages: Vec<BloomStage>,
+    fpr: f64,
+}
+
+impl BloomFilter {
+    /// A filter sized for `capacity` keys, answering absent keys wrongly at most at the rate `fpr`
+    pub fn new(capacity: usize, fpr: f64) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "the false positive rate of a filter has to be between 0 and 1"
+        );
+
+        Self {
+            stages: alloc::vec![BloomStage::new(capacity.max(MIN_CAPACITY), fpr / 2.0)],
+            fpr,
+        }
+    }
+
+    pub fn insert<K: Key + PrimInt>(&mut self, key: &K) {
+        let last = self
+            .stages
+            .last()
+            .expect("a bloom filter has at least one stage");
+        if last.len >= last.capacity {
+            let stage = BloomStage::new(
+                last.capacity * 2,
+                self.fpr / 2.0.powi(self.stages.len() as i32 + 1),
+            );
+            self.stages.push(stage);
+        }
+
+        let hash = hash(key);
+        self.stages.last_mut().unwrap().insert(hash);
+    }
+
+    /// Whether `key` may have been inserted, which is certain for every key that was
+    pub fn may_contain<K: Key + PrimInt>(&self, key: &K) -> bool {
+        let hash = hash(key);
+        self.stages.iter().any(|stage| stage.contains(hash))
+    }
+
+    /// Number of keys inserted
+    pub fn len(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of stages, which is one until inserts outgrow the capacity of the first
+    pub fn stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Bytes held by the bits of every stage
+    pub fn memory_usage(&self) -> usize {
+        self.stages
+            .iter()
+            .map(|stage| stage.bits.len() * size_of::<u64>())
+            .sum()
+    }
+}
+
+/// The keys `start`, `start + stride`, and so on, `len` of them, which a learned filter holds
+/// exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterSegment<K> {
+    pub start: K,
+    pub stride: K,
+    pub len: usize,
+}
+
+impl<K: Key + PrimInt> FilterSegment<K> {
+    pub fn contains(&self, key: &K) -> bool {
+        if self.stride.is_zero() {
+            return *key == self.start;
+        }
+
+        // Keys past the end of the segment can be too far from its start to subtract
+        match key.checked_sub(&self.start) {
+            Some(offset) if *key >= self.start => {
+                (offset % self.stride).is_zero()
+                    && (offset / self.stride)
+                        .to_usize()
+                        .is_some_and(|at| at < self.len)
+            }
+            _ => false,
+        }
+    }
+
+    /// The key following the last key of the segment, unless it doesn't fit into `K`
+    fn next(&self) -> Option<K> {
+        let len = K::from(self.len)?;
+        self.start.checked_add(&self.stride.checked_mul(&len)?)
+    }
+
+    /// Whether holding the segment takes fewer bits than hashing its keys into a bloom filter
+    fn is_worthwhile(&self, fpr: f64) -> bool {
+        self.len as f64 * bits_per_key(fpr) > (size_of::<Self>() * 8) as f64
+    }
+}
+
+/// Filter of an index with a `filter` field. Built alongside the index by a `FilterBuilder`, and
+/// handed every key inserted afterwards, so it never answers that a key held by the index is
+/// absent.
+#[derive(Clone)]
+pub struct KeyFilter<K> {
+    kind: FilterKind,
+    segments: Vec<FilterSegment<K>>,
+    backup: BloomFilter,
+}
+
+impl<K: Key + PrimInt> KeyFilter<K> {
+    /// A filter over no keys, for an empty index
+    pub fn new(kind: FilterKind, fpr: f64) -> Self {
+        Self {
+            kind,
+            segments: Vec::new(),
+            backup: BloomFilter::new(0, fpr),
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    /// Whether `key` may be held by the index, which is certain for every key it holds
+    pub fn may_contain(&self, key: &K) -> bool {
+        // Segments are disjoint and ordered, so only the last one starting at or below the key
+        // can hold it
+        let after = self
+            .segments
+            .partition_point(|segment| segment.start <= *key);
+        if after > 0 && self.segments[after - 1].contains(key) {
+            return true;
+        }
+
+        self.backup.may_contain(key)
+    }
+
+    /// Record a key inserted into the index. A key continuing the last segment extends it, which
+    /// keeps a learned filter small under sequential appends.
+    pub fn insert(&mut self, key: &K) {
+        if let Some(last) = self.segments.last_mut() {
+            if last.contains(key) {
+                return;
+            }
+
+            if last.next() == Some(*key) {
+                last.len += 1;
+                return;
+            }
+        }
+
+        self.backup.insert(key);
+    }
+
+    /// The segments of the model, only kept by learned filters
+    pub fn segments(&self) -> &[FilterSegment<K>] {
+        &self.segments
+    }
+
+    /// Number of keys held by the segments of the model
+    pub fn learned_keys(&self) -> usize {
+        self.segments.iter().map(|segment| segment.len).sum()
+    }
+
+    /// The bloom filter holding every key no segment does
+    pub fn backup(&self) -> &BloomFilter {
+        &self.backup
+    }
+
+    /// Estimated bytes held by the model and the backup filter
+    pub fn memory_usage(&self) -> usize {
+        self.segments.capacity() * size_of::<FilterSegment<K>>() + self.backup.memory_usage()
+    }
+}
+
+/// Trains a `KeyFilter` over the keys an index is built from, which arrive in ascending order. A
+/// learned filter extends a run of keys for as long as they stay evenly spaced, and keeps the run
+/// as a segment once it ends if that takes less memory than hashing its keys.
+pub struct FilterBuilder<K> {
+    kind: FilterKind,
+    fpr: f64,
+    segments: Vec<FilterSegment<K>>,
+    run: Option<FilterSegment<K>>,
+    backup: Vec<K>,
+}
+
+impl<K: Key + PrimInt> FilterBuilder<K> {
+    pub fn new(kind: FilterKind, fpr: f64) -> Self {
+        Self {
+            kind,
+            fpr,
+            segments: Vec::new(),
+            run: None,
+            backup: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: K) {
+        if self.kind == FilterKind::Bloom {
+            self.backup.push(key);
+            return;
+        }
+
+        let Some(run) = self.run.as_mut() else {
+            self.run = Some(FilterSegment {
+                start: key,
+                stride: K::zero(),
+                len: 1,
+            });
+            return;
+        };
+
+        if run.len == 1 {
+            if let Some(stride) = key
+                .checked_sub(&run.start)
+                .filter(|stride| !stride.is_zero())
+            {
+                run.stride = stride;
+                run.len = 2;
+                return;
+            }
+        } else if run.next() == Some(key) {
+            run.len += 1;
+            return;
+        }
+
+        if run.contains(&key) {
+            return;
+        }
+
+        // Two keys are no run yet, so the second one may still start a run with this key
+        if run.len == 2 {
+            let second = run.start + run.stride;
+            self.backup.push(run.start);
+            self.run = Some(FilterSegment {
+                start: second,
+                stride: K::zero(),
+                len: 1,
+            });
+            self.push(key);
+            return;
+        }
+
+        self.close_run();
+        self.run = Some(FilterSegment {
+            start: key,
+            stride: K::zero(),
+            len: 1,
+        });
+    }
+
+    /// Keep the current run as a segment if it's worthwhile, and hash its keys otherwise
+    fn close_run(&mut self) {
+        let Some(run) = self.run.take() else {
+            return;
+        };
+
+        if run.is_worthwhile(self.fpr) {
+            self.segments.push(run);
+            return;
+        }
+
+        let mut key = Some(run.start);
+        for _ in 0..run.len {
+            let Some(next) = key else {
+                break;
+            };
+
+            self.backup.push(next);
+            key = next.checked_add(&run.stride);
+        }
+    }
+
+    pub fn finish(mut self) -> KeyFilter<K> {
+        self.close_run();
+
+        let mut backup = BloomFilter::new(self.backup.len(), self.fpr);
+        for key in self.backup.iter() {
+            backup.insert(key);
+        }
+
+        self.segments.shrink_to_fit();
+        KeyFilter {
+            kind: self.kind,
+            segments: self.segments,
+            backup,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Indirection for values which cannot live in the index itself. Components require `V: 'static`,
+//! so an index generated with `values: handles` stores a `ValueHandle` per key instead, and the
+//! values themselves live in a `ValueStore` provided by the user, which is free to hold values
+//! borrowing from an arena or other non-static data.
+
+use alloc::vec::Vec;
+
+/// Key of a value in a `ValueStore`
+pub type ValueHandle = u64;
+
+/// Storage for the values of an index generated with `values: handles`
+pub trait ValueStore<V> {
+    /// Store `value`, returning the handle to look it up with
+    fn put(&mut self, value: V) -> ValueHandle;
+
+    fn get(&self, handle: ValueHandle) -> Option<&V>;
+
+    /// Remove the value behind `handle`, whose handle may then be reused by `put`
+    fn take(&mut self, handle: ValueHandle) -> Option<V>;
+
+    /// Number of values in the store
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A `ValueStore` backed by a vector of slots, which reuses the slots of taken values
+#[derive(Debug, Clone)]
+pub struct SlabStore<V> {
+    slots: Vec<Option<V>>,
+    free: Vec<usize>,
+}
+
+impl<V> SlabStore<V> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<V> Default for SlabStore<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> ValueStore<V> for SlabStore<V> {
+    fn put(&mut self, value: V) -> ValueHandle {
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(value);
+                slot as ValueHandle
+            }
+            None => {
+                self.slots.push(Some(value));
+                (self.slots.len() - 1) as ValueHandle
+            }
+        }
+    }
+
+    fn get(&self, handle: ValueHandle) -> Option<&V> {
+        self.slots.get(handle as usize)?.as_ref()
+    }
+
+    fn take(&mut self, handle: ValueHandle) -> Option<V> {
+        let value = self.slots.get_mut(handle as usize)?.take()?;
+        self.free.push(handle as usize);
+        Some(value)
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
@@ -0,0 +1,346 @@
+//! Bulk loading of in-memory indexes from files. Rows are streamed from the file, sorted in bounded
+//! memory by spilling sorted runs to temporary files, and merged straight into the layered `build`
+//! path, without ever holding the whole file in memory.
+//!
+//! Keys and values are parsed from the text of their column. If a key appears more than once, the
+//! row closest to the end of the file wins, as if the rows had been inserted in order.
+
+use crate::{KVStore, Key, Value};
+use num::PrimInt;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Selects a column of the source file, either by position or by header name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Self {
+        Self::Name(name.to_string())
+    }
+}
+
+impl From<String> for Column {
+    fn from(name: String) -> Self {
+        Self::Name(name)
+    }
+}
+
+impl Column {
+    fn position<'a>(&self, mut names: impl Iterator<Item = &'a str>) -> crate::Result<usize> {
+        match self {
+            Self::Index(index) => Ok(*index),
+            Self::Name(name) => names
+                .position(|column| column == name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown column `{}`!", name)),
+        }
+    }
+}
+
+/// Default number of rows sorted in memory before a run is spilled to disk
+pub const DEFAULT_RUN_LEN: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+
+    /// Whether the first row names the columns, which is required to select columns by name
+    pub has_headers: bool,
+
+    /// Number of rows sorted in memory before a run is spilled to disk
+    pub run_len: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            run_len: DEFAULT_RUN_LEN,
+        }
+    }
+}
+
+/// Build an index from two columns of a CSV file with a header row
+pub fn build_from_csv<I, K, V>(
+    path: impl AsRef<Path>,
+    key: impl Into<Column>,
+    value: impl Into<Column>,
+) -> crate::Result<I>
+where
+    I: KVStore<K, V>,
+    K: Key + PrimInt,
+    V: Value + FromStr,
+{
+    build_from_csv_with(path, key, value, &CsvOptions::default())
+}
+
+pub fn build_from_csv_with<I, K, V>(
+    path: impl AsRef<Path>,
+    key: impl Into<Column>,
+    value: impl Into<Column>,
+    options: &CsvOptions,
+) -> crate::Result<I>
+where
+    I: KVStore<K, V>,
+    K: Key + PrimInt,
+    V: Value + FromStr,
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.has_headers)
+        .from_path(path)?;
+
+    let (key, value) = (key.into(), value.into());
+    let (key, value) = if options.has_headers {
+        let headers = reader.headers()?.clone();
+        (key.position(headers.iter())?, value.position(headers.iter())?)
+    } else {
+        match (key, value) {
+            (Column::Index(key), Column::Index(value)) => (key, value),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Columns can only be selected by name in files with headers!"
+                ))
+            }
+        }
+    };
+
+    let rows = reader.into_records().map(move |record| {
+        let record = record?;
+        let field = |column: usize| {
+            record.get(column).map(str::to_string).ok_or_else(|| {
+                anyhow::anyhow!("Row {} has no column {}!", row_number(&record), column)
+            })
+        };
+
+        Ok((field(key)?, field(value)?))
+    });
+
+    build_sorted(rows, options.run_len)
+}
+
+fn row_number(record: &csv::StringRecord) -> u64 {
+    record.position().map_or(0, |position| position.line())
+}
+
+/// Build an index from two columns of a Parquet file. Columns are converted to text before being
+/// parsed, so any column whose values print as the key or value type can be used.
+#[cfg(feature = "parquet")]
+pub fn build_from_parquet<I, K, V>(
+    path: impl AsRef<Path>,
+    key: impl Into<Column>,
+    value: impl Into<Column>,
+) -> crate::Result<I>
+where
+    I: KVStore<K, V>,
+    K: Key + PrimInt,
+    V: Value + FromStr,
+{
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let reader = SerializedFileReader::new(std::fs::File::open(path)?)?;
+
+    let fields = reader.metadata().file_metadata().schema().get_fields();
+    let names = || fields.iter().map(|field| field.name());
+    let (key, value) = (key.into().position(names())?, value.into().position(names())?);
+
+    let text = |field: &Field| match field {
+        Field::Str(text) => text.clone(),
+        field => field.to_string(),
+    };
+
+    let rows = reader.get_row_iter(None)?.map(|row| {
+        let row = row?;
+        let columns = row.get_column_iter().collect::<Vec<_>>();
+        let field = |column: usize| {
+            columns
+                .get(column)
+                .map(|(_, field)| text(field))
+                .ok_or_else(|| anyhow::anyhow!("Row has no column {}!", column))
+        };
+
+        Ok((field(key)?, field(value)?))
+    });
+
+    build_sorted(rows, DEFAULT_RUN_LEN)
+}
+
+fn parse_key<K: Key + PrimInt>(text: &str) -> crate::Result<K> {
+    <K as num::Num>::from_str_radix(text.trim(), 10)
+        .map_err(|_| anyhow::anyhow!("Invalid key {:?}!", text))
+}
+
+/// A sorted run of (key, value text) rows
+type Run<'a, K> = Box<dyn Iterator<Item = crate::Result<(K, String)>> + 'a>;
+
+/// Sort the text rows in runs of `run_len`, and build the index from their merge
+fn build_sorted<I, K, V>(
+    rows: impl Iterator<Item = crate::Result<(String, String)>>,
+    run_len: usize,
+) -> crate::Result<I>
+where
+    I: KVStore<K, V>,
+    K: Key + PrimInt,
+    V: Value + FromStr,
+{
+    let run_len = run_len.max(1);
+    let mut spilled = Vec::new();
+    let mut buffer: Vec<(K, String, String)> = Vec::new();
+
+    for row in rows {
+        let (key, value) = row?;
+        buffer.push((parse_key(&key)?, key, value));
+
+        if buffer.len() >= run_len {
+            spilled.push(spill(&mut buffer)?);
+        }
+    }
+
+    // The last run, which is the only one if the file fits in memory, is merged from memory
+    buffer.sort_by_key(|(key, _, _)| *key);
+
+    let mut runs: Vec<Run<'_, K>> = Vec::new();
+    for file in spilled.iter() {
+        runs.push(read_run(file)?);
+    }
+    runs.push(Box::new(
+        buffer.into_iter().map(|(key, _, value)| Ok((key, value))),
+    ));
+
+    let mut error = None;
+    let index = I::build(Merge::new(runs, &mut error));
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(index),
+    }
+}
+
+/// Sort the buffered rows and write them out to a temporary file. Sorting is stable, so rows with
+/// the same key keep their order from the file.
+fn spill<K: Key + PrimInt>(
+    buffer: &mut Vec<(K, String, String)>,
+) -> crate::Result<tempfile::NamedTempFile> {
+    buffer.sort_by_key(|(key, _, _)| *key);
+
+    let file = tempfile::NamedTempFile::new()?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(std::io::BufWriter::new(file.reopen()?));
+
+    for (_, key, value) in buffer.drain(..) {
+        writer.write_record([key, value])?;
+    }
+
+    writer.flush()?;
+    Ok(file)
+}
+
+fn read_run<'a, K: Key + PrimInt>(file: &tempfile::NamedTempFile) -> crate::Result<Run<'a, K>> {
+    let reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(std::io::BufReader::new(file.reopen()?));
+
+    Ok(Box::new(reader.into_records().map(|record| {
+        let record = record?;
+        let (Some(key), Some(value)) = (record.get(0), record.get(1)) else {
+            return Err(anyhow::anyhow!("Corrupted sort run!"));
+        };
+
+        Ok((parse_key(key)?, value.to_string()))
+    })))
+}
+
+/// K-way merge of sorted runs, yielding only the last row of every key. The first error ends the
+/// iteration, and is stored in `error`.
+struct Merge<'a, 'e, K, V> {
+    runs: Vec<Run<'a, K>>,
+
+    /// Smallest unmerged key of every run, ties are broken by the run index, since later runs
+    /// hold later rows of the file
+    heads: BinaryHeap<Reverse<(K, usize)>>,
+    values: Vec<Option<String>>,
+    error: &'e mut Option<anyhow::Error>,
+    _ph: std::marker::PhantomData<V>,
+}
+
+impl<'a, 'e, K: Key + PrimInt, V: FromStr> Merge<'a, 'e, K, V> {
+    fn new(runs: Vec<Run<'a, K>>, error: &'e mut Option<anyhow::Error>) -> Self {
+        let mut merge = Self {
+            values: vec![None; runs.len()],
+            runs,
+            heads: BinaryHeap::new(),
+            error,
+            _ph: std::marker::PhantomData,
+        };
+
+        for run in 0..merge.runs.len() {
+            merge.advance(run);
+        }
+
+        merge
+    }
+
+    fn advance(&mut self, run: usize) {
+        match self.runs[run].next() {
+            Some(Ok((key, value))) => {
+                self.heads.push(Reverse((key, run)));
+                self.values[run] = Some(value);
+            }
+            Some(Err(error)) => self.fail(error),
+            None => (),
+        }
+    }
+
+    fn fail(&mut self, error: anyhow::Error) {
+        self.error.get_or_insert(error);
+        self.heads.clear();
+    }
+}
+
+impl<K: Key + PrimInt, V: FromStr> Iterator for Merge<'_, '_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, run)) = self.heads.pop()?;
+        let mut value = self.values[run].take();
+        self.advance(run);
+
+        // Skip ahead to the last row with the same key
+        while let Some(&Reverse((next, run))) = self.heads.peek() {
+            if next != key {
+                break;
+            }
+
+            self.heads.pop();
+            value = self.values[run].take();
+            self.advance(run);
+        }
+
+        if self.error.is_some() {
+            return None;
+        }
+
+        let value = value?;
+        match value.parse() {
+            Ok(value) => Some((key, value)),
+            Err(_) => {
+                self.fail(anyhow::anyhow!("Invalid value {:?}!", value));
+                None
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use std::ops::Bound;
+use alloc::vec::Vec;
+use core::ops::Bound;
 
-use crate::{node_layer::NodeLayer, traits::Address};
+use crate::{
+    node_layer::NodeLayer,
+    traits::{Address, KeyBound},
+};
 
 // ----------------------------------------
 // Iterator Type
@@ -10,7 +14,13 @@ pub struct Iter<'n, K, N, SA, PA> {
     layer: &'n N,
     current: Option<SA>,
     end: Bound<SA>,
-    _ph: std::marker::PhantomData<(K, PA)>,
+
+    /// Whether the next node is keyed by `NegInf` rather than its lower bound, see `anchored`
+    anchored: bool,
+
+    /// Number of nodes left, only known for ranges over the whole layer
+    remaining: Option<usize>,
+    _ph: core::marker::PhantomData<(K, PA)>,
 }
 
 impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> Iter<'n, K, N, SA, PA>
@@ -19,29 +29,60 @@ where
     PA: Address,
 {
     pub fn range(layer: &'n N, start: Bound<SA>, end: Bound<SA>) -> Self {
+        let remaining = match (&start, &end) {
+            (Bound::Unbounded, Bound::Unbounded) => Some(layer.node_count()),
+            _ => None,
+        };
+
         match start {
             Bound::Excluded(start) => Self {
                 layer,
                 current: layer.next(start),
                 end,
-                _ph: std::marker::PhantomData,
+                remaining,
+                anchored: false,
+                _ph: core::marker::PhantomData,
             },
 
             Bound::Included(start) => Self {
                 layer,
                 current: Some(start.clone()),
                 end,
-                _ph: std::marker::PhantomData,
+                remaining,
+                anchored: false,
+                _ph: core::marker::PhantomData,
             },
 
             Bound::Unbounded => Self {
                 layer,
                 current: Some(layer.first()),
                 end,
-                _ph: std::marker::PhantomData,
+                remaining,
+                anchored: false,
+                _ph: core::marker::PhantomData,
             },
         }
     }
+
+    /// Bound the first node of the range by `NegInf` instead of its lower bound. The lower bound
+    /// of the first node of a layer drops as smaller keys are inserted into it, so layers built
+    /// over it anchor their first entry, which keeps later split points from sorting before it.
+    pub fn anchored(mut self) -> Self {
+        self.anchored = true;
+        self
+    }
+
+    /// Clears `buffer` and refills it with up to `len` consecutive entries, returning how many were
+    /// written. Returns 0 once the iterator is exhausted, and fewer than `len` only for the last
+    /// chunk. `buffer` grows to `len` entries at most, so it can be reused across chunks without
+    /// allocating again.
+    pub fn next_chunk(&mut self, buffer: &mut Vec<(KeyBound<K>, SA)>, len: usize) -> usize {
+        buffer.clear();
+        buffer.reserve(len);
+
+        buffer.extend(self.by_ref().take(len));
+        buffer.len()
+    }
 }
 
 impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> Iterator for Iter<'n, K, N, SA, PA>
@@ -49,36 +90,109 @@ where
     SA: Address,
     PA: Address,
 {
-    type Item = (K, SA);
+    type Item = (KeyBound<K>, SA);
 
     fn next(&mut self) -> Option<Self::Item> {
         let current = self.current.clone()?;
 
         match self.end.clone() {
-            Bound::Excluded(end) => {
-                if current == end {
-                    return None;
-                }
+            Bound::Excluded(end) if current == end => {
+                return None;
             }
 
-            Bound::Included(end) => {
-                if current == end {
-                    self.current = None;
-                }
+            Bound::Included(end) if current == end => {
+                self.current = None;
             }
 
             _ => (),
         }
 
-        // Advance pointer
+        // Advance pointer, and start pulling in the next node while the caller works on this one
         if let Some(current) = self.current.clone() {
             self.current = self.layer.next(current);
+
+            if let Some(next) = self.current.clone() {
+                self.layer.prefetch(next);
+            }
+        }
+
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(1);
         }
 
-        Some(((self.layer.lower_bound(current.clone())), current))
+        let bound = match core::mem::take(&mut self.anchored) {
+            true => KeyBound::NegInf,
+            false => self.layer.lower_bound(current.clone()),
+        };
+
+        Some((bound, current))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (self.remaining, &self.current) {
+            (Some(remaining), _) => (remaining, Some(remaining)),
+            (None, None) => (0, Some(0)),
+            // The position within the layer is unknown, but the range can't outgrow it
+            (None, Some(_)) => (1, Some(self.layer.node_count())),
+        }
+    }
+
+    fn count(self) -> usize {
+        match self.remaining {
+            Some(remaining) => remaining,
+            None => self.fold(0, |count, _| count + 1),
+        }
     }
 }
 
+// ----------------------------------------
+// Whole Layer Iterator Type
+// ----------------------------------------
+
+/// An iterator over every node of a layer, whose length is known upfront
+pub struct Nodes<'n, K, N, SA, PA> {
+    inner: Iter<'n, K, N, SA, PA>,
+}
+
+impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> Nodes<'n, K, N, SA, PA>
+where
+    SA: Address,
+    PA: Address,
+{
+    pub fn new(layer: &'n N) -> Self {
+        Self {
+            inner: Iter::range(layer, Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+}
+
+impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> Iterator for Nodes<'n, K, N, SA, PA>
+where
+    SA: Address,
+    PA: Address,
+{
+    type Item = (KeyBound<K>, SA);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.inner.count()
+    }
+}
+
+impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> ExactSizeIterator for Nodes<'n, K, N, SA, PA>
+where
+    SA: Address,
+    PA: Address,
+{
+}
+
 // ----------------------------------------
 // Mutable Iterator Type
 // ----------------------------------------
@@ -87,7 +201,8 @@ pub struct IterMut<'n, K, N, SA, PA> {
     layer: &'n mut N,
     current: Option<SA>,
     end: Bound<SA>,
-    _ph: std::marker::PhantomData<(K, PA)>,
+    anchored: bool,
+    _ph: core::marker::PhantomData<(K, PA)>,
 }
 
 impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> IterMut<'n, K, N, SA, PA>
@@ -105,7 +220,8 @@ where
                     layer,
                     current,
                     end,
-                    _ph: std::marker::PhantomData,
+                    anchored: false,
+                    _ph: core::marker::PhantomData,
                 }
             }
 
@@ -113,7 +229,8 @@ where
                 layer,
                 current: Some(start.clone()),
                 end,
-                _ph: std::marker::PhantomData,
+                anchored: false,
+                _ph: core::marker::PhantomData,
             },
 
             Bound::Unbounded => {
@@ -123,28 +240,32 @@ where
                     layer,
                     current,
                     end,
-                    _ph: std::marker::PhantomData,
+                    anchored: false,
+                    _ph: core::marker::PhantomData,
                 }
             }
         }
     }
 
+    /// Bound the first node of the range by `NegInf` instead of its lower bound, see
+    /// `Iter::anchored`
+    pub fn anchored(mut self) -> Self {
+        self.anchored = true;
+        self
+    }
+
     #[allow(clippy::type_complexity)]
     #[allow(clippy::should_implement_trait)]
-    pub fn next(&mut self) -> Option<(K, SA, IterMutParentView<'_, K, N, SA, PA>)> {
+    pub fn next(&mut self) -> Option<(KeyBound<K>, SA, IterMutParentView<'_, K, N, SA, PA>)> {
         let current = self.current.clone()?;
 
         match self.end.clone() {
-            Bound::Excluded(end) => {
-                if current == end {
-                    return None;
-                }
+            Bound::Excluded(end) if current == end => {
+                return None;
             }
 
-            Bound::Included(end) => {
-                if current == end {
-                    self.current = None;
-                }
+            Bound::Included(end) if current == end => {
+                self.current = None;
             }
 
             _ => (),
@@ -155,12 +276,15 @@ where
             self.current = self.layer.next(current);
         }
 
-        let key = self.layer.lower_bound(current.clone()).clone();
+        let key = match core::mem::take(&mut self.anchored) {
+            true => KeyBound::NegInf,
+            false => self.layer.lower_bound(current.clone()),
+        };
         let current = current.clone();
         let parent = IterMutParentView {
             layer: self.layer,
             address: current.clone(),
-            _ph: std::marker::PhantomData,
+            _ph: core::marker::PhantomData,
         };
 
         Some((key, current, parent))
@@ -170,7 +294,7 @@ where
 pub struct IterMutParentView<'n, K, N, SA, PA> {
     layer: &'n mut N,
     address: SA,
-    _ph: std::marker::PhantomData<(K, PA)>,
+    _ph: core::marker::PhantomData<(K, PA)>,
 }
 
 impl<'n, K, SA, PA, N: NodeLayer<K, SA, PA>> IterMutParentView<'n, K, N, SA, PA>
@@ -1,5 +1,16 @@
-use crate::{Key, Persisted, Value};
+#[cfg(feature = "std")]
+use crate::Persisted;
+use crate::{Address, Key, NodeLayer, Value, Version};
+use core::cell::Cell;
+use core::fmt;
+#[cfg(feature = "std")]
+use num::PrimInt;
+#[cfg(feature = "std")]
 use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
 
 pub trait KVStore<K, V>
 where
@@ -8,13 +19,26 @@ where
 {
     fn search(&self, key: K) -> Option<V>;
 
+    /// Insert a key, returning the value it held before
     fn insert(&mut self, key: K, value: V) -> Option<V>;
 
+    /// Insert a key only if it isn't present yet, otherwise hand back both values untouched
+    fn try_insert(&mut self, key: K, value: V) -> Result<(), OccupiedError<V>> {
+        match self.search(key) {
+            Some(existing) => Err(OccupiedError { existing, value }),
+            None => {
+                self.insert(key, value);
+                Ok(())
+            }
+        }
+    }
+
     fn empty() -> Self;
 
     fn build(iter: impl Iterator<Item = (K, V)>) -> Self;
 }
 
+#[cfg(feature = "std")]
 pub trait PersistedKVStore<K, V>
 where
     Self: Sized,
@@ -23,7 +47,454 @@ where
 {
     fn search(&self, key: K) -> crate::Result<Option<V>>;
 
+    /// Insert a key, returning the value it held before
     fn insert(&mut self, key: K, value: V) -> crate::Result<Option<V>>;
 
+    /// Insert a key only if it isn't present yet, otherwise hand back both values untouched
+    fn try_insert(&mut self, key: K, value: V) -> crate::Result<Result<(), OccupiedError<V>>> {
+        match self.search(key)? {
+            Some(existing) => Ok(Err(OccupiedError { existing, value })),
+            None => {
+                self.insert(key, value)?;
+                Ok(Ok(()))
+            }
+        }
+    }
+
     fn open(path: impl AsRef<Path>) -> crate::Result<Self>;
 }
+
+/// Returned by `try_insert` when the key is already present
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OccupiedError<V> {
+    /// The value the key holds, which was left in place
+    pub existing: V,
+
+    /// The value which was not inserted
+    pub value: V,
+}
+
+impl<V> fmt::Display for OccupiedError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("key is already present")
+    }
+}
+
+impl<V: fmt::Debug> core::error::Error for OccupiedError<V> {}
+
+/// Returned by `insert_bounded` when the index already holds more memory than its `max_memory`
+/// budget, in which case nothing was inserted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityExceeded {
+    /// Estimated bytes held by the index, see `memory_usage`
+    pub used: usize,
+
+    /// The `max_memory` budget of the layout
+    pub budget: usize,
+}
+
+impl fmt::Display for CapacityExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index holds {} bytes, over its budget of {} bytes",
+            self.used, self.budget
+        )
+    }
+}
+
+impl core::error::Error for CapacityExceeded {}
+
+/// Returned by `build_in` of a layout with `alloc: fixed` when the buffer provided is too small to
+/// hold the layers of the index, in which case the buffer is left unused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError {
+    /// Bytes the build asked the buffer for, counting the requests which didn't fit
+    pub needed: usize,
+
+    /// Size of the buffer provided
+    pub available: usize,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index needs {} bytes, over the buffer of {} bytes",
+            self.needed, self.available
+        )
+    }
+}
+
+impl core::error::Error for CapacityError {}
+
+/// What `try_build` does with several entries for the same key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Fail the build
+    #[default]
+    Error,
+
+    /// Keep the last entry, as if they had been inserted one by one
+    LastWins,
+
+    /// Keep the first entry
+    FirstWins,
+}
+
+/// Returned by `try_build` when its entries break the order `build` relies on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError<K> {
+    /// `key` came after the larger key `previous`
+    Unsorted { previous: K, key: K },
+
+    /// `key` came more than once, and duplicates are an error
+    Duplicate { key: K },
+}
+
+impl<K: fmt::Debug> fmt::Display for BuildError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsorted { previous, key } => {
+                write!(f, "key {:?} came after the larger key {:?}", key, previous)
+            }
+            Self::Duplicate { key } => write!(f, "key {:?} came more than once", key),
+        }
+    }
+}
+
+impl<K: fmt::Debug> core::error::Error for BuildError<K> {}
+
+/// Entries passed on to `build` as long as they are sorted by key, resolving duplicate keys as
+/// told. The first error ends the entries and is left in `error`, so the index built from the
+/// entries before it has to be thrown away.
+pub struct CheckedEntries<'a, I, K, V> {
+    entries: I,
+    duplicates: Duplicates,
+
+    /// The next entry, held back until the entries after it show it isn't a duplicate
+    next: Option<(K, V)>,
+    error: &'a mut Option<BuildError<K>>,
+}
+
+impl<'a, I, K, V> CheckedEntries<'a, I, K, V>
+where
+    I: Iterator<Item = (K, V)>,
+{
+    pub fn new(
+        mut entries: I,
+        duplicates: Duplicates,
+        error: &'a mut Option<BuildError<K>>,
+    ) -> Self {
+        Self {
+            next: entries.next(),
+            entries,
+            duplicates,
+            error,
+        }
+    }
+}
+
+impl<I, K, V> Iterator for CheckedEntries<'_, I, K, V>
+where
+    I: Iterator<Item = (K, V)>,
+    K: Key,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let mut next = self.next.take()?;
+
+        for entry in self.entries.by_ref() {
+            if entry.0 > next.0 {
+                self.next = Some(entry);
+                break;
+            }
+
+            if entry.0 < next.0 {
+                *self.error = Some(BuildError::Unsorted {
+                    previous: next.0,
+                    key: entry.0,
+                });
+                return None;
+            }
+
+            match self.duplicates {
+                Duplicates::Error => {
+                    *self.error = Some(BuildError::Duplicate { key: entry.0 });
+                    return None;
+                }
+                Duplicates::LastWins => next = entry,
+                Duplicates::FirstWins => (),
+            }
+        }
+
+        Some(next)
+    }
+}
+
+/// Lookups into an index, implemented by every generated index whether it lives in memory or on
+/// disk. In-memory indexes never fail.
+pub trait IndexRead<K, V> {
+    fn try_search(&self, key: K) -> crate::Result<Option<V>>;
+
+    /// Number of entries in the index, which is kept up to date rather than counted
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Inserts into an index, implemented by every generated index which is not `read_only`
+pub trait IndexWrite<K, V> {
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>>;
+}
+
+/// An index supporting both lookups and inserts
+pub trait Index<K, V>: IndexRead<K, V> + IndexWrite<K, V> {}
+
+impl<T, K, V> Index<K, V> for T where T: IndexRead<K, V> + IndexWrite<K, V> {}
+
+/// Implemented by in-memory indexes which can tell ahead of time whether an insert stays within a
+/// single base node, or may restructure the layers above it
+pub trait QuickInsert<K> {
+    /// True if inserting `key` is certain not to propagate past the base layer
+    fn is_quick_insert(&self, key: &K) -> bool;
+}
+
+/// A `KVStore` which keeps the history of every key, generated by layouts with `versioning: mvcc`
+pub trait VersionedKVStore<K, V>: KVStore<K, V>
+where
+    Self: Sized,
+    K: Key,
+    V: Value,
+{
+    /// The version of the most recent write
+    fn version(&self) -> Version;
+
+    /// The value of `key` as of `version`, ignoring every later write
+    fn search_at(&self, key: K, version: Version) -> Option<V>;
+
+    /// Drop every value which is no longer visible as of `before_version` or later
+    fn gc(&mut self, before_version: Version);
+
+    /// A read-only view of the index as of `version`
+    fn snapshot_at(&self, version: Version) -> Snapshot<'_, Self, K, V> {
+        Snapshot {
+            index: self,
+            version,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+/// A view of a `VersionedKVStore` frozen at a version
+pub struct Snapshot<'a, I, K, V> {
+    index: &'a I,
+    version: Version,
+    _ph: core::marker::PhantomData<(K, V)>,
+}
+
+impl<'a, I, K, V> Snapshot<'a, I, K, V>
+where
+    I: VersionedKVStore<K, V>,
+    K: Key,
+    V: Value,
+{
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn search(&self, key: K) -> Option<V> {
+        self.index.search_at(key, self.version)
+    }
+}
+
+/// Remembers the base node of the last insert made through it. An insert with a hint skips the
+/// descent from the top whenever the key falls within the bounds of that node, which is the common
+/// case for workloads appending near the tail. A hint should only be used with the index which
+/// filled it, and a hint whose node was since replaced by a rebuild or `compact_memory` misses.
+pub struct SearchHint<SA> {
+    node: Cell<Option<SA>>,
+}
+
+impl<SA: Address + Copy> SearchHint<SA> {
+    pub fn new() -> Self {
+        Self {
+            node: Cell::new(None),
+        }
+    }
+
+    /// Forget the remembered node
+    pub fn clear(&self) {
+        self.node.set(None);
+    }
+
+    pub fn set(&self, node: SA) {
+        self.node.set(Some(node));
+    }
+
+    /// The remembered node, if a descent through the layers above `layer` would route `key` to it
+    pub fn node_for<K: Ord, PA: Address>(
+        &self,
+        layer: &impl NodeLayer<K, SA, PA>,
+        key: &K,
+    ) -> Option<SA> {
+        // The index may have been rebuilt or compacted since the node was remembered
+        let node = layer.check_node(self.node.get()?).ok()?;
+        routes_to(layer, node, key).then_some(node)
+    }
+}
+
+impl<SA: Address + Copy> Default for SearchHint<SA> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a descent through the layers above `layer` would route `key` to `node`
+pub(crate) fn routes_to<K: Ord, SA: Address + Copy, PA: Address>(
+    layer: &impl NodeLayer<K, SA, PA>,
+    node: SA,
+    key: &K,
+) -> bool {
+    // Keys below the lower bound of the first node are routed to it as well
+    let above = node == layer.first() || layer.lower_bound(node).covers(key);
+    let below = layer
+        .next(node)
+        .is_none_or(|next| !layer.lower_bound(next).covers(key));
+
+    above && below
+}
+
+/// A small table of base nodes, kept by layouts with `fast_fences: H`. A search first looks up
+/// the slot of its key among the `H` slots, and goes straight to the base node found there if that
+/// node still covers the key. Otherwise it descends through every layer as usual, and remembers
+/// the base node it reached in the slot, so the keys and regions searched most often stay cached.
+///
+/// Entries are validated on every use, so splits and rebuilds of the base layer never route a key
+/// to the wrong node, they only cost a miss.
+#[cfg(feature = "std")]
+pub struct FastFences<SA> {
+    slots: Mutex<Vec<Option<SA>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[cfg(feature = "std")]
+impl<SA: Address + Copy> FastFences<SA> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "fast fences need at least one slot");
+
+        Self {
+            slots: Mutex::new(vec![None; capacity]),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        lock(&self.slots).len()
+    }
+
+    fn slot<K: Key + PrimInt>(key: &K, capacity: usize) -> usize {
+        // Fibonacci hashing of the key folded down to 64 bits, so neighbouring keys spread over the
+        // slots. Only keys wider than 128 bits fall back to their number of set bits.
+        let bits = key
+            .to_u128()
+            .or_else(|| key.to_i128().map(|key| key as u128))
+            .map(|bits| (bits ^ (bits >> 64)) as u64)
+            .unwrap_or_else(|| key.count_ones() as u64);
+
+        (bits.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize % capacity
+    }
+
+    /// The cached base node of `key`, if it's still the node a descent would route `key` to. The
+    /// table is skipped while another search holds it.
+    pub fn node_for<K: Key + PrimInt, PA: Address>(
+        &self,
+        layer: &impl NodeLayer<K, SA, PA>,
+        key: &K,
+    ) -> Option<SA> {
+        let cached = {
+            let slots = self.slots.try_lock().ok()?;
+            slots[Self::slot(key, slots.len())]
+        };
+
+        match cached.filter(|&node| layer.contains_node(node) && routes_to(layer, node, key)) {
+            Some(node) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(node)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Cache `node` as the base node of `key`, replacing whatever held its slot
+    pub fn remember<K: Key + PrimInt>(&self, key: &K, node: SA) {
+        if let Ok(mut slots) = self.slots.try_lock() {
+            let slot = Self::slot(key, slots.len());
+            slots[slot] = Some(node);
+        }
+    }
+
+    /// Forget every cached node, and reset the counters
+    pub fn clear(&self) {
+        lock(&self.slots).fill(None);
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Searches which went straight to a cached base node
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Searches which descended through every layer
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// A clone of an index shares the addresses of its nodes, so the cached nodes carry over
+#[cfg(feature = "std")]
+impl<SA: Address + Copy> Clone for FastFences<SA> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: Mutex::new(lock(&self.slots).clone()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn fast_fence_slots_spread_wide_keys() {
+        // Keys past 64 bits, whose few set bits alone would only reach a handful of slots
+        let wide: HashSet<usize> = (0..1_000u128)
+            .map(|key| FastFences::<usize>::slot(&((1 << 100) + (key << 70)), 4096))
+            .collect();
+        assert!(wide.len() > 129, "{} slots", wide.len());
+
+        let negative: HashSet<usize> = (0..1_000i128)
+            .map(|key| FastFences::<usize>::slot(&(i128::MIN + (key << 70)), 4096))
+            .collect();
+        assert!(negative.len() > 129, "{} slots", negative.len());
+    }
+}
@@ -0,0 +1,110 @@
+//! Caps on the length of trained segments. Keys which follow a line closely can be segmented into
+//! a single huge node, which is cheap to search but slow to grow, since every insert into a full
+//! node reallocates all of its keys. Capping the length of segments bounds that cost.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use learned_index_segmentation::SegmentationModel;
+use serde::{Deserialize, Serialize};
+
+/// A model `M` whose trained segments hold at most `MAX_LEN` entries, selected with the `max_len`
+/// field of a `pgm` component. Longer segments are split into runs of `MAX_LEN` entries, and a
+/// model is retrained for each run. Only training is capped: nodes still grow past `MAX_LEN` as
+/// keys are inserted, until the layer is rebuilt.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Capped<M, const MAX_LEN: usize> {
+    model: M,
+}
+
+impl<K, M, const MAX_LEN: usize> SegmentationModel<K> for Capped<M, MAX_LEN>
+where
+    M: SegmentationModel<K>,
+{
+    fn train<V>(data: impl Iterator<Item = (K, V)>) -> Vec<(Self, Vec<(K, V)>)> {
+        assert!(MAX_LEN > 0, "A segment must hold at least one entry!");
+
+        let mut result = Vec::new();
+
+        for (model, entries) in M::train(data) {
+            if entries.len() <= MAX_LEN {
+                result.push((Self { model }, entries));
+                continue;
+            }
+
+            let mut entries = entries.into_iter();
+            loop {
+                let run: Vec<(K, V)> = entries.by_ref().take(MAX_LEN).collect();
+                if run.is_empty() {
+                    break;
+                }
+
+                // A run is never longer than the cap, so neither are the segments trained on it
+                result.extend(
+                    M::train(run.into_iter())
+                        .into_iter()
+                        .map(|(model, entries)| (Self { model }, entries)),
+                );
+            }
+        }
+
+        result
+    }
+
+    fn sentinel() -> Self {
+        Self {
+            model: M::sentinel(),
+        }
+    }
+
+    fn min_key(&self) -> &K {
+        self.model.min_key()
+    }
+
+    fn approximate(&self, key: &K) -> Range<usize> {
+        self.model.approximate(key)
+    }
+
+    fn hint(&self, key: &K) -> usize {
+        self.model.hint(key)
+    }
+
+    fn rescale(&mut self, c: f64) {
+        self.model.rescale(c);
+    }
+
+    fn epsilon() -> Option<usize> {
+        M::epsilon()
+    }
+
+    fn line(&self) -> Option<(f64, f64)> {
+        self.model.line()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use learned_index_segmentation::LinearModel;
+
+    #[test]
+    fn capped_train_splits() {
+        type Model = Capped<LinearModel<u64, 16>, 100>;
+
+        let trained = Model::train((0..1_050u64).map(|key| (key * 3, key)));
+        let lens: Vec<usize> = trained.iter().map(|(_, entries)| entries.len()).collect();
+
+        assert!(lens.iter().all(|&len| len <= 100));
+        assert_eq!(lens.iter().sum::<usize>(), 1_050);
+        assert_eq!(trained.len(), 11);
+
+        let values: Vec<u64> = trained
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(_, value)| *value))
+            .collect();
+        assert_eq!(values, (0..1_050).collect::<Vec<_>>());
+
+        for (model, entries) in &trained {
+            assert_eq!(model.min_key(), &entries[0].0);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Verified training. Models approximate ranks with `f64` arithmetic, so when keys are spread over
+//! a very wide range such as the whole of `u128`, or a transform maps many keys onto nearly the same
+//! one, a trained line can lose enough precision that some keys fall outside of its approximation
+//! window, where searches which trust the window never find them.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use learned_index_segmentation::SegmentationModel;
+use serde::{Deserialize, Serialize};
+
+/// A model `M` whose trained segments are checked against their approximation windows, selected
+/// with the `checked` flag of a `pgm` component. A segment in which any key falls outside of the
+/// window around its rank is split in half, and a model is retrained for each half, until every
+/// key is approximated within bounds. A segment of a single key is always within bounds.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checked<M> {
+    model: M,
+}
+
+impl<M> Checked<M> {
+    /// Push the segment trained by `model` if it holds up, otherwise split it
+    fn push_verified<K, V>(
+        model: M,
+        mut entries: Vec<(K, V)>,
+        result: &mut Vec<(Self, Vec<(K, V)>)>,
+    ) where
+        M: SegmentationModel<K>,
+    {
+        let within = entries
+            .iter()
+            .enumerate()
+            .all(|(rank, (key, _))| model.approximate(key).contains(&rank));
+
+        if within || entries.len() <= 1 {
+            result.push((Self { model }, entries));
+            return;
+        }
+
+        let right = entries.split_off(entries.len() / 2);
+        for half in [entries, right] {
+            for (model, entries) in M::train(half.into_iter()) {
+                Self::push_verified(model, entries, result);
+            }
+        }
+    }
+}
+
+impl<K, M> SegmentationModel<K> for Checked<M>
+where
+    M: SegmentationModel<K>,
+{
+    fn train<V>(data: impl Iterator<Item = (K, V)>) -> Vec<(Self, Vec<(K, V)>)> {
+        let mut result = Vec::new();
+
+        for (model, entries) in M::train(data) {
+            Self::push_verified(model, entries, &mut result);
+        }
+
+        result
+    }
+
+    fn sentinel() -> Self {
+        Self {
+            model: M::sentinel(),
+        }
+    }
+
+    fn min_key(&self) -> &K {
+        self.model.min_key()
+    }
+
+    fn approximate(&self, key: &K) -> Range<usize> {
+        self.model.approximate(key)
+    }
+
+    fn hint(&self, key: &K) -> usize {
+        self.model.hint(key)
+    }
+
+    fn rescale(&mut self, c: f64) {
+        self.model.rescale(c);
+    }
+
+    fn epsilon() -> Option<usize> {
+        M::epsilon()
+    }
+
+    fn line(&self) -> Option<(f64, f64)> {
+        self.model.line()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learned::{LogTransform, Transformed};
+    use learned_index_segmentation::LinearModel;
+
+    /// Number of keys outside of the window of the segment they were trained into
+    fn misses<M: SegmentationModel<u128>>(keys: &[u128]) -> usize {
+        let trained = M::train(keys.iter().map(|&key| (key, ())));
+
+        let trained_keys: Vec<u128> = trained
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(key, _)| *key))
+            .collect();
+        assert_eq!(trained_keys, keys);
+
+        trained
+            .iter()
+            .flat_map(|(model, entries)| {
+                entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(rank, (key, _))| !model.approximate(key).contains(rank))
+            })
+            .count()
+    }
+
+    #[test]
+    fn checked_train_within_bounds() {
+        type Model = Transformed<u128, LinearModel<u128, 4>, LogTransform>;
+
+        // Dense keys far from zero all have the same logarithm, which the line can't tell apart
+        let keys: Vec<u128> = (0..1_000).map(|key| (1 << 100) + key).collect();
+
+        assert!(misses::<Model>(&keys) > 0);
+        assert_eq!(misses::<Checked<Model>>(&keys), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn checked_models_persist() {
+        type Model = Checked<Transformed<u128, LinearModel<u128, 4>, LogTransform>>;
+
+        fn round_trip<M: learned_index_segmentation::PersistedModel<u128>>(model: &M) -> M {
+            bincode::deserialize(&bincode::serialize(model).unwrap()).unwrap()
+        }
+
+        let keys: Vec<u128> = (0..1_000).map(|key| (1 << 100) + key * key).collect();
+
+        for (model, entries) in Model::train(keys.iter().map(|&key| (key, ()))) {
+            let copy = round_trip(&model);
+
+            assert_eq!(copy.min_key(), model.min_key());
+            for (key, _) in entries {
+                assert_eq!(copy.approximate(&key), model.approximate(&key));
+            }
+        }
+    }
+}
@@ -1,5 +1,19 @@
+pub mod capped;
+pub mod checked;
 pub mod pgm_memory;
+pub mod rmi_top;
+pub mod transform;
+pub mod viz;
 
 mod node;
+mod packed;
+mod report;
 
+pub use capped::Capped;
+pub use checked::Checked;
+pub use learned_index_segmentation::{LinearModel, PersistedModel, SegmentationModel};
 pub use pgm_memory::*;
+pub use report::LayerReport;
+pub use rmi_top::RMITopComponent;
+pub use transform::{AffineTransform, KeyTransform, LogTransform, Transformed};
+pub use viz::{KeyPoint, LayerPlot, SegmentPlot};
@@ -1,68 +1,301 @@
-use learned_index_segmentation::LinearModel;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use learned_index_segmentation::{LinearModel, SegmentationModel};
 
-use crate::{Key, KeyBounded, StaticBounded};
-use gapped_array::GappedKVArray;
+use crate::common::prefetch::prefetch_slice;
+use crate::explain::Probe;
+use crate::learned::packed::PackedKeys;
+use crate::traits::PrefetchData;
+use crate::{Key, KeyBound, KeyBounded};
+use gapped_array::{GappedKVArray, SearchMode};
+use num::PrimInt;
 
-impl<K: StaticBounded, const EPSILON: usize> KeyBounded<K> for LinearModel<K, EPSILON> {
-    fn lower_bound(&self) -> &K {
-        self.min_key()
+impl<K, const EPSILON: usize> KeyBounded<K> for LinearModel<K, EPSILON> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        KeyBound::Key(self.min_key())
     }
 }
 
-#[derive(Debug)]
-pub struct PGMNode<K: Key, V, const EPSILON: usize> {
-    gapped: GappedKVArray<K, V>,
-    model: LinearModel<K, EPSILON>,
+/// Models with an epsilon at least this wide are searched by galloping from their hints, since
+/// keys are then often far from where they are predicted
+pub const GALLOP_EPSILON: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct PGMNode<K: Key + PrimInt, V, M> {
+    entries: Entries<K, V>,
+    model: M,
+}
+
+/// The entries of a node, either in a gapped array which can be inserted into, or with their keys
+/// packed for nodes which are only read
+#[derive(Debug, Clone)]
+enum Entries<K: Key + PrimInt, V> {
+    Gapped(GappedKVArray<K, V>),
+    Packed(PackedKeys<K>, Vec<V>),
 }
 
-impl<K: Key, V, const EPSILON: usize> KeyBounded<K> for PGMNode<K, V, EPSILON> {
-    fn lower_bound(&self) -> &K {
-        self.gapped.min().unwrap_or(&K::max_ref())
+impl<K: Key + PrimInt, V, M> KeyBounded<K> for PGMNode<K, V, M> {
+    fn lower_bound(&self) -> KeyBound<&K> {
+        let min = match self.entries {
+            Entries::Gapped(ref gapped) => gapped.min(),
+            Entries::Packed(ref keys, _) => keys.first(),
+        };
+
+        match min {
+            Some(key) => KeyBound::Key(key),
+            None => KeyBound::PosInf,
+        }
+    }
+}
+
+impl<K: Key + PrimInt, V, M> PrefetchData for PGMNode<K, V, M> {
+    fn prefetch_data(&self) {
+        match self.entries {
+            Entries::Gapped(ref gapped) => {
+                let (bitmap, keys, values) = gapped.raw_parts();
+                prefetch_slice(bitmap);
+                prefetch_slice(keys);
+                prefetch_slice(values);
+            }
+            Entries::Packed(ref keys, ref values) => {
+                prefetch_slice(keys.words());
+                prefetch_slice(values);
+            }
+        }
     }
 }
 
-impl<K: Key, V, const EPSILON: usize> Default for PGMNode<K, V, EPSILON> {
+impl<K: Key + PrimInt, V, M: SegmentationModel<K>> Default for PGMNode<K, V, M> {
     fn default() -> Self {
         Self {
-            gapped: GappedKVArray::new(0),
-            model: LinearModel::sentinel(),
+            entries: Entries::Gapped(GappedKVArray::new(0).with_mode(Self::search_mode())),
+            model: M::sentinel(),
         }
     }
 }
 
-impl<K: Key, V, const EPSILON: usize> PGMNode<K, V, EPSILON> {
-    pub fn from_trained(model: LinearModel<K, EPSILON>, entries: Vec<(K, V)>) -> Self {
+impl<K: Key + PrimInt, V, M: SegmentationModel<K>> PGMNode<K, V, M> {
+    pub fn from_trained(model: M, entries: Vec<(K, V)>) -> Self {
+        let gapped = Self::gapped(&model, entries);
+
+        Self {
+            entries: Entries::Gapped(gapped),
+            model,
+        }
+    }
+
+    fn gapped(model: &M, entries: Vec<(K, V)>) -> GappedKVArray<K, V> {
         // NOTE: Filling at 0.5 utilization is just a heuristic, eventually this should be a param
-        let mut gapped = GappedKVArray::new(entries.len() * 2);
+        let mut gapped = GappedKVArray::new(entries.len() * 2).with_mode(Self::search_mode());
         for (key, value) in entries {
             let hint = model.hint(&key).min(gapped.len() - 1);
             gapped
                 .initial_model_based_insert((key, value), hint)
                 .unwrap();
         }
-        Self { gapped, model }
+        gapped
+    }
+
+    /// How the gapped entries of nodes are searched outwards from the hints of their models
+    pub fn search_mode() -> SearchMode {
+        match M::epsilon() {
+            Some(epsilon) if epsilon >= GALLOP_EPSILON => SearchMode::Gallop,
+            _ => SearchMode::Scan,
+        }
     }
 
     pub fn search_exact(&self, key: &K) -> Option<&V> {
         let hint = self.model.hint(key);
-        self.gapped.search_exact(key, Some(hint))
+        self.trace_window(key);
+
+        match self.entries {
+            Entries::Gapped(ref gapped) => gapped.search_exact(key, Some(hint)),
+            Entries::Packed(ref keys, ref values) => match keys.floor(key, hint).0 {
+                Some(index) if keys.get(index) == *key => Some(&values[index]),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn search_exact_mut(&mut self, key: &K) -> Option<&mut V> {
+        let hint = self.model.hint(key);
+        self.trace_window(key);
+
+        match self.entries {
+            Entries::Gapped(ref mut gapped) => gapped.search_exact_mut(key, Some(hint)),
+            Entries::Packed(ref keys, ref mut values) => match keys.floor(key, hint).0 {
+                Some(index) if keys.get(index) == *key => Some(&mut values[index]),
+                _ => None,
+            },
+        }
+    }
+
+    /// The stored entry of `key`. Packed nodes decode their keys as they are searched, so only
+    /// gapped nodes can lend theirs out.
+    pub fn get_entry(&self, key: &K) -> Option<(&K, &V)> {
+        let Entries::Gapped(ref gapped) = self.entries else {
+            panic!("Packed nodes don't store their keys!");
+        };
+
+        self.trace_window(key);
+        gapped.search_exact_entry(key, Some(self.model.hint(key)))
     }
 
     pub fn search_pir(&self, key: &K) -> &V {
         let hint = self.model.hint(key);
-        match self.gapped.search_pir(key, Some(hint)) {
-            Some(val) => val,
-            None => self.gapped.min_val().unwrap(),
+        self.trace_window(key);
+
+        match self.entries {
+            Entries::Gapped(ref gapped) => match gapped.search_pir(key, Some(hint)) {
+                Some(val) => val,
+                None => gapped.min_val().unwrap(),
+            },
+            Entries::Packed(ref keys, ref values) => &values[keys.floor(key, hint).0.unwrap_or(0)],
+        }
+    }
+
+    /// The approximation window of the model for `key`, and the comparisons made to search it
+    pub fn probe(&self, key: &K) -> Probe {
+        let hint = self.model.hint(key);
+        let comparisons = match self.entries {
+            Entries::Gapped(ref gapped) => gapped.search_comparisons(key, Some(hint)),
+            Entries::Packed(ref keys, _) => keys.floor(key, hint).1,
+        };
+
+        Probe {
+            window: Some(self.model.approximate(key).len()),
+            comparisons,
+        }
+    }
+
+    /// Report the width of the approximation window of the model for `key`
+    #[inline(always)]
+    fn trace_window(&self, key: &K) {
+        #[cfg(feature = "trace")]
+        tracing::trace!(window = self.model.approximate(key).len());
+
+        #[cfg(not(feature = "trace"))]
+        let _ = key;
+    }
+
+    pub fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        match self.entries {
+            Entries::Gapped(ref gapped) => Box::new(gapped.iter().map(|(_, _, value)| value)),
+            Entries::Packed(_, ref values) => Box::new(values.iter()),
+        }
+    }
+
+    pub fn values_mut(&mut self) -> Box<dyn Iterator<Item = &mut V> + '_> {
+        match self.entries {
+            Entries::Gapped(ref mut gapped) => Box::new(gapped.values_mut()),
+            Entries::Packed(_, ref mut values) => Box::new(values.iter_mut()),
+        }
+    }
+
+    /// Number of keys stored in the node
+    pub fn size(&self) -> usize {
+        match self.entries {
+            Entries::Gapped(ref gapped) => gapped.size(),
+            Entries::Packed(ref keys, _) => keys.len(),
         }
     }
 
-    pub fn grow_insert(&mut self, entry: (K, V)) {
-        if self.gapped.density() >= 0.8 {
+    /// Number of slots of the node, including gaps
+    pub fn capacity(&self) -> usize {
+        match self.entries {
+            Entries::Gapped(ref gapped) => gapped.len(),
+            Entries::Packed(ref keys, _) => keys.len(),
+        }
+    }
+
+    /// Bytes held by the slots of the node, which live on the heap
+    pub fn heap_size(&self) -> usize {
+        match self.entries {
+            Entries::Gapped(ref gapped) => {
+                gapped.len() * (1 + core::mem::size_of::<K>() + core::mem::size_of::<V>())
+            }
+            Entries::Packed(ref keys, ref values) => {
+                keys.heap_size() + values.len() * core::mem::size_of::<V>()
+            }
+        }
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Whether the keys of the node are packed
+    pub fn is_packed(&self) -> bool {
+        matches!(self.entries, Entries::Packed(..))
+    }
+
+    /// Every key in the node, along with the slot it is stored in
+    pub fn slots(&self) -> Box<dyn Iterator<Item = (usize, K)> + '_> {
+        match self.entries {
+            Entries::Gapped(ref gapped) => Box::new(gapped.iter().map(|(ix, key, _)| (ix, *key))),
+            Entries::Packed(ref keys, _) => Box::new(keys.iter().enumerate()),
+        }
+    }
+
+    /// Sum of the distances between the slot predicted by the model and the actual slot of each
+    /// key in the node
+    pub fn model_error(&self) -> usize {
+        self.slots()
+            .map(|(ix, key)| self.model.hint(&key).abs_diff(ix))
+            .sum()
+    }
+
+    /// Pack the keys of the node, unless they are too far apart to take fewer bits packed. Since
+    /// the model was trained on the ranks of the keys, it predicts their index among the packed
+    /// keys just as well.
+    pub fn pack(&mut self)
+    where
+        V: Clone,
+    {
+        let Entries::Gapped(ref gapped) = self.entries else {
+            return;
+        };
+
+        let keys: Vec<K> = gapped.iter().map(|(_, key, _)| *key).collect();
+        if let Some(packed) = PackedKeys::pack(&keys) {
+            let values = gapped.iter().map(|(_, _, value)| value.clone()).collect();
+            self.entries = Entries::Packed(packed, values);
+        }
+    }
+
+    /// Insert an entry, returning the value its key held before. Packed nodes are unpacked first.
+    pub fn grow_insert(&mut self, entry: (K, V)) -> Option<V> {
+        if let Entries::Packed(..) = self.entries {
+            let Entries::Packed(keys, values) =
+                core::mem::replace(&mut self.entries, Entries::Gapped(GappedKVArray::new(0)))
+            else {
+                unreachable!()
+            };
+
+            let entries = keys.iter().zip(values).collect();
+            self.entries = Entries::Gapped(Self::gapped(&self.model, entries));
+        }
+
+        let Entries::Gapped(ref mut gapped) = self.entries else {
+            unreachable!()
+        };
+
+        // An empty node, such as the cap of an empty layer, has no slots to insert into and only a
+        // sentinel model, so it is trained afresh over the entry
+        if gapped.is_empty() {
+            let (model, entries) = M::train(core::iter::once(entry))
+                .pop()
+                .expect("training over an entry yields a segment");
+            *self = Self::from_trained(model, entries);
+            return None;
+        }
+
+        if gapped.density() >= 0.8 {
             let scale_factor = 2.0;
-            self.gapped.rescale(scale_factor).unwrap();
+            gapped.rescale(scale_factor).unwrap();
             self.model.rescale(scale_factor as f64);
         }
         let hint = self.model.hint(&entry.0);
-        self.gapped.upsert_with_hint(entry, hint).unwrap();
+        gapped.upsert_with_hint(entry, hint).unwrap()
     }
 }
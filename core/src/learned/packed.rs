@@ -0,0 +1,242 @@
+//! Sorted keys stored as bit-packed residuals from a line through the first and last key. The keys
+//! of a learned segment are nearly linear by construction, so the residuals only take a few bits
+//! each, far fewer than the keys themselves.
+
+use crate::Key;
+use alloc::vec;
+use alloc::vec::Vec;
+use num::PrimInt;
+
+/// A sorted sequence of keys, packed into as few bits per key as their residuals need
+#[derive(Debug, Clone)]
+pub struct PackedKeys<K> {
+    first: K,
+    len: usize,
+
+    /// Mean distance between consecutive keys, the slope of the line keys are predicted by
+    gap: f64,
+
+    /// Smallest residual, which every stored residual is relative to
+    offset: i128,
+
+    /// Bits taken by every residual
+    width: u32,
+    words: Vec<u64>,
+}
+
+impl<K> PackedKeys<K> {
+    /// The residuals of the keys, packed into words
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+}
+
+impl<K: Key + PrimInt> PackedKeys<K> {
+    /// Pack sorted `keys`, or `None` if their residuals don't take fewer bits than the keys. Keys
+    /// further than 64 bits apart are never packed.
+    pub fn pack(keys: &[K]) -> Option<Self> {
+        let first = *keys.first()?;
+        let offsets: Vec<u64> = keys
+            .iter()
+            .map(|key| num::cast::<K, u64>(key.checked_sub(&first)?))
+            .collect::<Option<_>>()?;
+
+        let span = *offsets.last().unwrap();
+        let gap = match keys.len() {
+            1 => 0.0,
+            len => span as f64 / (len - 1) as f64,
+        };
+
+        let residuals: Vec<i128> = offsets
+            .iter()
+            .enumerate()
+            .map(|(index, offset)| *offset as i128 - predict(gap, index) as i128)
+            .collect();
+
+        let offset = *residuals.iter().min().unwrap();
+        let max = residuals
+            .iter()
+            .map(|residual| residual - offset)
+            .max()
+            .unwrap();
+        let width = 128 - max.leading_zeros();
+
+        if width > 64 || width as usize >= 8 * core::mem::size_of::<K>() {
+            return None;
+        }
+
+        let mut words = vec![0u64; (keys.len() * width as usize).div_ceil(64)];
+        for (index, residual) in residuals.into_iter().enumerate() {
+            let value = (residual - offset) as u64;
+            let bit = index * width as usize;
+            let (word, shift) = (bit / 64, bit % 64);
+
+            if width == 0 {
+                continue;
+            }
+
+            words[word] |= value << shift;
+            if shift + width as usize > 64 {
+                words[word + 1] |= value >> (64 - shift);
+            }
+        }
+
+        Some(Self {
+            first,
+            len: keys.len(),
+            gap,
+            offset,
+            width,
+            words,
+        })
+    }
+
+    /// The key at `index`
+    pub fn get(&self, index: usize) -> K {
+        let width = self.width as usize;
+        let bit = index * width;
+        let (word, shift) = (bit / 64, bit % 64);
+
+        let residual = match width {
+            0 => 0,
+            _ => {
+                let mut value = self.words[word] >> shift;
+                if shift + width > 64 {
+                    value |= self.words[word + 1] << (64 - shift);
+                }
+
+                value & (u64::MAX >> (64 - width))
+            }
+        };
+
+        let offset = predict(self.gap, index) as i128 + self.offset + residual as i128;
+        self.first + num::cast::<u64, K>(offset as u64).unwrap()
+    }
+
+    /// The first key, which is stored as is
+    pub fn first(&self) -> Option<&K> {
+        match self.len {
+            0 => None,
+            _ => Some(&self.first),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Every key, in order
+    pub fn iter(&self) -> impl Iterator<Item = K> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    /// Index of the largest key at most `key`, searching outwards from `hint`, along with the
+    /// number of keys compared
+    pub fn floor(&self, key: &K, hint: usize) -> (Option<usize>, usize) {
+        let mut comparisons = 0;
+
+        if self.len == 0 {
+            return (None, comparisons);
+        }
+
+        // Gallop from the hint until the key is bracketed by `low` and `high`
+        let start = hint.min(self.len - 1);
+        let mut low;
+        let mut high;
+        let mut step = 1;
+
+        if self.at_most(start, key, &mut comparisons) {
+            low = start;
+            loop {
+                let next = low + step;
+                if next >= self.len {
+                    high = self.len;
+                    break;
+                }
+
+                if !self.at_most(next, key, &mut comparisons) {
+                    high = next;
+                    break;
+                }
+
+                low = next;
+                step *= 2;
+            }
+        } else {
+            high = start;
+            loop {
+                let next = high.saturating_sub(step);
+                if self.at_most(next, key, &mut comparisons) {
+                    low = next;
+                    break;
+                }
+
+                if next == 0 {
+                    return (None, comparisons);
+                }
+
+                high = next;
+                step *= 2;
+            }
+        }
+
+        while high - low > 1 {
+            let middle = (low + high) / 2;
+            if self.at_most(middle, key, &mut comparisons) {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        (Some(low), comparisons)
+    }
+
+    fn at_most(&self, index: usize, key: &K, comparisons: &mut usize) -> bool {
+        *comparisons += 1;
+        self.get(index) <= *key
+    }
+
+    /// Bytes held by the packed residuals, which live on the heap
+    pub fn heap_size(&self) -> usize {
+        self.words.len() * core::mem::size_of::<u64>()
+    }
+}
+
+/// Offset of the key at `index` from the first key, as predicted by the line
+fn predict(gap: f64, index: usize) -> u64 {
+    (gap * index as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_keys_round_trip() {
+        let keys: Vec<i64> = (0..1_000).map(|i| -5_000 + i * 7 + (i * i) % 5).collect();
+        let packed = PackedKeys::pack(&keys).unwrap();
+
+        assert_eq!(packed.iter().collect::<Vec<_>>(), keys);
+        assert!(packed.heap_size() * 4 < keys.len() * core::mem::size_of::<i64>());
+
+        for (index, key) in keys.iter().enumerate() {
+            for hint in [0, index, keys.len() - 1] {
+                assert_eq!(packed.floor(key, hint).0, Some(index));
+                assert_eq!(packed.floor(&(key + 1), hint).0, Some(index));
+            }
+        }
+        assert_eq!(packed.floor(&-5_001, 500).0, None);
+    }
+
+    #[test]
+    fn packed_keys_skip_wide_spans() {
+        assert!(PackedKeys::pack(&[i128::MIN, 0, i128::MAX]).is_none());
+        assert!(PackedKeys::pack(&[0u8, 1, 2, 255]).is_none());
+
+        // Perfectly linear keys take no bits at all
+        let packed = PackedKeys::pack(&[10u32, 20, 30, 40]).unwrap();
+        assert_eq!(packed.heap_size(), 0);
+        assert_eq!(packed.get(3), 40);
+    }
+}
@@ -2,17 +2,28 @@
 // Layer Type
 // ----------------------------------------
 
-use std::ops::Bound;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::{Bound, RangeBounds};
+use hashbrown::HashMap;
 
-use learned_index_segmentation::linear_simple_segmentation;
+use learned_index_segmentation::SegmentationModel;
+use num::PrimInt;
 
+use crate::common::list::alloc::{ArenaAlloc, DefaultAlloc};
 use crate::common::list::memory::*;
+use crate::component::{LayerInsert, RebuildPlan};
 use crate::iter::Iter;
 use crate::learned::node::PGMNode;
+use crate::learned::{LayerPlot, LayerReport};
 use crate::{impl_node_layer, Address, Key, NodeLayer};
 
-pub struct MemoryPGMLayer<K: Key, V, const EPSILON: usize, PA> {
-    inner: MemoryList<PGMNode<K, V, EPSILON>, PA>,
+#[derive(Clone)]
+pub struct MemoryPGMLayer<K: Key + PrimInt, V, M, PA, AL: ArenaAlloc = DefaultAlloc> {
+    inner: MemoryList<PGMNode<K, V, M>, PA, AL>,
+
+    /// Times every node of the layer was replaced, by a build or a rebuild
+    rebuilds: usize,
 }
 
 struct FillerIter<'a, K, B, SA, PA>
@@ -24,7 +35,7 @@ where
 }
 impl<'a, K, B, SA, PA> Iterator for FillerIter<'a, K, B, SA, PA>
 where
-    K: Clone,
+    K: Key + PrimInt,
     B: NodeLayer<K, SA, PA>,
     SA: Address,
     PA: Address,
@@ -32,60 +43,216 @@ where
     type Item = (K, SA);
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some((key, address)) => Some((key, address)),
-            None => None,
-        }
+        self.iter
+            .next()
+            .map(|(key, address)| (key.resolve(), address))
     }
 }
 
-impl<K, V, const EPSILON: usize, PA> MemoryPGMLayer<K, V, EPSILON, PA>
+impl<K, V, M, PA, AL> MemoryPGMLayer<K, V, M, PA, AL>
 where
-    K: Key,
+    K: Key + PrimInt,
+    V: Clone,
+    M: SegmentationModel<K>,
+    PA: Clone,
+    AL: ArenaAlloc,
 {
-    pub fn empty() -> Self {
+    pub fn empty() -> Self
+    where
+        AL: Default,
+    {
+        Self::with_alloc(AL::default())
+    }
+
+    pub fn with_alloc(alloc: AL) -> Self {
         Self {
-            inner: MemoryList::empty(),
+            inner: MemoryList::with_alloc(alloc),
+            rebuilds: 0,
         }
     }
 
     pub fn fill(&mut self, iter: impl Iterator<Item = (K, V)>) {
-        let trained = linear_simple_segmentation::<_, _, EPSILON>(iter);
+        let trained = M::train(iter);
+        self.record_replace(
+            trained.len(),
+            trained.iter().map(|(_, entries)| entries.len()).sum(),
+            None,
+        );
 
-        let mut ptr = self.inner.clear();
+        // One node per segment, plus the cap node
+        let mut ptr = self.inner.clear_with_hint(|| trained.len() + 1);
 
         for (model, entries) in trained.into_iter().rev() {
             let node = PGMNode::from_trained(model, entries);
+            self.inner.track_heap(0, node.heap_size());
             ptr = self.inner.insert_before(node, ptr);
         }
     }
 
+    /// Pack the keys of every node, see `PGMNode::pack`. Nodes are unpacked again as they are
+    /// inserted into.
+    pub fn pack(&mut self)
+    where
+        PA: Address,
+    {
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(current) = ptr {
+            let before = self.inner[current].heap_size();
+            self.inner[current].pack();
+            self.inner
+                .track_heap(before, self.inner[current].heap_size());
+            ptr = self.inner.next(current);
+        }
+    }
+
+    /// Lay the nodes out in list order, see `MemoryList::compact`
+    pub fn compact(&mut self) -> Remap {
+        self.inner.compact()
+    }
+
+    pub fn remap_parents(&mut self, remap: &HashMap<PA, PA>)
+    where
+        PA: Eq + Hash,
+    {
+        self.inner.remap_parents(remap);
+    }
+
+    /// Point every value at where its node moved when the layer below was compacted
+    pub fn remap_values(&mut self, remap: &HashMap<V, V>)
+    where
+        V: Eq + Hash,
+    {
+        self.inner.for_each_mut(|node| {
+            for value in node.values_mut() {
+                if let Some(moved) = remap.get(value) {
+                    *value = moved.clone();
+                }
+            }
+        });
+    }
+
     pub fn fill_will_parent<B: NodeLayer<K, V, ArenaID>>(&mut self, base: &mut B)
     where
         V: Address,
     {
-        let iter = base.range(Bound::Unbounded, Bound::Unbounded);
+        let plan = Self::plan_with_parent(base);
+        self.install(base, plan);
+    }
+
+    /// Train the nodes of the layer on `base`, without touching either
+    pub fn plan_with_parent<B: NodeLayer<K, V, ArenaID>>(base: &B) -> RebuildPlan<PGMNode<K, V, M>>
+    where
+        V: Address,
+    {
+        let iter = base.range(Bound::Unbounded, Bound::Unbounded).anchored();
         let iter = FillerIter { iter };
 
-        let trained = linear_simple_segmentation::<_, _, EPSILON>(iter);
+        let nodes = M::train(iter)
+            .into_iter()
+            .map(|(model, entries)| PGMNode::from_trained(model, entries))
+            .collect();
 
-        let mut ptr = self.inner.clear();
+        RebuildPlan::new(nodes, base.node_count())
+    }
 
-        for (model, entries) in trained.into_iter().rev() {
-            let node = PGMNode::from_trained(model, entries.clone());
+    pub fn apply_with_parent<B: NodeLayer<K, V, ArenaID>>(
+        &mut self,
+        base: &mut B,
+        plan: RebuildPlan<PGMNode<K, V, M>>,
+    ) -> crate::Result<()>
+    where
+        V: Address,
+    {
+        plan.check(base)?;
+        self.install(base, plan);
+        Ok(())
+    }
+
+    fn install<B: NodeLayer<K, V, ArenaID>>(
+        &mut self,
+        base: &mut B,
+        plan: RebuildPlan<PGMNode<K, V, M>>,
+    ) where
+        V: Address,
+    {
+        let entries = plan.nodes.iter().map(PGMNode::size).sum();
+        self.record_replace(plan.len(), entries, Some(plan.base_nodes()));
+
+        // One node per segment, plus the cap node
+        let mut ptr = self.inner.clear_with_hint(|| plan.len() + 1);
+
+        for node in plan.nodes.into_iter().rev() {
+            self.inner.track_heap(0, node.heap_size());
             ptr = self.inner.insert_before(node, ptr);
-            for (_, value) in entries.iter() {
+            for value in self.inner[ptr].values() {
                 base.set_parent(value.clone(), ptr);
             }
         }
     }
 
-    pub fn insert(&mut self, key: K, value: V, ptr: ArenaID) -> Option<(K, ArenaID, PA)>
+    /// Count a replacement of every node of the layer, and with the `debug-internals` feature
+    /// emit an event with the number of nodes and entries it installs. `base_nodes` is the number
+    /// of nodes below the layer a rebuild plan was checked against.
+    fn record_replace(&mut self, nodes: usize, entries: usize, base_nodes: Option<usize>) {
+        self.rebuilds += 1;
+
+        #[cfg(feature = "debug-internals")]
+        tracing::debug!(
+            target: "limousine::replace",
+            replaced = self.inner.len(),
+            nodes,
+            entries,
+            base_nodes,
+            rebuilds = self.rebuilds,
+        );
+
+        #[cfg(not(feature = "debug-internals"))]
+        let _ = (nodes, entries, base_nodes);
+    }
+
+    pub fn report(&self, epsilon: usize) -> LayerReport
     where
         PA: Address,
     {
-        self.inner[ptr].grow_insert((key, value));
-        None
+        let mut nodes = Vec::new();
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(current) = ptr {
+            let node = &self.inner[current];
+            nodes.push((node.size(), node.model_error()));
+            ptr = self.inner.next(current);
+        }
+
+        LayerReport {
+            rebuilds: self.rebuilds,
+            ..LayerReport::from_nodes(epsilon, nodes.into_iter())
+        }
+    }
+
+    /// Plot the segments of the layer holding keys in `range`
+    pub fn plot(&self, range: &impl RangeBounds<K>) -> LayerPlot
+    where
+        PA: Address,
+    {
+        let mut nodes = Vec::new();
+        let mut ptr = Some(self.inner.first());
+
+        while let Some(current) = ptr {
+            nodes.push(&self.inner[current]);
+            ptr = self.inner.next(current);
+        }
+
+        LayerPlot::from_nodes(nodes.into_iter(), range)
+    }
+
+    /// Insert an entry into the segment at `ptr`, returning the value the key held before.
+    /// Segments grow in place, so no node is ever split off.
+    pub fn insert(&mut self, key: K, value: V, ptr: ArenaID) -> LayerInsert<K, V, ArenaID, PA>
+    where
+        PA: Address,
+    {
+        (self.grow_insert(ptr, (key, value)), None)
     }
 
     pub fn insert_with_parent<B: NodeLayer<K, V, ArenaID>>(
@@ -99,26 +266,48 @@ where
         V: Address,
         PA: Address,
     {
-        self.inner[ptr].grow_insert((key, value.clone()));
+        self.grow_insert(ptr, (key, value.clone()));
         base.set_parent(value, ptr);
         None
     }
+
+    /// Insert into the segment at `ptr`, accounting for the slots it grows by
+    fn grow_insert(&mut self, ptr: ArenaID, entry: (K, V)) -> Option<V> {
+        let before = self.inner[ptr].heap_size();
+        let previous = self.inner[ptr].grow_insert(entry);
+        self.inner.track_heap(before, self.inner[ptr].heap_size());
+
+        previous
+    }
 }
 
-impl<K: Key, V, const EPSILON: usize, PA> core::ops::Index<ArenaID>
-    for MemoryPGMLayer<K, V, EPSILON, PA>
+impl<K: Key + PrimInt, V, M, PA, AL: ArenaAlloc> core::ops::Index<ArenaID>
+    for MemoryPGMLayer<K, V, M, PA, AL>
 {
-    type Output = PGMNode<K, V, EPSILON>;
+    type Output = PGMNode<K, V, M>;
 
     fn index(&self, index: ArenaID) -> &Self::Output {
         &self.inner[index]
     }
 }
 
-impl<K, V, const EPSILON: usize, PA> NodeLayer<K, ArenaID, PA> for MemoryPGMLayer<K, V, EPSILON, PA>
+impl<K: Key + PrimInt, V, M, PA: Clone, AL: ArenaAlloc> core::ops::IndexMut<ArenaID>
+    for MemoryPGMLayer<K, V, M, PA, AL>
+where
+    PGMNode<K, V, M>: Clone,
+{
+    fn index_mut(&mut self, index: ArenaID) -> &mut Self::Output {
+        &mut self.inner[index]
+    }
+}
+
+impl<K, V, M, PA, AL> NodeLayer<K, ArenaID, PA> for MemoryPGMLayer<K, V, M, PA, AL>
 where
-    K: Key,
+    K: Key + PrimInt,
+    V: Clone,
+    M: SegmentationModel<K>,
     PA: Address,
+    AL: ArenaAlloc,
 {
     impl_node_layer!(ArenaID, PA);
 }
@@ -1,11 +1,20 @@
+use core::hash::Hash;
+use core::ops::RangeBounds;
+use hashbrown::HashMap;
+use learned_index_segmentation::{LinearModel, SegmentationModel};
 use num::PrimInt;
 
 use crate::{
-    common::list::memory::ArenaID, impl_node_layer, Address, BaseComponent, InternalComponent, Key,
-    NodeLayer, PropagateInsert, StaticBounded, Value,
+    common::list::memory::ArenaID,
+    explain::Probe,
+    impl_node_layer,
+    learned::{LayerPlot, LayerReport},
+    Address, BaseComponent, BaseInsert, CompactComponent, EntryComponent, InternalComponent, Key,
+    NodeLayer, PropagateInsert, RebuildComponent, RebuildPlan, RemapComponent, Value,
 };
 
-use self::layer::MemoryPGMLayer;
+pub use self::layer::MemoryPGMLayer;
+use super::node::PGMNode;
 
 mod layer;
 
@@ -15,34 +24,90 @@ mod layer;
 
 pub type PGMInternalAddress = ArenaID;
 
-pub struct PGMInternalComponent<K: Key, X: 'static, const EPSILON: usize, BA, PA> {
-    inner: MemoryPGMLayer<K, BA, EPSILON, PA>,
-    _ph: std::marker::PhantomData<X>,
+/// An internal layer of learned segments, whose values are the addresses of the nodes below. With
+/// `PACKED`, the keys of every segment built by `build` or a rebuild are stored as bit-packed
+/// residuals from a line, with the addresses stored densely next to them, see `PGMNode::pack`.
+/// Gapped segments hold twice as many slots as keys, so this shrinks the layer by more than half.
+/// A segment is unpacked again as soon as a split below inserts into it.
+#[derive(Clone)]
+pub struct PGMInternalComponent<
+    K: Key + PrimInt,
+    X: 'static,
+    const EPSILON: usize,
+    BA,
+    PA,
+    M = LinearModel<K, EPSILON>,
+    const PACKED: bool = false,
+> {
+    inner: MemoryPGMLayer<K, BA, M, PA>,
+    _ph: core::marker::PhantomData<X>,
 }
 
-impl<K, X, const EPSILON: usize, BA, PA> NodeLayer<K, PGMInternalAddress, PA>
-    for PGMInternalComponent<K, X, EPSILON, BA, PA>
+impl<K, X, const EPSILON: usize, BA, PA, M, const PACKED: bool> NodeLayer<K, PGMInternalAddress, PA>
+    for PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
 where
-    K: Clone + Ord + StaticBounded + PrimInt,
+    K: Key + PrimInt,
     BA: Address,
     PA: Address,
+    M: SegmentationModel<K>,
 {
     impl_node_layer!(ArenaID, PA);
 }
 
-impl<K, X, BA, PA, B: NodeLayer<K, BA, PGMInternalAddress>, const EPSILON: usize>
-    InternalComponent<K, B, BA, PGMInternalAddress, PA>
-    for PGMInternalComponent<K, X, EPSILON, BA, PA>
+impl<K, X, const EPSILON: usize, BA, PA, M, const PACKED: bool>
+    PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+    M: SegmentationModel<K>,
+{
+    /// Summarize how well this layer segmented its data
+    pub fn report(&self) -> LayerReport {
+        self.inner.report(EPSILON)
+    }
+
+    /// Plot the segments of this layer holding keys in `range`
+    pub fn plot(&self, range: impl RangeBounds<K>) -> LayerPlot {
+        self.inner.plot(&range)
+    }
+
+    /// The node to search from `ptr`. The cap node ending the layer holds no entries, and is only
+    /// reached by the maximum key, whose floor then lies in the node before it.
+    fn occupied(&self, ptr: PGMInternalAddress) -> PGMInternalAddress {
+        match self.inner[ptr].size() {
+            0 => self.inner.prev(ptr).unwrap_or(ptr),
+            _ => ptr,
+        }
+    }
+}
+
+impl<
+        K,
+        X,
+        BA,
+        PA,
+        B: NodeLayer<K, BA, PGMInternalAddress>,
+        const EPSILON: usize,
+        M,
+        const PACKED: bool,
+    > InternalComponent<K, B, BA, PGMInternalAddress, PA>
+    for PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
 where
     K: Key + PrimInt,
     BA: Address,
     PA: Address,
+    M: SegmentationModel<K>,
 {
     fn search(&self, _: &B, ptr: PGMInternalAddress, key: &K) -> BA {
-        let node = &self.inner[ptr];
+        let node = &self.inner[self.occupied(ptr)];
         node.search_pir(key).clone()
     }
 
+    fn probe(&self, _: &B, ptr: PGMInternalAddress, key: &K) -> Probe {
+        self.inner[self.occupied(ptr)].probe(key)
+    }
+
     fn insert(
         &mut self,
         base: &mut B,
@@ -63,10 +128,79 @@ where
         let mut result = MemoryPGMLayer::empty();
         result.fill_will_parent(base);
 
+        if PACKED {
+            result.pack();
+        }
+
         Self {
             inner: result,
-            _ph: std::marker::PhantomData,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        K,
+        X,
+        BA,
+        PA,
+        B: NodeLayer<K, BA, PGMInternalAddress>,
+        const EPSILON: usize,
+        M,
+        const PACKED: bool,
+    > RebuildComponent<K, B, BA, PGMInternalAddress, PA>
+    for PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address,
+    M: SegmentationModel<K>,
+{
+    type Node = PGMNode<K, BA, M>;
+
+    fn plan_rebuild(&self, base: &B) -> RebuildPlan<Self::Node> {
+        MemoryPGMLayer::<K, BA, M, PA>::plan_with_parent(base)
+    }
+
+    fn apply_rebuild(&mut self, base: &mut B, plan: RebuildPlan<Self::Node>) -> crate::Result<()> {
+        self.inner.apply_with_parent(base, plan)?;
+
+        if PACKED {
+            self.inner.pack();
         }
+
+        Ok(())
+    }
+}
+
+impl<K, X, const EPSILON: usize, BA, PA, M, const PACKED: bool>
+    CompactComponent<PGMInternalAddress, PA>
+    for PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    BA: Address,
+    PA: Address + Hash,
+    M: SegmentationModel<K>,
+{
+    fn compact(&mut self) -> HashMap<PGMInternalAddress, PGMInternalAddress> {
+        self.inner.compact()
+    }
+
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
+    }
+}
+
+impl<K, X, const EPSILON: usize, BA, PA, M, const PACKED: bool> RemapComponent<BA>
+    for PGMInternalComponent<K, X, EPSILON, BA, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    BA: Address + Hash,
+    PA: Address,
+    M: SegmentationModel<K>,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        self.inner.remap_values(remap);
     }
 }
 
@@ -76,54 +210,154 @@ where
 
 pub type PGMBaseAddress = PGMInternalAddress;
 
-pub struct PGMBaseComponent<K: Key, V, const EPSILON: usize, PA> {
-    inner: MemoryPGMLayer<K, V, EPSILON, PA>,
+/// The base layer of learned segments. With `PACKED`, the keys of every segment built by `build`
+/// are stored as bit-packed residuals from a line, see `PGMNode::pack`, which suits layers which
+/// are mostly read. A segment is unpacked again as soon as it is inserted into.
+#[derive(Clone)]
+pub struct PGMBaseComponent<
+    K: Key + PrimInt,
+    V,
+    const EPSILON: usize,
+    PA,
+    M = LinearModel<K, EPSILON>,
+    const PACKED: bool = false,
+> {
+    inner: MemoryPGMLayer<K, V, M, PA>,
+
+    /// Number of entries across every segment
+    len: usize,
 }
 
-impl<K, V, const EPSILON: usize, PA: 'static> NodeLayer<K, PGMBaseAddress, PA>
-    for PGMBaseComponent<K, V, EPSILON, PA>
+impl<K, V, const EPSILON: usize, PA: 'static, M, const PACKED: bool>
+    NodeLayer<K, PGMBaseAddress, PA> for PGMBaseComponent<K, V, EPSILON, PA, M, PACKED>
 where
     K: Key + PrimInt,
     V: Value,
     PA: Address,
+    M: SegmentationModel<K>,
 {
     impl_node_layer!(ArenaID, PA);
 }
 
-impl<K, V, const EPSILON: usize, PA: 'static> BaseComponent<K, V, PGMBaseAddress, PA>
-    for PGMBaseComponent<K, V, EPSILON, PA>
+impl<K, V, const EPSILON: usize, PA, M, const PACKED: bool>
+    PGMBaseComponent<K, V, EPSILON, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address,
+    M: SegmentationModel<K>,
+{
+    /// Summarize how well this layer segmented its data
+    pub fn report(&self) -> LayerReport {
+        self.inner.report(EPSILON)
+    }
+
+    /// Plot the segments of this layer holding keys in `range`
+    pub fn plot(&self, range: impl RangeBounds<K>) -> LayerPlot {
+        self.inner.plot(&range)
+    }
+}
+
+impl<K, V, const EPSILON: usize, PA: 'static, M, const PACKED: bool>
+    BaseComponent<K, V, PGMBaseAddress, PA> for PGMBaseComponent<K, V, EPSILON, PA, M, PACKED>
 where
     K: Key + PrimInt,
     V: Value,
     PA: Address,
+    M: SegmentationModel<K>,
 {
     fn insert(
         &mut self,
         ptr: PGMBaseAddress,
         key: K,
         value: V,
-    ) -> Option<PropagateInsert<K, PGMBaseAddress, PA>> {
-        if let Some((key, address, parent)) = self.inner.insert(key, value, ptr) {
-            Some(PropagateInsert::Single(key, address, parent))
-        } else {
-            None
+    ) -> BaseInsert<K, V, PGMBaseAddress, PA> {
+        let (previous, split) = self.inner.insert(key, value, ptr);
+        if previous.is_none() {
+            self.len += 1;
+        }
+
+        BaseInsert {
+            previous,
+            propagate: split
+                .map(|(key, address, parent)| PropagateInsert::Single(key, address, parent)),
         }
     }
 
     fn search(&self, ptr: PGMBaseAddress, key: &K) -> Option<V> {
-        self.inner[ptr].search_exact(key).cloned()
+        self.get(ptr, key).cloned()
+    }
+
+    fn get(&self, ptr: PGMBaseAddress, key: &K) -> Option<&V> {
+        self.inner[ptr].search_exact(key)
+    }
+
+    fn get_mut(&mut self, ptr: PGMBaseAddress, key: &K) -> Option<&mut V> {
+        self.inner[ptr].search_exact_mut(key)
+    }
+
+    fn probe(&self, ptr: PGMBaseAddress, key: &K) -> Probe {
+        self.inner[ptr].probe(key)
+    }
+
+    fn absorbs(&self, _: PGMBaseAddress, _: &K) -> bool {
+        // Segments grow in place and are never split by an insert
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.len
     }
 
     fn empty() -> Self {
         let result = MemoryPGMLayer::empty();
 
-        Self { inner: result }
+        Self {
+            inner: result,
+            len: 0,
+        }
     }
 
     fn build(iter: impl Iterator<Item = (K, V)>) -> Self {
         let mut result = MemoryPGMLayer::empty();
-        result.fill(iter);
+        let mut len = 0;
+        result.fill(iter.inspect(|_| len += 1));
+
+        if PACKED {
+            result.pack();
+        }
+
+        Self { inner: result, len }
+    }
+}
+
+// Only unpacked segments store their keys
+impl<K, V, const EPSILON: usize, PA: 'static, M> EntryComponent<K, V, PGMBaseAddress, PA>
+    for PGMBaseComponent<K, V, EPSILON, PA, M, false>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address,
+    M: SegmentationModel<K>,
+{
+    fn get_entry(&self, ptr: PGMBaseAddress, key: &K) -> Option<(&K, &V)> {
+        self.inner[ptr].get_entry(key)
+    }
+}
+
+impl<K, V, const EPSILON: usize, PA, M, const PACKED: bool> CompactComponent<PGMBaseAddress, PA>
+    for PGMBaseComponent<K, V, EPSILON, PA, M, PACKED>
+where
+    K: Key + PrimInt,
+    V: Value,
+    PA: Address + Hash,
+    M: SegmentationModel<K>,
+{
+    fn compact(&mut self) -> HashMap<PGMBaseAddress, PGMBaseAddress> {
+        self.inner.compact()
+    }
 
-        Self { inner: result }
+    fn remap_parents(&mut self, remap: &HashMap<PA, PA>) {
+        self.inner.remap_parents(remap);
     }
 }
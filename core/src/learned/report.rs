@@ -0,0 +1,80 @@
+use crate::auto::AutoDecision;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A summary of how well a learned layer segmented its data, used to decide whether a layout
+/// should change its epsilon or add/remove layers
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerReport {
+    /// Position of the layer in the layout, counting up from the base layer at 0
+    pub layer: usize,
+    pub epsilon: usize,
+    /// Number of trained segments (i.e. nodes) in the layer
+    pub segments: usize,
+    pub avg_segment_len: f64,
+    pub max_segment_len: usize,
+    /// Average distance between the predicted and actual slot of a key
+    pub avg_model_error: f64,
+    /// Histogram of keys per node, bucketed by powers of two, so that `keys_per_node[i]` counts
+    /// the nodes holding between `2^i` and `2^(i + 1) - 1` keys
+    pub keys_per_node: Vec<usize>,
+    /// Times every node of the layer was replaced, counting the build of the layer
+    pub rebuilds: usize,
+    /// What an `auto()` layer measured when it was built, and the kind of layer it picked
+    pub auto: Option<AutoDecision>,
+}
+
+impl LayerReport {
+    /// Build a report from the (size, total model error) pairs of every node in a layer. Empty
+    /// nodes, such as the sentinel at the end of a layer, are skipped.
+    pub(crate) fn from_nodes(epsilon: usize, nodes: impl Iterator<Item = (usize, usize)>) -> Self {
+        let mut segments = 0;
+        let mut keys = 0;
+        let mut max_segment_len = 0;
+        let mut error = 0;
+        let mut keys_per_node = Vec::new();
+
+        for (size, node_error) in nodes.filter(|&(size, _)| size > 0) {
+            segments += 1;
+            keys += size;
+            max_segment_len = max_segment_len.max(size);
+            error += node_error;
+
+            let bucket = size.ilog2() as usize;
+            if keys_per_node.len() <= bucket {
+                keys_per_node.resize(bucket + 1, 0);
+            }
+            keys_per_node[bucket] += 1;
+        }
+
+        let average = |total: usize, count: usize| {
+            if count > 0 {
+                total as f64 / count as f64
+            } else {
+                0.0
+            }
+        };
+
+        Self {
+            layer: 0,
+            epsilon,
+            segments,
+            avg_segment_len: average(keys, segments),
+            max_segment_len,
+            avg_model_error: average(error, keys),
+            keys_per_node,
+            rebuilds: 0,
+            auto: None,
+        }
+    }
+
+    /// The non-empty buckets of `keys_per_node`, as the range of keys per node each bucket covers
+    /// along with the number of nodes in it
+    pub fn histogram(&self) -> impl Iterator<Item = (Range<usize>, usize)> + '_ {
+        self.keys_per_node
+            .iter()
+            .enumerate()
+            .filter(|&(_, &nodes)| nodes > 0)
+            .map(|(bucket, &nodes)| ((1 << bucket)..(1 << (bucket + 1)), nodes))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::component::{PropagateInsert, RemapComponent, TopComponent};
+use crate::explain::Probe;
+use crate::learned::node::PGMNode;
+use crate::node_layer::NodeLayer;
+use crate::traits::Address;
+use crate::Key;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Bound;
+use hashbrown::HashMap;
+use learned_index_segmentation::{LinearModel, SegmentationModel};
+use num::PrimInt;
+
+/// Tops over fewer nodes than this are never retrained, since they stay cheap to search anyway
+const MIN_RETRAIN_SIZE: usize = 1024;
+
+/// A `TopComponent` implementation as a two stage recursive model index over the layer below.
+///
+/// The leaves are PGM segments trained over the nodes of the layer below, and the root is a single
+/// linear model over the lower bounds of the leaves, corrected by a short walk. Inserts go straight
+/// into the leaves, and the whole top is retrained from the layer below once it has doubled in
+/// size since it was last trained. This suits layers below the top with smooth key distributions,
+/// where the root predicts the right leaf almost every time.
+#[derive(Clone)]
+pub struct RMITopComponent<K: Key + PrimInt, X, A, const EPSILON: usize = 16> {
+    root: Root<K>,
+    leaves: Vec<PGMNode<K, A, LinearModel<K, EPSILON>>>,
+
+    /// Number of entries in the leaves, and how many there were when the top was last trained
+    size: usize,
+    trained: usize,
+    _ph: core::marker::PhantomData<X>,
+}
+
+/// Linear model from a key to the leaf covering it
+#[derive(Clone)]
+struct Root<K> {
+    first: K,
+    slope: f64,
+
+    /// Lower bound of every leaf, with the first anchored at the minimum key
+    bounds: Vec<K>,
+}
+
+impl<K: Key + PrimInt> Root<K> {
+    fn train(mut bounds: Vec<K>) -> Self {
+        let first = bounds[0];
+        let last = *bounds.last().unwrap();
+
+        let span = num::cast::<K, f64>(last.saturating_sub(first)).unwrap_or(f64::MAX);
+        let slope = match span > 0.0 {
+            true => (bounds.len() - 1) as f64 / span,
+            false => 0.0,
+        };
+
+        bounds[0] = K::min_value();
+        Self {
+            first,
+            slope,
+            bounds,
+        }
+    }
+
+    /// Index of the leaf covering `key`, and the comparisons made correcting the prediction
+    fn locate(&self, key: &K) -> (usize, usize) {
+        let run = num::cast::<K, f64>(key.saturating_sub(self.first)).unwrap_or(0.0);
+        let mut index = ((run * self.slope) as usize).min(self.bounds.len() - 1);
+        let mut comparisons = 0;
+
+        while index + 1 < self.bounds.len() && self.bounds[index + 1] <= *key {
+            comparisons += 1;
+            index += 1;
+        }
+
+        while index > 0 && self.bounds[index] > *key {
+            comparisons += 1;
+            index -= 1;
+        }
+
+        (index, comparisons + 1)
+    }
+}
+
+impl<K, X, A, const EPSILON: usize> RMITopComponent<K, X, A, EPSILON>
+where
+    K: Key + PrimInt,
+    A: Address + Copy,
+{
+    fn train(entries: impl Iterator<Item = (K, A)>) -> Self {
+        let leaves: Vec<_> = LinearModel::<K, EPSILON>::train(entries)
+            .into_iter()
+            .map(|(model, entries)| PGMNode::from_trained(model, entries))
+            .collect();
+
+        let root = Root::train(leaves.iter().map(|leaf| *leaf.model().min_key()).collect());
+        let size = leaves.iter().map(|leaf| leaf.size()).sum();
+
+        Self {
+            root,
+            leaves,
+            size,
+            trained: size,
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// Number of leaves trained over the layer below
+    pub fn leaves(&self) -> usize {
+        self.leaves.len()
+    }
+}
+
+impl<K, X, BA, const EPSILON: usize> RemapComponent<BA> for RMITopComponent<K, X, BA, EPSILON>
+where
+    K: Key + PrimInt,
+    BA: Address + Copy + Hash,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        for leaf in self.leaves.iter_mut() {
+            for address in leaf.values_mut() {
+                if let Some(&to) = remap.get(address) {
+                    *address = to;
+                }
+            }
+        }
+    }
+}
+
+impl<K, X, Base, BA, const EPSILON: usize> TopComponent<K, Base, BA, ()>
+    for RMITopComponent<K, X, BA, EPSILON>
+where
+    Base: NodeLayer<K, BA, ()>,
+    K: Key + PrimInt,
+    BA: Address + Copy,
+{
+    fn search(&self, _: &Base, key: &K) -> BA {
+        let (index, _) = self.root.locate(key);
+        *self.leaves[index].search_pir(key)
+    }
+
+    fn probe(&self, _: &Base, key: &K) -> Probe {
+        let (index, comparisons) = self.root.locate(key);
+        Probe::counted(comparisons).then(self.leaves[index].probe(key))
+    }
+
+    fn insert(&mut self, base: &mut Base, prop: PropagateInsert<K, BA, ()>) {
+        match prop {
+            PropagateInsert::Single(key, address, _) => {
+                base.set_parent(address, ());
+
+                let (index, _) = self.root.locate(&key);
+                self.leaves[index].grow_insert((key, address));
+                self.size += 1;
+
+                if self.size >= MIN_RETRAIN_SIZE && self.size > 2 * self.trained {
+                    *self = Self::train(
+                        base.range(Bound::Unbounded, Bound::Unbounded)
+                            .map(|(key, address)| (key.resolve(), address)),
+                    );
+                }
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn build(base: &mut Base) -> Self {
+        let mut entries = Vec::new();
+        let mut iter = base.range_mut(Bound::Unbounded, Bound::Unbounded);
+
+        while let Some((key, address, parent)) = iter.next() {
+            entries.push((key.resolve(), address));
+            parent.set(());
+        }
+
+        Self::train(entries.into_iter())
+    }
+}
@@ -0,0 +1,206 @@
+//! Monotone key transforms, applied by learned layers before training their models and before every
+//! lookup. Transforms only change how well a model fits the key space: nodes still store and order
+//! the original keys, so any non-decreasing transform keeps the index correct.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use learned_index_segmentation::SegmentationModel;
+use num::PrimInt;
+use serde::{Deserialize, Serialize};
+
+/// A non-decreasing map of the key space onto itself, selected with the `transform` field of the
+/// layout macro
+pub trait KeyTransform<K>: 'static {
+    fn apply(key: &K) -> K;
+}
+
+/// Cast into `K`, saturating at the bounds of the key space
+fn saturating_cast<K: PrimInt>(value: f64) -> K {
+    num::cast(value).unwrap_or(if value < 0.0 {
+        K::min_value()
+    } else {
+        K::max_value()
+    })
+}
+
+/// Sign-preserving logarithm, rescaled to cover the whole key space. Flattens key spaces which
+/// grow exponentially, or which have a few far away outliers.
+pub struct LogTransform;
+
+impl<K: PrimInt + 'static> KeyTransform<K> for LogTransform {
+    fn apply(key: &K) -> K {
+        let key: f64 = num::cast(*key).unwrap_or(0.0);
+        let max: f64 = num::cast(K::max_value()).unwrap_or(f64::MAX);
+
+        // Largest logarithm of a key, so that the result stays within the key space
+        let bits = K::zero().count_zeros() as f64;
+        let range = bits * core::f64::consts::LN_2 + 1.0;
+
+        let log = key.signum() * num::Float::ln_1p(key.abs());
+        saturating_cast(log / range * max)
+    }
+}
+
+/// `key * SCALE + OFFSET`, saturating at the bounds of the key space. Normalizes keys which are
+/// clustered around a large offset.
+pub struct AffineTransform<const SCALE: i64, const OFFSET: i64>;
+
+impl<K: PrimInt + 'static, const SCALE: i64, const OFFSET: i64> KeyTransform<K>
+    for AffineTransform<SCALE, OFFSET>
+{
+    fn apply(key: &K) -> K {
+        let key: f64 = num::cast(*key).unwrap_or(0.0);
+        saturating_cast(key * SCALE as f64 + OFFSET as f64)
+    }
+}
+
+/// A model `M` trained on, and queried with, keys transformed by `T`
+#[derive(Serialize, Deserialize)]
+pub struct Transformed<K, M, T> {
+    model: M,
+
+    /// Smallest original key indexed by the model
+    min_key: K,
+
+    #[serde(skip)]
+    _ph: core::marker::PhantomData<T>,
+}
+
+impl<K: Clone, M: Clone, T> Clone for Transformed<K, M, T> {
+    fn clone(&self) -> Self {
+        Self {
+            model: self.model.clone(),
+            min_key: self.min_key.clone(),
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, M, T> Transformed<K, M, T>
+where
+    K: PrimInt + 'static,
+    M: SegmentationModel<K>,
+{
+    /// Train the inner model on entries with strictly increasing transformed keys
+    fn train_strict<V>(chunk: Vec<(K, (K, V))>) -> Vec<(Self, Vec<(K, V)>)> {
+        M::train(chunk.into_iter())
+            .into_iter()
+            .map(|(model, entries)| {
+                let entries: Vec<(K, V)> = entries.into_iter().map(|(_, entry)| entry).collect();
+                let min_key = entries.first().map_or(K::max_value(), |(key, _)| *key);
+
+                let model = Self {
+                    model,
+                    min_key,
+                    _ph: core::marker::PhantomData,
+                };
+
+                (model, entries)
+            })
+            .collect()
+    }
+}
+
+impl<K, M, T> SegmentationModel<K> for Transformed<K, M, T>
+where
+    K: PrimInt + 'static,
+    M: SegmentationModel<K>,
+    T: KeyTransform<K>,
+{
+    fn train<V>(data: impl Iterator<Item = (K, V)>) -> Vec<(Self, Vec<(K, V)>)> {
+        let mut result = Vec::new();
+        let mut chunk = Vec::new();
+        let mut last: Option<K> = None;
+
+        // Transforms may map distinct keys onto the same one, but segmentation expects strictly
+        // increasing keys, so ties are broken by bumping the later key. Keys saturated at the top
+        // of the key space can't be bumped, so they are trained separately instead.
+        for (key, value) in data {
+            let mut transformed = T::apply(&key);
+
+            if let Some(previous) = last.filter(|&previous| transformed <= previous) {
+                if previous == K::max_value() {
+                    result.extend(Self::train_strict(core::mem::take(&mut chunk)));
+                } else {
+                    transformed = previous + K::one();
+                }
+            }
+
+            last = Some(transformed);
+            chunk.push((transformed, (key, value)));
+        }
+
+        result.extend(Self::train_strict(chunk));
+        result
+    }
+
+    fn sentinel() -> Self {
+        Self {
+            model: M::sentinel(),
+            min_key: K::max_value(),
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    fn min_key(&self) -> &K {
+        &self.min_key
+    }
+
+    fn approximate(&self, key: &K) -> Range<usize> {
+        self.model.approximate(&T::apply(key))
+    }
+
+    fn hint(&self, key: &K) -> usize {
+        self.model.hint(&T::apply(key))
+    }
+
+    fn rescale(&mut self, c: f64) {
+        self.model.rescale(c);
+    }
+
+    fn epsilon() -> Option<usize> {
+        M::epsilon()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use learned_index_segmentation::LinearModel;
+
+    #[test]
+    fn log_transform_monotone() {
+        let keys = [i64::MIN, -1_000_000, -1, 0, 1, 1_000, 1 << 40, i64::MAX];
+        let transformed: Vec<i64> = keys.iter().map(LogTransform::apply).collect();
+
+        assert!(transformed.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(LogTransform::apply(&0u64), 0);
+    }
+
+    #[test]
+    fn transformed_train_ties() {
+        // Every key is mapped onto the same transformed key
+        type Model = Transformed<u64, LinearModel<u64, 4>, AffineTransform<0, 7>>;
+
+        let trained = Model::train((100..1_000u64).map(|key| (key, key)));
+        let entries: Vec<u64> = trained
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(key, _)| *key))
+            .collect();
+
+        assert_eq!(entries, (100..1_000).collect::<Vec<_>>());
+        assert_eq!(*trained[0].0.min_key(), 100);
+
+        // Every key saturates at the top of the key space
+        type Saturated = Transformed<u64, LinearModel<u64, 4>, AffineTransform<1, { i64::MAX }>>;
+
+        let keys = (u64::MAX - 100)..u64::MAX;
+        let trained = Saturated::train(keys.clone().map(|key| (key, key)));
+        let entries: Vec<u64> = trained
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(key, _)| *key))
+            .collect();
+
+        assert_eq!(entries, keys.collect::<Vec<_>>());
+    }
+}
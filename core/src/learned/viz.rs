@@ -0,0 +1,243 @@
+//! Plots of the segments of a learned layer, for debugging the models of a real layout. A
+//! `LayerPlot` holds, for every segment with keys in the plotted range, the line of its model,
+//! the slot it predicts for every key along with the window it guarantees the key lies in, and
+//! the slot the key is actually stored in. It can be inspected directly, or rendered to an SVG.
+
+use super::node::PGMNode;
+use crate::Key;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::{Range, RangeBounds};
+use learned_index_segmentation::SegmentationModel;
+use num::PrimInt;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+const WIDTH: f64 = 960.0;
+const HEIGHT: f64 = 540.0;
+const MARGIN: f64 = 48.0;
+
+/// A key of a segment, where the model of the segment places it, and where it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyPoint {
+    pub key: f64,
+    pub slot: usize,
+
+    /// Slot the model predicts for the key, where searches start
+    pub predicted: usize,
+
+    /// Slots the model guarantees to hold the key
+    pub window: Range<usize>,
+}
+
+impl KeyPoint {
+    /// Distance between the predicted and the actual slot of the key
+    pub fn error(&self) -> usize {
+        self.predicted.abs_diff(self.slot)
+    }
+}
+
+/// A segment of a learned layer, along with its keys in the plotted range
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentPlot {
+    /// Smallest key of the segment, which may lie before the plotted range
+    pub min_key: f64,
+
+    /// Keys held by the segment, including those outside the plotted range
+    pub size: usize,
+
+    /// Slots of the segment, including gaps
+    pub capacity: usize,
+
+    /// Slope and intercept of the model, see `SegmentationModel::line`
+    pub line: Option<(f64, f64)>,
+
+    pub points: Vec<KeyPoint>,
+}
+
+impl SegmentPlot {
+    /// Plot the keys of `node` in `range`, or `None` if it holds none of them
+    fn from_node<K: Key + PrimInt, V, M: SegmentationModel<K>>(
+        node: &PGMNode<K, V, M>,
+        range: &impl RangeBounds<K>,
+    ) -> Option<Self> {
+        let (_, min_key) = node.slots().next()?;
+        let model = node.model();
+
+        let points: Vec<KeyPoint> = node
+            .slots()
+            .filter(|(_, key)| range.contains(key))
+            .map(|(slot, key)| KeyPoint {
+                key: to_f64(&key),
+                slot,
+                predicted: model.hint(&key),
+                window: model.approximate(&key),
+            })
+            .collect();
+
+        if points.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            min_key: to_f64(&min_key),
+            size: node.size(),
+            capacity: node.capacity(),
+            line: model.line(),
+            points,
+        })
+    }
+
+    /// Largest distance between the predicted and actual slot of a plotted key
+    pub fn max_error(&self) -> usize {
+        self.points.iter().map(KeyPoint::error).max().unwrap_or(0)
+    }
+}
+
+fn to_f64<K: Key + PrimInt>(key: &K) -> f64 {
+    num::cast::<K, f64>(*key).unwrap_or(f64::NAN)
+}
+
+/// The segments of a learned layer with keys in the plotted range, in key order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayerPlot {
+    /// Position of the layer in the layout, counting up from the base layer at 0
+    pub layer: usize,
+    pub segments: Vec<SegmentPlot>,
+}
+
+impl LayerPlot {
+    pub(crate) fn from_nodes<'a, K, V, M>(
+        nodes: impl Iterator<Item = &'a PGMNode<K, V, M>>,
+        range: &impl RangeBounds<K>,
+    ) -> Self
+    where
+        K: Key + PrimInt,
+        V: 'a,
+        M: SegmentationModel<K>,
+    {
+        Self {
+            layer: 0,
+            segments: nodes
+                .filter_map(|node| SegmentPlot::from_node(node, range))
+                .collect(),
+        }
+    }
+
+    /// Render the plot as an SVG. Keys run along the x axis, and slots along the y axis, with the
+    /// slots of every segment stacked on top of those of the segments before it. Every segment
+    /// is drawn as the envelope of its error windows, the line of its predictions, and a dot for
+    /// the actual slot of every key, with a dashed line where the segment starts.
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">"#
+        )
+        .unwrap();
+        writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+
+        let points = self.segments.iter().flat_map(|segment| &segment.points);
+        let (min_x, max_x) = points.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+            (min.min(p.key), max.max(p.key))
+        });
+
+        // Every segment is offset by the slots of the segments plotted before it
+        let mut offsets = Vec::new();
+        let mut max_y = 0;
+        for segment in &self.segments {
+            offsets.push(max_y);
+            max_y += segment.capacity;
+        }
+
+        let span_x = if max_x > min_x { max_x - min_x } else { 1.0 };
+        let span_y = max_y.max(1) as f64;
+        let x = |key: f64| MARGIN + (key - min_x) / span_x * (WIDTH - 2.0 * MARGIN);
+        let y = |slot: usize| HEIGHT - MARGIN - slot as f64 / span_y * (HEIGHT - 2.0 * MARGIN);
+
+        for (segment, offset) in self.segments.iter().zip(offsets) {
+            let first = &segment.points[0];
+            if first.key == segment.min_key {
+                writeln!(
+                    svg,
+                    r##"<line x1="{0:.2}" y1="{1:.2}" x2="{0:.2}" y2="{2:.2}" stroke="#bab0ac" stroke-dasharray="4 4"/>"##,
+                    x(first.key),
+                    MARGIN,
+                    HEIGHT - MARGIN,
+                )
+                .unwrap();
+            }
+
+            let lower = segment.points.iter().map(|p| (p.key, p.window.start));
+            let upper = segment.points.iter().rev().map(|p| (p.key, p.window.end));
+            let envelope: Vec<String> = lower
+                .chain(upper)
+                .map(|(key, slot)| format!("{:.2},{:.2}", x(key), y(offset + slot)))
+                .collect();
+            writeln!(
+                svg,
+                r##"<polygon points="{}" fill="#4e79a7" fill-opacity="0.15"/>"##,
+                envelope.join(" ")
+            )
+            .unwrap();
+
+            let predictions: Vec<String> = segment
+                .points
+                .iter()
+                .map(|p| format!("{:.2},{:.2}", x(p.key), y(offset + p.predicted)))
+                .collect();
+            writeln!(
+                svg,
+                r##"<polyline points="{}" fill="none" stroke="#4e79a7"/>"##,
+                predictions.join(" ")
+            )
+            .unwrap();
+
+            for point in &segment.points {
+                writeln!(
+                    svg,
+                    r##"<circle cx="{:.2}" cy="{:.2}" r="1.5" fill="#e15759"/>"##,
+                    x(point.key),
+                    y(offset + point.slot)
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(
+            svg,
+            r#"<text x="{MARGIN}" y="{}" font-family="monospace" font-size="14">layer {}: {} segments</text>"#,
+            MARGIN / 2.0,
+            self.layer,
+            self.segments.len()
+        )
+        .unwrap();
+
+        if !self.segments.is_empty() {
+            writeln!(
+                svg,
+                r#"<text x="{MARGIN}" y="{}" font-family="monospace" font-size="12">{min_x}</text>"#,
+                HEIGHT - MARGIN / 2.0,
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-family="monospace" font-size="12" text-anchor="end">{max_x}</text>"#,
+                WIDTH - MARGIN,
+                HEIGHT - MARGIN / 2.0,
+            )
+            .unwrap();
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_svg(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_svg())
+    }
+}
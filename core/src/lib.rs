@@ -1,34 +1,154 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "async")]
+pub mod async_index;
+pub mod auto;
+pub mod budget;
 pub mod classical;
 pub mod component;
+pub mod cursor;
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod drift;
+pub mod explain;
+pub mod filter;
+pub mod handles;
+#[cfg(feature = "std")]
+pub mod ingest;
 pub mod iter;
 pub mod kv_store;
 pub mod learned;
+pub mod lsn;
+pub mod maintenance;
+pub mod merge;
+pub mod namespace;
+#[cfg(feature = "encryption")]
+pub mod order_preserving;
+#[cfg(feature = "std")]
+pub mod paging;
+pub mod projection;
+#[cfg(feature = "std")]
+pub mod rcu;
+pub mod set_ops;
+#[cfg(feature = "std")]
+pub mod shadow;
+#[cfg(feature = "std")]
+pub mod sharded;
+#[cfg(feature = "std")]
+pub mod sstable;
+pub mod swappable;
+#[cfg(feature = "std")]
+pub mod testkit;
+#[cfg(feature = "async")]
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod workloads;
+pub mod write_batch;
 
 mod common;
 mod node_layer;
 mod traits;
 
 // Used by proc_macro
+#[doc(hidden)]
+pub use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
 pub use anyhow::Result;
+pub use serde;
+pub use slice_search::{BinarySearch, BranchlessSearch, LinearSearch, OptimalSearch, Search};
+#[cfg(any(feature = "trace", feature = "debug-internals"))]
+pub use tracing;
+
+// The remaps of `RemapComponent`, which custom components have to name
+pub use hashbrown::HashMap;
 
+#[cfg(feature = "async")]
+pub use async_index::{AsyncIndex, Executor, Job, Task, ThreadExecutor};
+pub use auto::{
+    AnyInternal, AutoChoice, AutoDecision, AutoInternalAddress, AutoInternalComponent, AUTO_FANOUT,
+};
+pub use budget::{ProbeBudget, SearchOutcome};
 pub use classical::*;
-pub use common::storage::GlobalStore;
+pub use common::list::alloc::{
+    ArenaAlloc, BumpAlloc, DefaultAlloc, FixedAlloc, FixedBuffer, NumaAlloc, PresizedAlloc,
+    StaticBuffer,
+};
+pub use common::mvcc::{Version, VersionChain};
+pub use common::reverse::ReverseIndex;
+#[cfg(feature = "encryption")]
+pub use common::storage::EncryptionKey;
+#[cfg(feature = "metrics")]
+pub use common::storage::{LatencyHistogram, LatencyOp, LatencySummary, LatencyTimer, Percentiles};
+#[cfg(feature = "std")]
+pub use common::storage::{
+    CachePolicy, CachePriority, CacheStats, DiskBuilder, DiskStats, DiskUsage, FileBackend,
+    GlobalStore, IndexStats, LocalStore, Lz4, MarbleBackend, MemoryBackend, MergedRuns,
+    NoCompression, PageCompression, PageDelta, RecoveryReport, RemapStoreIDs, StatsStore,
+    StorageBackend, StorageStats, StoreIDRemap, StoreLocked, VLogValue, ValueLog, ValuePointer,
+    WarmStats, WriteAheadLog, Zstd, DEFAULT_RUN_ENTRIES,
+};
+pub use common::tombstone::Entry;
+pub use common::ttl::Expiring;
+#[cfg(feature = "std")]
+pub use common::ttl::unix_millis;
+pub use common::u256::{ParseU256Error, U256};
 pub use learned::*;
 
 pub use component::*;
+pub use cursor::{CasError, Cursor, CursorError, CursorIndex, CursorMut, ScanHandle};
+pub use diff::{diff, DiffReport, LayerDiff};
+#[cfg(feature = "std")]
+pub use drift::DriftMonitor;
+pub use explain::{LookupStep, LookupTrace, Probe};
+pub use filter::{BloomFilter, FilterBuilder, FilterKind, FilterSegment, KeyFilter};
+pub use handles::{SlabStore, ValueHandle, ValueStore};
 pub use kv_store::*;
+pub use lsn::{Change, ChangesError, Lsn, Sequenced};
+#[cfg(feature = "std")]
+pub use maintenance::{AttachScheduler, MaintenanceScheduler};
+pub use maintenance::{MaintenanceConfig, MaintenanceStats, ManualMaintenance};
+pub use merge::{MergeFn, Merging};
+pub use namespace::{Scope, ScopeRange};
+#[cfg(feature = "encryption")]
+pub use order_preserving::{Encoded, EncodedRange, OrderPreserving};
 pub use node_layer::*;
+#[cfg(feature = "std")]
+pub use paging::{CursorPage, CursorToken};
+pub use projection::{project, FieldSelector, Projectable};
+#[cfg(feature = "std")]
+pub use rcu::{Rcu, RcuGuard};
+pub use set_ops::{IntersectKeys, Union};
+#[cfg(feature = "std")]
+pub use shadow::Shadowed;
+#[cfg(feature = "std")]
+pub use sharded::{Sharded, ShardedRange, ShardedRead};
+#[cfg(feature = "std")]
+pub use sstable::{ExportSorted, ImportSorted, SortedReader, SortedWriter};
+pub use swappable::{AnyTop, BTreeTop, RMITop, SwappableTop, TopKind};
 pub use traits::*;
+#[cfg(feature = "async")]
+pub use watch::{ChangeEvent, Lagged, WatchItem, WatchStream, Watchers, DEFAULT_WATCH_CAPACITY};
+pub use write_batch::{apply_in_place, BatchError, WriteBatch};
 
+#[cfg(feature = "std")]
 pub use std::path::Path;
 
+#[cfg(feature = "std")]
 pub fn add_prefix_to_path<P: AsRef<Path>>(
     path: P,
     prefix: String,
 ) -> Result<std::path::PathBuf, std::io::Error> {
     let path = path.as_ref();
 
-    if let Some(last_component) = path.iter().last() {
+    if let Some(last_component) = path.iter().next_back() {
         let new_last_component = format!("{}_{}", prefix, last_component.to_str().unwrap());
 
         let mut new_path = std::path::PathBuf::new();
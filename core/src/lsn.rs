@@ -0,0 +1,259 @@
+//! Logical sequence numbers for replication. A `Sequenced` index numbers every insert with the
+//! next LSN, and keeps the latest changes in a ring buffer, so replicas and CDC consumers can tail
+//! the index with `changes_since(lsn)`, passing the last LSN they applied. A consumer which was
+//! disconnected for long enough that its changes were overwritten is told so, rather than silently
+//! missing them, and has to resynchronize from a copy of the index instead.
+
+use crate::{IndexRead, IndexWrite};
+use alloc::collections::VecDeque;
+use core::fmt;
+
+/// Position of a change in the history of an index. The first change is numbered 1, so 0 is the
+/// LSN of an index before any change.
+pub type Lsn = u64;
+
+/// Changes kept for consumers to catch up on, unless set with `with_retained`
+pub const DEFAULT_RETAINED: usize = 4096;
+
+/// A key set to a value, along with its LSN
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change<K, V> {
+    pub lsn: Lsn,
+    pub key: K,
+    pub value: V,
+}
+
+/// Returned by `changes_since` when it can't yield every change after the LSN a consumer asked for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangesError {
+    /// Changes the consumer hasn't seen were already overwritten, the oldest kept is `oldest`
+    Truncated { since: Lsn, oldest: Lsn },
+
+    /// The consumer asked for changes after an LSN the index hasn't reached, its latest is `last`
+    Ahead { since: Lsn, last: Lsn },
+}
+
+impl fmt::Display for ChangesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangesError::Truncated { since, oldest } => write!(
+                f,
+                "changes after LSN {since} were overwritten, the oldest kept is LSN {oldest}"
+            ),
+            ChangesError::Ahead { since, last } => write!(
+                f,
+                "LSN {since} is ahead of the latest change, which is LSN {last}"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ChangesError {}
+
+/// An index along with the latest changes made through it
+pub struct Sequenced<I, K, V> {
+    index: I,
+
+    /// The latest changes in order of their LSNs, which are consecutive
+    changes: VecDeque<Change<K, V>>,
+    retained: usize,
+    last_lsn: Lsn,
+}
+
+impl<I, K, V> Sequenced<I, K, V> {
+    pub fn new(index: I) -> Self {
+        Self::starting_at(index, 0)
+    }
+
+    /// Number changes after `last_lsn`, for an index reloaded along with the LSN it was saved at,
+    /// so consumers can resume where they left off
+    pub fn starting_at(index: I, last_lsn: Lsn) -> Self {
+        Self {
+            index,
+            changes: VecDeque::new(),
+            retained: DEFAULT_RETAINED,
+            last_lsn,
+        }
+    }
+
+    /// Keep the latest `changes` changes for consumers to catch up on
+    pub fn with_retained(mut self, changes: usize) -> Self {
+        assert!(changes > 0, "At least one change has to be kept!");
+
+        self.retained = changes;
+        while self.changes.len() > changes {
+            self.changes.pop_front();
+        }
+        self
+    }
+
+    /// LSN of the latest change, or the LSN the index started at if there wasn't one
+    pub fn last_lsn(&self) -> Lsn {
+        self.last_lsn
+    }
+
+    /// Every change after `lsn` in order, or an error if some of them were already overwritten or
+    /// `lsn` is past the latest change
+    pub fn changes_since(
+        &self,
+        lsn: Lsn,
+    ) -> Result<impl Iterator<Item = &Change<K, V>> + '_, ChangesError> {
+        let Some(unseen) = self.last_lsn.checked_sub(lsn) else {
+            return Err(ChangesError::Ahead {
+                since: lsn,
+                last: self.last_lsn,
+            });
+        };
+
+        // Every kept change was numbered, so there are never more of them than the latest LSN
+        let kept = self.changes.len() as Lsn;
+        if unseen > kept {
+            return Err(ChangesError::Truncated {
+                since: lsn,
+                oldest: (self.last_lsn - kept).saturating_add(1),
+            });
+        }
+
+        Ok(self.changes.iter().skip((kept - unseen) as usize))
+    }
+
+    /// The wrapped index
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// Hand back the index, dropping the changes kept
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+}
+
+impl<I, K, V> IndexRead<K, V> for Sequenced<I, K, V>
+where
+    I: IndexRead<K, V>,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        self.index.try_search(key)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+/// Inserts which fail are not numbered, since they didn't change the index
+impl<I, K, V> IndexWrite<K, V> for Sequenced<I, K, V>
+where
+    I: IndexWrite<K, V>,
+    K: Clone,
+    V: Clone,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        let Some(lsn) = self.last_lsn.checked_add(1) else {
+            anyhow::bail!("Every LSN was used up!");
+        };
+        let previous = self.index.try_put(key.clone(), value.clone())?;
+
+        self.last_lsn = lsn;
+        if self.changes.len() == self.retained {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(Change {
+            lsn: self.last_lsn,
+            key,
+            value,
+        });
+
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[derive(Default)]
+    struct Map(BTreeMap<u64, u64>);
+
+    impl IndexRead<u64, u64> for Map {
+        fn try_search(&self, key: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.get(&key).copied())
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl IndexWrite<u64, u64> for Map {
+        fn try_put(&mut self, key: u64, value: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.insert(key, value))
+        }
+    }
+
+    fn keys(index: &Sequenced<Map, u64, u64>, lsn: Lsn) -> Result<Vec<u64>, ChangesError> {
+        Ok(index.changes_since(lsn)?.map(|change| change.key).collect())
+    }
+
+    #[test]
+    fn sequenced_tails_changes() {
+        let mut index = Sequenced::new(Map::default()).with_retained(3);
+        assert_eq!(index.last_lsn(), 0);
+        assert_eq!(keys(&index, 0), Ok(vec![]));
+
+        for key in 1..=4 {
+            index.try_put(key, key * 10).unwrap();
+        }
+        assert_eq!(index.try_put(2, 0).unwrap(), Some(20));
+        assert_eq!(index.last_lsn(), 5);
+        assert_eq!(index.len(), 4);
+
+        // A consumer resuming after LSN 2 gets the three changes kept
+        assert_eq!(keys(&index, 2), Ok(vec![3, 4, 2]));
+        assert_eq!(keys(&index, 4), Ok(vec![2]));
+        assert_eq!(keys(&index, 5), Ok(vec![]));
+
+        // One further behind has missed a change
+        assert_eq!(
+            keys(&index, 1),
+            Err(ChangesError::Truncated {
+                since: 1,
+                oldest: 3
+            })
+        );
+
+        // One which applied changes the index never made is told so
+        assert_eq!(
+            keys(&index, 6),
+            Err(ChangesError::Ahead { since: 6, last: 5 })
+        );
+
+        let index = Sequenced::starting_at(index.into_inner(), 5);
+        assert_eq!(keys(&index, 5), Ok(vec![]));
+        assert!(keys(&index, 4).is_err());
+    }
+
+    #[test]
+    fn sequenced_runs_out_of_lsns() {
+        let mut index = Sequenced::starting_at(Map::default(), Lsn::MAX - 1);
+        assert_eq!(
+            keys(&index, Lsn::MAX),
+            Err(ChangesError::Ahead {
+                since: Lsn::MAX,
+                last: Lsn::MAX - 1
+            })
+        );
+
+        index.try_put(1, 1).unwrap();
+        assert_eq!(keys(&index, Lsn::MAX - 1), Ok(vec![1]));
+        assert_eq!(keys(&index, Lsn::MAX), Ok(vec![]));
+
+        // The last LSN is never reused, and the index is left untouched
+        assert!(index.try_put(2, 2).is_err());
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.last_lsn(), Lsn::MAX);
+    }
+}
@@ -0,0 +1,268 @@
+//! Maintenance under a budget. A `MaintenanceScheduler` owns an index along with the jobs which
+//! keep it in shape, such as retraining learned layers, compacting, flushing caches or rebuilding
+//! once inserts drift, and runs them on a thread of its own within a `MaintenanceConfig`.
+//!
+//! Targets without threads or a clock, such as `wasm32-unknown-unknown`, run the same kind of jobs
+//! through `ManualMaintenance` instead, which only runs them when it is ticked, so the caller
+//! decides when maintenance may take its time.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+mod scheduler;
+
+#[cfg(feature = "std")]
+pub use scheduler::{AttachScheduler, MaintenanceScheduler};
+
+/// The budget of a `MaintenanceScheduler`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaintenanceConfig {
+    /// Fraction of wall time jobs may take, `0.1` by default
+    pub max_duty: f64,
+
+    /// Pages jobs may write per second, unbounded by default
+    pub max_pages_per_sec: Option<u64>,
+
+    /// How long the scheduler sleeps when no job is due
+    pub poll_interval: Duration,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            max_duty: 0.1,
+            max_pages_per_sec: None,
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn with_max_duty(mut self, max_duty: f64) -> Self {
+        assert!(
+            max_duty > 0.0 && max_duty <= 1.0,
+            "The duty of maintenance has to be in (0, 1]!"
+        );
+
+        self.max_duty = max_duty;
+        self
+    }
+
+    pub fn with_max_pages_per_sec(mut self, pages: u64) -> Self {
+        assert!(
+            pages > 0,
+            "Maintenance has to be allowed at least a page per second!"
+        );
+
+        self.max_pages_per_sec = Some(pages);
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long to pause after a job which ran for `busy` and wrote `pages`, so that both stay
+    /// within budget
+    pub fn pause_after(&self, busy: Duration, pages: u64) -> Duration {
+        let duty = busy.mul_f64((1.0 - self.max_duty) / self.max_duty);
+
+        let paging = match self.max_pages_per_sec {
+            Some(rate) => Duration::from_secs_f64(pages as f64 / rate as f64),
+            None => Duration::ZERO,
+        };
+
+        // The job itself already took `busy` of the time its pages are allowed
+        duty.max(paging.saturating_sub(busy))
+    }
+}
+
+/// Counters of the jobs a scheduler ran
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    pub runs: u64,
+
+    /// Time jobs held the index
+    pub busy: Duration,
+
+    /// Pages jobs reported writing
+    pub pages: u64,
+}
+
+/// Runs a job on the index, returning the number of pages it wrote
+type JobFn<I> = Box<dyn FnMut(&mut I) -> u64>;
+
+/// Decides whether a job is due from the index
+type Condition<I> = Box<dyn Fn(&I) -> bool>;
+
+enum Trigger<I> {
+    Every { ticks: u64, next: u64 },
+    When(Condition<I>),
+}
+
+struct ManualJob<I> {
+    name: String,
+    trigger: Trigger<I>,
+    run: JobFn<I>,
+}
+
+/// Maintenance jobs run by explicit calls to `tick` rather than from a thread. Periods are counted
+/// in ticks instead of time, so the caller can tick once per frame, or once per batch of inserts,
+/// whichever suits the moments it can spare.
+pub struct ManualMaintenance<I> {
+    jobs: Vec<ManualJob<I>>,
+    ticks: u64,
+    stats: MaintenanceStats,
+}
+
+impl<I> ManualMaintenance<I> {
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            ticks: 0,
+            stats: MaintenanceStats::default(),
+        }
+    }
+
+    /// Run `job` every `ticks` ticks. The job returns the number of pages it wrote.
+    pub fn every(
+        &mut self,
+        name: impl ToString,
+        ticks: u64,
+        job: impl FnMut(&mut I) -> u64 + 'static,
+    ) {
+        assert!(ticks > 0, "A job has to be run at most once per tick!");
+
+        let trigger = Trigger::Every {
+            ticks,
+            next: self.ticks + ticks,
+        };
+        self.add(name, trigger, Box::new(job));
+    }
+
+    /// Run `job` on every tick where `condition` holds
+    pub fn when(
+        &mut self,
+        name: impl ToString,
+        condition: impl Fn(&I) -> bool + 'static,
+        job: impl FnMut(&mut I) -> u64 + 'static,
+    ) {
+        self.add(name, Trigger::When(Box::new(condition)), Box::new(job));
+    }
+
+    fn add(&mut self, name: impl ToString, trigger: Trigger<I>, run: JobFn<I>) {
+        self.jobs.push(ManualJob {
+            name: name.to_string(),
+            trigger,
+            run,
+        });
+    }
+
+    /// Stop running the jobs named `name`, returning how many there were
+    pub fn cancel(&mut self, name: &str) -> usize {
+        let before = self.jobs.len();
+        self.jobs.retain(|job| job.name != name);
+        before - self.jobs.len()
+    }
+
+    /// Names of every job, in the order they are checked
+    pub fn jobs(&self) -> Vec<String> {
+        self.jobs.iter().map(|job| job.name.clone()).collect()
+    }
+
+    /// Counters of the jobs run so far. There is no clock to time them with, so `busy` stays zero.
+    pub fn stats(&self) -> MaintenanceStats {
+        self.stats
+    }
+
+    /// Advance by one tick, and run every job which is due in the order they were added, returning
+    /// how many ran
+    pub fn tick(&mut self, index: &mut I) -> usize {
+        self.ticks += 1;
+        let mut runs = 0;
+
+        for job in self.jobs.iter_mut() {
+            let due = match &mut job.trigger {
+                Trigger::Every { ticks, next } if *next <= self.ticks => {
+                    *next = self.ticks + *ticks;
+                    true
+                }
+                Trigger::Every { .. } => false,
+                Trigger::When(condition) => condition(index),
+            };
+
+            if due {
+                self.stats.pages += (job.run)(index);
+                self.stats.runs += 1;
+                runs += 1;
+            }
+        }
+
+        runs
+    }
+}
+
+impl<I> Default for ManualMaintenance<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn pause_after_within_budget() {
+        let config = MaintenanceConfig::default().with_max_duty(0.25);
+        assert_eq!(
+            config.pause_after(Duration::from_millis(10), 0),
+            Duration::from_millis(30)
+        );
+
+        // Writing pages faster than allowed pauses for the rest of their time
+        let config = config.with_max_pages_per_sec(100);
+        assert_eq!(
+            config.pause_after(Duration::from_millis(10), 50),
+            Duration::from_millis(490)
+        );
+        assert_eq!(
+            config.pause_after(Duration::from_millis(10), 1),
+            Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn manual_maintenance_ticks() {
+        let mut maintenance = ManualMaintenance::<Vec<u64>>::new();
+        maintenance.every("append", 2, |index| {
+            index.push(0);
+            1
+        });
+        maintenance.when(
+            "clear",
+            |index| index.len() >= 2,
+            |index| {
+                index.clear();
+                0
+            },
+        );
+
+        let mut index = Vec::new();
+        let runs: Vec<usize> = (0..4).map(|_| maintenance.tick(&mut index)).collect();
+
+        // The second append is cleared right away, on the tick it happens
+        assert_eq!(runs, vec![0, 1, 0, 2]);
+        assert!(index.is_empty());
+        assert_eq!(maintenance.stats().runs, 3);
+        assert_eq!(maintenance.stats().pages, 2);
+
+        assert_eq!(maintenance.cancel("append"), 1);
+        assert_eq!(maintenance.jobs(), vec!["clear".to_string()]);
+    }
+}
@@ -0,0 +1,290 @@
+//! The thread running the jobs of a `MaintenanceScheduler`. Every job holds the index exclusively
+//! while it runs, so the scheduler paces them: after each run it pauses for long enough that jobs
+//! take at most a fraction of wall time, and write at most a number of pages per second, which
+//! leaves the rest to foreground queries.
+
+use super::{MaintenanceConfig, MaintenanceStats};
+use crate::kv_store::KVStore;
+use crate::traits::{Key, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Runs a job on the index, returning the number of pages it wrote
+type JobFn<I> = Box<dyn FnMut(&mut I) -> u64 + Send>;
+
+/// Decides whether a job is due from the index, without holding it exclusively
+type Condition<I> = Box<dyn Fn(&I) -> bool + Send>;
+
+enum Trigger<I> {
+    Every { period: Duration, next: Instant },
+    When(Condition<I>),
+}
+
+struct MaintenanceJob<I> {
+    name: String,
+    trigger: Trigger<I>,
+    run: JobFn<I>,
+}
+
+struct Shared<I> {
+    index: RwLock<I>,
+    jobs: Mutex<Vec<MaintenanceJob<I>>>,
+    stats: Mutex<MaintenanceStats>,
+    config: MaintenanceConfig,
+
+    /// Set once the scheduler is dropped, waking the thread from any pause
+    stopped: Mutex<bool>,
+    wake: Condvar,
+    running: AtomicBool,
+}
+
+/// Take a lock even if a panicking job poisoned it, jobs only ever leave an index consistent
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<I> Shared<I> {
+    /// Sleep for `duration`, returning false if the scheduler stopped meanwhile
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut stopped = lock(&self.stopped);
+
+        while !*stopped {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+
+            stopped = self
+                .wake
+                .wait_timeout(stopped, deadline - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+
+        false
+    }
+
+    /// Run the first job which is due, returning how long to pause afterwards, or `None` if no
+    /// job was due
+    fn run_due(&self) -> Option<Duration> {
+        let mut jobs = lock(&self.jobs);
+        let now = Instant::now();
+
+        let due = jobs.iter().position(|job| match &job.trigger {
+            Trigger::Every { next, .. } => *next <= now,
+            Trigger::When(condition) => {
+                condition(&self.index.read().unwrap_or_else(|p| p.into_inner()))
+            }
+        })?;
+
+        // Queued behind every job which was due as well, so that none of them starves
+        let mut job = jobs.remove(due);
+
+        let start = Instant::now();
+        let pages = (job.run)(&mut self.index.write().unwrap_or_else(|p| p.into_inner()));
+        let busy = start.elapsed();
+
+        if let Trigger::Every { period, next } = &mut job.trigger {
+            *next = Instant::now() + *period;
+        }
+        jobs.push(job);
+
+        let mut stats = lock(&self.stats);
+        stats.runs += 1;
+        stats.busy += busy;
+        stats.pages += pages;
+
+        Some(self.config.pause_after(busy, pages))
+    }
+
+    fn work(&self) {
+        loop {
+            let pause = self.run_due().unwrap_or(self.config.poll_interval);
+
+            if !self.sleep(pause) {
+                return;
+            }
+        }
+    }
+}
+
+/// An index along with the background jobs which maintain it. Foreground queries go through
+/// `read` and `write`, or `search` and `insert`, and only wait on a job which is running, never on
+/// the pause between jobs.
+pub struct MaintenanceScheduler<I: Send + Sync + 'static> {
+    shared: Arc<Shared<I>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<I: Send + Sync + 'static> MaintenanceScheduler<I> {
+    /// Take over `index`, and start the thread running its jobs within the budget of `config`
+    pub fn new(index: I, config: MaintenanceConfig) -> Self {
+        let shared = Arc::new(Shared {
+            index: RwLock::new(index),
+            jobs: Mutex::new(Vec::new()),
+            stats: Mutex::new(MaintenanceStats::default()),
+            config,
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+            running: AtomicBool::new(true),
+        });
+
+        let worker = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                shared.work();
+                shared.running.store(false, Ordering::SeqCst);
+            })
+        };
+
+        Self {
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Run `job` every `period`, such as flushing the caches of a persisted index. The job returns
+    /// the number of pages it wrote, which count against `max_pages_per_sec`.
+    pub fn every(
+        &self,
+        name: impl ToString,
+        period: Duration,
+        job: impl FnMut(&mut I) -> u64 + Send + 'static,
+    ) {
+        self.add(
+            name,
+            Trigger::Every {
+                period,
+                next: Instant::now() + period,
+            },
+            Box::new(job),
+        );
+    }
+
+    /// Run `job` whenever `condition` holds, such as rebuilding once the drift of a
+    /// `DriftMonitor` exceeds a threshold. The condition is checked with the index locked for
+    /// reading, every time the scheduler looks for a job which is due.
+    pub fn when(
+        &self,
+        name: impl ToString,
+        condition: impl Fn(&I) -> bool + Send + 'static,
+        job: impl FnMut(&mut I) -> u64 + Send + 'static,
+    ) {
+        self.add(name, Trigger::When(Box::new(condition)), Box::new(job));
+    }
+
+    fn add(&self, name: impl ToString, trigger: Trigger<I>, run: JobFn<I>) {
+        lock(&self.shared.jobs).push(MaintenanceJob {
+            name: name.to_string(),
+            trigger,
+            run,
+        });
+    }
+
+    /// Stop running the jobs named `name`, returning how many there were
+    pub fn cancel(&self, name: &str) -> usize {
+        let mut jobs = lock(&self.shared.jobs);
+        let before = jobs.len();
+        jobs.retain(|job| job.name != name);
+        before - jobs.len()
+    }
+
+    /// Names of every job, in the order they are checked
+    pub fn jobs(&self) -> Vec<String> {
+        lock(&self.shared.jobs)
+            .iter()
+            .map(|job| job.name.clone())
+            .collect()
+    }
+
+    pub fn stats(&self) -> MaintenanceStats {
+        *lock(&self.shared.stats)
+    }
+
+    pub fn config(&self) -> &MaintenanceConfig {
+        &self.shared.config
+    }
+
+    /// Whether the thread running jobs is still alive, which it is until the scheduler is dropped
+    /// unless a job panicked
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::SeqCst)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, I> {
+        self.shared
+            .index
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, I> {
+        self.shared
+            .index
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn search<K: Key, V: Value>(&self, key: K) -> Option<V>
+    where
+        I: KVStore<K, V>,
+    {
+        self.read().search(key)
+    }
+
+    /// Insert a key, returning the value it held before
+    pub fn insert<K: Key, V: Value>(&self, key: K, value: V) -> Option<V>
+    where
+        I: KVStore<K, V>,
+    {
+        self.write().insert(key, value)
+    }
+
+    /// Stop the thread running jobs, waiting for a job which is running to finish
+    fn stop(&mut self) {
+        *lock(&self.shared.stopped) = true;
+        self.shared.wake.notify_all();
+
+        if let Some(worker) = self.worker.take() {
+            // A job which panicked already stopped the thread
+            let _ = worker.join();
+        }
+    }
+
+    /// Stop running jobs, and hand back the index
+    pub fn into_inner(mut self) -> I {
+        self.stop();
+
+        // Dropping the scheduler after `stop` only releases the jobs
+        let shared = self.shared.clone();
+        drop(self);
+
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => shared
+                .index
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            Err(_) => unreachable!("The thread running jobs was joined"),
+        }
+    }
+}
+
+impl<I: Send + Sync + 'static> Drop for MaintenanceScheduler<I> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Hands an index over to a `MaintenanceScheduler`, as `index.attach_scheduler(config)`
+pub trait AttachScheduler: Sized + Send + Sync + 'static {
+    fn attach_scheduler(self, config: MaintenanceConfig) -> MaintenanceScheduler<Self> {
+        MaintenanceScheduler::new(self, config)
+    }
+}
+
+impl<I: Send + Sync + 'static> AttachScheduler for I {}
@@ -0,0 +1,218 @@
+//! Merge operators, as in RocksDB. A `Merging` index takes updates such as increments or appends
+//! as operands through `merge(key, operand)`, without reading the value they apply to. Operands
+//! are buffered per key, and since the merge function is associative, the operands of a key are
+//! folded into one as they arrive. Reads fold the buffered operand into the stored value, and
+//! `flush` writes every buffered operand through to the index, so counters and append-lists don't
+//! need a read-modify-write round trip through every layer per update.
+
+use crate::{IndexRead, IndexWrite};
+use alloc::collections::BTreeMap;
+
+/// Keys with buffered operands before `merge` flushes them, unless set with `with_max_pending`
+pub const DEFAULT_MAX_PENDING: usize = 4096;
+
+/// Combines two values, where the left one comes first: a stored value and an operand merged
+/// after it, or two operands. It has to be associative, so that operands can be folded into one
+/// before the value they apply to is read.
+pub trait MergeFn<V> {
+    fn merge(&self, left: V, right: V) -> V;
+}
+
+impl<V, F: Fn(V, V) -> V> MergeFn<V> for F {
+    fn merge(&self, left: V, right: V) -> V {
+        self(left, right)
+    }
+}
+
+/// An index along with the operands merged into it which haven't been written through yet
+pub struct Merging<I, K, V, F> {
+    index: I,
+    merge: F,
+
+    /// The fold of every operand merged into a key since it was last written
+    pending: BTreeMap<K, V>,
+    max_pending: usize,
+}
+
+impl<I, K, V, F> Merging<I, K, V, F>
+where
+    I: IndexRead<K, V> + IndexWrite<K, V>,
+    K: Ord + Clone,
+    V: Clone,
+    F: MergeFn<V>,
+{
+    pub fn new(index: I, merge: F) -> Self {
+        Self {
+            index,
+            merge,
+            pending: BTreeMap::new(),
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+
+    /// Flush once operands are buffered for `keys` keys
+    pub fn with_max_pending(mut self, keys: usize) -> Self {
+        assert!(keys > 0, "At least one key has to be buffered!");
+
+        self.max_pending = keys;
+        self
+    }
+
+    /// Merge `operand` into the value of `key`. A key without a value takes the operand as its
+    /// value. Nothing is read from the index, unless the buffer is full and has to be flushed.
+    pub fn merge(&mut self, key: K, operand: V) -> crate::Result<()> {
+        let operand = match self.pending.remove(&key) {
+            Some(pending) => self.merge.merge(pending, operand),
+            None => operand,
+        };
+        self.pending.insert(key, operand);
+
+        if self.pending.len() >= self.max_pending {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every buffered operand through to the index, returning the number of keys written
+    pub fn flush(&mut self) -> crate::Result<usize> {
+        let pending = core::mem::take(&mut self.pending);
+        let keys = pending.len();
+
+        for (key, operand) in pending {
+            let value = match self.index.try_search(key.clone())? {
+                Some(value) => self.merge.merge(value, operand),
+                None => operand,
+            };
+            self.index.try_put(key, value)?;
+        }
+
+        Ok(keys)
+    }
+
+    /// Number of keys with buffered operands
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The wrapped index, which doesn't see operands until they are flushed
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// Flush every buffered operand, and hand back the index
+    pub fn into_inner(mut self) -> crate::Result<I> {
+        self.flush()?;
+        Ok(self.index)
+    }
+}
+
+impl<I, K, V, F> IndexRead<K, V> for Merging<I, K, V, F>
+where
+    I: IndexRead<K, V>,
+    K: Ord + Clone,
+    V: Clone,
+    F: MergeFn<V>,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        let operand = self.pending.get(&key).cloned();
+
+        Ok(match (self.index.try_search(key)?, operand) {
+            (Some(value), Some(operand)) => Some(self.merge.merge(value, operand)),
+            (value, operand) => operand.or(value),
+        })
+    }
+
+    /// Keys which only have buffered operands are counted once they are flushed, since telling
+    /// them apart from keys already in the index would take a read per buffered key
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+/// Inserting a value replaces it along with every operand merged into it before
+impl<I, K, V, F> IndexWrite<K, V> for Merging<I, K, V, F>
+where
+    I: IndexWrite<K, V>,
+    K: Ord + Clone,
+    V: Clone,
+    F: MergeFn<V>,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        let operand = self.pending.remove(&key);
+
+        Ok(match (self.index.try_put(key, value)?, operand) {
+            (Some(previous), Some(operand)) => Some(self.merge.merge(previous, operand)),
+            (previous, operand) => operand.or(previous),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::Cell;
+
+    /// A map counting the lookups made through it
+    #[derive(Default)]
+    struct Map {
+        entries: BTreeMap<u64, Vec<u64>>,
+        reads: Cell<usize>,
+    }
+
+    impl IndexRead<u64, Vec<u64>> for Map {
+        fn try_search(&self, key: u64) -> crate::Result<Option<Vec<u64>>> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(self.entries.get(&key).cloned())
+        }
+
+        fn len(&self) -> usize {
+            self.entries.len()
+        }
+    }
+
+    impl IndexWrite<u64, Vec<u64>> for Map {
+        fn try_put(&mut self, key: u64, value: Vec<u64>) -> crate::Result<Option<Vec<u64>>> {
+            Ok(self.entries.insert(key, value))
+        }
+    }
+
+    fn append(mut left: Vec<u64>, right: Vec<u64>) -> Vec<u64> {
+        left.extend(right);
+        left
+    }
+
+    #[test]
+    fn merging_appends() {
+        let mut index = Merging::new(Map::default(), append).with_max_pending(3);
+
+        index.try_put(1, vec![0]).unwrap();
+        index.merge(1, vec![1]).unwrap();
+        index.merge(1, vec![2]).unwrap();
+        index.merge(2, vec![5]).unwrap();
+
+        // Operands are folded without reading the stored value
+        assert_eq!(index.inner().reads.get(), 0);
+        assert_eq!(index.pending(), 2);
+        assert_eq!(index.try_search(1).unwrap(), Some(vec![0, 1, 2]));
+        assert_eq!(index.try_search(2).unwrap(), Some(vec![5]));
+        assert_eq!(index.try_search(3).unwrap(), None);
+
+        // Inserts replace the operands merged before them
+        assert_eq!(index.try_put(2, vec![7]).unwrap(), Some(vec![5]));
+        assert_eq!(index.try_search(2).unwrap(), Some(vec![7]));
+
+        // The third key fills the buffer, which writes every operand through
+        index.merge(2, vec![8]).unwrap();
+        index.merge(3, vec![9]).unwrap();
+        assert_eq!(index.pending(), 0);
+        index.merge(4, vec![9]).unwrap();
+
+        let map = index.into_inner().unwrap();
+        assert_eq!(map.entries[&1], vec![0, 1, 2]);
+        assert_eq!(map.entries[&2], vec![7, 8]);
+        assert_eq!(map.entries[&4], vec![9]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Tenants sharing a single index. `index.scope(tenant)` returns a `Scope`, which stores the keys
+//! of the tenant with the tenant in the upper half of their bits, and the key itself in the lower
+//! half. The keys of a tenant are then contiguous in the index, so ranges over a scope never see
+//! the keys of another tenant, and clearing a scope removes the keys of that tenant in place,
+//! walking only those keys.
+//!
+//! Tenants and keys of a scope have to fit in half of the bits of the key type, so in `0..2^64`
+//! for `i128` keys.
+
+use crate::cursor::{Cursor, CursorIndex, CursorMut};
+use crate::kv_store::KVStore;
+use crate::traits::{Key, Value};
+use core::ops::{Bound, RangeBounds};
+use num::PrimInt;
+
+/// Bits of the key type holding the key within its scope
+fn half_bits<K>() -> usize {
+    4 * core::mem::size_of::<K>()
+}
+
+/// Whether a tenant or a key fits in the lower half of the bits of `K`
+fn fits<K: Key + PrimInt>(value: K) -> bool {
+    value >= K::zero() && (value >> half_bits::<K>()).is_zero()
+}
+
+/// The keys of a single tenant of an index
+pub struct Scope<'a, K, V, I> {
+    index: &'a mut I,
+    prefix: K,
+    _ph: core::marker::PhantomData<V>,
+}
+
+impl<'a, K, V, I> Scope<'a, K, V, I>
+where
+    K: Key + PrimInt,
+    V: Value,
+    I: CursorIndex<K, V> + KVStore<K, V>,
+{
+    /// The scope of `tenant`, which has to fit in half of the bits of the key
+    pub fn new(index: &'a mut I, tenant: K) -> Self {
+        assert!(fits(tenant), "tenant does not fit in half of the key");
+
+        Self {
+            index,
+            prefix: tenant << half_bits::<K>(),
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    pub fn tenant(&self) -> K {
+        self.prefix >> half_bits::<K>()
+    }
+
+    /// The key the index stores `key` of the scope under
+    fn compose(&self, key: K) -> Option<K> {
+        fits(key).then(|| self.prefix | key)
+    }
+
+    /// Smallest and largest key of the index which belong to the scope
+    fn bounds(&self) -> (K, K) {
+        let mask = (K::one() << half_bits::<K>()) - K::one();
+        (self.prefix, self.prefix | mask)
+    }
+
+    pub fn search(&self, key: K) -> Option<V> {
+        self.index.search(self.compose(key)?)
+    }
+
+    /// Insert a key, returning the value it held before. The key has to fit in half of the bits
+    /// of the key type.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let key = self
+            .compose(key)
+            .expect("key does not fit in half of the key");
+
+        self.index.insert(key, value)
+    }
+
+    /// The entries of the scope with keys in `range`, in key order
+    pub fn range(&self, range: impl RangeBounds<K>) -> ScopeRange<'_, K, V, I> {
+        let (low, high) = self.bounds();
+        let mask = high ^ low;
+
+        // Clamp the range to the keys a scope can hold
+        let start = match range.start_bound() {
+            Bound::Included(&key) => Some(key),
+            Bound::Excluded(&key) => key.checked_add(&K::one()),
+            Bound::Unbounded => Some(K::zero()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&key) => Some(key),
+            Bound::Excluded(&key) => key.checked_sub(&K::one()),
+            Bound::Unbounded => Some(mask),
+        };
+
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if start <= mask && end >= K::zero() && start <= end => {
+                (low | start.max(K::zero()), low | end.min(mask))
+            }
+            // Nothing of the scope falls in the range
+            _ => (high, low),
+        };
+
+        ScopeRange {
+            cursor: Cursor::new(&*self.index, &start),
+            end,
+            mask,
+        }
+    }
+
+    /// Remove every entry of the scope, returning how many there were
+    pub fn clear_scope(&mut self) -> usize {
+        let (low, high) = self.bounds();
+        let mut cursor = CursorMut::new(&mut *self.index, &low);
+        let mut removed = 0;
+
+        while cursor.key().is_some_and(|&key| key <= high) {
+            cursor.remove_current();
+            removed += 1;
+        }
+
+        removed
+    }
+}
+
+/// Iterator over the entries of a `Scope` in a range of keys, returned by `Scope::range`
+pub struct ScopeRange<'a, K, V, I: CursorIndex<K, V>> {
+    cursor: Cursor<'a, K, V, I>,
+    end: K,
+    mask: K,
+}
+
+impl<K: Key + PrimInt, V: Value, I: CursorIndex<K, V>> Iterator for ScopeRange<'_, K, V, I> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&key, value) = self.cursor.current()?;
+        if key > self.end {
+            return None;
+        }
+
+        let entry = (key & self.mask, value.clone());
+        self.cursor.move_next();
+        Some(entry)
+    }
+}
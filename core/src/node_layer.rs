@@ -1,8 +1,8 @@
 // Type dependence hierarchy
 
-use std::ops::Bound;
+use core::ops::Bound;
 
-use crate::iter::{Iter, IterMut};
+use crate::iter::{Iter, IterMut, Nodes};
 use crate::traits::*;
 
 /// A `NodeLayer` is has the interface of a linked list of key-bounded nodes which implement the
@@ -17,23 +17,68 @@ where
 
     fn set_parent(&mut self, ptr: SA, parent: PA);
 
-    fn lower_bound(&self, ptr: SA) -> K;
+    fn lower_bound(&self, ptr: SA) -> KeyBound<K>;
 
     fn next(&self, ptr: SA) -> Option<SA>;
 
     fn prev(&self, ptr: SA) -> Option<SA>;
 
+    /// Whether `ptr` still addresses a node of the layer. Layers whose addresses never go stale
+    /// return true.
+    fn contains_node(&self, _ptr: SA) -> bool {
+        true
+    }
+
+    /// `ptr` if it still addresses a node of the layer, for addresses held across mutations of the
+    /// index, such as those remembered by hints and tokens
+    fn check_node(&self, ptr: SA) -> Result<SA, StaleAddress> {
+        if self.contains_node(ptr.clone()) {
+            Ok(ptr)
+        } else {
+            Err(StaleAddress)
+        }
+    }
+
     /// First node in the current node layer
     fn first(&self) -> SA;
 
     /// Last node in the current node layer
     fn last(&self) -> SA;
 
+    /// Number of nodes in the current node layer, which is maintained as nodes are added rather
+    /// than counted
+    fn node_count(&self) -> usize;
+
+    /// Estimated bytes of memory held by the layer. Layers which don't keep their nodes in memory
+    /// return 0.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Number of nodes still shared with a clone of the layer, which are copied the first time
+    /// either layer modifies them. Layers which don't share nodes between clones return 0.
+    fn shared_count(&self) -> usize {
+        0
+    }
+
+    /// Hold every node so that clones of the layer share it, until either layer modifies it.
+    /// Layers which can't share nodes between clones do nothing.
+    fn share_nodes(&mut self) {}
+
+    /// Hint that the node at `ptr` is about to be read. Layers which keep their nodes in memory
+    /// can use this to pull the node into cache ahead of a scan, by default this does nothing
+    fn prefetch(&self, _ptr: SA) {}
+
     /// An immutable iterator over the layer, returning (Key, Address) pairs
     fn range(&self, start: Bound<SA>, end: Bound<SA>) -> Iter<'_, K, Self, SA, PA> {
         Iter::range(self, start, end)
     }
 
+    /// An immutable iterator over every node of the layer, returning (Key, Address) pairs
+    fn nodes(&self) -> Nodes<'_, K, Self, SA, PA> {
+        Nodes::new(self)
+    }
+
     /// An iterator over the layer, returning (Key, Address, ParentView) pairs, where parents
     /// can be modified by the ParentView struct
     fn range_mut(&mut self, start: Bound<SA>, end: Bound<SA>) -> IterMut<'_, K, Self, SA, PA>
@@ -44,6 +89,21 @@ where
     }
 }
 
+/// An address which no longer points at a node of its layer, because the layer was cleared,
+/// rebuilt or compacted since the address was handed out. In-memory layers address nodes by slot
+/// and generation, and every structural change bumps the generation, so a stale address is caught
+/// rather than reading whichever node took over its slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleAddress;
+
+impl core::fmt::Display for StaleAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "address no longer points at a node of its layer")
+    }
+}
+
+impl core::error::Error for StaleAddress {}
+
 macro_rules! impl_node_layer {
     ($SA:ty, $PA:ty) => {
         fn parent(&self, ptr: $SA) -> Option<$PA> {
@@ -54,7 +114,7 @@ macro_rules! impl_node_layer {
             self.inner.set_parent(ptr, parent)
         }
 
-        fn lower_bound(&self, ptr: $SA) -> K {
+        fn lower_bound(&self, ptr: $SA) -> $crate::traits::KeyBound<K> {
             self.inner.lower_bound(ptr)
         }
 
@@ -66,6 +126,10 @@ macro_rules! impl_node_layer {
             self.inner.prev(ptr)
         }
 
+        fn contains_node(&self, ptr: $SA) -> bool {
+            self.inner.contains_node(ptr)
+        }
+
         fn first(&self) -> $SA {
             self.inner.first()
         }
@@ -73,6 +137,26 @@ macro_rules! impl_node_layer {
         fn last(&self) -> $SA {
             self.inner.last()
         }
+
+        fn node_count(&self) -> usize {
+            self.inner.node_count()
+        }
+
+        fn memory_usage(&self) -> usize {
+            self.inner.memory_usage()
+        }
+
+        fn shared_count(&self) -> usize {
+            self.inner.shared_count()
+        }
+
+        fn share_nodes(&mut self) {
+            self.inner.share_nodes()
+        }
+
+        fn prefetch(&self, ptr: $SA) {
+            self.inner.prefetch(ptr)
+        }
     };
 }
 
@@ -0,0 +1,295 @@
+//! Keys encoded under a secret key before they enter an index. `OrderPreserving` maps every key to
+//! a code in a much larger key type, such that codes are ordered like the keys they encode, so an
+//! index over the codes answers point lookups and range queries just like one over the keys, while
+//! only callers holding the `EncryptionKey` can tell which keys the codes stand for.
+//!
+//! The mapping is a random order-preserving function, sampled lazily: the domain of keys is split
+//! in half recursively, and the image of every split is drawn from ChaCha20 keyed with the secret
+//! key, within the room left for either half. Codes reveal the order of the keys, which equal keys
+//! always share, and roughly how far apart they are, but nothing beyond that.
+
+use crate::cursor::{Cursor, CursorIndex};
+use crate::{EncryptionKey, IndexRead, IndexWrite, Key, Value};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use core::ops::{Bound, RangeBounds};
+use num::{NumCast, PrimInt};
+
+/// Bits of `K`
+fn bits<K>() -> u32 {
+    8 * core::mem::size_of::<K>() as u32
+}
+
+/// A keyed order-preserving encoding of keys `K` into codes `E`. Codes span all but the top bit of
+/// `E`, so they are never negative, and have to be at least 16 bits wider than the keys.
+#[derive(Clone)]
+pub struct OrderPreserving<K, E> {
+    key: [u8; 32],
+    _ph: core::marker::PhantomData<(K, E)>,
+}
+
+impl<K, E> core::fmt::Debug for OrderPreserving<K, E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("OrderPreserving(..)")
+    }
+}
+
+impl<K: Key + PrimInt, E: Key + PrimInt> OrderPreserving<K, E> {
+    pub fn new(key: &EncryptionKey) -> Self {
+        assert!(bits::<K>() <= 64, "Keys can be at most 64 bits wide!");
+        assert!(
+            bits::<E>() > bits::<K>() + 16,
+            "Codes must be at least 16 bits wider than keys!"
+        );
+
+        Self {
+            key: *key.as_bytes(),
+            _ph: core::marker::PhantomData,
+        }
+    }
+
+    /// Position of `key` among every key of `K`, starting from the smallest
+    fn ordinal(key: K) -> u128 {
+        let key = key.to_i128().expect("keys fit in 64 bits");
+        let min = K::min_value().to_i128().expect("keys fit in 64 bits");
+        (key - min) as u128
+    }
+
+    fn from_ordinal(ordinal: u128) -> K {
+        let min = K::min_value().to_i128().expect("keys fit in 64 bits");
+        NumCast::from(ordinal as i128 + min).expect("ordinals of keys fit in their key")
+    }
+
+    /// 128 pseudo-random bits for the split of the domain starting at `low` at `depth`, which
+    /// together pick out a single split
+    fn random(&self, depth: u32, low: u128) -> u128 {
+        let mut nonce = [0; 12];
+        nonce[0] = depth as u8;
+        nonce[1..9].copy_from_slice(&(low as u64).to_le_bytes());
+
+        let mut block = [0; 16];
+        ChaCha20::new(&self.key.into(), &nonce.into()).apply_keystream(&mut block);
+        u128::from_le_bytes(block)
+    }
+
+    /// Walk down the splits of the domain towards the ordinal `target` picks at every split, and
+    /// return the ordinal and the code of the leaf reached
+    fn descend(&self, target: impl Fn(u128, u128) -> bool) -> (u128, u128) {
+        let (mut low, mut high) = (0u128, u128::MAX >> (128 - bits::<K>()));
+        let (mut code_low, mut code_high) = (0u128, u128::MAX >> (129 - bits::<E>()));
+
+        for depth in 0.. {
+            let room = code_high - code_low;
+
+            // A single key left is placed anywhere in the codes left for it
+            if low == high {
+                return (low, code_low + self.random(depth, low) % (room + 1));
+            }
+
+            // The middle key goes where both halves still have a code for each of their keys
+            let middle = low + (high - low) / 2;
+            let (left, right) = (middle - low + 1, high - middle);
+            let split = code_low + (left - 1) + self.random(depth, low) % (room + 2 - left - right);
+
+            if target(middle, split) {
+                (high, code_high) = (middle, split);
+            } else {
+                (low, code_low) = (middle + 1, split + 1);
+            }
+        }
+
+        unreachable!()
+    }
+
+    pub fn encode(&self, key: K) -> E {
+        let ordinal = Self::ordinal(key);
+        let (_, code) = self.descend(|middle, _| ordinal <= middle);
+
+        NumCast::from(code).expect("codes fit in all but the top bit")
+    }
+
+    /// The key `code` encodes, or `None` if it isn't the code of any key under this encoding
+    pub fn decode(&self, code: E) -> Option<K> {
+        let code = code.to_u128()?;
+        if code >> (bits::<E>() - 1) != 0 {
+            return None;
+        }
+
+        let (ordinal, leaf) = self.descend(|_, split| code <= split);
+        (leaf == code).then(|| Self::from_ordinal(ordinal))
+    }
+
+    /// The codes of the keys in `range`, as a range over codes
+    pub fn encode_range(&self, range: impl RangeBounds<K>) -> (Bound<E>, Bound<E>) {
+        let encode = |bound: Bound<&K>| match bound {
+            Bound::Included(&key) => Bound::Included(self.encode(key)),
+            Bound::Excluded(&key) => Bound::Excluded(self.encode(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        (encode(range.start_bound()), encode(range.end_bound()))
+    }
+}
+
+/// An index storing its keys as codes of an `OrderPreserving` encoding, which encodes keys as they
+/// are searched and inserted, and decodes them as they are read back from ranges. The wrapped
+/// index, and anything it writes to disk, only ever sees the codes.
+pub struct Encoded<I, K, E> {
+    index: I,
+    encoding: OrderPreserving<K, E>,
+}
+
+impl<I, K: Key + PrimInt, E: Key + PrimInt> Encoded<I, K, E> {
+    /// Wrap an index over codes encoded under `key`, which has to be the same key every time the
+    /// index is wrapped
+    pub fn new(index: I, key: &EncryptionKey) -> Self {
+        Self {
+            index,
+            encoding: OrderPreserving::new(key),
+        }
+    }
+
+    /// The wrapped index. Writes have to go through the wrapper, so it is only lent out immutably.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+
+    pub fn encoding(&self) -> &OrderPreserving<K, E> {
+        &self.encoding
+    }
+
+    /// The entries with keys in `range`, in key order
+    pub fn range<V: Value>(&self, range: impl RangeBounds<K>) -> EncodedRange<'_, K, V, E, I>
+    where
+        I: CursorIndex<E, V>,
+    {
+        let (start, end) = self.encoding.encode_range(range);
+
+        let cursor = match start {
+            Bound::Included(code) => Cursor::new(&self.index, &code),
+            Bound::Excluded(code) => {
+                let mut cursor = Cursor::new(&self.index, &code);
+                if cursor.key() == Some(&code) {
+                    cursor.move_next();
+                }
+                cursor
+            }
+            // Codes are never negative
+            Bound::Unbounded => Cursor::new(&self.index, &E::zero()),
+        };
+
+        EncodedRange {
+            cursor,
+            end,
+            encoding: &self.encoding,
+        }
+    }
+}
+
+impl<I, K, V, E> IndexRead<K, V> for Encoded<I, K, E>
+where
+    I: IndexRead<E, V>,
+    K: Key + PrimInt,
+    E: Key + PrimInt,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        self.index.try_search(self.encoding.encode(key))
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<I, K, V, E> IndexWrite<K, V> for Encoded<I, K, E>
+where
+    I: IndexWrite<E, V>,
+    K: Key + PrimInt,
+    E: Key + PrimInt,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        self.index.try_put(self.encoding.encode(key), value)
+    }
+}
+
+/// Iterator over the entries of an `Encoded` index in a range of keys, returned by
+/// `Encoded::range`
+pub struct EncodedRange<'a, K, V, E, I: CursorIndex<E, V>> {
+    cursor: Cursor<'a, E, V, I>,
+    end: Bound<E>,
+    encoding: &'a OrderPreserving<K, E>,
+}
+
+impl<K, V, E, I> Iterator for EncodedRange<'_, K, V, E, I>
+where
+    K: Key + PrimInt,
+    V: Value,
+    E: Key + PrimInt,
+    I: CursorIndex<E, V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&code, value) = self.cursor.current()?;
+        let past = match self.end {
+            Bound::Included(end) => code > end,
+            Bound::Excluded(end) => code >= end,
+            Bound::Unbounded => false,
+        };
+
+        if past {
+            return None;
+        }
+
+        let key = self
+            .encoding
+            .decode(code)
+            .expect("index holds a code which wasn't encoded under its key");
+        let entry = (key, value.clone());
+        self.cursor.move_next();
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::TestRng;
+
+    #[test]
+    fn encoding_preserves_order() {
+        let encoding = OrderPreserving::<i64, i128>::new(&EncryptionKey::from_bytes([7; 32]));
+        let mut rng = TestRng::new(3);
+
+        let mut keys: Vec<i64> = (0..500).map(|_| rng.next_u64() as i64).collect();
+        keys.extend([i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX - 1, i64::MAX]);
+        keys.sort();
+        keys.dedup();
+
+        let codes: Vec<i128> = keys.iter().map(|&key| encoding.encode(key)).collect();
+        assert!(codes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(codes.iter().all(|&code| code >= 0));
+
+        for (&key, &code) in keys.iter().zip(&codes) {
+            assert_eq!(encoding.decode(code), Some(key));
+        }
+
+        // Codes between the codes of two keys encode nothing
+        assert_eq!(encoding.decode(codes[100] + 1), None);
+        assert_eq!(encoding.decode(-1), None);
+    }
+
+    #[test]
+    fn encoding_depends_on_key() {
+        let one = OrderPreserving::<u32, u64>::new(&EncryptionKey::from_bytes([1; 32]));
+        let other = OrderPreserving::<u32, u64>::new(&EncryptionKey::from_bytes([2; 32]));
+
+        assert_eq!(one.encode(42), one.encode(42));
+        assert_ne!(one.encode(42), other.encode(42));
+        assert_eq!(one.decode(one.encode(u32::MAX)), Some(u32::MAX));
+    }
+}
@@ -0,0 +1,89 @@
+//! Paging through persisted indexes across restarts. A `CursorToken` remembers where a page of
+//! entries ended: the base node holding the last key handed out, the position just past that key
+//! within the node, and the key itself. Resuming from a token reads on from that node without
+//! descending from the top, as long as the node still covers the key. Otherwise the key is sought
+//! again, so a token stays valid however the index changed since it was handed out.
+
+use crate::common::storage::format;
+use crate::kv_store::routes_to;
+use crate::node_layer::NodeLayer;
+use crate::traits::{Address, Persisted};
+use serde::{Deserialize, Serialize};
+
+/// A resumable position in the base layer of a persisted index, just after the key `after`.
+/// Tokens are opaque to clients, which can hold on to them as bytes through `to_bytes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorToken<K, SA> {
+    node: SA,
+
+    /// Index of the first entry after `after` in the node, unknown for tokens which were sought
+    /// rather than handed out with a page
+    offset: Option<usize>,
+
+    after: K,
+}
+
+impl<K, SA> CursorToken<K, SA> {
+    pub fn new(node: SA, offset: Option<usize>, after: K) -> Self {
+        Self {
+            node,
+            offset,
+            after,
+        }
+    }
+
+    /// The key which entries read from the token follow
+    pub fn after(&self) -> &K {
+        &self.after
+    }
+
+    /// The index within its node of the first entry to read, if the node hasn't changed since
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// The base node the token points at, if it's still the node a descent would route its key to
+    pub fn node_for<PA: Address>(&self, layer: &impl NodeLayer<K, SA, PA>) -> Option<SA>
+    where
+        K: Ord,
+        SA: Address + Copy,
+    {
+        let node = self.node;
+        (layer.contains_node(node) && routes_to(layer, node, &self.after)).then_some(node)
+    }
+}
+
+impl<K: Persisted, SA: Persisted> CursorToken<K, SA> {
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        format::encode(self)
+    }
+
+    /// Decode a token written by `to_bytes`, failing on anything else
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<Self> {
+        format::decode(bytes).map_err(|_| anyhow::anyhow!("Invalid cursor token!"))
+    }
+}
+
+/// Entries read from a `CursorToken`, in key order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorPage<K, V, SA> {
+    pub entries: Vec<(K, V)>,
+
+    /// Resumes after the last entry of the page, `None` once every entry has been read
+    pub next: Option<CursorToken<K, SA>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_token_round_trip() {
+        let token = CursorToken::<u64, u64>::new(7, Some(3), 42);
+        let bytes = token.to_bytes().unwrap();
+
+        assert_eq!(CursorToken::from_bytes(&bytes).unwrap(), token);
+        assert!(CursorToken::<u64, u64>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(CursorToken::<u64, u64>::from_bytes(b"LIMO\x09garbage").is_err());
+    }
+}
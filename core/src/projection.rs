@@ -0,0 +1,102 @@
+//! Reads of a few fields of a value. A struct value which derives `Projectable` can be searched
+//! with `search_project::<S>(key)`, where `S` is a `FieldSelector` generated for one of its
+//! fields, or a tuple of them. Persisted base layers then read the fields straight from the bytes
+//! of the page: entries before the key only have their values skipped, entries after the key, and
+//! fields after the last one picked, aren't read at all, and nothing is added to the page cache.
+//!
+//! Fields are read in the order they are serialized, so the value has to use the plain
+//! `Serialize` and `Deserialize` derives, without attributes which change its layout.
+
+use core::marker::PhantomData;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+/// A struct whose fields can be read on their own, implemented with `#[derive(Projectable)]`
+pub trait Projectable: Sized {
+    /// Names of the fields, in the order they are serialized
+    const FIELDS: &'static [&'static str];
+
+    /// Every field as an `Option`, holding only the fields which were read
+    type Partial: Default;
+
+    /// Read the fields in `mask` from a serialized value. Reading stops after the last field in
+    /// `mask`, the fields before it which aren't in `mask` are skipped.
+    fn read_fields<'de, A: SeqAccess<'de>>(seq: A, mask: u64) -> Result<Self::Partial, A::Error>;
+
+    /// Copy the fields in `mask` out of a value in memory
+    fn copy_fields(&self, mask: u64) -> Self::Partial;
+}
+
+/// Picks some of the fields of a `Projectable` value
+pub trait FieldSelector<V: Projectable> {
+    /// Bit `i` is set if the `i`th field is picked
+    const MASK: u64;
+
+    type Output;
+
+    /// Take the picked fields out of a partial value which holds at least the fields in `MASK`
+    fn get(partial: &V::Partial) -> Self::Output;
+}
+
+macro_rules! impl_tuple_selector {
+    ($($s:ident),+) => {
+        impl<V: Projectable, $($s: FieldSelector<V>),+> FieldSelector<V> for ($($s,)+) {
+            const MASK: u64 = $($s::MASK)|+;
+
+            type Output = ($($s::Output,)+);
+
+            fn get(partial: &V::Partial) -> Self::Output {
+                ($($s::get(partial),)+)
+            }
+        }
+    };
+}
+
+impl_tuple_selector!(A);
+impl_tuple_selector!(A, B);
+impl_tuple_selector!(A, B, C);
+impl_tuple_selector!(A, B, C, D);
+
+/// The fields picked by `S` of a value in memory
+pub fn project<V: Projectable, S: FieldSelector<V>>(value: &V) -> S::Output {
+    S::get(&value.copy_fields(S::MASK))
+}
+
+/// Deserializes a `T` and drops it, used to step over fields and values which aren't read
+pub struct Skip<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Skip<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer)?;
+        Ok(Skip(PhantomData))
+    }
+}
+
+/// Reads the fields picked by `S` out of a serialized `V`
+pub struct ProjectSeed<V, S>(PhantomData<(V, S)>);
+
+impl<V, S> Default for ProjectSeed<V, S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<'de, V: Projectable, S: FieldSelector<V>> DeserializeSeed<'de> for ProjectSeed<V, S> {
+    type Value = S::Output;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(V::FIELDS.len(), self)
+    }
+}
+
+impl<'de, V: Projectable, S: FieldSelector<V>> Visitor<'de> for ProjectSeed<V, S> {
+    type Value = S::Output;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(formatter, "a struct with {} fields", V::FIELDS.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        Ok(S::get(&V::read_fields(seq, S::MASK)?))
+    }
+}
@@ -0,0 +1,264 @@
+//! Read-copy-update for indexes shared between threads. Readers pin the current version of an
+//! index with a guard, which only takes an atomic increment and never waits. Writers build the next
+//! version off to the side, such as a copy with a rebuilt layer or a swapped top, and atomically
+//! swap it in. Versions which were swapped out are reclaimed once no reader can still hold them, so
+//! even a full rebuild of every layer never blocks a single read.
+//!
+//! Building the next version clones the current one. A clone copies every node, unless
+//! `snapshot` moved the nodes of the index behind a reference count first, in which case it still
+//! copies one `Arc` per node and the top layer in full. Either way each swap costs O(n), which
+//! `extend` amortizes over many writes by applying them to a single clone.
+//!
+//! Readers are counted per epoch, in one of two counters by the parity of the epoch they pinned
+//! in. Writers only advance the epoch once the counter of the previous epoch has drained, so a
+//! version retired in epoch `e` is unreachable once the epoch reaches `e + 2`: both counters were
+//! seen empty after it was swapped out.
+
+use crate::kv_store::KVStore;
+use crate::traits::{Key, Value};
+use std::convert::Infallible;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// A value which readers see consistently while writers replace it. Every atomic uses `SeqCst`,
+/// reclamation relies on the total order of the swaps, reader counts and epochs.
+pub struct Rcu<T> {
+    current: AtomicPtr<T>,
+    epoch: AtomicUsize,
+
+    /// Readers which pinned in an even and in an odd epoch respectively
+    readers: [AtomicUsize; 2],
+
+    /// Versions swapped out, with the epoch they were retired in. Also serializes writers.
+    retired: Mutex<Vec<(usize, *mut T)>>,
+}
+
+// Versions are only freed once no reader can reach them, and the value is shared between readers
+unsafe impl<T: Send + Sync> Send for Rcu<T> {}
+unsafe impl<T: Send + Sync> Sync for Rcu<T> {}
+
+/// Hold the lock of the retired list even if a panicking writer poisoned it, the list itself is
+/// only modified once the next version was built
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<T> Rcu<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Pin the current version, which stays valid for as long as the guard lives. Never waits,
+    /// but a guard held for long delays the reclamation of every version replaced meanwhile.
+    pub fn read(&self) -> RcuGuard<'_, T> {
+        let slot = self.epoch.load(Ordering::SeqCst) & 1;
+        self.readers[slot].fetch_add(1, Ordering::SeqCst);
+
+        RcuGuard {
+            rcu: self,
+            slot,
+            value: self.current.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Swap in the version built by `next` from the current one. Writers are serialized, so
+    /// `next` always sees the latest version, while readers carry on with the current one until
+    /// the swap.
+    pub fn update(&self, next: impl FnOnce(&T) -> T) {
+        let Ok(()) = self.try_update(|current| Ok::<_, Infallible>(next(current)));
+    }
+
+    /// Like `update`, but keeps the current version if building the next one fails, so readers
+    /// see either all of a change or none of it
+    pub fn try_update<E>(&self, next: impl FnOnce(&T) -> Result<T, E>) -> Result<(), E> {
+        let mut retired = lock(&self.retired);
+
+        // Only writers swap versions, so the current one can't be retired under this writer
+        let current = self.current.load(Ordering::SeqCst);
+        let next = Box::into_raw(Box::new(next(unsafe { &*current })?));
+
+        self.current.store(next, Ordering::SeqCst);
+        retired.push((self.epoch.load(Ordering::SeqCst), current));
+
+        self.reclaim(&mut retired);
+        Ok(())
+    }
+
+    /// Apply `modify` to a copy of the current version, and swap the copy in, for instance to
+    /// `rebuild` the index or `swap_top` while readers carry on with the current version
+    pub fn modify(&self, modify: impl FnOnce(&mut T))
+    where
+        T: Clone,
+    {
+        self.update(|current| {
+            let mut next = current.clone();
+            modify(&mut next);
+            next
+        });
+    }
+
+    /// Replace the current version with `value`
+    pub fn replace(&self, value: T) {
+        self.update(move |_| value);
+    }
+
+    /// Number of versions swapped out but not reclaimed yet, since readers may still hold them
+    pub fn pending(&self) -> usize {
+        let mut retired = lock(&self.retired);
+        self.reclaim(&mut retired);
+        retired.len()
+    }
+
+    /// Advance the epoch as far as readers allow, and free the versions no reader can reach
+    fn reclaim(&self, retired: &mut Vec<(usize, *mut T)>) {
+        for _ in 0..2 {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+
+            // Readers of the previous epoch pinned in the slot the next epoch reuses
+            if self.readers[(epoch + 1) & 1].load(Ordering::SeqCst) != 0 {
+                break;
+            }
+
+            self.epoch.store(epoch + 1, Ordering::SeqCst);
+        }
+
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        retired.retain(|&(retired_in, value)| {
+            if epoch < retired_in + 2 {
+                return true;
+            }
+
+            drop(unsafe { Box::from_raw(value) });
+            false
+        });
+    }
+
+    pub fn into_inner(mut self) -> T {
+        // Leaves nothing for `drop` to free but the retired versions
+        let current = std::mem::replace(self.current.get_mut(), std::ptr::null_mut());
+        *unsafe { Box::from_raw(current) }
+    }
+}
+
+impl<T> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        // No reader outlives the borrow of the `Rcu`, so every version can be freed
+        let retired = self
+            .retired
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for (_, value) in retired.drain(..) {
+            drop(unsafe { Box::from_raw(value) });
+        }
+
+        let current = *self.current.get_mut();
+        if !current.is_null() {
+            drop(unsafe { Box::from_raw(current) });
+        }
+    }
+}
+
+impl<T: Default> Default for Rcu<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// A version of the value of an `Rcu`, pinned by `Rcu::read`
+pub struct RcuGuard<'a, T> {
+    rcu: &'a Rcu<T>,
+    slot: usize,
+    value: *const T,
+}
+
+impl<T> Deref for RcuGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Pinned, so the version isn't freed before the guard is dropped
+        unsafe { &*self.value }
+    }
+}
+
+impl<T> Drop for RcuGuard<'_, T> {
+    fn drop(&mut self) {
+        self.rcu.readers[self.slot].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<I> Rcu<I> {
+    pub fn search<K: Key, V: Value>(&self, key: K) -> Option<V>
+    where
+        I: KVStore<K, V>,
+    {
+        self.read().search(key)
+    }
+
+    /// Insert a key into a copy of the current version, and swap it in, returning the value the
+    /// key held before. Copies the whole index, prefer `extend` for more than a few keys.
+    pub fn insert<K: Key, V: Value>(&self, key: K, value: V) -> Option<V>
+    where
+        I: KVStore<K, V> + Clone,
+    {
+        let mut previous = None;
+        self.modify(|index| previous = index.insert(key, value));
+        previous
+    }
+
+    /// Insert every entry into one copy of the current version, and swap it in once, so readers
+    /// see all of the entries or none of them
+    pub fn extend<K: Key, V: Value>(&self, entries: impl IntoIterator<Item = (K, V)>)
+    where
+        I: KVStore<K, V> + Clone,
+    {
+        self.modify(|index| {
+            for (key, value) in entries {
+                index.insert(key, value);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn rcu_reclaims_unpinned_versions() {
+        // Every version holds a reference, so the count tells how many are still alive
+        let alive = Arc::new(());
+        let rcu = Rcu::new((0, alive.clone()));
+
+        let first = rcu.read();
+        rcu.update(|(version, alive)| (version + 1, alive.clone()));
+        let second = rcu.read();
+        rcu.update(|(version, alive)| (version + 1, alive.clone()));
+
+        assert_eq!((first.0, second.0, rcu.read().0), (0, 1, 2));
+        assert_eq!(rcu.pending(), 2);
+        assert_eq!(Arc::strong_count(&alive), 4);
+
+        drop(first);
+        assert_eq!(rcu.pending(), 1);
+        drop(second);
+        assert_eq!(rcu.pending(), 0);
+        assert_eq!(Arc::strong_count(&alive), 2);
+
+        // A failed update keeps the current version
+        assert_eq!(rcu.try_update(|_| Err(())), Err(()));
+        assert_eq!(rcu.read().0, 2);
+
+        drop(rcu);
+        assert_eq!(Arc::strong_count(&alive), 1);
+    }
+}
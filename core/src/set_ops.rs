@@ -0,0 +1,121 @@
+//! Set operations over the keys of two indexes, for indexes used as posting lists. Both walk the
+//! base layers of the indexes side by side with a cursor each, in a single linear merge, so they
+//! cost one descent per index and a step per entry from then on.
+
+use crate::cursor::{Cursor, CursorIndex};
+use crate::traits::{Key, Value};
+use core::cmp::Ordering;
+
+/// Iterator over the keys present in both of two indexes, in key order, returned by
+/// `intersect_keys`
+pub struct IntersectKeys<'a, K, V, W, I: CursorIndex<K, V>, J: CursorIndex<K, W>> {
+    left: Cursor<'a, K, V, I>,
+    right: Cursor<'a, K, W, J>,
+}
+
+impl<'a, K, V, W, I, J> IntersectKeys<'a, K, V, W, I, J>
+where
+    K: Key,
+    V: Value,
+    W: Value,
+    I: CursorIndex<K, V>,
+    J: CursorIndex<K, W>,
+{
+    pub fn new(left: &'a I, right: &'a J) -> Self {
+        Self {
+            left: Cursor::first(left),
+            right: Cursor::first(right),
+        }
+    }
+}
+
+impl<'a, K, V, W, I, J> Iterator for IntersectKeys<'a, K, V, W, I, J>
+where
+    K: Key,
+    V: Value,
+    W: Value,
+    I: CursorIndex<K, V>,
+    J: CursorIndex<K, W>,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Cursors wrap around past their last entry, so neither moves once it ran out
+        loop {
+            let (left, right) = (self.left.key()?, self.right.key()?);
+
+            match left.cmp(right) {
+                Ordering::Less => self.left.move_next(),
+                Ordering::Greater => self.right.move_next(),
+                Ordering::Equal => {
+                    self.left.move_next();
+                    self.right.move_next();
+                    return Some(left);
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the keys present in either of two indexes, in key order, returned by `union`.
+/// Keys present in both are returned once.
+pub struct Union<'a, K, V, W, I: CursorIndex<K, V>, J: CursorIndex<K, W>> {
+    left: Cursor<'a, K, V, I>,
+    right: Cursor<'a, K, W, J>,
+}
+
+impl<'a, K, V, W, I, J> Union<'a, K, V, W, I, J>
+where
+    K: Key,
+    V: Value,
+    W: Value,
+    I: CursorIndex<K, V>,
+    J: CursorIndex<K, W>,
+{
+    pub fn new(left: &'a I, right: &'a J) -> Self {
+        Self {
+            left: Cursor::first(left),
+            right: Cursor::first(right),
+        }
+    }
+}
+
+impl<'a, K, V, W, I, J> Iterator for Union<'a, K, V, W, I, J>
+where
+    K: Key,
+    V: Value,
+    W: Value,
+    I: CursorIndex<K, V>,
+    J: CursorIndex<K, W>,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.left.key(), self.right.key()) {
+            (Some(left), Some(right)) => match left.cmp(right) {
+                Ordering::Less => {
+                    self.left.move_next();
+                    Some(left)
+                }
+                Ordering::Greater => {
+                    self.right.move_next();
+                    Some(right)
+                }
+                Ordering::Equal => {
+                    self.left.move_next();
+                    self.right.move_next();
+                    Some(left)
+                }
+            },
+            (Some(left), None) => {
+                self.left.move_next();
+                Some(left)
+            }
+            (None, Some(right)) => {
+                self.right.move_next();
+                Some(right)
+            }
+            (None, None) => None,
+        }
+    }
+}
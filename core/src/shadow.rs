@@ -0,0 +1,193 @@
+//! Differential checking against a `BTreeMap`. A `Shadowed` index forwards every operation to both
+//! the wrapped index and a replica, and panics as soon as their results differ, which makes it a
+//! drop-in correctness canary when changing a layout or upgrading between versions.
+
+use crate::testkit::{TestRng, DEFAULT_SEED};
+use crate::{IndexRead, IndexWrite};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// An index mirrored into a `BTreeMap`. Inserts are always applied to both, since the replica
+/// would drift otherwise, while only a sampled fraction of searches is checked against it.
+pub struct Shadowed<I, K, V> {
+    index: I,
+    replica: BTreeMap<K, V>,
+
+    /// Fraction of searches checked against the replica
+    sample: f64,
+    rng: RefCell<TestRng>,
+}
+
+impl<I, K, V> Shadowed<I, K, V>
+where
+    K: Ord + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+{
+    /// Shadow an empty index
+    pub fn new(index: I) -> Self {
+        Self::with_entries(index, std::iter::empty())
+    }
+
+    /// Shadow an index which already holds `entries`, such as one created with `build`
+    pub fn with_entries(index: I, entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        Self {
+            index,
+            replica: entries.into_iter().collect(),
+            sample: 1.0,
+            rng: RefCell::new(TestRng::new(DEFAULT_SEED)),
+        }
+    }
+
+    /// Only check a `fraction` of searches, chosen at random
+    pub fn sample_reads(mut self, fraction: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "Sampled fraction must be within 0 and 1!"
+        );
+
+        self.sample = fraction;
+        self
+    }
+
+    /// The wrapped index. Writes have to go through the wrapper, so it is only lent out immutably.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+
+    pub fn len(&self) -> usize {
+        self.replica.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replica.is_empty()
+    }
+
+    /// Check every entry of the replica against the index, regardless of sampling
+    pub fn verify(&self) -> crate::Result<()>
+    where
+        I: IndexRead<K, V>,
+    {
+        for (key, value) in self.replica.iter() {
+            check(
+                "search",
+                key,
+                &self.index.try_search(key.clone())?,
+                &Some(value.clone()),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn check<K: Debug, V: PartialEq + Debug>(op: &str, key: &K, actual: &V, expected: &V) {
+    assert!(
+        actual == expected,
+        "Shadowed {} of {:?} returned {:?}, expected {:?}!",
+        op,
+        key,
+        actual,
+        expected
+    );
+}
+
+impl<I, K, V> IndexRead<K, V> for Shadowed<I, K, V>
+where
+    I: IndexRead<K, V>,
+    K: Ord + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        let actual = self.index.try_search(key.clone())?;
+
+        if self.sample >= 1.0 || self.rng.borrow_mut().chance(self.sample) {
+            check("search", &key, &actual, &self.replica.get(&key).cloned());
+        }
+
+        Ok(actual)
+    }
+
+    fn len(&self) -> usize {
+        let actual = self.index.len();
+        check("len", &(), &actual, &self.replica.len());
+        actual
+    }
+}
+
+impl<I, K, V> IndexWrite<K, V> for Shadowed<I, K, V>
+where
+    I: IndexWrite<K, V>,
+    K: Ord + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        let actual = self.index.try_put(key.clone(), value.clone())?;
+        let expected = self.replica.insert(key.clone(), value);
+
+        check("insert", &key, &actual, &expected);
+        Ok(actual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Forgets every other insert
+    #[derive(Default)]
+    struct Lossy {
+        map: BTreeMap<u64, u64>,
+        inserts: usize,
+    }
+
+    impl IndexRead<u64, u64> for Lossy {
+        fn try_search(&self, key: u64) -> crate::Result<Option<u64>> {
+            Ok(self.map.get(&key).copied())
+        }
+
+        fn len(&self) -> usize {
+            self.map.len()
+        }
+    }
+
+    impl IndexWrite<u64, u64> for Lossy {
+        fn try_put(&mut self, key: u64, value: u64) -> crate::Result<Option<u64>> {
+            self.inserts += 1;
+
+            if self.inserts.is_multiple_of(2) {
+                return Ok(None);
+            }
+
+            Ok(self.map.insert(key, value))
+        }
+    }
+
+    #[test]
+    fn shadowed_sampled_reads() {
+        let mut index = Shadowed::new(Lossy::default()).sample_reads(0.0);
+
+        for key in 0..100 {
+            index.try_put(key, key).unwrap();
+        }
+
+        // Unsampled reads of forgotten keys go unnoticed
+        assert_eq!(index.try_search(1).unwrap(), None);
+        let verify = std::panic::AssertUnwindSafe(|| index.verify());
+        assert!(std::panic::catch_unwind(verify).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Shadowed search of 1")]
+    fn shadowed_divergence() {
+        let mut index = Shadowed::new(Lossy::default());
+
+        index.try_put(0, 0).unwrap();
+        index.try_put(1, 1).unwrap();
+        index.try_search(1).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+//! Partitioned indexes for multi-core ingest. A `Sharded` index spreads its keys over `N` inner
+//! indexes, each behind its own lock, so writers to different shards never wait on each other.
+//! Keys are assigned to shards either by hash, which spreads any key distribution evenly, or by
+//! split keys, which keeps each shard a contiguous range of the key space.
+//!
+//! A striped index is range partitioned at the fences of an existing index, the lower bounds of the
+//! nodes of one of its internal layers, so that no node of that layer straddles two stripes. Each
+//! stripe is a whole index over its range behind a lock of its own, so writers to disjoint ranges
+//! proceed in parallel, and readers only ever wait on writers to their own stripe.
+
+use crate::cursor::{Cursor, CursorIndex};
+use crate::kv_store::KVStore;
+use crate::traits::{Key, Value};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// How keys are assigned to shards
+enum Partition<K> {
+    Hash(fn(&K) -> u64),
+    /// The keys each shard but the first starts at, up to `N - 1` of them
+    Range(Vec<K>),
+}
+
+fn hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Take a lock even if a panicking writer poisoned it, an index is left consistent by every
+/// operation which returned
+fn read<I>(lock: &RwLock<I>) -> RwLockReadGuard<'_, I> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<I>(lock: &RwLock<I>) -> RwLockWriteGuard<'_, I> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An index partitioned over `N` inner indexes. Every operation on a single key only locks the
+/// shard it belongs to, and takes `&self`, so a `Sharded` index can be shared between threads.
+pub struct Sharded<I, K, V, const N: usize> {
+    shards: [RwLock<I>; N],
+    partition: Partition<K>,
+    _ph: std::marker::PhantomData<V>,
+}
+
+impl<I, K, V, const N: usize> Sharded<I, K, V, N>
+where
+    I: KVStore<K, V>,
+    K: Key,
+    V: Value,
+{
+    fn new(partition: Partition<K>) -> Self {
+        assert!(N > 0, "A sharded index needs at least one shard!");
+
+        Self {
+            shards: std::array::from_fn(|_| RwLock::new(I::empty())),
+            partition,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    /// Empty shards, with keys assigned to them by hash
+    pub fn hashed() -> Self
+    where
+        K: Hash,
+    {
+        Self::new(Partition::Hash(hash::<K>))
+    }
+
+    /// Empty shards, with keys assigned to them by `N - 1` increasing split keys. Shard `i` holds
+    /// the keys from split key `i - 1` up to, but excluding, split key `i`.
+    pub fn ranged(splits: impl IntoIterator<Item = K>) -> Self {
+        let splits: Vec<K> = splits.into_iter().collect();
+        assert!(
+            splits.len() + 1 == N && splits.windows(2).all(|pair| pair[0] < pair[1]),
+            "Range partitioning needs N - 1 increasing split keys!"
+        );
+
+        Self::new(Partition::Range(splits))
+    }
+
+    /// Range partition the entries of `index` at `N - 1` of `fences`, which have to be increasing,
+    /// spaced evenly over them. The first fence bounds the whole index, so it never splits it.
+    /// Copies every entry out of `index` and builds each stripe from scratch, as `with_entries`
+    /// does. Fewer fences than stripes leave the last stripes empty until keys past the last fence
+    /// arrive.
+    pub fn striped(index: I, fences: impl IntoIterator<Item = K>) -> Self
+    where
+        I: CursorIndex<K, V> + Send + Sync,
+        K: Send,
+        V: Send,
+    {
+        let fences: Vec<K> = fences.into_iter().collect();
+
+        let mut splits: Vec<K> = (1..N)
+            .map(|stripe| stripe * fences.len() / N)
+            .filter(|&fence| fence > 0)
+            .map(|fence| fences[fence])
+            .collect();
+        splits.dedup();
+
+        let mut entries = Vec::new();
+        let mut cursor = Cursor::first(&index);
+        while let Some((&key, value)) = cursor.current() {
+            entries.push((key, value.clone()));
+            cursor.move_next();
+        }
+
+        // Free the nodes of the index before the stripes are built
+        drop(index);
+        Self::new(Partition::Range(splits)).with_entries(entries)
+    }
+
+    /// Replace the contents of every shard with `entries`, which have to be sorted by key as for
+    /// `build`. Each shard is built on its own thread.
+    pub fn with_entries(self, entries: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        I: Send + Sync,
+        K: Send,
+        V: Send,
+    {
+        let mut parts: [Vec<(K, V)>; N] = std::array::from_fn(|_| Vec::new());
+        for (key, value) in entries {
+            parts[self.shard_of(&key)].push((key, value));
+        }
+
+        std::thread::scope(|scope| {
+            for (shard, part) in self.shards.iter().zip(parts) {
+                scope.spawn(move || *write(shard) = I::build(part.into_iter()));
+            }
+        });
+
+        self
+    }
+
+    /// The shard `key` belongs to
+    pub fn shard_of(&self, key: &K) -> usize {
+        match &self.partition {
+            Partition::Hash(hash) => (hash(key) % N as u64) as usize,
+            Partition::Range(splits) => splits.partition_point(|split| split <= key),
+        }
+    }
+
+    pub fn search(&self, key: K) -> Option<V> {
+        read(&self.shards[self.shard_of(&key)]).search(key)
+    }
+
+    /// Insert a key, returning the value it held before
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        write(&self.shards[self.shard_of(&key)]).insert(key, value)
+    }
+
+    /// Lock every shard for reading, for operations spanning all of them such as `range`. Shards
+    /// are always locked in order, and writers only ever hold a single lock, so this can't
+    /// deadlock, but it does block writers until the guard is dropped.
+    pub fn read(&self) -> ShardedRead<'_, I, N> {
+        ShardedRead {
+            shards: std::array::from_fn(|shard| read(&self.shards[shard])),
+        }
+    }
+
+    pub fn into_shards(self) -> [I; N] {
+        self.shards.map(|shard| {
+            shard
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        })
+    }
+}
+
+/// Every shard of a `Sharded` index, locked for reading, returned by `Sharded::read`
+pub struct ShardedRead<'a, I, const N: usize> {
+    shards: [RwLockReadGuard<'a, I>; N],
+}
+
+impl<I, const N: usize> ShardedRead<'_, I, N> {
+    pub fn shard(&self, shard: usize) -> &I {
+        &self.shards[shard]
+    }
+
+    /// The entries of every shard with keys in `range`, merged into key order
+    pub fn range<K, V>(&self, range: impl RangeBounds<K>) -> ShardedRange<'_, K, V, I, N>
+    where
+        K: Key,
+        V: Value,
+        I: CursorIndex<K, V>,
+    {
+        let cursors = std::array::from_fn(|shard| {
+            let index: &I = &self.shards[shard];
+
+            match range.start_bound() {
+                Bound::Included(key) => Cursor::new(index, key),
+                Bound::Excluded(key) => {
+                    let mut cursor = Cursor::new(index, key);
+                    if cursor.key() == Some(key) {
+                        cursor.move_next();
+                    }
+                    cursor
+                }
+                Bound::Unbounded => Cursor::first(index),
+            }
+        });
+
+        ShardedRange {
+            cursors,
+            end: range.end_bound().cloned(),
+        }
+    }
+}
+
+/// Iterator over the entries of a `Sharded` index in a range of keys, merged from a cursor per
+/// shard, returned by `ShardedRead::range`
+pub struct ShardedRange<'a, K, V, I: CursorIndex<K, V>, const N: usize> {
+    cursors: [Cursor<'a, K, V, I>; N],
+    end: Bound<K>,
+}
+
+impl<K: Key, V: Value, I: CursorIndex<K, V>, const N: usize> Iterator
+    for ShardedRange<'_, K, V, I, N>
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Cursors wrap around past their last entry, so exhausted ones are never moved again
+        let (shard, &key, value) = self
+            .cursors
+            .iter()
+            .enumerate()
+            .filter_map(|(shard, cursor)| {
+                let (key, value) = cursor.current()?;
+                Some((shard, key, value))
+            })
+            .min_by_key(|(_, key, _)| *key)?;
+
+        let within = match self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        if !within {
+            return None;
+        }
+
+        let entry = (key, value.clone());
+        self.cursors[shard].move_next();
+        Some(entry)
+    }
+}
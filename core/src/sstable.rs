@@ -0,0 +1,300 @@
+//! Sorted streams of entries in an SSTable-like format, for handing an index to an LSM engine or
+//! replicating a static index without shipping its layers. A stream starts with a magic number and
+//! the version of the format, followed by blocks of entries in strictly increasing key order. Every
+//! block is prefixed by its length and followed by the checksum of its bytes, and a block of length
+//! zero ends the stream, followed by the number of entries it holds. A reader therefore notices a
+//! corrupted, reordered or truncated stream rather than importing part of it.
+//!
+//! Entries are serialized with the portable page format, so a stream written on one host reads on
+//! any other.
+
+use crate::common::storage::format::{self, checksum};
+use crate::cursor::{Cursor, CursorIndex};
+use crate::kv_store::KVStore;
+use crate::traits::{Key, Persisted, Value};
+use std::io::{Read, Write};
+
+/// Marks a sorted stream
+const MAGIC: [u8; 4] = *b"LIMS";
+
+/// Version of the stream format written by `SortedWriter`
+pub const STREAM_VERSION: u8 = 1;
+
+/// Entries per block of a sorted stream
+pub const BLOCK_ENTRIES: usize = 4096;
+
+/// Writes entries in strictly increasing key order as a sorted stream
+pub struct SortedWriter<W: Write, K, V> {
+    writer: W,
+    block: Vec<(K, V)>,
+    last: Option<K>,
+    entries: u64,
+}
+
+impl<W: Write, K: Key + Persisted, V: Persisted> SortedWriter<W, K, V> {
+    /// Start a stream by writing its header
+    pub fn new(mut writer: W) -> crate::Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[STREAM_VERSION])?;
+
+        Ok(Self {
+            writer,
+            block: Vec::with_capacity(BLOCK_ENTRIES),
+            last: None,
+            entries: 0,
+        })
+    }
+
+    /// Append an entry, which fails unless its key is larger than every key written before
+    pub fn push(&mut self, key: K, value: V) -> crate::Result<()> {
+        if self.last.is_some_and(|last| key <= last) {
+            return Err(anyhow::anyhow!(
+                "Keys of a sorted stream have to be increasing!"
+            ));
+        }
+
+        self.last = Some(key);
+        self.block.push((key, value));
+        self.entries += 1;
+
+        if self.block.len() >= BLOCK_ENTRIES {
+            self.flush_block()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> crate::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let data = format::encode(&self.block)?;
+        self.writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&data)?;
+        self.writer.write_all(&checksum(&data).to_le_bytes())?;
+        self.block.clear();
+
+        Ok(())
+    }
+
+    /// End the stream, returning the writer along with the number of entries written
+    pub fn finish(mut self) -> crate::Result<(W, u64)> {
+        self.flush_block()?;
+        self.writer.write_all(&0u64.to_le_bytes())?;
+        self.writer.write_all(&self.entries.to_le_bytes())?;
+        self.writer.flush()?;
+
+        Ok((self.writer, self.entries))
+    }
+}
+
+/// Reads the entries of a sorted stream, one block at a time. Every block is verified against its
+/// checksum before any of its entries are returned, and the stream fails with an error rather
+/// than ending early if it was cut short.
+pub struct SortedReader<R: Read, K, V> {
+    reader: R,
+    block: std::vec::IntoIter<(K, V)>,
+    last: Option<K>,
+    entries: u64,
+    done: bool,
+}
+
+impl<R: Read, K: Key + Persisted, V: Persisted> SortedReader<R, K, V> {
+    /// Open a stream by reading its header
+    pub fn new(mut reader: R) -> crate::Result<Self> {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header)?;
+
+        match header {
+            [magic @ .., STREAM_VERSION] if magic == MAGIC => {}
+            [magic @ .., version] if magic == MAGIC => {
+                return Err(anyhow::anyhow!(
+                    "Stream was written in version {}, but only versions up to {} can be read!",
+                    version,
+                    STREAM_VERSION
+                ))
+            }
+            _ => return Err(anyhow::anyhow!("Not a sorted stream!")),
+        }
+
+        Ok(Self {
+            reader,
+            block: Vec::new().into_iter(),
+            last: None,
+            entries: 0,
+            done: false,
+        })
+    }
+
+    fn read_u64(&mut self) -> crate::Result<u64> {
+        let mut bytes = [0; 8];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Read the next block, returning false once the end of the stream was verified
+    fn read_block(&mut self) -> crate::Result<bool> {
+        let len = self.read_u64()?;
+
+        if len == 0 {
+            let entries = self.read_u64()?;
+            if entries != self.entries {
+                return Err(anyhow::anyhow!(
+                    "Sorted stream holds {} entries, but {} were read!",
+                    entries,
+                    self.entries
+                ));
+            }
+
+            return Ok(false);
+        }
+
+        let mut data = vec![0; len as usize];
+        self.reader.read_exact(&mut data)?;
+
+        if self.read_u64()? != checksum(&data) {
+            return Err(anyhow::anyhow!(
+                "Block after entry {} of the sorted stream is corrupted!",
+                self.entries
+            ));
+        }
+
+        let block: Vec<(K, V)> = format::decode(&data)?;
+        for (key, _) in block.iter() {
+            if self.last.is_some_and(|last| *key <= last) {
+                return Err(anyhow::anyhow!(
+                    "Keys of the sorted stream are out of order!"
+                ));
+            }
+            self.last = Some(*key);
+        }
+
+        self.entries += block.len() as u64;
+        self.block = block.into_iter();
+
+        Ok(true)
+    }
+}
+
+impl<R: Read, K: Key + Persisted, V: Persisted> Iterator for SortedReader<R, K, V> {
+    type Item = crate::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.block.next() {
+                return Some(Ok(entry));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            match self.read_block() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Writes every entry of an in-memory index whose base layer a cursor can walk, as
+/// `index.export_sorted(writer)`
+pub trait ExportSorted<K, V> {
+    /// Write every entry in key order as a sorted stream, returning the number of entries
+    fn export_sorted(&self, writer: impl Write) -> crate::Result<u64>;
+}
+
+impl<K, V, I> ExportSorted<K, V> for I
+where
+    K: Key + Persisted,
+    V: Value + Persisted,
+    I: CursorIndex<K, V>,
+{
+    fn export_sorted(&self, writer: impl Write) -> crate::Result<u64> {
+        let mut stream = SortedWriter::new(writer)?;
+        let mut cursor = Cursor::first(self);
+
+        while let Some((key, value)) = cursor.current() {
+            stream.push(*key, value.clone())?;
+            cursor.move_next();
+        }
+
+        Ok(stream.finish()?.1)
+    }
+}
+
+/// Builds an in-memory index from a sorted stream, as `Index::import_sorted(reader)`
+pub trait ImportSorted<K, V>: Sized {
+    /// Build an index from the entries of a sorted stream, failing if the stream is corrupted
+    fn import_sorted(reader: impl Read) -> crate::Result<Self>;
+}
+
+impl<K, V, I> ImportSorted<K, V> for I
+where
+    K: Key + Persisted,
+    V: Value + Persisted,
+    I: KVStore<K, V>,
+{
+    fn import_sorted(reader: impl Read) -> crate::Result<Self> {
+        let mut error = None;
+        let entries = SortedReader::new(reader)?.map_while(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(failure) => {
+                error = Some(failure);
+                None
+            }
+        });
+
+        // The stream is built from as it's read, and the index thrown away if it fails
+        let index = I::build(entries);
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(entries: u64) -> Vec<u8> {
+        let mut stream = SortedWriter::new(Vec::new()).unwrap();
+        for key in 0..entries {
+            stream.push(key * 2, key).unwrap();
+        }
+        stream.finish().unwrap().0
+    }
+
+    fn read(data: &[u8]) -> crate::Result<Vec<(u64, u64)>> {
+        SortedReader::new(data)?.collect()
+    }
+
+    #[test]
+    fn sorted_stream_round_trip() {
+        let entries = 3 * BLOCK_ENTRIES as u64 + 5;
+        let data = stream(entries);
+
+        let read = read(&data).unwrap();
+        assert_eq!(read.len() as u64, entries);
+        assert!(read.iter().all(|&(key, value)| key == value * 2));
+        assert_eq!(self::read(&stream(0)).unwrap(), vec![]);
+
+        // Corrupted and truncated streams fail instead of ending early
+        let mut corrupted = data.clone();
+        corrupted[100] ^= 1;
+        assert!(self::read(&corrupted).is_err());
+        assert!(self::read(&data[..data.len() - 8]).is_err());
+        assert!(self::read(&data[..data.len() / 2]).is_err());
+
+        let mut writer = SortedWriter::new(Vec::new()).unwrap();
+        writer.push(2u64, 0u64).unwrap();
+        assert!(writer.push(2, 0).is_err());
+    }
+}
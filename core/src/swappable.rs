@@ -0,0 +1,148 @@
+//! A top component which can be replaced at runtime, generated for layouts with
+//! `swappable_top: true`. An index can start out with a `btree_top` while its key distribution is
+//! still unknown, and switch to an `rmi_top` once it has stabilized, with `swap_top::<RMITop>()`.
+//! Only the top is rebuilt, from the layer below it.
+
+use crate::classical::BTreeTopComponent;
+use crate::component::{PropagateInsert, RemapComponent, TopComponent};
+use crate::explain::Probe;
+use crate::learned::RMITopComponent;
+use crate::node_layer::NodeLayer;
+use crate::traits::Address;
+use crate::Key;
+use core::hash::Hash;
+use hashbrown::HashMap;
+use num::PrimInt;
+
+/// Every top a `SwappableTop` can hold
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum AnyTop<K: Key + PrimInt, X, A> {
+    BTree(BTreeTopComponent<K, X, A>),
+    RMI(RMITopComponent<K, X, A>),
+}
+
+/// A kind of top, which `SwappableTop` can be built as or swapped to
+pub trait TopKind {
+    /// Name of the top, as reported by `explain`
+    const NAME: &'static str;
+
+    fn build<K, X, A, Base>(base: &mut Base) -> AnyTop<K, X, A>
+    where
+        Base: NodeLayer<K, A, ()>,
+        K: Key + PrimInt,
+        A: Address + Copy;
+}
+
+/// The `BTreeTopComponent` kind of top
+#[derive(Clone, Copy, Debug)]
+pub struct BTreeTop;
+
+impl TopKind for BTreeTop {
+    const NAME: &'static str = "BTreeTop";
+
+    fn build<K, X, A, Base>(base: &mut Base) -> AnyTop<K, X, A>
+    where
+        Base: NodeLayer<K, A, ()>,
+        K: Key + PrimInt,
+        A: Address + Copy,
+    {
+        AnyTop::BTree(TopComponent::build(base))
+    }
+}
+
+/// The `RMITopComponent` kind of top
+#[derive(Clone, Copy, Debug)]
+pub struct RMITop;
+
+impl TopKind for RMITop {
+    const NAME: &'static str = "RMITop";
+
+    fn build<K, X, A, Base>(base: &mut Base) -> AnyTop<K, X, A>
+    where
+        Base: NodeLayer<K, A, ()>,
+        K: Key + PrimInt,
+        A: Address + Copy,
+    {
+        AnyTop::RMI(TopComponent::build(base))
+    }
+}
+
+/// A `TopComponent` which is built as the top `T`, and can be swapped to any other kind of top
+#[derive(Clone)]
+pub struct SwappableTop<K: Key + PrimInt, X, A, T = BTreeTop> {
+    top: AnyTop<K, X, A>,
+    name: &'static str,
+    _ph: core::marker::PhantomData<T>,
+}
+
+impl<K: Key + PrimInt, X, A: Address + Copy, T> SwappableTop<K, X, A, T> {
+    /// Rebuild the top as the kind `S` from the layer below it
+    pub fn swap<S: TopKind, Base: NodeLayer<K, A, ()>>(&mut self, base: &mut Base) {
+        self.top = S::build(base);
+        self.name = S::NAME;
+    }
+
+    /// Rebuild the top from the layer below it, as the kind of top it currently holds
+    pub fn rebuild<Base: NodeLayer<K, A, ()>>(&mut self, base: &mut Base) {
+        self.top = match self.top {
+            AnyTop::BTree(_) => BTreeTop::build(base),
+            AnyTop::RMI(_) => RMITop::build(base),
+        };
+    }
+
+    /// Name of the kind of top currently held
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<K, X, BA, T> RemapComponent<BA> for SwappableTop<K, X, BA, T>
+where
+    K: Key + PrimInt,
+    BA: Address + Copy + Hash,
+{
+    fn remap_children(&mut self, remap: &HashMap<BA, BA>) {
+        match self.top {
+            AnyTop::BTree(ref mut top) => top.remap_children(remap),
+            AnyTop::RMI(ref mut top) => top.remap_children(remap),
+        }
+    }
+}
+
+impl<K, X, Base, BA, T> TopComponent<K, Base, BA, ()> for SwappableTop<K, X, BA, T>
+where
+    Base: NodeLayer<K, BA, ()>,
+    K: Key + PrimInt,
+    BA: Address + Copy,
+    T: TopKind,
+{
+    fn search(&self, base: &Base, key: &K) -> BA {
+        match self.top {
+            AnyTop::BTree(ref top) => top.search(base, key),
+            AnyTop::RMI(ref top) => top.search(base, key),
+        }
+    }
+
+    fn probe(&self, base: &Base, key: &K) -> Probe {
+        match self.top {
+            AnyTop::BTree(ref top) => top.probe(base, key),
+            AnyTop::RMI(ref top) => top.probe(base, key),
+        }
+    }
+
+    fn insert(&mut self, base: &mut Base, prop: PropagateInsert<K, BA, ()>) {
+        match self.top {
+            AnyTop::BTree(ref mut top) => top.insert(base, prop),
+            AnyTop::RMI(ref mut top) => top.insert(base, prop),
+        }
+    }
+
+    fn build(base: &mut Base) -> Self {
+        Self {
+            top: T::build(base),
+            name: T::NAME,
+            _ph: core::marker::PhantomData,
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Deterministic generators and a `BTreeMap` oracle for testing indexes. Every generator is driven
+//! by a seeded `TestRng`, so a failing sequence can be replayed exactly from its seed, which is
+//! taken from the `LIMOUSINE_SEED` environment variable when set.
+//!
+//! Indexes don't support removal yet, so operation sequences only mix inserts and searches.
+
+use crate::{Index, IndexRead, Key};
+use num::PrimInt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// Seed used by `TestRng::from_env` when `LIMOUSINE_SEED` is not set
+pub const DEFAULT_SEED: u64 = 0x5EED;
+
+/// A SplitMix64 generator. Unlike the generators in `rand`, its output is fixed forever, so seeds
+/// stay reproducible across dependency upgrades.
+#[derive(Clone, Debug)]
+pub struct TestRng {
+    state: u64,
+}
+
+impl TestRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from `LIMOUSINE_SEED`, or `DEFAULT_SEED` if it is unset or invalid
+    pub fn from_env() -> Self {
+        let seed = std::env::var("LIMOUSINE_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(DEFAULT_SEED);
+
+        Self::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly random number in `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+        assert!(bound > 0, "Empty range!");
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// A uniformly random number in `0.0..1.0`
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// True with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// A uniformly random key over the whole domain of `K`
+    pub fn key<K: Key + PrimInt>(&mut self) -> K {
+        let bits = K::zero().count_zeros() as usize;
+        let mut key = K::zero();
+
+        // Assemble the key bit by bit, since not every bit pattern is castable into `K`
+        for chunk in (0..bits).step_by(64) {
+            let random = self.next_u64();
+
+            for bit in 0..64.min(bits - chunk) {
+                if random >> bit & 1 == 1 {
+                    key = key | (K::one() << (chunk + bit));
+                }
+            }
+        }
+
+        key
+    }
+}
+
+/// `count` random keys, which may contain duplicates
+pub fn unsorted_keys<K: Key + PrimInt>(rng: &mut TestRng, count: usize) -> Vec<K> {
+    (0..count).map(|_| rng.key()).collect()
+}
+
+/// Up to `count` distinct random keys in increasing order, ready to `build` an index from
+pub fn sorted_keys<K: Key + PrimInt>(rng: &mut TestRng, count: usize) -> Vec<K> {
+    let mut keys = unsorted_keys(rng, count);
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Distinct sorted entries, where the value of every key is a random key
+pub fn sorted_entries<K: Key + PrimInt>(rng: &mut TestRng, count: usize) -> Vec<(K, K)> {
+    sorted_keys(rng, count)
+        .into_iter()
+        .map(|key| (key, rng.key()))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<K, V> {
+    Insert(K, V),
+    Search(K),
+}
+
+/// Proportions of an operation sequence
+#[derive(Clone, Copy, Debug)]
+pub struct OpMix {
+    /// Fraction of operations which are inserts, the rest are searches
+    pub inserts: f64,
+
+    /// Fraction of operations which reuse a key seen earlier in the sequence, which turns inserts
+    /// into overwrites and searches into hits
+    pub reuse: f64,
+}
+
+impl Default for OpMix {
+    fn default() -> Self {
+        Self {
+            inserts: 0.5,
+            reuse: 0.5,
+        }
+    }
+}
+
+/// A random sequence of `count` operations, with values drawn as random keys
+pub fn operations<K: Key + PrimInt>(rng: &mut TestRng, count: usize, mix: OpMix) -> Vec<Op<K, K>> {
+    let mut seen: Vec<K> = Vec::new();
+
+    (0..count)
+        .map(|_| {
+            let key = if !seen.is_empty() && rng.chance(mix.reuse) {
+                seen[rng.below(seen.len())]
+            } else {
+                rng.key()
+            };
+
+            if rng.chance(mix.inserts) {
+                seen.push(key);
+                Op::Insert(key, rng.key())
+            } else {
+                Op::Search(key)
+            }
+        })
+        .collect()
+}
+
+/// Mirrors an index in a `BTreeMap`, and reports the first operation where the two disagree
+#[derive(Clone, Debug, Default)]
+pub struct Oracle<K, V> {
+    map: BTreeMap<K, V>,
+}
+
+impl<K, V> Oracle<K, V>
+where
+    K: Ord + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+{
+    pub fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+
+    /// An oracle for an index built from `entries`
+    pub fn from_entries(entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        Self {
+            map: entries.into_iter().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Apply every operation to both the index and the oracle, failing on the first differing
+    /// result
+    pub fn run<I: Index<K, V>>(
+        &mut self,
+        index: &mut I,
+        ops: impl IntoIterator<Item = Op<K, V>>,
+    ) -> crate::Result<()> {
+        for (step, op) in ops.into_iter().enumerate() {
+            let (actual, expected) = match op {
+                Op::Insert(ref key, ref value) => (
+                    index.try_put(key.clone(), value.clone())?,
+                    self.map.insert(key.clone(), value.clone()),
+                ),
+                Op::Search(ref key) => (index.try_search(key.clone())?, self.map.get(key).cloned()),
+            };
+
+            if actual != expected {
+                return Err(anyhow::anyhow!(
+                    "Step {}: {:?} returned {:?}, expected {:?}!",
+                    step,
+                    op,
+                    actual,
+                    expected
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the index holds every entry of the oracle and no more, and agrees with it on
+    /// `probes`
+    pub fn verify<I: IndexRead<K, V>>(
+        &self,
+        index: &I,
+        probes: impl IntoIterator<Item = K>,
+    ) -> crate::Result<()> {
+        if index.len() != self.map.len() {
+            return Err(anyhow::anyhow!(
+                "Index holds {} entries, expected {}!",
+                index.len(),
+                self.map.len()
+            ));
+        }
+
+        let keys = self.map.keys().cloned().chain(probes);
+
+        for key in keys {
+            let actual = index.try_search(key.clone())?;
+            let expected = self.map.get(&key).cloned();
+
+            if actual != expected {
+                return Err(anyhow::anyhow!(
+                    "Search({:?}) returned {:?}, expected {:?}!",
+                    key,
+                    actual,
+                    expected
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_deterministic() {
+        let a: Vec<i128> = unsorted_keys(&mut TestRng::new(7), 100);
+        let b: Vec<i128> = unsorted_keys(&mut TestRng::new(7), 100);
+        let c: Vec<i128> = unsorted_keys(&mut TestRng::new(8), 100);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        // Keys cover the whole domain, including negative ones
+        assert!(a.iter().any(|&key| key < 0) && a.iter().any(|&key| key > 0));
+    }
+
+    #[test]
+    fn rng_small_keys() {
+        let keys: Vec<u8> = sorted_keys(&mut TestRng::new(1), 10_000);
+        assert_eq!(keys.len(), 256);
+    }
+
+    #[test]
+    fn operations_mix() {
+        let ops: Vec<Op<u64, u64>> = operations(&mut TestRng::new(3), 10_000, OpMix::default());
+        let inserts = ops.iter().filter(|op| matches!(op, Op::Insert(..))).count();
+
+        assert!((4_000..6_000).contains(&inserts));
+    }
+}
@@ -1,5 +1,5 @@
-use lazy_static::lazy_static;
-use num::PrimInt;
+use num::Bounded;
+pub use num::PrimInt;
 use serde::{Deserialize, Serialize};
 use trait_set::trait_set;
 
@@ -11,60 +11,137 @@ trait_set! {
     /// General trait for types which are serialized to disk
     pub trait Persisted = Serialize + for<'de> Deserialize<'de> + Clone + Default + Eq + 'static;
 
-    /// General key type
-    pub trait Key = PrimInt + Clone + StaticBounded + 'static ;
+    /// General key type. Only ordered, so that keys such as fixed length strings work as well as
+    /// integers, components which compute with their keys additionally require `PrimInt`.
+    pub trait Key = Ord + Copy + 'static;
 
     /// General value type
     pub trait Value = Clone + 'static;
 }
 
-pub trait KeyBounded<K> {
-    fn lower_bound(&self) -> &K;
+/// Lower bound of a node. Nodes which hold no keys yet, such as the node an empty layer starts out
+/// with, or the cap node at the end of a learned layer, are bounded by a sentinel instead of an
+/// extreme of the key space. Sentinels order below and above every key respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum KeyBound<K> {
+    NegInf,
+    Key(K),
+    PosInf,
 }
 
-pub trait StaticBounded: Ord + 'static {
-    fn min_ref() -> &'static Self;
+impl<K> KeyBound<K> {
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            Self::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    pub fn into_key(self) -> Option<K> {
+        match self {
+            Self::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_ref(&self) -> KeyBound<&K> {
+        match self {
+            Self::NegInf => KeyBound::NegInf,
+            Self::Key(key) => KeyBound::Key(key),
+            Self::PosInf => KeyBound::PosInf,
+        }
+    }
+
+    /// Whether `key` lies at or above the bound
+    pub fn covers(&self, key: &K) -> bool
+    where
+        K: Ord,
+    {
+        match self {
+            Self::NegInf => true,
+            Self::Key(bound) => bound <= key,
+            Self::PosInf => false,
+        }
+    }
 
-    fn max_ref() -> &'static Self;
+    /// A key to index the node by in a layer above which stores plain integer keys, such as a
+    /// learned layer. Sentinels are only resolved to the extremes of the key space here, layers
+    /// which order their keys without computing with them keep the bound as it is.
+    pub fn resolve(self) -> K
+    where
+        K: Bounded,
+    {
+        match self {
+            Self::NegInf => K::min_value(),
+            Self::Key(key) => key,
+            Self::PosInf => K::max_value(),
+        }
+    }
+}
+
+impl<K> KeyBound<KeyBound<K>> {
+    /// The bound of a node of a layer whose keys are themselves bounds
+    pub fn flatten(self) -> KeyBound<K> {
+        match self {
+            Self::NegInf => KeyBound::NegInf,
+            Self::Key(bound) => bound,
+            Self::PosInf => KeyBound::PosInf,
+        }
+    }
+}
+
+impl<K: Clone> KeyBound<&K> {
+    pub fn cloned(self) -> KeyBound<K> {
+        match self {
+            KeyBound::NegInf => KeyBound::NegInf,
+            KeyBound::Key(key) => KeyBound::Key(key.clone()),
+            KeyBound::PosInf => KeyBound::PosInf,
+        }
+    }
+}
+
+pub trait KeyBounded<K> {
+    fn lower_bound(&self) -> KeyBound<&K>;
+}
+
+/// Nodes which keep their entries behind pointers of their own, such as vectors or boxed slices,
+/// prefetch those ahead of a scan. Nodes which store everything inline do nothing.
+pub trait PrefetchData {
+    fn prefetch_data(&self) {}
 }
 
 macro_rules! impl_integer {
     ($($t:ty),+) => {
         $(
-            impl StaticBounded for $t {
-                fn min_ref() -> &'static Self {
-                    static MIN: $t = <$t>::min_value();
-                    &MIN
-                }
-
-                fn max_ref() -> &'static Self {
-                    static MAX: $t = <$t>::max_value();
-                    &MAX
-                }
-            }
-
             impl KeyBounded<$t> for $t {
-                fn lower_bound(&self) -> &$t {
-                    Self::min_ref()
+                fn lower_bound(&self) -> KeyBound<&$t> {
+                    KeyBound::Key(self)
                 }
             }
+
+            impl PrefetchData for $t {}
         )*
     }
 }
 
 impl_integer!(usize, u8, u16, u32, u64, u128, isize, i8, i16, i32, i64, i128);
 
-lazy_static! {
-    static ref MIN_STRING: String = "".to_string();
-    static ref MAX_STRING: String = "".to_string();
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl StaticBounded for String {
-    fn min_ref() -> &'static Self {
-        &MIN_STRING
-    }
+    #[test]
+    fn key_bound_sentinels() {
+        assert!(KeyBound::NegInf < KeyBound::Key(i32::MIN));
+        assert!(KeyBound::Key(i32::MAX) < KeyBound::PosInf);
+
+        assert!(KeyBound::NegInf.covers(&i32::MIN));
+        assert!(!KeyBound::PosInf.covers(&i32::MAX));
+        assert!(KeyBound::Key(5).covers(&5));
+        assert!(!KeyBound::Key(5).covers(&4));
 
-    fn max_ref() -> &'static Self {
-        &MAX_STRING
+        assert_eq!(KeyBound::<u8>::NegInf.resolve(), 0);
+        assert_eq!(KeyBound::<u8>::PosInf.resolve(), 255);
+        assert_eq!(KeyBound::<u8>::NegInf.into_key(), None);
     }
 }
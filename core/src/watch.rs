@@ -0,0 +1,307 @@
+//! Change notifications for layouts with `watch: true`. Every insert into such an index, and every
+//! write and removal through `compare_and_swap`, `apply_batch` or a scope, is reported to the
+//! `WatchStream`s subscribed to a range holding its key, so that caches and materialized views can
+//! follow the index without polling it. A stream ends once the index is dropped and its buffer
+//! drained.
+//!
+//! Events are buffered per stream until they are polled, up to the capacity of the stream. A
+//! stream which falls further behind loses its oldest events, and reports how many it lost with a
+//! `Lagged` before the events it still holds, so that a follower knows to resync from the index.
+//!
+//! A `WatchStream` is a `futures_core::Stream`, and also offers a `changed` future for callers
+//! without the stream combinators, and `try_next` for callers which don't run async.
+
+use core::fmt;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::{Bound, RangeBounds};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+/// Number of events a stream buffers unless subscribed with `watch_with_capacity`
+pub const DEFAULT_WATCH_CAPACITY: usize = 1024;
+
+/// A change to a key of a watched index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeEvent<K, V> {
+    /// The key wasn't present before
+    Insert { key: K, value: V },
+
+    /// The key held `previous` before
+    Update { key: K, previous: V, value: V },
+
+    /// The key held `previous`, and is no longer present
+    Remove { key: K, previous: V },
+}
+
+impl<K, V> ChangeEvent<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            ChangeEvent::Insert { key, .. }
+            | ChangeEvent::Update { key, .. }
+            | ChangeEvent::Remove { key, .. } => key,
+        }
+    }
+
+    /// The value the key holds after the change, or `None` if it was removed
+    pub fn value(&self) -> Option<&V> {
+        match self {
+            ChangeEvent::Insert { value, .. } | ChangeEvent::Update { value, .. } => Some(value),
+            ChangeEvent::Remove { .. } => None,
+        }
+    }
+
+    /// The value the key held before the change, or `None` if it wasn't present
+    pub fn previous(&self) -> Option<&V> {
+        match self {
+            ChangeEvent::Update { previous, .. } | ChangeEvent::Remove { previous, .. } => {
+                Some(previous)
+            }
+            ChangeEvent::Insert { .. } => None,
+        }
+    }
+}
+
+/// Handed out by a `WatchStream` in place of the events it lost because its buffer was full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lagged {
+    /// Number of events lost since the last `Lagged`
+    pub missed: u64,
+}
+
+impl fmt::Display for Lagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "watch stream fell behind and lost {} events",
+            self.missed
+        )
+    }
+}
+
+impl std::error::Error for Lagged {}
+
+/// What a `WatchStream` hands out: a change, or the number of changes it lost
+pub type WatchItem<K, V> = Result<ChangeEvent<K, V>, Lagged>;
+
+struct WatchState<K, V> {
+    events: VecDeque<ChangeEvent<K, V>>,
+    capacity: usize,
+
+    /// Events dropped from the front of a full buffer, not reported yet
+    missed: u64,
+    waker: Option<Waker>,
+
+    /// Set once the index is dropped, after which no more events arrive
+    closed: bool,
+}
+
+struct Watcher<K, V> {
+    range: (Bound<K>, Bound<K>),
+    state: Arc<Mutex<WatchState<K, V>>>,
+}
+
+/// The streams subscribed to an index, kept next to its layers
+pub struct Watchers<K, V> {
+    watchers: Mutex<Vec<Watcher<K, V>>>,
+}
+
+impl<K, V> Default for Watchers<K, V> {
+    fn default() -> Self {
+        Self {
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Watchers<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the changes of every key in `range`, buffering `DEFAULT_WATCH_CAPACITY` events
+    pub fn watch(&self, range: impl RangeBounds<K>) -> WatchStream<K, V> {
+        self.watch_with_capacity(range, DEFAULT_WATCH_CAPACITY)
+    }
+
+    /// Subscribe to the changes of every key in `range`, buffering at most `capacity` events
+    pub fn watch_with_capacity(
+        &self,
+        range: impl RangeBounds<K>,
+        capacity: usize,
+    ) -> WatchStream<K, V> {
+        assert!(
+            capacity > 0,
+            "a watch stream has to buffer at least one event"
+        );
+
+        let state = Arc::new(Mutex::new(WatchState {
+            events: VecDeque::new(),
+            capacity,
+            missed: 0,
+            waker: None,
+            closed: false,
+        }));
+
+        lock(&self.watchers).push(Watcher {
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            state: state.clone(),
+        });
+
+        WatchStream { state }
+    }
+
+    /// Report that `key` now holds `value`, and held `previous` before
+    pub fn notify(&self, key: &K, value: &V, previous: Option<&V>) {
+        self.publish(key, || match previous {
+            Some(previous) => ChangeEvent::Update {
+                key: key.clone(),
+                previous: previous.clone(),
+                value: value.clone(),
+            },
+            None => ChangeEvent::Insert {
+                key: key.clone(),
+                value: value.clone(),
+            },
+        });
+    }
+
+    /// Report that `key`, which held `previous`, was removed
+    pub fn notify_remove(&self, key: &K, previous: &V) {
+        self.publish(key, || ChangeEvent::Remove {
+            key: key.clone(),
+            previous: previous.clone(),
+        });
+    }
+
+    /// Buffer an event for every stream watching `key`, dropping the oldest event of a full buffer.
+    /// Streams which were dropped are unsubscribed along the way.
+    fn publish(&self, key: &K, event: impl Fn() -> ChangeEvent<K, V>) {
+        let mut watchers = lock(&self.watchers);
+        watchers.retain(|watcher| Arc::strong_count(&watcher.state) > 1);
+
+        for watcher in watchers
+            .iter()
+            .filter(|watcher| watcher.range.contains(key))
+        {
+            let mut state = lock(&watcher.state);
+            if state.events.len() == state.capacity {
+                state.events.pop_front();
+                state.missed += 1;
+            }
+            state.events.push_back(event());
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Number of streams still subscribed
+    pub fn len(&self) -> usize {
+        lock(&self.watchers)
+            .iter()
+            .filter(|watcher| Arc::strong_count(&watcher.state) > 1)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Drop for Watchers<K, V> {
+    fn drop(&mut self) {
+        let watchers = self
+            .watchers
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for watcher in watchers.drain(..) {
+            let mut state = lock(&watcher.state);
+            state.closed = true;
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// The changes to a range of keys of an index, returned by `watch`
+pub struct WatchStream<K, V> {
+    state: Arc<Mutex<WatchState<K, V>>>,
+}
+
+impl<K, V> WatchStream<K, V> {
+    /// The next change, `Pending` until there is one, or `None` once the index was dropped
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<WatchItem<K, V>>> {
+        let mut state = lock(&self.state);
+
+        match next(&mut state) {
+            Some(item) => Poll::Ready(Some(item)),
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Resolves to the next change, or `None` once the index was dropped
+    pub fn changed(&mut self) -> Changed<'_, K, V> {
+        Changed { stream: self }
+    }
+
+    /// The next change if there is one already, without waiting
+    pub fn try_next(&mut self) -> Option<WatchItem<K, V>> {
+        next(&mut lock(&self.state))
+    }
+
+    /// Number of events buffered and not polled yet
+    pub fn buffered(&self) -> usize {
+        lock(&self.state).events.len()
+    }
+
+    /// Whether the index was dropped, so that no more changes will arrive
+    pub fn is_closed(&self) -> bool {
+        lock(&self.state).closed
+    }
+}
+
+/// The future returned by `WatchStream::changed`
+pub struct Changed<'a, K, V> {
+    stream: &'a mut WatchStream<K, V>,
+}
+
+impl<K, V> Future for Changed<'_, K, V> {
+    type Output = Option<WatchItem<K, V>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
+
+impl<K, V> futures_core::Stream for WatchStream<K, V> {
+    type Item = WatchItem<K, V>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        WatchStream::poll_next(self.get_mut(), cx)
+    }
+}
+
+/// The lost events if there are any to report, otherwise the oldest buffered event
+fn next<K, V>(state: &mut WatchState<K, V>) -> Option<WatchItem<K, V>> {
+    match std::mem::take(&mut state.missed) {
+        0 => state.events.pop_front().map(Ok),
+        missed => Some(Err(Lagged { missed })),
+    }
+}
+
+/// Lock a mutex even if it was poisoned, the buffered events stay consistent regardless
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,527 @@
+//! Reproducible workloads for comparing layouts. `Dataset` generates keys approximating the SOSD
+//! benchmark datasets, and `zipfian_ops` draws operations whose keys follow a zipfian popularity,
+//! both from a seeded `TestRng`, so a published comparison can be rerun from its seed alone.
+//!
+//! Workloads taken from a live application are captured instead by wrapping its index in a
+//! `Recorder`, which records every operation into a `Trace`. A trace can be written to a file and
+//! replayed against any other layout, which then sees exactly the same operations.
+
+use crate::common::storage::format::{self, checksum};
+use crate::testkit::{Op, OpMix, TestRng};
+use crate::traits::{Key, Persisted};
+use crate::{Index, IndexRead, IndexWrite};
+use num::PrimInt;
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// Marks a trace written by `Trace::write_to`
+const MAGIC: [u8; 4] = *b"LIMT";
+
+/// Version of the format written by `Trace::write_to`
+pub const TRACE_VERSION: u8 = 1;
+
+/// Approximations of the datasets of the SOSD benchmark, which are sorted unsigned 64 bit keys.
+/// Their shapes, rather than their exact keys, are what make learned layouts behave differently
+/// on them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dataset {
+    /// Popularity of books on Amazon, a smooth distribution with heavy tailed gaps
+    Books,
+
+    /// Cell ids of OpenStreetMap locations, dense clusters spread over the whole domain
+    Osm,
+
+    /// Facebook user ids, close to uniform apart from a few outliers far above the rest
+    Fb,
+
+    /// Timestamps of Wikipedia edits, bursting and slowing down with the time of day
+    Wiki,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 4] = [Dataset::Books, Dataset::Osm, Dataset::Fb, Dataset::Wiki];
+
+    /// The name of the dataset in SOSD
+    pub fn name(self) -> &'static str {
+        match self {
+            Dataset::Books => "books",
+            Dataset::Osm => "osm",
+            Dataset::Fb => "fb",
+            Dataset::Wiki => "wiki",
+        }
+    }
+
+    /// `count` distinct keys in increasing order, ready to `build` an index from
+    pub fn keys(self, rng: &mut TestRng, count: usize) -> Vec<u64> {
+        match self {
+            Dataset::Books => {
+                let start = rng.next_u64() >> 32;
+                ascending(start, count, |_| log_normal(rng, 4.0, 2.0))
+            }
+            Dataset::Osm => {
+                let clusters: Vec<(u64, f64)> = (0..count.div_ceil(1_000).max(1))
+                    .map(|_| (rng.next_u64(), log_normal(rng, 8.0, 3.0)))
+                    .collect();
+
+                distinct(rng, count, |rng| {
+                    // Squaring skews which clusters are picked, so some grow far denser
+                    let pick = rng.unit() * rng.unit();
+                    let (center, spread) = clusters[(pick * clusters.len() as f64) as usize];
+                    let offset = normal(rng) * spread;
+
+                    if offset < 0.0 {
+                        center.saturating_sub(-offset as u64)
+                    } else {
+                        center.saturating_add(offset as u64)
+                    }
+                })
+            }
+            Dataset::Fb => distinct(rng, count, |rng| {
+                if rng.chance(0.0005) {
+                    u64::MAX - (rng.next_u64() >> 8)
+                } else {
+                    rng.next_u64() >> 26
+                }
+            }),
+            Dataset::Wiki => {
+                const DAY: f64 = 86_400_000.0;
+                let mut time = 1_000_000_000_000.0;
+
+                ascending(time as u64, count, |_| {
+                    // Edits come in three times as fast at the busiest time of day as at the
+                    // quietest
+                    let rate = 2.0 + (2.0 * PI * time / DAY).sin();
+                    let gap = -(1.0 - rng.unit()).ln() * 200.0 / rate;
+                    time += gap + 1.0;
+                    gap
+                })
+            }
+        }
+    }
+}
+
+/// A standard normal sample, by the Box-Muller transform
+fn normal(rng: &mut TestRng) -> f64 {
+    let radius = (-2.0 * (1.0 - rng.unit()).ln()).sqrt();
+    radius * (2.0 * PI * rng.unit()).cos()
+}
+
+fn log_normal(rng: &mut TestRng, mu: f64, sigma: f64) -> f64 {
+    (mu + sigma * normal(rng)).exp()
+}
+
+/// `count` keys from `start`, every one larger than the last by one more than `gap`
+fn ascending(start: u64, count: usize, mut gap: impl FnMut(usize) -> f64) -> Vec<u64> {
+    let mut key = start;
+
+    (0..count)
+        .map(|index| {
+            let current = key;
+            key = key.saturating_add(gap(index) as u64 + 1);
+            current
+        })
+        .collect()
+}
+
+/// `count` distinct keys drawn from `sample`, sorted
+fn distinct(
+    rng: &mut TestRng,
+    count: usize,
+    mut sample: impl FnMut(&mut TestRng) -> u64,
+) -> Vec<u64> {
+    let mut keys = Vec::with_capacity(count);
+
+    // Samples colliding with earlier ones are dropped, and drawn again
+    while keys.len() < count {
+        keys.extend((keys.len()..count).map(|_| sample(rng)));
+        keys.sort_unstable();
+        keys.dedup();
+    }
+
+    keys
+}
+
+/// Ranks drawn from a zipfian distribution over `0..n`, where rank 0 is the most popular, by the
+/// method of Gray et al. used by YCSB
+#[derive(Clone, Debug)]
+pub struct Zipf {
+    n: usize,
+    theta: f64,
+    zeta: f64,
+    alpha: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    /// A distribution skewed by `theta`, between 0 for uniform and 1 for the most skewed. YCSB
+    /// defaults to 0.99.
+    pub fn new(n: usize, theta: f64) -> Self {
+        assert!(n > 0, "Empty range!");
+        assert!(
+            theta > 0.0 && theta < 1.0,
+            "The skew of a zipfian distribution has to be between 0 and 1!"
+        );
+
+        let zeta = |n: usize| (1..=n).map(|rank| 1.0 / (rank as f64).powf(theta)).sum();
+        let zeta_n: f64 = zeta(n);
+        let zeta_2: f64 = zeta(2.min(n));
+
+        Self {
+            n,
+            theta,
+            zeta: zeta_n,
+            alpha: 1.0 / (1.0 - theta),
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta_2 / zeta_n),
+        }
+    }
+
+    pub fn sample(&self, rng: &mut TestRng) -> usize {
+        let unit = rng.unit();
+        let scaled = unit * self.zeta;
+
+        if scaled < 1.0 {
+            return 0;
+        }
+
+        if scaled < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.n - 1);
+        }
+
+        let rank = self.n as f64 * (self.eta * unit - self.eta + 1.0).powf(self.alpha);
+        (rank as usize).min(self.n - 1)
+    }
+}
+
+/// A random sequence of `count` operations over `keys`, with values drawn as random keys. Every
+/// operation reusing a key, at the rate `mix.reuse`, picks one of `keys` by zipfian popularity
+/// skewed by `theta`, and the others use a fresh random key. The popular keys are scattered over
+/// `keys` rather than being its smallest ones.
+pub fn zipfian_ops<K: Key + PrimInt>(
+    rng: &mut TestRng,
+    keys: &[K],
+    count: usize,
+    mix: OpMix,
+    theta: f64,
+) -> Vec<Op<K, K>> {
+    let zipf = (!keys.is_empty()).then(|| Zipf::new(keys.len(), theta));
+
+    (0..count)
+        .map(|_| {
+            let key = match zipf {
+                Some(ref zipf) if rng.chance(mix.reuse) => {
+                    let rank = zipf.sample(rng);
+                    keys[(TestRng::new(rank as u64).next_u64() % keys.len() as u64) as usize]
+                }
+                _ => rng.key(),
+            };
+
+            if rng.chance(mix.inserts) {
+                Op::Insert(key, rng.key())
+            } else {
+                Op::Search(key)
+            }
+        })
+        .collect()
+}
+
+/// A sequence of operations made against an index, recorded by a `Recorder` or assembled from
+/// generated operations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace<K, V> {
+    ops: Vec<Op<K, V>>,
+}
+
+impl<K, V> Default for Trace<K, V> {
+    fn default() -> Self {
+        Self { ops: Vec::new() }
+    }
+}
+
+impl<K, V> From<Vec<Op<K, V>>> for Trace<K, V> {
+    fn from(ops: Vec<Op<K, V>>) -> Self {
+        Self { ops }
+    }
+}
+
+/// How a replay of a trace went
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub inserts: usize,
+
+    /// Inserts of a key the index already held
+    pub overwrites: usize,
+
+    pub searches: usize,
+
+    /// Searches which found their key
+    pub hits: usize,
+
+    pub elapsed: Duration,
+}
+
+impl Replay {
+    /// Whether two replays of the same trace saw the same results, whatever their timing. Replays
+    /// against indexes holding the same entries beforehand always should.
+    pub fn agrees_with(&self, other: &Replay) -> bool {
+        Replay {
+            elapsed: Duration::ZERO,
+            ..*self
+        } == Replay {
+            elapsed: Duration::ZERO,
+            ..*other
+        }
+    }
+
+    pub fn ops_per_sec(&self) -> f64 {
+        (self.inserts + self.searches) as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl<K, V> Trace<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, op: Op<K, V>) {
+        self.ops.push(op);
+    }
+
+    pub fn ops(&self) -> &[Op<K, V>] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Apply every operation to `index` in order. The index should hold the same entries as the
+    /// recorded one did when recording started, such as by being built from the same dataset.
+    pub fn replay<I: Index<K, V>>(&self, index: &mut I) -> crate::Result<Replay>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut replay = Replay::default();
+        let start = Instant::now();
+
+        for op in self.ops.iter() {
+            match op {
+                Op::Insert(key, value) => {
+                    replay.inserts += 1;
+                    if index.try_put(key.clone(), value.clone())?.is_some() {
+                        replay.overwrites += 1;
+                    }
+                }
+                Op::Search(key) => {
+                    replay.searches += 1;
+                    if index.try_search(key.clone())?.is_some() {
+                        replay.hits += 1;
+                    }
+                }
+            }
+        }
+
+        replay.elapsed = start.elapsed();
+        Ok(replay)
+    }
+
+    /// Write the trace in the portable page format, behind a header and followed by its checksum
+    pub fn write_to<W: Write>(&self, mut writer: W) -> crate::Result<()>
+    where
+        K: Persisted,
+        V: Persisted,
+    {
+        let data = format::encode(&self.ops)?;
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[TRACE_VERSION])?;
+        writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        writer.write_all(&data)?;
+        writer.write_all(&checksum(&data).to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Read a trace written by `write_to`, which fails if it was corrupted or cut short
+    pub fn read_from<R: Read>(mut reader: R) -> crate::Result<Self>
+    where
+        K: Persisted,
+        V: Persisted,
+    {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header)?;
+
+        match header {
+            [magic @ .., TRACE_VERSION] if magic == MAGIC => {}
+            [magic @ .., version] if magic == MAGIC => {
+                return Err(anyhow::anyhow!(
+                    "Trace was written in version {}, but only versions up to {} can be read!",
+                    version,
+                    TRACE_VERSION
+                ))
+            }
+            _ => return Err(anyhow::anyhow!("Not a trace!")),
+        }
+
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        let mut data = vec![0; u64::from_le_bytes(bytes) as usize];
+        reader.read_exact(&mut data)?;
+
+        reader.read_exact(&mut bytes)?;
+        if u64::from_le_bytes(bytes) != checksum(&data) {
+            return Err(anyhow::anyhow!("Trace is corrupted!"));
+        }
+
+        Ok(Self {
+            ops: format::decode(&data)?,
+        })
+    }
+}
+
+/// An index recording every operation made through it into a `Trace`, while forwarding each to
+/// the wrapped index unchanged
+pub struct Recorder<I, K, V> {
+    index: I,
+    trace: RefCell<Trace<K, V>>,
+}
+
+impl<I, K, V> Recorder<I, K, V> {
+    pub fn new(index: I) -> Self {
+        Self {
+            index,
+            trace: RefCell::new(Trace::new()),
+        }
+    }
+
+    /// The wrapped index. Writes have to go through the recorder, so it is only lent out
+    /// immutably.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// Number of operations recorded so far
+    pub fn recorded(&self) -> usize {
+        self.trace.borrow().len()
+    }
+
+    /// Hand out the operations recorded so far, and record anew from here
+    pub fn take_trace(&self) -> Trace<K, V> {
+        self.trace.take()
+    }
+
+    pub fn into_parts(self) -> (I, Trace<K, V>) {
+        (self.index, self.trace.into_inner())
+    }
+}
+
+impl<I, K, V> IndexRead<K, V> for Recorder<I, K, V>
+where
+    I: IndexRead<K, V>,
+    K: Clone,
+{
+    fn try_search(&self, key: K) -> crate::Result<Option<V>> {
+        self.trace.borrow_mut().push(Op::Search(key.clone()));
+        self.index.try_search(key)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<I, K, V> IndexWrite<K, V> for Recorder<I, K, V>
+where
+    I: IndexWrite<K, V>,
+    K: Clone,
+    V: Clone,
+{
+    fn try_put(&mut self, key: K, value: V) -> crate::Result<Option<V>> {
+        self.trace
+            .get_mut()
+            .push(Op::Insert(key.clone(), value.clone()));
+        self.index.try_put(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct Map(BTreeMap<u64, u64>);
+
+    impl IndexRead<u64, u64> for Map {
+        fn try_search(&self, key: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.get(&key).copied())
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl IndexWrite<u64, u64> for Map {
+        fn try_put(&mut self, key: u64, value: u64) -> crate::Result<Option<u64>> {
+            Ok(self.0.insert(key, value))
+        }
+    }
+
+    #[test]
+    fn datasets_deterministic() {
+        for dataset in Dataset::ALL {
+            let keys = dataset.keys(&mut TestRng::new(1), 10_000);
+
+            assert_eq!(keys.len(), 10_000, "{}", dataset.name());
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(keys, dataset.keys(&mut TestRng::new(1), 10_000));
+            assert_ne!(keys, dataset.keys(&mut TestRng::new(2), 10_000));
+        }
+    }
+
+    #[test]
+    fn zipf_skew() {
+        let zipf = Zipf::new(1_000, 0.99);
+        let mut rng = TestRng::new(5);
+        let mut counts = vec![0; 1_000];
+
+        for _ in 0..100_000 {
+            counts[zipf.sample(&mut rng)] += 1;
+        }
+
+        assert!(counts[0] > counts[1] && counts[1] > counts[10] && counts[10] > counts[500]);
+        assert!(counts[0] > 10_000);
+    }
+
+    #[test]
+    fn record_replay() {
+        let keys = Dataset::Books.keys(&mut TestRng::new(3), 1_000);
+        let ops = zipfian_ops(&mut TestRng::new(4), &keys, 5_000, OpMix::default(), 0.99);
+
+        let mut recorder = Recorder::new(Map::default());
+        let live = Trace::from(ops).replay(&mut recorder).unwrap();
+        assert_eq!(recorder.recorded(), 5_000);
+        assert!(live.hits > 0 && live.overwrites > 0);
+
+        let (_, trace) = recorder.into_parts();
+        let mut bytes = Vec::new();
+        trace.write_to(&mut bytes).unwrap();
+        let read = Trace::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(read, trace);
+
+        let replayed = read.replay(&mut Map::default()).unwrap();
+        assert!(replayed.agrees_with(&live));
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(Trace::<u64, u64>::read_from(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Writes to many keys applied as one, with `index.apply_batch(batch)`. In-memory indexes apply a
+//! batch in place, logging the value every write replaced, and undo the writes from that log when
+//! one of them is refused, so the index never keeps part of a batch. Persisted indexes log the
+//! batch to their `WriteAheadLog` first, and replay it when they are opened again after a crash
+//! left it half applied.
+
+use crate::cursor::{CursorIndex, CursorMut};
+use crate::kv_store::{CapacityExceeded, KVStore};
+use crate::traits::{Key, Value};
+use alloc::vec::Vec;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Puts and deletes collected to be applied all at once, in the order they were added
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteBatch<K, V> {
+    writes: Vec<(K, Option<V>)>,
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> WriteBatch<K, V> {
+    pub fn new() -> Self {
+        Self { writes: Vec::new() }
+    }
+
+    /// Write `value` to `key`
+    pub fn put(&mut self, key: K, value: V) -> &mut Self {
+        self.writes.push((key, Some(value)));
+        self
+    }
+
+    /// Remove `key`, if the index holds it
+    pub fn delete(&mut self, key: K) -> &mut Self {
+        self.writes.push((key, None));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn has_deletes(&self) -> bool {
+        self.writes.iter().any(|(_, value)| value.is_none())
+    }
+
+    /// The writes of the batch in order, deletes holding no value
+    pub fn iter(&self) -> impl Iterator<Item = (&K, Option<&V>)> {
+        self.writes.iter().map(|(key, value)| (key, value.as_ref()))
+    }
+}
+
+impl<K, V> IntoIterator for WriteBatch<K, V> {
+    type Item = (K, Option<V>);
+    type IntoIter = alloc::vec::IntoIter<(K, Option<V>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.writes.into_iter()
+    }
+}
+
+impl<K, V> FromIterator<(K, Option<V>)> for WriteBatch<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, Option<V>)>>(iter: I) -> Self {
+        Self {
+            writes: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Returned by `apply_batch` of an in-memory index when a put of a new key would grow the index
+/// past its `max_memory` budget, in which case none of the batch was applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchError<K> {
+    /// Position of the write in the batch
+    pub index: usize,
+    pub key: K,
+    pub error: CapacityExceeded,
+}
+
+impl<K: fmt::Debug> fmt::Display for BatchError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "write {} of the batch, putting {:?}, failed: {}",
+            self.index, self.key, self.error
+        )
+    }
+}
+
+impl<K: fmt::Debug> core::error::Error for BatchError<K> {}
+
+/// Apply every write of `batch` to `index` in place, in order. Before a put inserts a new key,
+/// `admit` is asked whether the index may grow. Once it refuses, the writes applied so far are
+/// undone in reverse from the values they replaced, so the index holds the entries it held before.
+pub fn apply_in_place<K, V, I>(
+    index: &mut I,
+    batch: WriteBatch<K, V>,
+    mut admit: impl FnMut(&I) -> Result<(), CapacityExceeded>,
+) -> Result<(), BatchError<K>>
+where
+    K: Key,
+    V: Value,
+    I: CursorIndex<K, V> + KVStore<K, V>,
+{
+    let mut undo = Vec::with_capacity(batch.len());
+
+    for (position, (key, value)) in batch.into_iter().enumerate() {
+        if value.is_some() && CursorMut::new(index, &key).key() != Some(&key) {
+            if let Err(error) = admit(index) {
+                for (key, previous) in undo.into_iter().rev() {
+                    write(index, key, previous);
+                }
+
+                return Err(BatchError {
+                    index: position,
+                    key,
+                    error,
+                });
+            }
+        }
+
+        undo.push((key, write(index, key, value)));
+    }
+
+    Ok(())
+}
+
+/// Write `value` to `key`, or remove `key` if `value` is `None`, returning the value it held
+fn write<K, V, I>(index: &mut I, key: K, value: Option<V>) -> Option<V>
+where
+    K: Key,
+    V: Value,
+    I: CursorIndex<K, V> + KVStore<K, V>,
+{
+    let mut cursor = CursorMut::new(index, &key);
+
+    if cursor.key() != Some(&key) {
+        if let Some(value) = value {
+            cursor
+                .insert_before(key, value)
+                .expect("the cursor points at the first entry after the key");
+        }
+
+        return None;
+    }
+
+    match value {
+        Some(value) => cursor.replace_current(value),
+        None => cursor.remove_current().map(|(_, previous)| previous),
+    }
+}
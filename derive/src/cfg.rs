@@ -0,0 +1,157 @@
+//! Components of a `layout` or `prepend` guarded by `#[cfg(...)]`. A proc macro can't evaluate the
+//! predicates of the crate invoking it, so the macro instead invokes itself once for every
+//! combination of the predicates, with the components that combination rules out removed. Every
+//! invocation is guarded by a `#[cfg(...)]` which only holds for its combination, so the compiler
+//! strips all of them but one before they are expanded, and only that layout is ever checked.
+
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
+use quote::quote;
+
+/// Distinct predicates the components of a single invocation can be guarded by, since every one of
+/// them doubles the number of invocations
+const MAX_PREDICATES: usize = 6;
+
+/// Fields listing components, which can be guarded
+const GUARDED_FIELDS: &[&str] = &["layout", "prepend"];
+
+/// A component along with the predicates of its `#[cfg(...)]` attributes, which all have to hold
+struct Guarded {
+    predicates: Vec<TokenStream>,
+    component: TokenStream,
+}
+
+/// One invocation per combination of the predicates guarding components of `input`, or `None` if
+/// no component is guarded
+pub fn expand_guarded(input: &TokenStream) -> Option<syn::Result<TokenStream>> {
+    let tokens: Vec<TokenTree> = input.clone().into_iter().collect();
+
+    // Positions of the bracketed component lists, along with their components
+    let mut lists = Vec::new();
+    for (index, window) in tokens.windows(3).enumerate() {
+        if let [TokenTree::Ident(field), TokenTree::Punct(colon), TokenTree::Group(group)] = window
+        {
+            if GUARDED_FIELDS.contains(&field.to_string().as_str())
+                && colon.as_char() == ':'
+                && group.delimiter() == Delimiter::Bracket
+            {
+                match components(group.stream()) {
+                    Ok(components) => lists.push((index + 2, components)),
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+        }
+    }
+
+    let mut predicates: Vec<TokenStream> = Vec::new();
+    for guarded in lists.iter().flat_map(|(_, components)| components) {
+        for predicate in &guarded.predicates {
+            if !predicates
+                .iter()
+                .any(|seen| seen.to_string() == predicate.to_string())
+            {
+                predicates.push(predicate.clone());
+            }
+        }
+    }
+
+    if predicates.is_empty() {
+        return None;
+    }
+
+    if predicates.len() > MAX_PREDICATES {
+        return Some(Err(syn::Error::new_spanned(
+            &predicates[MAX_PREDICATES],
+            format!(
+                "Layout components can be guarded by at most {} distinct `cfg` predicates!",
+                MAX_PREDICATES
+            ),
+        )));
+    }
+
+    let mut invocations = TokenStream::new();
+    for combination in 0..1usize << predicates.len() {
+        let holds = |predicate: &TokenStream| {
+            let index = predicates
+                .iter()
+                .position(|seen| seen.to_string() == predicate.to_string())
+                .unwrap();
+            combination & (1 << index) != 0
+        };
+
+        let condition = predicates.iter().map(|predicate| {
+            if holds(predicate) {
+                quote! { #predicate }
+            } else {
+                quote! { not(#predicate) }
+            }
+        });
+
+        let mut tokens = tokens.clone();
+        for (index, components) in &lists {
+            let enabled = components
+                .iter()
+                .filter(|guarded| guarded.predicates.iter().all(holds))
+                .map(|guarded| &guarded.component);
+
+            let TokenTree::Group(group) = &tokens[*index] else {
+                unreachable!()
+            };
+
+            let mut replaced = Group::new(Delimiter::Bracket, quote! { #(#enabled),* });
+            replaced.set_span(group.span());
+            tokens[*index] = TokenTree::Group(replaced);
+        }
+
+        let tokens: TokenStream = tokens.into_iter().collect();
+        invocations.extend(quote! {
+            #[cfg(all(#(#condition),*))]
+            ::limousine_engine::prelude::create_kv_store! { #tokens }
+        });
+    }
+
+    Some(Ok(invocations))
+}
+
+/// Split a list of components at its commas, stripping the `#[cfg(...)]` attributes of each one
+fn components(list: TokenStream) -> syn::Result<Vec<Guarded>> {
+    let mut components = vec![Vec::new()];
+    for token in list {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => components.push(Vec::new()),
+            token => components.last_mut().unwrap().push(token),
+        }
+    }
+
+    components.retain(|component| !component.is_empty());
+    components.into_iter().map(guarded).collect()
+}
+
+fn guarded(tokens: Vec<TokenTree>) -> syn::Result<Guarded> {
+    let mut predicates = Vec::new();
+    let mut rest = tokens.as_slice();
+
+    while let [TokenTree::Punct(pound), TokenTree::Group(attribute), tail @ ..] = rest {
+        if pound.as_char() != '#' || attribute.delimiter() != Delimiter::Bracket {
+            break;
+        }
+
+        let contents: Vec<TokenTree> = attribute.stream().into_iter().collect();
+        match contents.as_slice() {
+            [TokenTree::Ident(cfg), TokenTree::Group(predicate)]
+                if cfg == "cfg" && predicate.delimiter() == Delimiter::Parenthesis =>
+            {
+                predicates.push(predicate.stream());
+            }
+            _ => {
+                bail!(attribute, "Only `#[cfg(...)]` can be put on layout components!");
+            }
+        }
+
+        rest = tail;
+    }
+
+    Ok(Guarded {
+        predicates,
+        component: rest.iter().cloned().collect(),
+    })
+}
@@ -0,0 +1,225 @@
+//! `search_batch`, which searches keys in sorted order and skips the descent from the top whenever
+//! the next key falls within the same base node as the previous one, and `search_pipelined`, which
+//! moves the whole batch through the layers one stage at a time
+
+use crate::HybridLayout;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+pub fn create_batch_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
+        .rev()
+        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
+        .collect();
+
+    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
+    let persisted = layout.is_persisted();
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    let mut descent = TokenStream::new();
+    let top = layout.internal.len() + 1;
+
+    // Persisted components can fail
+    let fallible = |persisted: bool| {
+        if persisted {
+            quote! { ? }
+        } else {
+            TokenStream::new()
+        }
+    };
+
+    // Top component
+    let search = search_vars[0].clone();
+    let field = component_vars[0].clone();
+    let next = component_vars[1].clone();
+
+    descent.extend(quote! { let #search = self.#field.search(&self.#next, &key); });
+
+    // Internal components
+    for index in 1..=layout.internal.len() {
+        let search = search_vars[index].clone();
+        let prev_search = search_vars[index - 1].clone();
+        let field = component_vars[index].clone();
+        let next = component_vars[index + 1].clone();
+        let fallible = fallible(layout.internal[index - 1].is_persisted());
+
+        descent.extend(quote! {
+            let #search = self.#field.search(&self.#next, #prev_search, &key)#fallible;
+        });
+    }
+
+    // Base component, whose raw values are resolved the same way as in `search`
+    let prev_search = search_vars[top - 1].clone();
+    let field = component_vars[top].clone();
+    let fallible = fallible(persisted);
+
+    let resolve = resolve_value(layout);
+    let pipeline = create_pipeline(layout, &search_vars, &component_vars, &resolve);
+
+    let body = quote! {
+        // Visit the keys in sorted order, so that keys sharing a base node are searched in a row
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        if !keys.windows(2).all(|pair| pair[0] <= pair[1]) {
+            order.sort_unstable_by_key(|&index| keys[index]);
+        }
+
+        let hint = SearchHint::new();
+        let mut results = vec![None; keys.len()];
+
+        for index in order {
+            let key = keys[index];
+
+            let #prev_search = match hint.node_for(&self.#field, &key) {
+                Some(node) => node,
+                None => {
+                    #descent
+                    #prev_search
+                }
+            };
+            hint.set(#prev_search);
+
+            let value = self.#field.search(#prev_search, &key)#fallible;
+            results[index] = #resolve;
+        }
+    };
+
+    if persisted {
+        quote! {
+            impl<K, V> #name<K, V>
+            where
+                K: Persisted + #key_bound,
+                V: Persisted + Value,
+            {
+                /// Search for every key in `keys`, returning the results in the same order. Only
+                /// keys which fall outside the base node of the previous key descend from the top,
+                /// so batches of nearby keys are much cheaper than searching them one by one.
+                pub fn search_batch(&self, keys: &[K]) -> limousine_engine::Result<Vec<Option<V>>> {
+                    #body
+                    Ok(results)
+                }
+
+                /// Search for every key in `keys`, returning the results in the same order. The
+                /// whole batch moves through the layers one stage at a time, searching the top for
+                /// every key, then every internal layer in turn, and finally the base, which keeps
+                /// the code of a single layer hot for the whole stage.
+                pub fn search_pipelined(&self, keys: &[K]) -> limousine_engine::Result<Vec<Option<V>>> {
+                    #pipeline
+                    Ok(results)
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl<K: #key_bound, V: #value_bound> #name<K, V> {
+                /// Search for every key in `keys`, returning the results in the same order. Only
+                /// keys which fall outside the base node of the previous key descend from the top,
+                /// so batches of nearby keys are much cheaper than searching them one by one.
+                pub fn search_batch(&self, keys: &[K]) -> Vec<Option<V>> {
+                    #body
+                    results
+                }
+
+                /// Search for every key in `keys`, returning the results in the same order. The
+                /// whole batch moves through the layers one stage at a time, searching the top for
+                /// every key, then every internal layer in turn, and finally the base. Every stage
+                /// prefetches the nodes the next one reads, so that large batches of scattered
+                /// keys wait on memory far less than when searching them one by one.
+                pub fn search_pipelined(&self, keys: &[K]) -> Vec<Option<V>> {
+                    #pipeline
+                    results
+                }
+            }
+        }
+    }
+}
+
+/// Turn the raw `value` found in the base layer into the value `search` returns
+pub fn resolve_value(layout: &HybridLayout) -> TokenStream {
+    if layout.value_log_threshold().is_some() {
+        quote! {
+            match value {
+                Some(value) => Some(self.vlog.resolve(value)?),
+                None => None,
+            }
+        }
+    } else if layout.is_versioned() {
+        quote! { value.and_then(|chain| chain.latest().cloned()) }
+    } else if layout.tombstones {
+        quote! { value.and_then(Entry::into_value) }
+    } else if layout.has_ttl() {
+        quote! { value.and_then(|entry| entry.into_live(unix_millis())) }
+    } else {
+        quote! { value }
+    }
+}
+
+/// Search a batch of `keys` one layer at a time, collecting the addresses every stage ends up at
+/// into a vector named after the search variable of the layer, and prefetching the nodes they
+/// point to before the next stage reads them
+fn create_pipeline(
+    layout: &HybridLayout,
+    search_vars: &[Ident],
+    component_vars: &[Ident],
+    resolve: &TokenStream,
+) -> TokenStream {
+    let top = layout.internal.len() + 1;
+
+    // Top component
+    let search = search_vars[0].clone();
+    let field = component_vars[0].clone();
+    let next = component_vars[1].clone();
+
+    let mut pipeline = quote! {
+        let mut #search = Vec::with_capacity(keys.len());
+        for key in keys {
+            #search.push(self.#field.search(&self.#next, key));
+        }
+    };
+
+    // Internal components
+    for index in 1..=layout.internal.len() {
+        let search = search_vars[index].clone();
+        let prev_search = search_vars[index - 1].clone();
+        let field = component_vars[index].clone();
+        let next = component_vars[index + 1].clone();
+        let fallible = if layout.internal[index - 1].is_persisted() {
+            quote! { ? }
+        } else {
+            TokenStream::new()
+        };
+
+        pipeline.extend(quote! {
+            for ptr in #prev_search.iter() {
+                self.#field.prefetch(ptr.clone());
+            }
+
+            let mut #search = Vec::with_capacity(keys.len());
+            for (key, ptr) in keys.iter().zip(#prev_search) {
+                #search.push(self.#field.search(&self.#next, ptr, key)#fallible);
+            }
+        });
+    }
+
+    // Base component
+    let prev_search = search_vars[top - 1].clone();
+    let field = component_vars[top].clone();
+    let fallible = if layout.is_persisted() {
+        quote! { ? }
+    } else {
+        TokenStream::new()
+    };
+
+    pipeline.extend(quote! {
+        for ptr in #prev_search.iter() {
+            self.#field.prefetch(ptr.clone());
+        }
+
+        let mut results = Vec::with_capacity(keys.len());
+        for (key, ptr) in keys.iter().zip(#prev_search) {
+            let value = self.#field.search(ptr, key)#fallible;
+            results.push(#resolve);
+        }
+    });
+
+    pipeline
+}
@@ -0,0 +1,142 @@
+//! `search_with_budget`, a search which gives up as soon as descending another layer would take it
+//! over a `ProbeBudget`
+
+use super::batch::resolve_value;
+use crate::HybridLayout;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+pub fn create_budget_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
+        .rev()
+        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
+        .collect();
+
+    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
+    let persisted = layout.is_persisted();
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    let top = layout.internal.len() + 1;
+
+    // Persisted components can fail
+    let fallible = |persisted: bool| {
+        if persisted {
+            quote! { ? }
+        } else {
+            TokenStream::new()
+        }
+    };
+
+    // Only persisted layouts read from disk, counted by the store since the search started
+    let disk_bytes = if persisted {
+        quote! { self.stats.bytes_read() - start }
+    } else {
+        quote! { 0 }
+    };
+
+    // Give up before visiting `layer` if the budget doesn't allow for another node
+    let check = |layer: usize| {
+        let exceeded = quote! {
+            SearchOutcome::Exceeded {
+                layer: #layer,
+                nodes,
+                disk_bytes: #disk_bytes,
+            }
+        };
+
+        let exceeded = if persisted {
+            quote! { Ok(#exceeded) }
+        } else {
+            exceeded
+        };
+
+        quote! {
+            if !budget.allows_next(nodes, #disk_bytes) {
+                return #exceeded;
+            }
+            nodes += 1;
+        }
+    };
+
+    let mut body = if persisted {
+        quote! { let start = self.stats.bytes_read(); }
+    } else {
+        TokenStream::new()
+    };
+
+    // Top component
+    let search = search_vars[0].clone();
+    let field = component_vars[0].clone();
+    let next = component_vars[1].clone();
+    let check_top = check(top);
+
+    body.extend(quote! {
+        let mut nodes = 0;
+
+        #check_top
+        let #search = self.#field.search(&self.#next, &key);
+    });
+
+    // Internal components
+    for index in 1..=layout.internal.len() {
+        let search = search_vars[index].clone();
+        let prev_search = search_vars[index - 1].clone();
+        let field = component_vars[index].clone();
+        let next = component_vars[index + 1].clone();
+        let fallible = fallible(layout.internal[index - 1].is_persisted());
+        let check = check(top - index);
+
+        body.extend(quote! {
+            #check
+            let #search = self.#field.search(&self.#next, #prev_search, &key)#fallible;
+        });
+    }
+
+    // Base component, whose raw value is resolved the same way as in `search`
+    let prev_search = search_vars[top - 1].clone();
+    let field = component_vars[top].clone();
+    let fallible = fallible(persisted);
+    let check = check(0);
+    let resolve = resolve_value(layout);
+
+    body.extend(quote! {
+        #check
+        let value = self.#field.search(#prev_search, &key)#fallible;
+        let outcome = SearchOutcome::from(#resolve);
+    });
+
+    if persisted {
+        quote! {
+            impl<K, V> #name<K, V>
+            where
+                K: Persisted + #key_bound,
+                V: Persisted + Value,
+            {
+                /// Search for `key`, unless that takes more than `budget`. The budget is checked
+                /// before every layer is descended, so the read of the last node visited can take
+                /// the bytes read from disk over the budget, but no further layer is visited then.
+                pub fn search_with_budget(
+                    &self,
+                    key: &K,
+                    budget: ProbeBudget,
+                ) -> limousine_engine::Result<SearchOutcome<V>> {
+                    let key = *key;
+                    #body
+                    Ok(outcome)
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl<K: #key_bound, V: #value_bound> #name<K, V> {
+                /// Search for `key`, unless that takes visiting more nodes than `budget` allows.
+                /// In-memory layouts never read from disk, so only the nodes are counted.
+                pub fn search_with_budget(&self, key: &K, budget: ProbeBudget) -> SearchOutcome<V> {
+                    let key = *key;
+                    #body
+                    outcome
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
+use super::{metrics, trace};
+use crate::component::CachePolicy;
 use crate::HybridLayout;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 
 pub fn create_index_struct(
     name: &Ident,
-    _layout: &HybridLayout,
+    layout: &HybridLayout,
     alias: &[Ident],
 ) -> (TokenStream, Vec<Ident>) {
     // Create fields
@@ -21,16 +23,47 @@ pub fn create_index_struct(
     for (index, component) in alias.iter().enumerate() {
         let field = fields[index].clone();
 
-        // TODO: remove the `pub` field, this is for debugging purposes
+        // Components stay `pub` for inspection and debugging, but are hidden from the docs since
+        // they change with the layout and aren't part of the stable surface of the index
         field_bodies.push(quote! {
+            #[doc(hidden)]
             pub #field: #component<K, V>,
         });
     }
 
+    // The value log has to be declared before the store, so that it is dropped first
+    if layout.value_log_threshold().is_some() {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub vlog: ValueLog<K, V>,
+        });
+    }
+
+    // Batches are logged to the store as well, so the log is dropped before it
+    if !layout.read_only {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub wal: WriteAheadLog<K, V>,
+        });
+    }
+
+    // Also dropped before the store, after every layer has flushed its pages
+    field_bodies.push(quote! {
+        pub stats: StatsStore,
+    });
+
+    // Indexes opened inside an external store leave it to the caller
+    let store = if layout.is_external() {
+        quote! { Option<GlobalStore> }
+    } else {
+        quote! { GlobalStore }
+    };
+
     let body = quote! {
-        pub struct #name<K: Persisted + Key, V: Persisted + Value> {
+        pub struct #name<K: Persisted + Key + PrimInt, V: Persisted + Value> {
             #(#field_bodies)*
-            pub store: GlobalStore,
+            #[doc(hidden)]
+            pub store: #store,
         }
     };
 
@@ -43,14 +76,82 @@ pub fn create_index_impl(
     aliases: &[Ident],
     fields: &[Ident],
 ) -> TokenStream {
-    let search_body = create_search_body(layout, aliases, fields);
-    let insert_body = create_insert_body(layout, aliases, fields);
-    let load_body = create_load_body(layout, aliases, fields);
+    let mut body = create_store_impl(name, layout, aliases, fields);
+    body.extend(create_projection_impl(name, layout, aliases, fields));
+    body.extend(create_contains_impl(name, layout, fields));
+    body.extend(create_search_many_impl(name, layout, fields));
+    body.extend(create_paging_impl(name, layout, fields));
+    body.extend(create_disk_usage_impl(name, layout, fields));
+    body.extend(create_warm_impl(name, layout, fields));
+    body.extend(create_write_batch_impl(name, layout, aliases, fields));
+    body.extend(create_external_build_impl(name, layout, aliases, fields));
+    body.extend(create_backend_open_impl(name, layout, aliases, fields));
+    body.extend(create_encrypted_open_impl(name, layout, aliases, fields));
+    body.extend(create_external_open_impl(name, layout, aliases, fields));
+    body.extend(create_external_copy_impl(name, layout, aliases, fields));
+    body
+}
+
+fn create_store_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    let search_body = create_search_body(layout, aliases, fields, quote! { search });
+    let insert_body = create_insert_body(layout, aliases, fields, false);
+    let hinted_insert_body = create_insert_body(layout, aliases, fields, true);
+    let load_body = create_load_body(layout, aliases, fields, quote! { GlobalStore::load(path)? });
 
     let checksum = layout.persist_checksum();
 
+    if layout.read_only {
+        return create_read_only_index_impl(name, &fields[0], search_body, load_body, checksum);
+    }
+
+    if layout.value_log_threshold().is_some() {
+        return create_value_log_index_impl(name, search_body, insert_body, load_body, checksum);
+    }
+
+    if layout.tombstones {
+        let base = fields[0].clone();
+        return create_tombstone_index_impl(
+            name,
+            &base,
+            search_body,
+            insert_body,
+            load_body,
+            checksum,
+        );
+    }
+
+    if layout.has_ttl() {
+        let base = fields[0].clone();
+        return create_ttl_index_impl(name, &base, search_body, insert_body, load_body, checksum);
+    }
+
+    let base_address = layout.base.address_type();
+
     let body = quote! {
-        impl<K: Key, V: Value> PersistedKVStore<K, V> for #name<K, V>
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Insert a key, skipping the descent from the top if it falls within the base node
+            /// remembered by `hint`. The hint is updated to the node the key was inserted into.
+            pub fn insert_with_hint(
+                &mut self,
+                key: K,
+                value: V,
+                hint: &SearchHint<#base_address>,
+            ) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
+                #hinted_insert_body
+            }
+        }
+
+        impl<K: Key + PrimInt, V: Value> PersistedKVStore<K, V> for #name<K, V>
         where
             K: limousine_engine::private::Persisted,
             V: limousine_engine::private::Persisted,
@@ -60,6 +161,7 @@ pub fn create_index_impl(
             }
 
             fn insert(&mut self, key: K, value: V) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
                 #insert_body
             }
 
@@ -73,67 +175,308 @@ pub fn create_index_impl(
     body
 }
 
-fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident]) -> TokenStream {
-    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
-        .rev()
-        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
-        .collect();
+/// A `read_only` index implements `IndexRead` and an inherent `open` instead of `PersistedKVStore`,
+/// which would require `insert`
+fn create_read_only_index_impl(
+    name: &Ident,
+    base: &Ident,
+    search_body: TokenStream,
+    load_body: TokenStream,
+    checksum: String,
+) -> TokenStream {
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            pub fn open(path: impl AsRef<Path>) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #load_body
+            }
+        }
 
-    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
-    let mut search_body = TokenStream::new();
+        impl<K: Key + PrimInt, V: Value> IndexRead<K, V> for #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn try_search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                #search_body
+            }
 
-    // Top component
-    let search = search_vars[0].clone();
-    let field = component_vars[0].clone();
-    let next = component_vars[1].clone();
+            fn len(&self) -> usize {
+                self.#base.len()
+            }
+        }
+    }
+}
 
-    search_body.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+/// With a value log, the base layer stores `VLogValue<V>` instead of `V`. The usual search and
+/// insert bodies then operate on the raw base layer values, and the trait methods translate between
+/// the two representations.
+fn create_value_log_index_impl(
+    name: &Ident,
+    search_body: TokenStream,
+    insert_body: TokenStream,
+    load_body: TokenStream,
+    checksum: String,
+) -> TokenStream {
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search_raw(&self, key: K) -> limousine_engine::Result<Option<VLogValue<V>>> {
+                #search_body
+            }
 
-    // Internal components
-    for index in 1..=layout.internal.len() {
-        let search = search_vars[index].clone();
-        let prev_search = search_vars[index - 1].clone();
-        let field = component_vars[index].clone();
-        let next = component_vars[index + 1].clone();
+            fn insert_raw(&mut self, key: K, value: VLogValue<V>) -> limousine_engine::Result<Option<VLogValue<V>>> {
+                #insert_body
+            }
 
-        if layout.internal[index - 1].is_persisted() {
-            search_body.extend(
-                quote! { let #search = self.#field.search(&self.#next, #prev_search, &key)?;},
-            );
-        } else {
-            search_body.extend(
-                quote! { let #search = self.#field.search(&self.#next, #prev_search, &key);},
-            );
+            /// Reclaim space in the value log by relocating the live values of segments which
+            /// are mostly garbage
+            pub fn maintenance(&mut self) -> limousine_engine::Result<()> {
+                self.stats.record_compaction();
+
+                for segment in self.vlog.gc_candidates() {
+                    for (ptr, key, value) in self.vlog.segment_entries(segment)? {
+                        if self.search_raw(key.clone())? == Some(VLogValue::Pointer(ptr)) {
+                            let relocated = self.vlog.append(key.clone(), value)?;
+                            self.insert_raw(key, VLogValue::Pointer(relocated))?;
+                        }
+                    }
+
+                    self.vlog.remove_segment(segment)?;
+                }
+
+                Ok(())
+            }
+        }
+
+        impl<K: Key + PrimInt, V: Value> PersistedKVStore<K, V> for #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                match self.search_raw(key)? {
+                    Some(value) => Ok(Some(self.vlog.resolve(value)?)),
+                    None => Ok(None),
+                }
+            }
+
+            fn insert(&mut self, key: K, value: V) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
+                let value = self.vlog.store(&key, value)?;
+
+                match self.insert_raw(key, value)? {
+                    Some(previous) => {
+                        self.vlog.release(&previous);
+                        Ok(Some(self.vlog.resolve(previous)?))
+                    }
+                    None => Ok(None),
+                }
+            }
+
+            fn open(path: impl AsRef<Path>) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #load_body
+            }
+        }
+    }
+}
+
+/// With tombstones, the base layer stores `Entry<V>` instead of `V`. As with a value log, the usual
+/// search and insert bodies operate on the raw base layer values, and deletes insert a tombstone.
+fn create_tombstone_index_impl(
+    name: &Ident,
+    base: &Ident,
+    search_body: TokenStream,
+    insert_body: TokenStream,
+    load_body: TokenStream,
+    checksum: String,
+) -> TokenStream {
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search_raw(&self, key: K) -> limousine_engine::Result<Option<Entry<V>>> {
+                #search_body
+            }
+
+            fn insert_raw(&mut self, key: K, value: Entry<V>) -> limousine_engine::Result<Option<Entry<V>>> {
+                #insert_body
+            }
+
+            /// Delete a key by overwriting its value with a tombstone, returning the value it held
+            pub fn remove(&mut self, key: K) -> limousine_engine::Result<Option<V>> {
+                // Keys which are missing don't need a tombstone
+                if self.search_raw(key.clone())?.and_then(Entry::into_value).is_none() {
+                    return Ok(None);
+                }
+
+                let previous = self.insert_raw(key, Entry::Tombstone)?;
+
+                // Tombstones don't count as entries
+                self.#base.set_len(self.#base.len() - 1);
+                Ok(previous.and_then(Entry::into_value))
+            }
+
+            /// Reclaim the space held by tombstones in the base layer, returning how many were
+            /// dropped
+            pub fn purge_tombstones(&mut self) -> limousine_engine::Result<usize> {
+                self.#base.retain(|entry| !entry.is_tombstone())
+            }
+        }
+
+        impl<K: Key + PrimInt, V: Value> PersistedKVStore<K, V> for #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                Ok(self.search_raw(key)?.and_then(Entry::into_value))
+            }
+
+            fn insert(&mut self, key: K, value: V) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
+                let previous = self.insert_raw(key, Entry::Value(value))?;
+
+                // Writing over a tombstone adds back the entry it deleted
+                if previous.as_ref().is_some_and(Entry::is_tombstone) {
+                    self.#base.set_len(self.#base.len() + 1);
+                }
+
+                Ok(previous.and_then(Entry::into_value))
+            }
+
+            fn open(path: impl AsRef<Path>) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #load_body
+            }
+        }
+    }
+}
+
+/// With `ttl: enabled`, the base layer stores `Expiring<V>` instead of `V`. As with tombstones, the
+/// usual search and insert bodies operate on the raw base layer values, and searches skip values
+/// which expired as of the time of the search.
+fn create_ttl_index_impl(
+    name: &Ident,
+    base: &Ident,
+    search_body: TokenStream,
+    insert_body: TokenStream,
+    load_body: TokenStream,
+    checksum: String,
+) -> TokenStream {
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search_raw(&self, key: K) -> limousine_engine::Result<Option<Expiring<V>>> {
+                #search_body
+            }
+
+            fn insert_raw(&mut self, key: K, value: Expiring<V>) -> limousine_engine::Result<Option<Expiring<V>>> {
+                #insert_body
+            }
+
+            /// Insert a key which expires at `expires_at`, in milliseconds since the Unix epoch,
+            /// returning the value it held before unless that expired
+            pub fn insert_expiring(&mut self, key: K, value: V, expires_at: u64) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
+                let previous = self.insert_raw(key, Expiring::new(value, Some(expires_at)))?;
+                Ok(previous.and_then(|entry| entry.into_live(unix_millis())))
+            }
+
+            /// Insert a key which expires once `ttl` has passed
+            pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: ::core::time::Duration) -> limousine_engine::Result<Option<V>> {
+                let expires_at = unix_millis().saturating_add(ttl.as_millis() as u64);
+                self.insert_expiring(key, value, expires_at)
+            }
+
+            /// Drop every entry which expired as of `now`, in milliseconds since the Unix epoch,
+            /// returning how many were dropped
+            pub fn sweep_expired(&mut self, now: u64) -> limousine_engine::Result<usize> {
+                let swept = self.#base.retain(|entry| !entry.is_expired(now))?;
+                self.#base.set_len(self.#base.len() - swept);
+                Ok(swept)
+            }
+        }
+
+        impl<K: Key + PrimInt, V: Value> PersistedKVStore<K, V> for #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            fn search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                Ok(self.search_raw(key)?.and_then(|entry| entry.into_live(unix_millis())))
+            }
+
+            fn insert(&mut self, key: K, value: V) -> limousine_engine::Result<Option<V>> {
+                self.stats.record_insert();
+                let previous = self.insert_raw(key, Expiring::new(value, None))?;
+                Ok(previous.and_then(|entry| entry.into_live(unix_millis())))
+            }
+
+            fn open(path: impl AsRef<Path>) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #load_body
+            }
         }
     }
+}
+
+/// `base_search` is the method of the base component called with the node and the key
+fn create_search_body(
+    layout: &HybridLayout,
+    _aliases: &[Ident],
+    fields: &[Ident],
+    base_search: TokenStream,
+) -> TokenStream {
+    let mut search_body = TokenStream::new();
+
+    search_body.extend(trace::span("search"));
+    search_body.extend(metrics::timer("Search"));
+    search_body.extend(create_descent(layout, fields, true));
 
     // Base component
-    let index = layout.internal.len() + 1;
-    let search = search_vars[index].clone();
-    let prev_search = search_vars[index - 1].clone();
-    let field = component_vars[index].clone();
+    let base = fields[0].clone();
 
-    search_body.extend(quote! { let #search = self.#field.search(#prev_search, &key)?;});
-    search_body.extend(quote! { Ok(#search) });
+    search_body.extend(quote! { let s0 = self.#base.#base_search(s1, &key)?;});
+    search_body.extend(trace::found(layout, &Ident::new("s0", Span::call_site())));
+    search_body.extend(quote! { Ok(s0) });
 
     search_body
 }
 
-fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident]) -> TokenStream {
+/// Search the top and internal components for `key`, leaving the base node in `s1`
+fn create_descent(layout: &HybridLayout, fields: &[Ident], traced: bool) -> TokenStream {
     let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
         .rev()
         .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
         .collect();
 
     let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
-    let mut insert_body = TokenStream::new();
+    let mut descent = TokenStream::new();
+    let top = layout.internal.len() + 1;
 
     // Top component
     let search = search_vars[0].clone();
     let field = component_vars[0].clone();
     let next = component_vars[1].clone();
 
-    insert_body.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+    descent.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+    if traced {
+        descent.extend(trace::descend(layout, top, &search));
+    }
 
     // Internal components
     for index in 1..=layout.internal.len() {
@@ -143,25 +486,69 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
         let next = component_vars[index + 1].clone();
 
         if layout.internal[index - 1].is_persisted() {
-            insert_body.extend(
+            descent.extend(
                 quote! { let #search = self.#field.search(&self.#next, #prev_search, &key)?;},
             );
         } else {
-            insert_body.extend(
+            descent.extend(
                 quote! { let #search = self.#field.search(&self.#next, #prev_search, &key);},
             );
         }
+
+        if traced {
+            descent.extend(trace::descend(layout, top - index, &search));
+        }
     }
 
+    descent
+}
+
+/// With `hinted`, the descent is skipped if the base node remembered by `hint` covers the key
+fn create_insert_body(
+    layout: &HybridLayout,
+    _aliases: &[Ident],
+    fields: &[Ident],
+    hinted: bool,
+) -> TokenStream {
+    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
+        .rev()
+        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
+        .collect();
+
+    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
+    let mut insert_body = TokenStream::new();
+
+    insert_body.extend(trace::span("insert"));
+    insert_body.extend(metrics::timer("Insert"));
+    let descent = create_descent(layout, fields, true);
+
     // Base component
     let index = layout.internal.len() + 1;
-    let search = search_vars[index].clone();
     let prev_search = search_vars[index - 1].clone();
     let field = component_vars[index].clone();
 
-    insert_body.extend(quote! { let #search = self.#field.search(#prev_search, &key)?;});
+    if hinted {
+        insert_body.extend(quote! {
+            let #prev_search = match hint.node_for(&self.#field, &key) {
+                Some(node) => node,
+                None => {
+                    #descent
+                    #prev_search
+                }
+            };
+            hint.set(#prev_search);
+        });
+    } else {
+        insert_body.extend(descent);
+    }
 
-    insert_body.extend(quote! { let result = s0; });
+    // The base component hands back the previous value of the key along with the insert
+    let result = Ident::new("result", Span::call_site());
+    insert_body.extend(quote! {
+        let inserted = self.#field.insert(#prev_search, key, value)?;
+        let #result = inserted.previous;
+    });
+    insert_body.extend(trace::found(layout, &result));
 
     // Insert stage
     let insert_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
@@ -169,12 +556,13 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
         .collect();
 
     let var = insert_vars[0].clone();
-    let field = fields[0].clone();
-    let search = search_vars[search_vars.len() - 2].clone();
+    let prop = Ident::new("x", Span::call_site());
+    let trace = trace::propagate(layout, 0, &prop);
 
     insert_body.extend(quote! {
         let #var;
-        if let Some(x) = self.#field.insert(#search, key, value)? {
+        if let Some(x) = inserted.propagate {
+            #trace
             #var = x;
         } else {
             return Ok(result);
@@ -187,11 +575,13 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
 
         let field = fields[index].clone();
         let prev_field = fields[index - 1].clone();
+        let trace = trace::propagate(layout, index, &prop);
 
         if layout.internal[layout.internal.len() - index].is_persisted() {
             insert_body.extend(quote! {
                 let #var;
                 if let Some(x) = self.#field.insert(&mut self.#prev_field, #prev_var)? {
+                    #trace
                     #var = x;
                 } else {
                     return Ok(result);
@@ -201,6 +591,7 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
             insert_body.extend(quote! {
                 let #var;
                 if let Some(x) = self.#field.insert(&mut self.#prev_field, #prev_var) {
+                    #trace
                     #var = x;
                 } else {
                     return Ok(result);
@@ -225,35 +616,810 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
     insert_body
 }
 
-fn create_load_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident]) -> TokenStream {
-    let mut empty_body = TokenStream::new();
+/// Layouts whose base layer stores `V` as is can read a few fields of a value straight from the
+/// pages of the base layer
+fn create_projection_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if layout.value_log_threshold().is_some() || layout.tombstones || layout.has_ttl() {
+        return TokenStream::new();
+    }
 
-    // Add body as the first component
-    let alias = aliases[0].clone();
-    let var = fields[0].clone();
+    let search_body = create_search_body(layout, aliases, fields, quote! { search_project::<S> });
 
-    empty_body.extend(quote! {
-        // Load the store
-        let mut store = GlobalStore::load(path)?;
-    });
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Search for `key`, only reading the fields of its value picked by `S`
+            pub fn search_project<S>(&self, key: K) -> limousine_engine::Result<Option<S::Output>>
+            where
+                V: Projectable,
+                S: FieldSelector<V>,
+            {
+                #search_body
+            }
+        }
+    }
+}
 
-    // Base layer is guaranteed to be a disk component
-    let alias_name = alias.to_string();
-    empty_body.extend(quote! {
-        // Load the store
-        let mut #var = #alias::load(&mut store, #alias_name)?;
-    });
+/// `contains_key` stops at the keys of the base node, and never reads the value. Tombstones and
+/// expired values are only told apart from live values by reading them.
+fn create_contains_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let base = fields[0].clone();
 
-    // Add internal components
-    for index in 1..=layout.internal.len() {
-        let alias = aliases[index].clone();
-        let var = fields[index].clone();
-        let prev_var = fields[index - 1].clone();
+    let body = if layout.tombstones || layout.has_ttl() {
+        quote! { Ok(PersistedKVStore::search(self, *key)?.is_some()) }
+    } else {
+        let mut body = TokenStream::new();
+        body.extend(quote! { let key = *key; });
+        body.extend(trace::span("contains_key"));
+        body.extend(create_descent(layout, fields, true));
+        body.extend(quote! { self.#base.contains(s1, &key) });
+        body
+    };
 
-        let alias_name = alias.to_string();
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Whether `key` is present, without reading its value
+            pub fn contains_key(&self, key: &K) -> limousine_engine::Result<bool> {
+                #body
+            }
+        }
+    }
+}
+
+/// `search_many` descends for every key before reading any base node, so that keys sharing a base
+/// node are answered by a single read of its page. Layouts wrapping the values of the base layer
+/// search for every key on its own, since those values have to be translated one by one.
+fn create_search_many_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let base = fields[0].clone();
+
+    let body = if layout.value_log_threshold().is_some() || layout.tombstones || layout.has_ttl() {
+        quote! {
+            keys.iter()
+                .map(|&key| PersistedKVStore::search(self, key))
+                .collect()
+        }
+    } else {
+        let descent = create_descent(layout, fields, false);
+
+        quote! {
+            let mut nodes = Vec::with_capacity(keys.len());
+            for (index, &key) in keys.iter().enumerate() {
+                #descent
+                nodes.push((s1, index));
+            }
+            nodes.sort_unstable_by_key(|&(node, _)| node);
+
+            let mut values: Vec<Option<V>> = keys.iter().map(|_| None).collect();
+            for group in nodes.chunk_by(|a, b| a.0 == b.0) {
+                let node_keys: Vec<K> = group.iter().map(|&(_, index)| keys[index]).collect();
+                let found = self.#base.search_node(group[0].0, &node_keys)?;
+
+                for (&(_, index), value) in group.iter().zip(found) {
+                    values[index] = value;
+                }
+            }
+
+            Ok(values)
+        }
+    };
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// The value of every key in `keys`, in the same order. Keys falling into the same base
+            /// node are answered by one read of its page, so batches of nearby keys read far
+            /// fewer pages than searching for each of them.
+            pub fn search_many(&self, keys: &[K]) -> limousine_engine::Result<Vec<Option<V>>> {
+                #body
+            }
+        }
+    }
+}
+
+/// `cursor_token` and `resume` page through the base layer, and only descend from the top when
+/// the node a token points at no longer covers its key. Layouts wrapping the values of the base
+/// layer would hand out tombstones, expired values and value log pointers as is.
+fn create_paging_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if layout.value_log_threshold().is_some() || layout.tombstones || layout.has_ttl() {
+        return TokenStream::new();
+    }
+
+    let base = fields[0].clone();
+    let base_address = layout.base.address_type();
+
+    let mut seek = TokenStream::new();
+    seek.extend(trace::span("cursor_token"));
+    seek.extend(create_descent(layout, fields, true));
+    let timer = metrics::timer("Range");
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// A token resuming right after `after`, whether or not it is present. Tokens can be
+            /// kept as bytes, and stay valid across restarts and writes to the index.
+            pub fn cursor_token(&self, after: K) -> limousine_engine::Result<CursorToken<K, #base_address>> {
+                let key = after;
+                #seek
+                Ok(CursorToken::new(s1, None, key))
+            }
+
+            /// Up to `limit` entries following the key of `token`, in key order, along with the
+            /// token resuming after them
+            pub fn resume(
+                &self,
+                token: &CursorToken<K, #base_address>,
+                limit: usize,
+            ) -> limousine_engine::Result<CursorPage<K, V, #base_address>> {
+                #timer
+                let s1 = match token.node_for(&self.#base) {
+                    Some(node) => node,
+                    None => {
+                        let key = *token.after();
+                        #seek
+                        s1
+                    }
+                };
+
+                self.#base.page(s1, token, limit)
+            }
+        }
+    }
+}
+
+/// `disk_usage` attributes the size of the store to every persisted layer, `compact` reclaims dead
+/// space on demand instead of only when the index is dropped, and `cache_stats` counts the pages
+/// found in the cache. None of them apply to a store shared with other indexes, which is measured,
+/// compacted and cached by its owner.
+fn create_disk_usage_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let latency_impl = metrics::summary_impl();
+    let stats_impl = quote! {
+        /// Counters accumulated over the lifetime of the index, across restarts. They are
+        /// saved when the index is dropped.
+        pub fn stats(&self) -> IndexStats {
+            self.stats.get()
+        }
+
+        /// Zero the counters of `stats`, and restart the lifetime of the index
+        pub fn reset_stats(&mut self) {
+            self.stats.reset();
+        }
+
+        #latency_impl
+    };
+
+    if layout.is_external() {
+        return quote! {
+            impl<K: Key + PrimInt, V: Value> #name<K, V>
+            where
+                K: limousine_engine::private::Persisted,
+                V: limousine_engine::private::Persisted,
+            {
+                #stats_impl
+            }
+        };
+    }
+
+    // The base layer is always persisted
+    let mut layers = vec![{
+        let field = fields[0].clone();
+        quote! { (0, self.#field.node_count() as u64) }
+    }];
+
+    for (mut index, component) in layout.internal.iter().rev().enumerate() {
+        index += 1;
+
+        if component.is_persisted() {
+            let field = fields[index].clone();
+            layers.push(quote! { (#index, self.#field.node_count() as u64) });
+        }
+    }
+
+    let value_log = if layout.value_log_threshold().is_some() {
+        quote! { Some(self.vlog.segment_count() as u64) }
+    } else {
+        quote! { None }
+    };
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Size of the index on disk, broken down by persisted layer. Pages which are only
+            /// cached so far are counted, but not their bytes.
+            pub fn disk_usage(&self) -> DiskStats {
+                DiskStats::attribute(self.store.stats(), [#(#layers),*], #value_log)
+            }
+
+            /// Reclaim the space held by overwritten and freed pages now, rather than when the
+            /// index is dropped, returning the number of pages moved
+            pub fn compact(&mut self) -> limousine_engine::Result<usize> {
+                self.stats.record_compaction();
+                self.store.maintenance()
+            }
+
+            /// Lookups into the page caches of the index since it was opened, under its
+            /// `cache_policy`
+            pub fn cache_stats(&self) -> CacheStats {
+                self.store.cache_stats()
+            }
+
+            #stats_impl
+        }
+    }
+}
+
+/// `warm` reads the pages of every persisted internal layer, and of the base nodes holding a range
+/// of keys, into the cache right after loading, so that the first lookups don't have to
+fn create_warm_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let mut internal = TokenStream::new();
+
+    for (mut index, component) in layout.internal.iter().enumerate() {
+        index = layout.internal.len() - index;
+
+        if component.is_persisted() {
+            let field = fields[index].clone();
+            internal.extend(quote! { self.#field.warm(&mut stats)?; });
+        }
+    }
+
+    let descent = create_descent(layout, fields, false);
+    let base = fields[0].clone();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Read the pages the first lookups into `range` would, ahead of time: every page of
+            /// the persisted internal layers, and the base nodes holding keys in `range`. Pass `..`
+            /// to read the whole index, or an empty range such as `0..0` to only read the internal
+            /// layers. Pages of internal layers stay cached, but base pages are evicted as usual,
+            /// so warming much more of the base layer than fits in the cache is wasted work.
+            pub fn warm(
+                &self,
+                range: impl std::ops::RangeBounds<K>,
+            ) -> limousine_engine::Result<WarmStats> {
+                use std::ops::Bound;
+
+                let mut stats = WarmStats::default();
+
+                // Internal layers, from the top down
+                #internal
+
+                let empty = match (range.start_bound(), range.end_bound()) {
+                    (Bound::Included(start), Bound::Included(end)) => start > end,
+                    (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => start >= end,
+                    _ => false,
+                };
+
+                if empty {
+                    return Ok(stats);
+                }
+
+                let first = match range.start_bound() {
+                    Bound::Included(key) | Bound::Excluded(key) => self.locate(*key)?,
+                    Bound::Unbounded => self.#base.first(),
+                };
+
+                let last = match range.end_bound() {
+                    Bound::Included(key) | Bound::Excluded(key) => self.locate(*key)?,
+                    Bound::Unbounded => self.#base.last(),
+                };
+
+                let mut ptr = Some(first);
+                while let Some(node) = ptr {
+                    self.#base.warm_node(node, &mut stats)?;
+
+                    if node == last {
+                        break;
+                    }
+
+                    ptr = self.#base.next(node);
+                }
+
+                Ok(stats)
+            }
+
+            /// The base node a search for `key` ends up in
+            fn locate(&self, key: K) -> limousine_engine::Result<A0> {
+                #descent
+                Ok(s1)
+            }
+        }
+    }
+}
+
+/// Every persisted index can also be opened over any `StorageBackend` with `open_with_backend`,
+/// and read-only with `open_read_only` or `open_read_only_with_backend`. A backend has no path to
+/// prefix with the checksum of the layout, so it must only ever hold a single layout.
+fn create_backend_open_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    let load_body = create_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { GlobalStore::with_backend(backend)? },
+    );
+    let read_only_body = create_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { GlobalStore::with_backend_read_only(backend)? },
+    );
+    let read_only_path_body = create_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { GlobalStore::load_read_only(path)? },
+    );
+    let checksum = layout.persist_checksum();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Open the index kept by `backend`, or create it if the backend holds no pages
+            pub fn open_with_backend(
+                backend: impl StorageBackend,
+            ) -> limousine_engine::Result<Self> {
+                #load_body
+            }
+
+            /// Open the index at `path` without ever writing to it. Inserts fail, and an index
+            /// which was never created can't be opened. The `MarbleBackend` used by `open` locks
+            /// its directory, so this fails with `StoreLocked` while the index is open elsewhere.
+            pub fn open_read_only(path: impl AsRef<Path>) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #read_only_path_body
+            }
+
+            /// Open the index kept by `backend` without ever writing to it, alongside the process
+            /// which owns it, as with a `FileBackend` opened with `open_read_only`. A batch the
+            /// owner is still applying is ignored, so the index is seen as it was before the batch.
+            pub fn open_read_only_with_backend(
+                backend: impl StorageBackend,
+            ) -> limousine_engine::Result<Self> {
+                #read_only_body
+            }
+        }
+    }
+}
+
+/// With the `encryption` feature, every persisted index also gets an `open_with_key`, which opens
+/// the index like `open` but encrypts every page with the given key. Pages are sealed under random
+/// nonces, so `deterministic` indexes don't get it.
+fn create_encrypted_open_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if !cfg!(feature = "encryption") || layout.deterministic {
+        return TokenStream::new();
+    }
+
+    let load_body = create_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { GlobalStore::load_with_key(path, key)? },
+    );
+    let checksum = layout.persist_checksum();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Open the index stored at `path` with every page encrypted with `key`, or create it.
+            /// An index created with a key can only be opened again with the same key.
+            pub fn open_with_key(
+                path: impl AsRef<Path>,
+                key: &EncryptionKey,
+            ) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #load_body
+            }
+        }
+    }
+}
+
+/// With `storage: external`, indexes also get an `open_in`, which opens the index inside a
+/// `GlobalStore` owned by the caller. The local stores of the index are named after `ident` and
+/// the checksum of the layout, so several indexes, value logs and other local stores can share a
+/// single store, as long as every index has its own `ident`.
+fn create_external_open_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if !layout.is_external() {
+        return TokenStream::new();
+    }
+
+    let load_body = create_layers_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { store },
+        TokenStream::new(),
+        quote! { None },
+    );
+    let checksum = layout.persist_checksum();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Open the index `ident` inside `store`, or create it. The index has to be dropped
+            /// before `store`, which is flushed and compacted by its owner.
+            pub fn open_in(
+                store: &mut GlobalStore,
+                ident: impl ToString,
+            ) -> limousine_engine::Result<Self> {
+                let prefix = format!("{}/{}/", ident.to_string(), #checksum);
+                #load_body
+            }
+        }
+    }
+}
+
+/// Indexes opened with `open_in` can also be copied into another `GlobalStore`, or into the same
+/// one under another `ident`. A value log is referenced by the values of the base layer, which are
+/// copied as they are, so layouts with one can't be copied.
+fn create_external_copy_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if !layout.is_external() || layout.value_log_threshold().is_some() {
+        return TokenStream::new();
+    }
+
+    // Only the base and the persisted internal layers have pages
+    let persisted: Vec<(Ident, String)> = (0..=layout.internal.len())
+        .filter(|&index| index == 0 || layout.internal[layout.internal.len() - index].is_persisted())
+        .map(|index| (fields[index].clone(), aliases[index].to_string()))
+        .collect();
+
+    let reserve = persisted.iter().map(|(field, _)| {
+        quote! { remap.reserve(store, self.#field.pages()); }
+    });
+    let copy = persisted.iter().map(|(field, alias)| {
+        quote! { self.#field.copy_into(store, format!("{}{}", prefix, #alias), &remap)?; }
+    });
+    let checksum = layout.persist_checksum();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Copy the index into `store` as the index `ident`, which can then be opened with
+            /// `open_in`. Pages are given new ids in `store`, and every reference between them is
+            /// translated, so the index can be copied into a store which already holds other
+            /// pages. The copy starts out with fresh `stats`, and `ident` must not hold an index
+            /// with this layout yet.
+            pub fn copy_into(
+                &self,
+                store: &mut GlobalStore,
+                ident: impl ToString,
+            ) -> limousine_engine::Result<()> {
+                let prefix = format!("{}/{}/", ident.to_string(), #checksum);
+
+                // Layers reference the pages of the layers around them, so every page is given its
+                // new id before any is copied
+                let mut remap = StoreIDRemap::default();
+                #(#reserve)*
+                #(#copy)*
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Plain layouts also get a `build_external`, which builds the index from entries in any order
+/// with a `DiskBuilder`, so that it can be built over more entries than fit in memory
+fn create_external_build_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if layout.read_only
+        || layout.tombstones
+        || layout.has_ttl()
+        || layout.value_log_threshold().is_some()
+    {
+        return TokenStream::new();
+    }
+
+    let base = fields[0].clone();
+    let build_body = create_load_body_with(
+        layout,
+        aliases,
+        fields,
+        quote! { GlobalStore::load(path)? },
+        quote! {
+            let mut builder = DiskBuilder::load(&mut store, "Runs", run_entries)?;
+            builder.extend(entries)?;
+            #base.fill(builder.finish()?)?;
+        },
+    );
+    let checksum = layout.persist_checksum();
+
+    quote! {
+        impl<K: Key + PrimInt, V: Value> #name<K, V>
+        where
+            K: limousine_engine::private::Persisted,
+            V: limousine_engine::private::Persisted,
+        {
+            /// Build the index at `path` from entries in any order, holding at most `run_entries`
+            /// of them in memory. Entries are sorted into runs which are spilled to the store, then
+            /// merged straight into the base layer, from which the layers above are built. Of
+            /// several entries with the same key, the last one is kept. The index at `path` must
+            /// not hold any entries yet.
+            pub fn build_external(
+                path: impl AsRef<Path>,
+                entries: impl IntoIterator<Item = (K, V)>,
+                run_entries: usize,
+            ) -> limousine_engine::Result<Self> {
+                let path = limousine_engine::private::add_prefix_to_path(path, #checksum.to_string())?;
+                #build_body
+            }
+        }
+    }
+}
+
+/// `apply_batch` logs the batch to the write-ahead log, then applies it and flushes every
+/// persisted layer in a batch of the store, which writes every page along with the cleared log at
+/// once. If any write fails, the batch is aborted and every layer reloads.
+fn create_write_batch_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if layout.read_only {
+        return TokenStream::new();
+    }
+
+    // Only layers stored on disk have pages to write out, the rest are rebuilt as the index loads
+    let mut persisted = vec![fields[0].clone()];
+    for (field, component) in fields[1..].iter().zip(layout.internal.iter().rev()) {
+        if component.is_persisted() {
+            persisted.push(field.clone());
+        }
+    }
+
+    // Layers reload from the bottom up, each linked to the one below as it was when loaded
+    let base = fields[0].clone();
+    let mut reload = quote! { self.#base.reload()?; };
+    for index in 1..=layout.internal.len() + 1 {
+        let alias = aliases[index].clone();
+        let var = fields[index].clone();
+        let prev_var = fields[index - 1].clone();
+
+        let is_persisted = index <= layout.internal.len()
+            && layout.internal[layout.internal.len() - index].is_persisted();
+        if is_persisted {
+            reload.extend(quote! { self.#var.reload(&mut self.#prev_var)?; });
+        } else {
+            reload.extend(quote! { self.#var = #alias::build(&mut self.#prev_var); });
+        }
+    }
+
+    let (flush_vlog, reload_vlog) = if layout.value_log_threshold().is_some() {
+        (quote! { self.vlog.flush()?; }, quote! { self.vlog.reload()?; })
+    } else {
+        (TokenStream::new(), TokenStream::new())
+    };
+
+    // Without tombstones, the log refuses batches which delete keys
+    let delete = if layout.tombstones {
+        quote! { self.remove(key)?; }
+    } else {
+        quote! { unreachable!("deletes are refused as the batch is logged"); }
+    };
+
+    quote! {
+        impl<K, V> #name<K, V>
+        where
+            K: Persisted + Key + PrimInt,
+            V: Persisted + Value,
+        {
+            /// Apply every write of `batch` in order, as one. The batch is logged before any of it
+            /// is written, so if the process dies halfway through, opening the index again applies
+            /// the whole batch. If a write fails, none of the batch is applied.
+            pub fn apply_batch(&mut self, batch: WriteBatch<K, V>) -> limousine_engine::Result<()> {
+                self.wal.log(&batch)?;
+                self.write_batch(batch)
+            }
+
+            fn write_batch(&mut self, batch: WriteBatch<K, V>) -> limousine_engine::Result<()> {
+                // An aborted batch reloads the layers as the backend holds them, so nothing written
+                // before the batch may be left unflushed
+                #(self.#persisted.flush()?;)*
+                #flush_vlog
+
+                self.wal.begin()?;
+                match self.write_pending(batch) {
+                    Ok(()) => Ok(()),
+                    Err(error) => {
+                        self.abort_batch()?;
+                        Err(error)
+                    }
+                }
+            }
+
+            /// Write the batch, and every page it changed along with the cleared log in one batch
+            /// of the store
+            fn write_pending(&mut self, batch: WriteBatch<K, V>) -> limousine_engine::Result<()> {
+                for (key, value) in batch {
+                    match value {
+                        Some(value) => {
+                            PersistedKVStore::insert(self, key, value)?;
+                        }
+                        None => {
+                            #delete
+                        }
+                    }
+                }
+
+                #(self.#persisted.flush()?;)*
+                #flush_vlog
+                self.wal.commit()
+            }
+
+            /// Drop every page the batch wrote, reload the layers as they were before it, and
+            /// clear the log
+            fn abort_batch(&mut self) -> limousine_engine::Result<()> {
+                self.wal.abort();
+                #reload
+                #reload_vlog
+                self.wal.clear()
+            }
+
+            /// Apply the batch left in the log by an `apply_batch` which was cut short
+            fn replay_batch(&mut self) -> limousine_engine::Result<()> {
+                match self.wal.pending() {
+                    Some(batch) => self.write_batch(batch),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+fn create_load_body(
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+    load_store: TokenStream,
+) -> TokenStream {
+    create_load_body_with(layout, aliases, fields, load_store, TokenStream::new())
+}
+
+/// Load every layer, running `fill_base` right after the base layer is loaded, before any of the
+/// layers above are built from it
+fn create_load_body_with(
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+    load_store: TokenStream,
+    fill_base: TokenStream,
+) -> TokenStream {
+    let load_store = match layout.cache_policy {
+        CachePolicy::Lru => load_store,
+        CachePolicy::TinyLfu => quote! { #load_store.with_cache_policy(CachePolicy::TinyLfu) },
+    };
+
+    let load_store = match layout.deterministic {
+        true => quote! { #load_store.deterministic()? },
+        false => load_store,
+    };
+
+    let mut body = quote! {
+        // Load the store
+        let mut store = #load_store;
+    };
+
+    // Local stores of an owned store keep their plain names
+    if layout.is_external() {
+        body.extend(quote! { let prefix = String::new(); });
+    }
+
+    body.extend(create_layers_load_body(
+        layout,
+        aliases,
+        fields,
+        quote! { &mut store },
+        fill_base,
+        quote! { Some(store) },
+    ));
+    body
+}
+
+/// Load every layer from the store `store_ref`, and finish with `Self`, holding `owned_store`.
+/// With `storage: external`, the name of every local store is prefixed by a `prefix` variable,
+/// which the caller has to define.
+fn create_layers_load_body(
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+    store_ref: TokenStream,
+    fill_base: TokenStream,
+    owned_store: TokenStream,
+) -> TokenStream {
+    let mut empty_body = TokenStream::new();
+
+    let local_ident = |ident: &str| {
+        if layout.is_external() {
+            quote! { format!("{}{}", prefix, #ident) }
+        } else {
+            quote! { #ident }
+        }
+    };
+
+    // Add body as the first component
+    let alias = aliases[0].clone();
+    let var = fields[0].clone();
+
+    // Base layer is guaranteed to be a disk component
+    let alias_name = local_ident(&alias.to_string());
+    empty_body.extend(quote! {
+        // Load the store
+        let mut #var = #alias::load(#store_ref, #alias_name)?;
+    });
+
+    empty_body.extend(fill_base);
+
+    // Add internal components
+    for index in 1..=layout.internal.len() {
+        let alias = aliases[index].clone();
+        let var = fields[index].clone();
+        let prev_var = fields[index - 1].clone();
+
+        let alias_name = local_ident(&alias.to_string());
         if layout.internal[layout.internal.len() - index].is_persisted() {
+            // Internal pages are on the path of every lookup, so only base pages are evicted
             empty_body.extend(quote! {
-                let mut #var = #alias::load(&mut #prev_var, &mut store, #alias_name)?;
+                let mut #var = #alias::load(&mut #prev_var, #store_ref, #alias_name)?;
+                #var.pin_resident();
             });
         } else {
             empty_body.extend(quote! {
@@ -271,12 +1437,60 @@ fn create_load_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident])
         let mut #var = #alias::build(&mut #prev_var);
     });
 
+    let stats_name = local_ident("Stats");
     empty_body.extend(quote! {
-        Ok(Self {
-            #(#fields,)*
-            store,
-        })
+        let stats = StatsStore::load(#store_ref, #stats_name)?;
     });
 
+    // Only an external store may be missing, owned stores are always there
+    let store = if layout.is_external() {
+        quote! { store: #owned_store, }
+    } else {
+        quote! { store, }
+    };
+
+    let vlog = if let Some(threshold) = layout.value_log_threshold() {
+        let vlog_name = local_ident("ValueLog");
+        empty_body.extend(quote! {
+            let vlog = ValueLog::load(#store_ref, #vlog_name, #threshold)?;
+        });
+        quote! { vlog, }
+    } else {
+        TokenStream::new()
+    };
+
+    // A batch left in the log was cut short, and is applied again before the index is used. A
+    // read-only store can't write it, so the batch is left in the log for the process which owns
+    // the store, and the index is opened as it was before the batch.
+    if layout.read_only {
+        empty_body.extend(quote! {
+            Ok(Self {
+                #(#fields,)*
+                #vlog
+                stats,
+                #store
+            })
+        });
+    } else {
+        let wal_name = local_ident("WriteAheadLog");
+        let tombstones = layout.tombstones;
+        empty_body.extend(quote! {
+            let wal = WriteAheadLog::load(#store_ref, #wal_name, #tombstones)?;
+            let read_only = store.is_read_only();
+
+            let mut index = Self {
+                #(#fields,)*
+                #vlog
+                wal,
+                stats,
+                #store
+            };
+            if !read_only {
+                index.replay_batch()?;
+            }
+            Ok(index)
+        });
+    }
+
     empty_body
 }
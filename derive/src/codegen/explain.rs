@@ -0,0 +1,126 @@
+//! `explain`, a search which records every layer it visits in a `LookupTrace`
+
+use super::trace::component_name;
+use crate::component::InternalComponent;
+use crate::HybridLayout;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+pub fn create_explain_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
+        .rev()
+        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
+        .collect();
+
+    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
+    let persisted = layout.is_persisted();
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    let mut body = TokenStream::new();
+    let top = layout.internal.len() + 1;
+
+    // Persisted components can fail
+    let fallible = |persisted: bool| {
+        if persisted {
+            quote! { ? }
+        } else {
+            TokenStream::new()
+        }
+    };
+
+    // Top component
+    let search = search_vars[0].clone();
+    let field = component_vars[0].clone();
+    let next = component_vars[1].clone();
+    // A swappable top reports the kind of top it currently holds
+    let component = if layout.swappable_top {
+        quote! { self.#field.name() }
+    } else {
+        let component = component_name(layout, top);
+        quote! { #component }
+    };
+
+    body.extend(quote! {
+        steps.push(LookupStep {
+            layer: #top,
+            component: #component,
+            node: None,
+            probe: self.#field.probe(&self.#next, &key),
+        });
+        let #search = self.#field.search(&self.#next, &key);
+    });
+
+    // Internal components
+    for index in 1..=layout.internal.len() {
+        let search = search_vars[index].clone();
+        let prev_search = search_vars[index - 1].clone();
+        let field = component_vars[index].clone();
+        let next = component_vars[index + 1].clone();
+
+        let layer = top - index;
+        // An auto layer reports the kind of layer it picked
+        let component = if layout.internal[index - 1] == InternalComponent::Auto {
+            quote! { self.#field.name() }
+        } else {
+            let component = component_name(layout, layer);
+            quote! { #component }
+        };
+        let fallible = fallible(layout.internal[index - 1].is_persisted());
+
+        body.extend(quote! {
+            steps.push(LookupStep {
+                layer: #layer,
+                component: #component,
+                node: self.#field.lower_bound(#prev_search.clone()).into_key(),
+                probe: self.#field.probe(&self.#next, #prev_search.clone(), &key)#fallible,
+            });
+            let #search = self.#field.search(&self.#next, #prev_search, &key)#fallible;
+        });
+    }
+
+    // Base component
+    let prev_search = search_vars[top - 1].clone();
+    let field = component_vars[top].clone();
+    let component = component_name(layout, 0);
+    let fallible = fallible(persisted);
+
+    body.extend(quote! {
+        steps.push(LookupStep {
+            layer: 0,
+            component: #component,
+            node: self.#field.lower_bound(#prev_search.clone()).into_key(),
+            probe: self.#field.probe(#prev_search.clone(), &key)#fallible,
+        });
+        let found = self.#field.search(#prev_search, &key)#fallible.is_some();
+    });
+
+    if persisted {
+        quote! {
+            impl<K, V> #name<K, V>
+            where
+                K: Persisted + #key_bound,
+                V: Persisted + Value,
+            {
+                /// Search for `key`, recording every layer visited and the work done in each
+                pub fn explain(&self, key: &K) -> limousine_engine::Result<LookupTrace<K>> {
+                    let key = *key;
+                    let mut steps = Vec::new();
+                    #body
+                    Ok(LookupTrace { key, steps, found })
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl<K: #key_bound, V: #value_bound> #name<K, V> {
+                /// Search for `key`, recording every layer visited and the work done in each
+                pub fn explain(&self, key: &K) -> LookupTrace<K> {
+                    let key = *key;
+                    let mut steps = Vec::new();
+                    #body
+                    LookupTrace { key, steps, found }
+                }
+            }
+        }
+    }
+}
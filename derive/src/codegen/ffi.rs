@@ -0,0 +1,138 @@
+use crate::HybridLayout;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+/// Generate `extern "C"` bindings over `u64` keys and values. Every function is prefixed with the
+/// lowercase name of the index, and returns 1 if a value was found, 0 if not, and -1 on error.
+pub fn create_ffi_impl(name: &Ident, layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
+    let prefix = name.to_string().to_lowercase();
+    let ident =
+        |suffix: &str| Ident::new(format!("{}_{}", prefix, suffix).as_str(), Span::call_site());
+
+    let new_fn = ident("new");
+    let open_fn = ident("open");
+    let insert_fn = ident("insert");
+    let search_fn = ident("search");
+    let free_fn = ident("free");
+
+    let constructor_fn = if layout.is_persisted() {
+        open_fn.clone()
+    } else {
+        new_fn.clone()
+    };
+
+    let constructor = if layout.is_persisted() {
+        quote! {
+            /// Open the index stored at the null-terminated `path`, returning null on failure.
+            ///
+            /// # Safety
+            /// `path` must point to a valid null-terminated string.
+            #[no_mangle]
+            pub unsafe extern "C" fn #open_fn(path: *const ::std::os::raw::c_char) -> *mut #name<u64, u64> {
+                if path.is_null() {
+                    return ::std::ptr::null_mut();
+                }
+
+                let Ok(path) = ::std::ffi::CStr::from_ptr(path).to_str() else {
+                    return ::std::ptr::null_mut();
+                };
+
+                match #name::open(path) {
+                    Ok(index) => Box::into_raw(Box::new(index)),
+                    Err(_) => ::std::ptr::null_mut(),
+                }
+            }
+        }
+    } else {
+        quote! {
+            /// Create an empty index, which must be released with the matching `free` function.
+            #[no_mangle]
+            pub extern "C" fn #new_fn() -> *mut #name<u64, u64> {
+                Box::into_raw(Box::new(#name::empty()))
+            }
+        }
+    };
+
+    // `IndexRead` and `IndexWrite` report errors the same way for every layout
+    let insert_call = quote! {
+        match IndexWrite::try_put(index, key, value) {
+            Ok(result) => result,
+            Err(_) => return -1,
+        }
+    };
+    let search_call = quote! {
+        match IndexRead::try_search(index, key) {
+            Ok(result) => result,
+            Err(_) => return -1,
+        }
+    };
+
+    let exports = vec![
+        constructor_fn,
+        insert_fn.clone(),
+        search_fn.clone(),
+        free_fn.clone(),
+    ];
+
+    let body = quote! {
+        #constructor
+
+        /// Insert a key-value pair, writing the previous value into `previous` if it is not null.
+        ///
+        /// # Safety
+        /// `index` must be a live index returned by this library, and `previous` must either be
+        /// null or valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn #insert_fn(index: *mut #name<u64, u64>, key: u64, value: u64, previous: *mut u64) -> i32 {
+            let Some(index) = index.as_mut() else {
+                return -1;
+            };
+
+            match #insert_call {
+                Some(result) => {
+                    if !previous.is_null() {
+                        *previous = result;
+                    }
+                    1
+                }
+                None => 0,
+            }
+        }
+
+        /// Search for a key, writing its value into `value` if it is not null.
+        ///
+        /// # Safety
+        /// `index` must be a live index returned by this library, and `value` must either be
+        /// null or valid for writes.
+        #[no_mangle]
+        pub unsafe extern "C" fn #search_fn(index: *const #name<u64, u64>, key: u64, value: *mut u64) -> i32 {
+            let Some(index) = index.as_ref() else {
+                return -1;
+            };
+
+            match #search_call {
+                Some(result) => {
+                    if !value.is_null() {
+                        *value = result;
+                    }
+                    1
+                }
+                None => 0,
+            }
+        }
+
+        /// Release an index, flushing it to disk if it is persisted.
+        ///
+        /// # Safety
+        /// `index` must be null or a live index returned by this library, and must not be used
+        /// afterwards.
+        #[no_mangle]
+        pub unsafe extern "C" fn #free_fn(index: *mut #name<u64, u64>) {
+            if !index.is_null() {
+                drop(Box::from_raw(index));
+            }
+        }
+    };
+
+    (body, exports)
+}
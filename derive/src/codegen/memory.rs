@@ -1,10 +1,12 @@
+use super::trace;
+use crate::component::{AllocMode, BaseComponent, Filter, ValueStorage};
 use crate::HybridLayout;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 
 pub fn create_index_struct(
     name: &Ident,
-    _layout: &HybridLayout,
+    layout: &HybridLayout,
     alias: &[Ident],
 ) -> (TokenStream, Vec<Ident>) {
     // Create fields
@@ -21,14 +23,62 @@ pub fn create_index_struct(
     for (index, component) in alias.iter().enumerate() {
         let field = fields[index].clone();
 
-        // TODO: remove the `pub` field, this is for debugging purposes
+        // Components stay `pub` for inspection and debugging, but are hidden from the docs since
+        // they change with the layout and aren't part of the stable surface of the index
         field_bodies.push(quote! {
+            #[doc(hidden)]
             pub #field: #component<K, V>,
         });
     }
 
+    // Bookkeeping for versioned layouts, the version of the latest write and every key which
+    // holds more than one version
+    if layout.is_versioned() {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub version: Version,
+            #[doc(hidden)]
+            pub versioned_keys: BTreeSet<K>,
+        });
+    }
+
+    // Keys holding every value, for `find_value`
+    if layout.reverse_lookup {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub reverse: ReverseIndex<K, V>,
+        });
+    }
+
+    // Streams subscribed through `watch`
+    if layout.watch {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub watchers: Watchers<K, V>,
+        });
+    }
+
+    // Base nodes of the keys searched most often, for `fast_fences`
+    if layout.fast_fences.is_some() {
+        let base_address = layout.base.address_type();
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub fences: FastFences<#base_address>,
+        });
+    }
+
+    // Filter answering lookups of absent keys, for `filter`
+    if layout.filter.is_some() {
+        field_bodies.push(quote! {
+            #[doc(hidden)]
+            pub filter: KeyFilter<K>,
+        });
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
     let body = quote! {
-        pub struct #name<K: Key, V: Value> {
+        pub struct #name<K: #key_bound, V: #value_bound> {
             #(#field_bodies)*
         }
     };
@@ -42,13 +92,46 @@ pub fn create_index_impl(
     aliases: &[Ident],
     fields: &[Ident],
 ) -> TokenStream {
-    let search_body = create_search_body(layout, aliases, fields);
-    let insert_body = create_insert_body(layout, aliases, fields);
+    let search_body = create_search_body(layout, aliases, fields, quote! { search });
+    let insert_body = create_insert_body(layout, aliases, fields, false);
+    let hinted_insert_body = create_insert_body(layout, aliases, fields, true);
     let empty_body = create_empty_body(layout, aliases, fields);
     let build_body = create_build_body(layout, aliases, fields);
+    let clone_body = create_clone_body(layout, fields);
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    if layout.read_only {
+        return create_read_only_index_impl(
+            name,
+            &fields[0],
+            key_bound,
+            value_bound,
+            search_body,
+            build_body,
+            clone_body,
+        );
+    }
+
+    if layout.is_versioned() {
+        return create_versioned_index_impl(name, layout, aliases, fields);
+    }
+
+    let base_address = layout.base.address_type();
+    let base = fields[0].clone();
+    let quick_descent = create_descent(layout, fields, false);
 
     let body = quote! {
-        impl<K: Key, V: Value> KVStore<K, V> for #name<K, V> {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Insert a key, skipping the descent from the top if it falls within the base node
+            /// remembered by `hint`. The hint is updated to the node the key was inserted into.
+            pub fn insert_with_hint(&mut self, key: K, value: V, hint: &SearchHint<#base_address>) -> Option<V> {
+                #hinted_insert_body
+            }
+        }
+
+        impl<K: #key_bound, V: #value_bound> KVStore<K, V> for #name<K, V> {
             fn search(&self, key: K) -> Option<V> {
                 #search_body
             }
@@ -65,12 +148,948 @@ pub fn create_index_impl(
                 #build_body
             }
         }
+
+        impl<K: #key_bound, V: #value_bound> QuickInsert<K> for #name<K, V> {
+            fn is_quick_insert(&self, key: &K) -> bool {
+                let key = *key;
+                #quick_descent
+                self.#base.absorbs(s1, &key)
+            }
+        }
+
+        // Only fully in-memory layouts are `Clone`, every component is backed by an arena
+        impl<K: #key_bound, V: #value_bound> Clone for #name<K, V> {
+            fn clone(&self) -> Self {
+                #clone_body
+            }
+        }
     };
 
     body
 }
 
-fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident]) -> TokenStream {
+/// A `read_only` index is only ever built, so it implements `IndexRead` and an inherent `build`
+/// instead of `KVStore`, which would require `insert`
+fn create_read_only_index_impl(
+    name: &Ident,
+    base: &Ident,
+    key_bound: TokenStream,
+    value_bound: TokenStream,
+    search_body: TokenStream,
+    build_body: TokenStream,
+    clone_body: TokenStream,
+) -> TokenStream {
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            pub fn build(iter: impl Iterator<Item = (K, V)>) -> Self {
+                #build_body
+            }
+        }
+
+        impl<K: #key_bound, V: #value_bound> IndexRead<K, V> for #name<K, V> {
+            fn try_search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                Ok({ #search_body })
+            }
+
+            fn len(&self) -> usize {
+                self.#base.len()
+            }
+        }
+
+        impl<K: #key_bound, V: #value_bound> Clone for #name<K, V> {
+            fn clone(&self) -> Self {
+                #clone_body
+            }
+        }
+    }
+}
+
+/// With MVCC versioning, the base layer stores a `VersionChain<V>` instead of `V`. The chains are
+/// borrowed from the base node they are stored in, lookups read them and writes to existing keys
+/// extend them in place, so only new keys go through the usual insert body.
+fn create_versioned_index_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    let get_body = create_search_body(layout, aliases, fields, quote! { get });
+    let get_mut_body = create_search_body(layout, aliases, fields, quote! { get_mut });
+    let insert_body = create_insert_body(layout, aliases, fields, false);
+    let empty_body = create_empty_body(layout, aliases, fields);
+    let build_body = create_build_body(layout, aliases, fields);
+    let clone_body = create_clone_body(layout, fields);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        impl<K: #key_bound, V: Value> #name<K, V> {
+            fn chain(&self, key: K) -> Option<&VersionChain<V>> {
+                #get_body
+            }
+
+            fn chain_mut(&mut self, key: K) -> Option<&mut VersionChain<V>> {
+                #get_mut_body
+            }
+
+            fn insert_raw(&mut self, key: K, value: VersionChain<V>) -> Option<VersionChain<V>> {
+                #insert_body
+            }
+        }
+
+        impl<K: #key_bound, V: Value> KVStore<K, V> for #name<K, V> {
+            fn search(&self, key: K) -> Option<V> {
+                self.chain(key)?.latest().cloned()
+            }
+
+            fn insert(&mut self, key: K, value: V) -> Option<V> {
+                self.version += 1;
+                let version = self.version;
+
+                match self.chain_mut(key) {
+                    Some(chain) => {
+                        let previous = chain.latest().cloned();
+                        chain.push(version, value);
+                        self.versioned_keys.insert(key);
+                        previous
+                    }
+                    None => {
+                        self.insert_raw(key, VersionChain::new(self.version, value));
+                        None
+                    }
+                }
+            }
+
+            fn empty() -> Self {
+                let version = 0;
+                #empty_body
+            }
+
+            // The initial entries are all written as a single version
+            fn build(iter: impl Iterator<Item = (K, V)>) -> Self {
+                let version = 1;
+                let iter = iter.map(|(key, value)| (key, VersionChain::new(version, value)));
+                #build_body
+            }
+        }
+
+        impl<K: #key_bound, V: Value> VersionedKVStore<K, V> for #name<K, V> {
+            fn version(&self) -> Version {
+                self.version
+            }
+
+            fn search_at(&self, key: K, version: Version) -> Option<V> {
+                self.chain(key)?.at(version).cloned()
+            }
+
+            fn gc(&mut self, before_version: Version) {
+                for key in ::core::mem::take(&mut self.versioned_keys) {
+                    if let Some(chain) = self.chain_mut(key) {
+                        chain.gc(before_version);
+
+                        if chain.len() > 1 {
+                            self.versioned_keys.insert(key);
+                        }
+                    }
+                }
+            }
+        }
+
+        impl<K: #key_bound, V: Value> Clone for #name<K, V> {
+            fn clone(&self) -> Self {
+                #clone_body
+            }
+        }
+    }
+}
+
+/// In-memory layouts without `versioning` whose base layer is a BTree can be walked with cursors,
+/// which only descend from the top to find the node they start in
+pub fn create_cursor_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if layout.is_persisted()
+        || layout.is_versioned()
+        || !matches!(layout.base, BaseComponent::BTree { .. })
+    {
+        return TokenStream::new();
+    }
+
+    let base = fields[0].clone();
+    let descent = create_descent(layout, fields, false);
+
+    // Writes through a cursor bypass `insert`, which keeps the reverse lookup up to date and
+    // records new keys in the filter. Scopes clear themselves through a cursor as well. Watchers
+    // are told of the values a cursor replaces or removes, but not of those changed in place
+    // through `value_mut`, so watched layouts keep every write but `cursor_mut`.
+    let bypassed = layout.read_only || layout.reverse_lookup || layout.filter.is_some();
+    let admit = match layout.max_memory {
+        Some(budget) => {
+            let budget = proc_macro2::Literal::usize_unsuffixed(budget as usize);
+
+            quote! {
+                |index: &Self| match index.memory_usage() {
+                    used if used > #budget => Err(CapacityExceeded { used, budget: #budget }),
+                    _ => Ok(()),
+                }
+            }
+        }
+        None => quote! { |_: &Self| Ok(()) },
+    };
+
+    let cursor_mut = match bypassed || layout.watch {
+        true => TokenStream::new(),
+        false => quote! {
+            /// A cursor at the first entry whose key is at least `key`, which can modify the index
+            pub fn cursor_mut(&mut self, key: K) -> CursorMut<'_, K, V, Self> {
+                CursorMut::new(self, &key)
+            }
+        },
+    };
+
+    let writes = if bypassed {
+        TokenStream::new()
+    } else {
+        quote! {
+            #cursor_mut
+
+            /// The entries of `tenant`, whose keys are stored with the tenant in their upper half,
+            /// so only integer keys can be scoped
+            pub fn scope(&mut self, tenant: K) -> Scope<'_, K, V, Self>
+            where
+                K: PrimInt,
+            {
+                Scope::new(self, tenant)
+            }
+
+            /// Write `new` to `key`, or remove `key` if `new` is `None`, only if the key holds
+            /// `expected`, where `None` expects the key to be absent. Otherwise nothing is written,
+            /// and the error hands back the value the key holds, so that optimistic writers can
+            /// detect a racing update and retry.
+            pub fn compare_and_swap(
+                &mut self,
+                key: K,
+                expected: Option<&V>,
+                new: Option<V>,
+            ) -> ::core::result::Result<(), CasError<V>>
+            where
+                V: PartialEq,
+            {
+                CursorMut::new(self, &key).compare_and_swap(key, expected, new)
+            }
+
+            /// Apply every write of `batch` in order, as one. The writes go to the index in place,
+            /// and are undone again if a put is refused for going over the `max_memory` budget, so
+            /// the index never keeps part of a batch.
+            pub fn apply_batch(
+                &mut self,
+                batch: WriteBatch<K, V>,
+            ) -> ::core::result::Result<(), BatchError<K>> {
+                apply_in_place(self, batch, #admit)
+            }
+        }
+    };
+
+    let notify = match layout.watch {
+        true => quote! {
+            fn replaced(&self, key: &K, previous: &V, value: &V) {
+                self.watchers.notify(key, value, Some(previous));
+            }
+
+            fn removed(&self, key: &K, previous: &V) {
+                self.watchers.notify_remove(key, previous);
+            }
+        },
+        false => TokenStream::new(),
+    };
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> CursorIndex<K, V> for #name<K, V> {
+            type Address = A0;
+            type Parent = A1;
+            type Base = C0<K, V>;
+
+            fn base(&self) -> &Self::Base {
+                &self.#base
+            }
+
+            fn base_mut(&mut self) -> &mut Self::Base {
+                &mut self.#base
+            }
+
+            fn locate(&self, key: &K) -> A0 {
+                let key = *key;
+                #descent
+                s1
+            }
+
+            fn node_counts(&self) -> Vec<(usize, usize)> {
+                Self::node_counts(self)
+            }
+
+            #notify
+        }
+
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// A cursor at the first entry whose key is at least `key`
+            pub fn cursor(&self, key: K) -> Cursor<'_, K, V, Self> {
+                Cursor::new(self, &key)
+            }
+
+            /// The entry with the smallest key, or `None` if the index is empty
+            pub fn first(&self) -> Option<(&K, &V)> {
+                Cursor::first(self).current()
+            }
+
+            /// The entry with the largest key, or `None` if the index is empty
+            pub fn last(&self) -> Option<(&K, &V)> {
+                Cursor::last(self).current()
+            }
+
+            /// A scan from the first entry whose key is at least `key`, which descends from the
+            /// top once and then hands out entries in batches with `next_n`
+            pub fn seek(&self, key: &K) -> ScanHandle<'_, K, V, Self> {
+                ScanHandle::new(self, key)
+            }
+
+            /// The keys present in both `self` and `other`, in key order
+            pub fn intersect_keys<'a>(&'a self, other: &'a Self) -> IntersectKeys<'a, K, V, V, Self, Self> {
+                IntersectKeys::new(self, other)
+            }
+
+            /// The keys present in either `self` or `other`, in key order and without duplicates
+            pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, K, V, V, Self, Self> {
+                Union::new(self, other)
+            }
+
+            #writes
+        }
+    }
+}
+
+/// `contains_key` and `get_key_value` descend like `search`, but borrow the value stored in the base
+/// layer instead of cloning it. Dense and packed bases don't store their keys, so they have no
+/// `get_key_value`.
+pub fn create_lookup_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if layout.is_persisted() {
+        return TokenStream::new();
+    }
+
+    let base = fields[0].clone();
+    let descent = create_descent(layout, fields, false);
+
+    // With MVCC versioning, the base layer stores the whole history of the key
+    let (found, value) = if layout.is_versioned() {
+        (
+            quote! { self.#base.get(s1, &key).and_then(|chain| chain.latest()).is_some() },
+            quote! { stored.latest()? },
+        )
+    } else {
+        (
+            quote! { self.#base.get(s1, &key).is_some() },
+            quote! { stored },
+        )
+    };
+
+    let get_key_value = match layout.base {
+        BaseComponent::BTree { .. } | BaseComponent::PGM { packed: false, .. } => quote! {
+            /// The entry stored at `key`, with both the key and the value borrowed from the index
+            pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+                let key = *key;
+                #descent
+                let (key, stored) = self.#base.get_entry(s1, &key)?;
+                Some((key, #value))
+            }
+        },
+        _ => TokenStream::new(),
+    };
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Whether `key` is present, without cloning its value
+            pub fn contains_key(&self, key: &K) -> bool {
+                let key = *key;
+                #descent
+                #found
+            }
+
+            #get_key_value
+        }
+    }
+}
+
+/// In-memory indexes convert from maps through `build`, a `BTreeMap` directly since it iterates in
+/// key order, and any other collection of entries, such as a `HashMap`, after sorting them. They
+/// also build from entries which are only checked to be sorted, or not checked at all.
+pub fn create_conversion_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if layout.is_persisted() {
+        return TokenStream::new();
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> From<BTreeMap<K, V>> for #name<K, V> {
+            fn from(map: BTreeMap<K, V>) -> Self {
+                Self::build(map.into_iter())
+            }
+        }
+
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Build an index over entries in any order, such as those of a `HashMap`, which are
+            /// sorted first. Of several entries with the same key, the last one is kept, as if
+            /// they had been inserted one by one.
+            pub fn from_unsorted(entries: impl IntoIterator<Item = (K, V)>) -> Self {
+                let mut entries: Vec<(K, V)> = entries.into_iter().collect();
+
+                // The sort is stable, so reversing first puts the last entry of every key first
+                entries.reverse();
+                entries.sort_by_key(|(key, _)| *key);
+                entries.dedup_by_key(|(key, _)| *key);
+
+                Self::build(entries.into_iter())
+            }
+
+            /// Build an index over entries sorted by key, failing if they aren't. Entries with the
+            /// same key are resolved as told by `duplicates`.
+            pub fn try_build(
+                entries: impl IntoIterator<Item = (K, V)>,
+                duplicates: Duplicates,
+            ) -> ::core::result::Result<Self, BuildError<K>> {
+                let mut error = None;
+                let index = Self::build(CheckedEntries::new(entries.into_iter(), duplicates, &mut error));
+
+                match error {
+                    Some(error) => Err(error),
+                    None => Ok(index),
+                }
+            }
+
+            /// Build an index over entries the caller guarantees are sorted by key without
+            /// duplicates, which is not checked. Entries out of order leave the index unable to
+            /// find some of its keys.
+            pub fn build_unchecked(entries: impl IntoIterator<Item = (K, V)>) -> Self {
+                Self::build(entries.into_iter())
+            }
+        }
+    }
+}
+
+/// In-memory indexes hold their nodes inline, until `snapshot` moves them behind a reference count
+/// shared with the snapshot, after which only the nodes modified are copied
+pub fn create_snapshot_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if layout.is_persisted() {
+        return TokenStream::new();
+    }
+
+    // Every layer below the top is a linked list of nodes which can be shared
+    let layers = &fields[..fields.len() - 1];
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// A copy of the index which shares every node with it, until either of them modifies
+            /// the node. Cheap enough to fork an index for a "what-if" experiment. The first
+            /// snapshot moves every node behind a reference count, which clones of either index
+            /// share as well.
+            pub fn snapshot(&mut self) -> Self {
+                #(self.#layers.share_nodes();)*
+                self.clone()
+            }
+
+            /// Number of nodes still shared with a snapshot or clone of the index
+            pub fn shared_nodes(&self) -> usize {
+                0 #(+ self.#layers.shared_count())*
+            }
+        }
+    }
+}
+
+/// In-memory indexes estimate the memory held by their layers, and with a `max_memory` budget also
+/// get an `insert_bounded` which refuses to grow the index past it
+pub fn create_memory_usage_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    fields: &[Ident],
+) -> TokenStream {
+    if layout.is_persisted() {
+        return TokenStream::new();
+    }
+
+    // The top is a small map over the nodes of the layer below, and isn't counted
+    let layers = &fields[..fields.len() - 1];
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    let insert_bounded = match layout.max_memory {
+        Some(budget) => {
+            let budget = proc_macro2::Literal::usize_unsuffixed(budget as usize);
+
+            quote! {
+                /// Insert a key unless the index already holds more than its `max_memory` budget,
+                /// in which case nothing is inserted. Overwrites never grow the index, so they
+                /// always succeed.
+                pub fn insert_bounded(&mut self, key: K, value: V) -> ::core::result::Result<(), CapacityExceeded> {
+                    let used = self.memory_usage();
+                    if used > #budget && KVStore::search(self, key).is_none() {
+                        return Err(CapacityExceeded { used, budget: #budget });
+                    }
+
+                    KVStore::insert(self, key, value);
+                    Ok(())
+                }
+            }
+        }
+        None => TokenStream::new(),
+    };
+
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Estimated bytes of memory held by every layer of the index below the top
+            pub fn memory_usage(&self) -> usize {
+                0 #(+ self.#layers.memory_usage())*
+            }
+
+            #insert_bounded
+        }
+    }
+}
+
+/// In-memory indexes can lay out the nodes of every layer in list order again with
+/// `compact_memory`. Layers are compacted bottom up, and every move is passed on to the addresses
+/// held by the layer above and to the parents held by the layer below.
+pub fn create_compact_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if layout.is_persisted() {
+        return TokenStream::new();
+    }
+
+    let top = fields[fields.len() - 1].clone();
+    let base = fields[0].clone();
+
+    let mut body = quote! {
+        let remap = self.#base.compact();
+    };
+
+    for index in 1..fields.len() - 1 {
+        let layer = fields[index].clone();
+        let below = fields[index - 1].clone();
+
+        body.extend(quote! {
+            self.#layer.remap_children(&remap);
+            let remap = self.#layer.compact();
+            self.#below.remap_parents(&remap);
+        });
+    }
+
+    body.extend(quote! {
+        self.#top.remap_children(&remap);
+    });
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Lay out the nodes of every layer next to each other in key order, which restores
+            /// the locality of scans after many inserts have split nodes. Every node moves, and a
+            /// `SearchHint` into the index misses until it's filled again.
+            pub fn compact_memory(&mut self) {
+                #body
+            }
+        }
+    }
+}
+
+/// Generate `rebuild` and `rebuild_layer`, which build the layers above the base again from the
+/// layer below, as `build` does. Rebuilding a layer replaces every node of it, so every layer above
+/// is rebuilt along with it.
+pub fn create_rebuild_impl(
+    name: &Ident,
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+) -> TokenStream {
+    if layout.is_persisted() || layout.read_only {
+        return TokenStream::new();
+    }
+
+    let top = fields.len() - 1;
+    let mut body = TokenStream::new();
+
+    for index in 1..fields.len() {
+        let alias = aliases[index].clone();
+        let layer = fields[index].clone();
+        let below = fields[index - 1].clone();
+
+        // A swappable top keeps the kind of top it was swapped to
+        let build = if index == top && layout.swappable_top {
+            quote! { self.#layer.rebuild(&mut self.#below); }
+        } else {
+            quote! { self.#layer = #alias::build(&mut self.#below); }
+        };
+
+        body.extend(quote! {
+            if layer <= #index {
+                #build
+            }
+        });
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Rebuild every internal layer and the top from the base layer, as if the index had
+            /// just been built from its entries, for instance after a bulk of inserts
+            pub fn rebuild(&mut self) {
+                self.rebuild_layer(1);
+            }
+
+            /// Rebuild the layer `layer`, counting up from the base layer at 0, from the layer
+            /// below it, along with every layer above it. Panics unless the layer is above the
+            /// base and at most the top.
+            pub fn rebuild_layer(&mut self, layer: usize) {
+                assert!(
+                    (1..=#top).contains(&layer),
+                    "layer {} is not between the first internal layer and the top",
+                    layer,
+                );
+
+                #body
+            }
+        }
+    }
+}
+
+/// With `reverse_lookup: true`, every insert also records the key under its value, so the keys
+/// holding a value can be found without a scan
+pub fn create_reverse_lookup_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if !layout.reverse_lookup {
+        return TokenStream::new();
+    }
+
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: Value + Ord> #name<K, V> {
+            /// Every key holding `value`, in ascending order
+            pub fn find_value(&self, value: &V) -> impl Iterator<Item = &K> + '_ {
+                self.reverse.find(value)
+            }
+        }
+    }
+}
+
+/// With `watch: true`, every write is reported to the streams subscribed through `watch`
+pub fn create_watch_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if !layout.watch {
+        return TokenStream::new();
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Subscribe to every write to a key in `range`, the stream ends once the index is
+            /// dropped. Up to `DEFAULT_WATCH_CAPACITY` changes are buffered, after which the
+            /// oldest are dropped, and reported as `Lagged`.
+            pub fn watch(&self, range: impl ::core::ops::RangeBounds<K>) -> WatchStream<K, V> {
+                self.watchers.watch(range)
+            }
+
+            /// Subscribe like `watch`, buffering up to `capacity` changes
+            pub fn watch_with_capacity(
+                &self,
+                range: impl ::core::ops::RangeBounds<K>,
+                capacity: usize,
+            ) -> WatchStream<K, V> {
+                self.watchers.watch_with_capacity(range, capacity)
+            }
+        }
+    }
+}
+
+/// With `fast_fences: H`, expose the table of cached base nodes
+pub fn create_fast_fences_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if layout.fast_fences.is_none() {
+        return TokenStream::new();
+    }
+
+    let base_address = layout.base.address_type();
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// The base nodes cached for the keys searched most often, along with how many
+            /// searches hit them
+            pub fn fast_fences(&self) -> &FastFences<#base_address> {
+                &self.fences
+            }
+        }
+    }
+}
+
+/// With `filter`, expose the filter answering lookups of absent keys
+pub fn create_filter_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if layout.filter.is_none() {
+        return TokenStream::new();
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// The filter checked before every search, which reports the memory it holds
+            pub fn filter(&self) -> &KeyFilter<K> {
+                &self.filter
+            }
+        }
+    }
+}
+
+/// With `stripes: N`, generate `into_striped`, which range partitions the index into a `Sharded`
+/// index of `N` stripes at the fences of its lowest internal layer
+pub fn create_striped_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let Some(stripes) = layout.stripes else {
+        return TokenStream::new();
+    };
+
+    let internal = fields[1].clone();
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Range partition the index at the fences of its lowest internal layer, so that none
+            /// of its nodes straddles two stripes. Each stripe is rebuilt as an index of its own
+            /// behind a `RwLock`, so writers to disjoint key ranges proceed in parallel, and
+            /// readers only wait on writers to their own stripe.
+            pub fn into_striped(self) -> Sharded<Self, K, V, #stripes>
+            where
+                Self: Send + Sync,
+                K: Send,
+                V: Send,
+            {
+                let fences: Vec<K> = self
+                    .#internal
+                    .range(::core::ops::Bound::Unbounded, ::core::ops::Bound::Unbounded)
+                    .filter_map(|(fence, _)| fence.into_key())
+                    .collect();
+
+                Sharded::striped(self, fences)
+            }
+        }
+    }
+}
+
+/// With `alloc: fixed`, generate the static buffer the arenas of the layers below the top allocate
+/// from, and `build_in`, which points it at a buffer provided by the caller before building
+pub fn create_fixed_alloc_impl(name: &Ident, layout: &HybridLayout) -> TokenStream {
+    if layout.alloc != AllocMode::Fixed {
+        return TokenStream::new();
+    }
+
+    let value_bound = super::value_bound(layout);
+    let key_bound = super::key_bound(layout);
+
+    quote! {
+        pub struct ArenaBuffer;
+
+        impl StaticBuffer for ArenaBuffer {
+            fn buffer() -> &'static FixedBuffer {
+                static BUFFER: FixedBuffer = FixedBuffer::new();
+                &BUFFER
+            }
+        }
+
+        impl<K: #key_bound, V: #value_bound> #name<K, V> {
+            /// Build the index with the nodes of its layers below the top in `buffer`, where they
+            /// stay for as long as the index lives. Every index of this layout shares the buffer,
+            /// so only one of them can be built at a time. If the nodes don't fit, the index is
+            /// dropped again and the buffer left unused.
+            pub fn build_in(
+                buffer: &'static mut [u8],
+                iter: impl Iterator<Item = (K, V)>,
+            ) -> ::core::result::Result<Self, CapacityError> {
+                let fixed = Self::buffer();
+                fixed.provide(buffer);
+
+                // Nodes which don't fit are put on the heap for the duration of the build, to
+                // find out how much memory was missing
+                fixed.set_spill(true);
+                let index = Self::build(iter);
+                fixed.set_spill(false);
+
+                match fixed.spilled() {
+                    0 => Ok(index),
+                    spilled => {
+                        let needed = fixed.used() + spilled;
+                        drop(index);
+
+                        Err(CapacityError {
+                            needed,
+                            available: fixed.capacity(),
+                        })
+                    }
+                }
+            }
+
+            /// The buffer the nodes of the layers below the top are allocated from
+            pub fn buffer() -> &'static FixedBuffer {
+                <ArenaBuffer as StaticBuffer>::buffer()
+            }
+        }
+    }
+}
+
+/// With `borrowed: true`, generate `NameRef<'a, K, V>`, which indexes an existing slice of entries
+/// instead of owning them. It wraps a `Name<K, usize>` storing the offset of every entry in the
+/// slice, so values are never copied.
+pub fn create_borrowed_impl(name: &Ident, layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
+    if !layout.borrowed {
+        return (TokenStream::new(), Vec::new());
+    }
+
+    let key_bound = super::key_bound(layout);
+    let ref_name = Ident::new(format!("{}Ref", name).as_str(), Span::call_site());
+
+    let search = if layout.read_only {
+        quote! { IndexRead::try_search(&self.index, key).ok().flatten() }
+    } else {
+        quote! { KVStore::search(&self.index, key) }
+    };
+
+    let body = quote! {
+        /// An index over a borrowed slice of entries, which only stores offsets into the slice
+        pub struct #ref_name<'a, K: #key_bound, V> {
+            pub index: #name<K, usize>,
+            entries: &'a [(K, V)],
+        }
+
+        impl<'a, K: #key_bound, V> #ref_name<'a, K, V> {
+            /// Index `entries`, which have to be sorted by key as for `build`
+            pub fn build(entries: &'a [(K, V)]) -> Self {
+                let offsets = entries.iter().enumerate().map(|(offset, (key, _))| (*key, offset));
+
+                Self {
+                    index: #name::build(offsets),
+                    entries,
+                }
+            }
+
+            pub fn search(&self, key: K) -> Option<&'a V> {
+                let offset = #search?;
+                Some(&self.entries[offset].1)
+            }
+
+            /// The indexed entries
+            pub fn entries(&self) -> &'a [(K, V)] {
+                self.entries
+            }
+
+            pub fn len(&self) -> usize {
+                self.entries.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.entries.is_empty()
+            }
+        }
+    };
+
+    (body, vec![ref_name])
+}
+
+pub fn create_handles_impl(name: &Ident, layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
+    if layout.values != ValueStorage::Handles {
+        return (TokenStream::new(), Vec::new());
+    }
+
+    let key_bound = super::key_bound(layout);
+    let handles_name = Ident::new(format!("{}Handles", name).as_str(), Span::call_site());
+
+    let body = quote! {
+        /// An index whose values live in a `ValueStore`, which only stores the handle of every value
+        pub struct #handles_name<'s, K: #key_bound, V> {
+            pub index: #name<K, ValueHandle>,
+            store: Box<dyn ValueStore<V> + 's>,
+        }
+
+        impl<'s, K: #key_bound, V> #handles_name<'s, K, V> {
+            pub fn new(store: impl ValueStore<V> + 's) -> Self {
+                Self {
+                    index: #name::empty(),
+                    store: Box::new(store),
+                }
+            }
+
+            /// Index `entries`, which have to be sorted by key as for `build`, putting every value
+            /// into `store`
+            pub fn build(entries: impl IntoIterator<Item = (K, V)>, store: impl ValueStore<V> + 's) -> Self {
+                let mut store = Box::new(store);
+                let handles: Vec<(K, ValueHandle)> = entries
+                    .into_iter()
+                    .map(|(key, value)| (key, store.put(value)))
+                    .collect();
+
+                Self {
+                    index: #name::build(handles.into_iter()),
+                    store,
+                }
+            }
+
+            pub fn search(&self, key: K) -> Option<&V> {
+                let handle = KVStore::search(&self.index, key)?;
+                self.store.get(handle)
+            }
+
+            /// Insert `value` under `key`, returning the value it replaced
+            pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+                let handle = self.store.put(value);
+                let previous = KVStore::insert(&mut self.index, key, handle)?;
+                self.store.take(previous)
+            }
+
+            pub fn store(&self) -> &dyn ValueStore<V> {
+                self.store.as_ref()
+            }
+        }
+    };
+
+    (body, vec![handles_name])
+}
+
+/// With `filter`, keys the filter rules out are answered without a descent. The base node is
+/// read with its `lookup` method, which clones the value with `search` or borrows it with `get`.
+fn create_search_body(
+    layout: &HybridLayout,
+    aliases: &[Ident],
+    fields: &[Ident],
+    lookup: TokenStream,
+) -> TokenStream {
+    let search_body = create_unfiltered_search_body(layout, aliases, fields, lookup);
+
+    if layout.filter.is_none() {
+        return search_body;
+    }
+
+    quote! {
+        if self.filter.may_contain(&key) {
+            #search_body
+        } else {
+            None
+        }
+    }
+}
+
+fn create_unfiltered_search_body(
+    layout: &HybridLayout,
+    _aliases: &[Ident],
+    fields: &[Ident],
+    lookup: TokenStream,
+) -> TokenStream {
     let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
         .rev()
         .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
@@ -78,6 +1097,32 @@ fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
 
     let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
     let mut search_body = TokenStream::new();
+    let top = layout.internal.len() + 1;
+
+    search_body.extend(trace::span("search"));
+
+    // A cached base node skips the descent, otherwise the node reached is cached for next time
+    if layout.fast_fences.is_some() {
+        let base = fields[0].clone();
+        let search = Ident::new("s0", Span::call_site());
+        let descent = create_descent(layout, fields, true);
+
+        search_body.extend(quote! {
+            let s1 = match self.fences.node_for(&self.#base, &key) {
+                Some(node) => node,
+                None => {
+                    #descent
+                    self.fences.remember(&key, s1);
+                    s1
+                }
+            };
+            let #search = self.#base.#lookup(s1, &key);
+        });
+        search_body.extend(trace::found(layout, &search));
+        search_body.extend(quote! { #search });
+
+        return search_body;
+    }
 
     // Top component
     let search = search_vars[0].clone();
@@ -85,6 +1130,7 @@ fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
     let next = component_vars[1].clone();
 
     search_body.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+    search_body.extend(trace::descend(layout, top, &search));
 
     // Internal components
     for index in 1..=layout.internal.len() {
@@ -95,6 +1141,8 @@ fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
 
         search_body
             .extend(quote! { let #search = self.#field.search(&self.#next, #prev_search, &key);});
+
+        search_body.extend(trace::descend(layout, top - index, &search));
     }
 
     // Base component
@@ -103,27 +1151,34 @@ fn create_search_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
     let prev_search = search_vars[index - 1].clone();
     let field = component_vars[index].clone();
 
-    search_body.extend(quote! { let #search = self.#field.search(#prev_search, &key);});
+    search_body.extend(quote! { let #search = self.#field.#lookup(#prev_search, &key);});
+    search_body.extend(trace::found(layout, &search));
     search_body.extend(quote! { #search });
 
     search_body
 }
 
-fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident]) -> TokenStream {
+/// Descend from the top to the base layer, binding the address of the base node covering `key` to
+/// `s1`. Only `traced` descents emit an event per layer.
+fn create_descent(layout: &HybridLayout, fields: &[Ident], traced: bool) -> TokenStream {
     let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
         .rev()
         .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
         .collect();
 
     let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
-    let mut insert_body = TokenStream::new();
+    let mut descent = TokenStream::new();
+    let top = layout.internal.len() + 1;
 
     // Top component
     let search = search_vars[0].clone();
     let field = component_vars[0].clone();
     let next = component_vars[1].clone();
 
-    insert_body.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+    descent.extend(quote! { let #search = self.#field.search(&self.#next, &key);});
+    if traced {
+        descent.extend(trace::descend(layout, top, &search));
+    }
 
     // Internal components
     for index in 1..=layout.internal.len() {
@@ -132,19 +1187,99 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
         let field = component_vars[index].clone();
         let next = component_vars[index + 1].clone();
 
-        insert_body
+        descent
             .extend(quote! { let #search = self.#field.search(&self.#next, #prev_search, &key);});
+
+        if traced {
+            descent.extend(trace::descend(layout, top - index, &search));
+        }
     }
 
+    descent
+}
+
+/// With `hinted`, the descent is skipped if the base node remembered by `hint` covers the key, and
+/// otherwise with `append_hint` if the last base node does
+fn create_insert_body(
+    layout: &HybridLayout,
+    _aliases: &[Ident],
+    fields: &[Ident],
+    hinted: bool,
+) -> TokenStream {
+    let search_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
+        .rev()
+        .map(|i| Ident::new(format!("s{}", i).as_str(), Span::call_site()))
+        .collect();
+
+    let component_vars: Vec<Ident> = fields.iter().cloned().rev().collect();
+    let mut insert_body = TokenStream::new();
+
+    insert_body.extend(trace::span("insert"));
+    if layout.filter.is_some() {
+        insert_body.extend(quote! { self.filter.insert(&key); });
+    }
+
+    let descent = create_descent(layout, fields, true);
+
     // Base component
     let index = layout.internal.len() + 1;
-    let search = search_vars[index].clone();
     let prev_search = search_vars[index - 1].clone();
     let field = component_vars[index].clone();
 
-    insert_body.extend(quote! { let #search = self.#field.search(#prev_search, &key);});
+    if hinted {
+        insert_body.extend(quote! {
+            let #prev_search = match hint.node_for(&self.#field, &key) {
+                Some(node) => node,
+                None => {
+                    #descent
+                    #prev_search
+                }
+            };
+            hint.set(#prev_search);
+        });
+    } else if layout.append_hint {
+        // Keys past the lower bound of the last base node are routed to it, so appends skip the
+        // descent, and only reach the layers above when the last node splits
+        insert_body.extend(quote! {
+            let #prev_search = {
+                let tail = self.#field.last();
+                if self.#field.lower_bound(tail).covers(&key) {
+                    tail
+                } else {
+                    #descent
+                    #prev_search
+                }
+            };
+        });
+    } else {
+        insert_body.extend(descent);
+    }
+
+    // The base component hands back the previous value of the key along with the insert
+    let result = Ident::new("result", Span::call_site());
+    if layout.reverse_lookup || layout.watch {
+        let watch = match layout.watch {
+            true => quote! { self.watchers.notify(&key, &value, #result.as_ref()); },
+            false => TokenStream::new(),
+        };
+        let reverse = match layout.reverse_lookup {
+            true => quote! { self.reverse.insert(key, value, #result.as_ref()); },
+            false => TokenStream::new(),
+        };
 
-    insert_body.extend(quote! { let result = s0; });
+        insert_body.extend(quote! {
+            let inserted = self.#field.insert(#prev_search, key, value.clone());
+            let #result = inserted.previous;
+            #watch
+            #reverse
+        });
+    } else {
+        insert_body.extend(quote! {
+            let inserted = self.#field.insert(#prev_search, key, value);
+            let #result = inserted.previous;
+        });
+    }
+    insert_body.extend(trace::found(layout, &result));
 
     // Insert stage
     let insert_vars: Vec<Ident> = (0..=layout.internal.len() + 1)
@@ -152,12 +1287,13 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
         .collect();
 
     let var = insert_vars[0].clone();
-    let field = fields[0].clone();
-    let search = search_vars[search_vars.len() - 2].clone();
+    let prop = Ident::new("x", Span::call_site());
+    let trace = trace::propagate(layout, 0, &prop);
 
     insert_body.extend(quote! {
         let #var;
-        if let Some(x) = self.#field.insert(#search, key, value) {
+        if let Some(x) = inserted.propagate {
+            #trace
             #var = x;
         } else {
             return result;
@@ -170,10 +1306,12 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
 
         let field = fields[index].clone();
         let prev_field = fields[index - 1].clone();
+        let trace = trace::propagate(layout, index, &prop);
 
         insert_body.extend(quote! {
             let #var;
             if let Some(x) = self.#field.insert(&mut self.#prev_field, #prev_var) {
+                #trace
                 #var = x;
             } else {
                 return result;
@@ -197,6 +1335,101 @@ fn create_insert_body(layout: &HybridLayout, _aliases: &[Ident], fields: &[Ident
     insert_body
 }
 
+fn create_clone_body(layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let versioning = versioning_fields(
+        layout,
+        quote! { self.version },
+        quote! { self.versioned_keys.clone() },
+    );
+
+    // Arena indices are preserved by the clone, so the addresses stored between components remain
+    // valid without any remapping
+    let reverse = reverse_field(layout, quote! { self.reverse.clone() });
+
+    // Clones start out without watchers, they only see the changes made to the original
+    let watch = watch_field(layout);
+    let fences = fences_field(layout, quote! { self.fences.clone() });
+    let filter = filter_field(layout, quote! { self.filter.clone() });
+
+    quote! {
+        Self {
+            #(#fields: self.#fields.clone(),)*
+            #versioning
+            #reverse
+            #watch
+            #fences
+            #filter
+        }
+    }
+}
+
+/// Initializers for the bookkeeping fields of versioned layouts
+fn versioning_fields(
+    layout: &HybridLayout,
+    version: TokenStream,
+    versioned_keys: TokenStream,
+) -> TokenStream {
+    if layout.is_versioned() {
+        quote! {
+            version: #version,
+            versioned_keys: #versioned_keys,
+        }
+    } else {
+        TokenStream::new()
+    }
+}
+
+/// Initializer for the reverse lookup of layouts with `reverse_lookup`
+fn reverse_field(layout: &HybridLayout, reverse: TokenStream) -> TokenStream {
+    if layout.reverse_lookup {
+        quote! { reverse: #reverse, }
+    } else {
+        TokenStream::new()
+    }
+}
+
+/// Initializer for the watchers of layouts with `watch`, which always start out empty
+fn watch_field(layout: &HybridLayout) -> TokenStream {
+    if layout.watch {
+        quote! { watchers: Watchers::new(), }
+    } else {
+        TokenStream::new()
+    }
+}
+
+/// A fresh table of cached base nodes
+fn fast_fences(layout: &HybridLayout) -> TokenStream {
+    let capacity = layout.fast_fences.unwrap_or_default();
+    quote! { FastFences::new(#capacity) }
+}
+
+/// Initializer for the cached base nodes of layouts with `fast_fences`
+fn fences_field(layout: &HybridLayout, fences: TokenStream) -> TokenStream {
+    if layout.fast_fences.is_some() {
+        quote! { fences: #fences, }
+    } else {
+        TokenStream::new()
+    }
+}
+
+/// Kind and false positive rate of the filter of layouts with `filter`
+fn filter_kind(layout: &HybridLayout) -> TokenStream {
+    match layout.filter {
+        Some(Filter::Bloom { fpr }) => quote! { FilterKind::Bloom, #fpr },
+        Some(Filter::Learned { fpr }) => quote! { FilterKind::Learned, #fpr },
+        None => TokenStream::new(),
+    }
+}
+
+/// Initializer for the filter of layouts with `filter`
+fn filter_field(layout: &HybridLayout, filter: TokenStream) -> TokenStream {
+    if layout.filter.is_some() {
+        quote! { filter: #filter, }
+    } else {
+        TokenStream::new()
+    }
+}
+
 fn create_empty_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident]) -> TokenStream {
     let mut empty_body = TokenStream::new();
 
@@ -228,9 +1461,20 @@ fn create_empty_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident])
         let mut #var = #alias::build(&mut #prev_var);
     });
 
+    let versioning = versioning_fields(layout, quote! { version }, quote! { Default::default() });
+    let reverse = reverse_field(layout, quote! { ReverseIndex::new() });
+    let watch = watch_field(layout);
+    let fences = fences_field(layout, fast_fences(layout));
+    let kind = filter_kind(layout);
+    let filter = filter_field(layout, quote! { KeyFilter::new(#kind) });
     empty_body.extend(quote! {
         Self {
             #(#fields,)*
+            #versioning
+            #reverse
+            #watch
+            #fences
+            #filter
         }
     });
 
@@ -244,6 +1488,23 @@ fn create_build_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident])
     let alias = aliases[0].clone();
     let var = fields[0].clone();
 
+    // The reverse lookup is filled in as the base layer consumes the entries
+    if layout.reverse_lookup {
+        build_body.extend(quote! {
+            let mut reverse = ReverseIndex::new();
+            let iter = iter.inspect(|(key, value)| reverse.insert(*key, value.clone(), None));
+        });
+    }
+
+    // The filter is trained on the keys as the base layer consumes them
+    if layout.filter.is_some() {
+        let kind = filter_kind(layout);
+        build_body.extend(quote! {
+            let mut filter = FilterBuilder::new(#kind);
+            let iter = iter.inspect(|(key, _)| filter.push(*key));
+        });
+    }
+
     build_body.extend(quote! {
         let mut #var = #alias::build(iter);
     });
@@ -268,9 +1529,19 @@ fn create_build_body(layout: &HybridLayout, aliases: &[Ident], fields: &[Ident])
         let mut #var = #alias::build(&mut #prev_var);
     });
 
+    let versioning = versioning_fields(layout, quote! { version }, quote! { Default::default() });
+    let reverse = reverse_field(layout, quote! { reverse });
+    let watch = watch_field(layout);
+    let fences = fences_field(layout, fast_fences(layout));
+    let filter = filter_field(layout, quote! { filter.finish() });
     build_body.extend(quote! {
         Self {
             #(#fields,)*
+            #versioning
+            #reverse
+            #watch
+            #fences
+            #filter
         }
     });
 
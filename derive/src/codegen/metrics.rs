@@ -0,0 +1,32 @@
+//! Latency histograms of persisted indexes, kept by their `StatsStore`. Without the `metrics`
+//! feature, every helper emits nothing.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+/// Time the rest of the generated body as an operation of kind `op`, a variant of `LatencyOp`
+pub fn timer(op: &str) -> TokenStream {
+    if !cfg!(feature = "metrics") {
+        return TokenStream::new();
+    }
+
+    let op = Ident::new(op, Span::call_site());
+    quote! {
+        let _latency = self.stats.time(LatencyOp::#op);
+    }
+}
+
+/// `latency_summary`, next to the `stats` of the index
+pub fn summary_impl() -> TokenStream {
+    if !cfg!(feature = "metrics") {
+        return TokenStream::new();
+    }
+
+    quote! {
+        /// Quantiles of the latencies of the searches, inserts and pages of range scans since
+        /// the index was opened, or its stats last reset
+        pub fn latency_summary(&self) -> LatencySummary {
+            self.stats.latency_summary()
+        }
+    }
+}
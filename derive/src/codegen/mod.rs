@@ -1,11 +1,23 @@
+use crate::component::{outer_path, outer_use, BaseComponent, InternalComponent, KeyTransform};
 use crate::HybridLayout;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 
+mod batch;
+mod budget;
 mod disk;
+mod explain;
+#[cfg(feature = "ffi")]
+mod ffi;
 mod memory;
+mod metrics;
+mod trace;
 
-pub fn create_implementation(name: Ident, layout: HybridLayout) -> proc_macro::TokenStream {
+pub fn create_implementation(
+    name: Ident,
+    layout: HybridLayout,
+    extern_c: bool,
+) -> TokenStream {
     let mod_name = proc_macro2::Ident::new(
         format!("__{}", name.to_string().to_lowercase()).as_str(),
         proc_macro2::Span::call_site(),
@@ -25,22 +37,118 @@ pub fn create_implementation(name: Ident, layout: HybridLayout) -> proc_macro::T
         memory::create_index_impl(&name, &layout, &alias, &index_fields)
     };
 
+    let report_impl = create_report_impl(&name, &layout, &index_fields);
+    let access_impl = create_access_impl(&name, &layout, &index_fields);
+    let explain_impl = explain::create_explain_impl(&name, &layout, &index_fields);
+    let batch_impl = batch::create_batch_impl(&name, &layout, &index_fields);
+    let budget_impl = budget::create_budget_impl(&name, &layout, &index_fields);
+
+    #[cfg(feature = "ffi")]
+    let (ffi_impl, ffi_exports) = if extern_c {
+        ffi::create_ffi_impl(&name, &layout)
+    } else {
+        (TokenStream::new(), Vec::new())
+    };
+
+    #[cfg(not(feature = "ffi"))]
+    let (ffi_impl, ffi_exports): (TokenStream, Vec<Ident>) = {
+        debug_assert!(
+            !extern_c,
+            "`extern` is rejected while parsing without the `ffi` feature"
+        );
+        (TokenStream::new(), Vec::new())
+    };
+
+    let (async_impl, async_exports) = create_async_alias(&name, &layout);
+    let (borrowed_impl, borrowed_exports) = memory::create_borrowed_impl(&name, &layout);
+    let (handles_impl, handles_exports) = memory::create_handles_impl(&name, &layout);
+    let cursor_impl = memory::create_cursor_impl(&name, &layout, &index_fields);
+    let lookup_impl = memory::create_lookup_impl(&name, &layout, &index_fields);
+    let conversion_impl = memory::create_conversion_impl(&name, &layout);
+    let snapshot_impl = memory::create_snapshot_impl(&name, &layout, &index_fields);
+    let reverse_lookup_impl = memory::create_reverse_lookup_impl(&name, &layout);
+    let watch_impl = memory::create_watch_impl(&name, &layout);
+    let fast_fences_impl = memory::create_fast_fences_impl(&name, &layout);
+    let filter_impl = memory::create_filter_impl(&name, &layout);
+    let striped_impl = memory::create_striped_impl(&name, &layout, &index_fields);
+    let fixed_alloc_impl = memory::create_fixed_alloc_impl(&name, &layout);
+    let memory_usage_impl = memory::create_memory_usage_impl(&name, &layout, &index_fields);
+    let compact_impl = memory::create_compact_impl(&name, &layout, &index_fields);
+    let rebuild_impl = memory::create_rebuild_impl(&name, &layout, &alias, &index_fields);
+    let swap_top_impl = create_swap_top_impl(&name, &layout, &index_fields);
+    let transform_impl = create_transform_impl(&layout);
+    let outer_paths = create_outer_paths(&layout);
+
     let mut implementation = proc_macro2::TokenStream::new();
+    // Only the index, and the items exported next to it, make up the stable surface of the macro.
+    // Everything else in the module is an implementation detail.
     implementation.extend(quote! {
+        #[doc(hidden)]
         pub mod #mod_name {
             use ::limousine_engine::private::*;
 
+            #outer_paths
+
+            #transform_impl
+
             #alias_body
 
             #index_body
 
             #index_impl
+
+            #report_impl
+
+            #access_impl
+
+            #explain_impl
+
+            #batch_impl
+
+            #budget_impl
+
+            #cursor_impl
+
+            #lookup_impl
+
+            #conversion_impl
+
+            #snapshot_impl
+
+            #reverse_lookup_impl
+
+            #watch_impl
+            #fast_fences_impl
+            #filter_impl
+
+            #striped_impl
+
+            #fixed_alloc_impl
+
+            #memory_usage_impl
+
+            #compact_impl
+            #rebuild_impl
+
+            #swap_top_impl
+
+            #ffi_impl
+
+            #async_impl
+
+            #borrowed_impl
+
+            #handles_impl
         }
 
         use #mod_name::#name;
+        #(use #mod_name::#ffi_exports;)*
+        #(use #mod_name::#async_exports;)*
+        #(use #mod_name::#borrowed_exports;)*
+        #(use #mod_name::#handles_exports;)*
     });
 
-    implementation.into()
+    implementation
 }
 
 fn create_type_aliases(layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
@@ -78,7 +186,20 @@ fn create_type_aliases(layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
     // Add body as the first component
     let parent_address_alias = address_alias[1].clone();
     let alias = type_alias[0].clone();
-    let body = layout.base.component_type(parent_address_alias);
+    let value = if layout.value_log_threshold().is_some() {
+        quote! { VLogValue<V> }
+    } else if layout.is_versioned() {
+        quote! { VersionChain<V> }
+    } else if layout.tombstones {
+        quote! { Entry<V> }
+    } else if layout.has_ttl() {
+        quote! { Expiring<V> }
+    } else {
+        quote! { V }
+    };
+    let body = layout
+        .base
+        .component_type(parent_address_alias, value, layout.alloc);
     type_alias_body.extend(quote::quote! {
         type #alias<K, V> = #body;
     });
@@ -90,7 +211,7 @@ fn create_type_aliases(layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
         let base_address_alias = address_alias[index - 1].clone();
         let parent_address_alias = address_alias[index + 1].clone();
 
-        let body = component.component_type(base_address_alias, parent_address_alias);
+        let body = component.component_type(base_address_alias, parent_address_alias, layout.alloc);
 
         let alias = type_alias[index].clone();
         type_alias_body.extend(quote! {
@@ -102,7 +223,12 @@ fn create_type_aliases(layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
     let index = layout.internal.len() + 1;
 
     let base_address_alias = address_alias[index - 1].clone();
-    let body = layout.top.component_type(base_address_alias);
+    let body = match layout.top.kind() {
+        Some(kind) if layout.swappable_top => {
+            quote! { SwappableTop<K, V, #base_address_alias, #kind> }
+        }
+        _ => layout.top.component_type(base_address_alias),
+    };
 
     let alias = type_alias[index].clone();
     type_alias_body.extend(quote! {
@@ -111,3 +237,291 @@ fn create_type_aliases(layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
 
     (type_alias_body, type_alias)
 }
+
+/// Bound on the keys of an index, which have to be integers unless every layer only compares them
+fn key_bound(layout: &HybridLayout) -> TokenStream {
+    if layout.is_ordered_only() {
+        quote! { Key }
+    } else {
+        quote! { Key + PrimInt }
+    }
+}
+
+/// Bound on the values of an in-memory index, which have to be ordered to keep a reverse lookup
+fn value_bound(layout: &HybridLayout) -> TokenStream {
+    if layout.reverse_lookup {
+        quote! { Value + Ord }
+    } else {
+        quote! { Value }
+    }
+}
+
+/// Implement `IndexRead` and `IndexWrite` by forwarding to the `KVStore` or `PersistedKVStore`
+/// implementation. `read_only` indexes implement `IndexRead` directly. The number of entries is
+/// kept by the base component, and is also exposed inherently so that `len` needs no trait import.
+fn create_access_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let value_bound = value_bound(layout);
+    let key_bound = key_bound(layout);
+    let base = fields[0].clone();
+
+    let bounds = if layout.is_persisted() {
+        quote! { K: Persisted + #key_bound, V: Persisted + Value }
+    } else {
+        quote! { K: #key_bound, V: #value_bound }
+    };
+
+    let len_impl = quote! {
+        impl<K, V> #name<K, V>
+        where
+            #bounds
+        {
+            /// Number of entries in the index, which is kept up to date rather than counted
+            pub fn len(&self) -> usize {
+                self.#base.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+        }
+    };
+
+    if layout.read_only {
+        return len_impl;
+    }
+
+    let (search_body, insert_body) = if layout.is_persisted() {
+        (
+            quote! { PersistedKVStore::search(self, key) },
+            quote! { PersistedKVStore::insert(self, key, value) },
+        )
+    } else {
+        (
+            quote! { Ok(KVStore::search(self, key)) },
+            quote! { Ok(KVStore::insert(self, key, value)) },
+        )
+    };
+
+    quote! {
+        #len_impl
+
+        impl<K, V> IndexRead<K, V> for #name<K, V>
+        where
+            #bounds
+        {
+            fn try_search(&self, key: K) -> limousine_engine::Result<Option<V>> {
+                #search_body
+            }
+
+            fn len(&self) -> usize {
+                self.#base.len()
+            }
+        }
+
+        impl<K, V> IndexWrite<K, V> for #name<K, V>
+        where
+            #bounds
+        {
+            fn try_put(&mut self, key: K, value: V) -> limousine_engine::Result<Option<V>> {
+                #insert_body
+            }
+        }
+    }
+}
+
+/// Custom models and transforms are written in the module invoking the macro, so they are
+/// re-exported into the generated module through an `outer` module, see `outer_use`
+fn create_outer_paths(layout: &HybridLayout) -> TokenStream {
+    let mut paths = Vec::new();
+
+    for component in &layout.internal {
+        if let InternalComponent::PGM {
+            model: Some(model), ..
+        } = component
+        {
+            paths.push(model);
+        }
+    }
+
+    if let BaseComponent::PGM {
+        model: Some(model), ..
+    } = &layout.base
+    {
+        paths.push(model);
+    }
+
+    if let KeyTransform::Custom(function) = &layout.transform {
+        paths.push(function);
+    }
+
+    if paths.is_empty() {
+        return TokenStream::new();
+    }
+
+    // The same model can be used by several components
+    let mut uses: Vec<TokenStream> = Vec::new();
+    for path in paths {
+        let re_export = outer_use(path);
+        if !uses.iter().any(|seen| seen.to_string() == re_export.to_string()) {
+            uses.push(re_export);
+        }
+    }
+
+    quote! {
+        mod outer {
+            #[allow(unused_imports)]
+            pub(super) use super::super::*;
+
+            #(#uses)*
+        }
+    }
+}
+
+/// A `custom(function)` key transform is wrapped in a type, since models are parametrized by it.
+/// The function is instantiated for the key type of the index, so it has to be generic over it.
+fn create_transform_impl(layout: &HybridLayout) -> TokenStream {
+    let KeyTransform::Custom(ref function) = layout.transform else {
+        return TokenStream::new();
+    };
+    let key_bound = key_bound(layout);
+
+    let function = outer_path(function);
+
+    quote! {
+        pub struct CustomKeyTransform;
+
+        impl<K: #key_bound> KeyTransform<K> for CustomKeyTransform {
+            fn apply(key: &K) -> K {
+                #function(*key)
+            }
+        }
+    }
+}
+
+/// With the `async` feature, alias the non-blocking facade over the index as `NameAsync`. Only
+/// plain in-memory layouts implement `QuickInsert`, and persisted layouts can't be sent to the
+/// executor.
+fn create_async_alias(name: &Ident, layout: &HybridLayout) -> (TokenStream, Vec<Ident>) {
+    if !cfg!(feature = "async")
+        || layout.is_persisted()
+        || layout.is_versioned()
+        || layout.read_only
+    {
+        return (TokenStream::new(), Vec::new());
+    }
+
+    let async_name = Ident::new(format!("{}Async", name).as_str(), Span::call_site());
+
+    let body = quote! {
+        /// Non-blocking facade over the index, inserts which may restructure it and operations
+        /// issued while it is busy run on the executor `E`
+        pub type #async_name<K, V, E = ThreadExecutor> = AsyncIndex<#name<K, V>, E>;
+    };
+
+    (body, vec![async_name])
+}
+
+/// With `swappable_top: true`, generate `swap_top`, which rebuilds the top as another kind of top
+/// from the layer below it
+fn create_swap_top_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    if !layout.swappable_top {
+        return TokenStream::new();
+    }
+
+    let top = fields[layout.internal.len() + 1].clone();
+    let below = fields[layout.internal.len()].clone();
+
+    let key_bound = key_bound(layout);
+    let bounds = if layout.is_persisted() {
+        quote! { K: Persisted + #key_bound, V: Persisted + Value }
+    } else {
+        let value_bound = value_bound(layout);
+        quote! { K: #key_bound, V: #value_bound }
+    };
+
+    quote! {
+        impl<K, V> #name<K, V>
+        where
+            #bounds
+        {
+            /// Rebuild the top as the kind of top `T`, such as `RMITop` once the key distribution
+            /// has stabilized. Only the top is rebuilt, from the layer below it.
+            pub fn swap_top<T: TopKind>(&mut self) {
+                self.#top.swap::<T, _>(&mut self.#below);
+            }
+
+            /// Name of the kind of top the index currently holds
+            pub fn top_kind(&self) -> &'static str {
+                self.#top.name()
+            }
+        }
+    }
+}
+
+/// Generate `layer_report` and `layer_plot`, which collect a `LayerReport` and a `LayerPlot` from
+/// every learned component, and `node_counts`, which counts the nodes of every layer
+fn create_report_impl(name: &Ident, layout: &HybridLayout, fields: &[Ident]) -> TokenStream {
+    let mut reports = Vec::new();
+    let mut plots = Vec::new();
+
+    if let BaseComponent::PGM { .. } = layout.base {
+        let field = fields[0].clone();
+        reports.push(quote! { LayerReport { layer: 0, ..self.#field.report() } });
+        plots.push(quote! { LayerPlot { layer: 0, ..self.#field.plot(range.clone()) } });
+    }
+
+    for (mut index, component) in layout.internal.iter().rev().enumerate() {
+        index += 1;
+
+        match component {
+            InternalComponent::PGM { .. } => {
+                let field = fields[index].clone();
+                reports.push(quote! { LayerReport { layer: #index, ..self.#field.report() } });
+                plots.push(quote! { LayerPlot { layer: #index, ..self.#field.plot(range.clone()) } });
+            }
+            // Reported whichever kind of layer it picked, along with the decision
+            InternalComponent::Auto => {
+                let field = fields[index].clone();
+                reports.push(quote! { LayerReport { layer: #index, ..self.#field.report() } });
+            }
+            _ => (),
+        }
+    }
+
+    // Every layer below the top is a list of nodes
+    let layers = &fields[..fields.len() - 1];
+    let indices = 0..layers.len();
+
+    let key_bound = key_bound(layout);
+    let bounds = if layout.is_persisted() {
+        quote! { K: Persisted + #key_bound, V: Persisted + Value }
+    } else {
+        let value_bound = value_bound(layout);
+        quote! { K: #key_bound, V: #value_bound }
+    };
+
+    quote! {
+        impl<K, V> #name<K, V>
+        where
+            #bounds
+        {
+            /// Segmentation statistics for every learned layer, ordered from the base layer up
+            pub fn layer_report(&self) -> Vec<LayerReport> {
+                vec![#(#reports),*]
+            }
+
+            /// Plots of the segments of every learned layer holding keys in `range`, ordered from
+            /// the base layer up
+            #[allow(unused_variables)]
+            pub fn layer_plot(&self, range: impl ::core::ops::RangeBounds<K> + Clone) -> Vec<LayerPlot> {
+                vec![#(#plots),*]
+            }
+
+            /// Number of nodes in every layer below the top as `(layer, nodes)`, ordered from the
+            /// base layer up
+            pub fn node_counts(&self) -> Vec<(usize, usize)> {
+                vec![#((#indices, self.#layers.node_count())),*]
+            }
+        }
+    }
+}
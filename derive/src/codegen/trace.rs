@@ -0,0 +1,78 @@
+//! `tracing` instrumentation of the generated search and insert bodies. Without the `trace`
+//! feature, every helper emits nothing.
+
+use crate::HybridLayout;
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+
+/// Name of the component at `layer`, counting up from the base layer
+pub fn component_name(layout: &HybridLayout, layer: usize) -> String {
+    let top = layout.internal.len() + 1;
+
+    match layer {
+        0 => layout.base.to_string(),
+        layer if layer == top && layout.swappable_top => "SwappableTop".to_string(),
+        layer if layer == top => layout.top.to_string(),
+        layer => layout.internal[top - 1 - layer].to_string(),
+    }
+}
+
+/// Open a span covering the rest of the generated body
+pub fn span(name: &str) -> TokenStream {
+    if !cfg!(feature = "trace") {
+        return TokenStream::new();
+    }
+
+    quote! {
+        let _span = ::limousine_engine::private::tracing::trace_span!(#name).entered();
+    }
+}
+
+/// The component at `layer` picked `node` in the layer below
+pub fn descend(layout: &HybridLayout, layer: usize, node: &Ident) -> TokenStream {
+    if !cfg!(feature = "trace") {
+        return TokenStream::new();
+    }
+
+    let component = component_name(layout, layer);
+    quote! {
+        ::limousine_engine::private::tracing::trace!(
+            layer = #layer,
+            component = #component,
+            node = ?#node,
+        );
+    }
+}
+
+/// The base layer was searched, `value` is the result
+pub fn found(layout: &HybridLayout, value: &Ident) -> TokenStream {
+    if !cfg!(feature = "trace") {
+        return TokenStream::new();
+    }
+
+    let component = component_name(layout, 0);
+    quote! {
+        ::limousine_engine::private::tracing::trace!(
+            layer = 0usize,
+            component = #component,
+            found = #value.is_some(),
+        );
+    }
+}
+
+/// An insert into the component at `layer` propagated `prop` to the layer above
+pub fn propagate(layout: &HybridLayout, layer: usize, prop: &Ident) -> TokenStream {
+    if !cfg!(feature = "trace") {
+        return TokenStream::new();
+    }
+
+    let component = component_name(layout, layer);
+    quote! {
+        ::limousine_engine::private::tracing::trace!(
+            layer = #layer,
+            component = #component,
+            split = matches!(#prop, PropagateInsert::Single(..)),
+            replace = matches!(#prop, PropagateInsert::Replace { .. }),
+        );
+    }
+}
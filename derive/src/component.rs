@@ -1,20 +1,49 @@
-use crate::bail;
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
 use std::collections::HashSet;
 use syn::{
     parenthesized,
     parse::{Parse, ParseStream},
-    Expr, Lit, LitInt, Token,
+    Expr, ExprLit, Lit, LitInt, Path, Token,
 };
 
 #[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Component {
-    BTreeTop,
-    BTree { fanout: usize, persist: bool },
-    PGM { epsilon: usize, },
+    BTreeTop {
+        max_entries: Option<usize>,
+    },
+    RMITop {
+        epsilon: Option<usize>,
+    },
+    ARTTop,
+    BTree {
+        fanout: usize,
+        persist: bool,
+        compression: Compression,
+        search: SearchStrategy,
+        split: SplitPolicy,
+        fences: Fences,
+    },
+    PGM {
+        epsilon: usize,
+        model: Option<Path>,
+        max_len: Option<usize>,
+        checked: bool,
+        packed: bool,
+    },
+    Bucket {
+        count: usize,
+    },
+    Dense {
+        page: Option<usize>,
+    },
+    Auto,
 }
 
+/// Consecutive keys a page of a dense base holds, unless set with its `page` attribute
+const DEFAULT_DENSE_PAGE: usize = 64;
+
 pub struct ParsedComponent {
     ident: Ident,
     component: Component,
@@ -37,6 +66,17 @@ impl ParsedComponent {
     pub fn ident(&self) -> &Ident {
         &self.ident
     }
+
+    pub fn has_truncated_fences(&self) -> bool {
+        match self.component {
+            Component::BTree { fences, .. } => fences != Fences::Full,
+            _ => false,
+        }
+    }
+
+    pub fn has_page_size(&self) -> bool {
+        matches!(self.component, Component::Dense { page: Some(_) })
+    }
 }
 
 impl Parse for ParsedComponent {
@@ -49,10 +89,36 @@ impl Parse for ParsedComponent {
         let mut attributes: Attributes = attributes.parse()?;
 
         let component = match ident.to_string().as_str() {
-            "btree_top" => Component::BTreeTop,
+            "btree_top" => {
+                let max_entries = match attributes.try_get_optional_integer("max_entries")? {
+                    Some(max_entries) if max_entries >= 2 => Some(max_entries as usize),
+                    Some(_) => {
+                        bail!(ident, "Specified max_entries is less than 2!");
+                    }
+                    None => None,
+                };
+
+                Component::BTreeTop { max_entries }
+            }
+            "rmi_top" => {
+                let epsilon = match attributes.try_get_optional_integer("epsilon")? {
+                    Some(epsilon) if epsilon > 0 => Some(epsilon as usize),
+                    Some(_) => {
+                        bail!(ident, "Specified epsilon is not positive!");
+                    }
+                    None => None,
+                };
+
+                Component::RMITop { epsilon }
+            }
+            "art_top" => Component::ARTTop,
             "btree" => {
                 let fanout = attributes.try_get_integer(&ident, "fanout")?;
                 let persist = attributes.try_get_bool("persist")?;
+                let compression = attributes.try_get_compression("compression")?;
+                let search = attributes.try_get_search("search")?;
+                let split = attributes.try_get_split("split")?;
+                let fences = attributes.try_get_fences("fences")?;
 
                 let fanout = if fanout >= 2 {
                     fanout as usize
@@ -60,19 +126,115 @@ impl Parse for ParsedComponent {
                     bail!(ident, "Specified fanout is less than 2!");
                 };
 
-                Component::BTree { fanout, persist }
+                if !persist && compression != Compression::None {
+                    bail!(ident, "Only persisted components can be compressed!");
+                }
+
+                if persist && search != SearchStrategy::Auto {
+                    bail!(
+                        ident,
+                        "Only in-memory components can pick a search strategy!"
+                    );
+                }
+
+                if persist && split != SplitPolicy::Even {
+                    bail!(ident, "Only in-memory components can pick a split policy!");
+                }
+
+                if persist && fences != Fences::Full {
+                    bail!(
+                        ident,
+                        "Only in-memory components can truncate their fences!"
+                    );
+                }
+
+                // Truncated fences are searched and split by a node of their own
+                if fences != Fences::Full
+                    && (search != SearchStrategy::Auto || split != SplitPolicy::Even)
+                {
+                    bail!(
+                        ident,
+                        "Components with truncated fences can't pick a search strategy or split policy!"
+                    );
+                }
+
+                Component::BTree {
+                    fanout,
+                    persist,
+                    compression,
+                    search,
+                    split,
+                    fences,
+                }
             }
             "pgm" => {
                 let epsilon = attributes.try_get_integer(&ident, "epsilon")?;
-                
+
                 let epsilon = if epsilon > 0 {
                     epsilon as usize
                 } else {
                     bail!(ident, "Specified epsilon is not positive");
                 };
 
-                Component::PGM { epsilon }
+                let model = attributes.try_get_path("model")?;
+
+                let max_len = match attributes.try_get_optional_integer("max_len")? {
+                    Some(max_len) if max_len > 0 => Some(max_len as usize),
+                    Some(_) => {
+                        bail!(ident, "Specified max_len is not positive!");
+                    }
+                    None => None,
+                };
+
+                let checked = attributes.try_get_bool("checked")?;
+                let packed = attributes.try_get_bool("packed")?;
+
+                // Segments are searched outwards from the position predicted by the model
+                if attributes.try_get_search("search")? != SearchStrategy::Auto {
+                    bail!(ident, "Only btree components can pick a search strategy!");
+                }
+
+                // Segments grow in place instead of splitting
+                if attributes.try_get_split("split")? != SplitPolicy::Even {
+                    bail!(ident, "Only btree components can pick a split policy!");
+                }
+
+                if attributes.try_get_fences("fences")? != Fences::Full {
+                    bail!(ident, "Only btree components can truncate their fences!");
+                }
+
+                Component::PGM {
+                    epsilon,
+                    model,
+                    max_len,
+                    checked,
+                    packed,
+                }
+            }
+            "bucket" => {
+                let count = attributes.try_get_integer(&ident, "count")?;
+
+                let count = if count > 0 {
+                    count as usize
+                } else {
+                    bail!(ident, "Specified count is not positive!");
+                };
+
+                Component::Bucket { count }
             }
+            "dense" => {
+                let page = match attributes.try_get_optional_integer("page")? {
+                    Some(page) if page > 0 => Some(page as usize),
+                    Some(_) => {
+                        bail!(ident, "Specified page is not positive!");
+                    }
+                    None => None,
+                };
+
+                Component::Dense { page }
+            }
+            // Built as a btree or a pgm, whichever suits the layer below
+            "auto" => Component::Auto,
             _ => {
                 bail!(ident, "Unknown component `{}`!", ident.to_string());
             }
@@ -82,24 +244,470 @@ impl Parse for ParsedComponent {
     }
 }
 
+/// Where the values of the index are stored, specified via the `values` field of the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ValueStorage {
+    #[default]
+    Inline,
+    ValueLog {
+        threshold: u64,
+    },
+    Handles,
+}
+
+impl Parse for ValueStorage {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        // Storages without attributes can leave out the parentheses
+        let mut attributes: Attributes = if input.peek(syn::token::Paren) {
+            let attributes;
+            parenthesized!(attributes in input);
+            attributes.parse()?
+        } else {
+            syn::parse2(TokenStream::new())?
+        };
+
+        match ident.to_string().as_str() {
+            "inline" => Ok(Self::Inline),
+            "handles" => Ok(Self::Handles),
+            "vlog" => {
+                let threshold = attributes.try_get_size(&ident, "threshold")?;
+                Ok(Self::ValueLog { threshold })
+            }
+            _ => {
+                bail!(ident, "Unknown value storage `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// Filter answering lookups of absent keys before the descent, specified via the `filter` field of
+/// the macro
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    Bloom { fpr: f64 },
+    Learned { fpr: f64 },
+}
+
+impl Parse for Filter {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        let attributes;
+        parenthesized!(attributes in input);
+        let mut attributes: Attributes = attributes.parse()?;
+
+        let fpr = attributes.try_get_float(&ident, "fpr")?;
+        if !(fpr > 0.0 && fpr < 1.0) {
+            bail!(ident, "The `fpr` of a filter has to be between 0 and 1!");
+        }
+
+        match ident.to_string().as_str() {
+            "bloom" => Ok(Self::Bloom { fpr }),
+            "learned" => Ok(Self::Learned { fpr }),
+            _ => {
+                bail!(ident, "Unknown filter `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// How the pages of a persisted component are compressed, specified via its `compression` attribute
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd {
+        level: i32,
+    },
+}
+
+impl Compression {
+    /// Default level of the `zstd` command line tool
+    const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+    fn try_from_expr(expr: &Expr) -> Option<syn::Result<Self>> {
+        match expr {
+            Expr::Path(path) => match path.path.get_ident()?.to_string().as_str() {
+                "none" => Some(Ok(Self::None)),
+                "lz4" => Some(Ok(Self::Lz4)),
+                "zstd" => Some(Ok(Self::Zstd {
+                    level: Self::DEFAULT_ZSTD_LEVEL,
+                })),
+                _ => None,
+            },
+            Expr::Call(call) => {
+                let Expr::Path(func) = call.func.as_ref() else {
+                    return None;
+                };
+
+                if !func.path.is_ident("zstd") || call.args.len() != 1 {
+                    return None;
+                }
+
+                let Some(Expr::Lit(ExprLit {
+                    lit: Lit::Int(lit), ..
+                })) = call.args.first()
+                else {
+                    return None;
+                };
+
+                Some(lit.base10_parse().and_then(|level| match level {
+                    1..=22 => Ok(Self::Zstd { level }),
+                    _ => Err(syn::Error::new_spanned(
+                        lit,
+                        "Zstd level must be between 1 and 22!",
+                    )),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Trailing generic argument selecting the page compression of a disk component
+    fn component_argument(&self) -> TokenStream {
+        match *self {
+            Self::None => TokenStream::new(),
+            Self::Lz4 => quote!(, Lz4),
+            Self::Zstd { level } => quote!(, Zstd<#level>),
+        }
+    }
+}
+
+/// How the keys within a node of an in-memory BTree component are searched, specified via its
+/// `search` attribute
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SearchStrategy {
+    /// Linear scans of nodes holding up to 1KB of keys, and binary searches of larger ones
+    #[default]
+    Auto,
+    Binary,
+    Linear,
+    Branchless,
+}
+
+impl SearchStrategy {
+    fn try_from_expr(expr: &Expr) -> Option<Self> {
+        let Expr::Path(path) = expr else {
+            return None;
+        };
+
+        match path.path.get_ident()?.to_string().as_str() {
+            "auto" => Some(Self::Auto),
+            "binary" => Some(Self::Binary),
+            "linear" => Some(Self::Linear),
+            "branchless" => Some(Self::Branchless),
+            _ => None,
+        }
+    }
+
+    /// Trailing generic arguments selecting the search and split policy of an in-memory BTree
+    /// component, and the allocator of its arena. Each comes after the one before, so the default
+    /// search and split policy have to be spelled out along with any later argument.
+    fn component_argument(&self, split: SplitPolicy, alloc: AllocMode) -> TokenStream {
+        let alloc = alloc.component_argument();
+        let search = match *self {
+            Self::Auto if split == SplitPolicy::Even && alloc.is_none() => {
+                return TokenStream::new()
+            }
+            Self::Auto => quote!(OptimalSearch),
+            Self::Binary => quote!(BinarySearch),
+            Self::Linear => quote!(LinearSearch),
+            Self::Branchless => quote!(BranchlessSearch),
+        };
+
+        let split = match split {
+            SplitPolicy::Even if alloc.is_none() => return quote!(, #search),
+            SplitPolicy::Even => quote!(EvenSplit),
+            SplitPolicy::LeanRight => quote!(LeanRightSplit),
+            SplitPolicy::Append => quote!(AppendSplit),
+        };
+
+        match alloc {
+            Some(alloc) => quote!(, #search, #split, #alloc),
+            None => quote!(, #search, #split),
+        }
+    }
+}
+
+/// Where full nodes of an in-memory BTree component are split, specified via its `split` attribute
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SplitPolicy {
+    /// In half
+    #[default]
+    Even,
+    /// Keeping nine tenths of the entries in the left node
+    LeanRight,
+    /// Keeping all but one entry when a key is appended past the end of the node
+    Append,
+}
+
+impl SplitPolicy {
+    fn try_from_expr(expr: &Expr) -> Option<Self> {
+        let Expr::Path(path) = expr else {
+            return None;
+        };
+
+        match path.path.get_ident()?.to_string().as_str() {
+            "even" => Some(Self::Even),
+            "lean_right" => Some(Self::LeanRight),
+            "append" => Some(Self::Append),
+            _ => None,
+        }
+    }
+}
+
+/// What an in-memory internal BTree component keeps of the keys routing to its children, specified
+/// via its `fences` attribute
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Fences {
+    /// The keys in full
+    #[default]
+    Full,
+    /// The leading bytes of every key, at most 8 of them
+    Truncated { bytes: usize },
+}
+
+impl Fences {
+    /// Widest fence which still fits in a `u64`
+    const MAX_TRUNCATED_BYTES: usize = 8;
+
+    fn try_from_expr(expr: &Expr) -> Option<syn::Result<Self>> {
+        match expr {
+            Expr::Path(path) => match path.path.get_ident()?.to_string().as_str() {
+                "full" => Some(Ok(Self::Full)),
+                _ => None,
+            },
+            Expr::Call(call) => {
+                let Expr::Path(func) = call.func.as_ref() else {
+                    return None;
+                };
+
+                if !func.path.is_ident("truncated") || call.args.len() != 1 {
+                    return None;
+                }
+
+                let Some(Expr::Lit(ExprLit {
+                    lit: Lit::Int(lit), ..
+                })) = call.args.first()
+                else {
+                    return None;
+                };
+
+                Some(lit.base10_parse().and_then(|bytes| match bytes {
+                    1..=Self::MAX_TRUNCATED_BYTES => Ok(Self::Truncated { bytes }),
+                    _ => Err(syn::Error::new_spanned(
+                        lit,
+                        "Truncated fences must be between 1 and 8 bytes!",
+                    )),
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether the index keeps older values around, specified via the `versioning` field of the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Versioning {
+    #[default]
+    None,
+    Mvcc,
+}
+
+impl Parse for Versioning {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "none" => Ok(Self::None),
+            "mvcc" => Ok(Self::Mvcc),
+            _ => {
+                bail!(ident, "Unknown versioning mode `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// Whether entries can expire, specified via the `ttl` field of the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Ttl {
+    #[default]
+    Disabled,
+    Enabled,
+}
+
+impl Parse for Ttl {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "disabled" => Ok(Self::Disabled),
+            "enabled" => Ok(Self::Enabled),
+            _ => {
+                bail!(ident, "Unknown ttl mode `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// Parse a size in bytes, such as `512`, `8B`, `64KB`, `16MB` or `1GB`
+pub fn parse_size(value: &LitInt) -> syn::Result<u64> {
+    let scale: u64 = match value.suffix() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        suffix => {
+            bail!(value, "Unknown size unit `{}`!", suffix);
+        }
+    };
+
+    Ok(value.base10_parse::<u64>()? * scale)
+}
+
+/// Who owns the `GlobalStore` of a persisted index, specified via the `storage` field of the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Storage {
+    /// Every index opens and owns its own store
+    #[default]
+    Owned,
+    /// The index can also be opened inside a store owned by the caller, shared with other indexes
+    External,
+}
+
+impl Parse for Storage {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "owned" => Ok(Self::Owned),
+            "external" => Ok(Self::External),
+            _ => {
+                bail!(ident, "Unknown storage mode `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// Where the in-memory layers of an index allocate their nodes, specified via the `alloc` field of
+/// the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AllocMode {
+    /// On the heap, through the global allocator
+    #[default]
+    Global,
+    /// From a byte buffer provided by the caller to `build_in`
+    Fixed,
+}
+
+impl AllocMode {
+    /// Trailing generic argument selecting the arena allocator of an in-memory BTree component
+    fn component_argument(&self) -> Option<TokenStream> {
+        match *self {
+            Self::Global => None,
+            Self::Fixed => Some(quote!(FixedAlloc<ArenaBuffer>)),
+        }
+    }
+}
+
+impl Parse for AllocMode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "global" => Ok(Self::Global),
+            "fixed" => Ok(Self::Fixed),
+            _ => {
+                bail!(ident, "Unknown allocation mode `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+/// How an owned store caches the pages read from disk, specified via the `cache_policy` field of
+/// the macro
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CachePolicy {
+    /// Every page read is cached, evicting the least recently used one
+    #[default]
+    Lru,
+    /// Pages read are only cached if they were accessed more often than the one they'd evict
+    TinyLfu,
+}
+
+impl Parse for CachePolicy {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "lru" => Ok(Self::Lru),
+            "tinylfu" => Ok(Self::TinyLfu),
+            _ => {
+                bail!(ident, "Unknown cache policy `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum TopComponent {
-    BTreeTop,
+    BTreeTop { max_entries: Option<usize> },
+    RMITop { epsilon: Option<usize> },
+    ARTTop,
+}
+
+impl std::fmt::Display for TopComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BTreeTop { .. } => write!(f, "BTreeTop"),
+            Self::RMITop { .. } => write!(f, "RMITop"),
+            Self::ARTTop => write!(f, "ARTTop"),
+        }
+    }
 }
 
 impl TopComponent {
     pub fn try_new(component: Component) -> Option<Self> {
         match component {
-            Component::BTreeTop => Some(Self::BTreeTop),
+            Component::BTreeTop { max_entries } => Some(Self::BTreeTop { max_entries }),
+            Component::RMITop { epsilon } => Some(Self::RMITop { epsilon }),
+            Component::ARTTop => Some(Self::ARTTop),
+            _ => None,
+        }
+    }
+
+    /// The `TopKind` a `SwappableTop` is built as, if this top has no parameters
+    pub fn kind(&self) -> Option<TokenStream> {
+        match *self {
+            TopComponent::BTreeTop { max_entries: None } => Some(quote! { BTreeTop }),
+            TopComponent::RMITop { epsilon: None } => Some(quote! { RMITop }),
             _ => None,
         }
     }
 
     pub fn component_type(&self, base_address: impl ToTokens) -> TokenStream {
         match *self {
-            TopComponent::BTreeTop => {
+            TopComponent::BTreeTop {
+                max_entries: Some(max_entries),
+            } => {
+                quote! { BTreeTopComponent<K, V, #base_address, #max_entries> }
+            }
+            TopComponent::BTreeTop { max_entries: None } => {
                 quote! { BTreeTopComponent<K, V, #base_address> }
             }
+            TopComponent::RMITop {
+                epsilon: Some(epsilon),
+            } => {
+                quote! { RMITopComponent<K, V, #base_address, #epsilon> }
+            }
+            TopComponent::RMITop { epsilon: None } => {
+                quote! { RMITopComponent<K, V, #base_address> }
+            }
+            TopComponent::ARTTop => quote! { ARTTopComponent<K, V, #base_address> },
         }
     }
 }
@@ -111,17 +719,79 @@ pub enum PersistType {
     DeepDisk,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum InternalComponent {
-    BTree { fanout: usize, persist: PersistType },
-    PGM {epsilon: usize},
+    BTree {
+        fanout: usize,
+        persist: PersistType,
+        compression: Compression,
+        search: SearchStrategy,
+        split: SplitPolicy,
+        fences: Fences,
+    },
+    PGM {
+        epsilon: usize,
+        model: Option<Path>,
+        transform: KeyTransform,
+        max_len: Option<usize>,
+        checked: bool,
+        packed: bool,
+    },
+    Bucket {
+        count: usize,
+    },
+    Dense,
+    Auto,
 }
 
-impl ToString for InternalComponent {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for InternalComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::BTree { fanout, persist } => format!("{persist:?}BTreeInternal{fanout:?}").to_string(),
-            Self::PGM {epsilon} => format!("PGMInternal{epsilon:?}").to_string(),
+            Self::BTree {
+                fanout,
+                persist,
+                compression,
+                fences,
+                ..
+            } => {
+                write!(f, "{persist:?}BTreeInternal{fanout:?}")?;
+                // Compressed pages can be decoded by every codec, so only whether the pages are
+                // compressed at all affects the on-disk format
+                if *compression != Compression::None {
+                    write!(f, "Compressed")?;
+                }
+                if let Fences::Truncated { bytes } = fences {
+                    write!(f, "Truncated{bytes:?}")?;
+                }
+                Ok(())
+            }
+            Self::PGM {
+                epsilon,
+                model,
+                max_len,
+                checked,
+                packed,
+                ..
+            } => {
+                write!(f, "PGMInternal{epsilon:?}")?;
+                if let Some(model) = model {
+                    write!(f, "{}", model.to_token_stream())?;
+                }
+                if let Some(max_len) = max_len {
+                    write!(f, "MaxLen{max_len:?}")?;
+                }
+                if *checked {
+                    write!(f, "Checked")?;
+                }
+                if *packed {
+                    write!(f, "Packed")?;
+                }
+                Ok(())
+            }
+            Self::Bucket { count } => write!(f, "BucketInternal{count:?}"),
+            Self::Dense => write!(f, "DenseInternal"),
+            Self::Auto => write!(f, "AutoInternal"),
         }
     }
 }
@@ -133,36 +803,76 @@ impl InternalComponent {
                 Component::BTree {
                     fanout,
                     persist: false,
+                    compression,
+                    search,
+                    split,
+                    fences,
                 },
                 false,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::InMemory,
+                compression,
+                search,
+                split,
+                fences,
             }),
             (
                 Component::BTree {
                     fanout,
                     persist: true,
+                    compression,
+                    search,
+                    split,
+                    fences,
                 },
                 false,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::BoundaryDisk,
+                compression,
+                search,
+                split,
+                fences,
             }),
             (
                 Component::BTree {
                     fanout,
                     persist: true,
+                    compression,
+                    search,
+                    split,
+                    fences,
                 },
                 true,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::DeepDisk,
+                compression,
+                search,
+                split,
+                fences,
             }),
             (
-                Component::PGM { epsilon },
-                _
-            ) => Some(Self::PGM { epsilon }),
+                Component::PGM {
+                    epsilon,
+                    model,
+                    max_len,
+                    checked,
+                    packed,
+                },
+                _,
+            ) => Some(Self::PGM {
+                epsilon,
+                model,
+                transform: KeyTransform::None,
+                max_len,
+                checked,
+                packed,
+            }),
+            (Component::Bucket { count }, _) => Some(Self::Bucket { count }),
+            (Component::Dense { page: None }, _) => Some(Self::Dense),
+            (Component::Auto, _) => Some(Self::Auto),
             _ => None,
         }
     }
@@ -171,70 +881,193 @@ impl InternalComponent {
         &self,
         base_address: impl ToTokens,
         parent_address: impl ToTokens,
+        alloc: AllocMode,
     ) -> TokenStream {
         match *self {
             InternalComponent::BTree {
                 fanout,
                 persist: PersistType::InMemory,
-            } => quote!(BTreeInternalComponent<K, V, #fanout, #base_address, #parent_address>)
-                .to_token_stream(),
+                fences: Fences::Truncated { bytes },
+                ..
+            } => {
+                quote!(TruncatedBTreeInternalComponent<K, V, #fanout, #bytes, #base_address, #parent_address>)
+                    .to_token_stream()
+            }
+
+            InternalComponent::BTree {
+                fanout,
+                persist: PersistType::InMemory,
+                search,
+                split,
+                ..
+            } => {
+                let search = search.component_argument(split, alloc);
+                quote!(BTreeInternalComponent<K, V, #fanout, #base_address, #parent_address #search>)
+                    .to_token_stream()
+            }
 
             InternalComponent::BTree {
                 fanout,
                 persist: PersistType::BoundaryDisk,
-            } => quote!(BoundaryDiskBTreeInternalComponent<K, V, #fanout, #base_address, #parent_address>)
-                .to_token_stream(),
-                
+                compression,
+                ..
+            } => {
+                let compression = compression.component_argument();
+                quote!(BoundaryDiskBTreeInternalComponent<K, V, #fanout, #base_address, #parent_address #compression>)
+                    .to_token_stream()
+            }
+
             InternalComponent::BTree {
                 fanout,
                 persist: PersistType::DeepDisk,
-            } => quote!(DeepDiskBTreeInternalComponent<K, V, #fanout, #base_address, #parent_address>)
-                .to_token_stream(),
-            
-            InternalComponent::PGM { epsilon } =>
-            quote!(PGMInternalComponent<K, V, #epsilon, #base_address, #parent_address>).to_token_stream(),
-        }
-    }
+                compression,
+                ..
+            } => {
+                let compression = compression.component_argument();
+                quote!(DeepDiskBTreeInternalComponent<K, V, #fanout, #base_address, #parent_address #compression>)
+                    .to_token_stream()
+            }
 
-    pub fn address_type(&self) -> TokenStream {
-        match *self {
-            InternalComponent::BTree { persist: PersistType::InMemory, .. } => {
-                quote!(BTreeInternalAddress).to_token_stream()
+            InternalComponent::PGM {
+                epsilon,
+                ref model,
+                ref transform,
+                max_len,
+                checked,
+                packed,
+            } => {
+                let model = model_type(model, epsilon, transform, max_len, checked);
+                let model = packed_argument(model, epsilon, packed);
+
+                quote!(PGMInternalComponent<K, V, #epsilon, #base_address, #parent_address #model>)
+                    .to_token_stream()
             }
 
-            InternalComponent::BTree { persist: PersistType::BoundaryDisk, .. } => {
-                quote!(BoundaryDiskBTreeInternalAddress).to_token_stream()
+            InternalComponent::Bucket { count } => {
+                quote!(BucketInternalComponent<K, V, #count, #base_address, #parent_address>)
+                    .to_token_stream()
             }
 
-            InternalComponent::BTree { persist: PersistType::DeepDisk, .. } => {
-                quote!(DeepDiskBTreeInternalAddress).to_token_stream()
+            InternalComponent::Dense => {
+                quote!(DenseInternalComponent<K, V, #base_address, #parent_address>)
+                    .to_token_stream()
             }
 
-            InternalComponent::PGM {..} => {
-                quote!(PGMInternalAddress).to_token_stream()
+            InternalComponent::Auto => {
+                quote!(AutoInternalComponent<K, V, #base_address, #parent_address>)
+                    .to_token_stream()
             }
         }
     }
 
+    pub fn address_type(&self) -> TokenStream {
+        match *self {
+            InternalComponent::BTree {
+                persist: PersistType::InMemory,
+                fences: Fences::Truncated { .. },
+                ..
+            } => quote!(TruncatedBTreeInternalAddress).to_token_stream(),
+
+            InternalComponent::BTree {
+                persist: PersistType::InMemory,
+                ..
+            } => quote!(BTreeInternalAddress).to_token_stream(),
+
+            InternalComponent::BTree {
+                persist: PersistType::BoundaryDisk,
+                ..
+            } => quote!(BoundaryDiskBTreeInternalAddress).to_token_stream(),
+
+            InternalComponent::BTree {
+                persist: PersistType::DeepDisk,
+                ..
+            } => quote!(DeepDiskBTreeInternalAddress).to_token_stream(),
+
+            InternalComponent::PGM { .. } => quote!(PGMInternalAddress).to_token_stream(),
+
+            InternalComponent::Bucket { .. } => quote!(BucketInternalAddress).to_token_stream(),
+
+            InternalComponent::Dense => quote!(DenseInternalAddress).to_token_stream(),
+
+            InternalComponent::Auto => quote!(AutoInternalAddress).to_token_stream(),
+        }
+    }
+
     pub fn is_persisted(&self) -> bool {
         match *self {
             InternalComponent::BTree { persist, .. } => persist != PersistType::InMemory,
-            InternalComponent::PGM {..} => false,
+            InternalComponent::PGM { .. }
+            | InternalComponent::Bucket { .. }
+            | InternalComponent::Dense
+            | InternalComponent::Auto => false,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum BaseComponent {
-    BTree { fanout: usize, persist: PersistType },
-    PGM {epsilon: usize},
+    BTree {
+        fanout: usize,
+        persist: PersistType,
+        compression: Compression,
+        search: SearchStrategy,
+        split: SplitPolicy,
+    },
+    PGM {
+        epsilon: usize,
+        model: Option<Path>,
+        transform: KeyTransform,
+        max_len: Option<usize>,
+        checked: bool,
+        packed: bool,
+    },
+    Dense {
+        page: usize,
+    },
 }
 
-impl ToString for BaseComponent {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for BaseComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::BTree { fanout, persist } => format!("{persist:?}BTreeBase{fanout:?}").to_string(),
-            Self::PGM {epsilon} => format!("PGMBase{epsilon:?}").to_string(),
+            Self::BTree {
+                fanout,
+                persist,
+                compression,
+                ..
+            } => {
+                write!(f, "{persist:?}BTreeBase{fanout:?}")?;
+                // Compressed pages can be decoded by every codec, so only whether the pages are
+                // compressed at all affects the on-disk format
+                if *compression != Compression::None {
+                    write!(f, "Compressed")?;
+                }
+                Ok(())
+            }
+            Self::PGM {
+                epsilon,
+                model,
+                max_len,
+                checked,
+                packed,
+                ..
+            } => {
+                write!(f, "PGMBase{epsilon:?}")?;
+                if let Some(model) = model {
+                    write!(f, "{}", model.to_token_stream())?;
+                }
+                if let Some(max_len) = max_len {
+                    write!(f, "MaxLen{max_len:?}")?;
+                }
+                if *checked {
+                    write!(f, "Checked")?;
+                }
+                if *packed {
+                    write!(f, "Packed")?;
+                }
+                Ok(())
+            }
+            Self::Dense { page } => write!(f, "DenseBase{page:?}"),
         }
     }
 }
@@ -246,59 +1079,136 @@ impl BaseComponent {
                 Component::BTree {
                     fanout,
                     persist: false,
+                    compression,
+                    search,
+                    split,
+                    ..
                 },
                 false,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::InMemory,
+                compression,
+                search,
+                split,
             }),
             (
                 Component::BTree {
                     fanout,
                     persist: true,
+                    compression,
+                    search,
+                    split,
+                    ..
                 },
                 false,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::BoundaryDisk,
+                compression,
+                search,
+                split,
             }),
             (
                 Component::BTree {
                     fanout,
                     persist: true,
+                    compression,
+                    search,
+                    split,
+                    ..
                 },
                 true,
             ) => Some(Self::BTree {
                 fanout,
                 persist: PersistType::DeepDisk,
+                compression,
+                search,
+                split,
+            }),
+            (
+                Component::PGM {
+                    epsilon,
+                    model,
+                    max_len,
+                    checked,
+                    packed,
+                },
+                _,
+            ) => Some(Self::PGM {
+                epsilon,
+                model,
+                transform: KeyTransform::None,
+                max_len,
+                checked,
+                packed,
+            }),
+            (Component::Dense { page }, _) => Some(Self::Dense {
+                page: page.unwrap_or(DEFAULT_DENSE_PAGE),
             }),
-            (Component::PGM {epsilon}, _) => Some(Self::PGM {epsilon}),
             _ => None,
         }
     }
 
-    pub fn component_type(&self, base_address: impl ToTokens) -> TokenStream {
+    pub fn component_type(
+        &self,
+        base_address: impl ToTokens,
+        value: impl ToTokens,
+        alloc: AllocMode,
+    ) -> TokenStream {
         match *self {
             BaseComponent::BTree {
                 fanout,
                 persist: PersistType::InMemory,
-            } => quote!(BTreeBaseComponent<K, V, #fanout, #base_address>).to_token_stream(),
+                search,
+                split,
+                ..
+            } => {
+                let search = search.component_argument(split, alloc);
+                quote!(BTreeBaseComponent<K, #value, #fanout, #base_address #search>)
+                    .to_token_stream()
+            }
 
             BaseComponent::BTree {
                 fanout,
                 persist: PersistType::BoundaryDisk,
-            } => quote!(BoundaryDiskBTreeBaseComponent<K, V, #fanout, #base_address>)
-                .to_token_stream(),
+                compression,
+                ..
+            } => {
+                let compression = compression.component_argument();
+                quote!(BoundaryDiskBTreeBaseComponent<K, #value, #fanout, #base_address #compression>)
+                    .to_token_stream()
+            }
 
             BaseComponent::BTree {
                 fanout,
                 persist: PersistType::DeepDisk,
-            } => quote!(DeepDiskBTreeBaseComponent<K, V, #fanout, #base_address>)
-                .to_token_stream(),
-            
+                compression,
+                ..
+            } => {
+                let compression = compression.component_argument();
+                quote!(DeepDiskBTreeBaseComponent<K, #value, #fanout, #base_address #compression>)
+                    .to_token_stream()
+            }
+
             BaseComponent::PGM {
-                epsilon
-            } => quote!(PGMBaseComponent<K, V, #epsilon, #base_address>).to_token_stream(),
+                epsilon,
+                ref model,
+                ref transform,
+                max_len,
+                checked,
+                packed,
+            } => {
+                let model = model_type(model, epsilon, transform, max_len, checked);
+                let model = packed_argument(model, epsilon, packed);
+
+                quote!(PGMBaseComponent<K, #value, #epsilon, #base_address #model>)
+                    .to_token_stream()
+            }
+
+            BaseComponent::Dense { page } => {
+                quote!(DenseBaseComponent<K, #value, #page, #base_address>).to_token_stream()
+            }
         }
     }
 
@@ -319,16 +1229,175 @@ impl BaseComponent {
                 ..
             } => quote!(DeepDiskBTreeBaseAddress).to_token_stream(),
 
-            BaseComponent::PGM {
-                ..
-            } => quote!(PGMBaseAddress).to_token_stream(),
+            BaseComponent::PGM { .. } => quote!(PGMBaseAddress).to_token_stream(),
+
+            BaseComponent::Dense { .. } => quote!(DenseBaseAddress).to_token_stream(),
         }
     }
 
     pub fn is_persisted(&self) -> bool {
         match *self {
             BaseComponent::BTree { persist, .. } => persist != PersistType::InMemory,
-            BaseComponent::PGM { .. } => false,
+            BaseComponent::PGM { .. } | BaseComponent::Dense { .. } => false,
+        }
+    }
+}
+
+/// Append `packed` to the model arguments of a PGM component. Packing comes after the model, so
+/// the default model has to be spelled out.
+fn packed_argument(model: TokenStream, epsilon: usize, packed: bool) -> TokenStream {
+    match (packed, model.is_empty()) {
+        (false, _) => model,
+        (true, true) => quote!(, LinearModel<K, #epsilon>, true),
+        (true, false) => quote!(#model, true),
+    }
+}
+
+/// Custom segmentation models are instantiated as `Model<K, EPSILON>`. Since the index is generated
+/// inside of a private module, relative paths are resolved from the module invoking the macro.
+fn model_type(
+    model: &Option<Path>,
+    epsilon: usize,
+    transform: &KeyTransform,
+    max_len: Option<usize>,
+    checked: bool,
+) -> TokenStream {
+    let mut inner = match model {
+        Some(model) => {
+            let model = outer_path(model);
+            quote!(#model<K, #epsilon>)
+        }
+        None => quote!(LinearModel<K, #epsilon>),
+    };
+
+    if let Some(transform) = transform.transform_type() {
+        inner = quote!(Transformed<K, #inner, #transform>);
+    }
+
+    // Capped runs are checked one by one, so the check sits below the cap
+    if checked {
+        inner = quote!(Checked<#inner>);
+    }
+
+    if let Some(max_len) = max_len {
+        inner = quote!(Capped<#inner, #max_len>);
+    }
+
+    // The components default to an unchecked, untransformed linear model
+    if model.is_none() && transform.transform_type().is_none() && max_len.is_none() && !checked {
+        return TokenStream::new();
+    }
+
+    quote!(, #inner)
+}
+
+/// Resolve a path written in the module invoking the macro from inside of the generated module,
+/// through its re-export from the `outer` module, see `outer_use`
+pub fn outer_path(path: &Path) -> TokenStream {
+    let alias = outer_alias(path);
+    quote!(outer::#alias)
+}
+
+/// Name a path is re-exported under from the `outer` module
+fn outer_alias(path: &Path) -> Ident {
+    let mut alias = String::from("Outer");
+    if path.leading_colon.is_some() {
+        alias += "_";
+    }
+
+    for segment in &path.segments {
+        alias += "_";
+        alias += &segment.ident.to_string();
+    }
+
+    Ident::new(&alias, Span::call_site())
+}
+
+/// Re-export `path` from the `outer` module of the generated module, which glob imports the
+/// module invoking the macro. A path then resolves just as it would where it was written, whether
+/// to an item of that module or to another crate, and only `self` and `super` have to be moved up
+/// the two modules in between.
+pub fn outer_use(path: &Path) -> TokenStream {
+    let alias = outer_alias(path);
+    let mut path = path.clone();
+
+    if path.leading_colon.is_none() {
+        let first = path
+            .segments
+            .first()
+            .map(|segment| segment.ident.to_string());
+
+        match first.as_deref() {
+            Some("self") => {
+                path.segments[0].ident = Ident::new("super", Span::call_site());
+                path = syn::parse_quote!(super::#path);
+            }
+            Some("super") => path = syn::parse_quote!(super::super::#path),
+            _ => {}
+        }
+    }
+
+    quote! {
+        pub(super) use #path as #alias;
+    }
+}
+
+/// Monotone transform applied to keys before they reach the models of learned components,
+/// specified via the `transform` field of the macro
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum KeyTransform {
+    #[default]
+    None,
+    Log,
+    Affine {
+        scale: i64,
+        offset: i64,
+    },
+    Custom(Path),
+}
+
+impl Parse for KeyTransform {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "none" => Ok(Self::None),
+            "log" => Ok(Self::Log),
+            "affine" => {
+                let attributes;
+                parenthesized!(attributes in input);
+
+                let mut attributes: Attributes = attributes.parse()?;
+                let scale = attributes.try_get_optional_i64("scale")?.unwrap_or(1);
+                let offset = attributes.try_get_optional_i64("offset")?.unwrap_or(0);
+
+                if scale <= 0 {
+                    bail!(ident, "Specified scale is not positive!");
+                }
+
+                Ok(Self::Affine { scale, offset })
+            }
+            "custom" => {
+                let function;
+                parenthesized!(function in input);
+
+                Ok(Self::Custom(function.parse()?))
+            }
+            _ => {
+                bail!(ident, "Unknown key transform `{}`!", ident.to_string());
+            }
+        }
+    }
+}
+
+impl KeyTransform {
+    /// Type implementing `KeyTransform` in the generated module, if keys are transformed at all
+    pub fn transform_type(&self) -> Option<TokenStream> {
+        match *self {
+            Self::None => None,
+            Self::Log => Some(quote!(LogTransform)),
+            Self::Affine { scale, offset } => Some(quote!(AffineTransform<#scale, #offset>)),
+            Self::Custom(_) => Some(quote!(CustomKeyTransform)),
         }
     }
 }
@@ -353,6 +1422,130 @@ impl Attributes {
         bail!(ident, "Could not find required attribute `{}`!", name);
     }
 
+    fn try_get_optional_integer(&mut self, name: &str) -> syn::Result<Option<i32>> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.try_get_integer() {
+                return value.base10_parse().map(Some);
+            }
+
+            bail!(attr.key(), "Failed to parse integer attribute `{}`!", name);
+        }
+
+        Ok(None)
+    }
+
+    fn try_get_optional_i64(&mut self, name: &str) -> syn::Result<Option<i64>> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.try_get_signed_integer() {
+                return Ok(Some(value?));
+            }
+
+            bail!(attr.key(), "Failed to parse integer attribute `{}`!", name);
+        }
+
+        Ok(None)
+    }
+
+    fn try_get_size(&mut self, ident: &Ident, name: &str) -> syn::Result<u64> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.try_get_integer() {
+                return parse_size(&value);
+            }
+
+            bail!(attr.key(), "Failed to parse size attribute `{}`!", name);
+        }
+
+        bail!(ident, "Could not find required attribute `{}`!", name);
+    }
+
+    fn try_get_float(&mut self, ident: &Ident, name: &str) -> syn::Result<f64> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.try_get_float() {
+                return value;
+            }
+
+            bail!(attr.key(), "Failed to parse float attribute `{}`!", name);
+        }
+
+        bail!(ident, "Could not find required attribute `{}`!", name);
+    }
+
+    fn try_get_path(&mut self, name: &str) -> syn::Result<Option<Path>> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.try_get_path() {
+                return Ok(Some(value));
+            }
+
+            bail!(attr.key(), "Failed to parse path attribute `{}`!", name);
+        }
+
+        Ok(None)
+    }
+
+    fn try_get_compression(&mut self, name: &str) -> syn::Result<Compression> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.value.as_ref().and_then(Compression::try_from_expr) {
+                return value;
+            }
+
+            bail!(
+                attr.key(),
+                "Failed to parse compression attribute `{}`, expected `none`, `lz4` or `zstd(level)`!",
+                name
+            );
+        }
+
+        Ok(Compression::None)
+    }
+
+    fn try_get_search(&mut self, name: &str) -> syn::Result<SearchStrategy> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.value.as_ref().and_then(SearchStrategy::try_from_expr) {
+                return Ok(value);
+            }
+
+            bail!(
+                attr.key(),
+                "Failed to parse search attribute `{}`, expected `auto`, `binary`, `linear` or `branchless`!",
+                name
+            );
+        }
+
+        Ok(SearchStrategy::Auto)
+    }
+
+    fn try_get_split(&mut self, name: &str) -> syn::Result<SplitPolicy> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.value.as_ref().and_then(SplitPolicy::try_from_expr) {
+                return Ok(value);
+            }
+
+            bail!(
+                attr.key(),
+                "Failed to parse split attribute `{}`, expected `even`, `lean_right` or `append`!",
+                name
+            );
+        }
+
+        Ok(SplitPolicy::Even)
+    }
+
+    fn try_get_fences(&mut self, name: &str) -> syn::Result<Fences> {
+        if let Some(attr) = self.attrs.take(name) {
+            if let Some(value) = attr.value.as_ref().and_then(Fences::try_from_expr) {
+                return value;
+            }
+
+            bail!(
+                attr.key(),
+                "Failed to parse fences attribute `{}`, expected `full` or `truncated(bytes)`!",
+                name
+            );
+        }
+
+        Ok(Fences::Full)
+    }
+
     fn try_get_bool(&mut self, name: &str) -> syn::Result<bool> {
         if let Some(attr) = self.attrs.take(name) {
             if let Some(value) = attr.try_get_bool() {
@@ -418,6 +1611,48 @@ impl Attribute {
         None
     }
 
+    // Try parsing the attribute as an integer, which may be negative
+    pub fn try_get_signed_integer(&self) -> Option<syn::Result<i64>> {
+        match self.value.as_ref()? {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(integer),
+                ..
+            }) => Some(integer.base10_parse()),
+            Expr::Unary(syn::ExprUnary {
+                op: syn::UnOp::Neg(_),
+                expr,
+                ..
+            }) => match expr.as_ref() {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(integer),
+                    ..
+                }) => Some(integer.base10_parse::<i64>().map(|value| -value)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Try parsing the attribute as a float
+    pub fn try_get_float(&self) -> Option<syn::Result<f64>> {
+        match self.value.as_ref()? {
+            Expr::Lit(ExprLit {
+                lit: Lit::Float(float),
+                ..
+            }) => Some(float.base10_parse()),
+            _ => None,
+        }
+    }
+
+    // Try parsing the attribute as a type path
+    pub fn try_get_path(&self) -> Option<Path> {
+        if let Some(Expr::Path(expr)) = self.value.clone() {
+            return Some(expr.path);
+        }
+
+        None
+    }
+
     // Try parsing the attribute as a boolean
     pub fn try_get_bool(&self) -> Option<bool> {
         if self.value.is_none() {
@@ -0,0 +1,156 @@
+//! Golden-file tests of the code generated by `create_kv_store!`. Every case is expanded and
+//! compared against `tests/expanded/<case><features>.rs`, so that any change to the generated
+//! surface shows up as a diff in review rather than silently reaching downstream users. The
+//! generated code depends on the enabled features, so every feature set gets its own golden files,
+//! suffixed with the enabled features. Run with `LIMOUSINE_BLESS=1` to rewrite the golden files
+//! after an intended change.
+
+use crate::{codegen, MacroInput};
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use std::path::PathBuf;
+
+const CASES: &[(&str, &str)] = &[
+    (
+        "btree_memory",
+        "name: BTreeIndex, layout: [btree_top(), btree(fanout = 8), btree(fanout = 32)]",
+    ),
+    (
+        "pgm_memory",
+        "name: PGMIndex, layout: [btree_top(), pgm(epsilon = 8), pgm(epsilon = 64, max_len = 4096)]",
+    ),
+    (
+        "read_only",
+        "name: ReadOnlyIndex, layout: [btree_top(), btree(fanout = 16)], read_only: true",
+    ),
+    (
+        "persisted",
+        "name: PersistedIndex, layout: [btree_top(), btree(fanout = 16), btree(fanout = 64, persist)]",
+    ),
+    (
+        "external_storage",
+        "name: SharedIndex, layout: [btree_top(), btree(fanout = 16, persist)], storage: external",
+    ),
+];
+
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("async", cfg!(feature = "async")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("metrics", cfg!(feature = "metrics")),
+        ("trace", cfg!(feature = "trace")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+fn golden_path(case: &str) -> PathBuf {
+    let features = enabled_features();
+    let suffix = if features.is_empty() {
+        String::new()
+    } else {
+        format!("@{}", features.join("+"))
+    };
+
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("expanded")
+        .join(format!("{case}{suffix}.rs"))
+}
+
+/// Expand the macro input `input`, with the generated code laid out one item per line
+fn expand(input: &str) -> String {
+    let input: MacroInput = syn::parse_str(input).expect("Failed to parse macro input!");
+    let tokens = codegen::create_implementation(input.name, input.layout, input.extern_c);
+
+    let mut out = String::new();
+    write_tokens(&mut out, tokens, 0, false);
+    out.push('\n');
+    out
+}
+
+/// A minimal, deterministic pretty printer, which breaks lines after every `;` and brace group so
+/// that diffs of the golden files stay readable. `braced` is whether `tokens` are directly inside
+/// of a brace group.
+fn write_tokens(out: &mut String, tokens: TokenStream, depth: usize, braced: bool) {
+    let mut joint = true;
+
+    // Open generic argument lists, whose commas don't break lines
+    let mut angles: usize = 0;
+    let mut arrow = false;
+
+    for token in tokens {
+        let line_start = out.is_empty() || out.ends_with('\n');
+        if line_start {
+            out.push_str(&"    ".repeat(depth));
+        } else if !joint {
+            out.push(' ');
+        }
+
+        joint = false;
+        match token {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                out.push_str("{\n");
+                write_tokens(out, group.stream(), depth + 1, true);
+
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&"    ".repeat(depth));
+                out.push_str("}\n");
+            }
+            TokenTree::Punct(punct) => {
+                let char = punct.as_char();
+                out.push(char);
+
+                match char {
+                    '<' => angles += 1,
+                    // The `>` of `->` and `=>` doesn't close a generic argument list
+                    '>' if !arrow => angles = angles.saturating_sub(1),
+                    _ => {}
+                }
+
+                // Statements, and the fields and arms of brace groups, each go on their own line
+                if char == ';' || (char == ',' && braced && angles == 0) {
+                    out.push('\n');
+                } else {
+                    joint = punct.spacing() == Spacing::Joint;
+                }
+
+                arrow = joint && (char == '-' || char == '=');
+                continue;
+            }
+            token => out.push_str(&token.to_string()),
+        }
+
+        arrow = false;
+    }
+}
+
+#[test]
+fn expanded_golden_files() {
+    let bless = std::env::var_os("LIMOUSINE_BLESS").is_some();
+    let mut mismatched = Vec::new();
+
+    for (case, input) in CASES {
+        let expanded = expand(input);
+        let path = golden_path(case);
+
+        if bless {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &expanded).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(golden) if golden == expanded => {}
+            _ => mismatched.push(path.display().to_string()),
+        }
+    }
+
+    assert!(
+        mismatched.is_empty(),
+        "Expanded code differs from the golden files, rerun with `LIMOUSINE_BLESS=1` if the change is intended: {mismatched:?}"
+    );
+}
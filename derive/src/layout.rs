@@ -1,5 +1,7 @@
-use crate::bail;
-use crate::component::{BaseComponent, InternalComponent, ParsedComponent, TopComponent};
+use crate::component::{
+    AllocMode, BaseComponent, CachePolicy, Fences, Filter, InternalComponent, KeyTransform,
+    ParsedComponent, PersistType, Storage, TopComponent, Ttl, ValueStorage, Versioning,
+};
 use syn::parse::Parse;
 use syn::Token;
 
@@ -8,9 +10,65 @@ pub struct HybridLayout {
     pub top: TopComponent,
     pub internal: Vec<InternalComponent>,
     pub base: BaseComponent,
+    pub values: ValueStorage,
+    pub versioning: Versioning,
+    pub storage: Storage,
+    pub cache_policy: CachePolicy,
+    pub deterministic: bool,
+    pub read_only: bool,
+    pub tombstones: bool,
+    pub ttl: Ttl,
+    pub borrowed: bool,
+    pub append_hint: bool,
+    pub reverse_lookup: bool,
+    pub watch: bool,
+    pub fast_fences: Option<usize>,
+    pub filter: Option<Filter>,
+    pub max_memory: Option<u64>,
+    pub stripes: Option<usize>,
+    pub alloc: AllocMode,
+    pub swappable_top: bool,
+    pub transform: KeyTransform,
 }
 
+/// Curated layouts which can be picked with `preset: name` instead of spelling out a `layout`
+const PRESETS: &[(&str, &str)] = &[
+    // Small learned internal layer over wide learned segments, for lookup-heavy workloads
+    (
+        "read_optimized",
+        "btree_top(), pgm(epsilon = 4), pgm(epsilon = 64)",
+    ),
+    // BTree nodes absorb inserts locally, and only split when they fill up
+    (
+        "write_optimized",
+        "btree_top(), btree(fanout = 32), btree(fanout = 64)",
+    ),
+    // Internal layers stay in memory, while both bottom layers are persisted
+    (
+        "disk_resident",
+        "btree_top(), btree(fanout = 64), btree(fanout = 64, persist), btree(fanout = 256, persist)",
+    ),
+    // Few, coarse segments over wide base nodes keep the index itself small
+    (
+        "memory_tight",
+        "btree_top(), pgm(epsilon = 128), btree(fanout = 256)",
+    ),
+];
+
 impl HybridLayout {
+    /// The layout of the preset `name`, in the syntax of `layout`
+    pub fn preset(name: &str) -> Option<&'static str> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, layout)| *layout)
+    }
+
+    /// Names of every preset, for error messages
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
     pub fn is_persisted(&self) -> bool {
         self.internal
             .iter()
@@ -18,6 +76,73 @@ impl HybridLayout {
             || self.base.is_persisted()
     }
 
+    pub fn value_log_threshold(&self) -> Option<u64> {
+        match self.values {
+            ValueStorage::ValueLog { threshold } => Some(threshold),
+            ValueStorage::Inline | ValueStorage::Handles => None,
+        }
+    }
+
+    pub fn is_versioned(&self) -> bool {
+        self.versioning == Versioning::Mvcc
+    }
+
+    pub fn has_ttl(&self) -> bool {
+        self.ttl == Ttl::Enabled
+    }
+
+    /// Whether the keys of the index only have to be ordered. In-memory BTree layers only compare
+    /// their keys, every other component and the filters compute with them.
+    pub fn is_ordered_only(&self) -> bool {
+        let internal = self.internal.iter().all(|component| {
+            matches!(
+                component,
+                InternalComponent::BTree {
+                    persist: PersistType::InMemory,
+                    fences: Fences::Full,
+                    ..
+                }
+            )
+        });
+
+        matches!(self.top, TopComponent::BTreeTop { .. })
+            && internal
+            && matches!(
+                self.base,
+                BaseComponent::BTree {
+                    persist: PersistType::InMemory,
+                    ..
+                }
+            )
+            && self.fast_fences.is_none()
+            && self.filter.is_none()
+            && !self.swappable_top
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.storage == Storage::External
+    }
+
+    /// Apply `transform` to the models of every learned component, returning false if there are none
+    pub fn set_transform(&mut self, transform: KeyTransform) -> bool {
+        let mut learned = false;
+
+        for component in self.internal.iter_mut() {
+            if let InternalComponent::PGM { transform: t, .. } = component {
+                *t = transform.clone();
+                learned = true;
+            }
+        }
+
+        if let BaseComponent::PGM { transform: t, .. } = &mut self.base {
+            *t = transform.clone();
+            learned = true;
+        }
+
+        self.transform = transform;
+        learned
+    }
+
     pub fn persist_checksum(&self) -> String {
         let mut feed = self.base.to_string();
         for component in self
@@ -28,6 +153,18 @@ impl HybridLayout {
             feed += &component.to_string();
         }
 
+        if let ValueStorage::ValueLog { .. } = self.values {
+            feed += "ValueLog";
+        }
+
+        if self.tombstones {
+            feed += "Tombstones";
+        }
+
+        if self.has_ttl() {
+            feed += "Ttl";
+        }
+
         use base64::prelude::*;
         BASE64_URL_SAFE.encode(md5::compute(feed).to_vec())
     }
@@ -70,6 +207,13 @@ impl Parse for HybridLayout {
                 );
             }
 
+            if parsed.has_page_size() {
+                bail!(
+                    parsed.ident(),
+                    "Only a dense base can pick a page size, a dense internal layer routes to the nodes below as they are!"
+                );
+            }
+
             let is_parent_persisted = parsed.is_persisted() && in_persisted_region;
             in_persisted_region |= parsed.is_persisted();
 
@@ -83,10 +227,16 @@ impl Parse for HybridLayout {
         }
 
         // Parse base components
+        let last = components.last().unwrap();
+        if last.has_truncated_fences() {
+            bail!(
+                last.ident(),
+                "Only internal components can truncate their fences, the base holds the full keys!"
+            );
+        }
+
         let base;
-        if let Some(base_component) =
-            BaseComponent::try_new(components.last().unwrap().into(), in_persisted_region)
-        {
+        if let Some(base_component) = BaseComponent::try_new(last.into(), in_persisted_region) {
             base = base_component;
         } else {
             bail!("Invalid base component type!")
@@ -96,6 +246,25 @@ impl Parse for HybridLayout {
             top,
             internal,
             base,
+            values: ValueStorage::Inline,
+            versioning: Versioning::None,
+            storage: Storage::Owned,
+            cache_policy: CachePolicy::Lru,
+            deterministic: false,
+            read_only: false,
+            tombstones: false,
+            ttl: Ttl::Disabled,
+            borrowed: false,
+            append_hint: false,
+            reverse_lookup: false,
+            watch: false,
+            fast_fences: None,
+            filter: None,
+            max_memory: None,
+            stripes: None,
+            alloc: AllocMode::Global,
+            swappable_top: false,
+            transform: KeyTransform::None,
         })
     }
 }
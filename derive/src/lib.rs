@@ -1,14 +1,171 @@
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::{format_ident, quote};
 use syn::bracketed;
+use syn::ext::IdentExt;
 use syn::parse::Parse;
 use syn::parse_macro_input;
-use syn::{LitStr, Token};
+use syn::{LitBool, LitInt, LitStr, Token};
 
+/// Generate a hybrid index named `name` from a `layout`, a stack of components listed from the top
+/// layer down to the base layer.
+///
+/// ```ignore
+/// create_kv_store! {
+///     name: ExampleStore,
+///     layout: [
+///         btree_top(),
+///         pgm(epsilon = 8),
+///         btree(fanout = 32),
+///         btree(fanout = 64, persist),
+///     ]
+/// }
+/// ```
+///
+/// # Components
+///
+/// - `btree_top()`: a small in-memory BTree, which `max_entries = 1024` caps by building a new layer
+///   beneath it whenever it grows past that many entries.
+/// - `rmi_top()`: a two stage recursive model index, with an optional `epsilon`, retrained whenever
+///   it doubles in size.
+/// - `art_top()`: an adaptive radix tree over the bytes of the keys.
+/// - `btree(fanout = 32)`: a layer of BTree nodes, kept on disk with `persist`. In-memory nodes take
+///   `search = auto | binary | linear | branchless` and `split = even | lean_right | append`,
+///   internal ones `fences = truncated(8)` to route by the leading bytes of every key, and persisted
+///   ones `compression = lz4 | zstd(3)`.
+/// - `pgm(epsilon = 8)`: a layer of learned segments. `model = MyModel` replaces the `LinearModel`
+///   with any `SegmentationModel` generic over `<K, const EPSILON: usize>`, `max_len = 4096` splits
+///   longer segments, `checked` splits segments until every key is within its window, and `packed`
+///   bit-packs the keys of built segments.
+/// - `bucket(count = 64)`: equi-depth buckets found by a binary search over their boundaries.
+/// - `dense()`: for dense integer keys, pages of `dense(page = 64)` consecutive keys as the base, or a
+///   table of children indexed by key as an internal layer.
+/// - `auto()`: an internal layer built as a `btree`, a `pgm` or a `dense` table, whichever is
+///   estimated to route a lookup in the fewest comparisons over the layer below.
+///
+/// Components can be guarded by `#[cfg(...)]`. The macro emits a copy of itself for every combination
+/// of the predicates, so at most six distinct predicates can be used per invocation.
+///
+/// # Options
+///
+/// - `preset: read_optimized | write_optimized | disk_resident | memory_tight` replaces the layout
+///   with a curated one, and `path: "layout.txt"` reads it from a file.
+/// - `extends: OtherStore, prepend: [...]` takes the layout of an index created earlier in the same
+///   module, or an enclosing one, with the layers of `prepend` inserted right below its top.
+/// - `values: handles` keeps values in a `ValueStore` outside of the index, and
+///   `values: vlog(threshold = 1KB)` moves large persisted values into a value log.
+/// - `versioning: mvcc` keeps the history of every key, and implements `VersionedKVStore`.
+/// - `read_only: true` drops `insert`, so the index only implements `IndexRead`.
+/// - `tombstones: true` and `ttl: enabled` support deletes and expiring entries in persisted layouts.
+/// - `storage: external` opens persisted indexes inside a `GlobalStore` shared with others, and
+///   `cache_policy: tinylfu` and `deterministic: true` tune how their pages are cached and written.
+/// - `borrowed: true`, `append_hint: true`, `reverse_lookup: true` and `watch: true` add in-memory
+///   companions, an append fast path, a value to key map and change subscriptions.
+/// - `fast_fences: 64` caches the base node reached by recent searches, and
+///   `filter: bloom(fpr = 0.01) | learned(fpr = 0.01)` answers most absent keys before the descent.
+/// - `max_memory: 64MB` bounds `insert_bounded` and `apply_batch` by `memory_usage()`.
+/// - `stripes: 16` generates `into_striped`, which range partitions the index at the fences of its
+///   lowest internal layer into 16 stripes, each behind a `RwLock` of its own.
+/// - `alloc: fixed` generates `build_in`, which builds the index with the nodes of its layers below
+///   the top in a byte buffer provided by the caller, and fails with a `CapacityError` if it is too
+///   small. The top stays on the heap, and inserts which outgrow the buffer panic.
+/// - `swappable_top: true` generates `swap_top`, which replaces the top at runtime.
+/// - `transform: log | affine(scale = 4, offset = -100) | custom(my_fn)` maps keys through a monotone
+///   function before the learned layers see them.
+/// - `extern: true` generates C bindings, with the `ffi` feature.
 #[proc_macro]
 pub fn create_kv_store(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    // A layout extending another is completed by the macro the other index left behind, which
+    // invokes this one again with the layout filled in
+    let tokens = TokenStream::from(input.clone());
+    if let Some(parent) = deferred_parent(&tokens) {
+        let layout_macro = layout_macro(&parent);
+        return quote! { #layout_macro! { #tokens } }.into();
+    }
+
+    // Components guarded by `#[cfg(...)]` are resolved by the compiler, which only keeps one of
+    // the invocations made for every combination of the predicates
+    if let Some(invocations) = cfg::expand_guarded(&tokens) {
+        return invocations
+            .unwrap_or_else(syn::Error::into_compile_error)
+            .into();
+    }
+
     let input = parse_macro_input!(input as MacroInput);
+    let layout_macro = layout_macro(&input.name);
+    let layout_tokens = input.layout_tokens;
+    let implementation = codegen::create_implementation(input.name, input.layout, input.extern_c);
+
+    quote! {
+        #implementation
+
+        #[doc(hidden)]
+        #[allow(unused_macros)]
+        macro_rules! #layout_macro {
+            ($($input:tt)*) => {
+                ::limousine_engine::prelude::create_kv_store! { __extends_layout: [#layout_tokens], $($input)* }
+            };
+        }
+    }
+    .into()
+}
 
-    codegen::create_implementation(input.name, input.layout)
+/// Name of the `macro_rules!` an index leaves behind, through which other layouts extend its own
+fn layout_macro(name: &Ident) -> Ident {
+    format_ident!("__limousine_layout_{}", name)
+}
+
+/// The index named by `extends`, if the layout it extends hasn't been filled in yet
+fn deferred_parent(input: &TokenStream) -> Option<Ident> {
+    let tokens: Vec<TokenTree> = input.clone().into_iter().collect();
+    let mut parent = None;
+
+    for window in tokens.windows(3) {
+        match window {
+            [TokenTree::Ident(field), TokenTree::Punct(colon), TokenTree::Ident(name)]
+                if field == "extends" && colon.as_char() == ':' =>
+            {
+                parent = Some(name.clone());
+            }
+            [TokenTree::Ident(field), ..] if field == "__extends_layout" => return None,
+            _ => {}
+        }
+    }
+
+    parent
+}
+
+/// Insert the components of `prepend` right below the top component of `layout`
+fn prepend_components(layout: TokenStream, prepend: TokenStream) -> TokenStream {
+    fn components(stream: TokenStream) -> Vec<TokenStream> {
+        let mut components = vec![TokenStream::new()];
+        for token in stream {
+            match token {
+                TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                    components.push(TokenStream::new())
+                }
+                token => components.last_mut().unwrap().extend([token]),
+            }
+        }
+
+        components.retain(|component| !component.is_empty());
+        components
+    }
+
+    let mut layout = components(layout);
+    let at = layout.len().min(1);
+    layout.splice(at..at, components(prepend));
+
+    quote! { #(#layout),* }
+}
+
+/// Implement `Projectable` for a struct, see `limousine_core::projection`
+#[proc_macro_derive(Projectable)]
+pub fn derive_projectable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    projection::derive_projectable(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
 
 macro_rules! bail {
@@ -26,17 +183,27 @@ macro_rules! bail {
     };
 }
 
-pub(crate) use bail;
-
+mod cfg;
 mod codegen;
 mod component;
+#[cfg(test)]
+mod golden;
 mod layout;
+mod projection;
 
+use component::{
+    parse_size, AllocMode, CachePolicy, Filter, KeyTransform, Storage, Ttl, ValueStorage,
+    Versioning,
+};
 use layout::HybridLayout;
 
 struct MacroInput {
     name: Ident,
     layout: HybridLayout,
+    extern_c: bool,
+
+    /// The components of `layout`, in the syntax of `layout`, for layouts extending this one
+    layout_tokens: TokenStream,
 }
 
 impl Parse for MacroInput {
@@ -44,10 +211,35 @@ impl Parse for MacroInput {
         let mut name = None;
         let mut path = None;
         let mut layout = None;
+        let mut preset = None;
+        let mut extends = None;
+        let mut prepend = None;
+        let mut extends_layout = None;
+        let mut values = None;
+        let mut versioning = None;
+        let mut storage = None;
+        let mut cache_policy = None;
+        let mut deterministic = None;
+        let mut read_only = None;
+        let mut tombstones = None;
+        let mut ttl = None;
+        let mut borrowed = None;
+        let mut append_hint = None;
+        let mut reverse_lookup = None;
+        let mut watch = None;
+        let mut fast_fences = None;
+        let mut filter = None;
+        let mut max_memory = None;
+        let mut stripes = None;
+        let mut alloc = None;
+        let mut swappable_top = None;
+        let mut transform = None;
+        let mut extern_c = None;
 
         // Parse the fields of the input struct
         while !input.is_empty() {
-            let field_ident = input.parse::<Ident>()?;
+            // `extern` is a keyword, so it has to be parsed as a raw identifier
+            let field_ident = input.call(Ident::parse_any)?;
             input.parse::<Token![:]>()?;
             let field = field_ident.to_string();
 
@@ -79,6 +271,198 @@ impl Parse for MacroInput {
                     let layout_stream: TokenStream = layout_buffer.parse()?;
                     layout = Some(layout_stream);
                 }
+                "preset" => {
+                    if preset.is_some() {
+                        bail!(field_ident, "`preset` is already defined!");
+                    }
+
+                    let preset_ident = input.parse::<Ident>()?;
+                    let Some(contents) = HybridLayout::preset(&preset_ident.to_string()) else {
+                        bail!(
+                            preset_ident,
+                            "No preset named `{}`, expected one of: {}!",
+                            preset_ident,
+                            HybridLayout::preset_names().join(", ")
+                        );
+                    };
+
+                    preset = Some(syn::parse_str::<TokenStream>(contents)?);
+                }
+                "extends" => {
+                    if extends.is_some() {
+                        bail!(field_ident, "`extends` is already defined!");
+                    }
+
+                    // The index extended was already resolved by `deferred_parent`
+                    input.parse::<Ident>()?;
+                    extends = Some(field_ident.clone());
+                }
+                "prepend" => {
+                    if prepend.is_some() {
+                        bail!(field_ident, "`prepend` is already defined!");
+                    }
+
+                    let prepend_buffer;
+                    bracketed!(prepend_buffer in input);
+                    prepend = Some((field_ident.clone(), prepend_buffer.parse::<TokenStream>()?));
+                }
+                // Filled in by the macro an index leaves behind for the layouts extending it
+                "__extends_layout" => {
+                    let extends_buffer;
+                    bracketed!(extends_buffer in input);
+                    extends_layout = Some(extends_buffer.parse::<TokenStream>()?);
+                }
+                "values" => {
+                    if values.is_some() {
+                        bail!(field_ident, "`values` is already defined!");
+                    }
+
+                    values = Some((field_ident.clone(), input.parse::<ValueStorage>()?));
+                }
+                "versioning" => {
+                    if versioning.is_some() {
+                        bail!(field_ident, "`versioning` is already defined!");
+                    }
+
+                    versioning = Some((field_ident.clone(), input.parse::<Versioning>()?));
+                }
+                "storage" => {
+                    if storage.is_some() {
+                        bail!(field_ident, "`storage` is already defined!");
+                    }
+
+                    storage = Some((field_ident.clone(), input.parse::<Storage>()?));
+                }
+                "cache_policy" => {
+                    if cache_policy.is_some() {
+                        bail!(field_ident, "`cache_policy` is already defined!");
+                    }
+
+                    cache_policy = Some((field_ident.clone(), input.parse::<CachePolicy>()?));
+                }
+                "deterministic" => {
+                    if deterministic.is_some() {
+                        bail!(field_ident, "`deterministic` is already defined!");
+                    }
+
+                    deterministic = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "read_only" => {
+                    if read_only.is_some() {
+                        bail!(field_ident, "`read_only` is already defined!");
+                    }
+
+                    read_only = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "tombstones" => {
+                    if tombstones.is_some() {
+                        bail!(field_ident, "`tombstones` is already defined!");
+                    }
+
+                    tombstones = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "ttl" => {
+                    if ttl.is_some() {
+                        bail!(field_ident, "`ttl` is already defined!");
+                    }
+
+                    ttl = Some((field_ident.clone(), input.parse::<Ttl>()?));
+                }
+                "borrowed" => {
+                    if borrowed.is_some() {
+                        bail!(field_ident, "`borrowed` is already defined!");
+                    }
+
+                    borrowed = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "append_hint" => {
+                    if append_hint.is_some() {
+                        bail!(field_ident, "`append_hint` is already defined!");
+                    }
+
+                    append_hint = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "reverse_lookup" => {
+                    if reverse_lookup.is_some() {
+                        bail!(field_ident, "`reverse_lookup` is already defined!");
+                    }
+
+                    reverse_lookup = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "watch" => {
+                    if watch.is_some() {
+                        bail!(field_ident, "`watch` is already defined!");
+                    }
+
+                    watch = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "fast_fences" => {
+                    if fast_fences.is_some() {
+                        bail!(field_ident, "`fast_fences` is already defined!");
+                    }
+
+                    let slots = input.parse::<LitInt>()?;
+                    fast_fences = Some((field_ident.clone(), slots.base10_parse::<usize>()?));
+                }
+                "filter" => {
+                    if filter.is_some() {
+                        bail!(field_ident, "`filter` is already defined!");
+                    }
+
+                    filter = Some((field_ident.clone(), input.parse::<Filter>()?));
+                }
+                "max_memory" => {
+                    if max_memory.is_some() {
+                        bail!(field_ident, "`max_memory` is already defined!");
+                    }
+
+                    let size = input.parse::<LitInt>()?;
+                    max_memory = Some((field_ident.clone(), parse_size(&size)?));
+                }
+                "stripes" => {
+                    if stripes.is_some() {
+                        bail!(field_ident, "`stripes` is already defined!");
+                    }
+
+                    let count = input.parse::<LitInt>()?;
+                    stripes = Some((field_ident.clone(), count.base10_parse::<usize>()?));
+                }
+                "alloc" => {
+                    if alloc.is_some() {
+                        bail!(field_ident, "`alloc` is already defined!");
+                    }
+
+                    alloc = Some((field_ident.clone(), input.parse::<AllocMode>()?));
+                }
+                "swappable_top" => {
+                    if swappable_top.is_some() {
+                        bail!(field_ident, "`swappable_top` is already defined!");
+                    }
+
+                    swappable_top = Some((field_ident.clone(), input.parse::<LitBool>()?.value));
+                }
+                "transform" => {
+                    if transform.is_some() {
+                        bail!(field_ident, "`transform` is already defined!");
+                    }
+
+                    transform = Some((field_ident.clone(), input.parse::<KeyTransform>()?));
+                }
+                "extern" => {
+                    if extern_c.is_some() {
+                        bail!(field_ident, "`extern` is already defined!");
+                    }
+
+                    let value = input.parse::<LitBool>()?.value;
+                    if value && !cfg!(feature = "ffi") {
+                        bail!(
+                            field_ident,
+                            "`extern` requires the `ffi` feature of `limousine_engine`!"
+                        );
+                    }
+
+                    extern_c = Some(value);
+                }
                 field => {
                     bail!(field_ident, "No rule to process field `{}`!", field);
                 }
@@ -89,6 +473,13 @@ impl Parse for MacroInput {
                 bail!(field_ident, "Cannot have both `layout` and `path` fields!");
             }
 
+            if preset.is_some() && (path.is_some() || layout.is_some()) {
+                bail!(
+                    field_ident,
+                    "Cannot have a `preset` along with a `layout` or `path` field!"
+                );
+            }
+
             if let Some(ref path) = path {
                 let file_contents = std::fs::read_to_string(path.value());
 
@@ -113,11 +504,34 @@ impl Parse for MacroInput {
             }
         }
 
-        let layout_stream: proc_macro::TokenStream;
-        if let Some(layout) = layout {
-            layout_stream = layout.into();
+        if let Some(extends_ident) = &extends {
+            if layout.is_some() || preset.is_some() {
+                bail!(
+                    extends_ident,
+                    "Cannot have `extends` along with a `layout`, `path` or `preset` field!"
+                );
+            }
+
+            let Some(extends_layout) = extends_layout else {
+                bail!(extends_ident, "The layout to extend was never filled in!");
+            };
+
+            layout = Some(match prepend {
+                Some((_, prepend)) => prepend_components(extends_layout, prepend),
+                None => extends_layout,
+            });
+        } else if let Some((prepend_ident, _)) = prepend {
+            bail!(
+                prepend_ident,
+                "`prepend` can only be used along with `extends`!"
+            );
+        }
+
+        let layout_stream: TokenStream;
+        if let Some(layout) = layout.or(preset) {
+            layout_stream = layout;
         } else {
-            bail!("No `layout` or `path` specified!");
+            bail!("No `layout`, `path`, `preset` or `extends` specified!");
         }
 
         let name_ident;
@@ -127,11 +541,320 @@ impl Parse for MacroInput {
             bail!("No `name` specified!")
         }
 
-        let layout = syn::parse(layout_stream)?;
+        let mut layout: HybridLayout = syn::parse2(layout_stream.clone())?;
+
+        if let Some((values_ident, values)) = values {
+            if let ValueStorage::ValueLog { .. } = values {
+                if !layout.is_persisted() {
+                    bail!(
+                        values_ident,
+                        "A value log can only be used with a persisted layout!"
+                    );
+                }
+            }
+
+            if values == ValueStorage::Handles && layout.is_persisted() {
+                bail!(
+                    values_ident,
+                    "Value handles can only be used with an in-memory layout!"
+                );
+            }
+
+            layout.values = values;
+        }
+
+        if let Some((versioning_ident, versioning)) = versioning {
+            if versioning != Versioning::None && layout.is_persisted() {
+                bail!(
+                    versioning_ident,
+                    "MVCC versioning can only be used with an in-memory layout!"
+                );
+            }
+
+            if versioning != Versioning::None && layout.values == ValueStorage::Handles {
+                bail!(
+                    versioning_ident,
+                    "MVCC versioning cannot be combined with value handles!"
+                );
+            }
+
+            layout.versioning = versioning;
+        }
+
+        if let Some((storage_ident, storage)) = storage {
+            if storage != Storage::Owned && !layout.is_persisted() {
+                bail!(
+                    storage_ident,
+                    "An external store can only be used with a persisted layout!"
+                );
+            }
+
+            layout.storage = storage;
+        }
+
+        if let Some((cache_policy_ident, cache_policy)) = cache_policy {
+            if !layout.is_persisted() {
+                bail!(
+                    cache_policy_ident,
+                    "A cache policy only applies to the pages of a persisted layout!"
+                );
+            }
+
+            if layout.is_external() {
+                bail!(
+                    cache_policy_ident,
+                    "An external store is cached as set up by its owner, with `GlobalStore::with_cache_policy`!"
+                );
+            }
+
+            layout.cache_policy = cache_policy;
+        }
+
+        if let Some((transform_ident, transform)) = transform {
+            if transform != KeyTransform::None && !layout.set_transform(transform) {
+                bail!(
+                    transform_ident,
+                    "A key transform only applies to learned components, and the layout has none!"
+                );
+            }
+        }
+
+        if let Some((tombstones_ident, true)) = tombstones {
+            if !layout.is_persisted() {
+                bail!(
+                    tombstones_ident,
+                    "Tombstones can only be used with a persisted layout!"
+                );
+            }
+
+            if layout.values != ValueStorage::Inline {
+                bail!(
+                    tombstones_ident,
+                    "Tombstones cannot be combined with a value log!"
+                );
+            }
+
+            layout.tombstones = true;
+        }
+
+        if let Some((ttl_ident, ttl)) = ttl {
+            if ttl != Ttl::Disabled && !layout.is_persisted() {
+                bail!(
+                    ttl_ident,
+                    "A `ttl` can only be used with a persisted layout!"
+                );
+            }
+
+            if ttl != Ttl::Disabled && (layout.values != ValueStorage::Inline || layout.tombstones)
+            {
+                bail!(
+                    ttl_ident,
+                    "A `ttl` cannot be combined with a value log or `tombstones`!"
+                );
+            }
+
+            layout.ttl = ttl;
+        }
+
+        if let Some((deterministic_ident, true)) = deterministic {
+            if !layout.is_persisted() {
+                bail!(
+                    deterministic_ident,
+                    "`deterministic` only applies to the pages of a persisted layout!"
+                );
+            }
+
+            if layout.is_external() {
+                bail!(
+                    deterministic_ident,
+                    "An external store is made deterministic by its owner, with `GlobalStore::deterministic`!"
+                );
+            }
+
+            if layout.has_ttl() {
+                bail!(
+                    deterministic_ident,
+                    "A `deterministic` index cannot use a `ttl`, whose expiry times follow the clock!"
+                );
+            }
+
+            layout.deterministic = true;
+        }
+
+        if let Some((read_only_ident, true)) = read_only {
+            if layout.values != ValueStorage::Inline
+                || layout.is_versioned()
+                || layout.tombstones
+                || layout.has_ttl()
+            {
+                bail!(
+                    read_only_ident,
+                    "A `read_only` index cannot use `values`, `versioning`, `tombstones` or `ttl`!"
+                );
+            }
+
+            if extern_c == Some(true) {
+                bail!(
+                    read_only_ident,
+                    "A `read_only` index cannot generate C bindings!"
+                );
+            }
+
+            layout.read_only = true;
+        }
+
+        if let Some((borrowed_ident, true)) = borrowed {
+            if layout.is_persisted() || layout.is_versioned() {
+                bail!(
+                    borrowed_ident,
+                    "A `borrowed` index can only be generated for an unversioned in-memory layout!"
+                );
+            }
+
+            layout.borrowed = true;
+        }
+
+        if let Some((append_hint_ident, true)) = append_hint {
+            if layout.is_persisted() || layout.read_only {
+                bail!(
+                    append_hint_ident,
+                    "An `append_hint` can only be used with a writable in-memory layout!"
+                );
+            }
+
+            layout.append_hint = true;
+        }
+
+        if let Some((reverse_lookup_ident, true)) = reverse_lookup {
+            if layout.is_persisted() || layout.is_versioned() {
+                bail!(
+                    reverse_lookup_ident,
+                    "A `reverse_lookup` can only be kept for an unversioned in-memory layout!"
+                );
+            }
+
+            layout.reverse_lookup = true;
+        }
+
+        if let Some((watch_ident, true)) = watch {
+            if !cfg!(feature = "async") {
+                bail!(watch_ident, "`watch` requires the `async` feature!");
+            }
+
+            if layout.is_persisted() || layout.is_versioned() || layout.read_only {
+                bail!(
+                    watch_ident,
+                    "A `watch` can only be kept for a writable, unversioned in-memory layout!"
+                );
+            }
+
+            layout.watch = true;
+        }
+
+        if let Some((fast_fences_ident, slots)) = fast_fences {
+            if layout.is_persisted() {
+                bail!(
+                    fast_fences_ident,
+                    "`fast_fences` can only be kept for an in-memory layout!"
+                );
+            }
+
+            if slots == 0 {
+                bail!(fast_fences_ident, "`fast_fences` needs at least one slot!");
+            }
+
+            layout.fast_fences = Some(slots);
+        }
+
+        if let Some((filter_ident, filter)) = filter {
+            if layout.is_persisted() {
+                bail!(
+                    filter_ident,
+                    "A `filter` can only be kept for an in-memory layout!"
+                );
+            }
+
+            layout.filter = Some(filter);
+        }
+
+        if let Some((max_memory_ident, max_memory)) = max_memory {
+            if layout.is_persisted() || layout.read_only {
+                bail!(
+                    max_memory_ident,
+                    "A `max_memory` budget can only be set for a writable in-memory layout!"
+                );
+            }
+
+            layout.max_memory = Some(max_memory);
+        }
+
+        if let Some((stripes_ident, count)) = stripes {
+            if layout.is_persisted()
+                || layout.is_versioned()
+                || layout.read_only
+                || !matches!(layout.base, component::BaseComponent::BTree { .. })
+            {
+                bail!(
+                    stripes_ident,
+                    "`stripes` can only be set for a writable, unversioned in-memory layout with a `btree` base!"
+                );
+            }
+
+            if layout.internal.is_empty() {
+                bail!(
+                    stripes_ident,
+                    "`stripes` are split at the fences of an internal layer, which the layout lacks!"
+                );
+            }
+
+            if count == 0 {
+                bail!(stripes_ident, "`stripes` needs at least one stripe!");
+            }
+
+            layout.stripes = Some(count);
+        }
+
+        if let Some((alloc_ident, AllocMode::Fixed)) = alloc {
+            let internal = layout.internal.iter().all(|component| {
+                matches!(
+                    component,
+                    component::InternalComponent::BTree {
+                        fences: component::Fences::Full,
+                        ..
+                    }
+                )
+            });
+
+            if layout.is_persisted()
+                || layout.is_versioned()
+                || !internal
+                || !matches!(layout.base, component::BaseComponent::BTree { .. })
+            {
+                bail!(
+                    alloc_ident,
+                    "A `fixed` allocation needs an unversioned in-memory layout with only `btree` layers below the top!"
+                );
+            }
+
+            layout.alloc = AllocMode::Fixed;
+        }
+
+        if let Some((swappable_top_ident, true)) = swappable_top {
+            if layout.top.kind().is_none() {
+                bail!(
+                    swappable_top_ident,
+                    "A `swappable_top` has to start out as a `btree_top()` or `rmi_top()` without parameters!"
+                );
+            }
+
+            layout.swappable_top = true;
+        }
 
         Ok(Self {
             name: name_ident,
             layout,
+            extern_c: extern_c.unwrap_or(false),
+            layout_tokens: layout_stream,
         })
     }
 }
@@ -0,0 +1,197 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+/// Implement `Projectable` for a struct with named fields, along with a module named after the
+/// struct holding a `FieldSelector` for every field: `Row` gets `row_fields::Id` for its `id`
+/// field, `row_fields::Name` for `name`, and so on. The partial value is a `RowPartial` struct
+/// next to `Row`.
+pub fn derive_projectable(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+
+    if !input.generics.params.is_empty() {
+        bail!(
+            input.generics,
+            "`Projectable` can't be derived for generic types!"
+        );
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                bail!(
+                    name,
+                    "`Projectable` can only be derived for structs with named fields!"
+                );
+            }
+        },
+        _ => {
+            bail!(name, "`Projectable` can only be derived for structs!");
+        }
+    };
+
+    if fields.len() > 64 {
+        bail!(name, "`Projectable` supports at most 64 fields!");
+    }
+
+    let partial = Ident::new(&format!("{}Partial", name), Span::call_site());
+    let mod_name = Ident::new(
+        &format!("{}_fields", to_snake_case(&name.to_string())),
+        Span::call_site(),
+    );
+
+    let field_names: Vec<&Ident> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let field_types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let field_strs: Vec<String> = field_names.iter().map(|f| f.to_string()).collect();
+    let selectors: Vec<Ident> = field_strs
+        .iter()
+        .map(|f| Ident::new(&to_camel_case(f), Span::call_site()))
+        .collect();
+
+    let mut reads = TokenStream::new();
+    for (index, (field, ty)) in field_names.iter().zip(field_types.iter()).enumerate() {
+        let bit = 1u64 << index;
+
+        reads.extend(quote! {
+            if mask & #bit != 0 {
+                partial.#field = Some(
+                    seq.next_element::<#ty>()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(#index, &"more fields"))?,
+                );
+            } else {
+                seq.next_element::<Skip<#ty>>()?;
+            }
+        });
+
+        // Stop once every picked field has been read
+        if index + 1 < fields.len() {
+            let rest = u64::MAX << (index + 1);
+
+            reads.extend(quote! {
+                if mask & #rest == 0 {
+                    return Ok(partial);
+                }
+            });
+        }
+    }
+
+    let bits: Vec<u64> = (0..fields.len()).map(|index| 1u64 << index).collect();
+
+    let mut selector_impls = TokenStream::new();
+    for (index, ((field, ty), selector)) in field_names
+        .iter()
+        .zip(field_types.iter())
+        .zip(selectors.iter())
+        .enumerate()
+    {
+        let bit = 1u64 << index;
+        let missing = format!("`{}` was not read!", field);
+
+        selector_impls.extend(quote! {
+            impl FieldSelector<#name> for #mod_name::#selector {
+                const MASK: u64 = #bit;
+
+                type Output = #ty;
+
+                fn get(partial: &#partial) -> #ty {
+                    partial.#field.clone().expect(#missing)
+                }
+            }
+        });
+    }
+
+    let partial_doc = format!("Fields of a `{}` read by a projection", name);
+    let doc = format!("Field selectors of `{}`", name);
+    let selector_docs: Vec<String> = field_strs
+        .iter()
+        .map(|f| format!("Picks the `{}` field", f))
+        .collect();
+
+    Ok(quote! {
+        #[doc = #partial_doc]
+        #[derive(Default)]
+        #vis struct #partial {
+            #(pub #field_names: Option<#field_types>,)*
+        }
+
+        #[doc = #doc]
+        #vis mod #mod_name {
+            #(
+                #[doc = #selector_docs]
+                pub struct #selectors;
+            )*
+        }
+
+        const _: () = {
+            use ::limousine_engine::private::serde;
+            use ::limousine_engine::private::projection::{FieldSelector, Projectable, Skip};
+            use serde::de::SeqAccess;
+
+            impl Projectable for #name {
+                const FIELDS: &'static [&'static str] = &[#(#field_strs),*];
+
+                type Partial = #partial;
+
+                fn read_fields<'de, A: SeqAccess<'de>>(
+                    mut seq: A,
+                    mask: u64,
+                ) -> Result<Self::Partial, A::Error> {
+                    let mut partial = #partial::default();
+
+                    if mask == 0 {
+                        return Ok(partial);
+                    }
+
+                    #reads
+
+                    Ok(partial)
+                }
+
+                fn copy_fields(&self, mask: u64) -> Self::Partial {
+                    let mut partial = #partial::default();
+
+                    #(
+                        if mask & #bits != 0 {
+                            partial.#field_names = Some(self.#field_names.clone());
+                        }
+                    )*
+
+                    partial
+                }
+            }
+
+            #selector_impls
+        };
+    })
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut result = String::new();
+
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+fn to_camel_case(name: &str) -> String {
+    name.trim_start_matches("r#")
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap();
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
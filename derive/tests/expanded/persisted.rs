@@ -0,0 +1,512 @@
+# [doc (hidden)] pub mod __persistedindex {
+    use :: limousine_engine :: private :: * ;
+    type A0 = BoundaryDiskBTreeBaseAddress ;
+    type A1 = BTreeInternalAddress ;
+    type A2 = () ;
+    type C0 < K , V > = BoundaryDiskBTreeBaseComponent < K , V , 64usize , A1 > ;
+    type C1 < K , V > = BTreeInternalComponent < K , V , 16usize , A0 , A2 > ;
+    type C2 < K , V > = BTreeTopComponent < K , V , A1 > ;
+    pub struct PersistedIndex < K : Persisted + Key + PrimInt , V : Persisted + Value > {
+        # [doc (hidden)] pub c0 : C0 < K , V > ,
+        # [doc (hidden)] pub c1 : C1 < K , V > ,
+        # [doc (hidden)] pub c2 : C2 < K , V > ,
+        # [doc (hidden)] pub wal : WriteAheadLog < K , V > ,
+        pub stats : StatsStore ,
+        # [doc (hidden)] pub store : GlobalStore ,
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Insert a key, skipping the descent from the top if it falls within the base node"] # [doc = r" remembered by `hint`. The hint is updated to the node the key was inserted into."] pub fn insert_with_hint (& mut self , key : K , value : V , hint : & SearchHint < BoundaryDiskBTreeBaseAddress > ,) -> limousine_engine :: Result < Option < V >> {
+            self . stats . record_insert () ;
+            let s1 = match hint . node_for (& self . c0 , & key) {
+                Some (node) => node ,
+                None => {
+                    let s2 = self . c2 . search (& self . c1 , & key) ;
+                    let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+                    s1
+                }
+            }
+            ;
+            hint . set (s1) ;
+            let inserted = self . c0 . insert (s1 , key , value) ? ;
+            let result = inserted . previous ;
+            let i0 ;
+            if let Some (x) = inserted . propagate {
+                i0 = x ;
+            }
+            else {
+                return Ok (result) ;
+            }
+            let i1 ;
+            if let Some (x) = self . c1 . insert (& mut self . c0 , i0) {
+                i1 = x ;
+            }
+            else {
+                return Ok (result) ;
+            }
+            let i2 = self . c2 . insert (& mut self . c1 , i1) ;
+            Ok (result)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedKVStore < K , V > for PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        fn search (& self , key : K) -> limousine_engine :: Result < Option < V >> {
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            let s0 = self . c0 . search (s1 , & key) ? ;
+            Ok (s0)
+        }
+        fn insert (& mut self , key : K , value : V) -> limousine_engine :: Result < Option < V >> {
+            self . stats . record_insert () ;
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            let inserted = self . c0 . insert (s1 , key , value) ? ;
+            let result = inserted . previous ;
+            let i0 ;
+            if let Some (x) = inserted . propagate {
+                i0 = x ;
+            }
+            else {
+                return Ok (result) ;
+            }
+            let i1 ;
+            if let Some (x) = self . c1 . insert (& mut self . c0 , i0) {
+                i1 = x ;
+            }
+            else {
+                return Ok (result) ;
+            }
+            let i2 = self . c2 . insert (& mut self . c1 , i1) ;
+            Ok (result)
+        }
+        fn open (path : impl AsRef < Path >) -> limousine_engine :: Result < Self > {
+            let path = limousine_engine :: private :: add_prefix_to_path (path , "-pYFDViAF-qoy4MU8wgdDg==" . to_string ()) ? ;
+            let mut store = GlobalStore :: load (path) ? ;
+            let mut c0 = C0 :: load (& mut store , "C0") ? ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            let mut c2 = C2 :: build (& mut c1) ;
+            let stats = StatsStore :: load (& mut store , "Stats") ? ;
+            let wal = WriteAheadLog :: load (& mut store , "WriteAheadLog" , false) ? ;
+            let read_only = store . is_read_only () ;
+            let mut index = Self {
+                c0 ,
+                c1 ,
+                c2 ,
+                wal ,
+                stats ,
+                store ,
+            }
+            ;
+            if ! read_only {
+                index . replay_batch () ? ;
+            }
+            Ok (index)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Search for `key`, only reading the fields of its value picked by `S`"] pub fn search_project < S > (& self , key : K) -> limousine_engine :: Result < Option < S :: Output >> where V : Projectable ,
+        S : FieldSelector < V > ,
+        {
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            let s0 = self . c0 . search_project :: < S > (s1 , & key) ? ;
+            Ok (s0)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Whether `key` is present, without reading its value"] pub fn contains_key (& self , key : & K) -> limousine_engine :: Result < bool > {
+            let key = * key ;
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            self . c0 . contains (s1 , & key)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" The value of every key in `keys`, in the same order. Keys falling into the same base"] # [doc = r" node are answered by one read of its page, so batches of nearby keys read far"] # [doc = r" fewer pages than searching for each of them."] pub fn search_many (& self , keys : & [K]) -> limousine_engine :: Result < Vec < Option < V >> > {
+            let mut nodes = Vec :: with_capacity (keys . len ()) ;
+            for (index , & key) in keys . iter () . enumerate () {
+                let s2 = self . c2 . search (& self . c1 , & key) ;
+                let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+                nodes . push ((s1 , index)) ;
+            }
+            nodes . sort_unstable_by_key (| & (node , _) | node) ;
+            let mut values : Vec < Option < V >> = keys . iter () . map (| _ | None) . collect () ;
+            for group in nodes . chunk_by (| a , b | a . 0 == b . 0) {
+                let node_keys : Vec < K > = group . iter () . map (| & (_ , index) | keys [index]) . collect () ;
+                let found = self . c0 . search_node (group [0] . 0 , & node_keys) ? ;
+                for (& (_ , index) , value) in group . iter () . zip (found) {
+                    values [index] = value ;
+                }
+            }
+            Ok (values)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" A token resuming right after `after`, whether or not it is present. Tokens can be"] # [doc = r" kept as bytes, and stay valid across restarts and writes to the index."] pub fn cursor_token (& self , after : K) -> limousine_engine :: Result < CursorToken < K , BoundaryDiskBTreeBaseAddress >> {
+            let key = after ;
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            Ok (CursorToken :: new (s1 , None , key))
+        }
+        # [doc = r" Up to `limit` entries following the key of `token`, in key order, along with the"] # [doc = r" token resuming after them"] pub fn resume (& self , token : & CursorToken < K , BoundaryDiskBTreeBaseAddress > , limit : usize ,) -> limousine_engine :: Result < CursorPage < K , V , BoundaryDiskBTreeBaseAddress >> {
+            let s1 = match token . node_for (& self . c0) {
+                Some (node) => node ,
+                None => {
+                    let key = * token . after () ;
+                    let s2 = self . c2 . search (& self . c1 , & key) ;
+                    let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+                    s1
+                }
+            }
+            ;
+            self . c0 . page (s1 , token , limit)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Size of the index on disk, broken down by persisted layer. Pages which are only"] # [doc = r" cached so far are counted, but not their bytes."] pub fn disk_usage (& self) -> DiskStats {
+            DiskStats :: attribute (self . store . stats () , [(0 , self . c0 . node_count () as u64)] , None)
+        }
+        # [doc = r" Reclaim the space held by overwritten and freed pages now, rather than when the"] # [doc = r" index is dropped, returning the number of pages moved"] pub fn compact (& mut self) -> limousine_engine :: Result < usize > {
+            self . stats . record_compaction () ;
+            self . store . maintenance ()
+        }
+        # [doc = r" Lookups into the page caches of the index since it was opened, under its"] # [doc = r" `cache_policy`"] pub fn cache_stats (& self) -> CacheStats {
+            self . store . cache_stats ()
+        }
+        # [doc = r" Counters accumulated over the lifetime of the index, across restarts. They are"] # [doc = r" saved when the index is dropped."] pub fn stats (& self) -> IndexStats {
+            self . stats . get ()
+        }
+        # [doc = r" Zero the counters of `stats`, and restart the lifetime of the index"] pub fn reset_stats (& mut self) {
+            self . stats . reset () ;
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Read the pages the first lookups into `range` would, ahead of time: every page of"] # [doc = r" the persisted internal layers, and the base nodes holding keys in `range`. Pass `..`"] # [doc = r" to read the whole index, or an empty range such as `0..0` to only read the internal"] # [doc = r" layers. Pages of internal layers stay cached, but base pages are evicted as usual,"] # [doc = r" so warming much more of the base layer than fits in the cache is wasted work."] pub fn warm (& self , range : impl std :: ops :: RangeBounds < K > ,) -> limousine_engine :: Result < WarmStats > {
+            use std :: ops :: Bound ;
+            let mut stats = WarmStats :: default () ;
+            let empty = match (range . start_bound () , range . end_bound ()) {
+                (Bound :: Included (start) , Bound :: Included (end)) => start > end ,
+                (Bound :: Included (start) | Bound :: Excluded (start) , Bound :: Included (end) | Bound :: Excluded (end)) => start >= end ,
+                _ => false ,
+            }
+            ;
+            if empty {
+                return Ok (stats) ;
+            }
+            let first = match range . start_bound () {
+                Bound :: Included (key) | Bound :: Excluded (key) => self . locate (* key) ? ,
+                Bound :: Unbounded => self . c0 . first () ,
+            }
+            ;
+            let last = match range . end_bound () {
+                Bound :: Included (key) | Bound :: Excluded (key) => self . locate (* key) ? ,
+                Bound :: Unbounded => self . c0 . last () ,
+            }
+            ;
+            let mut ptr = Some (first) ;
+            while let Some (node) = ptr {
+                self . c0 . warm_node (node , & mut stats) ? ;
+                if node == last {
+                    break ;
+                }
+                ptr = self . c0 . next (node) ;
+            }
+            Ok (stats)
+        }
+        # [doc = r" The base node a search for `key` ends up in"] fn locate (& self , key : K) -> limousine_engine :: Result < A0 > {
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            Ok (s1)
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value ,
+    {
+        # [doc = r" Apply every write of `batch` in order, as one. The batch is logged before any of it"] # [doc = r" is written, so if the process dies halfway through, opening the index again applies"] # [doc = r" the whole batch. If a write fails, none of the batch is applied."] pub fn apply_batch (& mut self , batch : WriteBatch < K , V >) -> limousine_engine :: Result < () > {
+            self . wal . log (& batch) ? ;
+            self . write_batch (batch)
+        }
+        fn write_batch (& mut self , batch : WriteBatch < K , V >) -> limousine_engine :: Result < () > {
+            self . c0 . flush () ? ;
+            self . wal . begin () ? ;
+            match self . write_pending (batch) {
+                Ok (()) => Ok (()) ,
+                Err (error) => {
+                    self . abort_batch () ? ;
+                    Err (error)
+                }
+            }
+        }
+        # [doc = r" Write the batch, and every page it changed along with the cleared log in one batch"] # [doc = r" of the store"] fn write_pending (& mut self , batch : WriteBatch < K , V >) -> limousine_engine :: Result < () > {
+            for (key , value) in batch {
+                match value {
+                    Some (value) => {
+                        PersistedKVStore :: insert (self , key , value) ? ;
+                    }
+                    None => {
+                        unreachable ! ("deletes are refused as the batch is logged") ;
+                    }
+                }
+            }
+            self . c0 . flush () ? ;
+            self . wal . commit ()
+        }
+        # [doc = r" Drop every page the batch wrote, reload the layers as they were before it, and"] # [doc = r" clear the log"] fn abort_batch (& mut self) -> limousine_engine :: Result < () > {
+            self . wal . abort () ;
+            self . c0 . reload () ? ;
+            self . c1 = C1 :: build (& mut self . c0) ;
+            self . c2 = C2 :: build (& mut self . c1) ;
+            self . wal . clear ()
+        }
+        # [doc = r" Apply the batch left in the log by an `apply_batch` which was cut short"] fn replay_batch (& mut self) -> limousine_engine :: Result < () > {
+            match self . wal . pending () {
+                Some (batch) => self . write_batch (batch) ,
+                None => Ok (()) ,
+            }
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Build the index at `path` from entries in any order, holding at most `run_entries`"] # [doc = r" of them in memory. Entries are sorted into runs which are spilled to the store, then"] # [doc = r" merged straight into the base layer, from which the layers above are built. Of"] # [doc = r" several entries with the same key, the last one is kept. The index at `path` must"] # [doc = r" not hold any entries yet."] pub fn build_external (path : impl AsRef < Path > , entries : impl IntoIterator < Item = (K , V) > , run_entries : usize ,) -> limousine_engine :: Result < Self > {
+            let path = limousine_engine :: private :: add_prefix_to_path (path , "-pYFDViAF-qoy4MU8wgdDg==" . to_string ()) ? ;
+            let mut store = GlobalStore :: load (path) ? ;
+            let mut c0 = C0 :: load (& mut store , "C0") ? ;
+            let mut builder = DiskBuilder :: load (& mut store , "Runs" , run_entries) ? ;
+            builder . extend (entries) ? ;
+            c0 . fill (builder . finish () ?) ? ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            let mut c2 = C2 :: build (& mut c1) ;
+            let stats = StatsStore :: load (& mut store , "Stats") ? ;
+            let wal = WriteAheadLog :: load (& mut store , "WriteAheadLog" , false) ? ;
+            let read_only = store . is_read_only () ;
+            let mut index = Self {
+                c0 ,
+                c1 ,
+                c2 ,
+                wal ,
+                stats ,
+                store ,
+            }
+            ;
+            if ! read_only {
+                index . replay_batch () ? ;
+            }
+            Ok (index)
+        }
+    }
+    impl < K : Key + PrimInt , V : Value > PersistedIndex < K , V > where K : limousine_engine :: private :: Persisted ,
+    V : limousine_engine :: private :: Persisted ,
+    {
+        # [doc = r" Open the index kept by `backend`, or create it if the backend holds no pages"] pub fn open_with_backend (backend : impl StorageBackend ,) -> limousine_engine :: Result < Self > {
+            let mut store = GlobalStore :: with_backend (backend) ? ;
+            let mut c0 = C0 :: load (& mut store , "C0") ? ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            let mut c2 = C2 :: build (& mut c1) ;
+            let stats = StatsStore :: load (& mut store , "Stats") ? ;
+            let wal = WriteAheadLog :: load (& mut store , "WriteAheadLog" , false) ? ;
+            let read_only = store . is_read_only () ;
+            let mut index = Self {
+                c0 ,
+                c1 ,
+                c2 ,
+                wal ,
+                stats ,
+                store ,
+            }
+            ;
+            if ! read_only {
+                index . replay_batch () ? ;
+            }
+            Ok (index)
+        }
+        # [doc = r" Open the index at `path` without ever writing to it. Inserts fail, and an index"] # [doc = r" which was never created can't be opened. The `MarbleBackend` used by `open` locks"] # [doc = r" its directory, so this fails with `StoreLocked` while the index is open elsewhere."] pub fn open_read_only (path : impl AsRef < Path >) -> limousine_engine :: Result < Self > {
+            let path = limousine_engine :: private :: add_prefix_to_path (path , "-pYFDViAF-qoy4MU8wgdDg==" . to_string ()) ? ;
+            let mut store = GlobalStore :: load_read_only (path) ? ;
+            let mut c0 = C0 :: load (& mut store , "C0") ? ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            let mut c2 = C2 :: build (& mut c1) ;
+            let stats = StatsStore :: load (& mut store , "Stats") ? ;
+            let wal = WriteAheadLog :: load (& mut store , "WriteAheadLog" , false) ? ;
+            let read_only = store . is_read_only () ;
+            let mut index = Self {
+                c0 ,
+                c1 ,
+                c2 ,
+                wal ,
+                stats ,
+                store ,
+            }
+            ;
+            if ! read_only {
+                index . replay_batch () ? ;
+            }
+            Ok (index)
+        }
+        # [doc = r" Open the index kept by `backend` without ever writing to it, alongside the process"] # [doc = r" which owns it, as with a `FileBackend` opened with `open_read_only`. A batch the"] # [doc = r" owner is still applying is ignored, so the index is seen as it was before the batch."] pub fn open_read_only_with_backend (backend : impl StorageBackend ,) -> limousine_engine :: Result < Self > {
+            let mut store = GlobalStore :: with_backend_read_only (backend) ? ;
+            let mut c0 = C0 :: load (& mut store , "C0") ? ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            let mut c2 = C2 :: build (& mut c1) ;
+            let stats = StatsStore :: load (& mut store , "Stats") ? ;
+            let wal = WriteAheadLog :: load (& mut store , "WriteAheadLog" , false) ? ;
+            let read_only = store . is_read_only () ;
+            let mut index = Self {
+                c0 ,
+                c1 ,
+                c2 ,
+                wal ,
+                stats ,
+                store ,
+            }
+            ;
+            if ! read_only {
+                index . replay_batch () ? ;
+            }
+            Ok (index)
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value {
+        # [doc = r" Segmentation statistics for every learned layer, ordered from the base layer up"] pub fn layer_report (& self) -> Vec < LayerReport > {
+            vec ! []
+        }
+        # [doc = r" Plots of the segments of every learned layer holding keys in `range`, ordered from"] # [doc = r" the base layer up"] # [allow (unused_variables)] pub fn layer_plot (& self , range : impl :: core :: ops :: RangeBounds < K > + Clone) -> Vec < LayerPlot > {
+            vec ! []
+        }
+        # [doc = r" Number of nodes in every layer below the top as `(layer, nodes)`, ordered from the"] # [doc = r" base layer up"] pub fn node_counts (& self) -> Vec < (usize , usize) > {
+            vec ! [(0usize , self . c0 . node_count ()) , (1usize , self . c1 . node_count ())]
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value {
+        # [doc = r" Number of entries in the index, which is kept up to date rather than counted"] pub fn len (& self) -> usize {
+            self . c0 . len ()
+        }
+        pub fn is_empty (& self) -> bool {
+            self . len () == 0
+        }
+    }
+    impl < K , V > IndexRead < K , V > for PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value {
+        fn try_search (& self , key : K) -> limousine_engine :: Result < Option < V >> {
+            PersistedKVStore :: search (self , key)
+        }
+        fn len (& self) -> usize {
+            self . c0 . len ()
+        }
+    }
+    impl < K , V > IndexWrite < K , V > for PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value {
+        fn try_put (& mut self , key : K , value : V) -> limousine_engine :: Result < Option < V >> {
+            PersistedKVStore :: insert (self , key , value)
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value ,
+    {
+        # [doc = r" Search for `key`, recording every layer visited and the work done in each"] pub fn explain (& self , key : & K) -> limousine_engine :: Result < LookupTrace < K >> {
+            let key = * key ;
+            let mut steps = Vec :: new () ;
+            steps . push (LookupStep { layer : 2usize , component : "BTreeTop" , node : None , probe : self . c2 . probe (& self . c1 , & key) , }) ;
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            steps . push (LookupStep { layer : 1usize , component : "InMemoryBTreeInternal16" , node : self . c1 . lower_bound (s2 . clone ()) . into_key () , probe : self . c1 . probe (& self . c0 , s2 . clone () , & key) , }) ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            steps . push (LookupStep { layer : 0 , component : "BoundaryDiskBTreeBase64" , node : self . c0 . lower_bound (s1 . clone ()) . into_key () , probe : self . c0 . probe (s1 . clone () , & key) ? , }) ;
+            let found = self . c0 . search (s1 , & key) ? . is_some () ;
+            Ok (LookupTrace { key , steps , found })
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value ,
+    {
+        # [doc = r" Search for every key in `keys`, returning the results in the same order. Only"] # [doc = r" keys which fall outside the base node of the previous key descend from the top,"] # [doc = r" so batches of nearby keys are much cheaper than searching them one by one."] pub fn search_batch (& self , keys : & [K]) -> limousine_engine :: Result < Vec < Option < V >> > {
+            let mut order : Vec < usize > = (0 .. keys . len ()) . collect () ;
+            if ! keys . windows (2) . all (| pair | pair [0] <= pair [1]) {
+                order . sort_unstable_by_key (| & index | keys [index]) ;
+            }
+            let hint = SearchHint :: new () ;
+            let mut results = vec ! [None ; keys . len ()] ;
+            for index in order {
+                let key = keys [index] ;
+                let s1 = match hint . node_for (& self . c0 , & key) {
+                    Some (node) => node ,
+                    None => {
+                        let s2 = self . c2 . search (& self . c1 , & key) ;
+                        let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+                        s1
+                    }
+                }
+                ;
+                hint . set (s1) ;
+                let value = self . c0 . search (s1 , & key) ? ;
+                results [index] = value ;
+            }
+            Ok (results)
+        }
+        # [doc = r" Search for every key in `keys`, returning the results in the same order. The"] # [doc = r" whole batch moves through the layers one stage at a time, searching the top for"] # [doc = r" every key, then every internal layer in turn, and finally the base, which keeps"] # [doc = r" the code of a single layer hot for the whole stage."] pub fn search_pipelined (& self , keys : & [K]) -> limousine_engine :: Result < Vec < Option < V >> > {
+            let mut s2 = Vec :: with_capacity (keys . len ()) ;
+            for key in keys {
+                s2 . push (self . c2 . search (& self . c1 , key)) ;
+            }
+            for ptr in s2 . iter () {
+                self . c1 . prefetch (ptr . clone ()) ;
+            }
+            let mut s1 = Vec :: with_capacity (keys . len ()) ;
+            for (key , ptr) in keys . iter () . zip (s2) {
+                s1 . push (self . c1 . search (& self . c0 , ptr , key)) ;
+            }
+            for ptr in s1 . iter () {
+                self . c0 . prefetch (ptr . clone ()) ;
+            }
+            let mut results = Vec :: with_capacity (keys . len ()) ;
+            for (key , ptr) in keys . iter () . zip (s1) {
+                let value = self . c0 . search (ptr , key) ? ;
+                results . push (value) ;
+            }
+            Ok (results)
+        }
+    }
+    impl < K , V > PersistedIndex < K , V > where K : Persisted + Key + PrimInt ,
+    V : Persisted + Value ,
+    {
+        # [doc = r" Search for `key`, unless that takes more than `budget`. The budget is checked"] # [doc = r" before every layer is descended, so the read of the last node visited can take"] # [doc = r" the bytes read from disk over the budget, but no further layer is visited then."] pub fn search_with_budget (& self , key : & K , budget : ProbeBudget ,) -> limousine_engine :: Result < SearchOutcome < V >> {
+            let key = * key ;
+            let start = self . stats . bytes_read () ;
+            let mut nodes = 0 ;
+            if ! budget . allows_next (nodes , self . stats . bytes_read () - start) {
+                return Ok (SearchOutcome :: Exceeded { layer : 2usize , nodes , disk_bytes : self . stats . bytes_read () - start , }) ;
+            }
+            nodes += 1 ;
+            let s2 = self . c2 . search (& self . c1 , & key) ;
+            if ! budget . allows_next (nodes , self . stats . bytes_read () - start) {
+                return Ok (SearchOutcome :: Exceeded { layer : 1usize , nodes , disk_bytes : self . stats . bytes_read () - start , }) ;
+            }
+            nodes += 1 ;
+            let s1 = self . c1 . search (& self . c0 , s2 , & key) ;
+            if ! budget . allows_next (nodes , self . stats . bytes_read () - start) {
+                return Ok (SearchOutcome :: Exceeded { layer : 0usize , nodes , disk_bytes : self . stats . bytes_read () - start , }) ;
+            }
+            nodes += 1 ;
+            let value = self . c0 . search (s1 , & key) ? ;
+            let outcome = SearchOutcome :: from (value) ;
+            Ok (outcome)
+        }
+    }
+}
+use __persistedindex :: PersistedIndex ;
+
@@ -0,0 +1,240 @@
+# [doc (hidden)] pub mod __readonlyindex {
+    use :: limousine_engine :: private :: * ;
+    type A0 = BTreeBaseAddress ;
+    type A1 = () ;
+    type C0 < K , V > = BTreeBaseComponent < K , V , 16usize , A1 > ;
+    type C1 < K , V > = BTreeTopComponent < K , V , A0 > ;
+    pub struct ReadOnlyIndex < K : Key , V : Value > {
+        # [doc (hidden)] pub c0 : C0 < K , V > ,
+        # [doc (hidden)] pub c1 : C1 < K , V > ,
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        pub fn build (iter : impl Iterator < Item = (K , V) >) -> Self {
+            let mut c0 = C0 :: build (iter) ;
+            let mut c1 = C1 :: build (& mut c0) ;
+            Self {
+                c0 ,
+                c1 ,
+            }
+        }
+    }
+    impl < K : Key , V : Value > IndexRead < K , V > for ReadOnlyIndex < K , V > {
+        fn try_search (& self , key : K) -> limousine_engine :: Result < Option < V >> {
+            Ok ({ let s1 = self . c1 . search (& self . c0 , & key) ; let s0 = self . c0 . search (s1 , & key) ; s0 })
+        }
+        fn len (& self) -> usize {
+            self . c0 . len ()
+        }
+    }
+    impl < K : Key , V : Value > Clone for ReadOnlyIndex < K , V > {
+        fn clone (& self) -> Self {
+            Self {
+                c0 : self . c0 . clone () ,
+                c1 : self . c1 . clone () ,
+            }
+        }
+    }
+    impl < K , V > ReadOnlyIndex < K , V > where K : Key ,
+    V : Value {
+        # [doc = r" Segmentation statistics for every learned layer, ordered from the base layer up"] pub fn layer_report (& self) -> Vec < LayerReport > {
+            vec ! []
+        }
+        # [doc = r" Plots of the segments of every learned layer holding keys in `range`, ordered from"] # [doc = r" the base layer up"] # [allow (unused_variables)] pub fn layer_plot (& self , range : impl :: core :: ops :: RangeBounds < K > + Clone) -> Vec < LayerPlot > {
+            vec ! []
+        }
+        # [doc = r" Number of nodes in every layer below the top as `(layer, nodes)`, ordered from the"] # [doc = r" base layer up"] pub fn node_counts (& self) -> Vec < (usize , usize) > {
+            vec ! [(0usize , self . c0 . node_count ())]
+        }
+    }
+    impl < K , V > ReadOnlyIndex < K , V > where K : Key ,
+    V : Value {
+        # [doc = r" Number of entries in the index, which is kept up to date rather than counted"] pub fn len (& self) -> usize {
+            self . c0 . len ()
+        }
+        pub fn is_empty (& self) -> bool {
+            self . len () == 0
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Search for `key`, recording every layer visited and the work done in each"] pub fn explain (& self , key : & K) -> LookupTrace < K > {
+            let key = * key ;
+            let mut steps = Vec :: new () ;
+            steps . push (LookupStep { layer : 1usize , component : "BTreeTop" , node : None , probe : self . c1 . probe (& self . c0 , & key) , }) ;
+            let s1 = self . c1 . search (& self . c0 , & key) ;
+            steps . push (LookupStep { layer : 0 , component : "InMemoryBTreeBase16" , node : self . c0 . lower_bound (s1 . clone ()) . into_key () , probe : self . c0 . probe (s1 . clone () , & key) , }) ;
+            let found = self . c0 . search (s1 , & key) . is_some () ;
+            LookupTrace {
+                key ,
+                steps ,
+                found
+            }
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Search for every key in `keys`, returning the results in the same order. Only"] # [doc = r" keys which fall outside the base node of the previous key descend from the top,"] # [doc = r" so batches of nearby keys are much cheaper than searching them one by one."] pub fn search_batch (& self , keys : & [K]) -> Vec < Option < V >> {
+            let mut order : Vec < usize > = (0 .. keys . len ()) . collect () ;
+            if ! keys . windows (2) . all (| pair | pair [0] <= pair [1]) {
+                order . sort_unstable_by_key (| & index | keys [index]) ;
+            }
+            let hint = SearchHint :: new () ;
+            let mut results = vec ! [None ; keys . len ()] ;
+            for index in order {
+                let key = keys [index] ;
+                let s1 = match hint . node_for (& self . c0 , & key) {
+                    Some (node) => node ,
+                    None => {
+                        let s1 = self . c1 . search (& self . c0 , & key) ;
+                        s1
+                    }
+                }
+                ;
+                hint . set (s1) ;
+                let value = self . c0 . search (s1 , & key) ;
+                results [index] = value ;
+            }
+            results
+        }
+        # [doc = r" Search for every key in `keys`, returning the results in the same order. The"] # [doc = r" whole batch moves through the layers one stage at a time, searching the top for"] # [doc = r" every key, then every internal layer in turn, and finally the base. Every stage"] # [doc = r" prefetches the nodes the next one reads, so that large batches of scattered"] # [doc = r" keys wait on memory far less than when searching them one by one."] pub fn search_pipelined (& self , keys : & [K]) -> Vec < Option < V >> {
+            let mut s1 = Vec :: with_capacity (keys . len ()) ;
+            for key in keys {
+                s1 . push (self . c1 . search (& self . c0 , key)) ;
+            }
+            for ptr in s1 . iter () {
+                self . c0 . prefetch (ptr . clone ()) ;
+            }
+            let mut results = Vec :: with_capacity (keys . len ()) ;
+            for (key , ptr) in keys . iter () . zip (s1) {
+                let value = self . c0 . search (ptr , key) ;
+                results . push (value) ;
+            }
+            results
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Search for `key`, unless that takes visiting more nodes than `budget` allows."] # [doc = r" In-memory layouts never read from disk, so only the nodes are counted."] pub fn search_with_budget (& self , key : & K , budget : ProbeBudget) -> SearchOutcome < V > {
+            let key = * key ;
+            let mut nodes = 0 ;
+            if ! budget . allows_next (nodes , 0) {
+                return SearchOutcome :: Exceeded {
+                    layer : 1usize ,
+                    nodes ,
+                    disk_bytes : 0 ,
+                }
+                ;
+            }
+            nodes += 1 ;
+            let s1 = self . c1 . search (& self . c0 , & key) ;
+            if ! budget . allows_next (nodes , 0) {
+                return SearchOutcome :: Exceeded {
+                    layer : 0usize ,
+                    nodes ,
+                    disk_bytes : 0 ,
+                }
+                ;
+            }
+            nodes += 1 ;
+            let value = self . c0 . search (s1 , & key) ;
+            let outcome = SearchOutcome :: from (value) ;
+            outcome
+        }
+    }
+    impl < K : Key , V : Value > CursorIndex < K , V > for ReadOnlyIndex < K , V > {
+        type Address = A0 ;
+        type Parent = A1 ;
+        type Base = C0 < K , V > ;
+        fn base (& self) -> & Self :: Base {
+            & self . c0
+        }
+        fn base_mut (& mut self) -> & mut Self :: Base {
+            & mut self . c0
+        }
+        fn locate (& self , key : & K) -> A0 {
+            let key = * key ;
+            let s1 = self . c1 . search (& self . c0 , & key) ;
+            s1
+        }
+        fn node_counts (& self) -> Vec < (usize , usize) > {
+            Self :: node_counts (self)
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" A cursor at the first entry whose key is at least `key`"] pub fn cursor (& self , key : K) -> Cursor < '_ , K , V , Self > {
+            Cursor :: new (self , & key)
+        }
+        # [doc = r" The entry with the smallest key, or `None` if the index is empty"] pub fn first (& self) -> Option < (& K , & V) > {
+            Cursor :: first (self) . current ()
+        }
+        # [doc = r" The entry with the largest key, or `None` if the index is empty"] pub fn last (& self) -> Option < (& K , & V) > {
+            Cursor :: last (self) . current ()
+        }
+        # [doc = r" A scan from the first entry whose key is at least `key`, which descends from the"] # [doc = r" top once and then hands out entries in batches with `next_n`"] pub fn seek (& self , key : & K) -> ScanHandle < '_ , K , V , Self > {
+            ScanHandle :: new (self , key)
+        }
+        # [doc = r" The keys present in both `self` and `other`, in key order"] pub fn intersect_keys < 'a > (& 'a self , other : & 'a Self) -> IntersectKeys < 'a , K , V , V , Self , Self > {
+            IntersectKeys :: new (self , other)
+        }
+        # [doc = r" The keys present in either `self` or `other`, in key order and without duplicates"] pub fn union < 'a > (& 'a self , other : & 'a Self) -> Union < 'a , K , V , V , Self , Self > {
+            Union :: new (self , other)
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Whether `key` is present, without cloning its value"] pub fn contains_key (& self , key : & K) -> bool {
+            let key = * key ;
+            let s1 = self . c1 . search (& self . c0 , & key) ;
+            self . c0 . get (s1 , & key) . is_some ()
+        }
+        # [doc = r" The entry stored at `key`, with both the key and the value borrowed from the index"] pub fn get_key_value (& self , key : & K) -> Option < (& K , & V) > {
+            let key = * key ;
+            let s1 = self . c1 . search (& self . c0 , & key) ;
+            let (key , stored) = self . c0 . get_entry (s1 , & key) ? ;
+            Some ((key , stored))
+        }
+    }
+    impl < K : Key , V : Value > From < BTreeMap < K , V >> for ReadOnlyIndex < K , V > {
+        fn from (map : BTreeMap < K , V >) -> Self {
+            Self :: build (map . into_iter ())
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Build an index over entries in any order, such as those of a `HashMap`, which are"] # [doc = r" sorted first. Of several entries with the same key, the last one is kept, as if"] # [doc = r" they had been inserted one by one."] pub fn from_unsorted (entries : impl IntoIterator < Item = (K , V) >) -> Self {
+            let mut entries : Vec < (K , V) > = entries . into_iter () . collect () ;
+            entries . reverse () ;
+            entries . sort_by_key (| (key , _) | * key) ;
+            entries . dedup_by_key (| (key , _) | * key) ;
+            Self :: build (entries . into_iter ())
+        }
+        # [doc = r" Build an index over entries sorted by key, failing if they aren't. Entries with the"] # [doc = r" same key are resolved as told by `duplicates`."] pub fn try_build (entries : impl IntoIterator < Item = (K , V) > , duplicates : Duplicates ,) -> :: core :: result :: Result < Self , BuildError < K >> {
+            let mut error = None ;
+            let index = Self :: build (CheckedEntries :: new (entries . into_iter () , duplicates , & mut error)) ;
+            match error {
+                Some (error) => Err (error) ,
+                None => Ok (index) ,
+            }
+        }
+        # [doc = r" Build an index over entries the caller guarantees are sorted by key without"] # [doc = r" duplicates, which is not checked. Entries out of order leave the index unable to"] # [doc = r" find some of its keys."] pub fn build_unchecked (entries : impl IntoIterator < Item = (K , V) >) -> Self {
+            Self :: build (entries . into_iter ())
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" A copy of the index which shares every node with it, until either of them modifies"] # [doc = r#" the node. Cheap enough to fork an index for a "what-if" experiment. The first"#] # [doc = r" snapshot moves every node behind a reference count, which clones of either index"] # [doc = r" share as well."] pub fn snapshot (& mut self) -> Self {
+            self . c0 . share_nodes () ;
+            self . clone ()
+        }
+        # [doc = r" Number of nodes still shared with a snapshot or clone of the index"] pub fn shared_nodes (& self) -> usize {
+            0 + self . c0 . shared_count ()
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Estimated bytes of memory held by every layer of the index below the top"] pub fn memory_usage (& self) -> usize {
+            0 + self . c0 . memory_usage ()
+        }
+    }
+    impl < K : Key , V : Value > ReadOnlyIndex < K , V > {
+        # [doc = r" Lay out the nodes of every layer next to each other in key order, which restores"] # [doc = r" the locality of scans after many inserts have split nodes. Every node moves, and a"] # [doc = r" `SearchHint` into the index misses until it's filled again."] pub fn compact_memory (& mut self) {
+            let remap = self . c0 . compact () ;
+            self . c1 . remap_children (& remap) ;
+        }
+    }
+}
+use __readonlyindex :: ReadOnlyIndex ;
+
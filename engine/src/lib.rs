@@ -30,6 +30,14 @@
 //! have two in-memory PGM learned layers with epsilon parameters of 8,
 //! and a tiny in-memory BTree as a top layer.
 //!
+//! The components and options a layout accepts are listed on
+//! `create_kv_store!`. The methods generated for an index, and the types
+//! re-exported here, are documented where they are defined.
+//!
+//! The `std` feature is enabled by default. Without it the engine is
+//! `no_std` and only needs `alloc`, which is enough for in-memory layouts,
+//! as in `examples/browser_demo`.
+//!
 //! **Since learned components are not yet fully supported, the above example
 //! will not compile. To get a working key-value store in the current version,
 //! we should only use BTree components.**
@@ -63,17 +71,120 @@
 //!
 //! assert_eq!(index.search(10)?, Some(50));
 //! ```
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 
 /// Include this at the top of the file when materializing a hybrid index or using a hybrid index.
 pub mod prelude {
     pub use limousine_derive::create_kv_store;
+    pub use limousine_derive::Projectable;
 
     pub use limousine_core::KVStore;
+    #[cfg(feature = "std")]
     pub use limousine_core::PersistedKVStore;
+    pub use limousine_core::VersionedKVStore;
+
+    pub use limousine_core::LinearModel;
+    pub use limousine_core::PersistedModel;
+    pub use limousine_core::SegmentationModel;
 }
 
+pub use limousine_core::AutoChoice;
+pub use limousine_core::AutoDecision;
+pub use limousine_core::BTreeTop;
+pub use limousine_core::BatchError;
+pub use limousine_core::BloomFilter;
+pub use limousine_core::BuildError;
+pub use limousine_core::CapacityError;
+pub use limousine_core::CapacityExceeded;
+pub use limousine_core::CasError;
+pub use limousine_core::Change;
+pub use limousine_core::ChangesError;
+pub use limousine_core::Cursor;
+pub use limousine_core::CursorError;
+pub use limousine_core::CursorIndex;
+pub use limousine_core::CursorMut;
+pub use limousine_core::DiffReport;
+pub use limousine_core::Duplicates;
+pub use limousine_core::FieldSelector;
+pub use limousine_core::FilterKind;
+pub use limousine_core::FilterSegment;
+pub use limousine_core::Index;
+pub use limousine_core::IndexRead;
+pub use limousine_core::IndexWrite;
+pub use limousine_core::IntersectKeys;
+pub use limousine_core::KeyBound;
+pub use limousine_core::KeyFilter;
+pub use limousine_core::LayerDiff;
+pub use limousine_core::LayerPlot;
+pub use limousine_core::LayerReport;
+pub use limousine_core::LookupStep;
+pub use limousine_core::LookupTrace;
+pub use limousine_core::Lsn;
+pub use limousine_core::MaintenanceConfig;
+pub use limousine_core::MaintenanceStats;
+pub use limousine_core::ManualMaintenance;
+pub use limousine_core::MergeFn;
+pub use limousine_core::Merging;
+pub use limousine_core::OccupiedError;
+pub use limousine_core::Probe;
+pub use limousine_core::ProbeBudget;
+pub use limousine_core::Projectable;
+pub use limousine_core::QuickInsert;
+pub use limousine_core::RMITop;
+pub use limousine_core::RebuildComponent;
+pub use limousine_core::RebuildPlan;
 pub use limousine_core::Result;
+pub use limousine_core::ScanHandle;
+pub use limousine_core::Scope;
+pub use limousine_core::ScopeRange;
+pub use limousine_core::SearchHint;
+pub use limousine_core::SearchOutcome;
+pub use limousine_core::Sequenced;
+pub use limousine_core::SlabStore;
+pub use limousine_core::Snapshot;
+pub use limousine_core::StaleAddress;
+pub use limousine_core::TopComponent;
+pub use limousine_core::TopKind;
+pub use limousine_core::Union;
+pub use limousine_core::ValueHandle;
+pub use limousine_core::ValueStore;
+pub use limousine_core::Version;
+pub use limousine_core::WriteBatch;
+pub use limousine_core::U256;
+
+pub use limousine_core::diff;
+
+#[cfg(feature = "std")]
+pub use limousine_core::{
+    AttachScheduler, CachePolicy, CacheStats, CursorPage, CursorToken, DiskBuilder, DiskStats,
+    DiskUsage, DriftMonitor, ExportSorted, FastFences, FileBackend, GlobalStore, ImportSorted,
+    IndexStats, LocalStore, MaintenanceScheduler, MarbleBackend, MemoryBackend, PageDelta, Rcu,
+    RcuGuard, RecoveryReport, Shadowed, Sharded, ShardedRange, ShardedRead, SortedReader,
+    SortedWriter, StorageBackend, StorageStats, StoreLocked, WarmStats,
+};
+
+#[cfg(feature = "std")]
+pub use limousine_core::ingest;
+#[cfg(feature = "std")]
+pub use limousine_core::testkit;
+#[cfg(feature = "std")]
+pub use limousine_core::workloads;
+
+#[cfg(feature = "encryption")]
+pub use limousine_core::{Encoded, EncodedRange, EncryptionKey, OrderPreserving};
+
+#[cfg(feature = "metrics")]
+pub use limousine_core::{LatencyHistogram, LatencySummary, Percentiles};
+
+#[cfg(feature = "olc")]
+pub use limousine_core::{OlcBTree, OlcWord};
+
+#[cfg(feature = "async")]
+pub use limousine_core::{AsyncIndex, Executor, Job, Task, ThreadExecutor};
+
+#[cfg(feature = "async")]
+pub use limousine_core::{ChangeEvent, Lagged, WatchItem, WatchStream, DEFAULT_WATCH_CAPACITY};
 
 #[doc(hidden)]
 pub use limousine_core as private;
@@ -0,0 +1,77 @@
+//! A PGM index in the browser. The page fetches a list of keys, hands it over to `Demo::new`, and
+//! looks keys up as they are typed in.
+//!
+//! Build with `wasm-pack build --target web` from this directory, then serve the directory over
+//! HTTP and open `index.html`. Without `std`, the engine needs no threads, clock or files, which
+//! `wasm32-unknown-unknown` doesn't have, so maintenance is ticked by the page instead.
+
+use limousine_engine::prelude::*;
+use limousine_engine::ManualMaintenance;
+use wasm_bindgen::prelude::*;
+
+create_kv_store! {
+    name: DemoIndex,
+    layout: [
+        btree_top(),
+        pgm(epsilon = 8),
+        pgm(epsilon = 16),
+    ],
+}
+
+/// Inserts between rebuilds of the learned layers
+const TICKS_PER_REBUILD: u64 = 1024;
+
+#[wasm_bindgen]
+pub struct Demo {
+    index: DemoIndex<u64, u32>,
+    maintenance: ManualMaintenance<DemoIndex<u64, u32>>,
+}
+
+#[wasm_bindgen]
+impl Demo {
+    /// Index `keys` in any order, mapping each key to its position in the list
+    #[wasm_bindgen(constructor)]
+    pub fn new(keys: Vec<u64>) -> Demo {
+        let index = DemoIndex::from_unsorted(keys.into_iter().zip(0..));
+
+        let mut maintenance = ManualMaintenance::new();
+        maintenance.every(
+            "rebuild",
+            TICKS_PER_REBUILD,
+            |index: &mut DemoIndex<u64, u32>| {
+                index.rebuild();
+                0
+            },
+        );
+
+        Demo { index, maintenance }
+    }
+
+    /// Position of `key` in the fetched list, if it was there
+    pub fn search(&self, key: u64) -> Option<u32> {
+        self.index.search(key)
+    }
+
+    /// Add `key` at `position`, rebuilding the internal layers every so many inserts
+    pub fn insert(&mut self, key: u64, position: u32) {
+        self.index.insert(key, position);
+        self.maintenance.tick(&mut self.index);
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of nodes in each learned layer, from the base up
+    pub fn nodes(&self) -> Vec<u32> {
+        self.index
+            .node_counts()
+            .into_iter()
+            .map(|(_, nodes)| nodes as u32)
+            .collect()
+    }
+}
@@ -0,0 +1,55 @@
+//! Smoke test for in-memory layouts in a `no_std` crate, which only have to depend on `alloc`.
+//!
+//! Build this crate on its own with `cargo build -p limousine_no_std`, since building it along
+//! with the rest of the workspace unifies the features of the engine and turns `std` back on.
+//! Adding `--target wasm32-unknown-unknown` checks the same layouts build for the browser.
+
+#![no_std]
+
+use limousine_engine::prelude::*;
+use limousine_engine::SlabStore;
+
+create_kv_store! {
+    name: BTreeStore,
+    layout: [
+        btree_top(),
+        btree(fanout = 8),
+        btree(fanout = 32),
+    ],
+    values: handles,
+}
+
+create_kv_store! {
+    name: PGMStore,
+    layout: [
+        btree_top(),
+        pgm(epsilon = 8),
+        pgm(epsilon = 8),
+    ],
+}
+
+/// Build both layouts over `size` keys and insert as many more, returning whether every key is
+/// found in both afterwards
+pub fn smoke(size: u64) -> bool {
+    let mut btree: BTreeStore<u64, u64> = BTreeStore::build((0..size).map(|key| (key * 2, key)));
+    let pgm: PGMStore<u64, u64> = PGMStore::from_unsorted((0..size).rev().map(|key| (key, key)));
+
+    for key in 0..size {
+        btree.insert(key * 2 + 1, key);
+    }
+
+    let handles = BTreeStoreHandles::build((0..size).map(|key| (key, key)), SlabStore::new());
+    let found = (0..2 * size).all(|key| btree.search(key) == Some(key / 2))
+        && (0..size).all(|key| pgm.search(key) == Some(key))
+        && (0..size).all(|key| handles.search(key) == Some(&key));
+
+    found && btree.layer_report().is_empty() && pgm.layer_report().len() == 2
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn in_memory_layouts() {
+        assert!(super::smoke(10_000));
+    }
+}
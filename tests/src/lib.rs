@@ -8,6 +8,165 @@ mod tests {
     type K = i128;
     type V = i128;
 
+    /// A toy model which cuts the data into fixed size segments and interpolates between the
+    /// smallest and largest key of each segment, used to exercise custom models in layouts
+    #[derive(Clone)]
+    struct InterpolationModel<T, const EPSILON: usize> {
+        min: T,
+        max: T,
+        size: usize,
+    }
+
+    impl<T: num::PrimInt + 'static, const EPSILON: usize> InterpolationModel<T, EPSILON> {
+        fn fit<X>(entries: Vec<(T, X)>) -> (Self, Vec<(T, X)>) {
+            let model = Self {
+                min: entries.first().unwrap().0,
+                max: entries.last().unwrap().0,
+                size: entries.len(),
+            };
+
+            (model, entries)
+        }
+    }
+
+    impl<T: num::PrimInt + 'static, const EPSILON: usize> SegmentationModel<T>
+        for InterpolationModel<T, EPSILON>
+    {
+        fn train<X>(data: impl Iterator<Item = (T, X)>) -> Vec<(Self, Vec<(T, X)>)> {
+            let mut result = Vec::new();
+            let mut segment = Vec::new();
+
+            for entry in data {
+                segment.push(entry);
+
+                if segment.len() == 16 * EPSILON {
+                    result.push(Self::fit(std::mem::take(&mut segment)));
+                }
+            }
+
+            if !segment.is_empty() {
+                result.push(Self::fit(segment));
+            }
+
+            result
+        }
+
+        fn sentinel() -> Self {
+            Self {
+                min: T::max_value(),
+                max: T::max_value(),
+                size: 0,
+            }
+        }
+
+        fn min_key(&self) -> &T {
+            &self.min
+        }
+
+        fn approximate(&self, _: &T) -> std::ops::Range<usize> {
+            0..self.size
+        }
+
+        fn hint(&self, key: &T) -> usize {
+            let cast = |x: T| num::cast::<T, f64>(x).unwrap();
+            let width = cast(self.max) - cast(self.min);
+
+            if *key <= self.min || width <= 0.0 {
+                return 0;
+            }
+
+            let pos = (cast(*key) - cast(self.min)) / width * self.size.saturating_sub(1) as f64;
+            (pos as usize).min(self.size.saturating_sub(1))
+        }
+
+        fn rescale(&mut self, c: f64) {
+            self.size = (self.size as f64 * c) as usize;
+        }
+    }
+
+    /// Collects the fields of every event as `name=value` strings
+    #[derive(Clone, Default)]
+    struct EventCollector {
+        events: std::sync::Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+    }
+
+    struct FieldVisitor(Vec<String>);
+
+    impl tracing::field::Visit for FieldVisitor {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for EventCollector {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut fields = FieldVisitor(Vec::new());
+            event.record(&mut fields);
+            self.events.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    /// Counts the pages read from a `MemoryBackend`, and fails a single read once `reads_left`
+    /// more reads succeeded
+    #[derive(Clone)]
+    struct CountingBackend {
+        inner: limousine_engine::MemoryBackend,
+        reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        reads_left: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl CountingBackend {
+        fn new() -> Self {
+            Self {
+                inner: limousine_engine::MemoryBackend::new(),
+                reads: Default::default(),
+                reads_left: std::sync::Arc::new(usize::MAX.into()),
+            }
+        }
+    }
+
+    impl limousine_engine::StorageBackend for CountingBackend {
+        fn read(&self, id: u64) -> limousine_engine::Result<Option<Vec<u8>>> {
+            use std::sync::atomic::Ordering;
+
+            if self.reads_left.fetch_sub(1, Ordering::Relaxed) == 0 {
+                self.reads_left.store(usize::MAX, Ordering::Relaxed);
+                return Err(std::io::Error::other("injected read failure").into());
+            }
+
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.inner.read(id)
+        }
+
+        fn write_batch(&self, batch: Vec<(u64, Option<Vec<u8>>)>) -> limousine_engine::Result<()> {
+            self.inner.write_batch(batch)
+        }
+
+        fn maintenance(&self) -> limousine_engine::Result<usize> {
+            self.inner.maintenance()
+        }
+
+        fn stats(&self) -> limousine_engine::StorageStats {
+            self.inner.stats()
+        }
+    }
+
     fn test_persisted_kv_store<KV: PersistedKVStore<K, V>>() -> limousine_engine::Result<()> {
         let temp_dir = tempdir()?;
         let temp_path = temp_dir.path();
@@ -126,7 +285,7 @@ mod tests {
 
         {
             // Test build
-            let kv_store = KV::build(keys.clone().into_iter().zip(values.clone().into_iter()));
+            let kv_store = KV::build(keys.clone().into_iter().zip(values.clone()));
 
             // Test searches
             for i in 0..num {
@@ -139,6 +298,168 @@ mod tests {
         }
     }
 
+    /// Searches empty indexes, both created with `empty` and built from no entries, at the extreme
+    /// keys, and then checks that inserts into them are found, the extremes included
+    fn test_kv_store_empty<KV: KVStore<K, V>>() {
+        let probes = [K::MIN, K::MIN + 1, -1, 0, 1, K::MAX - 1, K::MAX];
+
+        for mut kv_store in [KV::empty(), KV::build(std::iter::empty())] {
+            for key in probes {
+                assert_eq!(kv_store.search(key), None);
+            }
+
+            kv_store.insert(7, 70);
+            kv_store.insert(K::MAX, 2);
+            kv_store.insert(K::MIN, 1);
+
+            assert_eq!(kv_store.search(7), Some(70));
+            assert_eq!(kv_store.search(K::MAX), Some(2));
+            assert_eq!(kv_store.search(K::MIN), Some(1));
+            for key in probes.into_iter().filter(|&key| key != K::MIN && key != K::MAX) {
+                assert_eq!(kv_store.search(key), None);
+            }
+
+            for key in 0..1_000 {
+                kv_store.insert(key * 3, key);
+            }
+            for key in 0..1_000 {
+                assert_eq!(kv_store.search(key * 3), Some(key));
+                assert_eq!(kv_store.search(key * 3 + 2), None);
+            }
+            assert_eq!(kv_store.search(K::MAX), Some(2));
+            assert_eq!(kv_store.search(K::MIN), Some(1));
+        }
+    }
+
+    /// Overwrites every key a few times, and checks that each snapshot still sees the values of its
+    /// own version, before and after garbage collecting older versions
+    fn test_versioned_kv_store<KV: VersionedKVStore<K, V>>() {
+        let mut rng = thread_rng();
+        let key_dist = Uniform::new(K::MIN, K::MAX);
+
+        let num = 5_000;
+        let rounds = 3;
+        let keys: Vec<K> = (&mut rng)
+            .sample_iter(key_dist)
+            .filter(|&x| x < 0 as K || x > 10_000 as K)
+            .take(num)
+            .collect();
+
+        let mut kv_store = KV::empty();
+        assert_eq!(kv_store.version(), 0);
+
+        // Version after each round of writes
+        let mut versions = Vec::new();
+
+        for round in 0..rounds {
+            for &key in keys.iter() {
+                kv_store.insert(key, key.wrapping_add(round as V));
+            }
+
+            versions.push(kv_store.version());
+        }
+
+        assert_eq!(
+            kv_store.version(),
+            (num * rounds) as limousine_engine::Version
+        );
+
+        for (round, &version) in versions.iter().enumerate() {
+            let snapshot = kv_store.snapshot_at(version);
+
+            for &key in keys.iter() {
+                assert_eq!(snapshot.search(key), Some(key.wrapping_add(round as V)));
+            }
+        }
+
+        for &key in keys.iter() {
+            assert_eq!(kv_store.search_at(key, 0), None);
+            assert_eq!(
+                kv_store.search(key),
+                Some(key.wrapping_add(rounds as V - 1))
+            );
+        }
+
+        for key in 0..10_000 {
+            assert_eq!(kv_store.search_at(key as K, kv_store.version()), None);
+        }
+
+        // Only the history before the second round is dropped
+        kv_store.gc(versions[1]);
+
+        for &key in keys.iter() {
+            assert_eq!(
+                kv_store.search_at(key, versions[1]),
+                Some(key.wrapping_add(1))
+            );
+            assert_eq!(
+                kv_store.search_at(key, versions[2]),
+                Some(key.wrapping_add(2))
+            );
+        }
+    }
+
+    /// Checks that a cloned index is fully independent of the original, so inserts into the clone
+    /// never become visible through the original
+    fn test_kv_store_clone<KV: KVStore<K, V> + Clone>() {
+        let mut rng = thread_rng();
+        let key_dist = Uniform::new(K::MIN, K::MAX);
+        let value_dist = Uniform::new(V::MIN, V::MAX);
+
+        let num = 10_000;
+        let keys: Vec<K> = (&mut rng)
+            .sample_iter(key_dist)
+            .filter(|&x| x < 0 as K || x > 10_000 as K)
+            .take(2 * num)
+            .collect();
+
+        let values: Vec<V> = (&mut rng).sample_iter(value_dist).take(2 * num).collect();
+
+        {
+            let mut kv_store = KV::empty();
+
+            for i in 0..num {
+                kv_store.insert(keys[i], values[i]);
+            }
+
+            let mut cloned = kv_store.clone();
+
+            // Mutate the clone, which forces node splits in every layer
+            for i in num..2 * num {
+                cloned.insert(keys[i], values[i]);
+            }
+
+            for i in 0..num {
+                assert_eq!(kv_store.search(keys[i]), Some(values[i]));
+                assert_eq!(cloned.search(keys[i]), Some(values[i]));
+            }
+
+            for i in num..2 * num {
+                if !keys[..num].contains(&keys[i]) {
+                    assert_eq!(kv_store.search(keys[i]), None);
+                }
+                assert_eq!(cloned.search(keys[i]), Some(values[i]));
+            }
+        }
+    }
+
+    /// Drives an index purely through the `Index` traits, so the same checks apply to in-memory
+    /// and persisted indexes alike
+    fn test_index<I: limousine_engine::Index<K, V>>(index: &mut I) -> limousine_engine::Result<()> {
+        for key in 0..1_000 {
+            assert_eq!(index.try_put(key, key * 2)?, None);
+        }
+
+        for key in 0..1_000 {
+            assert_eq!(index.try_search(key)?, Some(key * 2));
+        }
+
+        assert_eq!(index.try_search(1_000)?, None);
+        assert_eq!(index.try_put(0, 1)?, Some(0));
+
+        Ok(())
+    }
+
     #[test]
     fn test_persisted_kv_store_1() -> limousine_engine::Result<()> {
         create_kv_store! {
@@ -152,6 +473,20 @@ mod tests {
         test_persisted_kv_store::<KVStore1<K, V>>()
     }
 
+    #[test]
+    fn test_persisted_kv_store_top_max_entries() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(max_entries = 4),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        test_persisted_kv_store::<KVStore1<K, V>>()
+    }
+
     #[test]
     fn test_persisted_kv_store_2() -> limousine_engine::Result<()> {
         create_kv_store! {
@@ -247,119 +582,5392 @@ mod tests {
     }
 
     #[test]
-    fn test_kv_store_1() {
+    fn test_persisted_kv_store_bucket() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
-                btree(fanout = 64),
+                bucket(count = 16),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        test_persisted_kv_store::<KVStore1<K, V>>()
     }
 
     #[test]
-    fn test_kv_store_2() {
+    fn test_persisted_kv_store_lz4() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
-                btree(fanout = 32),
+                btree(fanout = 8),
+                btree(fanout = 8, persist, compression = lz4),
+                btree(fanout = 32, persist, compression = lz4),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        test_persisted_kv_store::<KVStore1<K, V>>()
     }
 
     #[test]
-    fn test_kv_store_3() {
+    fn test_persisted_kv_store_zstd() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
-                btree(fanout = 32),
-                btree(fanout = 32),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 64, persist, compression = zstd(3)),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        test_persisted_kv_store::<KVStore1<K, V>>()
     }
 
     #[test]
-    fn test_kv_store_4() {
+    fn test_persisted_kv_store_disk_usage() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
                 btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 32),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+            // Overwrite every key, leaving dead pages behind
+            for round in 0..2 {
+                for key in 0..5_000 {
+                    index.insert(key, key + round)?;
+                }
+            }
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+        let usage = index.disk_usage();
+
+        let layers: Vec<usize> = usage.layers.iter().map(|(layer, _)| *layer).collect();
+        assert_eq!(layers, vec![0, 1]);
+        assert!(usage.layers[0].1.pages > usage.layers[1].1.pages);
+        assert!(usage.layers[0].1.bytes > 0);
+        assert!(usage.live_bytes > 0);
+        assert!(usage.value_log.is_none());
+
+        index.compact()?;
+        assert!(index.disk_usage().dead_bytes <= usage.dead_bytes);
+
+        for key in 0..5_000 {
+            assert_eq!(index.search(key)?, Some(key + 1));
+        }
+
+        Ok(())
     }
 
     #[test]
-    fn test_kv_store_5() {
+    fn test_persisted_kv_store_stats() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
                 btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 32),
+                btree(fanout = 32, persist),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+            for key in 0..1_000 {
+                index.insert(key, key)?;
+            }
+
+            index.compact()?;
+            assert_eq!(index.stats().inserts, 1_000);
+        }
+
+        let written = {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+            let stats = index.stats();
+
+            assert_eq!(stats.inserts, 1_000);
+            assert_eq!(stats.compactions, 1);
+            assert_eq!(stats.opens, 2);
+            assert!(stats.bytes_written > 0);
+            assert!(stats.created_at > 0);
+
+            for key in 0..500 {
+                index.insert(key, key + 1)?;
+            }
+
+            assert_eq!(index.stats().inserts, 1_500);
+            stats.bytes_written
+        };
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+            assert_eq!(index.stats().inserts, 1_500);
+            assert!(index.stats().bytes_written > written);
+
+            index.reset_stats();
+            index.insert(0, 0)?;
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+        let stats = index.stats();
+
+        assert_eq!(stats.inserts, 1);
+        assert_eq!(stats.compactions, 0);
+        assert_eq!(stats.opens, 1);
+
+        Ok(())
     }
 
     #[test]
-    fn test_kv_store_6() {
+    fn test_persisted_kv_store_warm() -> limousine_engine::Result<()> {
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
                 btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 32),
+                btree(fanout = 4, persist),
+                btree(fanout = 4, persist),
+                btree(fanout = 32, persist),
             ]
         }
 
-        test_kv_store::<KVStore1<K, V>>();
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+            for key in 0..5_000 {
+                index.insert(key, key)?;
+            }
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+        let usage = index.disk_usage();
+        let internal_pages = usage.layers[1].1.pages + usage.layers[2].1.pages;
+
+        // An empty range only reads the internal layers. The upper one was already read to build
+        // the in-memory layer above it, but the lower one is cold.
+        let stats = index.warm(0..0)?;
+        assert_eq!(stats.pages + stats.cached, internal_pages);
+        assert!(stats.pages > 0);
+        assert!(stats.bytes > 0);
+
+        let stats = index.warm(1_000..2_000)?;
+        assert_eq!(stats.cached, internal_pages);
+        assert!(stats.pages > 0);
+
+        // Warming the same range again doesn't read anything
+        let again = index.warm(1_000..2_000)?;
+        assert_eq!(again.pages, 0);
+        assert_eq!(again.bytes, 0);
+        assert_eq!(again.cached, internal_pages + stats.pages);
+
+        let stats = index.warm(..)?;
+        assert_eq!(stats.pages + stats.cached, usage.layers[0].1.pages + internal_pages);
+
+        for key in 0..5_000 {
+            assert_eq!(index.search(key)?, Some(key));
+        }
+
+        Ok(())
     }
 
     #[test]
-    fn test_kv_store_7() {
+    fn test_persisted_kv_store_cache_policy() -> limousine_engine::Result<()> {
+        use limousine_engine::CachePolicy;
+
         create_kv_store! {
             name: KVStore1,
             layout: [
                 btree_top(),
                 btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-                btree(fanout = 8),
-            ]
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ],
+            cache_policy: tinylfu,
         }
 
-        test_kv_store::<KVStore1<K, V>>();
-    }
+        let temp_dir = tempdir()?;
 
-    #[test]
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
 
-    fn test_pgm_store_3() {
-        create_kv_store! {
-            name: PGMStore1,
-            layout: [
-                btree_top(),
+            for key in 0..5_000 {
+                index.insert(key, key)?;
+            }
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+        assert_eq!(index.cache_stats().policy, CachePolicy::TinyLfu);
+
+        for _ in 0..2 {
+            for key in 0..5_000 {
+                assert_eq!(index.search(key)?, Some(key));
+            }
+        }
+
+        // Every page fits in the cache, so only the first lookup into a page misses
+        let stats = index.cache_stats();
+        assert!(stats.misses > 0);
+        assert!(stats.hit_rate() > 0.9);
+        assert_eq!(stats.evictions, 0);
+        assert_eq!(stats.rejected, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_latency_summary() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+        let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            index.insert(key, key)?;
+        }
+        for key in 0..500 {
+            index.search(key * 2)?;
+        }
+        index.resume(&index.cursor_token(0)?, 100)?;
+
+        let summary = index.latency_summary();
+        assert_eq!(summary.insert.count, 1_000);
+        assert_eq!(summary.search.count, 500);
+        assert_eq!(summary.range.count, 1);
+
+        for latencies in [summary.search, summary.insert, summary.range] {
+            assert!(latencies.p50 <= latencies.p99);
+            assert!(latencies.p99 <= latencies.p999);
+            assert!(latencies.p999 <= latencies.max);
+            assert!(latencies.max > std::time::Duration::ZERO);
+        }
+
+        index.reset_stats();
+        assert_eq!(index.latency_summary().insert.count, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_cursor_token() -> limousine_engine::Result<()> {
+        use limousine_engine::CursorToken;
+        use std::collections::BTreeMap;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8, persist),
+                btree(fanout = 16, persist),
+            ]
+        }
+
+        fn page_through<KV>(
+            open: impl Fn(&std::path::Path) -> limousine_engine::Result<KV>,
+            resume: impl Fn(
+                &KV,
+                &[u8],
+                usize,
+            ) -> limousine_engine::Result<(Vec<(K, V)>, Option<Vec<u8>>)>,
+            token: impl Fn(&KV, K) -> limousine_engine::Result<Vec<u8>>,
+        ) -> limousine_engine::Result<()>
+        where
+            KV: PersistedKVStore<K, V>,
+        {
+            let temp_dir = tempdir()?;
+            let mut expected = BTreeMap::new();
+
+            {
+                let mut index = open(temp_dir.path())?;
+                assert_eq!(resume(&index, &token(&index, 0)?, 10)?, (vec![], None));
+
+                for key in 0..2_000 {
+                    index.insert(key * 4, key)?;
+                    expected.insert(key * 4, key);
+                }
+            }
+
+            let mut bytes = token(&open(temp_dir.path())?, 100)?;
+            expected.retain(|&key, _| key > 100);
+
+            let mut pages = 0;
+            let mut seen = Vec::new();
+
+            loop {
+                // Every page is read by a fresh process, from a token kept as bytes
+                let mut index = open(temp_dir.path())?;
+                let (entries, next) = resume(&index, &bytes, 64)?;
+                assert!(entries.len() <= 64);
+                seen.extend(entries.iter().copied());
+                pages += 1;
+
+                let Some(next) = next else {
+                    break;
+                };
+                assert_eq!(entries.len(), 64);
+
+                // Writes between pages split the node the token points at, and keys behind the
+                // cursor are never read
+                let after = seen.last().unwrap().0;
+                for key in 1..4 {
+                    index.insert(after + key, -key)?;
+                    expected.insert(after + key, -key);
+                    index.insert(after - 4 * key + 1, -key)?;
+                }
+
+                bytes = next;
+            }
+
+            assert!(pages > 30);
+            assert_eq!(seen, expected.into_iter().collect::<Vec<_>>());
+
+            let index = open(temp_dir.path())?;
+            assert!(resume(&index, b"not a token", 10).is_err());
+
+            Ok(())
+        }
+
+        page_through(
+            |path: &std::path::Path| KVStore1::<K, V>::open(path),
+            |index: &KVStore1<K, V>, bytes: &[u8], limit| {
+                let page = index.resume(&CursorToken::from_bytes(bytes)?, limit)?;
+                let next = page.next.map(|token| token.to_bytes()).transpose()?;
+                Ok((page.entries, next))
+            },
+            |index: &KVStore1<K, V>, key| index.cursor_token(key)?.to_bytes(),
+        )?;
+
+        page_through(
+            |path: &std::path::Path| KVStore2::<K, V>::open(path),
+            |index: &KVStore2<K, V>, bytes: &[u8], limit| {
+                let page = index.resume(&CursorToken::from_bytes(bytes)?, limit)?;
+                let next = page.next.map(|token| token.to_bytes()).transpose()?;
+                Ok((page.entries, next))
+            },
+            |index: &KVStore2<K, V>, key| index.cursor_token(key)?.to_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_persisted_kv_store_build_external() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::TestRng;
+        use std::collections::BTreeMap;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+        let mut rng = TestRng::from_env();
+
+        // Keys in random order, with duplicates which are resolved in favor of the last entry
+        let entries: Vec<(K, V)> = (0..20_000)
+            .map(|value| (rng.below(10_000) as K, value))
+            .collect();
+        let expected: BTreeMap<K, V> = entries.iter().copied().collect();
+
+        {
+            let mut index: KVStore1<K, V> =
+                KVStore1::build_external(temp_dir.path(), entries.clone(), 1_000)?;
+
+            for key in 0..10_000 {
+                assert_eq!(index.search(key)?, expected.get(&key).copied());
+            }
+
+            index.insert(20_000, 0)?;
+        }
+
+        // Only an empty index can be built into
+        assert!(KVStore1::<K, V>::build_external(temp_dir.path(), entries, 1_000).is_err());
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key)?, expected.get(&key).copied());
+        }
+
+        assert_eq!(index.search(20_000)?, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_projection() -> limousine_engine::Result<()> {
+        #[derive(
+            Clone, Default, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize, Projectable,
+        )]
+        struct Row {
+            id: u64,
+            name: String,
+            payload: Vec<u8>,
+        }
+
+        fn row(key: K) -> Row {
+            Row {
+                id: key as u64,
+                name: format!("row {}", key),
+                payload: vec![key as u8; 256],
+            }
+        }
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore1<K, Row> = KVStore1::open(temp_dir.path())?;
+
+            for key in (0..2_000).map(|key| key * 2) {
+                index.insert(key, row(key))?;
+            }
+
+            // Pages which are still cached are projected in memory
+            assert_eq!(index.search_project::<row_fields::Id>(10)?, Some(10));
+        }
+
+        let index: KVStore1<K, Row> = KVStore1::open(temp_dir.path())?;
+
+        for key in (0..2_000).map(|key| key * 2) {
+            assert_eq!(
+                index.search_project::<row_fields::Name>(key)?,
+                Some(row(key).name)
+            );
+            assert_eq!(
+                index.search_project::<(row_fields::Payload, row_fields::Id)>(key)?,
+                Some((row(key).payload, key as u64))
+            );
+            assert_eq!(index.search_project::<row_fields::Id>(key + 1)?, None);
+            assert!(index.contains_key(&key)?);
+            assert!(!index.contains_key(&(key + 1))?);
+        }
+
+        assert_eq!(index.search_project::<row_fields::Id>(-1)?, None);
+        assert_eq!(index.search_project::<row_fields::Id>(4_000)?, None);
+        assert_eq!(index.search(42)?, Some(row(42)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_memory_backend() -> limousine_engine::Result<()> {
+        use limousine_engine::MemoryBackend;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let backend = MemoryBackend::new();
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+
+            for key in 0..2_000 {
+                index.insert(key, key + 1)?;
+            }
+        }
+
+        // Clones share their pages, so the index can be opened again
+        let index: KVStore1<K, V> = KVStore1::open_with_backend(backend)?;
+
+        for key in 0..2_000 {
+            assert_eq!(index.search(key)?, Some(key + 1));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_deterministic() -> limousine_engine::Result<()> {
+        use limousine_engine::{MemoryBackend, StorageBackend};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ],
+            deterministic: true,
+        }
+
+        let build = || -> limousine_engine::Result<MemoryBackend> {
+            let backend = MemoryBackend::new();
+            let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+
+            for key in 0..3_000 {
+                index.insert(key * 7 % 3_001, key)?;
+            }
+
+            assert_eq!(index.stats().created_at, 0);
+            Ok(backend)
+        };
+
+        // Both stores hold the same bytes, page for page
+        let (first, second) = (build()?, build()?);
+        assert_eq!(first.stats(), second.stats());
+
+        // Freed ids read as `None` from both
+        for id in 0..10_000 {
+            assert_eq!(first.read(id)?, second.read(id)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_open_read_only() -> limousine_engine::Result<()> {
+        use limousine_engine::FileBackend;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let dir = tempfile::tempdir()?;
+        let mut index: KVStore1<K, V> = KVStore1::open_with_backend(FileBackend::open(&dir)?)?;
+
+        // Applying a batch flushes the index, so the inspector sees every key
+        let mut batch = limousine_engine::WriteBatch::new();
+        for key in 0..2_000 {
+            batch.put(key, key + 1);
+        }
+        index.apply_batch(batch)?;
+
+        // An inspector reads the index alongside its owner, but can't write to it
+        {
+            let mut inspector: KVStore1<K, V> = KVStore1::open_read_only_with_backend(FileBackend::open_read_only(&dir)?)?;
+            for key in 0..2_000 {
+                assert_eq!(inspector.search(key)?, Some(key + 1));
+            }
+
+            assert!(inspector.insert(5_000, 0).is_err());
+        }
+
+        // A batch the owner is still applying is left to the owner, and isn't seen
+        let mut batch = limousine_engine::WriteBatch::new();
+        batch.put(6_000, 1);
+        index.wal.log(&batch)?;
+
+        {
+            let inspector: KVStore1<K, V> = KVStore1::open_read_only_with_backend(FileBackend::open_read_only(&dir)?)?;
+            assert_eq!(inspector.search(6_000)?, None);
+            assert_eq!(inspector.search(1_999)?, Some(2_000));
+            assert!(inspector.wal.pending().is_some());
+        }
+
+        index.wal.clear()?;
+        index.insert(5_000, 1)?;
+        assert_eq!(index.search(5_000)?, Some(1));
+
+        // A second owner of the directory is turned away
+        let err = FileBackend::open(&dir).err().unwrap();
+        assert!(err.downcast_ref::<limousine_engine::StoreLocked>().is_some());
+
+        // By path, the index can only be inspected once its owner is gone
+        let path = dir.path().join("by_path");
+        let mut owner: KVStore1<K, V> = KVStore1::open(&path)?;
+        owner.insert(7, 8)?;
+
+        let err = KVStore1::<K, V>::open_read_only(&path).err().unwrap();
+        assert!(err.downcast_ref::<limousine_engine::StoreLocked>().is_some());
+
+        drop(owner);
+        let inspector: KVStore1<K, V> = KVStore1::open_read_only(&path)?;
+        assert_eq!(inspector.search(7)?, Some(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_reopen_reads() -> limousine_engine::Result<()> {
+        use std::sync::atomic::Ordering;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let backend = CountingBackend::new();
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+
+            for key in 0..10_000 {
+                index.insert(key, key + 1)?;
+            }
+        }
+
+        // The layers above the base are built from the fences in its catalog, rather than from
+        // the hundreds of base nodes
+        backend.reads.store(0, Ordering::Relaxed);
+        let index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+        assert!(backend.reads.load(Ordering::Relaxed) < 10);
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key)?, Some(key + 1));
+        }
+        assert_eq!(index.search(10_000)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_search_many() -> limousine_engine::Result<()> {
+        use std::sync::atomic::Ordering;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 64, persist),
+            ]
+        }
+
+        let backend = CountingBackend::new();
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+
+            for key in 0..10_000 {
+                index.insert(key * 2, key)?;
+            }
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+        backend.reads.store(0, Ordering::Relaxed);
+
+        // A clustered batch, out of order and with keys which are missing
+        let keys: Vec<K> = (5_000..5_500).rev().collect();
+        let values = index.search_many(&keys)?;
+
+        for (&key, value) in keys.iter().zip(values) {
+            assert_eq!(value, (key % 2 == 0).then_some(key / 2));
+        }
+
+        // 250 present keys span a handful of nodes of at most 64 entries
+        assert!(backend.reads.load(Ordering::Relaxed) <= 10);
+        assert_eq!(index.search_many(&[])?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_external_storage() -> limousine_engine::Result<()> {
+        use limousine_engine::{GlobalStore, LocalStore, MemoryBackend};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ],
+            storage: external,
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32, persist),
+            ],
+            values: vlog(threshold = 8B),
+            storage: external,
+        }
+
+        let backend = MemoryBackend::new();
+
+        {
+            let mut store = GlobalStore::with_backend(backend.clone())?;
+            let mut first: KVStore1<K, V> = KVStore1::open_in(&mut store, "first")?;
+            let mut second: KVStore1<K, V> = KVStore1::open_in(&mut store, "second")?;
+            let mut logged: KVStore2<K, V> = KVStore2::open_in(&mut store, "logged")?;
+
+            let mut metadata: LocalStore<u64, ()> = store.load_local_store("Metadata")?;
+            metadata.catalog = 2_000;
+
+            for key in 0..2_000 {
+                first.insert(key, key + 1)?;
+                second.insert(key, -key)?;
+                logged.insert(key, key * 3)?;
+            }
+        }
+
+        let mut store = GlobalStore::with_backend(backend)?;
+        let first: KVStore1<K, V> = KVStore1::open_in(&mut store, "first")?;
+        let second: KVStore1<K, V> = KVStore1::open_in(&mut store, "second")?;
+        let logged: KVStore2<K, V> = KVStore2::open_in(&mut store, "logged")?;
+        let metadata: LocalStore<u64, ()> = store.load_local_store("Metadata")?;
+
+        assert_eq!(metadata.catalog, 2_000);
+        for key in 0..2_000 {
+            assert_eq!(first.search(key)?, Some(key + 1));
+            assert_eq!(second.search(key)?, Some(-key));
+            assert_eq!(logged.search(key)?, Some(key * 3));
+        }
+
+        assert_eq!(first.stats().inserts, 2_000);
+        assert!(first.store.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_incremental_backup() -> limousine_engine::Result<()> {
+        use limousine_engine::{GlobalStore, MemoryBackend};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ],
+            storage: external,
+        }
+
+        let mut primary = GlobalStore::with_backend(MemoryBackend::new())?;
+        let mut replica = GlobalStore::with_backend(MemoryBackend::new())?;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_in(&mut primary, "index")?;
+            for key in 0..2_000 {
+                index.insert(key, key)?;
+            }
+        }
+
+        let mut full = Vec::new();
+        let generation = primary.export_changed_since(0, &mut full)?;
+        replica.import_changes(full.as_slice())?;
+
+        // Only overwrite the tail of the keys, which touches a few pages
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_in(&mut primary, "index")?;
+            for key in 1_900..2_100 {
+                index.insert(key, -key)?;
+            }
+        }
+
+        let mut incremental = Vec::new();
+        primary.export_changed_since(generation, &mut incremental)?;
+        assert!(incremental.len() < full.len() / 2);
+        replica.import_changes(incremental.as_slice())?;
+
+        let index: KVStore1<K, V> = KVStore1::open_in(&mut replica, "index")?;
+        for key in 0..2_100 {
+            let expected = if key < 1_900 { key } else { -key };
+            assert_eq!(index.search(key)?, Some(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_copy_into() -> limousine_engine::Result<()> {
+        use limousine_engine::{GlobalStore, MemoryBackend};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ],
+            storage: external,
+        }
+
+        let mut source = GlobalStore::with_backend(MemoryBackend::new())?;
+        let mut target = GlobalStore::with_backend(MemoryBackend::new())?;
+
+        // Both stores hand out the same page ids, so the pages of the copy collide with these
+        let mut other: KVStore1<K, V> = KVStore1::open_in(&mut target, "other")?;
+        let mut index: KVStore1<K, V> = KVStore1::open_in(&mut source, "index")?;
+        for key in 0..2_000 {
+            other.insert(key, -key)?;
+            index.insert(key, key)?;
+        }
+
+        index.copy_into(&mut target, "copy")?;
+        index.copy_into(&mut source, "clone")?;
+        assert!(index.copy_into(&mut target, "copy").is_err());
+
+        let mut copy: KVStore1<K, V> = KVStore1::open_in(&mut target, "copy")?;
+        let clone: KVStore1<K, V> = KVStore1::open_in(&mut source, "clone")?;
+
+        // Inserts into the copy split nodes, which follow the translated links and parents
+        for key in 2_000..4_000 {
+            copy.insert(key, key)?;
+        }
+
+        for key in 0..2_000 {
+            assert_eq!(other.search(key)?, Some(-key));
+            assert_eq!(clone.search(key)?, Some(key));
+            assert_eq!(copy.search(key)?, Some(key));
+            assert_eq!(copy.search(key + 2_000)?, Some(key + 2_000));
+            assert_eq!(index.search(key + 2_000)?, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_encrypted() -> limousine_engine::Result<()> {
+        use limousine_engine::EncryptionKey;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist, compression = lz4),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+        let key = EncryptionKey::generate();
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_with_key(temp_dir.path(), &key)?;
+
+            for key in 0..2_000 {
+                index.insert(key, key * key)?;
+            }
+        }
+
+        assert!(KVStore1::<K, V>::open(temp_dir.path()).is_err());
+        assert!(
+            KVStore1::<K, V>::open_with_key(temp_dir.path(), &EncryptionKey::generate()).is_err()
+        );
+
+        let index: KVStore1<K, V> = KVStore1::open_with_key(temp_dir.path(), &key)?;
+
+        for key in 0..2_000 {
+            assert_eq!(index.search(key)?, Some(key * key));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_vlog() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 8, persist),
+            ],
+            values: vlog(threshold = 8B)
+        }
+
+        test_persisted_kv_store::<KVStore1<K, V>>()
+    }
+
+    #[test]
+    fn test_persisted_kv_store_vlog_maintenance() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ],
+            values: vlog(threshold = 1KB)
+        }
+
+        let temp_dir = tempdir()?;
+        let value = |key: K, round: u8| vec![round; 512 + 8 * (key as usize % 256)];
+
+        {
+            let mut index: KVStore1<K, Vec<u8>> = KVStore1::open(temp_dir.path())?;
+
+            for key in 0..1_000 {
+                assert_eq!(index.insert(key, value(key, 0))?, None);
+            }
+
+            // Overwrite most values, leaving mostly-dead segments behind
+            for key in (0..1_000).filter(|key| key % 10 != 0) {
+                assert_eq!(index.insert(key, value(key, 1))?, Some(value(key, 0)));
+            }
+
+            let segments = index.vlog.segment_count();
+            index.maintenance()?;
+            assert!(index.vlog.segment_count() < segments);
+
+            for key in 0..1_000 {
+                let round = if key % 10 == 0 { 0 } else { 1 };
+                assert_eq!(index.search(key)?, Some(value(key, round)));
+            }
+        }
+
+        let index: KVStore1<K, Vec<u8>> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            let round = if key % 10 == 0 { 0 } else { 1 };
+            assert_eq!(index.search(key)?, Some(value(key, round)));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_tombstones() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ],
+            tombstones: true
+        }
+
+        test_persisted_kv_store::<KVStore1<K, V>>()?;
+
+        let temp_dir = tempdir()?;
+        let deleted = |key: K| key % 3 == 0;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+            for key in 0..1_000 {
+                index.insert(key, key * 2)?;
+            }
+
+            for key in (0..1_000).filter(|&key| deleted(key)) {
+                assert_eq!(index.remove(key)?, Some(key * 2));
+                assert_eq!(index.remove(key)?, None);
+            }
+
+            assert_eq!(index.remove(5_000)?, None);
+
+            assert_eq!(index.purge_tombstones()?, 334);
+            assert_eq!(index.purge_tombstones()?, 0);
+
+            // Tombstones which were the smallest key of their node are purged all the same
+            for ptr in index.c0.pages() {
+                let node = index.c0.inner.get_node(ptr)?;
+                assert!(node
+                    .entries()
+                    .iter()
+                    .all(|entry| !entry.value.is_tombstone()));
+            }
+
+            // Deleted keys can be inserted again
+            assert_eq!(index.insert(3, 7)?, None);
+            assert_eq!(index.remove(3)?, Some(7));
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            let expected = (!deleted(key)).then_some(key * 2);
+            assert_eq!(index.search(key)?, expected);
+            assert_eq!(index.contains_key(&key)?, !deleted(key));
+        }
+
+        let keys: Vec<K> = (0..1_000).rev().collect();
+        let expected: Vec<Option<V>> = keys
+            .iter()
+            .map(|&key| (!deleted(key)).then_some(key * 2))
+            .collect();
+        assert_eq!(index.search_many(&keys)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_apply_batch() -> limousine_engine::Result<()> {
+        use limousine_engine::WriteBatch;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 16, persist),
+            ],
+            tombstones: true
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+            index.apply_batch((0..1_000).map(|key| (key, Some(key))).collect())?;
+
+            let mut batch = WriteBatch::new();
+            batch.put(1, 10).delete(2).delete(5_000).put(2_000, 2_000);
+            index.apply_batch(batch)?;
+
+            assert_eq!(index.search(1)?, Some(10));
+            assert_eq!(index.search(2)?, None);
+            assert_eq!(index.search(2_000)?, Some(2_000));
+        }
+
+        // A batch which was logged but never applied, as if the process died right after logging
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+            assert_eq!(index.search(1)?, Some(10));
+            assert_eq!(index.search(2)?, None);
+
+            let mut batch = WriteBatch::new();
+            batch.delete(3).put(4, 40);
+            index.wal.log(&batch)?;
+            assert_eq!(index.search(3)?, Some(3));
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+        assert_eq!(index.search(3)?, None);
+        assert_eq!(index.search(4)?, Some(40));
+        assert_eq!(index.search(999)?, Some(999));
+        assert!(index.wal.pending().is_none());
+
+        // Layouts without tombstones refuse deletes before writing anything
+        let temp_dir = tempdir()?;
+        let mut index: KVStore2<K, V> = KVStore2::open(temp_dir.path())?;
+
+        let mut batch = WriteBatch::new();
+        batch.put(1, 1).delete(2);
+        assert!(index.apply_batch(batch).is_err());
+        assert_eq!(index.search(1)?, None);
+        assert!(index.wal.pending().is_none());
+
+        index.apply_batch((0..100).map(|key| (key, Some(key))).collect())?;
+        assert_eq!(index.search(99)?, Some(99));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_apply_batch_rollback() -> limousine_engine::Result<()> {
+        use limousine_engine::WriteBatch;
+        use std::sync::atomic::Ordering;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 16, persist),
+            ],
+            tombstones: true
+        }
+
+        let backend = CountingBackend::new();
+
+        // Scattered over the base nodes, so the batch reads many of them back
+        let batch = || -> WriteBatch<K, V> {
+            let mut batch = WriteBatch::new();
+            for key in 0..1_000 {
+                batch.put(key * 337 % 1_000 * 10 + 5, -key);
+            }
+            batch.delete(0);
+            batch
+        };
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+            index.apply_batch((0..1_000).map(|key| (key * 10, Some(key))).collect())?;
+
+            // A batch which fails partway through leaves no trace, in memory or in the store
+            backend.reads_left.store(20, Ordering::Relaxed);
+            let error = index.apply_batch(batch()).unwrap_err();
+            assert_eq!(error.root_cause().to_string(), "injected read failure");
+
+            assert!(index.wal.pending().is_none());
+            assert_eq!(index.len(), 1_000);
+            for key in 0..1_000 {
+                assert_eq!(index.search(key * 10)?, Some(key));
+                assert_eq!(index.search(key * 10 + 5)?, None);
+            }
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::open_with_backend(backend.clone())?;
+        assert!(index.wal.pending().is_none());
+        for key in 0..1_000 {
+            assert_eq!(index.search(key * 10)?, Some(key));
+            assert_eq!(index.search(key * 10 + 5)?, None);
+        }
+
+        // The same batch applies once the store reads again
+        index.apply_batch(batch())?;
+        assert_eq!(index.search(0)?, None);
+        assert_eq!(index.search(3_375)?, Some(-1));
+        assert_eq!(index.search(9_995)?, Some(-727));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persisted_kv_store_ttl() -> limousine_engine::Result<()> {
+        use std::time::Duration;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ],
+            ttl: enabled
+        }
+
+        test_persisted_kv_store::<KVStore1<K, V>>()?;
+
+        let temp_dir = tempdir()?;
+        let expired = |key: K| key % 3 == 0;
+        let now = limousine_engine::private::unix_millis();
+
+        {
+            let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+            for key in 0..1_000 {
+                if expired(key) {
+                    index.insert_expiring(key, key * 2, now - 1)?;
+                } else if key % 2 == 0 {
+                    index.insert_with_ttl(key, key * 2, Duration::from_secs(3_600))?;
+                } else {
+                    index.insert(key, key * 2)?;
+                }
+            }
+
+            // Expired values are missing, even before they are swept
+            assert_eq!(index.search(3)?, None);
+            assert!(!index.contains_key(&3)?);
+            assert_eq!(index.insert(3, 7)?, None);
+            assert_eq!(index.insert_expiring(3, 6, now - 1)?, Some(7));
+
+            // Only keys which expired by then are swept
+            assert_eq!(index.sweep_expired(now - 2)?, 0);
+            assert_eq!(index.sweep_expired(now)?, 334);
+            assert_eq!(index.sweep_expired(now)?, 0);
+        }
+
+        let index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            let expected = (!expired(key)).then_some(key * 2);
+            assert_eq!(index.search(key)?, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_ffi() {
+        create_kv_store! {
+            name: FFIStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            extern: true
+        }
+
+        unsafe {
+            let index = ffistore_new();
+            let mut value = 0;
+
+            for key in 0..1_000 {
+                assert_eq!(
+                    ffistore_insert(index, key, key * 2, std::ptr::null_mut()),
+                    0
+                );
+            }
+
+            assert_eq!(ffistore_insert(index, 10, 7, &mut value), 1);
+            assert_eq!(value, 20);
+
+            assert_eq!(ffistore_search(index, 10, &mut value), 1);
+            assert_eq!(value, 7);
+            assert_eq!(ffistore_search(index, 999, &mut value), 1);
+            assert_eq!(value, 1998);
+            assert_eq!(ffistore_search(index, 1_000, &mut value), 0);
+
+            assert_eq!(ffistore_search(std::ptr::null(), 10, &mut value), -1);
+            ffistore_free(index);
+        }
+    }
+
+    #[test]
+    fn test_persisted_kv_store_ffi() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: PersistedFFIStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ],
+            extern: true
+        }
+
+        let temp_dir = tempdir()?;
+        let path = std::ffi::CString::new(temp_dir.path().to_str().unwrap())?;
+
+        unsafe {
+            let index = persistedffistore_open(path.as_ptr());
+            assert!(!index.is_null());
+
+            for key in 0..1_000 {
+                assert_eq!(
+                    persistedffistore_insert(index, key, key + 1, std::ptr::null_mut()),
+                    0
+                );
+            }
+            persistedffistore_free(index);
+
+            let index = persistedffistore_open(path.as_ptr());
+            let mut value = 0;
+            assert_eq!(persistedffistore_search(index, 500, &mut value), 1);
+            assert_eq!(value, 501);
+            assert_eq!(persistedffistore_search(index, 5_000, &mut value), 0);
+            persistedffistore_free(index);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_1() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 64),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_2() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_3() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 32),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_4() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_5() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_6() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_7() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_get_key_value() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 16),
+            ]
+        }
+
+        let entries = (0..1_000).map(|key| (key * 2, key * 3));
+        let btree = KVStore1::<K, V>::build(entries.clone());
+        let pgm = KVStore2::<K, V>::build(entries);
+
+        for key in (0..1_000).map(|key| key * 2) {
+            let value = key / 2 * 3;
+
+            assert!(btree.contains_key(&key));
+            assert!(pgm.contains_key(&key));
+            assert_eq!(btree.get_key_value(&key), Some((&key, &value)));
+            assert_eq!(pgm.get_key_value(&key), Some((&key, &value)));
+
+            assert!(!btree.contains_key(&(key + 1)));
+            assert!(!pgm.contains_key(&(key + 1)));
+            assert_eq!(btree.get_key_value(&(key + 1)), None);
+            assert_eq!(pgm.get_key_value(&(key + 1)), None);
+        }
+
+        assert!(!btree.contains_key(&-1));
+        assert!(!pgm.contains_key(&5_000));
+
+        // The key is borrowed from the index, so the entry outlives the key looked up
+        let entry = {
+            let key = 10;
+            btree.get_key_value(&key)
+        };
+        assert_eq!(entry, Some((&10, &15)));
+    }
+
+    #[test]
+    fn test_kv_store_presets() {
+        create_kv_store! {
+            name: ReadStore,
+            preset: read_optimized,
+        }
+
+        create_kv_store! {
+            name: WriteStore,
+            preset: write_optimized,
+        }
+
+        create_kv_store! {
+            name: TightStore,
+            preset: memory_tight,
+        }
+
+        // Learned layers are trained on the keys they are built over
+        test_kv_store_build::<ReadStore<K, V>>();
+        test_kv_store::<WriteStore<K, V>>();
+        test_kv_store_build::<TightStore<K, V>>();
+
+        let index = ReadStore::<K, V>::build((0..100).map(|key| (key, key)));
+        assert_eq!(index.layer_report().len(), 2);
+    }
+
+    #[test]
+    fn test_kv_store_extends() {
+        use limousine_engine::private::NodeLayer;
+        use limousine_engine::IndexRead;
+
+        create_kv_store! {
+            name: SmallStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: LargeStore,
+            extends: SmallStore,
+            prepend: [btree(fanout = 16)],
+        }
+
+        // Layouts extending an extended layout see the layers it prepended
+        create_kv_store! {
+            name: LargerStore,
+            extends: LargeStore,
+            prepend: [pgm(epsilon = 8)],
+            read_only: true,
+        }
+
+        test_kv_store::<LargeStore<K, V>>();
+        test_kv_store_build::<LargeStore<K, V>>();
+
+        let entries = || (0..1_000).map(|key| (key, key));
+        let small = SmallStore::<K, V>::build(entries());
+        let large = LargeStore::<K, V>::build(entries());
+        let larger = LargerStore::<K, V>::build(entries());
+
+        // The prepended layer sits between the top and the layers of the extended layout
+        assert_eq!(large.c1.node_count(), small.c1.node_count());
+        assert!(large.c2.node_count() < large.c1.node_count());
+        assert_eq!(larger.layer_report().len(), 1);
+        assert_eq!(larger.try_search(999).unwrap(), Some(999));
+    }
+
+    #[test]
+    fn test_persisted_kv_store_preset() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: DiskStore,
+            preset: disk_resident,
+        }
+
+        test_persisted_kv_store::<DiskStore<K, V>>()
+    }
+
+    #[test]
+    fn test_kv_store_top_max_entries() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(max_entries = 4),
+                btree(fanout = 8),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store_build::<KVStore1<K, V>>();
+        test_kv_store_clone::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_bucket() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                bucket(count = 16),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store_build::<KVStore1<K, V>>();
+        test_kv_store_clone::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_pgm_store_bucket() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                bucket(count = 4),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        test_kv_store_build::<PGMStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_truncated_fences() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8, fences = truncated(1)),
+                btree(fanout = 8, fences = truncated(8)),
+                btree(fanout = 8),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store_build::<KVStore1<K, V>>();
+        test_kv_store_clone::<KVStore1<K, V>>();
+
+        // Keys below 2^64 share every byte the fences keep, so they are only told apart by the full
+        // keys in the base
+        let mut kv_store = KVStore1::<K, V>::build((0..5_000).map(|key| (key * 2, key)));
+        for key in 0..5_000 {
+            kv_store.insert(key * 2 + 1, -key);
+        }
+
+        for key in 0..5_000 {
+            assert_eq!(kv_store.search(key * 2), Some(key));
+            assert_eq!(kv_store.search(key * 2 + 1), Some(-key));
+        }
+        assert_eq!(kv_store.search(10_000), None);
+    }
+
+    #[test]
+    fn test_kv_store_auto() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                auto(),
+                auto(),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store_build::<KVStore1<K, V>>();
+        test_kv_store_clone::<KVStore1<K, V>>();
+
+        // Nodes over linear keys are spread evenly, so the layer above the base picks a dense table
+        let index = KVStore1::<K, V>::build((0..100_000).map(|key| (3 * key, key)));
+        let report = index.layer_report();
+        assert_eq!(
+            report.iter().map(|layer| layer.layer).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let decision = report[0].auto.as_ref().unwrap();
+        assert_eq!(decision.choice, limousine_engine::AutoChoice::Dense);
+        assert!(decision.keys >= 100_000 / 32);
+        assert_eq!(decision.costs.len(), 4);
+        assert_eq!(report[0].segments, 1);
+
+        let trace = index.explain(&300);
+        assert!(trace.found);
+        assert_eq!(trace.steps[1].component, "AutoDenseInternal");
+    }
+
+    #[test]
+    fn test_kv_store_dense() {
+        create_kv_store! {
+            name: DenseStore1,
+            layout: [
+                btree_top(),
+                dense(),
+                dense(page = 128),
+            ]
+        }
+
+        create_kv_store! {
+            name: DenseStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 16),
+                dense(),
+            ]
+        }
+
+        test_kv_store::<DenseStore1<K, V>>();
+        test_kv_store_build::<DenseStore1<K, V>>();
+        test_kv_store_clone::<DenseStore1<K, V>>();
+        test_kv_store::<DenseStore2<K, V>>();
+
+        // Dense keys with every third one missing, and the missing ones inserted afterwards along
+        // with keys below the first page
+        let mut index = DenseStore1::<K, V>::build(
+            (0..20_000)
+                .filter(|key| key % 3 != 0)
+                .map(|key| (key, -key)),
+        );
+        assert_eq!(index.search(3), None);
+
+        for key in (-1_000..20_000).filter(|key| key % 3 == 0) {
+            index.insert(key, key);
+        }
+
+        for key in -1_000..20_000 {
+            let value = if key % 3 == 0 { key } else { -key };
+            assert_eq!(index.search(key), (key >= 0 || key % 3 == 0).then_some(value));
+        }
+        assert_eq!(index.search(20_000), None);
+
+        let trace = index.explain(&300);
+        assert!(trace.found);
+        assert_eq!(trace.steps[1].component, "DenseInternal");
+        assert_eq!(trace.steps[1].probe.comparisons, 1);
+        assert_eq!(trace.steps[2].component, "DenseBase128");
+    }
+
+    /// Minimal executor for the futures returned by async facades, parking the thread until woken
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::sync::Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(output) => return output,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_kv_store_async() -> limousine_engine::Result<()> {
+        use limousine_engine::ThreadExecutor;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let index = KVStore1Async::new(KVStore1::<K, V>::empty(), ThreadExecutor);
+
+        let num = 10_000;
+        let mut inline = 0;
+        for key in 0..num {
+            let task = index.insert(key, key + 1);
+            inline += task.is_ready() as usize;
+            assert_eq!(block_on(task)?, None);
+        }
+
+        // Most inserts fit into their base node, the rest split it
+        assert!(inline > num as usize / 2 && inline < num as usize);
+
+        // Operations issued behind a scheduled job are queued in order
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let job = index.schedule(move |index| {
+            receiver.recv().unwrap();
+            KVStore::insert(index, 0, 0)
+        });
+
+        let search = index.search(0);
+        assert!(!search.is_ready());
+
+        sender.send(()).unwrap();
+        assert_eq!(block_on(job)?, Some(1));
+        assert_eq!(block_on(search)?, Some(0));
+
+        for key in 1..num {
+            assert_eq!(block_on(index.search(key))?, Some(key + 1));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_watch() -> limousine_engine::Result<()> {
+        use limousine_engine::{ChangeEvent, ThreadExecutor};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ],
+            watch: true,
+        }
+
+        let mut index = KVStore1::<K, V>::build((0..100).map(|key| (key, key)));
+        let mut low = index.watch(..50);
+        let mut high = index.watch(1_000..=2_000);
+
+        // Building the index reports nothing, only the changes after subscribing
+        assert_eq!(low.try_next(), None);
+
+        index.insert(10, -10);
+        index.insert(1_500, 1);
+        index.insert(500, 0);
+        index.insert(1_500, 2);
+
+        assert_eq!(
+            block_on(low.changed()),
+            Some(Ok(ChangeEvent::Update {
+                key: 10,
+                previous: 10,
+                value: -10
+            }))
+        );
+        assert_eq!(low.try_next(), None);
+
+        assert_eq!(
+            block_on(high.changed()),
+            Some(Ok(ChangeEvent::Insert {
+                key: 1_500,
+                value: 1
+            }))
+        );
+        let update = block_on(high.changed()).unwrap()?;
+        assert_eq!((update.key(), update.value()), (&1_500, Some(&2)));
+
+        // Clones don't inherit the watchers of the original
+        let mut clone = index.clone();
+        clone.insert(20, 0);
+        assert_eq!(low.try_next(), None);
+
+        // Inserts through the async facade are reported as they run on the executor
+        let index = KVStore1Async::new(index, ThreadExecutor);
+        for key in 0..1_000 {
+            block_on(index.insert(key, key))?;
+        }
+
+        let mut count = 0;
+        while let Some(event) = low.try_next() {
+            assert!(*event?.key() < 50);
+            count += 1;
+        }
+        assert_eq!(count, 50);
+
+        // Dropping the index ends its streams, once their buffered changes are drained
+        let mut stream = clone.watch(..);
+        clone.insert(30, 0);
+        drop(clone);
+
+        assert!(stream.is_closed());
+        assert_eq!(stream.try_next().map(|event| *event.unwrap().key()), Some(30));
+        assert_eq!(block_on(stream.changed()), None);
+        assert!(!high.is_closed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_watch_writes() -> limousine_engine::Result<()> {
+        use limousine_engine::{ChangeEvent, Lagged, WatchStream, WriteBatch};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ],
+            watch: true,
+        }
+
+        fn drain(stream: &mut WatchStream<K, V>) -> Vec<ChangeEvent<K, V>> {
+            std::iter::from_fn(|| stream.try_next())
+                .map(|event| event.unwrap())
+                .collect()
+        }
+
+        let mut index = KVStore1::<K, V>::build((0..100).map(|key| (key, key)));
+        let mut stream = index.watch(..);
+
+        // Replacing and removing through `compare_and_swap` are reported
+        index.compare_and_swap(5, Some(&5), Some(50)).unwrap();
+        index.compare_and_swap(6, Some(&6), None).unwrap();
+        index.compare_and_swap(6, None, Some(60)).unwrap();
+        assert!(index.compare_and_swap(7, Some(&0), None).is_err());
+
+        assert_eq!(
+            drain(&mut stream),
+            vec![
+                ChangeEvent::Update {
+                    key: 5,
+                    previous: 5,
+                    value: 50
+                },
+                ChangeEvent::Remove {
+                    key: 6,
+                    previous: 6
+                },
+                ChangeEvent::Insert { key: 6, value: 60 },
+            ]
+        );
+
+        // So is every write of a batch, in order
+        let mut batch = WriteBatch::new();
+        batch.put(10, 0).put(200, 1).delete(11).delete(300);
+        index.apply_batch(batch).unwrap();
+
+        assert_eq!(
+            drain(&mut stream),
+            vec![
+                ChangeEvent::Update {
+                    key: 10,
+                    previous: 10,
+                    value: 0
+                },
+                ChangeEvent::Insert { key: 200, value: 1 },
+                ChangeEvent::Remove {
+                    key: 11,
+                    previous: 11
+                },
+            ]
+        );
+
+        // And the removals of a cleared scope
+        let mut scoped = KVStore1::<K, V>::empty();
+        let mut tenant = scoped.watch(..);
+        let mut scope = scoped.scope(1);
+        scope.insert(3, 30);
+        assert_eq!(scope.clear_scope(), 1);
+
+        let events = drain(&mut tenant);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].previous(), Some(&30));
+        assert_eq!(events[1].value(), None);
+
+        // A stream which falls behind drops its oldest changes, and reports how many it lost
+        let mut slow = index.watch_with_capacity(..1_000, 4);
+        for key in 0..10 {
+            index.insert(key, -key);
+        }
+        assert_eq!(slow.buffered(), 4);
+        assert_eq!(slow.try_next(), Some(Err(Lagged { missed: 6 })));
+        assert_eq!(
+            drain(&mut slow).iter().map(|event| *event.key()).collect::<Vec<_>>(),
+            vec![6, 7, 8, 9]
+        );
+
+        // Streams poll like any other `Stream`
+        index.insert(1, 1);
+        let next = block_on(std::future::poll_fn(|cx| {
+            futures_core::Stream::poll_next(std::pin::Pin::new(&mut slow), cx)
+        }));
+        assert_eq!(
+            next,
+            Some(Ok(ChangeEvent::Update {
+                key: 1,
+                previous: -1,
+                value: 1
+            }))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_ingest_csv() -> limousine_engine::Result<()> {
+        use limousine_engine::ingest::{self, CsvOptions};
+        use limousine_engine::testkit::TestRng;
+        use std::io::Write;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let mut expected = std::collections::BTreeMap::new();
+
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(file, "name,id,value")?;
+        for row in 0..5_000 {
+            // Reuse a small key range, so that later rows overwrite earlier ones
+            let key = rng.below(2_000) as K - 1_000;
+            writeln!(file, "row{},{},{}", row, key, row)?;
+            expected.insert(key, row as V);
+        }
+        file.flush()?;
+
+        // Small runs force the rows to be sorted through temporary files
+        let options = CsvOptions {
+            run_len: 300,
+            ..Default::default()
+        };
+        let index: KVStore1<K, V> = ingest::build_from_csv_with(file.path(), "id", 2, &options)?;
+
+        for key in -1_000..1_000 {
+            assert_eq!(index.search(key), expected.get(&key).cloned());
+        }
+
+        // Malformed rows are reported instead of being skipped
+        writeln!(file, "row,not a key,0")?;
+        file.flush()?;
+        let result: limousine_engine::Result<KVStore1<K, V>> =
+            ingest::build_from_csv(file.path(), "id", "value");
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_mvcc() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            versioning: mvcc,
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 16),
+            ],
+            versioning: mvcc,
+        }
+
+        create_kv_store! {
+            name: KVStore3,
+            layout: [
+                btree_top(),
+                btree(fanout = 16),
+                dense(),
+            ],
+            versioning: mvcc,
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store_build::<KVStore1<K, V>>();
+        test_kv_store_clone::<KVStore1<K, V>>();
+        test_versioned_kv_store::<KVStore1<K, V>>();
+        test_kv_store::<KVStore2<K, V>>();
+        test_versioned_kv_store::<KVStore2<K, V>>();
+        test_kv_store::<KVStore3<K, V>>();
+        test_versioned_kv_store::<KVStore3<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_index() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+
+        test_index(&mut <KVStore1<K, V> as KVStore<K, V>>::empty())?;
+        test_index(&mut <KVStore2<K, V> as PersistedKVStore<K, V>>::open(
+            temp_dir.path(),
+        )?)
+    }
+
+    #[test]
+    fn test_kv_store_testkit() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{operations, OpMix, Oracle, TestRng};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let ops = operations::<K>(&mut rng, 20_000, OpMix::default());
+
+        let mut index = <KVStore1<K, V> as KVStore<K, V>>::empty();
+        Oracle::new().run(&mut index, ops.clone())?;
+
+        let temp_dir = tempdir()?;
+        let mut index = <KVStore2<K, V> as PersistedKVStore<K, V>>::open(temp_dir.path())?;
+        Oracle::new().run(&mut index, ops)
+    }
+
+    #[test]
+    fn test_pgm_store_testkit() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{sorted_entries, unsorted_keys, Oracle, TestRng};
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let entries = sorted_entries::<K>(&mut rng, 20_000);
+
+        let index = <PGMStore1<K, V> as KVStore<K, V>>::build(entries.clone().into_iter());
+        Oracle::from_entries(entries).verify(&index, unsorted_keys(&mut rng, 10_000))
+    }
+
+    #[test]
+    fn test_kv_store_workloads() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{OpMix, TestRng};
+        use limousine_engine::workloads::{zipfian_ops, Dataset, Recorder, Trace};
+
+        create_kv_store! {
+            name: BTreeStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: PGMStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        for dataset in Dataset::ALL {
+            let keys: Vec<K> = dataset
+                .keys(&mut rng, 10_000)
+                .into_iter()
+                .map(K::from)
+                .collect();
+            let entries = || keys.iter().map(|&key| (key, key));
+
+            // Record a workload against one layout, and replay it against another
+            let mix = OpMix {
+                inserts: 0.2,
+                reuse: 0.9,
+            };
+            let mut recorder = Recorder::new(BTreeStore::<K, V>::build(entries()));
+            let live = Trace::from(zipfian_ops(&mut rng, &keys, 20_000, mix, 0.99))
+                .replay(&mut recorder)?;
+            let (_, trace) = recorder.into_parts();
+            assert_eq!(trace.len(), 20_000);
+            assert!(live.hits > live.searches / 2, "{}", dataset.name());
+
+            let mut bytes = Vec::new();
+            trace.write_to(&mut bytes)?;
+            let trace = Trace::<K, V>::read_from(bytes.as_slice())?;
+
+            let replayed = trace.replay(&mut PGMStore::<K, V>::build(entries()))?;
+            assert!(replayed.agrees_with(&live), "{}", dataset.name());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_shadowed() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{sorted_entries, unsorted_keys, TestRng};
+        use limousine_engine::{IndexRead, IndexWrite, Shadowed};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let entries = sorted_entries::<K>(&mut rng, 10_000);
+
+        let index = <KVStore1<K, V> as KVStore<K, V>>::build(entries.clone().into_iter());
+        let mut index = Shadowed::with_entries(index, entries).sample_reads(0.5);
+
+        for key in unsorted_keys::<K>(&mut rng, 10_000) {
+            index.try_put(key, key)?;
+            index.try_search(key)?;
+        }
+
+        index.verify()
+    }
+
+    #[test]
+    fn test_kv_store_sequenced() -> limousine_engine::Result<()> {
+        use limousine_engine::{ChangesError, IndexRead, IndexWrite, Sequenced};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let index = KVStore1::<K, V>::build((0..1_000).map(|key| (key * 2, key)));
+        let mut index = Sequenced::new(index).with_retained(100);
+
+        for key in 0..200 {
+            assert_eq!(index.try_put(key * 2 + 1, -key)?, None);
+        }
+        assert_eq!(index.try_put(0, 7)?, Some(0));
+        assert_eq!(index.last_lsn(), 201);
+        assert_eq!(index.try_search(1)?, Some(0));
+        assert_eq!(index.len(), 1_200);
+
+        // A replica which applied up to LSN 150 catches up on the rest in order
+        let mut replica = KVStore1::<K, V>::empty();
+        for change in index.changes_since(150).unwrap() {
+            replica.insert(change.key, change.value);
+        }
+        assert_eq!(replica.search(301), Some(-150));
+        assert_eq!(replica.search(399), Some(-199));
+        assert_eq!(replica.search(0), Some(7));
+        assert_eq!(replica.search(299), None);
+
+        assert_eq!(
+            index.changes_since(100).err(),
+            Some(ChangesError::Truncated {
+                since: 100,
+                oldest: 102
+            })
+        );
+        assert_eq!(
+            index.changes_since(202).err(),
+            Some(ChangesError::Ahead {
+                since: 202,
+                last: 201
+            })
+        );
+
+        // The index keeps working on its own once unwrapped
+        let index = index.into_inner();
+        assert_eq!(index.search(399), Some(-199));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_drift() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{sorted_entries, TestRng};
+        use limousine_engine::{DriftMonitor, IndexRead, IndexWrite};
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let entries = sorted_entries::<K>(&mut rng, 10_000);
+        let keys: Vec<K> = entries.iter().map(|(key, _)| *key).collect();
+
+        let drifted = Rc::new(Cell::new(None));
+        let callback = drifted.clone();
+
+        let index = <KVStore1<K, V> as KVStore<K, V>>::build(entries.into_iter());
+        let mut index = DriftMonitor::new(index, keys.iter().copied())
+            .on_drift(0.5, move |drift| callback.set(Some(drift)));
+
+        // Overwriting trained keys follows their distribution
+        for _ in 0..5_000 {
+            let key = keys[rng.below(keys.len())];
+            index.try_put(key, key)?;
+        }
+
+        assert!(index.drift() < 0.2);
+        assert_eq!(drifted.get(), None);
+
+        // Appending past the largest key doesn't
+        let max = *keys.last().unwrap();
+        for offset in 1..=10_000 {
+            index.try_put(max.saturating_add(offset), offset)?;
+        }
+
+        assert!(index.drift() > 0.5);
+        assert!(drifted.get().is_some_and(|drift| drift > 0.5));
+        assert_eq!(index.try_search(max.saturating_add(1))?, Some(1));
+
+        index.retrained();
+        assert_eq!(index.drift(), 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_maintenance() {
+        use limousine_engine::{AttachScheduler, MaintenanceConfig};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let index = KVStore1::<K, V>::build((0..10_000).map(|key| (key * 2, key)))
+            .attach_scheduler(MaintenanceConfig::default().with_max_duty(0.5));
+
+        index.every("rebuild", Duration::from_millis(1), |index| {
+            index.rebuild();
+            1
+        });
+
+        // Runs once, since the job makes its own condition false
+        let stale = Arc::new(AtomicBool::new(true));
+        let flag = stale.clone();
+        index.when("flush", move |_| flag.load(Ordering::SeqCst), {
+            let stale = stale.clone();
+            move |_| {
+                stale.store(false, Ordering::SeqCst);
+                0
+            }
+        });
+        assert_eq!(index.jobs(), vec!["rebuild", "flush"]);
+
+        for key in 0..1_000 {
+            assert_eq!(index.insert(key * 2 + 1, key), None);
+        }
+
+        let start = Instant::now();
+        while (index.stats().runs < 3 || stale.load(Ordering::SeqCst))
+            && start.elapsed() < Duration::from_secs(10)
+        {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(index.is_running());
+        assert!(!stale.load(Ordering::SeqCst));
+
+        let stats = index.stats();
+        assert!(stats.runs >= 3);
+        assert!(stats.pages >= stats.runs - 1);
+
+        assert_eq!(index.cancel("rebuild"), 1);
+        assert_eq!(index.jobs(), vec!["flush"]);
+
+        let index = index.into_inner();
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+        }
+        for key in 0..1_000 {
+            assert_eq!(index.search(key * 2 + 1), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_sharded() {
+        use limousine_engine::testkit::{sorted_entries, unsorted_keys, TestRng};
+        use limousine_engine::Sharded;
+        use std::collections::BTreeMap;
+        use std::ops::Bound;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let mut rng = TestRng::from_env();
+        let entries = sorted_entries::<K>(&mut rng, 10_000);
+        let keys = unsorted_keys::<K>(&mut rng, 20_000);
+
+        let hashed = Sharded::<KVStore1<K, V>, K, V, 4>::hashed().with_entries(entries.clone());
+        let ranged = Sharded::<KVStore1<K, V>, K, V, 4>::ranged([-1 << 64, 0, 1 << 64])
+            .with_entries(entries.clone());
+
+        // Insert from a thread per chunk of keys
+        for index in [&hashed, &ranged] {
+            std::thread::scope(|scope| {
+                for chunk in keys.chunks(5_000) {
+                    scope.spawn(move || {
+                        for &key in chunk {
+                            index.insert(key, key);
+                        }
+                    });
+                }
+            });
+        }
+
+        let mut expected: BTreeMap<K, V> = entries.into_iter().collect();
+        expected.extend(keys.iter().map(|&key| (key, key)));
+
+        for index in [&hashed, &ranged] {
+            for (&key, &value) in expected.iter() {
+                assert_eq!(index.search(key), Some(value));
+            }
+
+            let shards = index.read();
+            assert!(shards.range(..).eq(expected.clone()));
+
+            let (low, high) = (keys[0].min(keys[1]), keys[0].max(keys[1]));
+            for bounds in [
+                (Bound::Included(low), Bound::Included(high)),
+                (Bound::Excluded(low), Bound::Excluded(high)),
+            ] {
+                let entries = expected.range(bounds).map(|(&key, &value)| (key, value));
+                assert!(shards.range(bounds).eq(entries));
+            }
+        }
+
+        // Each shard of a range partitioned index holds a contiguous range of keys
+        let shards = ranged.into_shards();
+        for (&key, &value) in expected.iter() {
+            let shard = [-1 << 64, 0, 1 << 64].partition_point(|&split| split <= key);
+            assert_eq!(shards[shard].search(key), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_pgm_store_rebuild() -> limousine_engine::Result<()> {
+        use limousine_engine::{RebuildComponent, TopComponent};
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let mut index =
+            <PGMStore1<K, V> as KVStore<K, V>>::build((0..10_000).map(|key| (key * 2, key)));
+
+        // Inserts which don't split a base node leave the plan valid
+        let plan = index.c1.plan_rebuild(&index.c0);
+        index.insert(1, 1);
+
+        index.c1.apply_rebuild(&mut index.c0, plan)?;
+        index.c2 = TopComponent::build(&mut index.c1);
+        assert_eq!(index.c1.report().rebuilds, 2);
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+        }
+        assert_eq!(index.search(1), Some(1));
+
+        // Splits make it stale
+        let plan = index.c1.plan_rebuild(&index.c0);
+        for key in 20_000..21_000 {
+            index.insert(key, key);
+        }
+
+        assert!(index.c1.apply_rebuild(&mut index.c0, plan).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_u256_store() -> limousine_engine::Result<()> {
+        use limousine_engine::U256;
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: PGMStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        // Keys spread over the whole key space, which collide once converted into floats
+        let key = |i: u64| U256::from_words([i % 7, u64::MAX, i / 7, i]);
+        let mut entries: Vec<(U256, u64)> = (0..20_000).map(|i| (key(i), i)).collect();
+        entries.sort();
+
+        let mut index =
+            <PGMStore1<U256, u64> as KVStore<U256, u64>>::build(entries.clone().into_iter());
+        let pgm_index =
+            <PGMStore2<U256, u64> as KVStore<U256, u64>>::build(entries.clone().into_iter());
+
+        for (key, value) in entries.iter() {
+            assert_eq!(index.search(*key), Some(*value));
+            assert_eq!(pgm_index.search(*key), Some(*value));
+        }
+        assert_eq!(index.search(U256::from_words([7, 0, 0, 0])), None);
+
+        let temp_dir = tempdir()?;
+        let mut disk_index =
+            <KVStore1<U256, u64> as PersistedKVStore<U256, u64>>::open(temp_dir.path())?;
+
+        for i in 20_000..30_000 {
+            index.insert(key(i), i);
+            disk_index.insert(key(i), i)?;
+        }
+
+        for i in 20_000..30_000 {
+            assert_eq!(index.search(key(i)), Some(i));
+            assert_eq!(disk_index.search(key(i))?, Some(i));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_search_batch() -> limousine_engine::Result<()> {
+        use limousine_engine::testkit::{sorted_entries, unsorted_keys, TestRng};
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ],
+            tombstones: true
+        }
+
+        let mut rng = TestRng::from_env();
+        let entries = sorted_entries::<K>(&mut rng, 10_000);
+
+        // Hits in sorted and shuffled order, and misses
+        let mut keys: Vec<K> = entries.iter().map(|(key, _)| *key).collect();
+        keys.extend(unsorted_keys::<K>(&mut rng, 2_000));
+        keys.extend(entries.iter().rev().map(|(key, _)| *key));
+
+        let index = <PGMStore1<K, V> as KVStore<K, V>>::build(entries.clone().into_iter());
+        let expected: Vec<Option<V>> = keys.iter().map(|key| index.search(*key)).collect();
+        assert_eq!(index.search_batch(&keys), expected);
+        assert_eq!(index.search_pipelined(&keys), expected);
+        assert_eq!(index.search_pipelined(&[]), vec![]);
+
+        let temp_dir = tempdir()?;
+        let mut index = <KVStore1<K, V> as PersistedKVStore<K, V>>::open(temp_dir.path())?;
+        for (key, value) in entries.iter().step_by(3) {
+            index.insert(*key, *value)?;
+        }
+        index.remove(entries[0].0)?;
+
+        let expected = keys
+            .iter()
+            .map(|key| index.search(*key))
+            .collect::<limousine_engine::Result<Vec<_>>>()?;
+        assert_eq!(index.search_batch(&keys)?, expected);
+        assert_eq!(index.search_batch(&[])?, vec![]);
+        assert_eq!(index.search_pipelined(&keys)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_search_with_budget() -> limousine_engine::Result<()> {
+        use limousine_engine::{ProbeBudget, SearchOutcome};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        let index = <KVStore1<K, V> as KVStore<K, V>>::build((0..5_000).map(|key| (key * 2, key)));
+
+        // The top and the internal layer fit in two nodes, the base doesn't
+        let budget = ProbeBudget::unlimited().with_nodes(2);
+        assert_eq!(
+            index.search_with_budget(&10, budget),
+            SearchOutcome::Exceeded {
+                layer: 0,
+                nodes: 2,
+                disk_bytes: 0
+            }
+        );
+
+        let budget = ProbeBudget::unlimited().with_nodes(3);
+        assert_eq!(index.search_with_budget(&10, budget), SearchOutcome::Found(5));
+        assert_eq!(index.search_with_budget(&11, budget), SearchOutcome::NotFound);
+
+        for key in (0..10_000).step_by(7) {
+            let outcome = index.search_with_budget(&key, ProbeBudget::default());
+            assert_eq!(outcome.completed(), Some(index.search(key)));
+        }
+
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore2<K, V> = KVStore2::open(temp_dir.path())?;
+
+            for key in 0..5_000 {
+                index.insert(key, key)?;
+            }
+        }
+
+        // After reopening, nothing is cached, so the first persisted layer already reads from
+        // disk and the lookup gives up before the base
+        let index: KVStore2<K, V> = KVStore2::open(temp_dir.path())?;
+        let budget = ProbeBudget::unlimited().with_disk_bytes(0);
+        match index.search_with_budget(&1_234, budget)? {
+            SearchOutcome::Exceeded {
+                layer, disk_bytes, ..
+            } => {
+                assert_eq!(layer, 0);
+                assert!(disk_bytes > 0);
+            }
+            outcome => panic!("expected the budget to run out, got {:?}", outcome),
+        }
+
+        assert_eq!(
+            index.search_with_budget(&1_234, ProbeBudget::default())?,
+            SearchOutcome::Found(1_234)
+        );
+
+        // Once the path is cached, the same lookup reads nothing from disk
+        assert_eq!(
+            index.search_with_budget(&1_234, budget)?,
+            SearchOutcome::Found(1_234)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_insert_with_hint() -> limousine_engine::Result<()> {
+        use limousine_engine::SearchHint;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        // Mostly appending at the tail, with the occasional key far behind it
+        let keys: Vec<K> = (0..20_000)
+            .map(|i| if i % 100 == 0 { -i } else { i })
+            .collect();
+
+        let mut index = <KVStore1<K, V> as KVStore<K, V>>::empty();
+        let hint = SearchHint::new();
+        for &key in keys.iter() {
+            assert_eq!(index.insert_with_hint(key, key * 2, &hint), None);
+        }
+        assert_eq!(index.insert_with_hint(50, 0, &hint), Some(100));
+
+        for &key in keys.iter().filter(|&&key| key != 50) {
+            assert_eq!(index.search(key), Some(key * 2));
+        }
+
+        let temp_dir = tempdir()?;
+        let mut index = <KVStore2<K, V> as PersistedKVStore<K, V>>::open(temp_dir.path())?;
+        let hint = SearchHint::new();
+        for &key in keys.iter() {
+            assert_eq!(index.insert_with_hint(key, key * 2, &hint)?, None);
+        }
+
+        for &key in keys.iter() {
+            assert_eq!(index.search(key)?, Some(key * 2));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_read_only() -> limousine_engine::Result<()> {
+        use limousine_engine::IndexRead;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            read_only: true,
+        }
+
+        fn count_present(index: &impl IndexRead<K, V>) -> limousine_engine::Result<usize> {
+            let mut count = 0;
+            for key in 0..2_000 {
+                count += index.try_search(key)?.is_some() as usize;
+            }
+            Ok(count)
+        }
+
+        let index = KVStore1::<K, V>::build((0..1_000).map(|key| (key * 2, key)));
+        assert_eq!(index.try_search(10)?, Some(5));
+        assert_eq!(index.try_search(11)?, None);
+        assert_eq!(count_present(&index)?, 1_000);
+        assert_eq!(count_present(&index.clone())?, 1_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_seek() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let index: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key * 2, key)));
+
+        let mut scan = index.seek(&501);
+        assert_eq!(scan.peek(), Some((&502, &251)));
+
+        // Batches pick up right where the previous one stopped
+        let mut keys = Vec::new();
+        loop {
+            let batch = scan.next_n(7);
+            keys.extend(batch.iter().map(|(&key, _)| key));
+
+            if batch.len() < 7 {
+                break;
+            }
+        }
+        assert_eq!(keys, (251..1_000).map(|key| key * 2).collect::<Vec<_>>());
+
+        // An exhausted scan stays exhausted
+        assert!(scan.is_exhausted());
+        assert!(scan.next_n(10).is_empty());
+        assert!(index.seek(&5_000).is_exhausted());
+
+        let scan = index.seek(&0);
+        assert_eq!(scan.take(3).map(|(&key, _)| key).collect::<Vec<_>>(), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_kv_store_cfg_layout() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                #[cfg(test)] pgm(epsilon = 8),
+                #[cfg(not(test))] btree(fanout = 32),
+                #[cfg(all(test, not(test)))] btree(fanout = 64),
+                btree(fanout = 8),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+
+        // Only the learned layer is compiled in while testing
+        let index: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key, key)));
+        assert_eq!(index.layer_report().len(), 1);
+        assert_eq!(index.node_counts().len(), 2);
+    }
+
+    #[test]
+    fn test_kv_store_len() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore3,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ],
+            tombstones: true
+        }
+
+        create_kv_store! {
+            name: KVStore4,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+            ],
+            ttl: enabled
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::empty();
+        assert!(index.is_empty());
+
+        let mut index2: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key * 2, key)));
+        assert_eq!(index2.len(), 1_000);
+
+        // Overwrites don't add entries
+        for key in 0..2_000 {
+            index.insert(key, key);
+            index2.insert(key, key);
+        }
+        assert_eq!(index.len(), 2_000);
+        assert_eq!(index2.len(), 2_000);
+
+        let mut cursor = index.cursor_mut(0);
+        assert_eq!(cursor.remove_current(), Some((0, 0)));
+        assert_eq!(index.len(), 1_999);
+
+        // Every layer below the top reports its nodes, and the base has the most
+        let nodes = index.node_counts();
+        assert_eq!(nodes.iter().map(|(layer, _)| *layer).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(nodes[0].1 > nodes[1].1);
+
+        // Persisted counts survive a restart
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore2<K, V> = KVStore2::open(temp_dir.path())?;
+            for round in 0..2 {
+                for key in 0..1_000 {
+                    index.insert(key, key + round)?;
+                }
+            }
+            assert_eq!(index.len(), 1_000);
+        }
+
+        let index: KVStore2<K, V> = KVStore2::open(temp_dir.path())?;
+        assert_eq!(index.len(), 1_000);
+        assert!(index.node_counts()[0].1 > 1);
+
+        // Tombstones don't count, whether or not they were purged
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index: KVStore3<K, V> = KVStore3::open(temp_dir.path())?;
+            for key in 0..100 {
+                index.insert(key, key)?;
+            }
+            for key in 0..50 {
+                index.remove(key)?;
+            }
+            assert_eq!(index.len(), 50);
+
+            index.insert(0, 0)?;
+            assert_eq!(index.len(), 51);
+            index.remove(0)?;
+
+            index.purge_tombstones()?;
+            assert_eq!(index.len(), 50);
+        }
+
+        let index: KVStore3<K, V> = KVStore3::open(temp_dir.path())?;
+        assert_eq!(index.len(), 50);
+
+        // Expired entries count until they are swept
+        let temp_dir = tempdir()?;
+        let now = limousine_engine::private::unix_millis();
+
+        {
+            let mut index: KVStore4<K, V> = KVStore4::open(temp_dir.path())?;
+            for key in 0..100 {
+                if key < 50 {
+                    index.insert_expiring(key, key, now - 1)?;
+                } else {
+                    index.insert(key, key)?;
+                }
+            }
+            assert_eq!(index.len(), 100);
+
+            index.sweep_expired(now)?;
+            assert_eq!(index.len(), 50);
+        }
+
+        let index: KVStore4<K, V> = KVStore4::open(temp_dir.path())?;
+        assert_eq!(index.len(), 50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_cursor() {
+        use limousine_engine::CursorError;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key * 2, key)));
+
+        // Walk forwards and backwards from the middle
+        let mut cursor = index.cursor(501);
+        assert_eq!(cursor.current(), Some((&502, &251)));
+        assert_eq!(cursor.peek_prev(), Some((&500, &250)));
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&500));
+
+        let mut keys = Vec::new();
+        while let Some(&key) = cursor.key() {
+            keys.push(key);
+            cursor.move_next();
+        }
+        assert_eq!(keys, (250..1_000).map(|key| key * 2).collect::<Vec<_>>());
+
+        // The ghost position wraps around in both directions
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&0));
+        cursor.move_prev();
+        assert_eq!(cursor.key(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&1_998));
+        assert_eq!(index.cursor(5_000).key(), None);
+
+        // Merge-join style pass: double every value, and fill in every odd key
+        let mut cursor = index.cursor_mut(0);
+        while cursor.key().is_some() {
+            *cursor.value_mut().unwrap() *= 2;
+            cursor.move_next();
+
+            if let Some(&key) = cursor.key() {
+                assert_eq!(cursor.insert_before(key - 1, -1), Ok(()));
+            }
+        }
+
+        for key in 0..1_999 {
+            let expected = if key % 2 == 0 { key } else { -1 };
+            assert_eq!(index.search(key), Some(expected));
+        }
+
+        // Keys have to fit between the entries around the cursor
+        let mut cursor = index.cursor_mut(100);
+        assert_eq!(cursor.insert_before(99, 0), Err(CursorError::UnorderedKey));
+        assert_eq!(cursor.insert_before(100, 0), Err(CursorError::UnorderedKey));
+        assert_eq!(cursor.insert_before(101, 0), Err(CursorError::UnorderedKey));
+
+        // Remove every odd key again, whichever node it falls in
+        let mut cursor = index.cursor_mut(0);
+        while let Some(&key) = cursor.key() {
+            if key % 2 == 0 {
+                cursor.move_next();
+            } else {
+                assert_eq!(cursor.remove_current(), Some((key, -1)));
+            }
+        }
+
+        for key in 0..1_999 {
+            assert_eq!(index.search(key), (key % 2 == 0).then_some(key));
+        }
+
+        index.insert(1, 7);
+        assert_eq!(index.search(1), Some(7));
+    }
+
+    #[test]
+    fn test_kv_store_cursor_remove() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 4),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key, key)));
+        let nodes = index.node_counts();
+
+        // Keys fencing a base node are removed like any other, and the node keeps routing the
+        // keys above them
+        let fences: Vec<K> = (1..1_000)
+            .filter(|key| index.explain(key).steps.last().unwrap().node == Some(*key))
+            .collect();
+        assert!(fences.len() > 10);
+
+        for &fence in fences.iter() {
+            assert_eq!(index.cursor_mut(fence).remove_current(), Some((fence, fence)));
+            assert_eq!(index.search(fence), None);
+            assert_eq!(index.search(fence + 1), Some(fence + 1));
+        }
+        assert_eq!(index.len(), 1_000 - fences.len());
+
+        // Base nodes emptied by removals stay in place and are skipped over
+        let mut cursor = index.cursor_mut(200);
+        while cursor.key().is_some_and(|&key| key < 600) {
+            cursor.remove_current();
+        }
+        let above = (600..1_000).find(|key| !fences.contains(key));
+        let below = (0..200).rev().find(|key| !fences.contains(key));
+        assert_eq!(index.cursor(200).key().copied(), above);
+        assert_eq!(index.cursor(200).peek_prev().map(|(&key, _)| key), below);
+        assert_eq!(index.node_counts(), nodes);
+
+        // Removed keys can be inserted again, into the nodes they were removed from
+        for key in 0..1_000 {
+            index.insert(key, -key);
+        }
+        for key in 0..1_000 {
+            assert_eq!(index.search(key), Some(-key));
+        }
+        assert_eq!(index.len(), 1_000);
+        assert_eq!(index.node_counts(), nodes);
+
+        // Removing every key from the back leaves an empty index which still takes inserts
+        while let Some((&key, _)) = index.last() {
+            assert_eq!(index.cursor_mut(key).remove_current(), Some((key, -key)));
+        }
+        assert_eq!(index.first(), None);
+        assert!(index.is_empty());
+
+        index.insert(500, 1);
+        index.insert(5, 2);
+        assert_eq!(index.first(), Some((&5, &2)));
+        assert_eq!(index.last(), Some((&500, &1)));
+    }
+
+    #[test]
+    fn test_kv_store_set_ops() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let evens = KVStore1::<K, V>::build((0..1_000).map(|key| (key * 2, key)));
+        let threes = KVStore1::<K, V>::build((0..700).map(|key| (key * 3, key)));
+
+        let intersection: Vec<K> = evens.intersect_keys(&threes).copied().collect();
+        let expected: Vec<K> = (0..2_000).filter(|key| key % 6 == 0).collect();
+        assert_eq!(intersection, expected);
+
+        let union: Vec<K> = evens.union(&threes).copied().collect();
+        let expected: Vec<K> = (0..2_100)
+            .filter(|key| (key % 2 == 0 && *key < 2_000) || key % 3 == 0)
+            .collect();
+        assert_eq!(union, expected);
+
+        // Both are symmetric
+        assert!(threes.intersect_keys(&evens).copied().eq(intersection));
+        assert!(threes.union(&evens).copied().eq(union));
+
+        // Indexes without entries in common, or without entries at all
+        let high = KVStore1::<K, V>::build((5_000..5_010).map(|key| (key, key)));
+        let empty = KVStore1::<K, V>::empty();
+        assert_eq!(evens.intersect_keys(&high).count(), 0);
+        assert_eq!(evens.intersect_keys(&empty).count(), 0);
+        assert_eq!(evens.union(&high).count(), 1_010);
+        assert!(empty.union(&evens).copied().eq((0..1_000).map(|key| key * 2)));
+        assert_eq!(empty.union(&empty).count(), 0);
+        assert_eq!(evens.intersect_keys(&evens).count(), 1_000);
+    }
+
+    #[test]
+    fn test_kv_store_diff() {
+        use limousine_engine::{diff, LayerDiff};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        // Inserting one by one splits nodes, so the layers end up shaped differently than a build
+        let rebuilt = KVStore1::<K, V>::build((0..1_000).map(|key| (key, key)));
+        let mut maintained = KVStore1::<K, V>::empty();
+        for key in (0..1_000).rev() {
+            maintained.insert(key, key);
+        }
+
+        let report = diff(&rebuilt, &maintained);
+        assert!(report.same_entries());
+        assert!(!report.is_empty());
+        assert_eq!(report.layers[0].layer, 0);
+        assert_ne!(report.layers[0].left, report.layers[0].right);
+        assert!(diff(&rebuilt, &rebuilt).is_empty());
+
+        let mut changed = KVStore1::<K, V>::build((2..1_000).map(|key| (key, key)));
+        changed.insert(5, 50);
+        changed.insert(2_000, 2_000);
+
+        let report = diff(&rebuilt, &changed);
+        assert_eq!(report.removed, vec![(0, 0), (1, 1)]);
+        assert_eq!(report.added, vec![(2_000, 2_000)]);
+        assert_eq!(report.changed, vec![(5, 5, 50)]);
+
+        // The other way around, added and removed entries swap places
+        let report = diff(&changed, &rebuilt);
+        assert_eq!(report.added, vec![(0, 0), (1, 1)]);
+        assert_eq!(report.removed, vec![(2_000, 2_000)]);
+        assert_eq!(report.changed, vec![(5, 50, 5)]);
+
+        let empty = KVStore1::<K, V>::empty();
+        let report = diff(&empty, &rebuilt);
+        assert_eq!(report.added.len(), 1_000);
+        assert!(report.removed.is_empty() && report.changed.is_empty());
+        assert!(report.layers.iter().all(|layer: &LayerDiff| layer.left < layer.right));
+        assert!(diff(&empty, &empty).is_empty());
+    }
+
+    #[test]
+    fn test_kv_store_order_preserving() -> limousine_engine::Result<()> {
+        use limousine_engine::{Encoded, EncryptionKey, IndexRead, IndexWrite};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let key = EncryptionKey::generate();
+        let mut index: Encoded<KVStore1<K, V>, i64, K> = Encoded::new(KVStore1::empty(), &key);
+
+        for raw in (-500..500).map(|raw: i64| raw * 7) {
+            assert_eq!(index.try_put(raw, raw as V)?, None);
+        }
+
+        assert_eq!(index.try_search(-49)?, Some(-49));
+        assert_eq!(index.try_search(50)?, None);
+        assert_eq!(index.len(), 1_000);
+
+        // The wrapped index only holds codes, ordered like the keys
+        let code = index.encoding().encode(-49);
+        assert_eq!(index.inner().try_search(-49)?, None);
+        assert_eq!(index.inner().try_search(code)?, Some(-49));
+
+        let range: Vec<_> = index.range(-21..=14).collect();
+        assert_eq!(range, [-21, -14, -7, 0, 7, 14].map(|raw| (raw, raw as V)));
+        assert_eq!(index.range(3_493..).count(), 1);
+        assert_eq!(index.range(..).count(), 1_000);
+
+        // Without the key, the same codes decode to nothing
+        let stranger: Encoded<KVStore1<K, V>, i64, K> =
+            Encoded::new(index.into_inner(), &EncryptionKey::generate());
+        assert_eq!(stranger.try_search(-49)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_scope() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::build((0..100).map(|key| (key, key)));
+
+        // The same keys for three tenants, which don't see each other
+        for tenant in 1..4 {
+            let mut scope = index.scope(tenant);
+            assert_eq!(scope.tenant(), tenant);
+
+            for key in 0..1_000 {
+                assert_eq!(scope.insert(key, tenant * 10_000 + key), None);
+            }
+        }
+
+        let scope = index.scope(2);
+        assert_eq!(scope.search(7), Some(20_007));
+        assert_eq!(scope.search(1_000), None);
+        assert_eq!(scope.search(-1), None);
+        assert_eq!(scope.search(1 << 64), None);
+
+        let range: Vec<_> = scope.range(995..).collect();
+        assert_eq!(range, (995..1_000).map(|key| (key, 20_000 + key)).collect::<Vec<_>>());
+        assert_eq!(scope.range(..3).count(), 3);
+        assert_eq!(scope.range(-5..=0).collect::<Vec<_>>(), vec![(0, 20_000)]);
+        assert_eq!(scope.range(2_000..).count(), 0);
+        assert_eq!(scope.range(..).count(), 1_000);
+
+        // Clearing a scope leaves every other tenant, and the unscoped keys, in place, and removes
+        // the keys of the scope from their nodes rather than rebuilding the index
+        let node_counts = index.node_counts();
+        assert_eq!(index.scope(2).clear_scope(), 1_000);
+        assert_eq!(index.node_counts(), node_counts);
+        assert_eq!(index.scope(2).range(..).count(), 0);
+        assert_eq!(index.scope(2).clear_scope(), 0);
+
+        for key in 0..1_000 {
+            assert_eq!(index.scope(1).search(key), Some(10_000 + key));
+            assert_eq!(index.scope(3).search(key), Some(30_000 + key));
+        }
+        for key in 0..100 {
+            assert_eq!(index.search(key), Some(key));
+        }
+
+        index.scope(2).insert(5, 5);
+        assert_eq!(index.scope(2).range(..).collect::<Vec<_>>(), vec![(5, 5)]);
+    }
+
+    #[test]
+    fn test_kv_store_compare_and_swap() {
+        use limousine_engine::CasError;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::build((0..1_000).map(|key| (key * 2, key)));
+
+        // Updates only go through while the key holds the expected value
+        assert_eq!(index.compare_and_swap(10, Some(&5), Some(50)), Ok(()));
+        assert_eq!(
+            index.compare_and_swap(10, Some(&5), Some(500)),
+            Err(CasError::Mismatch {
+                actual: Some(50),
+                new: Some(500)
+            })
+        );
+        assert_eq!(index.search(10), Some(50));
+
+        // Absent keys are expected as `None`, and can be inserted
+        assert_eq!(
+            index.compare_and_swap(11, Some(&0), Some(11)),
+            Err(CasError::Mismatch {
+                actual: None,
+                new: Some(11)
+            })
+        );
+        assert_eq!(index.compare_and_swap(11, None, Some(11)), Ok(()));
+        assert_eq!(index.compare_and_swap(5_000, None, Some(0)), Ok(()));
+        assert_eq!(index.search(11), Some(11));
+        assert_eq!(index.search(5_000), Some(0));
+
+        // Removals as well, and racing removals see the key gone
+        assert_eq!(index.compare_and_swap(11, Some(&11), None), Ok(()));
+        assert_eq!(
+            index.compare_and_swap(11, Some(&11), None),
+            Err(CasError::Mismatch {
+                actual: None,
+                new: None
+            })
+        );
+        assert_eq!(index.compare_and_swap(11, None, None), Ok(()));
+        assert_eq!(index.search(11), None);
+
+        // Keys fencing a base node are removed like any other
+        let fence = (100..1_000)
+            .map(|key| key * 2)
+            .find(|key| index.explain(key).steps.last().unwrap().node == Some(*key))
+            .unwrap();
+        assert_eq!(index.compare_and_swap(fence, Some(&(fence / 2)), None), Ok(()));
+        assert_eq!(index.search(fence), None);
+        assert_eq!(index.compare_and_swap(fence, None, Some(0)), Ok(()));
+        assert_eq!(index.search(fence), Some(0));
+
+        // A cursor elsewhere in the index moves to the key first
+        let mut cursor = index.cursor_mut(1_500);
+        assert_eq!(cursor.compare_and_swap(13, None, Some(13)), Ok(()));
+        assert_eq!(cursor.compare_and_swap(20, Some(&10), None), Ok(()));
+        assert_eq!(cursor.key(), Some(&22));
+        assert_eq!(index.search(13), Some(13));
+        assert_eq!(index.search(20), None);
+    }
+
+    #[test]
+    fn test_kv_store_apply_batch() {
+        use limousine_engine::WriteBatch;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: BoundedStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            max_memory: 64KB,
+        }
+
+        let mut index = KVStore1::<K, V>::build((0..1_000).map(|key| (key * 2, key)));
+
+        let mut batch = WriteBatch::new();
+        batch.put(1, 1).put(2, 20).delete(0).delete(3).put(0, 100);
+        assert_eq!(batch.len(), 5);
+        index.apply_batch(batch).unwrap();
+
+        assert_eq!(index.search(0), Some(100));
+        assert_eq!(index.search(1), Some(1));
+        assert_eq!(index.search(2), Some(20));
+        assert_eq!(index.search(3), None);
+
+        // Deletes reach the keys fencing base nodes as well
+        let batch: WriteBatch<K, V> = (0..1_000).map(|key| (key, None)).collect();
+        index.apply_batch(batch).unwrap();
+        assert_eq!(index.first(), Some((&1_000, &500)));
+
+        // A put past the budget undoes the writes before it, deletes included
+        let mut bounded = BoundedStore1::<K, V>::empty();
+        let mut entries = 0;
+        while bounded.insert_bounded(entries, entries).is_ok() {
+            entries += 1;
+        }
+
+        let mut batch = WriteBatch::new();
+        batch.delete(0).put(1, -1).delete(2).put(entries, 0);
+        let error = bounded.apply_batch(batch).unwrap_err();
+        assert_eq!((error.index, error.key), (3, entries));
+
+        for key in 0..entries {
+            assert_eq!(bounded.search(key), Some(key));
+        }
+        assert_eq!(bounded.search(entries), None);
+
+        // Deletes and overwrites never grow the index, so they still go through
+        let mut batch = WriteBatch::new();
+        batch.delete(0).put(1, -1);
+        bounded.apply_batch(batch).unwrap();
+        assert_eq!(bounded.search(0), None);
+        assert_eq!(bounded.search(1), Some(-1));
+    }
+
+    #[test]
+    fn test_kv_store_export_sorted() -> limousine_engine::Result<()> {
+        use limousine_engine::{ExportSorted, ImportSorted};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        let mut index: KVStore1<K, V> = KVStore1::build((0..10_000).map(|key| (key * 2, key)));
+        for key in 0..1_000 {
+            index.insert(key * 20 + 1, -key);
+        }
+
+        let mut stream = Vec::new();
+        assert_eq!(index.export_sorted(&mut stream)?, 11_000);
+
+        // Streams can be read into any in-memory layout
+        let copy = KVStore1::<K, V>::import_sorted(stream.as_slice())?;
+        let learned = PGMStore1::<K, V>::import_sorted(stream.as_slice())?;
+        for key in 0..20_000 {
+            assert_eq!(copy.search(key), index.search(key));
+            assert_eq!(learned.search(key), index.search(key));
+        }
+
+        // Nothing is imported from a damaged stream
+        let mut corrupted = stream.clone();
+        let middle = corrupted.len() / 2;
+        corrupted[middle] ^= 1;
+        assert!(KVStore1::<K, V>::import_sorted(corrupted.as_slice()).is_err());
+        assert!(KVStore1::<K, V>::import_sorted(&stream[..stream.len() - 1]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_try_insert() -> limousine_engine::Result<()> {
+        use limousine_engine::OccupiedError;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 4),
+                pgm(epsilon = 4),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore3,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8, persist),
+                btree(fanout = 8, persist),
+            ]
+        }
+
+        fn check<KV: KVStore<K, V>>() {
+            let mut index = KV::build((0..1_000).map(|key| (key * 2, key)));
+
+            // Overwrites hand back the previous value, from both base node kinds
+            assert_eq!(index.insert(10, -10), Some(5));
+            assert_eq!(index.insert(10, -20), Some(-10));
+            assert_eq!(index.insert(11, 11), None);
+            assert_eq!(index.insert(11, 12), Some(11));
+
+            assert_eq!(index.try_insert(13, 13), Ok(()));
+            assert_eq!(
+                index.try_insert(10, 0),
+                Err(OccupiedError {
+                    existing: -20,
+                    value: 0
+                })
+            );
+            assert_eq!(index.search(10), Some(-20));
+            assert_eq!(index.search(13), Some(13));
+        }
+
+        check::<KVStore1<K, V>>();
+        check::<KVStore2<K, V>>();
+
+        let temp_dir = tempdir()?;
+        let mut index: KVStore3<K, V> = KVStore3::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            assert_eq!(index.insert(key * 2, key)?, None);
+        }
+
+        assert_eq!(index.insert(10, -10)?, Some(5));
+        assert_eq!(index.insert(11, 11)?, None);
+        assert_eq!(index.try_insert(13, 13)?, Ok(()));
+        assert_eq!(
+            index.try_insert(11, 0)?,
+            Err(OccupiedError {
+                existing: 11,
+                value: 0
+            })
+        );
+        assert_eq!(index.search(11)?, Some(11));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_borrowed() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ],
+            borrowed: true,
+        }
+
+        create_kv_store! {
+            name: ReadOnlyKVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            read_only: true,
+            borrowed: true,
+        }
+
+        // Values don't even have to be `Clone`, since they are never copied into the index
+        #[derive(Debug, PartialEq)]
+        struct Blob(Vec<u8>);
+
+        let entries: Vec<(K, Blob)> = (0..2_000)
+            .map(|key| (key * 2, Blob(vec![key as u8; 16])))
+            .collect();
+
+        let index = KVStore1Ref::build(&entries);
+        let read_only = ReadOnlyKVStore1Ref::build(&entries);
+        assert_eq!(index.len(), 2_000);
+
+        for key in 0..4_000 {
+            let expected = (key % 2 == 0).then(|| &entries[key as usize / 2].1);
+            assert_eq!(index.search(key), expected);
+            assert_eq!(read_only.search(key), expected);
+        }
+
+        assert!(std::ptr::eq(index.search(10).unwrap(), &entries[5].1));
+    }
+
+    #[test]
+    fn test_kv_store_handles() {
+        use limousine_engine::SlabStore;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            values: handles,
+        }
+
+        // Values borrow from a local, which components could not store themselves
+        let words: Vec<String> = (0..1_000).map(|key| format!("word{}", key)).collect();
+
+        let mut index = KVStore1Handles::build(
+            (0..500).map(|key| (key as K * 2, words[key].as_str())),
+            SlabStore::new(),
+        );
+
+        for key in 0..1_000 {
+            let expected = (key % 2 == 0).then(|| words[key as usize / 2].as_str());
+            assert_eq!(index.search(key).copied(), expected);
+        }
+
+        // Overwrites hand back the previous value and free its slot in the store
+        assert_eq!(
+            index.insert(10, words[999].as_str()),
+            Some(words[5].as_str())
+        );
+        assert_eq!(index.insert(11, words[998].as_str()), None);
+        assert_eq!(index.search(10).copied(), Some(words[999].as_str()));
+        assert_eq!(index.search(11).copied(), Some(words[998].as_str()));
+        assert_eq!(index.store().len(), 501);
+
+        let mut index = KVStore1Handles::new(SlabStore::new());
+        assert!(index.store().is_empty());
+        assert_eq!(index.insert(3, &words[3][..]), None);
+        assert_eq!(index.search(3).copied(), Some("word3"));
+    }
+
+    #[test]
+    fn test_persisted_kv_store_read_only() -> limousine_engine::Result<()> {
+        use limousine_engine::IndexRead;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ]
+        }
+
+        create_kv_store! {
+            name: ReadOnlyKVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, persist),
+            ],
+            read_only: true,
+        }
+
+        let temp_dir = tempdir()?;
+
+        {
+            let mut index = KVStore1::<K, V>::open(temp_dir.path())?;
+            for key in 0..1_000 {
+                index.insert(key, key + 1)?;
+            }
+        }
+
+        // Opens the files written by the writable layout above
+        let index = ReadOnlyKVStore1::<K, V>::open(temp_dir.path())?;
+        for key in 0..1_000 {
+            assert_eq!(index.try_search(key)?, Some(key + 1));
+        }
+        assert_eq!(index.try_search(1_000)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pgm_store_mvcc() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ],
+            versioning: mvcc,
+        }
+
+        test_kv_store_build::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_kv_store_trace() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let collector = EventCollector::default();
+        tracing::subscriber::with_default(collector.clone(), || {
+            let mut kv_store = KVStore1::<K, V>::empty();
+
+            for key in 0..1_000 {
+                kv_store.insert(key, key);
+            }
+
+            collector.events.lock().unwrap().clear();
+            assert_eq!(kv_store.search(500), Some(500));
+        });
+
+        // One event per layer on the way down
+        let events = collector.events.lock().unwrap().clone();
+        assert_eq!(events.len(), 3);
+        assert!(events[0].contains(&"component=\"BTreeTop\"".to_string()));
+        assert!(events[1].contains(&"component=\"InMemoryBTreeInternal8\"".to_string()));
+        assert!(events[2].contains(&"found=true".to_string()));
+
+        // Inserting in order splits the rightmost base node every so often
+        let collector = EventCollector::default();
+        tracing::subscriber::with_default(collector.clone(), || {
+            let mut kv_store = KVStore1::<K, V>::empty();
+
+            for key in 0..1_000 {
+                kv_store.insert(key, key);
+            }
+        });
+
+        let events = collector.events.lock().unwrap().clone();
+        assert!(events
+            .iter()
+            .any(|event| event.contains(&"split=true".to_string())));
+    }
+
+    #[test]
+    fn test_pgm_store_explain() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let index = PGMStore1::<K, V>::build((0..10_000).map(|key| (2 * key, key)));
+
+        let trace = index.explain(&500);
+        assert!(trace.found);
+        assert_eq!(trace.key, 500);
+
+        let layers: Vec<usize> = trace.steps.iter().map(|step| step.layer).collect();
+        assert_eq!(layers, vec![2, 1, 0]);
+
+        // Every node searched covers the key
+        assert_eq!(trace.steps[0].node, None);
+        assert!(trace.steps[1..]
+            .iter()
+            .all(|step| step.node.is_some_and(|node| node <= 500)));
+
+        // Only the learned layer has an approximation window
+        assert!(trace.steps[1].probe.window.is_some());
+        assert!(trace.steps[2].probe.window.is_none());
+        assert!(trace.steps.iter().all(|step| step.probe.comparisons > 0));
+
+        assert!(!index.explain(&501).found);
+        assert!(trace.to_string().contains("PGMInternal8"));
+    }
+
+    #[test]
+    fn test_persisted_kv_store_explain() -> limousine_engine::Result<()> {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16, persist),
+                btree(fanout = 16, persist),
+            ]
+        }
+
+        let temp_dir = tempdir()?;
+        let mut index: KVStore1<K, V> = KVStore1::open(temp_dir.path())?;
+
+        for key in 0..1_000 {
+            index.insert(key, key)?;
+        }
+
+        let trace = index.explain(&123)?;
+        assert!(trace.found);
+        assert_eq!(trace.steps.len(), 4);
+        assert_eq!(trace.steps[3].layer, 0);
+        assert!(trace.comparisons() > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_store_clone_1() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        test_kv_store_clone::<KVStore1<K, V>>();
+    }
+
+    #[test]
+    fn test_pgm_store_clone() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        let keys: Vec<K> = (0..10_000).map(|x| 3 * x).collect();
+        let original = PGMStore1::<K, V>::build(keys.iter().map(|&k| (k, k + 1)));
+        let cloned = original.clone();
+        drop(original);
+
+        for &key in keys.iter() {
+            assert_eq!(cloned.search(key), Some(key + 1));
+            assert_eq!(cloned.search(key + 1), None);
+        }
+    }
+
+    #[test]
+    fn test_kv_store_stale_hint() {
+        use limousine_engine::SearchHint;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ]
+        }
+
+        let mut index = <KVStore1<K, V> as KVStore<K, V>>::empty();
+        let hint = SearchHint::new();
+        for key in 0..5_000 {
+            index.insert_with_hint(key, key * 2, &hint);
+        }
+
+        // Every node moves, so the hint points at a slot of the old layout
+        index.compact_memory();
+        for key in 5_000..10_000 {
+            index.insert_with_hint(key, key * 2, &hint);
+        }
+
+        index.rebuild();
+        index.compact_memory();
+        assert_eq!(index.insert_with_hint(7_500, 0, &hint), Some(15_000));
+        assert_eq!(index.insert_with_hint(10_000, 1, &hint), None);
+
+        for key in (0..10_000).filter(|&key| key != 7_500) {
+            assert_eq!(index.search(key), Some(key * 2));
+        }
+        assert_eq!(index.search(10_000), Some(1));
+    }
+
+    #[test]
+    fn test_kv_store_append_hint() {
+        create_kv_store! {
+            name: AppendStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            append_hint: true,
+        }
+
+        create_kv_store! {
+            name: AppendStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 16),
+            ],
+            append_hint: true,
+        }
+
+        test_kv_store::<AppendStore1<K, V>>();
+        test_kv_store::<AppendStore2<K, V>>();
+
+        // Ascending keys take the fast path, with a few keys behind the tail mixed in
+        let mut index = AppendStore1::<K, V>::empty();
+        for key in 0..20_000 {
+            assert_eq!(index.insert(key * 2, key), None);
+
+            if key % 100 == 50 {
+                assert_eq!(index.insert(key + 1, -key), None);
+                assert_eq!(index.insert(key, 0), Some(key / 2));
+            }
+        }
+
+        for key in 0..20_000 {
+            let behind = key % 50 == 25 && key < 10_000;
+            let expected = if behind { 0 } else { key };
+
+            assert_eq!(index.search(key * 2), Some(expected));
+            assert_eq!(index.search(key * 2 + 1), behind.then_some(-key * 2));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_snapshot() {
+        create_kv_store! {
+            name: BTreeStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ]
+        }
+
+        let mut index = BTreeStore1::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        assert_eq!(index.shared_nodes(), 0);
+
+        // Nodes are held inline, so a clone copies them
+        let clone = index.clone();
+        assert_eq!((index.shared_nodes(), clone.shared_nodes()), (0, 0));
+
+        let mut snapshot = index.snapshot();
+        let shared = index.shared_nodes();
+        assert!(shared > 0);
+        assert_eq!(snapshot.shared_nodes(), shared);
+
+        // Writes to either index only copy the nodes along their path
+        for key in 0..10 {
+            snapshot.insert(key * 2 + 1, -key);
+        }
+        index.insert(1_000, 0);
+
+        assert!(index.shared_nodes() < shared);
+        assert!(index.shared_nodes() > shared / 2);
+
+        for key in 0..10 {
+            assert_eq!(snapshot.search(key * 2 + 1), Some(-key));
+            assert_eq!(index.search(key * 2 + 1), None);
+        }
+        assert_eq!(index.search(1_000), Some(0));
+        assert_eq!(snapshot.search(1_000), Some(500));
+
+        drop(index);
+        assert_eq!(snapshot.shared_nodes(), 0);
+    }
+
+    #[test]
+    fn test_kv_store_compact_memory() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                rmi_top(),
+                bucket(count = 16),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        // Inserting in scrambled order splits nodes all over the arenas
+        let keys: Vec<K> = (0..10_007).map(|key| (key * 7_919) % 10_007).collect();
+        let mut index = KVStore1::<K, V>::empty();
+        for &key in keys.iter() {
+            index.insert(key, -key);
+        }
+
+        index.compact_memory();
+
+        let mut cursor = index.cursor(0);
+        for key in 0..10_007 {
+            assert_eq!(cursor.current(), Some((&key, &-key)));
+            assert_eq!(index.search(key), Some(-key));
+            cursor.move_next();
+        }
+        assert_eq!(cursor.key(), None);
+
+        // Inserts keep splitting nodes, and compacting twice is fine
+        for key in 10_007..20_000 {
+            index.insert(key, -key);
+        }
+        index.compact_memory();
+        index.compact_memory();
+
+        // Nodes shared with a snapshot move back into the arenas, copied if still shared
+        let snapshot = index.snapshot();
+        assert!(index.shared_nodes() > 0);
+        index.compact_memory();
+        assert_eq!((index.shared_nodes(), snapshot.shared_nodes()), (0, 0));
+        assert_eq!(snapshot.search(19_999), Some(-19_999));
+
+        for key in 0..20_000 {
+            assert_eq!(index.search(key), Some(-key));
+        }
+
+        let mut index = KVStore2::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        for &key in keys.iter().filter(|key| *key % 2 == 1) {
+            index.insert(key, -key);
+        }
+
+        index.compact_memory();
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            if key * 2 + 1 < 10_007 {
+                assert_eq!(index.search(key * 2 + 1), Some(-(key * 2 + 1)));
+            }
+        }
+
+        index.insert(-1, 1);
+        assert_eq!(index.search(-1), Some(1));
+    }
+
+    #[test]
+    fn test_kv_store_rebuild() {
+        use limousine_engine::{RMITop, RebuildComponent};
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                rmi_top(),
+                bucket(count = 16),
+                btree(fanout = 8),
+            ],
+            swappable_top: true,
+        }
+
+        let mut index = KVStore1::<K, V>::build((0..5_000).map(|key| (key * 2, key)));
+        for key in 0..5_000 {
+            index.insert(key * 2 + 1, -key);
+        }
+
+        // A rebuild installed into the internal layer in place counts as a replacement
+        let plan = index.c1.plan_rebuild(&index.c0);
+        index.c1.apply_rebuild(&mut index.c0, plan).unwrap();
+        assert_eq!(index.layer_report()[0].rebuilds, 2);
+
+        // Rebuilding only the top leaves the internal layer as is
+        index.rebuild_layer(2);
+        assert_eq!(index.layer_report()[0].rebuilds, 2);
+
+        // While a full rebuild builds it from scratch
+        index.rebuild();
+        assert_eq!(index.layer_report()[0].rebuilds, 1);
+
+        for key in 0..5_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            assert_eq!(index.search(key * 2 + 1), Some(-key));
+        }
+
+        index.insert(10_000, 0);
+        assert_eq!(index.search(10_000), Some(0));
+
+        let mut index = KVStore2::<K, V>::empty();
+        for key in 0..5_000 {
+            index.insert(key, -key);
+        }
+
+        index.swap_top::<RMITop>();
+        index.rebuild();
+        assert_eq!(index.top_kind(), "RMITop");
+
+        index.rebuild_layer(2);
+        for key in 0..5_000 {
+            assert_eq!(index.search(key), Some(-key));
+        }
+
+        let result = std::panic::catch_unwind(move || index.rebuild_layer(3));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_kv_store_rebuild_below_min() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 4),
+                btree(fanout = 4),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                bucket(count = 4),
+                btree(fanout = 4),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore3,
+            layout: [
+                btree_top(),
+                btree(fanout = 4, fences = truncated(2)),
+                dense(),
+                btree(fanout = 4),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore4,
+            layout: [
+                art_top(),
+                pgm(epsilon = 4),
+                btree(fanout = 4),
+            ]
+        }
+
+        const LATER: [K; 15] = [
+            305, 352, 394, 12, 206, 233, 172, 244, 91, 364, 185, 389, -7, 2, 1,
+        ];
+
+        // Every layer above the base is rebuilt while the smallest key is 329, and keys below it
+        // are inserted afterwards
+        fn check<S: KVStore<K, V>>(mut index: S, rebuild: impl Fn(&mut S)) {
+            index.insert(333, 0);
+            index.insert(329, 1);
+            rebuild(&mut index);
+
+            for key in LATER {
+                index.insert(key, key + 1);
+            }
+
+            assert_eq!(index.search(333), Some(0));
+            assert_eq!(index.search(329), Some(1));
+            for key in LATER {
+                assert_eq!(index.search(key), Some(key + 1));
+            }
+        }
+
+        check(KVStore1::<K, V>::empty(), KVStore1::rebuild);
+        check(KVStore1::<K, V>::empty(), |index| index.rebuild_layer(2));
+        check(KVStore2::<K, V>::empty(), KVStore2::rebuild);
+        check(KVStore2::<K, V>::empty(), |index| index.rebuild_layer(2));
+        check(KVStore3::<K, V>::empty(), KVStore3::rebuild);
+        check(KVStore3::<K, V>::empty(), |index| index.rebuild_layer(3));
+        check(KVStore4::<K, V>::empty(), KVStore4::rebuild);
+        check(KVStore4::<K, V>::empty(), |index| index.rebuild_layer(2));
+
+        // A freshly built index is anchored the same way
+        let mut index = KVStore1::<K, V>::build([(329, 1), (333, 0)].into_iter());
+        for key in LATER {
+            index.insert(key, key + 1);
+        }
+        for key in LATER {
+            assert_eq!(index.search(key), Some(key + 1));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_string_keys() {
+        create_kv_store! {
+            name: NameStore,
+            layout: [
+                btree_top(max_entries = 4),
+                btree(fanout = 4),
+                btree(fanout = 4),
+            ]
+        }
+
+        // Fixed length strings have no integer arithmetic, BTree layers only compare them
+        type Name = [u8; 6];
+
+        fn name(index: usize) -> Name {
+            let mut name = *b"      ";
+            let mut rest = index;
+            for byte in name.iter_mut().rev().take(4) {
+                *byte = b'a' + (rest % 26) as u8;
+                rest /= 26;
+            }
+            name
+        }
+
+        let mut index = NameStore::<Name, usize>::build((500..1000).map(|i| (name(i), i)));
+        for i in (0..500).rev() {
+            index.insert(name(i), i);
+        }
+
+        // Keys sorting below every key present when the index was built
+        index.insert(*b"      ", usize::MAX);
+        index.rebuild();
+        index.insert(*b"     a", usize::MAX - 1);
+
+        for i in 0..1000 {
+            assert_eq!(index.search(name(i)), Some(i));
+        }
+        assert_eq!(index.search(*b"      "), Some(usize::MAX));
+        assert_eq!(index.search(*b"     a"), Some(usize::MAX - 1));
+        assert_eq!(index.search(*b"zzzzzz"), None);
+
+        let mut cursor = index.cursor(name(0));
+        for i in 0..1000 {
+            assert_eq!(cursor.current(), Some((&name(i), &i)));
+            cursor.move_next();
+        }
+    }
+
+    #[test]
+    fn test_kv_store_search_strategy() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 16, search = branchless),
+                btree(fanout = 16, search = linear),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 64, search = binary),
+                btree(fanout = 256, search = branchless),
+            ]
+        }
+
+        test_kv_store::<KVStore1<K, V>>();
+        test_kv_store::<KVStore2<K, V>>();
+
+        let index = KVStore2::<K, V>::build((0..10_000).map(|key| (key * 3, key)));
+        for key in -10..30_010 {
+            let expected = ((0..30_000).contains(&key) && key % 3 == 0).then_some(key / 3);
+            assert_eq!(index.search(key), expected);
+        }
+    }
+
+    #[test]
+    fn test_kv_store_split_policy() {
+        use limousine_engine::private::NodeLayer;
+
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 16),
+                btree(fanout = 32),
+            ]
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                btree(fanout = 16, split = lean_right),
+                btree(fanout = 32, search = linear, split = append),
+            ]
+        }
+
+        test_kv_store::<KVStore2<K, V>>();
+
+        let mut even = KVStore1::<K, V>::empty();
+        let mut append = KVStore2::<K, V>::empty();
+        for key in 0..10_000 {
+            even.insert(key, -key);
+            append.insert(key, -key);
+        }
+
+        // Appends fill base nodes instead of leaving them half empty
+        assert!(append.c0.node_count() * 3 < even.c0.node_count() * 2);
+        assert!(append.c1.node_count() * 3 < even.c1.node_count() * 2);
+
+        for key in 0..10_000 {
+            assert_eq!(append.search(key), Some(-key));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_fast_fences() {
+        create_kv_store! {
+            name: KVStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ],
+            fast_fences: 64,
+        }
+
+        create_kv_store! {
+            name: KVStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ],
+            fast_fences: 16,
+        }
+
+        let mut index = KVStore1::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        assert_eq!(index.fast_fences().capacity(), 64);
+
+        // Repeated lookups of a few hot keys only descend once per key
+        let hot = [4, 1_000, 7_777 * 2, 19_998];
+        for _ in 0..10 {
+            for &key in hot.iter() {
+                assert_eq!(index.search(key), Some(key / 2));
+            }
+        }
+        assert_eq!(index.fast_fences().misses(), hot.len());
+        assert_eq!(index.fast_fences().hits(), 9 * hot.len());
+
+        // Splits move keys out of their cached nodes, which only costs a miss
+        for key in 0..10_000 {
+            index.insert(key * 2 + 1, -key);
+        }
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            assert_eq!(index.search(key * 2 + 1), Some(-key));
+        }
+
+        // As do the nodes moved by compacting the layers
+        index.compact_memory();
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            assert_eq!(index.search(key * 2 + 1), Some(-key));
+        }
+
+        let clone = index.clone();
+        index.fast_fences().clear();
+        assert_eq!(index.fast_fences().hits(), 0);
+        for key in 0..20_000 {
+            assert_eq!(clone.search(key), index.search(key));
+        }
+
+        let mut index = KVStore2::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        for _ in 0..3 {
+            for key in -10..20_010 {
+                let expected = ((0..20_000).contains(&key) && key % 2 == 0).then_some(key / 2);
+                assert_eq!(index.search(key), expected);
+            }
+        }
+        assert!(index.fast_fences().hits() > 0);
+
+        for key in 0..10_000 {
+            index.insert(key * 2 + 1, -key);
+        }
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            assert_eq!(index.search(key * 2 + 1), Some(-key));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_filter() {
+        use limousine_engine::{FilterKind, IndexRead};
+
+        create_kv_store! {
+            name: LearnedStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 8),
+            ],
+            filter: learned(fpr = 0.01),
+        }
+
+        create_kv_store! {
+            name: BloomStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8),
+            ],
+            filter: bloom(fpr = 0.01),
+        }
+
+        create_kv_store! {
+            name: ReadOnlyStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 8),
+            ],
+            filter: learned(fpr = 0.01),
+            read_only: true,
+        }
+
+        test_kv_store::<LearnedStore<K, V>>();
+        test_kv_store::<BloomStore<K, V>>();
+
+        let entries = || (0..100_000).map(|key| (key * 4, key));
+        let learned = LearnedStore::<K, V>::build(entries());
+        let bloom = BloomStore::<K, V>::build(entries());
+        assert_eq!(learned.filter().kind(), FilterKind::Learned);
+        assert_eq!(bloom.filter().kind(), FilterKind::Bloom);
+
+        // Evenly spaced keys fit a single segment, and take next to no memory
+        assert_eq!(learned.filter().segments().len(), 1);
+        assert_eq!(learned.filter().learned_keys(), 100_000);
+        assert!(learned.filter().backup().is_empty());
+        assert!(learned.filter().memory_usage() * 100 < bloom.filter().memory_usage());
+
+        // Neither filter turns away a key the index holds, and both stay within their rate
+        let mut false_positives = 0;
+        for key in 0..100_000 {
+            assert_eq!(learned.search(key * 4), Some(key));
+            assert_eq!(bloom.search(key * 4), Some(key));
+
+            assert_eq!(learned.search(key * 4 + 1), None);
+            assert_eq!(bloom.search(key * 4 + 1), None);
+            assert!(!learned.filter().may_contain(&(key * 4 + 1)));
+            if bloom.filter().may_contain(&(key * 4 + 1)) {
+                false_positives += 1;
+            }
+        }
+        assert!(false_positives < 1_000);
+
+        // Irregular keys go to the backup filter, runs between them are still learned
+        let irregular = (1..1_000).map(|key| key * key * 7);
+        let run = (0..1_000).map(|key| 10_000_000 + key * 3);
+        let mixed = irregular.clone().chain(run).map(|key| (key, key));
+        let mut index = LearnedStore::<K, V>::build(mixed);
+        assert_eq!(index.filter().segments().len(), 1);
+        assert_eq!(index.filter().learned_keys(), 1_000);
+        assert_eq!(index.filter().backup().len(), 999);
+
+        // Appends continuing the segment extend it, other inserts grow the backup filter
+        for key in 1_000..2_000 {
+            index.insert(10_000_000 + key * 3, key);
+        }
+        assert_eq!(index.filter().learned_keys(), 2_000);
+
+        for key in 0..10_000 {
+            index.insert(key * 5 + 1, key);
+        }
+        assert!(index.filter().backup().stages() > 1);
+        for key in irregular {
+            assert_eq!(index.search(key), Some(key));
+        }
+        for key in 0..2_000 {
+            assert!(index.search(10_000_000 + key * 3).is_some());
+        }
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 5 + 1), Some(key));
+        }
+
+        // Clones keep the filter, and empty indexes start out with one
+        let clone = index.clone();
+        assert_eq!(clone.search(10_000_000), Some(10_000_000));
+
+        let mut empty = LearnedStore::<K, V>::empty();
+        assert_eq!(empty.search(3), None);
+        empty.insert(3, 3);
+        assert_eq!(empty.search(3), Some(3));
+
+        let index = ReadOnlyStore::<K, V>::build(entries());
+        for key in 0..1_000 {
+            assert_eq!(index.try_search(key * 4).unwrap(), Some(key));
+            assert_eq!(index.try_search(key * 4 + 2).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_kv_store_insert_bounded() {
+        use limousine_engine::CapacityExceeded;
+
+        create_kv_store! {
+            name: BoundedStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            max_memory: 256KB,
+        }
+
+        create_kv_store! {
+            name: BoundedStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 16),
+            ]
+        }
+
+        // Learned layers count the slots of their segments, which hold every entry
+        let index = BoundedStore2::<K, V>::build((0..100_000).map(|key| (key, key)));
+        assert!(index.memory_usage() > 100_000 * 2 * std::mem::size_of::<K>());
+
+        let mut index = BoundedStore1::<K, V>::empty();
+        let mut inserted = 0;
+
+        let error = loop {
+            let usage = index.memory_usage();
+
+            match index.insert_bounded(inserted, inserted) {
+                Ok(()) => inserted += 1,
+                Err(error) => break error,
+            }
+
+            assert!(index.memory_usage() >= usage);
+        };
+
+        let (used, budget) = (index.memory_usage(), 256 << 10);
+        assert_eq!(error, CapacityExceeded { used, budget });
+        assert!(used > budget);
+        assert!(inserted > 1_000);
+
+        // Overwrites don't grow the index, and unbounded inserts ignore the budget
+        assert_eq!(index.insert_bounded(0, -1), Ok(()));
+        assert_eq!(index.insert(inserted, inserted), None);
+
+        for key in 0..=inserted {
+            let expected = if key == 0 { -1 } else { key };
+            assert_eq!(index.search(key), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_art_top() {
+        create_kv_store! {
+            name: ARTStore1,
+            layout: [
+                art_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 16),
+            ]
+        }
+
+        create_kv_store! {
+            name: ARTStore2,
+            layout: [
+                art_top(),
+                pgm(epsilon = 4),
+                btree(fanout = 16),
+            ]
+        }
+
+        test_kv_store_build::<ARTStore1<K, V>>();
+        test_kv_store::<ARTStore2<K, V>>();
+
+        // Dense keys on both sides of zero, so the bytes of the keys flip at the sign bit
+        let mut index = ARTStore1::<K, V>::build((-5_000..5_000).map(|key| (key * 2, key)));
+        assert_eq!(index.explain(&300).steps[0].component, "ARTTop");
+
+        for key in -5_000..5_000 {
+            index.insert(key * 2 + 1, -key);
+        }
+
+        for key in -5_000..5_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+            assert_eq!(index.search(key * 2 + 1), Some(-key));
+        }
+        assert_eq!(index.search(10_001), None);
+        assert_eq!(index.search(-10_001), None);
+    }
+
+    #[test]
+    fn test_kv_store_swap_top() {
+        use limousine_engine::{BTreeTop, RMITop};
+
+        create_kv_store! {
+            name: SwapStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            swappable_top: true,
+        }
+
+        create_kv_store! {
+            name: SwapStore2,
+            layout: [
+                rmi_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 16),
+            ],
+            swappable_top: true,
+        }
+
+        create_kv_store! {
+            name: RMIStore1,
+            layout: [
+                rmi_top(epsilon = 4),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ]
+        }
+
+        test_kv_store::<SwapStore1<K, V>>();
+        test_kv_store::<SwapStore2<K, V>>();
+        test_kv_store::<RMIStore1<K, V>>();
+
+        let mut index = SwapStore1::<K, V>::build((0..10_000).map(|key| (key * 3, key)));
+        assert_eq!(index.top_kind(), "BTreeTop");
+
+        // Swapping only rebuilds the top, the entries are untouched
+        index.swap_top::<RMITop>();
+        assert_eq!(index.top_kind(), "RMITop");
+        assert_eq!(index.explain(&300).steps[0].component, "RMITop");
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 3), Some(key));
+            assert_eq!(index.search(key * 3 + 1), None);
+        }
+
+        // The new top keeps up with inserts, even ones which would have retrained it
+        for key in 0..20_000 {
+            index.insert(key * 3 + 1, -key);
+        }
+
+        index.swap_top::<BTreeTop>();
+        assert_eq!(index.top_kind(), "BTreeTop");
+
+        for key in 0..20_000 {
+            assert_eq!(index.search(key * 3 + 1), Some(-key));
+        }
+        assert_eq!(index.search(300), Some(100));
+    }
+
+    #[test]
+    fn test_kv_store_striped() {
+        create_kv_store! {
+            name: StripedStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32),
+            ],
+            stripes: 4,
+        }
+
+        let index = StripedStore::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        let striped = index.into_striped();
+
+        // Stripes split the key space into contiguous ranges of about the same size
+        let stripes: Vec<usize> = (0..4)
+            .map(|stripe| striped.shard_of(&(stripe * 5_000 + 2_500)))
+            .collect();
+        assert_eq!(stripes, vec![0, 1, 2, 3]);
+
+        // Writers to disjoint ranges each take the lock of their own stripe
+        std::thread::scope(|scope| {
+            for stripe in 0..4 {
+                let striped = &striped;
+                scope.spawn(move || {
+                    for key in stripe * 2_500..(stripe + 1) * 2_500 {
+                        striped.insert(key * 2 + 1, -key);
+                    }
+                });
+            }
+        });
+
+        for key in 0..10_000 {
+            assert_eq!(striped.search(key * 2), Some(key));
+            assert_eq!(striped.search(key * 2 + 1), Some(-key));
+        }
+        assert_eq!(striped.read().range(..).count(), 20_000);
+
+        // A single internal node has no fence to split at, so the first stripe takes every key
+        let small = StripedStore::<K, V>::build((0..10).map(|key| (key, key)));
+        let small = small.into_striped();
+        assert_eq!(small.search(5), Some(5));
+        assert_eq!(small.shard_of(&K::MAX), 0);
+    }
+
+    #[test]
+    fn test_kv_store_fixed_alloc() {
+        create_kv_store! {
+            name: FixedStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 32, search = binary),
+            ],
+            alloc: fixed,
+        }
+
+        let buffer = |len: usize| Box::leak(vec![0u8; len].into_boxed_slice());
+        let entries = || (0..10_000).map(|key| (key * 2, key));
+
+        // An undersized buffer fails the build, and is left unused
+        let error = FixedStore::<K, V>::build_in(buffer(4 * 1024), entries())
+            .err()
+            .unwrap();
+        assert_eq!(error.available, 4 * 1024);
+        assert!(error.needed > error.available);
+        assert_eq!(FixedStore::<K, V>::buffer().used(), 0);
+
+        // Every node of the layers below the top fits into a large enough one
+        let mut index = FixedStore::<K, V>::build_in(buffer(error.needed * 2), entries()).unwrap();
+        let fixed = FixedStore::<K, V>::buffer();
+        assert_eq!(fixed.spilled(), 0);
+        assert!(fixed.used() > 0 && fixed.used() <= fixed.capacity());
+
+        for key in 0..10_000 {
+            assert_eq!(index.search(key * 2), Some(key));
+        }
+
+        // Inserts split nodes within the buffer
+        for key in 0..1_000 {
+            index.insert(key * 2 + 1, -key);
+        }
+        assert_eq!(index.search(1_999), Some(-999));
+        assert_eq!(fixed.spilled(), 0);
+
+        // Once the index is dropped, the buffer of the layout can be replaced
+        drop(index);
+        let index = FixedStore::<K, V>::build_in(buffer(error.needed * 2), entries()).unwrap();
+        assert_eq!(index.search(100), Some(50));
+    }
+
+    #[test]
+    fn test_kv_store_rcu() {
+        use limousine_engine::{BTreeTop, RMITop, Rcu};
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        create_kv_store! {
+            name: RcuStore,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            swappable_top: true,
+        }
+
+        let index = RcuStore::<K, V>::build((0..10_000).map(|key| (key * 2, key)));
+        let index = Rcu::new(index);
+
+        // A pinned version doesn't see later writes, and is only freed once unpinned
+        let pinned = index.read();
+        assert_eq!(index.insert(1, 1), None);
+        assert_eq!(pinned.search(1), None);
+        assert_eq!(index.search(1), Some(1));
+        assert_eq!(index.pending(), 1);
+        drop(pinned);
+        assert_eq!(index.pending(), 0);
+
+        // Many inserts are swapped in as one version
+        let pinned = index.read();
+        index.extend((0..100).map(|key| (key * 2 + 20_001, key)));
+        assert_eq!(index.pending(), 1);
+        assert_eq!(pinned.search(20_001), None);
+        for key in 0..100 {
+            assert_eq!(index.search(key * 2 + 20_001), Some(key));
+        }
+        drop(pinned);
+        assert_eq!(index.pending(), 0);
+
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let version = index.read();
+                        for key in (0..10_000).step_by(97) {
+                            assert_eq!(version.search(key * 2), Some(key));
+                        }
+                    }
+                });
+            }
+
+            for key in 0..1_000 {
+                index.insert(key * 2 + 1, key);
+
+                // Readers stay on the previous version while the top is swapped, or every layer
+                // is rebuilt
+                match key % 300 {
+                    0 => index.modify(|next| next.swap_top::<RMITop>()),
+                    100 => index.modify(|next| next.swap_top::<BTreeTop>()),
+                    200 => index.modify(|next| next.rebuild()),
+                    _ => {}
+                }
+            }
+
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(index.pending(), 0);
+
+        let index = index.into_inner();
+        assert_eq!(index.top_kind(), "RMITop");
+        for key in 0..1_000 {
+            assert_eq!(index.search(key * 2 + 1), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_kv_store_reverse_lookup() {
+        create_kv_store! {
+            name: ReverseStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 8),
+                btree(fanout = 16),
+            ],
+            reverse_lookup: true,
+        }
+
+        create_kv_store! {
+            name: ReverseStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 16),
+            ],
+            reverse_lookup: true,
+        }
+
+        test_kv_store::<ReverseStore1<K, V>>();
+        test_kv_store::<ReverseStore2<K, V>>();
+
+        let mut index = ReverseStore2::<K, V>::build((0..10_000).map(|key| (key, key % 10)));
+        assert_eq!(index.find_value(&3).count(), 1_000);
+        assert!(index.find_value(&3).all(|key| key % 10 == 3));
+        assert!(index.find_value(&3).is_sorted());
+
+        // Overwriting a key moves it to its new value
+        assert_eq!(index.insert(13, 7), Some(3));
+        assert_eq!(index.insert(20_000, 3), None);
+        assert_eq!(index.find_value(&3).count(), 1_000);
+        assert!(!index.find_value(&3).any(|key| *key == 13));
+        assert_eq!(index.find_value(&3).last(), Some(&20_000));
+        assert_eq!(index.find_value(&7).count(), 1_001);
+        assert_eq!(index.find_value(&10).next(), None);
+
+        let cloned = index.clone();
+        assert_eq!(cloned.find_value(&7).count(), 1_001);
+    }
+
+    #[test]
+    fn test_kv_store_from_maps() {
+        use limousine_engine::IndexRead;
+        use std::collections::{BTreeMap, HashMap};
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+            ]
+        }
+
+        create_kv_store! {
+            name: ReadOnlyStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 16),
+            ],
+            read_only: true,
+        }
+
+        let map: BTreeMap<K, V> = (0..5_000).map(|key| (key * 7, key)).collect();
+        let unsorted: HashMap<K, V> = map.clone().into_iter().collect();
+
+        let sorted = PGMStore1::from(map.clone());
+        let hashed = PGMStore1::from_unsorted(unsorted.clone());
+        let read_only = ReadOnlyStore1::from_unsorted(unsorted);
+
+        for (&key, &value) in map.iter() {
+            assert_eq!(sorted.search(key), Some(value));
+            assert_eq!(hashed.search(key), Some(value));
+            assert_eq!(
+                read_only.try_search(key).unwrap(),
+                Some(value)
+            );
+            assert_eq!(hashed.search(key + 1), None);
+        }
+
+        // Any iterator of entries works, and the last entry of a repeated key wins
+        let repeated = ReadOnlyStore1::from_unsorted([(3, 1), (1, 1), (3, 2), (2, 1), (3, 3)]);
+        for (key, value) in [(1, 1), (2, 1), (3, 3)] {
+            assert_eq!(
+                repeated.try_search(key).unwrap(),
+                Some(value)
+            );
+        }
+    }
+
+    #[test]
+    fn test_kv_store_try_build() {
+        use limousine_engine::{BuildError, Duplicates};
+
+        create_kv_store! {
+            name: BTreeStore1,
+            layout: [
+                btree_top(),
+                btree(fanout = 16),
+            ]
+        }
+
+        let entries: Vec<(K, V)> = (0..5_000).map(|key| (key * 3, key)).collect();
+        let index = BTreeStore1::try_build(entries.clone(), Duplicates::Error).unwrap();
+        let unchecked = BTreeStore1::build_unchecked(entries.clone());
+        for &(key, value) in entries.iter() {
+            assert_eq!(index.search(key), Some(value));
+            assert_eq!(unchecked.search(key), Some(value));
+        }
+        assert_eq!(index.search(1), None);
+
+        let unsorted = [(1, 1), (3, 1), (2, 1)];
+        assert_eq!(
+            BTreeStore1::try_build(unsorted, Duplicates::LastWins).err(),
+            Some(BuildError::Unsorted {
+                previous: 3,
+                key: 2
+            })
+        );
+
+        let repeated = [(1, 1), (3, 1), (3, 2), (3, 3), (4, 1)];
+        assert_eq!(
+            BTreeStore1::try_build(repeated, Duplicates::Error).err(),
+            Some(BuildError::Duplicate { key: 3 })
+        );
+
+        let last = BTreeStore1::try_build(repeated, Duplicates::LastWins).unwrap();
+        let first = BTreeStore1::try_build(repeated, Duplicates::FirstWins).unwrap();
+        for key in [1, 3, 4] {
+            let value = if key == 3 { 3 } else { 1 };
+            assert_eq!(last.search(key), Some(value));
+            assert_eq!(first.search(key), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_pgm_store_layer_report() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 16),
+                btree(fanout = 8),
+                pgm(epsilon = 4),
+            ]
+        }
+
+        let num = 10_000;
+        let index = PGMStore1::<K, V>::build((0..num).map(|key| (key * key, key)));
+
+        let report = index.layer_report();
+        assert_eq!(report.len(), 2);
+
+        let (base, internal) = (&report[0], &report[1]);
+        assert_eq!((base.layer, base.epsilon), (0, 4));
+        assert_eq!((internal.layer, internal.epsilon), (2, 16));
+
+        // Every key of the base layer lands in exactly one segment
+        assert!(base.segments > 1);
+        assert_eq!(base.keys_per_node.iter().sum::<usize>(), base.segments);
+        assert!(base.max_segment_len as f64 >= base.avg_segment_len);
+        assert!((base.avg_segment_len * base.segments as f64 - num as f64).abs() < 1e-6);
+
+        // Both layers were only ever built
+        assert_eq!((base.rebuilds, internal.rebuilds), (1, 1));
+    }
+
+    #[test]
+    fn test_pgm_store_max_segment_len() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 64, max_len = 256),
+            ]
+        }
+
+        let num = 10_000;
+        let index = PGMStore1::<K, V>::build((0..num).map(|key| (key * 5, key)));
+
+        // The keys are perfectly linear, so only the cap splits them into segments
+        let base = &index.layer_report()[0];
+        assert_eq!(base.max_segment_len, 256);
+        assert_eq!(base.segments, 40);
+
+        let histogram: Vec<_> = base.histogram().collect();
+        assert_eq!(histogram.last(), Some(&(256..512, 39)));
+        assert_eq!(histogram.iter().map(|(_, nodes)| nodes).sum::<usize>(), 40);
+
+        for key in 0..num {
+            assert_eq!(index.search(key * 5), Some(key));
+            assert_eq!(index.search(key * 5 + 1), None);
+        }
+    }
+
+    #[test]
+    fn test_pgm_store_checked() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8, checked),
+                pgm(epsilon = 4, max_len = 512, checked),
+            ],
+            transform: log
+        }
+
+        create_kv_store! {
+            name: PGMStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 4, max_len = 512),
+            ],
+            transform: log
+        }
+
+        test_kv_store_build::<PGMStore1<K, V>>();
+
+        // Dense keys far from zero all have nearly the same logarithm
+        let keys: Vec<K> = (0..10_000).map(|key| (1 << 100) + key).collect();
+
+        let checked = PGMStore1::<K, V>::build(keys.iter().map(|&key| (key, key)));
+        let unchecked = PGMStore2::<K, V>::build(keys.iter().map(|&key| (key, key)));
+
+        for &key in keys.iter() {
+            assert_eq!(checked.search(key), Some(key));
+        }
+        assert_eq!(checked.search((1 << 100) - 1), None);
+
+        // Segments whose keys stray from their windows are split until none do
+        let segments = |report: Vec<limousine_engine::LayerReport>| report[0].segments;
+        assert!(segments(checked.layer_report()) > segments(unchecked.layer_report()));
+    }
+
+    #[test]
+    fn test_pgm_store_packed_keys() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8, max_len = 1024, packed),
+            ]
+        }
+
+        create_kv_store! {
+            name: PGMStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8, max_len = 1024),
+            ]
+        }
+
+        test_kv_store_build::<PGMStore1<K, V>>();
+
+        // Nearly linear keys, whose residuals take only a few bits each
+        let entries = || (0..100_000).map(|key| (key * 13 + (key * key) % 7, key));
+        let mut packed = PGMStore1::<K, V>::build(entries());
+        let unpacked = PGMStore2::<K, V>::build(entries());
+
+        assert!(2 * packed.memory_usage() < unpacked.memory_usage());
+
+        for (key, value) in entries() {
+            assert_eq!(packed.search(key), Some(value));
+            assert_eq!(packed.search(key + 7), None);
+        }
+
+        // Inserting unpacks only the segments inserted into
+        for key in 0..1_000 {
+            packed.insert(key * 13 + 7, -key);
+        }
+
+        for key in 0..1_000 {
+            assert_eq!(packed.search(key * 13 + 7), Some(-key));
+        }
+        for (key, value) in entries().step_by(97) {
+            assert_eq!(packed.search(key), Some(value));
+        }
+        assert!(packed.memory_usage() < unpacked.memory_usage());
+    }
+
+    #[test]
+    fn test_pgm_store_packed_internal() {
+        use limousine_engine::private::NodeLayer;
+
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 4, packed),
+                btree(fanout = 4),
+            ]
+        }
+
+        create_kv_store! {
+            name: PGMStore2,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 4),
+                btree(fanout = 4),
+            ]
+        }
+
+        test_kv_store::<PGMStore1<K, V>>();
+        test_kv_store_build::<PGMStore1<K, V>>();
+
+        let entries = || (0..100_000).map(|key| (key * 3, key));
+        let mut packed = PGMStore1::<K, V>::build(entries());
+        let unpacked = PGMStore2::<K, V>::build(entries());
+
+        assert!(2 * packed.c1.memory_usage() < unpacked.c1.memory_usage());
+
+        for (key, value) in entries() {
+            assert_eq!(packed.search(key), Some(value));
+        }
+
+        // Splits below unpack only the segments they insert into
+        for key in 0..10_000 {
+            packed.insert(key * 3 + 1, -key);
+        }
+        for key in 0..10_000 {
+            assert_eq!(packed.search(key * 3 + 1), Some(-key));
+        }
+        for (key, value) in entries().step_by(7) {
+            assert_eq!(packed.search(key), Some(value));
+        }
+
+        packed.rebuild();
+        for (key, value) in entries().step_by(7) {
+            assert_eq!(packed.search(key), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_pgm_store_gallop() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 64),
+            ]
+        }
+
+        test_kv_store_build::<PGMStore1<K, V>>();
+
+        // Skewed keys, so that the wide window of the store is mostly used up
+        let entries = || (0..50_000).map(|key| (2 * (key * key / 64 + key), key));
+        let mut wide = PGMStore1::<K, V>::build(entries());
+
+        for (key, value) in entries() {
+            assert_eq!(wide.search(key), Some(value));
+        }
+
+        // The insert path gallops as well
+        for key in (0..50_000).step_by(7) {
+            let key = 2 * (key * key / 64 + key);
+            wide.insert(key + 1, -key);
+        }
+        for (key, value) in entries() {
+            assert_eq!(wide.search(key), Some(value));
+        }
+        for key in (0..50_000).step_by(7) {
+            let key = 2 * (key * key / 64 + key);
+            assert_eq!(wide.search(key + 1), Some(-key));
+        }
+
+        // Galloping compares a logarithmic number of keys in the window, not a linear one
+        let (comparisons, window) = entries()
+            .step_by(101)
+            .map(|(key, _)| wide.explain(&key).steps[1].probe)
+            .fold((0, 0), |(comparisons, window), probe| {
+                (
+                    comparisons + probe.comparisons,
+                    window + probe.window.unwrap(),
+                )
+            });
+        assert!(2 * comparisons < window);
+    }
+
+    #[test]
+    fn test_pgm_store_layer_plot() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 16),
+                btree(fanout = 8),
+                pgm(epsilon = 4),
+            ]
+        }
+
+        let index = PGMStore1::<K, V>::build((0..10_000).map(|key| (key * key, key)));
+
+        let plots = index.layer_plot(1_000_000..4_000_000);
+        assert_eq!(plots.len(), 2);
+        assert_eq!((plots[0].layer, plots[1].layer), (0, 2));
+
+        // Only keys in the range are plotted, all within the error window of their segment
+        let base = &plots[0];
+        let points: Vec<_> = base.segments.iter().flat_map(|s| &s.points).collect();
+        assert_eq!(points.len(), 1_000);
+        assert!(base.segments.len() > 1);
+
+        for segment in &base.segments {
+            assert!(segment.points.len() <= segment.size);
+            assert!(segment.line.is_some());
+        }
+
+        for point in &points {
+            assert!((1_000_000.0..4_000_000.0).contains(&point.key));
+            assert!(point.window.contains(&point.slot));
+        }
+
+        let svg = base.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 1_000);
+        assert_eq!(svg.matches("<polygon").count(), base.segments.len());
+
+        // Below every key, the internal layer only holds the minimum key its first node is
+        // anchored at
+        let below = index.layer_plot(..0);
+        assert!(below[0].segments.is_empty());
+        let anchors: Vec<_> = below[1].segments.iter().flat_map(|s| &s.points).collect();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[0].key, K::MIN as f64);
+    }
+
+    /// Monotone key transform for `transform: custom(...)`, generic over the key type
+    fn halve_key<T: num::PrimInt>(key: T) -> T {
+        key >> 1
+    }
+
+    #[test]
+    fn test_pgm_store_transform() {
+        create_kv_store! {
+            name: LogStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 4),
+            ],
+            transform: log
+        }
+
+        create_kv_store! {
+            name: AffineStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                btree(fanout = 8),
+                pgm(model = InterpolationModel, epsilon = 4),
+            ],
+            transform: affine(scale = 3, offset = -5)
+        }
+
+        create_kv_store! {
+            name: CustomStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 8),
+                pgm(epsilon = 4),
+            ],
+            transform: custom(halve_key)
+        }
+
+        test_kv_store_build::<LogStore<K, V>>();
+        test_kv_store_build::<AffineStore<K, V>>();
+        test_kv_store_build::<CustomStore<K, V>>();
+
+        create_kv_store! {
+            name: PlainStore,
+            layout: [
+                btree_top(),
+                pgm(epsilon = 4),
+            ]
+        }
+
+        // Exponentially spaced keys are linear after a logarithm
+        let mut keys: Vec<K> = (0..20_000).map(|i| 1.003f64.powi(i) as K).collect();
+        keys.dedup();
+
+        let plain = PlainStore::<K, V>::build(keys.iter().map(|&key| (key, key)));
+        let log = LogStore::<K, V>::build(keys.iter().map(|&key| (key, key)));
+
+        for &key in keys.iter() {
+            assert_eq!(log.search(key), Some(key));
+        }
+
+        let segments = |report: Vec<limousine_engine::LayerReport>| report[0].segments;
+        assert!(segments(log.layer_report()) < segments(plain.layer_report()));
+    }
+
+    #[test]
+    fn test_pgm_store_custom_model() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
+                pgm(model = InterpolationModel, epsilon = 4),
+                pgm(epsilon = 8),
+                pgm(model = InterpolationModel, epsilon = 4),
+            ]
+        }
+
+        test_kv_store_build::<PGMStore1<K, V>>();
+
+        create_kv_store! {
+            name: PGMStore2,
+            layout: [
+                btree_top(),
+                pgm(model = self::InterpolationModel, epsilon = 4),
+                pgm(model = crate::tests::InterpolationModel, epsilon = 4),
+            ]
+        }
+
+        test_kv_store_build::<PGMStore2<K, V>>();
+    }
+
+    mod nested {
+        use super::*;
+
+        #[test]
+        fn test_pgm_store_super_model() {
+            create_kv_store! {
+                name: PGMStore1,
+                layout: [
+                    btree_top(),
+                    pgm(model = super::InterpolationModel, epsilon = 4),
+                ]
+            }
+
+            test_kv_store_build::<PGMStore1<K, V>>();
+        }
+    }
+
+    #[test]
+
+    fn test_pgm_store_3() {
+        create_kv_store! {
+            name: PGMStore1,
+            layout: [
+                btree_top(),
                 pgm(epsilon = 8),
                 pgm(epsilon = 8),
             ]
@@ -387,4 +5995,106 @@ mod tests {
 
         test_kv_store_build::<PGMStore1<K, V>>();
     }
+
+    #[test]
+    fn test_kv_store_empty_layouts() {
+        create_kv_store! {
+            name: BTreeStore,
+            layout: [btree_top(), btree(fanout = 8), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: PromotedStore,
+            layout: [btree_top(max_entries = 4), btree(fanout = 8), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: RMIStore,
+            layout: [rmi_top(), btree(fanout = 8), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: ARTStore,
+            layout: [art_top(), btree(fanout = 8), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: PGMStore,
+            layout: [btree_top(), pgm(epsilon = 8), pgm(epsilon = 8)]
+        }
+
+        create_kv_store! {
+            name: PromotedPGMStore,
+            layout: [btree_top(max_entries = 4), pgm(epsilon = 8), pgm(epsilon = 8)]
+        }
+
+        create_kv_store! {
+            name: RMIPGMStore,
+            layout: [rmi_top(epsilon = 4), pgm(epsilon = 8), pgm(epsilon = 8)]
+        }
+
+        create_kv_store! {
+            name: ARTPGMStore,
+            layout: [art_top(), pgm(epsilon = 8), pgm(epsilon = 8)]
+        }
+
+        create_kv_store! {
+            name: PackedStore,
+            layout: [btree_top(), pgm(epsilon = 8, packed), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: BucketStore,
+            layout: [art_top(), bucket(count = 4), pgm(epsilon = 8)]
+        }
+
+        create_kv_store! {
+            name: DenseStore,
+            layout: [btree_top(), dense(), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: AutoStore,
+            layout: [btree_top(), auto(), btree(fanout = 8)]
+        }
+
+        create_kv_store! {
+            name: DeepPGMStore,
+            layout: [btree_top(), pgm(epsilon = 8), pgm(epsilon = 8), pgm(epsilon = 8)]
+        }
+
+        test_kv_store_empty::<BTreeStore<K, V>>();
+        test_kv_store_empty::<PromotedStore<K, V>>();
+        test_kv_store_empty::<RMIStore<K, V>>();
+        test_kv_store_empty::<ARTStore<K, V>>();
+        test_kv_store_empty::<PGMStore<K, V>>();
+        test_kv_store_empty::<PromotedPGMStore<K, V>>();
+        test_kv_store_empty::<RMIPGMStore<K, V>>();
+        test_kv_store_empty::<ARTPGMStore<K, V>>();
+        test_kv_store_empty::<PackedStore<K, V>>();
+        test_kv_store_empty::<BucketStore<K, V>>();
+        test_kv_store_empty::<DenseStore<K, V>>();
+        test_kv_store_empty::<AutoStore<K, V>>();
+        test_kv_store_empty::<DeepPGMStore<K, V>>();
+
+        // Cursors over an empty index sit at the ghost position, and first and last are missing
+        let mut index = BTreeStore::<K, V>::empty();
+        assert_eq!(index.first(), None);
+        assert_eq!(index.last(), None);
+        assert_eq!(index.cursor(0).current(), None);
+        assert_eq!(index.cursor(K::MIN).peek_next(), None);
+        assert_eq!(index.cursor(K::MAX).peek_prev(), None);
+        assert!(index.seek(&K::MIN).is_exhausted());
+        assert!(index.seek(&K::MIN).next_n(10).is_empty());
+        assert!(index.is_empty());
+
+        index.insert(5, 50);
+        index.insert(-5, -50);
+        assert_eq!(index.first(), Some((&-5, &-50)));
+        assert_eq!(index.last(), Some((&5, &50)));
+
+        let index = BTreeStore::<K, V>::build((0..1_000).map(|key| (key, -key)));
+        assert_eq!(index.first(), Some((&0, &0)));
+        assert_eq!(index.last(), Some((&999, &-999)));
+    }
 }
@@ -1,6 +1,27 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt;
+use core::mem::size_of;
 use core::mem::MaybeUninit;
-use std::mem::size_of;
+
+/// How a gapped array searches outwards from the hint of a model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Step one occupied slot at a time, which is fastest when hints are close
+    #[default]
+    Scan,
+
+    /// Gallop in doubling steps until the needle is bracketed, then binary search between them,
+    /// which is faster for wide approximation windows and skewed errors
+    Gallop,
+}
 
 /// A sorted array which is constructed with intentional gaps to allow for practical in-place inserts
 /// NOTE: The current implementation assumes keys are unique. It may break if this is not true.
@@ -14,6 +35,7 @@ where
     keys: Box<[MaybeUninit<K>]>,
     vals: Box<[MaybeUninit<V>]>,
     size: usize,
+    mode: SearchMode,
 }
 
 impl<K, V> GappedKVArray<K, V>
@@ -34,14 +56,30 @@ where
             keys: keys_vec.into_boxed_slice(),
             vals: vals_vec.into_boxed_slice(),
             size: 0,
+            mode: SearchMode::Scan,
         }
     }
 
+    /// Search outwards from hints with the given mode, which is kept when the array is rescaled
+    pub fn with_mode(mut self, mode: SearchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> SearchMode {
+        self.mode
+    }
+
     /// The length of the gapped array (including gaps)
     pub const fn len(&self) -> usize {
         self.bitmap.len()
     }
 
+    /// Does the gapped array have zero slots?
+    pub const fn is_empty(&self) -> bool {
+        self.bitmap.is_empty()
+    }
+
     /// The length of the gapped arraycannot move out of `self.vals[_]` which is behind a mutable reference (excluding gaps)
     pub fn size(&self) -> usize {
         self.size
@@ -52,6 +90,35 @@ where
         self.len() <= self.size()
     }
 
+    /// The slots of the gapped array as laid out in memory, gaps included: the bitmap of occupied
+    /// slots, the keys, and the values
+    #[allow(clippy::type_complexity)]
+    pub fn raw_parts(&self) -> (&[bool], &[MaybeUninit<K>], &[MaybeUninit<V>]) {
+        (&self.bitmap, &self.keys, &self.vals)
+    }
+
+    /// Iterate over the occupied slots of the gapped array, yielding (slot, key, value)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &K, &V)> {
+        (0..self.len())
+            .filter(|&ix| self.bitmap[ix])
+            .map(|ix| unsafe {
+                (
+                    ix,
+                    self.keys[ix].assume_init_ref(),
+                    self.vals[ix].assume_init_ref(),
+                )
+            })
+    }
+
+    /// Iterate mutably over the values of the occupied slots of the gapped array
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.bitmap
+            .iter()
+            .zip(self.vals.iter_mut())
+            .filter(|(occupied, _)| **occupied)
+            .map(|(_, val)| unsafe { val.assume_init_mut() })
+    }
+
     /// The density of the gapped array
     pub fn density(&self) -> f32 {
         self.size as f32 / self.len() as f32
@@ -108,12 +175,131 @@ where
     /// Returns None if needle is smaller than everything in the array
     /// NOTE: Hint is just to help search speed. This ALWAYS returns a correct result.
     fn price_is_right(&self, needle: &K, hint: Option<usize>) -> Option<usize> {
+        self.price_is_right_counted(needle, hint, &mut 0)
+    }
+
+    /// Number of key comparisons a search for the needle makes, starting from the hint
+    pub fn search_comparisons(&self, needle: &K, hint: Option<usize>) -> usize {
+        let mut comparisons = 0;
+        self.price_is_right_counted(needle, hint, &mut comparisons);
+        comparisons
+    }
+
+    fn price_is_right_counted(
+        &self,
+        needle: &K,
+        hint: Option<usize>,
+        comparisons: &mut usize,
+    ) -> Option<usize> {
+        match self.mode {
+            SearchMode::Scan => self.scan_counted(needle, hint, comparisons),
+            SearchMode::Gallop => self.gallop_counted(needle, hint, comparisons),
+        }
+    }
+
+    /// Whether the occupied slot `ix` holds a key at most the needle
+    fn at_most(&self, ix: usize, needle: &K, comparisons: &mut usize) -> bool {
+        *comparisons += 1;
+        unsafe { self.keys[ix].assume_init_ref() <= needle }
+    }
+
+    /// Price is right by galloping from the hint in doubling steps over slots, snapping each probe
+    /// to an occupied slot, and then binary searching the slots between the last two probes
+    fn gallop_counted(
+        &self,
+        needle: &K,
+        hint: Option<usize>,
+        comparisons: &mut usize,
+    ) -> Option<usize> {
+        if self.size == 0 {
+            return None;
+        }
+
+        // The occupied slot nearest the hint, which there is since the array isn't empty
+        let start = hint.unwrap_or(self.len() / 2).min(self.len() - 1);
+        let start = self
+            .next_occupied_ix(start)
+            .or_else(|| self.prev_occupied_ix(start))?;
+
+        // Bracket the answer by an occupied slot `low` at most the needle, and a slot `high` past
+        // every such slot
+        let mut low;
+        let mut high;
+        let mut step = 1;
+
+        if self.at_most(start, needle, comparisons) {
+            low = start;
+            loop {
+                let Some(next) = self.next_occupied_ix(low + step) else {
+                    high = self.len();
+                    break;
+                };
+
+                if !self.at_most(next, needle, comparisons) {
+                    high = next;
+                    break;
+                }
+
+                low = next;
+                step *= 2;
+            }
+        } else {
+            high = start;
+            loop {
+                // Snap to the first occupied slot when there is none before the jump
+                let jump = high.saturating_sub(step);
+                let next = self
+                    .prev_occupied_ix(jump)
+                    .or_else(|| self.next_occupied_ix(jump))?;
+
+                // Every occupied slot is past the needle
+                if next == high {
+                    return None;
+                }
+
+                if self.at_most(next, needle, comparisons) {
+                    low = next;
+                    break;
+                }
+
+                high = next;
+                step *= 2;
+            }
+        }
+
+        // Every occupied slot strictly between `low` and `high` is still in question
+        loop {
+            let middle = low + (high - low) / 2;
+            let probe = match self.prev_occupied_ix(middle) {
+                Some(ix) if ix > low => ix,
+                _ => match self.next_occupied_ix(middle + 1) {
+                    Some(ix) if ix < high => ix,
+                    _ => return Some(low),
+                },
+            };
+
+            if self.at_most(probe, needle, comparisons) {
+                low = probe;
+            } else {
+                high = probe;
+            }
+        }
+    }
+
+    /// Price is right by stepping from the hint one occupied slot at a time
+    fn scan_counted(
+        &self,
+        needle: &K,
+        hint: Option<usize>,
+        comparisons: &mut usize,
+    ) -> Option<usize> {
         // First, move as far to the right as we can from the hint
         let mut check = self.next_occupied_ix(hint.unwrap_or(self.len() / 2));
         while check.is_some() {
             let next = self.next_occupied_ix(check.unwrap() + 1);
             match next {
                 Some(next_ix) => {
+                    *comparisons += 1;
                     unsafe {
                         if needle < self.keys[next_ix].assume_init_ref() {
                             break;
@@ -128,7 +314,7 @@ where
         check = match check {
             Some(ix) => Some(ix),
             None => {
-                if self.len() > 0 {
+                if !self.is_empty() {
                     self.prev_occupied_ix(self.len() - 1)
                 } else {
                     None
@@ -137,6 +323,7 @@ where
         };
         // Then ensure correctness by moving left as far as we need to
         while check.is_some() {
+            *comparisons += 1;
             unsafe {
                 if self.keys[check.unwrap()].assume_init_ref() <= needle {
                     break;
@@ -153,14 +340,11 @@ where
 
     /// Search the gapped array for a specific value, returning the "price is right" value
     /// (I.e. the biggest value without going over key)
-    /// TODO: Make exponential search
     /// TODO: Update slice_search so it can work on gapped arrays
     pub fn search_pir(&self, needle: &K, hint: Option<usize>) -> Option<&V> {
         match self.price_is_right(needle, hint) {
             Some(ix) => match self.vals.get(ix) {
-                Some(val) => unsafe {
-                    return Some(val.assume_init_ref());
-                },
+                Some(val) => unsafe { Some(val.assume_init_ref()) },
                 None => None,
             },
             None => None,
@@ -168,13 +352,32 @@ where
     }
 
     /// Search the gapped array for a specific value, using a starting hint
-    /// TODO: Make exponential search
     pub fn search_exact(&self, needle: &K, hint: Option<usize>) -> Option<&V> {
+        self.search_exact_entry(needle, hint).map(|(_, val)| val)
+    }
+
+    /// Search the gapped array for a specific key, returning its value to be modified in place
+    pub fn search_exact_mut(&mut self, needle: &K, hint: Option<usize>) -> Option<&mut V> {
         match self.price_is_right(needle, hint) {
             Some(ix) => unsafe {
                 if self.keys[ix].assume_init_ref() == needle {
+                    self.vals.get_mut(ix).map(|val| val.assume_init_mut())
+                } else {
+                    None
+                }
+            },
+            None => None,
+        }
+    }
+
+    /// Search the gapped array for a specific key, returning the stored key along with its value
+    pub fn search_exact_entry(&self, needle: &K, hint: Option<usize>) -> Option<(&K, &V)> {
+        match self.price_is_right(needle, hint) {
+            Some(ix) => unsafe {
+                let key = self.keys[ix].assume_init_ref();
+                if key == needle {
                     match self.vals.get(ix) {
-                        Some(val) => Some(val.assume_init_ref()),
+                        Some(val) => Some((key, val.assume_init_ref())),
                         None => None,
                     }
                 } else {
@@ -186,7 +389,7 @@ where
     }
 
     /// Helper function to copy within for all the needed arrays
-    fn copy_within(&mut self, src: std::ops::Range<usize>, dest: usize) {
+    fn copy_within(&mut self, src: core::ops::Range<usize>, dest: usize) {
         self.bitmap.copy_within(src.clone(), dest);
         unsafe {
             let key_src = self.keys.get_unchecked(src.start).as_ptr();
@@ -216,15 +419,15 @@ where
             Err("No such element exists for remove_at".to_string())
         } else {
             self.bitmap[ix] = false;
-            let key = std::mem::replace(&mut self.keys[ix], MaybeUninit::uninit());
-            let val = std::mem::replace(&mut self.vals[ix], MaybeUninit::uninit());
+            let key = core::mem::replace(&mut self.keys[ix], MaybeUninit::uninit());
+            let val = core::mem::replace(&mut self.vals[ix], MaybeUninit::uninit());
             self.size -= 1;
             unsafe { Ok((key.assume_init(), val.assume_init())) }
         }
     }
 
-    /// Upsert a specific value into the array with the given hint
-    pub fn upsert_with_hint(&mut self, pair: (K, V), hint: usize) -> Result<(), String> {
+    /// Upsert a specific value into the array with the given hint, returning the value it replaced
+    pub fn upsert_with_hint(&mut self, pair: (K, V), hint: usize) -> Result<Option<V>, String> {
         let maybe_ix = self.price_is_right(&pair.0, Some(hint));
         match maybe_ix {
             None => {
@@ -233,15 +436,17 @@ where
                     return Err("Gapped array is full (beginning)".to_string());
                 };
                 self.copy_within(0..closest_ix, 1);
+                self.bitmap[0] = false; // So size is updated correctly
                 self.upsert_at(pair, 0);
-                Ok(())
+                Ok(None)
             }
             Some(mut ix) => {
                 unsafe {
                     if self.keys[ix].assume_init_ref() == &pair.0 {
                         // If this is an update handle it quickly and return
-                        self.upsert_at(pair, ix);
-                        return Ok(());
+                        let previous =
+                            core::mem::replace(&mut self.vals[ix], MaybeUninit::new(pair.1));
+                        return Ok(Some(previous.assume_init()));
                     }
                 }
                 if ix + 1 == self.len() {
@@ -252,14 +457,14 @@ where
                     self.copy_within(closest_ix + 1..self.len(), closest_ix);
                     self.bitmap[self.len() - 1] = false; // So size is updated correctly
                     self.upsert_at(pair, self.len() - 1);
-                    Ok(())
+                    Ok(None)
                 } else {
                     // We're doing a "normal" upsert into the middle of the array
                     ix += 1; // Price-is-right quirk
                     if !self.bitmap[ix] {
                         // Easy win
                         self.upsert_at(pair, ix);
-                        return Ok(());
+                        return Ok(None);
                     }
                     let shift_left_ix = self.prev_free_ix(ix - 1);
                     let shift_right_ix = self.next_free_ix(ix + 1);
@@ -269,25 +474,25 @@ where
                                 self.copy_within(lix + 1..ix + 1, lix);
                                 self.bitmap[ix - 1] = false; // So size is updated correctly
                                 self.upsert_at(pair, ix - 1);
-                                Ok(())
+                                Ok(None)
                             } else {
                                 self.copy_within(ix..rix, ix + 1);
                                 self.bitmap[ix] = false; // So size is updated correctly
                                 self.upsert_at(pair, ix);
-                                Ok(())
+                                Ok(None)
                             }
                         }
                         (Some(lix), None) => {
                             self.copy_within(lix + 1..ix + 1, lix);
                             self.bitmap[ix - 1] = false; // So size is updated correctly
                             self.upsert_at(pair, ix - 1);
-                            Ok(())
+                            Ok(None)
                         }
                         (None, Some(rix)) => {
                             self.copy_within(ix..rix, ix + 1);
                             self.bitmap[ix] = false; // So size is updated correctly
                             self.upsert_at(pair, ix);
-                            Ok(())
+                            Ok(None)
                         }
                         _ => Err("Gapped array is full (_)".to_string()),
                     }
@@ -299,6 +504,7 @@ where
     /// Called to efficiently handle the initial upserts. NOTE: This makes two assumptions:
     /// - The values themselves are monotonically increasing
     /// - The hints are monotonically non-decreasing
+    ///
     /// If either of these assumptions break, bad stuff may happen (use regular upsert)
     pub fn initial_model_based_insert(&mut self, pair: (K, V), hint: usize) -> Result<(), String> {
         if !self.bitmap[hint] {
@@ -387,8 +593,8 @@ where
                 continue;
             }
             unsafe {
-                let key = std::mem::replace(&mut self.keys[ix], MaybeUninit::uninit());
-                let val = std::mem::replace(&mut self.vals[ix], MaybeUninit::uninit());
+                let key = core::mem::replace(&mut self.keys[ix], MaybeUninit::uninit());
+                let val = core::mem::replace(&mut self.vals[ix], MaybeUninit::uninit());
                 let Ok(_) = temp.initial_model_based_insert(
                     (key.assume_init(), val.assume_init()),
                     (ix as f32 * c) as usize,
@@ -444,10 +650,33 @@ where
     }
 }
 
+impl<K, V> Clone for GappedKVArray<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    /// Clones the gapped array, copying only the occupied slots and preserving the gap layout
+    fn clone(&self) -> Self {
+        let mut result = Self::new(self.len());
+        for ix in 0..self.len() {
+            if self.bitmap[ix] {
+                unsafe {
+                    result.keys[ix].write(self.keys[ix].assume_init_ref().clone());
+                    result.vals[ix].write(self.vals[ix].assume_init_ref().clone());
+                }
+                result.bitmap[ix] = true;
+            }
+        }
+        result.size = self.size;
+        result.mode = self.mode;
+        result
+    }
+}
+
 impl<K, V> fmt::Display for GappedKVArray<K, V>
 where
-    K: Default + Clone + Ord + std::fmt::Debug,
-    V: Default + Clone + Ord + std::fmt::Debug,
+    K: Default + Clone + Ord + core::fmt::Debug,
+    V: Default + Clone + Ord + core::fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut res = String::new();
@@ -471,7 +700,10 @@ where
 
 #[cfg(test)]
 mod gapped_array_tests {
+    extern crate std;
+
     use super::*;
+    use std::println;
     use itertools::Itertools;
     use kdam::{tqdm, BarExt};
 
@@ -513,6 +745,74 @@ mod gapped_array_tests {
         }
     }
 
+    #[test]
+    fn clone_preserves_gaps() {
+        let mut ga = GappedKVArray::<i32, i32>::new(8);
+        for num in [1, 3, 7] {
+            assert!(ga
+                .initial_model_based_insert((num, num * 10), num as usize)
+                .is_ok());
+        }
+        let mut cloned = ga.clone();
+        assert_eq!(ga.bitmap, cloned.bitmap);
+        assert_eq!(ga.size(), cloned.size());
+
+        assert!(cloned.upsert_with_hint((5, 50), 5).is_ok());
+        assert_eq!(ga.size(), 3);
+        assert_eq!(cloned.size(), 4);
+        assert_eq!(ga.search_exact(&5, None), None);
+        assert_eq!(cloned.search_exact(&5, None), Some(&50));
+    }
+
+    #[test]
+    fn upsert_returns_previous() {
+        let mut ga = GappedKVArray::<i32, i32>::new(8);
+
+        assert_eq!(ga.upsert_with_hint((3, 30), 3), Ok(None));
+        assert_eq!(ga.upsert_with_hint((5, 50), 5), Ok(None));
+        assert_eq!(ga.upsert_with_hint((3, 31), 3), Ok(Some(30)));
+        assert_eq!(ga.size(), 2);
+        assert_eq!(ga.search_exact(&3, None), Some(&31));
+    }
+
+    #[test]
+    fn search_exact_mut_updates_in_place() {
+        let mut ga = GappedKVArray::<i32, i32>::new(8);
+
+        assert_eq!(ga.upsert_with_hint((3, 30), 3), Ok(None));
+        assert_eq!(ga.upsert_with_hint((5, 50), 5), Ok(None));
+        *ga.search_exact_mut(&5, None).unwrap() += 1;
+
+        assert_eq!(ga.search_exact_mut(&4, None), None);
+        assert_eq!(ga.search_exact(&3, None), Some(&30));
+        assert_eq!(ga.search_exact(&5, None), Some(&51));
+        assert_eq!(ga.size(), 2);
+    }
+
+    #[test]
+    fn upsert_before_min_counts_entry() {
+        let mut ga = GappedKVArray::<i32, i32>::new(2);
+
+        assert_eq!(ga.upsert_with_hint((7, 70), 0), Ok(None));
+        assert_eq!(ga.upsert_with_hint((1, 10), 0), Ok(None));
+        assert_eq!(ga.size(), 2);
+        assert!(ga.is_full());
+        assert_eq!(ga.search_exact(&7, None), Some(&70));
+    }
+
+    #[test]
+    fn iter_skips_gaps() {
+        let mut ga = GappedKVArray::<i32, i32>::new(8);
+        for num in [1, 3, 7] {
+            assert!(ga
+                .initial_model_based_insert((num, num * 10), num as usize)
+                .is_ok());
+        }
+
+        let entries: Vec<(usize, i32, i32)> = ga.iter().map(|(ix, &k, &v)| (ix, k, v)).collect();
+        assert_eq!(entries, vec![(1, 1, 10), (3, 3, 30), (7, 7, 70)]);
+    }
+
     fn fill_backward_with_hint(size: usize, hint: usize) {
         let mut ga = GappedKVArray::<i32, i32>::new(size);
         for num in 0..size {
@@ -541,26 +841,24 @@ mod gapped_array_tests {
             return vec![];
         }
         if num_hints == 1 {
-            return (0..size).into_iter().map(|val| vec![val]).collect();
+            return (0..size).map(|val| vec![val]).collect();
         }
         let mut result: Vec<Vec<usize>> = vec![];
         for first_val in 0..size {
             let tails = get_all_possible_hints(size, num_hints - 1);
             for tail in tails {
                 let mut new_thing = vec![first_val];
-                new_thing.extend(tail.into_iter());
+                new_thing.extend(tail);
                 result.push(new_thing);
             }
         }
         result
     }
 
-    fn test_perm_with_hints(perm: &Vec<i32>, hints: &Vec<usize>) {
+    fn test_perm_with_hints(perm: &[i32], hints: &[usize]) {
         let mut ga = GappedKVArray::<i32, i32>::new(perm.len());
         for (value, hint) in perm.iter().zip(hints.iter()) {
-            assert!(ga
-                .upsert_with_hint((value.clone(), value.clone()), hint.clone())
-                .is_ok());
+            assert!(ga.upsert_with_hint((*value, *value), *hint).is_ok());
         }
         for ix in 0..ga.len() {
             let good = unsafe { ga.bitmap[ix] && ga.keys[ix].assume_init() == ix as i32 };
@@ -576,7 +874,7 @@ mod gapped_array_tests {
     #[test]
     fn permutation_test() {
         const SIZE: usize = 6;
-        let items: Vec<i32> = (0..SIZE).into_iter().map(|val| val as i32).collect();
+        let items: Vec<i32> = (0..SIZE).map(|val| val as i32).collect();
         let perms: Vec<Vec<i32>> = items.into_iter().permutations(SIZE).collect();
         let hints = get_all_possible_hints(SIZE, SIZE);
         let mut pb = tqdm!(total = perms.len() * hints.len());
@@ -588,26 +886,88 @@ mod gapped_array_tests {
         }
     }
 
+    #[test]
+    fn gallop_matches_scan() {
+        const SIZE: usize = 64;
+
+        // Every third slot occupied, with keys spaced so that needles fall in between them
+        let mut ga = GappedKVArray::<i32, i32>::new(SIZE);
+        for ix in (1..SIZE).step_by(3) {
+            assert!(ga
+                .initial_model_based_insert((ix as i32 * 2, ix as i32), ix)
+                .is_ok());
+        }
+        let galloping = ga.clone().with_mode(SearchMode::Gallop);
+        assert_eq!(galloping.mode(), SearchMode::Gallop);
+
+        for needle in -2..SIZE as i32 * 2 + 2 {
+            for hint in [
+                None,
+                Some(0),
+                Some(SIZE / 3),
+                Some(SIZE - 1),
+                Some(SIZE * 2),
+            ] {
+                assert_eq!(
+                    galloping.price_is_right(&needle, hint),
+                    ga.price_is_right(&needle, hint)
+                );
+                assert_eq!(
+                    galloping.search_exact(&needle, hint),
+                    ga.search_exact(&needle, hint)
+                );
+            }
+        }
+
+        // Far from the hint, galloping compares logarithmically many keys
+        assert!(
+            galloping.search_comparisons(&(SIZE as i32 * 2), Some(0))
+                < ga.search_comparisons(&(SIZE as i32 * 2), Some(0))
+        );
+        assert_eq!(
+            GappedKVArray::<i32, i32>::new(4)
+                .with_mode(SearchMode::Gallop)
+                .search_exact(&1, Some(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn gallop_permutation_test() {
+        const SIZE: usize = 5;
+        let items: Vec<i32> = (0..SIZE).map(|val| val as i32).collect();
+        let hints = get_all_possible_hints(SIZE, SIZE);
+        for perm in items.into_iter().permutations(SIZE) {
+            for hints in hints.iter() {
+                let mut ga = GappedKVArray::<i32, i32>::new(SIZE).with_mode(SearchMode::Gallop);
+                for (value, hint) in perm.iter().zip(hints.iter()) {
+                    assert!(ga.upsert_with_hint((*value, *value), *hint).is_ok());
+                }
+                for ix in 0..SIZE {
+                    assert_eq!(ga.search_exact(&(ix as i32), Some(0)), Some(&(ix as i32)));
+                }
+            }
+        }
+    }
+
     #[test]
     fn debug_gapped() {
-        let perm = vec![1, 2, 0, 3, 4];
-        let hints = vec![0, 0, 3, 0, 0];
+        let perm = [1, 2, 0, 3, 4];
+        let hints = [0, 0, 3, 0, 0];
         let mut ga = GappedKVArray::<i32, i32>::new(perm.len());
         // print_gapped_array(&ga);
         for (value, hint) in perm.iter().zip(hints.iter()) {
-            assert!(ga
-                .upsert_with_hint((value.clone(), value.clone()), hint.clone())
-                .is_ok());
+            assert!(ga.upsert_with_hint((*value, *value), *hint).is_ok());
             // println!("");
             // print_gapped_array(&ga);
         }
     }
 
-    unsafe fn test_nondec_seq(items: &Vec<i32>, hints: &Vec<usize>) {
+    unsafe fn test_nondec_seq(items: &[i32], hints: &[usize]) {
         let mut ga = GappedKVArray::<i32, i32>::new(items.len());
         for (value, hint) in items.iter().zip(hints.iter()) {
             assert!(ga
-                .initial_model_based_insert((value.clone(), value.clone()), hint.clone())
+                .initial_model_based_insert((*value, *value), *hint)
                 .is_ok());
         }
         for ix in 0..ga.len() {
@@ -624,7 +984,7 @@ mod gapped_array_tests {
     #[test]
     fn initial_inserts() {
         const SIZE: usize = 6;
-        let items: Vec<i32> = (0..SIZE).into_iter().map(|val| val as i32).collect();
+        let items: Vec<i32> = (0..SIZE).map(|val| val as i32).collect();
         let mut sequences = get_all_possible_hints(SIZE, SIZE);
         sequences.retain(|seq| {
             let mut last: Option<usize> = None;
@@ -646,17 +1006,15 @@ mod gapped_array_tests {
     #[test]
     fn update_gapped_array() {
         const SIZE: usize = 6;
-        let keys = vec![0, 1, 2, 3, 2, 3];
-        let vals = vec![10, 11, 22, 33, 42, 53];
+        let keys = [0, 1, 2, 3, 2, 3];
+        let vals = [10, 11, 22, 33, 42, 53];
         let all_hints = get_all_possible_hints(SIZE, SIZE);
         let mut ga = GappedKVArray::<i32, i32>::new(SIZE + 1);
-        let final_keys = vec![0, 1, 2, 3];
-        let final_vals = vec![10, 11, 42, 53];
+        let final_keys = [0, 1, 2, 3];
+        let final_vals = [10, 11, 42, 53];
         for hints in all_hints {
             for ((key, val), hint) in (keys.iter().zip(vals.iter())).zip(hints.iter()) {
-                assert!(ga
-                    .upsert_with_hint((key.clone(), val.clone()), hint.clone())
-                    .is_ok());
+                assert!(ga.upsert_with_hint((*key, *val), *hint).is_ok());
             }
             for (ix, (key, val)) in ga
                 .keys
@@ -677,8 +1035,8 @@ mod gapped_array_tests {
     fn trim_gapped_array() {
         const SIZE: usize = 6;
         let get_fresh_ga = || {
-            let keys = vec![0, 1, 2, 3, 4, 5];
-            let vals = vec![0, 1, 2, 3, 4, 5];
+            let keys = [0, 1, 2, 3, 4, 5];
+            let vals = [0, 1, 2, 3, 4, 5];
             let mut ga = GappedKVArray::<i32, i32>::new(SIZE);
             for (key, val) in keys.iter().zip(vals.iter()) {
                 ga.upsert_with_hint((*key, *val), 3).unwrap();
@@ -689,9 +1047,9 @@ mod gapped_array_tests {
             // Trim in the middle
             let mut mid_ga = get_fresh_ga();
             mid_ga.trim_window(2, 1, hint).unwrap();
-            let expected_keys = vec![0, 0, 0, 0, 4, 5];
-            let expected_vals = vec![0, 0, 0, 0, 4, 5];
-            let expected_bitmap = vec![true, false, false, false, true, true];
+            let expected_keys = [0, 0, 0, 0, 4, 5];
+            let expected_vals = [0, 0, 0, 0, 4, 5];
+            let expected_bitmap = [true, false, false, false, true, true];
             for ix in 0..SIZE {
                 assert!(mid_ga.bitmap[ix] == expected_bitmap[ix]);
                 if mid_ga.bitmap[ix] {
@@ -706,9 +1064,9 @@ mod gapped_array_tests {
             // Trim with clipping at both sides
             let mut mid_ga = get_fresh_ga();
             mid_ga.trim_window(2, u32::MAX, hint).unwrap();
-            let expected_keys = vec![0, 0, 0, 0, 0, 0];
-            let expected_vals = vec![0, 0, 0, 0, 0, 0];
-            let expected_bitmap = vec![false, false, false, false, false, false];
+            let expected_keys = [0, 0, 0, 0, 0, 0];
+            let expected_vals = [0, 0, 0, 0, 0, 0];
+            let expected_bitmap = [false, false, false, false, false, false];
             for ix in 0..SIZE {
                 assert!(mid_ga.bitmap[ix] == expected_bitmap[ix]);
                 if mid_ga.bitmap[ix] {
@@ -723,9 +1081,9 @@ mod gapped_array_tests {
             // Trim from beginning
             let mut front_ga = get_fresh_ga();
             front_ga.trim_window(0, 1, hint).unwrap();
-            let expected_keys = vec![0, 0, 2, 3, 4, 5];
-            let expected_vals = vec![0, 0, 2, 3, 4, 5];
-            let expected_bitmap = vec![false, false, true, true, true, true];
+            let expected_keys = [0, 0, 2, 3, 4, 5];
+            let expected_vals = [0, 0, 2, 3, 4, 5];
+            let expected_bitmap = [false, false, true, true, true, true];
             for ix in 0..SIZE {
                 assert!(front_ga.bitmap[ix] == expected_bitmap[ix]);
                 if front_ga.bitmap[ix] {
@@ -742,9 +1100,9 @@ mod gapped_array_tests {
             end_ga
                 .trim_window((end_ga.len() - 1) as i32, 1, hint)
                 .unwrap();
-            let expected_keys = vec![0, 1, 2, 3, 0, 0];
-            let expected_vals = vec![0, 1, 2, 3, 0, 0];
-            let expected_bitmap = vec![true, true, true, true, false, false];
+            let expected_keys = [0, 1, 2, 3, 0, 0];
+            let expected_vals = [0, 1, 2, 3, 0, 0];
+            let expected_bitmap = [true, true, true, true, false, false];
             for ix in 0..SIZE {
                 assert!(end_ga.bitmap[ix] == expected_bitmap[ix]);
                 if end_ga.bitmap[ix] {
@@ -759,13 +1117,13 @@ mod gapped_array_tests {
 
     #[test]
     fn debug_initial_gapped() {
-        let perm = vec![0, 1, 2, 3, 4, 5];
-        let hints = vec![0, 0, 0, 4, 4, 4];
+        let perm = [0, 1, 2, 3, 4, 5];
+        let hints = [0, 0, 0, 4, 4, 4];
         let mut ga = GappedKVArray::<i32, i32>::new(perm.len());
         // print_gapped_array(&ga);
         for (value, hint) in perm.iter().zip(hints.iter()) {
             assert!(ga
-                .initial_model_based_insert((value.clone(), value.clone()), hint.clone())
+                .initial_model_based_insert((*value, *value), *hint)
                 .is_ok());
             // println!("");
             // print_gapped_array(&ga);
@@ -1,5 +1,11 @@
 // Adapted from: [idalloc](https://github.com/udoprog/idalloc)
 
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
 /// A type that can be used an allocator index.
 pub trait ID: Copy {
     /// Allocate the initial, unallocated value.
@@ -146,7 +152,7 @@ impl<I> IDAllocator<I>
 where
     I: ID,
 {
-    pub fn iter(&self) -> IDAllocatorIterator<I> {
+    pub fn iter(&self) -> IDAllocatorIterator<'_, I> {
         IDAllocatorIterator {
             allocator: self,
             current: 0,
@@ -244,7 +250,9 @@ mod tests {
 
         // Allocate new IDs and check if freed IDs are reused
         let mut second_batch = (0..2).map(|_| allocator.allocate()).collect::<Vec<_>>();
-        assert_eq!(first_batch[1..3].sort(), second_batch[..].sort());
+        first_batch[1..3].sort();
+        second_batch.sort();
+        assert_eq!(first_batch[1..3], second_batch[..]);
     }
 
     #[test]
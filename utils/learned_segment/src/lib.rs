@@ -1,6 +1,10 @@
+#![no_std]
+
+extern crate alloc;
+
 mod model;
 mod point;
 mod segmentation;
 
-pub use model::LinearModel;
+pub use model::{LinearModel, PersistedModel, SegmentationModel};
 pub use segmentation::linear_simple_segmentation;
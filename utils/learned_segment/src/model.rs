@@ -4,9 +4,15 @@
 //! NOTE: We are making a simplification and forcing approximation lines
 //! to pass through the origin, which slightly degrades performance
 
+use alloc::vec::Vec;
+use core::ops::Range;
+
 use num::PrimInt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::segmentation::linear_simple_segmentation;
+
 /// A simple linear model for a key-rank segment of data.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LinearModel<K, const EPSILON: usize> {
@@ -20,34 +26,72 @@ pub struct LinearModel<K, const EPSILON: usize> {
     pub(crate) size: usize,
 }
 
-impl<K: PrimInt, const EPSILON: usize> LinearModel<K, EPSILON> {
-    /// Construct a new model from the smallest key, slope, and size
-    pub fn new(key: K, slope: f64, size: usize) -> Self {
-        debug_assert!(slope.is_normal());
-        Self { key, slope, size }
-    }
+/// A learned model approximating the rank of keys within one segment of sorted entries. This is
+/// the extension point for plugging custom models (splines, histograms, ...) into a PGM layer.
+///
+/// Models are expected to be generic over `<K, const EPSILON: usize>`, since the layout macro
+/// instantiates `pgm(model = MyModel, epsilon = 16)` as `MyModel<K, 16>`. Models which derive
+/// `Serialize` and `Deserialize` are also a `PersistedModel`, and can be persisted together with
+/// their node.
+pub trait SegmentationModel<K>: Clone + 'static {
+    /// Partition a sorted stream of entries into segments, training one model per segment
+    fn train<V>(data: impl Iterator<Item = (K, V)>) -> Vec<(Self, Vec<(K, V)>)>;
 
-    /// Approximation logic for linear models
-    pub fn approximate(&self, key: &K) -> (usize, usize) {
-        let run = num::cast::<K, f64>(key.clone().saturating_sub(self.key)).unwrap();
-        let pos = (run * self.slope).floor() as i64;
-        let pos = pos.max(0) as usize;
+    /// Construct a sentinel model which will sit at the end of a layer
+    fn sentinel() -> Self;
 
-        (pos.saturating_sub(EPSILON), pos + EPSILON + 2)
-    }
+    /// The smallest key indexed by this model
+    fn min_key(&self) -> &K;
+
+    /// A window of ranks which is guaranteed to contain the key, if it is present
+    fn approximate(&self, key: &K) -> Range<usize>;
 
     /// Instead of returning a window'd approximation, return a hint, which
     /// is better for gapped arrays with exponential search
     /// (I.e., it's a hint for where to _start_ searching for the element, not
     /// a window which is guaranteed to hold the value)
-    pub fn hint(&self, key: &K) -> usize {
-        let run = num::cast::<K, f64>(key.clone().saturating_sub(self.key)).unwrap();
-        let pos = (run * self.slope).floor() as i64;
-        pos.max(0) as usize
+    fn hint(&self, key: &K) -> usize {
+        let window = self.approximate(key);
+        window.start + (window.end - window.start) / 2
     }
 
-    /// Construct a sentinel model which will sit at the end of a layer
-    pub fn sentinel() -> Self {
+    /// Rescale the model after the underlying array has grown by a factor of `c`
+    fn rescale(&mut self, c: f64);
+
+    /// The bound on how far approximated ranks are from actual ones, if there is one. Nodes gallop
+    /// outwards from the hints of models with a wide bound instead of scanning.
+    fn epsilon() -> Option<usize> {
+        None
+    }
+
+    /// The slope and intercept of the model, as a line from the distance of a key to the smallest
+    /// key of the segment to its rank, if the model is a line at all
+    fn line(&self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+/// A `SegmentationModel` whose parameters can be written out and read back with serde, so that
+/// trained models can be persisted rather than retrained. Implemented for every model which is
+/// `Serialize` and `Deserialize`.
+pub trait PersistedModel<K>: SegmentationModel<K> + Serialize + DeserializeOwned {}
+
+impl<K, M> PersistedModel<K> for M where M: SegmentationModel<K> + Serialize + DeserializeOwned {}
+
+impl<K: PrimInt, const EPSILON: usize> LinearModel<K, EPSILON> {
+    /// Construct a new model from the smallest key, slope, and size
+    pub fn new(key: K, slope: f64, size: usize) -> Self {
+        debug_assert!(slope.is_normal());
+        Self { key, slope, size }
+    }
+}
+
+impl<K: PrimInt + 'static, const EPSILON: usize> SegmentationModel<K> for LinearModel<K, EPSILON> {
+    fn train<V>(data: impl Iterator<Item = (K, V)>) -> Vec<(Self, Vec<(K, V)>)> {
+        linear_simple_segmentation(data)
+    }
+
+    fn sentinel() -> Self {
         Self {
             key: K::max_value(),
             slope: 0.0,
@@ -55,10 +99,34 @@ impl<K: PrimInt, const EPSILON: usize> LinearModel<K, EPSILON> {
         }
     }
 
-    /// Rescales the slope of the model
-    pub fn rescale(&mut self, c: f64) {
+    fn min_key(&self) -> &K {
+        &self.key
+    }
+
+    /// Approximation logic for linear models
+    fn approximate(&self, key: &K) -> Range<usize> {
+        let pos = self.hint(key);
+
+        pos.saturating_sub(EPSILON)..pos + EPSILON + 2
+    }
+
+    fn hint(&self, key: &K) -> usize {
+        let run = num::cast::<K, f64>((*key).saturating_sub(self.key)).unwrap();
+        let pos = num::Float::floor(run * self.slope) as i64;
+        pos.max(0) as usize
+    }
+
+    fn rescale(&mut self, c: f64) {
         self.slope *= c;
     }
+
+    fn epsilon() -> Option<usize> {
+        Some(EPSILON)
+    }
+
+    fn line(&self) -> Option<(f64, f64)> {
+        Some((self.slope, 0.0))
+    }
 }
 
 impl<K, const EPSILON: usize> LinearModel<K, EPSILON> {
@@ -88,8 +156,8 @@ mod pgm_model_tests {
             let expected_lo = (test - key) * slope_usize - EPS;
             let expected_hi = expected_lo + EPS * 2 + 2;
 
-            assert!(approx.0 == expected_lo);
-            assert!(approx.1 == expected_hi);
+            assert!(approx.start == expected_lo);
+            assert!(approx.end == expected_hi);
         }
     }
 }
@@ -1,4 +1,4 @@
-use std::ops::Sub;
+use core::ops::Sub;
 
 use num::PrimInt;
 
@@ -1,3 +1,5 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use num::PrimInt;
 
 use crate::{model::LinearModel, point::Point};
@@ -49,8 +51,14 @@ impl<K: PrimInt, V, const EPSILON: usize> LinearSimpleSegmentation<K, V, EPSILON
             // debug_assert!(self.last_key.clone().unwrap() < entry.0);
         }
 
+        // A key repeating the first one has no run to fit a slope over, which happens when the
+        // layer below ends in a node at the maximum key followed by its cap
+        if self.first_key == Some(entry.0) {
+            return Err(entry);
+        }
+
         // Get the worst case points we care about
-        let base_point = Point::new(self.first_key.clone().unwrap(), 0);
+        let base_point = Point::new(self.first_key.unwrap(), 0);
         let max_point = Point::new(
             entry.0,
             self.num_entries
@@ -115,7 +123,7 @@ impl<K: PrimInt, V, const EPSILON: usize> LinearSimpleSegmentation<K, V, EPSILON
 
     /// Takes ownership of the entires generating this linear model
     pub fn take_entries(&mut self) -> Vec<(K, V)> {
-        std::mem::replace(&mut self.entries, vec![])
+        core::mem::take(&mut self.entries)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -124,6 +132,7 @@ impl<K: PrimInt, V, const EPSILON: usize> LinearSimpleSegmentation<K, V, EPSILON
 }
 
 #[must_use]
+#[allow(clippy::type_complexity)]
 pub fn linear_simple_segmentation<K: PrimInt, V, const EPSILON: usize>(
     data: impl Iterator<Item = (K, V)>,
 ) -> Vec<(LinearModel<K, EPSILON>, Vec<(K, V)>)> {
@@ -160,9 +169,13 @@ pub fn linear_simple_segmentation<K: PrimInt, V, const EPSILON: usize>(
 /// properly indexed
 #[cfg(test)]
 mod pgm_segmentation_tests {
+    extern crate std;
+
     use rand::{distributions::Uniform, Rng};
+    use std::println;
 
     use super::*;
+    use crate::model::SegmentationModel;
 
     type Key = usize;
     type Value = usize;
@@ -206,6 +219,7 @@ mod pgm_segmentation_tests {
         }
 
         /// Assuming data has already been generated, segments it as a layer
+        #[allow(clippy::type_complexity)]
         fn train(&mut self) {
             if self.verbose {
                 println!(
@@ -240,7 +254,7 @@ mod pgm_segmentation_tests {
             let range = self.models[model_ix].approximate(&entry.0);
             self.last_base_rank = base_rank;
             self.last_model_ix = model_ix;
-            return base_rank + range.0 <= entry.1 && entry.1 < base_rank + range.1;
+            base_rank + range.start <= entry.1 && entry.1 < base_rank + range.end
         }
 
         /// Assuming data has already been generated and trained on, tests that every key is correctly approximated
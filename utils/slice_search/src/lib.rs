@@ -3,9 +3,11 @@
 //! A collection of algorithms for searching within slices.
 //!
 //! This module provides different search strategies and utilities to work with sorted slices.
-//! Currently, it supports binary and linear search algorithms, as well as an optimal search
-//! algorithm which picks between binary and linear searches depending on the size of the slice.
+//! Currently, it supports binary, linear and branchless search algorithms, as well as an optimal
+//! search algorithm which picks between binary and linear searches depending on the size of the
+//! slice.
 #![deny(missing_docs)]
+#![cfg_attr(feature = "safe", forbid(unsafe_code))]
 
 /// Returns the index of the smallest element greater than or equal to the search
 /// key.
@@ -135,19 +137,62 @@ pub trait Search {
     /// assert_eq!(result, Ok(1));
     /// ```
     fn search_by_key<K: Ord, T: Borrow<K>>(slice: &[T], x: &K) -> Result<usize, usize>;
+
+    /// Like `search_by_key`, additionally returning the number of comparisons the search made
+    ///
+    /// ```
+    /// use slice_search::*;
+    ///
+    /// let slice = [1, 2, 3, 5, 8];
+    /// let (result, comparisons) = LinearSearch::search_by_key_counted(&slice, &3);
+    /// assert_eq!(result, Ok(2));
+    /// assert_eq!(comparisons, 4);
+    /// ```
+    fn search_by_key_counted<K: Ord, T: Borrow<K>>(
+        slice: &[T],
+        x: &K,
+    ) -> (Result<usize, usize>, usize);
 }
 
 /// Performs a binary search on a slice, with computational complexity `O(log n)`
 /// However, for small searches, a linear search may be faster.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct BinarySearch;
 
 impl Search for BinarySearch {
     fn search_by_key<K: Ord, T: Borrow<K>>(slice: &[T], x: &K) -> Result<usize, usize> {
         slice.binary_search_by(|y| y.borrow().cmp(x))
     }
+
+    fn search_by_key_counted<K: Ord, T: Borrow<K>>(
+        slice: &[T],
+        x: &K,
+    ) -> (Result<usize, usize>, usize) {
+        let mut comparisons = 0;
+        let result = slice.binary_search_by(|y| {
+            comparisons += 1;
+            y.borrow().cmp(x)
+        });
+
+        (result, comparisons)
+    }
+}
+
+/// The element at `index`, which has to be within the slice. With the `safe` feature the index is
+/// checked, otherwise it's trusted.
+#[inline(always)]
+fn element<K, T: Borrow<K>>(slice: &[T], index: usize) -> &K {
+    #[cfg(feature = "safe")]
+    return slice[index].borrow();
+
+    #[cfg(not(feature = "safe"))]
+    unsafe {
+        slice.get_unchecked(index).borrow()
+    }
 }
 
 /// Performs a simple linear search on a slice, with computational complexity `O(n)`
+#[derive(Clone, Copy, Debug, Default)]
 pub struct LinearSearch;
 
 impl Search for LinearSearch {
@@ -155,23 +200,39 @@ impl Search for LinearSearch {
         let mut index = 0;
         let size = slice.len();
 
-        while index < size && unsafe { slice.get_unchecked(index).borrow() } < x {
+        while index < size && element(slice, index) < x {
             index += 1;
         }
 
         if index >= size {
             Err(size)
-        } else if unsafe { slice.get_unchecked(index).borrow() } == x {
+        } else if element(slice, index) == x {
             Ok(index)
         } else {
             Err(index)
         }
     }
+
+    fn search_by_key_counted<K: Ord, T: Borrow<K>>(
+        slice: &[T],
+        x: &K,
+    ) -> (Result<usize, usize>, usize) {
+        let result = Self::search_by_key(slice, x);
+
+        // Every smaller element is compared once, and the element the scan stopped at twice
+        let comparisons = match result {
+            Ok(index) | Err(index) if index < slice.len() => index + 2,
+            _ => slice.len(),
+        };
+
+        (result, comparisons)
+    }
 }
 
 const BINARY_SEARCH_CUTOFF: usize = 1024;
 
 /// Chooses between binary and linear search depending on the size of the slice to search
+#[derive(Clone, Copy, Debug, Default)]
 pub struct OptimalSearch;
 
 impl Search for OptimalSearch {
@@ -182,6 +243,82 @@ impl Search for OptimalSearch {
             LinearSearch::search_by_key(slice, x)
         }
     }
+
+    fn search_by_key_counted<K: Ord, T: Borrow<K>>(
+        slice: &[T],
+        x: &K,
+    ) -> (Result<usize, usize>, usize) {
+        if slice.len() * core::mem::size_of::<K>() > BINARY_SEARCH_CUTOFF {
+            BinarySearch::search_by_key_counted(slice, x)
+        } else {
+            LinearSearch::search_by_key_counted(slice, x)
+        }
+    }
+}
+
+const BRANCHLESS_SCAN_CUTOFF: usize = 256;
+
+/// Searches a slice without branching on the comparisons, so that the outcome of a comparison
+/// never has to be predicted. Slices of up to 256 bytes of keys are scanned by counting the
+/// smaller elements, which the compiler turns into SIMD comparisons for primitive keys on targets
+/// which support them, and into plain scalar code elsewhere. Longer slices are binary searched
+/// with a conditional move in place of the branch, with computational complexity `O(log n)`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BranchlessSearch;
+
+impl BranchlessSearch {
+    /// Index of the first element which is not smaller than `x`, along with the number of
+    /// comparisons made to find it
+    fn partition<K: Ord, T: Borrow<K>>(slice: &[T], x: &K) -> (usize, usize) {
+        if slice.len() * core::mem::size_of::<K>() <= BRANCHLESS_SCAN_CUTOFF {
+            let index = slice
+                .iter()
+                .map(|y| (y.borrow() < x) as usize)
+                .sum::<usize>();
+
+            return (index, slice.len());
+        }
+
+        let mut base = 0;
+        let mut size = slice.len();
+        let mut comparisons = 1;
+
+        while size > 1 {
+            let half = size / 2;
+            base = if slice[base + half].borrow() < x {
+                base + half
+            } else {
+                base
+            };
+            size -= half;
+            comparisons += 1;
+        }
+
+        (base + (slice[base].borrow() < x) as usize, comparisons)
+    }
+}
+
+impl Search for BranchlessSearch {
+    fn search_by_key<K: Ord, T: Borrow<K>>(slice: &[T], x: &K) -> Result<usize, usize> {
+        Self::search_by_key_counted(slice, x).0
+    }
+
+    fn search_by_key_counted<K: Ord, T: Borrow<K>>(
+        slice: &[T],
+        x: &K,
+    ) -> (Result<usize, usize>, usize) {
+        if slice.is_empty() {
+            return (Err(0), 0);
+        }
+
+        let (index, comparisons) = Self::partition(slice, x);
+
+        match slice.get(index) {
+            Some(y) if y.borrow() == x => (Ok(index), comparisons + 1),
+            Some(_) => (Err(index), comparisons + 1),
+            None => (Err(index), comparisons),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -223,6 +360,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn counted_search() {
+        let array = [1, 2, 3, 4, 7, 10, 24, 55, 56, 57, 100];
+
+        for i in 0..110 {
+            let (result, comparisons) = BinarySearch::search_by_key_counted(&array[..], &i);
+            assert_eq!(result, BinarySearch::search(&array[..], &i));
+            assert!(comparisons <= array.len().ilog2() as usize + 2);
+
+            let (result, comparisons) = LinearSearch::search_by_key_counted(&array[..], &i);
+            assert_eq!(result, LinearSearch::search(&array[..], &i));
+            assert!(comparisons <= array.len() + 1);
+        }
+
+        assert_eq!(
+            LinearSearch::search_by_key_counted(&array[..], &1),
+            (Ok(0), 2)
+        );
+        assert_eq!(
+            LinearSearch::search_by_key_counted(&array[..], &200),
+            (Err(11), 11)
+        );
+    }
+
+    #[test]
+    fn binary_branchless_search() {
+        let array = [1, 2, 3, 4, 7, 10, 24, 55, 56, 57, 100];
+        let long: [u64; 100] = core::array::from_fn(|i| (i * i) as u64);
+
+        for i in -10..110 {
+            assert_eq!(
+                BinarySearch::search(&array[..], &i),
+                BranchlessSearch::search(&array[..], &i)
+            );
+        }
+
+        for i in 0..10_010 {
+            assert_eq!(
+                BinarySearch::search(&long[..], &i),
+                BranchlessSearch::search(&long[..], &i)
+            );
+
+            let (result, comparisons) = BranchlessSearch::search_by_key_counted(&long[..], &i);
+            assert_eq!(result, BinarySearch::search(&long[..], &i));
+            assert!(comparisons <= long.len().ilog2() as usize + 3);
+        }
+
+        assert_eq!(BranchlessSearch::search(&[0u64; 0][..], &1), Err(0));
+        assert_eq!(BranchlessSearch::search(&[5][..], &5), Ok(0));
+        assert_eq!(BranchlessSearch::search(&[5][..], &6), Err(1));
+    }
+
     #[test]
     fn binary_optimal_search() {
         let array = [1, 2, 3, 4, 7, 10, 24, 55, 56, 57, 100];
@@ -1,9 +1,17 @@
 //! Adapted from: [StackMap](https://github.com/komora-io/stack-map)
 //!
 //! A zero-allocation sorted array data structure, similar to SmallVec.
+//!
+//! With the `safe` feature, entries are kept in a vector holding at most `N` of them instead of an
+//! inline array of uninitialized slots. This allocates, and is somewhat slower, but doesn't need
+//! any unsafe code, so it can be checked under Miri.
 
 #![no_std]
 #![deny(missing_docs)]
+#![cfg_attr(feature = "safe", forbid(unsafe_code))]
+
+#[cfg(feature = "safe")]
+extern crate alloc;
 
 mod entry;
 pub use entry::SortedArrayEntry;
@@ -11,18 +19,23 @@ pub use entry::SortedArrayEntry;
 #[cfg(feature = "serde")]
 mod serde;
 
+#[cfg(not(feature = "safe"))]
 use core::mem::MaybeUninit;
 use slice_search::*;
 
 /// A constant-size, zero-allocation associative container based on a sorted array.
 pub struct SortedArray<K, V, const N: usize> {
+    #[cfg(not(feature = "safe"))]
     inner: [MaybeUninit<SortedArrayEntry<K, V>>; N],
+    #[cfg(feature = "safe")]
+    inner: alloc::vec::Vec<SortedArrayEntry<K, V>>,
     len: usize,
 }
 
 #[allow(unused)]
 impl<K, V, const N: usize> SortedArray<K, V, N> {
     /// Create an empty sorted array
+    #[cfg(not(feature = "safe"))]
     pub fn empty() -> Self {
         SortedArray {
             inner: unsafe {
@@ -32,6 +45,15 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
         }
     }
 
+    /// Create an empty sorted array
+    #[cfg(feature = "safe")]
+    pub fn empty() -> Self {
+        SortedArray {
+            inner: alloc::vec::Vec::with_capacity(N),
+            len: 0,
+        }
+    }
+
     /// Utility method to search the array by key
     fn search(&self, key: &K) -> Result<usize, usize>
     where
@@ -40,15 +62,61 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
         OptimalSearch::search_by_key(self.entries(), key)
     }
 
+    /// Number of comparisons a search for the key makes
+    pub fn search_comparisons(&self, key: &K) -> usize
+    where
+        K: Ord,
+    {
+        self.search_comparisons_with::<OptimalSearch>(key)
+    }
+
+    /// Number of comparisons a search for the key with the strategy `S` makes
+    pub fn search_comparisons_with<S: Search>(&self, key: &K) -> usize
+    where
+        K: Ord,
+    {
+        S::search_by_key_counted(self.entries(), key).1
+    }
+
     /// Return an entry which is an exact match for the key
     pub fn get_exact(&self, key: &K) -> Option<&V>
     where
         K: Ord,
     {
-        if let Ok(index) = self.search(key) {
-            Some(unsafe { &self.inner.get_unchecked(index).assume_init_ref().value })
-        } else {
-            None
+        self.get_exact_with::<OptimalSearch>(key)
+    }
+
+    /// Return an entry which is an exact match for the key, searching with the strategy `S`
+    pub fn get_exact_with<S: Search>(&self, key: &K) -> Option<&V>
+    where
+        K: Ord,
+    {
+        self.get_entry_with::<S>(key).map(|(_, value)| value)
+    }
+
+    /// Return a mutable reference to the value of the entry which is an exact match for the key,
+    /// searching with the strategy `S`
+    pub fn get_exact_mut_with<S: Search>(&mut self, key: &K) -> Option<&mut V>
+    where
+        K: Ord,
+    {
+        match S::search_by_key(self.entries(), key) {
+            Ok(index) => self.get_value_mut(index),
+            Err(_) => None,
+        }
+    }
+
+    /// Return the stored key and value of the entry which is an exact match for the key, searching
+    /// with the strategy `S`
+    pub fn get_entry_with<S: Search>(&self, key: &K) -> Option<(&K, &V)>
+    where
+        K: Ord,
+    {
+        match S::search_by_key(self.entries(), key) {
+            Ok(index) => self
+                .get_index(index)
+                .map(|entry| (&entry.key, &entry.value)),
+            Err(_) => None,
         }
     }
 
@@ -68,13 +136,19 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
     {
         match self.search(&key) {
             Ok(index) => {
-                let slot =
-                    unsafe { &mut self.inner.get_unchecked_mut(index).assume_init_mut().value };
+                let slot = self.get_value_mut(index).unwrap();
                 Some(core::mem::replace(slot, value))
             }
             Err(index) => {
                 assert!(self.len() < N);
 
+                #[cfg(feature = "safe")]
+                {
+                    self.inner.insert(index, SortedArrayEntry::new(key, value));
+                    self.len += 1;
+                }
+
+                #[cfg(not(feature = "safe"))]
                 unsafe {
                     if index < self.len() {
                         let src = self.inner.get_unchecked(index).as_ptr();
@@ -99,24 +173,49 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
     where
         K: Ord,
     {
-        // TODO: fix undefined behavior here
-        if let Ok(index) = self.search(key) {
-            unsafe {
-                let ret = core::ptr::read(self.inner.get_unchecked(index).as_ptr()).value;
+        let index = self.search(key).ok()?;
+        Some(self.remove_index(index).value)
+    }
 
-                if index + 1 < self.len() {
-                    let dst = self.inner.get_unchecked_mut(index).as_mut_ptr();
-                    let src = self.inner.get_unchecked(index + 1).as_ptr();
+    /// Remove the entry at `index` in the backing array, shifting every later entry down.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `index` is out of bounds.
+    pub fn remove_index(&mut self, index: usize) -> SortedArrayEntry<K, V> {
+        assert!(index < self.len());
+
+        #[cfg(feature = "safe")]
+        {
+            self.len -= 1;
+            self.inner.remove(index)
+        }
 
-                    core::ptr::copy(src, dst, self.len() - index);
-                }
+        #[cfg(not(feature = "safe"))]
+        unsafe {
+            let ret = core::ptr::read(self.inner.get_unchecked(index).as_ptr());
 
-                self.len -= 1;
+            if index + 1 < self.len() {
+                let dst = self.inner.get_unchecked_mut(index).as_mut_ptr();
+                let src = self.inner.get_unchecked(index + 1).as_ptr();
 
-                Some(ret)
+                core::ptr::copy(src, dst, self.len() - index - 1);
             }
-        } else {
-            None
+
+            self.len -= 1;
+
+            ret
+        }
+    }
+
+    /// Index of the first entry whose key is at least the provided key, which is `self.len()` if
+    /// every key is smaller.
+    pub fn position(&self, key: &K) -> usize
+    where
+        K: Ord,
+    {
+        match self.search(key) {
+            Ok(index) | Err(index) => index,
         }
     }
 
@@ -143,6 +242,13 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
 
         let mut rhs = Self::empty();
 
+        #[cfg(feature = "safe")]
+        {
+            rhs.inner = self.inner.split_off(split_idx);
+            rhs.inner.reserve(N - rhs.inner.len());
+        }
+
+        #[cfg(not(feature = "safe"))]
         for i in split_idx..self.len() {
             let src = self.inner[i].as_ptr();
             let dst = rhs.inner[i - split_idx].as_mut_ptr();
@@ -177,8 +283,15 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
     where
         K: Ord,
     {
-        // binary search LUB
-        let index = lower_bound_always(self.search(key));
+        self.get_lower_bound_always_with::<OptimalSearch>(key)
+    }
+
+    /// Like `get_lower_bound_always`, searching with the strategy `S`
+    pub fn get_lower_bound_always_with<S: Search>(&self, key: &K) -> &V
+    where
+        K: Ord,
+    {
+        let index = lower_bound_always(S::search_by_key(self.entries(), key));
         &self.entries()[index].value
     }
 }
@@ -186,6 +299,13 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
 #[allow(unused)]
 impl<K, V, const N: usize> SortedArray<K, V, N> {
     /// Borrow a slice view into the entries stored in the `SortedArray`
+    #[cfg(feature = "safe")]
+    pub fn entries(&self) -> &[SortedArrayEntry<K, V>] {
+        &self.inner
+    }
+
+    /// Borrow a slice view into the entries stored in the `SortedArray`
+    #[cfg(not(feature = "safe"))]
     pub fn entries(&self) -> &[SortedArrayEntry<K, V>] {
         // SAFETY: `len` must be strictly less than `F`
         debug_assert!(self.len <= N);
@@ -201,6 +321,10 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
     /// Get a key-value pair based on its internal relative
     /// index in the backing array.
     pub fn get_index(&self, index: usize) -> Option<&SortedArrayEntry<K, V>> {
+        #[cfg(feature = "safe")]
+        return self.inner.get(index);
+
+        #[cfg(not(feature = "safe"))]
         if index < self.len() {
             Some(unsafe { self.inner.get_unchecked(index).assume_init_ref() })
         } else {
@@ -208,6 +332,20 @@ impl<K, V, const N: usize> SortedArray<K, V, N> {
         }
     }
 
+    /// Get a mutable reference to the value at `index` in the backing array. Keys can't be
+    /// borrowed mutably, since changing them could break the order of the array.
+    pub fn get_value_mut(&mut self, index: usize) -> Option<&mut V> {
+        #[cfg(feature = "safe")]
+        return self.inner.get_mut(index).map(|entry| &mut entry.value);
+
+        #[cfg(not(feature = "safe"))]
+        if index < self.len() {
+            Some(unsafe { &mut self.inner.get_unchecked_mut(index).assume_init_mut().value })
+        } else {
+            None
+        }
+    }
+
     /// Returns the first key-value pair in the array, if any exists.
     pub fn first(&self) -> Option<&SortedArrayEntry<K, V>> {
         self.get_index(0)
@@ -264,6 +402,18 @@ impl<K: Debug, V: Debug, const N: usize> Debug for SortedArray<K, V, N> {
 }
 
 impl<K: Clone, V: Clone, const N: usize> Clone for SortedArray<K, V, N> {
+    #[cfg(feature = "safe")]
+    fn clone(&self) -> Self {
+        let mut inner = alloc::vec::Vec::with_capacity(N);
+        inner.extend(self.iter().cloned());
+
+        SortedArray {
+            inner,
+            len: self.len,
+        }
+    }
+
+    #[cfg(not(feature = "safe"))]
     fn clone(&self) -> Self {
         let mut inner: [MaybeUninit<SortedArrayEntry<K, V>>; N] = unsafe {
             MaybeUninit::<[MaybeUninit<SortedArrayEntry<K, V>>; N]>::uninit().assume_init()
@@ -316,6 +466,24 @@ mod tests {
         assert_eq!(stack_map.get_exact(&2), None);
     }
 
+    #[test]
+    fn test_remove_index() {
+        let mut stack_map: SortedArray<u32, &str, 3> = SortedArray::empty();
+        stack_map.insert(1, "one");
+        stack_map.insert(2, "two");
+        stack_map.insert(3, "three");
+
+        assert_eq!(stack_map.position(&2), 1);
+        assert_eq!(stack_map.position(&4), 3);
+        *stack_map.get_value_mut(0).unwrap() = "uno";
+
+        assert_eq!(stack_map.remove_index(1), SortedArrayEntry::new(2, "two"));
+        assert_eq!(stack_map.remove_index(1), SortedArrayEntry::new(3, "three"));
+        assert_eq!(stack_map.len(), 1);
+        assert_eq!(stack_map.get_exact(&1), Some(&"uno"));
+        assert_eq!(stack_map.get_value_mut(1), None);
+    }
+
     #[test]
     fn test_contains_key() {
         let mut stack_map: SortedArray<u32, &str, 3> = SortedArray::empty();